@@ -10,6 +10,7 @@ use tokio::sync::mpsc;
 use clap::Parser;
 use redis_rs::parser::cmd::command::CommandStrategy;
 use redis_rs::server::connection::Connection;
+use redis_rs::server::tls::ClientTlsConfig;
 
 #[derive(Parser, Clone)]
 struct BenchmarkConfig {
@@ -25,6 +26,20 @@ struct BenchmarkConfig {
     pub data_size: u32,
     #[arg(short, long, num_args = 1..)]
     pub tests: Vec<String>,
+    /// Unix socket to connect to instead of ip and port
+    #[arg(short, long)]
+    pub socket: Option<String>,
+    /// Connect over TLS
+    #[arg(long)]
+    pub tls: bool,
+    /// CA certificate the server certificate is checked against
+    #[arg(long, default_value = "")]
+    pub cacert: String,
+    /// Client certificate and key, for servers that authenticate clients
+    #[arg(long)]
+    pub cert: Option<String>,
+    #[arg(long)]
+    pub key: Option<String>,
 }
 
 unsafe impl Sync for BenchmarkConfig {}
@@ -95,6 +110,18 @@ async fn create_client(config: Arc<BenchmarkConfig>) -> Client {
     let host = config.ip.clone();
     let port = config.port;
     let addr = format!("{}:{}", host, port);
+    if let Some(path) = &config.socket {
+        return Client::connect_unix(path).await.unwrap();
+    }
+    if config.tls {
+        let tls = ClientTlsConfig {
+            ca_cert_file: config.cacert.clone(),
+            cert_file: config.cert.clone(),
+            key_file: config.key.clone(),
+            server_name: None,
+        };
+        return Client::connect_tls(&addr, &tls).await.unwrap();
+    }
     Client::connect(addr).await.unwrap()
 }
 
@@ -5,18 +5,35 @@ use redis_rs::parser::cmd::command::{CommandStrategy, RedisCommand};
 use redis_rs::parser::frame::Frame;
 use redis_rs::Result;
 use redis_rs::parser::cmd::conn::ConnCmd::{*};
+use redis_rs::parser::cmd::pubsub::PubSubCmd;
+use clap::Parser;
+
+#[derive(Parser)]
+struct CliArgs {
+    /// Unix socket to connect to, overriding the address of config.json
+    #[arg(short, long)]
+    socket: Option<String>,
+}
 
 pub async fn run_client() -> Result<()> {
     tracing_subscriber::fmt::try_init().expect("config log fail");
+    let args = CliArgs::parse();
     let config = ClientConfig::new(Some("./config.json"));
     let host = config.get_value("server_ip").unwrap().trim_matches('"').to_string();
     let port = config.get_value("server_port").unwrap();
 
-    let addr = format!("{}:{}", host, port);
+    let addr = match &args.socket {
+        Some(path) => path.clone(),
+        None => format!("{}:{}", host, port),
+    };
     info!("<{}>", addr);
     let mut db_idx = 0;
 
-    let mut client = Client::connect(addr.clone()).await.unwrap();
+    let mut client = match (&args.socket, config.tls_config()) {
+        (Some(path), _) => Client::connect_unix(path).await.unwrap(),
+        (None, Some(tls)) => Client::connect_tls(&addr, &tls).await.unwrap(),
+        (None, None) => Client::connect(addr.clone()).await.unwrap(),
+    };
     let mut command = String::new();
     'clear: loop {
         command.clear();
@@ -47,8 +64,29 @@ pub async fn run_client() -> Result<()> {
                 RedisCommand::Connection(Select { index}) => *index,
                 _ => db_idx,
             };
+            let subscribe = matches!(
+                &cmd,
+                RedisCommand::PubSub(PubSubCmd::Subscribe { .. } | PubSubCmd::PSubscribe { .. } | PubSubCmd::SSubscribe { .. })
+            );
             let frame = cmd.into_frame();
             let _ = client.conn.write_frame(&frame).await;
+            if subscribe {
+                // subscriber mode: print pushed messages until the connection closes
+                println!("Reading messages... (press Ctrl-C to quit)");
+                loop {
+                    match client.conn.read_frame().await {
+                        Ok(Some(res)) => println!("{}", res),
+                        Ok(None) => {
+                            println!("client quit");
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            println!("error: {}", e);
+                            return Ok(());
+                        }
+                    }
+                }
+            }
             let res = client.conn.read_frame().await;
             match res {
                 Ok(res) => {
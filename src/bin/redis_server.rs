@@ -1,26 +1,133 @@
+use redis_rs::config::ServerConfig;
 use redis_rs::server::server::run_server;
-use redis_rs::{DB_SIZE, DEFAULT_PORT};
+use redis_rs::server::zmalloc::CountingAllocator;
+use redis_rs::util::yesnotoi;
 
-use tokio::{net::TcpListener, signal};
+use clap::{CommandFactory, Parser};
+use tokio::net::TcpListener;
 use tracing::info;
 
+/// Counts allocations for INFO memory, MEMORY STATS and `maxmemory`
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// TinyRedis server. Any setting of the config file can also be given as
+/// `--<parameter> <value>`, e.g. `--maxmemory 100mb`, overriding the file.
+#[derive(Parser)]
+#[command(version)]
+struct ServerArgs {
+    /// JSON or TOML config file, ./server_config.json or ./server_config.toml if not set
+    #[arg(short, long)]
+    config: Option<String>,
+    /// Port to listen on
+    #[arg(short, long)]
+    port: Option<u16>,
+    /// Address to listen on
+    #[arg(short, long)]
+    bind: Option<String>,
+    /// Number of databases
+    #[arg(long)]
+    databases: Option<u32>,
+    /// Working directory, where the RDB file is written
+    #[arg(long)]
+    dir: Option<String>,
+    /// debug, verbose, notice, warning or nothing
+    #[arg(long)]
+    loglevel: Option<String>,
+}
+
+/// Splits the `--<parameter> <value>` (or `--<parameter>=<value>`) overrides of config
+/// settings from the flags `ServerArgs` knows.
+fn split_overrides(args: Vec<String>) -> (Vec<String>, Vec<(String, String)>) {
+    let command = ServerArgs::command();
+    let known: Vec<&str> = command.get_arguments().filter_map(|arg| arg.get_long()).chain(["help", "version"]).collect();
+    let mut flags = vec![];
+    let mut overrides = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            flags.push(arg);
+            continue;
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (name, None),
+        };
+        if known.contains(&name) {
+            flags.push(arg.clone());
+            continue;
+        }
+        match value.or_else(|| args.next()) {
+            Some(value) => overrides.push((name.to_string(), value)),
+            // clap reports the flag
+            None => flags.push(arg.clone()),
+        }
+    }
+    (flags, overrides)
+}
+
+/// Loads the config file, then applies the flags and overrides.
+fn load_config() -> Result<ServerConfig, String> {
+    let (flags, overrides) = split_overrides(std::env::args().collect());
+    let args = ServerArgs::parse_from(flags);
+    let mut config = ServerConfig::new(args.config.as_deref()).map_err(|err| err.to_string())?;
+    if let Some(port) = args.port {
+        config.port = port;
+    }
+    if let Some(bind) = args.bind {
+        config.bind = bind;
+    }
+    if let Some(databases) = args.databases {
+        config.db_num = databases;
+    }
+    if let Some(dir) = args.dir {
+        config.dir = Some(dir);
+    }
+    if let Some(loglevel) = args.loglevel {
+        config.loglevel = loglevel;
+    }
+    for (name, value) in overrides {
+        config.set_param(&name, &value).map_err(|err| err.to_string())?;
+    }
+    if let Some(dir) = &config.dir {
+        std::env::set_current_dir(dir).map_err(|err| format!("can't chdir to '{}': {}", dir, err))?;
+    }
+    Ok(config)
+}
+
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::try_init().expect("config log fail");
-    let port = DEFAULT_PORT;
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Fatal error, can't start the server: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let Some(level) = config.log_level() else {
+        eprintln!("Fatal error, invalid loglevel '{}'", config.loglevel);
+        std::process::exit(1);
+    };
+    tracing_subscriber::fmt().with_max_level(level).try_init().expect("config log fail");
 
     // Bind a TCP listener
-    let listener = TcpListener::bind(&format!("0.0.0.0:{}", port)).await.unwrap();
-    print_logo();
+    let listener = match TcpListener::bind((config.bind.as_str(), config.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Fatal error, can't listen on {}:{}: {}", config.bind, config.port, err);
+            std::process::exit(1);
+        }
+    };
+    let mode = if yesnotoi(&config.cluster_enabled) == Some(true) { "Cluster" } else { "Standalone" };
+    print_logo(config.port, mode);
     info!("Redis Server start");
     unsafe {
-        run_server(listener, signal::ctrl_c(), DB_SIZE as u32).await;
+        run_server(config, listener).await;
     }
 }
 
-pub fn print_logo() {
+pub fn print_logo(port: u16, mode: &str) {
     let version = "0.1.0";
-    let port = 8000;
     let pid = std::process::id();
 
     let logo = format!(
@@ -29,7 +136,7 @@ pub fn print_logo() {
           _.-``__ ''-._
      _.-``    `.  `_.  ''-._           TinyRedis {} (custom)
  .-`` .-```.  ```\/    _.,_ ''-._
-(    '      ,       .-`  | `,    )     Running in Standalone mode
+(    '      ,       .-`  | `,    )     Running in {} mode
 |`-._`-...-` __...-.``-._|'` _.-'|     Port: {}
 |    `-._   `._    /     _.-'    |     PID: {}
  `-._    `-._  `-./  _.-'    _.-'
@@ -43,7 +150,7 @@ pub fn print_logo() {
          `-._        _.-'
              `-.__.-'
 "#,
-        version, port, pid
+        version, mode, port, pid
     );
 
     println!("{}", logo)
@@ -1,8 +1,10 @@
 use tokio::io::AsyncWriteExt;
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::net::{TcpStream, ToSocketAddrs, UnixStream};
 use crate::parser::cmd::error::CommandError::{*};
-use crate::parser::cmd::command::{RedisCommand};
-use crate::server::connection::Connection;
+use bytes::Bytes;
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::server::connection::{Connection, Stream};
+use crate::server::tls::ClientTlsConfig;
 use crate::parser::cmd::hash::HashCmd::{HDel, HGet, HSet};
 use crate::parser::cmd::string::StringCmd::{*};
 use crate::parser::cmd::conn::ConnCmd::{*};
@@ -21,6 +23,24 @@ impl Client {
         Ok(Client { conn: connection })
     }
 
+    /// Connects to `addr` ("host:port") over TLS, checking the server certificate
+    /// against the CA of `tls`.
+    pub async fn connect_tls(addr: &str, tls: &ClientTlsConfig) -> crate::Result<Client> {
+        let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+        let server_name = tls.server_name(host.trim_start_matches('[').trim_end_matches(']'))?;
+        let socket = TcpStream::connect(addr).await?;
+        let stream = tls.connector()?.connect(server_name, socket).await?;
+
+        Ok(Client { conn: Connection::new(Stream::from(stream)) })
+    }
+
+    /// Connects to the Unix socket at `path`.
+    pub async fn connect_unix(path: &str) -> crate::Result<Client> {
+        let socket = UnixStream::connect(path).await?;
+
+        Ok(Client { conn: Connection::new(Stream::from(socket)) })
+    }
+
     pub async fn benchmark_send_command(&mut self, mut buf: Vec<u8>) -> crate::Result<()>
     {
         self.conn.stream.write_all(buf.as_mut_slice()).await?;
@@ -155,14 +175,22 @@ impl Tokens {
                 Ok(RedisCommand::String(Set {key, value}))
             }
             "setex" => {
+                if self.token.len() != 4 {
+                    return Err(ArgsErr(cmd_name).into())
+                }
                 let key = self.token[1].to_string();
                 let ttl:i128 = self.token[2].to_string().parse()?;
-                Ok(RedisCommand::String(SetEX {key, ttl: ttl * 1000}))
+                let value = self.token[3].to_string();
+                Ok(RedisCommand::String(SetEX {key, ttl: ttl * 1000, value}))
             }
             "setpx" => {
+                if self.token.len() != 4 {
+                    return Err(ArgsErr(cmd_name).into())
+                }
                 let key = self.token[1].to_string();
                 let ttl:i128 = self.token[2].to_string().parse()?;
-                Ok(RedisCommand::String(SetPX {key, ttl}))
+                let value = self.token[3].to_string();
+                Ok(RedisCommand::String(SetPX {key, ttl, value}))
             }
             "setnx" => {
                 let key = self.token[1].to_string();
@@ -228,7 +256,11 @@ impl Tokens {
                 let member = self.token[2].to_string();
                 Ok(RedisCommand::SortSet(ZScore {key, member}))
             }
-            _ => Err(NotSupport(cmd_name).into())
+            _ => {
+                // everything else is parsed the same way the server parses it
+                let frame = Frame::Array(self.token.into_iter().map(|t| Frame::Bulk(Bytes::from(t))).collect());
+                RedisCommand::from_frame(&cmd_name, frame)
+            }
         }
     }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use std::io::{Read};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value};
 use json_comments::StripComments;
+use crate::server::tls::ClientTlsConfig;
 
 
 pub const CONFIG_PATH_TOML: &str = "./config.toml";
@@ -14,6 +15,16 @@ pub struct ClientConfig {
     server_ip: String,
     #[serde(default = "server_port")]
     server_port: u16,
+    /// Connect over TLS, trusting the CA in `tls_ca_cert_file`
+    #[serde(default)]
+    tls: bool,
+    #[serde(default)]
+    tls_ca_cert_file: String,
+    /// Certificate and key for servers that authenticate their clients
+    #[serde(default)]
+    tls_cert_file: Option<String>,
+    #[serde(default)]
+    tls_key_file: Option<String>,
 }
 
 impl Default for ClientConfig {
@@ -21,6 +32,10 @@ impl Default for ClientConfig {
         Self {
             server_port: server_port(),
             server_ip: server_ip(),
+            tls: false,
+            tls_ca_cert_file: String::new(),
+            tls_cert_file: None,
+            tls_key_file: None,
         }
     }
 }
@@ -70,6 +85,16 @@ impl ClientConfig {
         }
     }
 
+    /// TLS settings of the connection, None for plaintext
+    pub fn tls_config(&self) -> Option<ClientTlsConfig> {
+        self.tls.then(|| ClientTlsConfig {
+            ca_cert_file: self.tls_ca_cert_file.clone(),
+            cert_file: self.tls_cert_file.clone(),
+            key_file: self.tls_key_file.clone(),
+            server_name: None,
+        })
+    }
+
     pub fn get_value(&self, k: &str) -> Option<String> {
         let str = serde_json::to_string(&self).unwrap();
         let mut value = serde_json::from_str::<Value>(&str).unwrap();
@@ -1,4 +1,475 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use tokio::net::TcpStream;
+use tracing::error;
+
+use crate::config::ServerConfig;
 use crate::db::crc::crc16::crc16;
+use crate::parser::frame::Frame;
+use crate::server::connection::{Connection, Stream};
+use crate::util::yesnotoi;
+
+/// Number of hash slots the keys are spread over
+pub const CLUSTER_SLOTS: usize = 16384;
+/// log2 of `CLUSTER_SLOTS`: in cluster mode each KvStore of a DB has one dict per slot
+pub const CLUSTER_SLOT_BITS: u64 = 14;
+/// Offset from the client port of the cluster bus port shown in CLUSTER NODES
+const CLUSTER_PORT_INCR: u32 = 10000;
+
+static CLUSTER_ENABLED: AtomicBool = AtomicBool::new(false);
+static CLUSTER: Mutex<Option<ClusterState>> = Mutex::new(None);
+
+/// A node of the cluster, this one included
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterNode {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+}
+
+impl ClusterNode {
+    /// "<ip>:<port>", as in -MOVED and -ASK
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+/// Change of the owner of a slot (CLUSTER SETSLOT)
+#[derive(Debug, Clone)]
+pub enum SetSlot {
+    /// The slot moves from this node to the node: keys not here anymore are asked from it
+    Migrating(String),
+    /// The slot moves from the node to this one: keys are served to clients sending ASKING
+    Importing(String),
+    /// Ends a migration or import
+    Stable,
+    /// Gives the slot to the node
+    Node(String),
+}
+
+/// Who serves each slot, as this node knows it. Nodes don't talk to each other: the map
+/// is loaded from `cluster-config-file` and changed with CLUSTER MEET, ADDSLOTS and
+/// SETSLOT sent to each node.
+struct ClusterState {
+    /// This node comes first
+    nodes: Vec<ClusterNode>,
+    /// Index in `nodes` of the owner of each slot
+    slots: Vec<Option<usize>>,
+    /// Slots of this node moving to another one, with the index of the target
+    migrating: HashMap<usize, usize>,
+    /// Slots of another node moving to this one, with the index of the source
+    importing: HashMap<usize, usize>,
+    config_file: String,
+}
+
+impl ClusterState {
+    fn new(myself: ClusterNode, config_file: String) -> Self {
+        ClusterState { nodes: vec![myself], slots: vec![None; CLUSTER_SLOTS], migrating: HashMap::new(), importing: HashMap::new(), config_file }
+    }
+
+    /// Loads the nodes written by `describe`. The address of this node is the one it
+    /// listens on now.
+    fn parse(content: &str, myself: ClusterNode, config_file: String) -> crate::Result<Self> {
+        let mut state = ClusterState::new(myself, config_file);
+        let mut moving = vec![];
+        let mut found_myself = false;
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with("vars ")) {
+            let (node, ranges, is_myself) = parse_node_line(line).ok_or_else(|| format!("invalid cluster node line '{}'", line))?;
+            let idx = if is_myself {
+                found_myself = true;
+                state.nodes[0].id = node.id;
+                0
+            } else {
+                state.nodes.push(node);
+                state.nodes.len() - 1
+            };
+            for range in ranges {
+                match range {
+                    SlotRange::Owned(start, end) => (start..=end).for_each(|slot| state.slots[slot] = Some(idx)),
+                    SlotRange::Migrating(slot, id) => moving.push((slot, id, true)),
+                    SlotRange::Importing(slot, id) => moving.push((slot, id, false)),
+                }
+            }
+        }
+        if !found_myself {
+            return Err("no myself node in the cluster config".into());
+        }
+        for (slot, id, migrating) in moving {
+            let idx = state.node_index(&id).ok_or_else(|| format!("unknown node {} in the cluster config", id))?;
+            if migrating {
+                state.migrating.insert(slot, idx);
+            } else {
+                state.importing.insert(slot, idx);
+            }
+        }
+        Ok(state)
+    }
+
+    fn node_index(&self, id: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    /// The slots of node `idx` as sorted ranges of consecutive slots
+    fn ranges(&self, idx: usize) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for slot in (0..CLUSTER_SLOTS).filter(|&slot| self.slots[slot] == Some(idx)) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == slot => *end = slot,
+                _ => ranges.push((slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    /// One line per node, as in CLUSTER NODES and in `cluster-config-file`
+    fn describe(&self) -> String {
+        let mut out = String::new();
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let flags = if idx == 0 { "myself,master" } else { "master" };
+            out.push_str(&format!(
+                "{} {}:{}@{} {} - 0 0 0 connected",
+                node.id,
+                node.ip,
+                node.port,
+                node.port as u32 + CLUSTER_PORT_INCR,
+                flags
+            ));
+            for (start, end) in self.ranges(idx) {
+                if start == end {
+                    out.push_str(&format!(" {}", start));
+                } else {
+                    out.push_str(&format!(" {}-{}", start, end));
+                }
+            }
+            if idx == 0 {
+                let mut migrating: Vec<_> = self.migrating.iter().collect();
+                migrating.sort();
+                for (slot, target) in migrating {
+                    out.push_str(&format!(" [{}->-{}]", slot, self.nodes[*target].id));
+                }
+                let mut importing: Vec<_> = self.importing.iter().collect();
+                importing.sort();
+                for (slot, source) in importing {
+                    out.push_str(&format!(" [{}-<-{}]", slot, self.nodes[*source].id));
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Writes the nodes to `cluster-config-file`, so the ID of this node and the slot
+    /// map survive a restart.
+    fn save(&self) -> crate::Result<()> {
+        let tmp = format!("{}.tmp", self.config_file);
+        std::fs::write(&tmp, format!("{}vars currentEpoch 0 lastVoteEpoch 0\n", self.describe()))?;
+        std::fs::rename(&tmp, &self.config_file)?;
+        Ok(())
+    }
+
+    fn save_or_log(&self) {
+        if let Err(err) = self.save() {
+            error!(cause = %err, "failed to save the cluster config");
+        }
+    }
+}
+
+/// Slots listed on a line of `cluster-config-file`
+#[derive(Debug, PartialEq)]
+enum SlotRange {
+    Owned(usize, usize),
+    /// `[<slot>->-<target id>]`
+    Migrating(usize, String),
+    /// `[<slot>-<-<source id>]`
+    Importing(usize, String),
+}
+
+fn parse_slot(slot: &str) -> Option<usize> {
+    slot.parse().ok().filter(|&slot| slot < CLUSTER_SLOTS)
+}
+
+/// Parses "<id> <ip>:<port>@<cport> <flags> <master> <ping> <pong> <epoch> <link> <slots>...",
+/// a line of CLUSTER NODES. Returns the node, its slots and whether it is this node.
+fn parse_node_line(line: &str) -> Option<(ClusterNode, Vec<SlotRange>, bool)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 8 {
+        return None;
+    }
+    let addr = fields[1].split('@').next()?;
+    let (ip, port) = addr.rsplit_once(':')?;
+    let node = ClusterNode { id: fields[0].to_string(), ip: ip.to_string(), port: port.parse().ok()? };
+    let is_myself = fields[2].split(',').any(|flag| flag == "myself");
+    let mut ranges = vec![];
+    for range in &fields[8..] {
+        let range = if let Some(moving) = range.strip_prefix('[').and_then(|range| range.strip_suffix(']')) {
+            if let Some((slot, id)) = moving.split_once("->-") {
+                SlotRange::Migrating(parse_slot(slot)?, id.to_string())
+            } else {
+                let (slot, id) = moving.split_once("-<-")?;
+                SlotRange::Importing(parse_slot(slot)?, id.to_string())
+            }
+        } else if let Some((start, end)) = range.split_once('-') {
+            let (start, end) = (parse_slot(start)?, parse_slot(end)?);
+            if start > end {
+                return None;
+            }
+            SlotRange::Owned(start, end)
+        } else {
+            let slot = parse_slot(range)?;
+            SlotRange::Owned(slot, slot)
+        };
+        ranges.push(range);
+    }
+    Some((node, ranges, is_myself))
+}
+
+fn new_node_id() -> String {
+    (0..40).map(|_| char::from_digit(rand::random_range(0..16), 16).unwrap()).collect()
+}
+
+/// Turns on cluster mode when `cluster-enabled` is yes, loading the slot map from
+/// `cluster-config-file` or creating a node owning no slot. Called before the DBs are
+/// created, they hold one dict per slot in cluster mode.
+pub fn load_cluster_config(config: &ServerConfig) -> crate::Result<()> {
+    let enabled = yesnotoi(&config.cluster_enabled)
+        .ok_or_else(|| format!("invalid cluster-enabled '{}'", config.cluster_enabled))?;
+    if !enabled {
+        return Ok(());
+    }
+    let ip = if config.bind == "0.0.0.0" { "127.0.0.1" } else { &config.bind };
+    let myself = ClusterNode { id: new_node_id(), ip: ip.to_string(), port: config.port };
+    let config_file = config.cluster_config_file.clone();
+    let state = match std::fs::read_to_string(&config_file) {
+        Ok(content) => ClusterState::parse(&content, myself, config_file)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => ClusterState::new(myself, config_file),
+        Err(err) => return Err(format!("can't read the cluster config '{}': {}", config_file, err).into()),
+    };
+    state.save()?;
+    *CLUSTER.lock().unwrap() = Some(state);
+    CLUSTER_ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn cluster_enabled() -> bool {
+    CLUSTER_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runs `f` on the slot map, which exists in cluster mode only
+fn with_cluster<T>(f: impl FnOnce(&mut ClusterState) -> crate::Result<T>) -> crate::Result<T> {
+    match CLUSTER.lock().unwrap().as_mut() {
+        Some(state) => f(state),
+        None => Err("ERR This instance has cluster support disabled".into()),
+    }
+}
+
+/// Where the keys of a command are served
+#[derive(Debug, PartialEq)]
+pub enum KeyRoute {
+    Local,
+    /// The slot of the keys is migrating from this node to `target`: keys already
+    /// moved are asked from it
+    Migrating { slot: usize, target: String },
+}
+
+/// Checks the keys of a command against the slot map. Fails with the error sent back
+/// instead of serving it: -CROSSSLOT for keys of several slots, -MOVED to the owner of
+/// a slot of another node, unless the slot is imported and the client sent ASKING.
+pub fn route_keys(keys: &[&str], asking: bool) -> Result<KeyRoute, String> {
+    let Some(first) = keys.first() else { return Ok(KeyRoute::Local) };
+    let slot = key_hash_slot(first) as usize;
+    if keys.iter().any(|key| key_hash_slot(key) as usize != slot) {
+        return Err("CROSSSLOT Keys in request don't hash to the same slot".to_string());
+    }
+    let guard = CLUSTER.lock().unwrap();
+    let Some(state) = guard.as_ref() else { return Ok(KeyRoute::Local) };
+    match state.slots[slot] {
+        None if asking && state.importing.contains_key(&slot) => Ok(KeyRoute::Local),
+        None => Err("CLUSTERDOWN Hash slot not served".to_string()),
+        Some(0) => match state.migrating.get(&slot) {
+            Some(&target) => Ok(KeyRoute::Migrating { slot, target: state.nodes[target].addr() }),
+            None => Ok(KeyRoute::Local),
+        },
+        Some(_) if asking && state.importing.contains_key(&slot) => Ok(KeyRoute::Local),
+        Some(owner) => Err(format!("MOVED {} {}", slot, state.nodes[owner].addr())),
+    }
+}
+
+pub fn myself() -> crate::Result<ClusterNode> {
+    with_cluster(|state| Ok(state.nodes[0].clone()))
+}
+
+/// CLUSTER NODES
+pub fn describe_nodes() -> crate::Result<String> {
+    with_cluster(|state| Ok(state.describe()))
+}
+
+/// A node and the inclusive slot ranges it serves
+pub type NodeSlots = (ClusterNode, Vec<(usize, usize)>);
+
+/// Every node with its slot ranges, for CLUSTER SLOTS and SHARDS
+pub fn nodes_slots() -> crate::Result<Vec<NodeSlots>> {
+    with_cluster(|state| Ok(state.nodes.iter().enumerate().map(|(idx, node)| (node.clone(), state.ranges(idx))).collect()))
+}
+
+/// The figures of CLUSTER INFO
+pub struct ClusterInfo {
+    pub slots_assigned: usize,
+    pub known_nodes: usize,
+    /// Nodes serving at least one slot
+    pub size: usize,
+}
+
+pub fn cluster_info() -> crate::Result<ClusterInfo> {
+    with_cluster(|state| {
+        let mut owners: Vec<usize> = state.slots.iter().flatten().copied().collect();
+        let slots_assigned = owners.len();
+        owners.sort_unstable();
+        owners.dedup();
+        Ok(ClusterInfo { slots_assigned, known_nodes: state.nodes.len(), size: owners.len() })
+    })
+}
+
+/// Gives `slots` to this node (CLUSTER ADDSLOTS), none of them if one is already owned.
+pub fn add_slots(slots: &[usize]) -> crate::Result<()> {
+    with_cluster(|state| {
+        for (i, &slot) in slots.iter().enumerate() {
+            if state.slots[slot].is_some() {
+                return Err(format!("ERR Slot {} is already busy", slot).into());
+            }
+            if slots[..i].contains(&slot) {
+                return Err(format!("ERR Slot {} specified multiple times", slot).into());
+            }
+        }
+        for &slot in slots {
+            state.slots[slot] = Some(0);
+            state.importing.remove(&slot);
+        }
+        state.save_or_log();
+        Ok(())
+    })
+}
+
+/// Leaves `slots` unassigned (CLUSTER DELSLOTS), none of them if one already is.
+pub fn del_slots(slots: &[usize]) -> crate::Result<()> {
+    with_cluster(|state| {
+        for (i, &slot) in slots.iter().enumerate() {
+            if state.slots[slot].is_none() {
+                return Err(format!("ERR Slot {} is already unassigned", slot).into());
+            }
+            if slots[..i].contains(&slot) {
+                return Err(format!("ERR Slot {} specified multiple times", slot).into());
+            }
+        }
+        for &slot in slots {
+            state.slots[slot] = None;
+            state.migrating.remove(&slot);
+            state.importing.remove(&slot);
+        }
+        state.save_or_log();
+        Ok(())
+    })
+}
+
+/// Whether this node owns `slot`
+pub fn owns_slot(slot: usize) -> crate::Result<bool> {
+    with_cluster(|state| Ok(state.slots[slot] == Some(0)))
+}
+
+/// CLUSTER SETSLOT. Giving away a slot this node still holds keys of is refused by the
+/// caller, which can count them.
+pub fn set_slot(slot: usize, change: SetSlot) -> crate::Result<()> {
+    with_cluster(|state| {
+        let known = |state: &ClusterState, id: &str| {
+            state.node_index(id).ok_or_else(|| crate::Error::from(format!("ERR I don't know about node {}", id)))
+        };
+        match change {
+            SetSlot::Migrating(id) => {
+                if state.slots[slot] != Some(0) {
+                    return Err(format!("ERR I'm not the owner of hash slot {}", slot).into());
+                }
+                let target = known(state, &id)?;
+                if target == 0 {
+                    return Err("ERR Target node is myself".into());
+                }
+                state.migrating.insert(slot, target);
+            }
+            SetSlot::Importing(id) => {
+                if state.slots[slot] == Some(0) {
+                    return Err(format!("ERR I'm already the owner of hash slot {}", slot).into());
+                }
+                let source = known(state, &id)?;
+                if source == 0 {
+                    return Err("ERR Source node is myself".into());
+                }
+                state.importing.insert(slot, source);
+            }
+            SetSlot::Stable => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+            SetSlot::Node(id) => {
+                let owner = known(state, &id)?;
+                state.slots[slot] = Some(owner);
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+        }
+        state.save_or_log();
+        Ok(())
+    })
+}
+
+/// CLUSTER SAVECONFIG
+pub fn save_config() -> crate::Result<()> {
+    with_cluster(|state| state.save().map_err(|err| format!("ERR error saving the cluster node config: {}", err).into()))
+}
+
+/// Adds the node at `ip:port` to the slot map (CLUSTER MEET), asking it for its ID and
+/// slots with CLUSTER NODES. The slots it owns that no node serves here are given to it.
+/// The other node is not told about this one, it is met from each node.
+pub async fn meet(ip: &str, port: u16) -> crate::Result<()> {
+    let socket = TcpStream::connect((ip, port)).await.map_err(|err| format!("ERR Can't connect to {}:{}: {}", ip, port, err))?;
+    let mut conn = Connection::<Stream>::new(socket);
+    let args = ["CLUSTER", "NODES"].iter().map(|arg| Frame::Bulk(Bytes::from_static(arg.as_bytes()))).collect();
+    conn.write_frame(&Frame::Array(args)).await?;
+    let nodes = match conn.read_frame().await?.ok_or("ERR connection closed by the node")? {
+        Frame::Bulk(nodes) => String::from_utf8_lossy(&nodes).to_string(),
+        reply => return Err(format!("ERR the node replied to CLUSTER NODES with {}", reply).into()),
+    };
+    let (node, ranges, _) = nodes
+        .lines()
+        .filter_map(parse_node_line)
+        .find(|(_, _, is_myself)| *is_myself)
+        .ok_or("ERR the node did not describe itself")?;
+    // the node as reached, it may know itself under another address
+    let node = ClusterNode { ip: ip.to_string(), port, ..node };
+    with_cluster(|state| {
+        let idx = match state.node_index(&node.id) {
+            Some(0) => return Err("ERR Can't meet myself".into()),
+            Some(idx) => {
+                state.nodes[idx] = node;
+                idx
+            }
+            None => {
+                state.nodes.push(node);
+                state.nodes.len() - 1
+            }
+        };
+        for range in ranges {
+            if let SlotRange::Owned(start, end) = range {
+                for slot in start..=end {
+                    state.slots[slot].get_or_insert(idx);
+                }
+            }
+        }
+        state.save_or_log();
+        Ok(())
+    })
+}
+
 #[inline]
 pub fn key_hash_slot(key: &str) -> u32 {
     let key_vec = key.as_bytes();
@@ -25,3 +496,44 @@ pub fn key_hash_slot(key: &str) -> u32 {
     }
     (crc16(&key_vec[s + 1..e]) & 0x3FFF) as u32
 }
+
+#[cfg(test)]
+mod test {
+    use crate::cluster::cluster::{key_hash_slot, parse_node_line, ClusterNode, ClusterState, SlotRange};
+
+    #[test]
+    fn hash_tags() {
+        assert_eq!(key_hash_slot("foo"), 12182);
+        assert_eq!(key_hash_slot("{user1000}.following"), key_hash_slot("{user1000}.followers"));
+        assert_eq!(key_hash_slot("{user1000}.following"), key_hash_slot("user1000"));
+        // an empty tag hashes the whole key
+        assert_ne!(key_hash_slot("foo{}{bar}"), key_hash_slot("bar"));
+        assert_eq!(key_hash_slot("foo{{bar}}zap"), key_hash_slot("{bar"));
+    }
+
+    #[test]
+    fn nodes_config() {
+        let myself = ClusterNode { id: "a".repeat(40), ip: "127.0.0.1".to_string(), port: 7000 };
+        let content = format!(
+            "{} 127.0.0.1:7001@17001 master - 0 0 0 connected 5-10 12\n{} 127.0.0.1:6999@16999 myself,master - 0 0 0 connected 0-4 11 [11->-{}]\nvars currentEpoch 0 lastVoteEpoch 0\n",
+            "b".repeat(40),
+            "c".repeat(40),
+            "b".repeat(40)
+        );
+        let state = ClusterState::parse(&content, myself, "nodes.conf".to_string()).unwrap();
+        assert_eq!(state.nodes[0].id, "c".repeat(40));
+        assert_eq!(state.nodes[0].port, 7000);
+        assert_eq!(state.ranges(0), vec![(0, 4), (11, 11)]);
+        assert_eq!(state.ranges(1), vec![(5, 10), (12, 12)]);
+        assert_eq!(state.migrating.get(&11), Some(&1));
+        let described = state.describe();
+        let (node, ranges, is_myself) = parse_node_line(described.lines().next().unwrap()).unwrap();
+        assert!(is_myself);
+        assert_eq!(node.port, 7000);
+        assert_eq!(
+            ranges,
+            vec![SlotRange::Owned(0, 4), SlotRange::Owned(11, 11), SlotRange::Migrating(11, "b".repeat(40))]
+        );
+        assert!(parse_node_line("x 127.0.0.1:7000 master - 0 0 0 connected 16384").is_none());
+    }
+}
@@ -2,76 +2,250 @@ use std::fs::File;
 use std::io::Read;
 use json_comments::StripComments;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing_subscriber::filter::LevelFilter;
 use crate::client::config::{ClientConfig, CONFIG_PATH_JSON, CONFIG_PATH_TOML};
 use crate::persistence::rdb_config::{RdbConfig, SaveParam};
+use crate::server::config::lookup_param;
+use crate::util::memtoull;
+use crate::DEFAULT_PORT;
 
 pub const SERVER_CONFIG_JSON: &str = "./server_config.json";
 pub const SERVER_CONFIG_TOML: &str = "./server_config.toml";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ServerConfig {
+    /// Address and port clients connect to
+    pub bind: String,
+    pub port: u16,
+    /// Working directory of the server, where relative paths such as the RDB file
+    /// are resolved. None keeps the one it was started in
+    pub dir: Option<String>,
+    /// "debug", "verbose", "notice", "warning" or "nothing"
+    pub loglevel: String,
+    /// File the pid of the server is written to while it runs
+    pub pidfile: Option<String>,
+    /// Seconds SHUTDOWN waits for lagging replicas before stopping, 0 does not wait
+    pub shutdown_timeout: u64,
     pub db_num: u32,
-    pub hash_max_ziplist_entries: usize,
-    pub hash_max_ziplist_value: usize,
+    /// Hashes with more fields, or a longer field or value, leave the listpack
+    /// encoding for a hash table
+    #[serde(alias = "hash_max_ziplist_entries")]
+    pub hash_max_listpack_entries: usize,
+    #[serde(alias = "hash_max_ziplist_value")]
+    pub hash_max_listpack_value: usize,
+    /// Sets of integers stay an intset up to this many members
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    /// Sorted sets leave the listpack encoding for a skiplist past these limits
+    #[serde(alias = "zset_max_ziplist_entries")]
+    pub zset_max_listpack_entries: usize,
+    #[serde(alias = "zset_max_ziplist_value")]
+    pub zset_max_listpack_value: usize,
     pub rdb_config: RdbConfig,
+    /// Save rules as `<seconds> <changes>` pairs, e.g. "3600 1 300 100". Replaces the
+    /// ones of `rdb_config` when set, an empty string disables saving
+    pub save: Option<String>,
+    /// Classes of keyspace events to publish, see `server::notify`
+    pub notify_keyspace_events: String,
+    /// Memory limit in bytes, 0 for none. See `db::evict`
+    pub maxmemory: u64,
+    /// How keys are picked for eviction once `maxmemory` is reached
+    pub maxmemory_policy: String,
+    /// Keys sampled per eviction by the LRU, LFU and TTL policies
+    pub maxmemory_samples: usize,
+    pub lfu_log_factor: i32,
+    /// Minutes after which the access counter of an idle key is decremented
+    pub lfu_decay_time: u64,
+    /// Address the Prometheus `/metrics` endpoint listens on, e.g. "127.0.0.1:9121".
+    /// None disables it
+    pub metrics_addr: Option<String>,
+    /// Commands taking longer than this many microseconds are logged, see
+    /// `server::slowlog`. Negative disables the slow log
+    pub slowlog_log_slower_than: i64,
+    pub slowlog_max_len: usize,
+    /// Internal events taking at least this many milliseconds are sampled by the
+    /// latency monitor, see `server::latency`. 0 disables it
+    pub latency_monitor_threshold: u64,
+    /// Seconds after which idle clients are closed, 0 never closes them. Pub/sub
+    /// and blocked clients are never idle
+    pub timeout: u64,
+    /// Seconds between TCP keepalive probes to the clients, 0 disables them
+    pub tcp_keepalive: u64,
+    /// Connections past this many are refused
+    pub maxclients: usize,
+    /// Bytes a client may send without them forming a whole command before it is closed
+    pub client_query_buffer_limit: usize,
+    /// `<class> <hard> <soft> <soft seconds>` for the normal, replica and pubsub
+    /// classes, see `server::clients::OutputBufferLimit`
+    pub client_output_buffer_limit: String,
+    /// Password of the default user, empty to let connections in without AUTH
+    pub requirepass: String,
+    /// File the ACL users are loaded from and saved to, see `server::acl`
+    pub aclfile: Option<String>,
+    /// Entries kept by ACL LOG
+    pub acllog_max_len: usize,
+    /// Port TLS clients connect to, 0 disables TLS. See `server::tls`
+    pub tls_port: u16,
+    /// PEM certificate chain and private key of the server
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    /// PEM certificates of the CAs client certificates must be signed by
+    pub tls_ca_cert_file: Option<String>,
+    /// "yes" to require a client certificate, "optional" to check one if sent, "no"
+    pub tls_auth_clients: String,
+    /// Path of a Unix socket to also listen on, None for TCP only
+    pub unixsocket: Option<String>,
+    /// Octal permissions of the Unix socket, e.g. "700". Empty keeps the umask ones
+    pub unixsocketperm: String,
+    /// Master to replicate as "<host> <port>", None to run as a primary
+    pub replicaof: Option<String>,
+    /// "yes" to refuse writes from the clients of a replica
+    pub replica_read_only: String,
+    /// User and password a replica authenticates to its master with
+    pub masteruser: String,
+    pub masterauth: String,
+    /// Bytes of the stream of writes kept for replicas resuming with a partial resync
+    pub repl_backlog_size: u64,
+    /// "yes" to run as a cluster node, keys spread over 16384 hash slots
+    pub cluster_enabled: String,
+    /// Where a cluster node keeps its ID and the slot map, written by the server
+    pub cluster_config_file: String,
+    /// File the config was loaded from, which CONFIG REWRITE writes back to
+    #[serde(skip)]
+    pub config_file: Option<String>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
+            bind: "0.0.0.0".to_string(),
+            port: DEFAULT_PORT,
+            dir: None,
+            loglevel: "notice".to_string(),
+            pidfile: None,
+            shutdown_timeout: 10,
             db_num: 1,
-            hash_max_ziplist_entries: 512,
-            hash_max_ziplist_value: 64,
+            hash_max_listpack_entries: 512,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
             rdb_config: RdbConfig::default(),
+            save: None,
+            notify_keyspace_events: String::new(),
+            maxmemory: 0,
+            maxmemory_policy: "noeviction".to_string(),
+            maxmemory_samples: 5,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+            metrics_addr: None,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            timeout: 0,
+            tcp_keepalive: 300,
+            maxclients: 10000,
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            client_output_buffer_limit: "normal 0 0 0 replica 256mb 64mb 60 pubsub 32mb 8mb 60".to_string(),
+            requirepass: String::new(),
+            aclfile: None,
+            acllog_max_len: 128,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: "yes".to_string(),
+            unixsocket: None,
+            unixsocketperm: String::new(),
+            replicaof: None,
+            replica_read_only: "yes".to_string(),
+            masteruser: String::new(),
+            masterauth: String::new(),
+            repl_backlog_size: 1024 * 1024,
+            cluster_enabled: "no".to_string(),
+            cluster_config_file: "nodes.conf".to_string(),
+            config_file: None,
         }
     }
 }
 
 impl ServerConfig {
-    pub fn new(path: Option<&str>) -> Self {
-        let config_path_show;
-        let mut file = if let Some(path) = path {
-            config_path_show = path;
-            if let Ok(file) = File::open(&path) {
-                file
-            } else {
-                println!("Config File: {} Read Fail, Use Default Config. ", config_path_show);
-                return ServerConfig::default();
-            }
-        } else {
-            if let Ok(file) = File::open(SERVER_CONFIG_JSON) {
-                config_path_show = SERVER_CONFIG_JSON;
-                file
-            } else {
-                if let Ok(file) = File::open(SERVER_CONFIG_TOML) {
-                    config_path_show = SERVER_CONFIG_TOML;
-                    file
-                } else {
-                    println!("Config File: {} Read Fail, Use Default Config. ", SERVER_CONFIG_JSON);
-                    return ServerConfig::default();
-                }
-            }
+    /// Loads the config file at `path`, failing if it can't be read or parsed. Without
+    /// a path, ./server_config.json or ./server_config.toml is used if it can be loaded
+    /// and the default config otherwise.
+    pub fn new(path: Option<&str>) -> crate::Result<Self> {
+        if let Some(path) = path {
+            return Self::load(path);
+        }
+        let Some(path) = [SERVER_CONFIG_JSON, SERVER_CONFIG_TOML].into_iter().find(|path| File::open(path).is_ok()) else {
+            println!("Config File: {} Read Fail, Use Default Config. ", SERVER_CONFIG_JSON);
+            return Ok(ServerConfig::default());
         };
+        Ok(Self::load(path).unwrap_or_else(|e| {
+            println!("Config File: {} Read Fail {e}, Use Default Config. ", path);
+            ServerConfig::default()
+        }))
+    }
+
+    /// Reads the TOML or JSON config file at `path`.
+    fn load(path: &str) -> crate::Result<Self> {
         let mut config_string = String::new();
-        if let Err(e) = file.read_to_string(&mut config_string) {
-            println!("Config File: {} Read Fail{e}, Use Default Config. ", config_path_show);
-            return ServerConfig::default();
-        }
-        println!("Config File: {}", config_path_show);
-        if let Ok(config) = toml::from_str(&config_string) {
-            config
-        } else {
-            let config_string = StripComments::new(config_string.as_bytes());
-            match serde_json::from_reader(config_string) {
-                Ok(data) => data,
-                Err(e) => {
-                    println!("Config File: {} Read Fail{e}, Use Default Config.", config_path_show);
-                    ServerConfig::default()
-                }
-            }
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut config_string))
+            .map_err(|e| format!("can't read config file '{}': {}", path, e))?;
+        let config: ServerConfig = match toml::from_str(&config_string) {
+            Ok(config) => config,
+            Err(_) => serde_json::from_reader(StripComments::new(config_string.as_bytes()))
+                .map_err(|e| format!("invalid config file '{}': {}", path, e))?,
+        };
+        println!("Config File: {}", path);
+        // absolute, so CONFIG REWRITE still finds it once `dir` changed the working directory
+        let config_file = std::fs::canonicalize(path).map_or(path.to_string(), |path| path.display().to_string());
+        Ok(ServerConfig { config_file: Some(config_file), ..config })
+    }
+
+    /// Sets the setting `name`, e.g. "maxmemory" or "hash-max-ziplist-entries", from a
+    /// command line override. `value` is read as the type of the setting.
+    pub fn set_param(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        let name = lookup_param(name).map_or(name, |param| param.name);
+        let key = if name == "databases" { "db_num".to_string() } else { name.replace('-', "_") };
+        let Value::Object(mut fields) = serde_json::to_value(&*self)? else { unreachable!() };
+        let field = fields.get_mut(&key).ok_or_else(|| format!("Bad directive or wrong number of arguments '{}'", name))?;
+        *field = match field {
+            Value::Number(_) => match value.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => Value::from(memtoull(value).ok_or_else(|| format!("invalid {} '{}'", name, value))?),
+            },
+            Value::String(_) | Value::Null => Value::String(value.to_string()),
+            _ => return Err(format!("{} can't be set from the command line", name).into()),
+        };
+        let config_file = self.config_file.take();
+        *self = serde_json::from_value(Value::Object(fields)).map_err(|err| format!("invalid {} '{}': {}", name, value, err))?;
+        self.config_file = config_file;
+        Ok(())
+    }
+
+    /// The most verbose level logged at `loglevel`
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        match &self.loglevel.to_lowercase()[..] {
+            "debug" => Some(LevelFilter::DEBUG),
+            "verbose" | "notice" => Some(LevelFilter::INFO),
+            "warning" => Some(LevelFilter::WARN),
+            "nothing" => Some(LevelFilter::OFF),
+            _ => None,
         }
     }
 
+    /// Whether the file sets any save rule, in `save` or `rdb_config`
+    pub fn has_save_params(&self) -> bool {
+        self.save.is_some() || !self.get_param().is_empty()
+    }
+
     pub fn set_rdb_save_param(&mut self, seconds: u64, changes: usize) {
         self.rdb_config.set_save_params(seconds, changes);
     }
@@ -79,4 +253,46 @@ impl ServerConfig {
     pub fn get_param(&self) -> &Vec<SaveParam> {
         self.rdb_config.get_save_params()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use crate::config::ServerConfig;
+
+    #[test]
+    fn set_param() {
+        let mut config = ServerConfig::default();
+        config.set_param("maxmemory", "1mb").unwrap();
+        config.set_param("hash-max-ziplist-entries", "16").unwrap();
+        config.set_param("slowlog_log_slower_than", "-1").unwrap();
+        config.set_param("databases", "4").unwrap();
+        config.set_param("unixsocket", "/tmp/redis.sock").unwrap();
+        config.set_param("maxmemory-policy", "allkeys-lru").unwrap();
+        assert_eq!(config.maxmemory, 1024 * 1024);
+        assert_eq!(config.hash_max_listpack_entries, 16);
+        assert_eq!(config.slowlog_log_slower_than, -1);
+        assert_eq!(config.db_num, 4);
+        assert_eq!(config.unixsocket.as_deref(), Some("/tmp/redis.sock"));
+        assert_eq!(config.maxmemory_policy, "allkeys-lru");
+        assert!(config.set_param("no-such-param", "1").is_err());
+        assert!(config.set_param("port", "-1").is_err());
+        assert!(config.set_param("rdb-config", "x").is_err());
+    }
+
+    #[test]
+    fn explicit_config_file() {
+        let dir = std::env::temp_dir().join(format!("tinyredis-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let valid = dir.join("valid.json");
+        std::fs::write(&valid, r#"{"port": 7777, "maxmemory": 1024}"#).unwrap();
+        let invalid = dir.join("invalid.json");
+        std::fs::write(&invalid, "{ port: ").unwrap();
+
+        let config = ServerConfig::new(valid.to_str()).unwrap();
+        assert_eq!(config.port, 7777);
+        assert_eq!(config.maxmemory, 1024);
+        assert!(config.config_file.is_some());
+        assert!(ServerConfig::new(invalid.to_str()).is_err());
+        assert!(ServerConfig::new(dir.join("missing.json").to_str()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
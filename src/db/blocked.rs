@@ -0,0 +1,352 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+use tracing::debug;
+
+use crate::db::db::RedisDb;
+use crate::parser::cmd::command::RedisCommand;
+use crate::db::data_structure::stream::StreamID;
+use crate::parser::cmd::list::{ListCmd, ListEnd};
+use crate::parser::cmd::stream::{StreamCmd, StreamReadId};
+use crate::parser::cmd::zset::SortedCmd;
+use crate::parser::frame::Frame;
+use crate::server::replication;
+
+/// What a blocked client does with a key once it holds data
+#[derive(Debug, Clone)]
+pub enum BlockOp {
+    /// BLPOP / BRPOP
+    ListPop { end: ListEnd },
+    /// BLMPOP / LMPOP
+    ListMPop { end: ListEnd, count: usize },
+    /// BLMOVE / LMOVE
+    ListMove { destination: String, from: ListEnd, to: ListEnd },
+    /// BZPOPMIN / BZPOPMAX
+    ZPop { max: bool },
+    /// BZMPOP / ZMPOP
+    ZMPop { max: bool, count: usize },
+    /// XREAD BLOCK, with the ID to read after for every stream
+    XRead { streams: Vec<(String, StreamID)>, count: Option<usize> },
+    /// XREADGROUP BLOCK with `>`
+    XReadGroup { group: String, consumer: String, count: Option<usize>, noack: bool },
+}
+
+impl BlockOp {
+    /// Serves the operation from `key`. Returns `None` when the key holds no data,
+    /// in which case nothing was modified and the client should keep waiting.
+    pub fn serve(&self, db: &mut RedisDb, key: &str) -> Option<crate::Result<Frame>> {
+        let res = match self {
+            BlockOp::ListPop { end } => ListCmd::pop_generic(db, key, *end, 1).map(|values| {
+                values.map(|mut values| {
+                    Frame::Array(vec![bulk(key.to_string()), bulk(values.remove(0))])
+                })
+            }),
+            BlockOp::ListMPop { end, count } => ListCmd::pop_generic(db, key, *end, *count).map(|values| {
+                values.map(|values| {
+                    let values = values.into_iter().map(bulk).collect();
+                    Frame::Array(vec![bulk(key.to_string()), Frame::Array(values)])
+                })
+            }),
+            BlockOp::ListMove { destination, from, to } => {
+                ListCmd::move_generic(db, key, destination, *from, *to).map(|value| value.map(bulk))
+            }
+            BlockOp::ZPop { max } => SortedCmd::zset_pop(db, key, *max, 1).map(|members| {
+                members.map(|mut members| {
+                    let (member, score) = members.remove(0);
+                    Frame::Array(vec![bulk(key.to_string()), bulk(member), bulk(score.to_string())])
+                })
+            }),
+            BlockOp::ZMPop { max, count } => SortedCmd::zset_pop(db, key, *max, *count).map(|members| {
+                members.map(|members| {
+                    let members = members
+                        .into_iter()
+                        .map(|(member, score)| Frame::Array(vec![bulk(member), bulk(score.to_string())]))
+                        .collect();
+                    Frame::Array(vec![bulk(key.to_string()), Frame::Array(members)])
+                })
+            }),
+            BlockOp::XRead { streams, count } => match streams.iter().find(|(k, _)| k == key) {
+                Some((_, id)) => StreamCmd::read_key(db, key, StreamReadId::Id(*id), *count)
+                    .map(|res| res.map(|res| Frame::Array(vec![res]))),
+                None => Ok(None),
+            },
+            BlockOp::XReadGroup { group, consumer, count, noack } => {
+                StreamCmd::read_group_key(db, key, group, consumer, StreamReadId::New, *count, *noack)
+                    .map(|res| res.map(|res| Frame::Array(vec![res])))
+            }
+        };
+        res.transpose()
+    }
+
+    /// The command a replica applies to end up where `serve` left `key`
+    pub fn propagated_command(&self, key: &str) -> Option<Frame> {
+        let pop = |end: &ListEnd| if *end == ListEnd::Left { "LPOP" } else { "RPOP" };
+        let zpop = |max: bool| if max { "ZPOPMAX" } else { "ZPOPMIN" };
+        let side = |end: &ListEnd| if *end == ListEnd::Left { "LEFT" } else { "RIGHT" };
+        let args: Vec<String> = match self {
+            BlockOp::ListPop { end } => vec![pop(end).into(), key.into()],
+            BlockOp::ListMPop { end, count } => vec![pop(end).into(), key.into(), count.to_string()],
+            BlockOp::ListMove { destination, from, to } => {
+                vec!["LMOVE".into(), key.into(), destination.clone(), side(from).into(), side(to).into()]
+            }
+            BlockOp::ZPop { max } => vec![zpop(*max).into(), key.into()],
+            BlockOp::ZMPop { max, count } => vec![zpop(*max).into(), key.into(), count.to_string()],
+            // reading modifies nothing
+            BlockOp::XRead { .. } => return None,
+            BlockOp::XReadGroup { group, consumer, count, noack } => {
+                let mut args = vec!["XREADGROUP".into(), "GROUP".into(), group.clone(), consumer.clone()];
+                if let Some(count) = count {
+                    args.extend(["COUNT".into(), count.to_string()]);
+                }
+                if *noack {
+                    args.push("NOACK".into());
+                }
+                args.extend(["STREAMS".into(), key.into(), ">".into()]);
+                args
+            }
+        };
+        Some(Frame::Array(args.into_iter().map(bulk).collect()))
+    }
+}
+
+fn bulk(s: String) -> Frame {
+    Frame::Bulk(Bytes::from(s.into_bytes()))
+}
+
+/// A blocking command as understood by the DB task: the keys to wait on, how long
+/// to wait (zero waits forever) and what to do once one of the keys holds data.
+pub struct BlockRequest {
+    pub keys: Vec<String>,
+    pub timeout: f64,
+    pub op: BlockOp,
+}
+
+impl BlockRequest {
+    pub fn from_command(cmd: RedisCommand, db: &mut RedisDb) -> crate::Result<Option<BlockRequest>> {
+        let (keys, timeout, op) = match cmd {
+            RedisCommand::List(ListCmd::BLPop { keys, timeout }) => (keys, timeout, BlockOp::ListPop { end: ListEnd::Left }),
+            RedisCommand::List(ListCmd::BRPop { keys, timeout }) => (keys, timeout, BlockOp::ListPop { end: ListEnd::Right }),
+            RedisCommand::List(ListCmd::BLMPop { keys, end, count, timeout }) => (keys, timeout, BlockOp::ListMPop { end, count }),
+            RedisCommand::List(ListCmd::BLMove { source, destination, from, to, timeout }) => {
+                (vec![source], timeout, BlockOp::ListMove { destination, from, to })
+            }
+            RedisCommand::SortSet(SortedCmd::BZPopMin { keys, timeout }) => (keys, timeout, BlockOp::ZPop { max: false }),
+            RedisCommand::SortSet(SortedCmd::BZPopMax { keys, timeout }) => (keys, timeout, BlockOp::ZPop { max: true }),
+            RedisCommand::SortSet(SortedCmd::BZMPop { keys, max, count, timeout }) => (keys, timeout, BlockOp::ZMPop { max, count }),
+            RedisCommand::Stream(cmd) if cmd.is_blocking() => cmd.block_request(db)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(BlockRequest { keys, timeout, op }))
+    }
+}
+
+/// A client parked in a DB task until one of its keys receives data
+pub struct BlockedClient {
+    pub id: u64,
+    pub keys: Vec<String>,
+    pub op: BlockOp,
+    /// `None` blocks forever
+    pub deadline: Option<Instant>,
+    pub reply: oneshot::Sender<crate::Result<Frame>>,
+}
+
+/// Clients blocked in one DB, together with the per-key FIFO queues of their ids
+#[derive(Default)]
+pub struct BlockedClients {
+    pub clients: HashMap<u64, BlockedClient>,
+    /// Keys with clients waiting for data (BLPOP), served first come first served
+    pub keys: HashMap<String, VecDeque<u64>>,
+}
+
+impl BlockedClients {
+    pub fn add(&mut self, client: BlockedClient) {
+        for key in &client.keys {
+            let queue = self.keys.entry(key.clone()).or_default();
+            if !queue.contains(&client.id) {
+                queue.push_back(client.id);
+            }
+        }
+        self.clients.insert(client.id, client);
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<BlockedClient> {
+        let client = self.clients.remove(&id)?;
+        for key in &client.keys {
+            if let Some(queue) = self.keys.get_mut(key) {
+                queue.retain(|c| *c != id);
+                if queue.is_empty() {
+                    self.keys.remove(key);
+                }
+            }
+        }
+        Some(client)
+    }
+
+    pub fn is_blocked_on(&self, key: &str) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.clients.values().filter_map(|c| c.deadline).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+impl RedisDb {
+    /// Serves a blocking command right away if one of its keys holds data, otherwise
+    /// parks the client until a push makes a key ready, the timeout fires or the client
+    /// is unblocked. The DB task never waits on the client.
+    pub fn block_for_keys(&mut self, id: u64, req: BlockRequest, reply: oneshot::Sender<crate::Result<Frame>>) {
+        for key in &req.keys {
+            if let Some(res) = req.op.serve(self, key) {
+                if res.is_ok() {
+                    self.propagate_blocked(&req.op, key);
+                }
+                let _ = reply.send(res);
+                return;
+            }
+        }
+        let deadline = if req.timeout > 0f64 {
+            Some(Instant::now() + Duration::from_secs_f64(req.timeout))
+        } else {
+            None
+        };
+        debug!("client {} blocked on {:?}", id, req.keys);
+        self.blocking_keys.add(BlockedClient {
+            id,
+            keys: req.keys,
+            op: req.op,
+            deadline,
+            reply,
+        });
+    }
+
+    /// Unblocks client `id` with a null reply, or an error when `error` is set.
+    /// Returns false if the client is not blocked in this DB.
+    pub fn unblock_client(&mut self, id: u64, error: bool) -> bool {
+        match self.blocking_keys.remove(id) {
+            Some(client) => {
+                let frame = if error {
+                    Err("UNBLOCKED client unblocked via CLIENT UNBLOCK".into())
+                } else {
+                    Ok(Frame::Null)
+                };
+                let _ = client.reply.send(frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Called after every write that may add data to `key`. Clients blocked on the key
+    /// are served by `handle_clients_blocked_on_keys` once the command is done.
+    pub fn signal_key_as_ready(&mut self, key: &str) {
+        if self.blocking_keys.is_blocked_on(key) && !self.read_keys.iter().any(|k| k == key) {
+            self.read_keys.push_back(key.to_string());
+        }
+    }
+
+    fn propagate_blocked(&self, op: &BlockOp, key: &str) {
+        if let Some(argv) = op.propagated_command(key) {
+            replication::propagate(self.id, &argv);
+        }
+    }
+
+    /// Serves the clients blocked on keys that received data, in FIFO order per key.
+    /// Serving a client may itself push to another key (BLMOVE), which is handled
+    /// in the same pass.
+    pub fn handle_clients_blocked_on_keys(&mut self) {
+        while let Some(key) = self.read_keys.pop_front() {
+            while let Some(id) = self.blocking_keys.keys.get(&key).and_then(|queue| queue.front().copied()) {
+                let op = match self.blocking_keys.clients.get(&id) {
+                    // the client went away while it was waiting
+                    Some(client) if client.reply.is_closed() => None,
+                    Some(client) => Some(client.op.clone()),
+                    None => None,
+                };
+                let op = match op {
+                    Some(op) => op,
+                    None => {
+                        self.blocking_keys.remove(id);
+                        continue;
+                    }
+                };
+                // the client stays at the head of every queue until it is actually served
+                match op.serve(self, &key) {
+                    Some(res) => {
+                        if res.is_ok() {
+                            self.propagate_blocked(&op, &key);
+                        }
+                        if let Some(client) = self.blocking_keys.remove(id) {
+                            let _ = client.reply.send(res);
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Replies with a null to every blocked client whose timeout elapsed and drops the
+    /// ones that disconnected.
+    pub fn handle_blocked_clients_timeout(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .blocking_keys
+            .clients
+            .values()
+            .filter(|c| c.deadline.map_or(false, |d| d <= now) || c.reply.is_closed())
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        for id in expired {
+            if let Some(client) = self.blocking_keys.remove(id) {
+                let _ = client.reply.send(Ok(Frame::Null));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn client(id: u64, keys: &[&str]) -> (BlockedClient, oneshot::Receiver<crate::Result<Frame>>) {
+        let (reply, rx) = oneshot::channel();
+        let client = BlockedClient {
+            id,
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            op: BlockOp::ListPop { end: ListEnd::Left },
+            deadline: None,
+            reply,
+        };
+        (client, rx)
+    }
+
+    #[test]
+    fn blocked_clients_fifo() {
+        let mut blocked = BlockedClients::default();
+        let (c1, _r1) = client(1, &["a", "b"]);
+        let (c2, _r2) = client(2, &["b"]);
+        blocked.add(c1);
+        blocked.add(c2);
+        assert_eq!(blocked.keys["b"], VecDeque::from(vec![1, 2]));
+
+        assert!(blocked.remove(1).is_some());
+        assert!(!blocked.is_blocked_on("a"));
+        assert_eq!(blocked.keys["b"], VecDeque::from(vec![2]));
+        assert!(blocked.remove(1).is_none());
+
+        blocked.remove(2);
+        assert!(blocked.is_empty());
+        assert!(blocked.keys.is_empty());
+    }
+}
@@ -87,6 +87,20 @@ impl<T: PartialEq> LinkList<T> {
         }
     }
 
+    pub fn pop_head(&mut self) -> Option<T> {
+        self.head.map(|node| unsafe {
+            self.delete_node(node);
+            Box::from_raw(node.as_ptr()).value
+        })
+    }
+
+    pub fn pop_tail(&mut self) -> Option<T> {
+        self.tail.map(|node| unsafe {
+            self.delete_node(node);
+            Box::from_raw(node.as_ptr()).value
+        })
+    }
+
     pub fn delete(&mut self, elem: T) {
         unsafe {
             if self.len > 0 {
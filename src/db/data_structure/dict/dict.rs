@@ -736,7 +736,7 @@ impl Dict {
                     } else {
                         self.ht_table[0][h as usize]
                     };
-                    if *he.unwrap().as_ptr() != DictEntry::default() {
+                    if he.is_some() {
                         break;
                     }
                 }
@@ -745,7 +745,7 @@ impl Dict {
                 loop {
                     let h = random_ulong() & m;
                     he = self.ht_table[0][h as usize];
-                    if *he.unwrap().as_ptr() != DictEntry::default() {
+                    if he.is_some() {
                         break;
                     }
                 }
@@ -776,7 +776,7 @@ impl Dict {
         if cnt == 0 {
             return self.get_random_key();
         }
-        let idx = gen_random() % count as u32;
+        let idx = gen_random() % cnt as u32;
         entries[idx as usize]
     }
 
@@ -832,7 +832,7 @@ impl Dict {
                         empty_len = 0;
                         while he.is_some() {
                             if stored < count {
-                                des[stored as usize] = he;
+                                des.push(he);
                             } else {
                                 let r = random_ulong() % (stored + 1);
                                 if r < count {
@@ -850,7 +850,7 @@ impl Dict {
                 i = (i + 1) & max_size_mask;
             }
         }
-        return if stored > count { stored } else { count };
+        stored.min(count)
     }
 
     pub fn find_by_hash_and_ptr(&self, key: String, hash: u64) -> Option<NonNull<DictEntry>> {
@@ -3,6 +3,6 @@ pub mod error;
 pub mod hash;
 pub mod iter;
 pub mod lib;
-mod stats;
+pub mod stats;
 mod test;
 pub mod iter_mut;
@@ -24,73 +24,87 @@ pub fn dict_combine_stats(from: &mut DictStats, into: &mut DictStats) {
     }
 }
 
+/// Appends the report of `stats` to `buf`, writing `buf_size` bytes at most. Returns
+/// the number of bytes written.
 pub fn dict_get_stats_msg(
     buf: &mut String,
     buf_size: usize,
     stats: &DictStats,
     full: bool,
 ) -> usize {
+    let start = buf.len();
+    let table_type = if stats.ht_idx == 0 {
+        "main hash table"
+    } else {
+        "rehashing target"
+    };
     if stats.ht_used == 0 {
-        let table_type = if stats.ht_idx == 0 {
-            "main hash table"
-        } else {
-            "rehashing target"
-        };
-        write!(
+        writeln!(
             buf,
-            "Hash table {} stats ({}): No stats available for empty dictionaries\n",
+            "Hash table {} stats ({}):\nNo stats available for empty dictionaries",
             stats.ht_idx, table_type
         )
         .unwrap();
-        return buf.len();
-    }
-    let table_type = if stats.ht_idx == 0 {
-        "main hash table"
     } else {
-        "rehashing target"
-    };
-    let mut l = 0;
-    write!(buf, "Hash table {} stats ({}): No stats available for empty dictionaries\n, table size: {}\n number of elements: {}\n", stats.ht_idx, table_type, stats.ht_size, stats.ht_used).unwrap();
-    l += buf.len();
-    if full {
-        let before_len = buf.len();
-        write!(&mut buf[l..].to_string(), "different slots: {}, max chain length: {}\n avg chain length (counted): {}, avg chain length (computed): {}\n Chain length distribution: \n", stats.buckets, stats.max_chain_len, stats.total_chain_len / stats.buckets, stats.ht_used / stats.buckets).unwrap();
-        l += buf.len() - before_len;
-
-        for i in 0..DICT_STATS_VECTLEN - 1 {
-            if stats.cl_vector[i] == 0 {
-                continue;
-            }
-            if l >= buf_size {
-                break;
-            }
-
-            let before_len = buf.len();
-            write!(
-                &mut buf[l..].to_string(),
-                " {}: {}({})\n",
-                i,
-                stats.cl_vector[i],
-                stats.cl_vector[i] / stats.ht_size + 1
+        writeln!(
+            buf,
+            "Hash table {} stats ({}):\n table size: {}\n number of elements: {}",
+            stats.ht_idx, table_type, stats.ht_size, stats.ht_used
+        )
+        .unwrap();
+        if full {
+            let buckets = stats.buckets.max(1) as f64;
+            writeln!(
+                buf,
+                " different slots: {}\n max chain length: {}\n avg chain length (counted): {:.2}\n avg chain length (computed): {:.2}\n Chain length distribution:",
+                stats.buckets,
+                stats.max_chain_len,
+                stats.total_chain_len as f64 / buckets,
+                stats.ht_used as f64 / buckets
             )
             .unwrap();
-            l += buf.len() - before_len;
+            for i in 0..DICT_STATS_VECTLEN - 1 {
+                if stats.cl_vector[i] == 0 {
+                    continue;
+                }
+                if buf.len() - start >= buf_size {
+                    break;
+                }
+                writeln!(
+                    buf,
+                    "   {}: {} ({:.2}%)",
+                    i,
+                    stats.cl_vector[i],
+                    stats.cl_vector[i] as f64 / stats.ht_size as f64 * 100.0
+                )
+                .unwrap();
+            }
         }
     }
-    buf.len()
+    buf.truncate(start + buf_size.min(buf.len() - start));
+    buf.len() - start
 }
 
-impl Dict {
-    pub fn get_stats_ht(&self, ht_idx: usize, full: bool) -> DictStats {
-        let cl_vector = Vec::with_capacity(DICT_STATS_VECTLEN);
-        let mut stats = DictStats {
+impl DictStats {
+    pub(crate) fn new(ht_idx: usize) -> Self {
+        DictStats {
             ht_idx,
             buckets: 0,
             max_chain_len: 0,
-            cl_vector,
+            total_chain_len: 0,
+            ht_size: 0,
+            ht_used: 0,
+            cl_vector: vec![0; DICT_STATS_VECTLEN],
+        }
+    }
+}
+
+impl Dict {
+    pub fn get_stats_ht(&self, ht_idx: usize, full: bool) -> DictStats {
+        let mut stats = DictStats {
             ht_size: dict_size(self.ht_size_exp[ht_idx]),
             ht_used: self.ht_used[ht_idx] as u64,
-            total_chain_len: 0,
+            ..DictStats::new(ht_idx)
         };
         if !full {
             return stats;
@@ -113,6 +127,7 @@ impl Dict {
                 } else {
                     stats.cl_vector[DICT_STATS_VECTLEN - 1] += 1;
                 }
+                stats.max_chain_len = stats.max_chain_len.max(chain_len as u64);
                 stats.total_chain_len += chain_len as u64;
             }
         }
@@ -121,13 +136,13 @@ impl Dict {
 
     pub fn get_stats(&self, buf: &mut String, mut buf_size: usize, full: bool) {
 
-        let mut main_ht_stats = self.get_stats_ht(0, full);
-        let l = dict_get_stats_msg(buf, buf_size, &mut main_ht_stats, full);
+        let main_ht_stats = self.get_stats_ht(0, full);
+        let l = dict_get_stats_msg(buf, buf_size, &main_ht_stats, full);
         buf_size -= l;
 
         if self.dict_is_rehashing() && buf_size > 0 {
-            let mut rehash_stats = self.get_stats_ht(1, full);
-            dict_get_stats_msg(buf, buf_size, &mut rehash_stats, full);
+            let rehash_stats = self.get_stats_ht(1, full);
+            dict_get_stats_msg(buf, buf_size, &rehash_stats, full);
         }
     }
 }
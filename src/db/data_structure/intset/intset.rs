@@ -240,6 +240,39 @@ impl IntSet {
         self.length
     }
 
+    /// The serialized form used by RDB: encoding and length as little endian u32,
+    /// then the contents.
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut blob = Vec::with_capacity(self.blob_len());
+        blob.extend_from_slice(&self.encoding.to_le_bytes());
+        blob.extend_from_slice(&self.length.to_le_bytes());
+        blob.extend_from_slice(&self.contents);
+        blob
+    }
+
+    /// Parses a blob written by `to_blob`, rejecting bad encodings, lengths and
+    /// unsorted or duplicated members.
+    pub fn from_blob(blob: &[u8]) -> Option<Self> {
+        if blob.len() < 8 {
+            return None;
+        }
+        let encoding = u32::from_le_bytes(blob[0..4].try_into().unwrap());
+        let length = u32::from_le_bytes(blob[4..8].try_into().unwrap());
+        if !matches!(encoding as u8, INTSET_ENC_INT16 | INTSET_ENC_INT32 | INTSET_ENC_INT64) || encoding > u8::MAX as u32 {
+            return None;
+        }
+        if (length as usize).checked_mul(encoding as usize)? != blob.len() - 8 {
+            return None;
+        }
+        let is = Self { encoding, length, contents: blob[8..].to_vec() };
+        for i in 1..length as usize {
+            if is._get(i) <= is._get(i - 1) {
+                return None;
+            }
+        }
+        Some(is)
+    }
+
     pub fn validate_integrity(&self, size: usize, deep: i32) -> bool {
         if size < size_of::<Self>() {
             return false;
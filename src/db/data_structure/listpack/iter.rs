@@ -0,0 +1,32 @@
+use crate::db::data_structure::listpack::lib::LpEntry;
+use crate::db::data_structure::listpack::ListPack;
+
+pub struct ListPackIter<'a> {
+    pos: Option<usize>,
+    listpack: &'a ListPack,
+}
+
+impl ListPack {
+    pub fn iter(&self) -> ListPackIter {
+        ListPackIter {
+            pos: self.first(),
+            listpack: self,
+        }
+    }
+
+    /// Iterates field/value (or member/score) pairs.
+    pub fn pairs(&self) -> impl Iterator<Item = (LpEntry, LpEntry)> + '_ {
+        let mut iter = self.iter();
+        std::iter::from_fn(move || Some((iter.next()?, iter.next()?)))
+    }
+}
+
+impl Iterator for ListPackIter<'_> {
+    type Item = LpEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos?;
+        self.pos = self.listpack.next(pos);
+        Some(self.listpack.get(pos))
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::{Display, Formatter};
+
+use crate::db::data_structure::listpack::*;
+
+/// A decoded listpack element
+#[derive(Clone, Debug, PartialEq)]
+pub enum LpEntry {
+    Str(Vec<u8>),
+    Int(i64),
+}
+
+impl LpEntry {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            LpEntry::Str(s) => s.clone(),
+            LpEntry::Int(v) => v.to_string().into_bytes(),
+        }
+    }
+
+    /// Compares with a string the way `lpCompare` does, integers are compared by
+    /// their decimal representation.
+    pub fn eq_bytes(&self, s: &[u8]) -> bool {
+        match self {
+            LpEntry::Str(v) => v == s,
+            LpEntry::Int(v) => lp_string_to_int64(s) == Some(*v),
+        }
+    }
+}
+
+impl Display for LpEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LpEntry::Str(s) => write!(f, "{}", String::from_utf8_lossy(s)),
+            LpEntry::Int(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Converts a string to an integer only if the conversion is lossless, so "01",
+/// "+1" or "-0" stay strings.
+pub fn lp_string_to_int64(s: &[u8]) -> Option<i64> {
+    if s.is_empty() || s.len() > 20 {
+        return None;
+    }
+    let v: i64 = std::str::from_utf8(s).ok()?.parse().ok()?;
+    if v.to_string().as_bytes() == s {
+        Some(v)
+    } else {
+        None
+    }
+}
+
+/// Writes the encoding of `v` into `buf` and returns the number of bytes used.
+pub fn lp_encode_integer(buf: &mut [u8; LP_MAX_INT_ENCODING_LEN], v: i64) -> usize {
+    if (0..=127).contains(&v) {
+        buf[0] = v as u8;
+        1
+    } else if (-4096..=4095).contains(&v) {
+        let v = if v < 0 { (1i64 << 13) + v } else { v } as u64;
+        buf[0] = ((v >> 8) as u8) | LP_ENCODING_13BIT_INT;
+        buf[1] = (v & 0xff) as u8;
+        2
+    } else if (-32768..=32767).contains(&v) {
+        buf[0] = LP_ENCODING_16BIT_INT;
+        buf[1..3].copy_from_slice(&(v as i16).to_le_bytes());
+        3
+    } else if (-8388608..=8388607).contains(&v) {
+        buf[0] = LP_ENCODING_24BIT_INT;
+        buf[1..4].copy_from_slice(&(v as i32).to_le_bytes()[..3]);
+        4
+    } else if (i32::MIN as i64..=i32::MAX as i64).contains(&v) {
+        buf[0] = LP_ENCODING_32BIT_INT;
+        buf[1..5].copy_from_slice(&(v as i32).to_le_bytes());
+        5
+    } else {
+        buf[0] = LP_ENCODING_64BIT_INT;
+        buf[1..9].copy_from_slice(&v.to_le_bytes());
+        9
+    }
+}
+
+/// Appends the encoding header of a string of `len` bytes to `buf`.
+pub fn lp_encode_string_header(buf: &mut Vec<u8>, len: usize) {
+    if len < 64 {
+        buf.push(LP_ENCODING_6BIT_STR | len as u8);
+    } else if len < 4096 {
+        buf.push(LP_ENCODING_12BIT_STR | (len >> 8) as u8);
+        buf.push((len & 0xff) as u8);
+    } else {
+        buf.push(LP_ENCODING_32BIT_STR);
+        buf.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+}
+
+/// Number of bytes needed to store `l` as a backlen.
+pub fn lp_backlen_size(l: usize) -> usize {
+    if l <= 127 {
+        1
+    } else if l < 16383 {
+        2
+    } else if l < 2097151 {
+        3
+    } else if l < 268435455 {
+        4
+    } else {
+        5
+    }
+}
+
+/// The backlen is read right to left, so the most significant 7 bits go first and
+/// every byte but the leftmost has its high bit set.
+pub fn lp_encode_backlen(buf: &mut Vec<u8>, l: usize) {
+    let size = lp_backlen_size(l);
+    for i in (0..size).rev() {
+        let mut b = ((l >> (7 * i)) & 127) as u8;
+        if i != size - 1 {
+            b |= 128;
+        }
+        buf.push(b);
+    }
+}
+
+/// Decodes the backlen whose last byte is at `p`, returning the element length and
+/// the size of the backlen itself.
+pub fn lp_decode_backlen(data: &[u8], mut p: usize) -> Option<(usize, usize)> {
+    let mut val = 0usize;
+    let mut shift = 0;
+    let mut size = 0;
+    loop {
+        let b = *data.get(p)?;
+        val |= ((b & 127) as usize) << shift;
+        size += 1;
+        if b & 128 == 0 {
+            break;
+        }
+        shift += 7;
+        if size == LP_MAX_BACKLEN_SIZE || p == 0 {
+            return None;
+        }
+        p -= 1;
+    }
+    Some((val, size))
+}
+
+/// Size of the encoding and data of the element at `p`, without the backlen. Every
+/// read is bound checked so this is also used to validate untrusted listpacks.
+pub fn lp_current_encoded_size(data: &[u8], p: usize) -> Option<usize> {
+    let b = *data.get(p)?;
+    let size = if b & LP_ENCODING_7BIT_UINT_MASK == LP_ENCODING_7BIT_UINT {
+        1
+    } else if b & LP_ENCODING_6BIT_STR_MASK == LP_ENCODING_6BIT_STR {
+        1 + (b & 0x3f) as usize
+    } else if b & LP_ENCODING_13BIT_INT_MASK == LP_ENCODING_13BIT_INT {
+        2
+    } else if b & LP_ENCODING_12BIT_STR_MASK == LP_ENCODING_12BIT_STR {
+        2 + ((((b & 0x0f) as usize) << 8) | *data.get(p + 1)? as usize)
+    } else {
+        match b {
+            LP_ENCODING_16BIT_INT => 3,
+            LP_ENCODING_24BIT_INT => 4,
+            LP_ENCODING_32BIT_INT => 5,
+            LP_ENCODING_64BIT_INT => 9,
+            LP_ENCODING_32BIT_STR => {
+                let len = data.get(p + 1..p + 5)?;
+                5 + u32::from_le_bytes(len.try_into().unwrap()) as usize
+            }
+            LP_EOF => 1,
+            _ => return None,
+        }
+    };
+    Some(size)
+}
+
+/// Decodes the element at `p`, which must hold a valid encoding.
+pub fn lp_decode_entry(data: &[u8], p: usize) -> LpEntry {
+    let b = data[p];
+    let (uval, negstart, negmax) = if b & LP_ENCODING_7BIT_UINT_MASK == LP_ENCODING_7BIT_UINT {
+        return LpEntry::Int((b & 0x7f) as i64);
+    } else if b & LP_ENCODING_6BIT_STR_MASK == LP_ENCODING_6BIT_STR {
+        let len = (b & 0x3f) as usize;
+        return LpEntry::Str(data[p + 1..p + 1 + len].to_vec());
+    } else if b & LP_ENCODING_13BIT_INT_MASK == LP_ENCODING_13BIT_INT {
+        let uval = (((b & 0x1f) as u64) << 8) | data[p + 1] as u64;
+        (uval, 1u64 << 12, (1u64 << 13) - 1)
+    } else if b & LP_ENCODING_12BIT_STR_MASK == LP_ENCODING_12BIT_STR {
+        let len = (((b & 0x0f) as usize) << 8) | data[p + 1] as usize;
+        return LpEntry::Str(data[p + 2..p + 2 + len].to_vec());
+    } else {
+        match b {
+            LP_ENCODING_16BIT_INT => {
+                let uval = u16::from_le_bytes([data[p + 1], data[p + 2]]) as u64;
+                (uval, 1u64 << 15, u16::MAX as u64)
+            }
+            LP_ENCODING_24BIT_INT => {
+                let uval = u32::from_le_bytes([data[p + 1], data[p + 2], data[p + 3], 0]) as u64;
+                (uval, 1u64 << 23, (1u64 << 24) - 1)
+            }
+            LP_ENCODING_32BIT_INT => {
+                let uval = u32::from_le_bytes(data[p + 1..p + 5].try_into().unwrap()) as u64;
+                (uval, 1u64 << 31, u32::MAX as u64)
+            }
+            LP_ENCODING_64BIT_INT => {
+                return LpEntry::Int(i64::from_le_bytes(data[p + 1..p + 9].try_into().unwrap()));
+            }
+            LP_ENCODING_32BIT_STR => {
+                let len = u32::from_le_bytes(data[p + 1..p + 5].try_into().unwrap()) as usize;
+                return LpEntry::Str(data[p + 5..p + 5 + len].to_vec());
+            }
+            _ => panic!("invalid listpack encoding {:#x}", b),
+        }
+    };
+    if uval >= negstart {
+        LpEntry::Int(-((negmax - uval) as i64) - 1)
+    } else {
+        LpEntry::Int(uval as i64)
+    }
+}
+
+/// Encodes `ele` as a complete element: encoding, data and backlen. Strings that
+/// look like integers get the integer encoding.
+pub fn lp_encode_entry(ele: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(ele.len() + LP_MAX_BACKLEN_SIZE + 5);
+    if let Some(v) = lp_string_to_int64(ele) {
+        let mut int_buf = [0u8; LP_MAX_INT_ENCODING_LEN];
+        let len = lp_encode_integer(&mut int_buf, v);
+        buf.extend_from_slice(&int_buf[..len]);
+    } else {
+        lp_encode_string_header(&mut buf, ele.len());
+        buf.extend_from_slice(ele);
+    }
+    let len = buf.len();
+    lp_encode_backlen(&mut buf, len);
+    buf
+}
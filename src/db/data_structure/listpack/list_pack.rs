@@ -0,0 +1,297 @@
+use crate::db::data_structure::listpack::lib::*;
+use crate::db::data_structure::listpack::*;
+
+/// A listpack: a serialized list of strings and integers in a single allocation.
+///
+/// Unlike the ziplist every element stores its own length at its end (the backlen),
+/// so changing an element never touches its neighbours and there is no cascade
+/// update. Elements are addressed by their byte offset, as with `ZipList`.
+///
+/// ```text
+/// <total-bytes u32> <num-elements u16> <element> ... <element> <0xff>
+/// element: <encoding + data> <backlen>
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListPack {
+    data: Vec<u8>,
+}
+
+impl Default for ListPack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ListPack {
+    pub fn new() -> Self {
+        let bytes = LP_HDR_SIZE + 1;
+        let mut data = vec![0u8; bytes];
+        lp_set_total_bytes(&mut data, bytes as u32);
+        lp_set_num_elements(&mut data, 0);
+        data[bytes - 1] = LP_EOF;
+        Self { data }
+    }
+
+    /// Wraps raw bytes without checking them, see `validate_integrity`.
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Total bytes as stored in the header.
+    pub fn bytes(&self) -> usize {
+        lp_get_total_bytes(&self.data) as usize
+    }
+
+    /// Number of elements. The header only holds counts below 65535, larger
+    /// listpacks are counted by walking them.
+    pub fn len(&self) -> usize {
+        let num = lp_get_num_elements(&self.data);
+        if num != LP_HDR_NUMELE_UNKNOWN {
+            return num as usize;
+        }
+        let mut count = 0;
+        let mut p = self.first();
+        while let Some(pos) = p {
+            count += 1;
+            p = self.next(pos);
+        }
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data[LP_HDR_SIZE] == LP_EOF
+    }
+
+    fn entry_size(&self, p: usize) -> usize {
+        let size = lp_current_encoded_size(&self.data, p).expect("invalid listpack encoding");
+        size + lp_backlen_size(size)
+    }
+
+    pub fn first(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(LP_HDR_SIZE)
+        }
+    }
+
+    pub fn last(&self) -> Option<usize> {
+        self.prev(self.data.len() - 1)
+    }
+
+    pub fn next(&self, p: usize) -> Option<usize> {
+        assert!(p < self.data.len() - 1);
+        let next = p + self.entry_size(p);
+        if self.data[next] == LP_EOF {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    /// The element before `p`, `p` may also be the EOF byte.
+    pub fn prev(&self, p: usize) -> Option<usize> {
+        if p <= LP_HDR_SIZE {
+            return None;
+        }
+        let (len, backlen_size) = lp_decode_backlen(&self.data, p - 1).expect("invalid listpack backlen");
+        Some(p - backlen_size - len)
+    }
+
+    pub fn get(&self, p: usize) -> LpEntry {
+        lp_decode_entry(&self.data, p)
+    }
+
+    /// Position of the element at `index`, negative indexes count from the tail.
+    pub fn seek(&self, index: i64) -> Option<usize> {
+        let len = self.len() as i64;
+        let index = if index < 0 { len + index } else { index };
+        if index < 0 || index >= len {
+            return None;
+        }
+        if index > len / 2 {
+            let mut p = self.last();
+            for _ in 0..len - 1 - index {
+                p = self.prev(p?);
+            }
+            p
+        } else {
+            let mut p = self.first();
+            for _ in 0..index {
+                p = self.next(p?);
+            }
+            p
+        }
+    }
+
+    /// Whether the element at `p` equals `s`.
+    pub fn compare(&self, p: usize, s: &[u8]) -> bool {
+        self.get(p).eq_bytes(s)
+    }
+
+    /// Looks for `s` starting at `p`, skipping `skip` elements after every
+    /// comparison, so a skip of 1 only looks at the fields of a field/value list.
+    pub fn find(&self, mut p: usize, s: &[u8], skip: usize) -> Option<usize> {
+        let int_val = lp_string_to_int64(s);
+        loop {
+            let found = match (self.get(p), int_val) {
+                (LpEntry::Int(v), Some(i)) => v == i,
+                (LpEntry::Str(v), None) => v == s,
+                _ => false,
+            };
+            if found {
+                return Some(p);
+            }
+            for _ in 0..=skip {
+                p = self.next(p)?;
+            }
+        }
+    }
+
+    fn incr_num_elements(&mut self, incr: i64) {
+        let num = lp_get_num_elements(&self.data);
+        if num == LP_HDR_NUMELE_UNKNOWN {
+            return;
+        }
+        let num = num as i64 + incr;
+        let num = if num >= LP_HDR_NUMELE_UNKNOWN as i64 { LP_HDR_NUMELE_UNKNOWN as u32 } else { num as u32 };
+        lp_set_num_elements(&mut self.data, num);
+    }
+
+    fn update_total_bytes(&mut self) {
+        let bytes = u32::try_from(self.data.len()).expect("listpack too large");
+        lp_set_total_bytes(&mut self.data, bytes);
+    }
+
+    /// Inserts `ele` before or after the element at `p`, or replaces it, returning
+    /// the position of the new element. `p` may be the EOF byte to append.
+    pub fn insert(&mut self, ele: &[u8], p: usize, whence: i32) -> usize {
+        let entry = lp_encode_entry(ele);
+        let pos = match whence {
+            LP_BEFORE => p,
+            LP_AFTER => p + self.entry_size(p),
+            LP_REPLACE => {
+                let old = self.entry_size(p);
+                self.data.splice(p..p + old, entry);
+                self.update_total_bytes();
+                return p;
+            }
+            _ => panic!("invalid listpack insert position {}", whence),
+        };
+        self.data.splice(pos..pos, entry);
+        self.update_total_bytes();
+        self.incr_num_elements(1);
+        pos
+    }
+
+    pub fn append(&mut self, ele: &[u8]) -> usize {
+        let eof = self.data.len() - 1;
+        self.insert(ele, eof, LP_BEFORE)
+    }
+
+    pub fn prepend(&mut self, ele: &[u8]) -> usize {
+        self.insert(ele, LP_HDR_SIZE, LP_BEFORE)
+    }
+
+    pub fn append_integer(&mut self, v: i64) -> usize {
+        self.append(v.to_string().as_bytes())
+    }
+
+    pub fn replace(&mut self, p: usize, ele: &[u8]) -> usize {
+        self.insert(ele, p, LP_REPLACE)
+    }
+
+    /// Deletes the element at `p`, returning the position of the element that took
+    /// its place.
+    pub fn delete(&mut self, p: usize) -> Option<usize> {
+        let size = self.entry_size(p);
+        self.data.drain(p..p + size);
+        self.update_total_bytes();
+        self.incr_num_elements(-1);
+        if self.data[p] == LP_EOF {
+            None
+        } else {
+            Some(p)
+        }
+    }
+
+    /// Deletes up to `num` elements starting at `p`.
+    pub fn delete_range_with_entry(&mut self, p: usize, num: usize) -> usize {
+        let mut end = p;
+        let mut deleted = 0;
+        while deleted < num && self.data[end] != LP_EOF {
+            end += self.entry_size(end);
+            deleted += 1;
+        }
+        self.data.drain(p..end);
+        self.update_total_bytes();
+        self.incr_num_elements(-(deleted as i64));
+        deleted
+    }
+
+    /// Deletes up to `num` elements starting at `index`.
+    pub fn delete_range(&mut self, index: i64, num: usize) -> usize {
+        match self.seek(index) {
+            Some(p) => self.delete_range_with_entry(p, num),
+            None => 0,
+        }
+    }
+
+    /// Appends every element of `other`.
+    pub fn merge(&mut self, other: &ListPack) {
+        let num = self.len() + other.len();
+        let eof = self.data.len() - 1;
+        let other_entries = &other.data[LP_HDR_SIZE..other.data.len() - 1];
+        self.data.splice(eof..eof, other_entries.iter().copied());
+        self.update_total_bytes();
+        let num = if num >= LP_HDR_NUMELE_UNKNOWN as usize { LP_HDR_NUMELE_UNKNOWN as u32 } else { num as u32 };
+        lp_set_num_elements(&mut self.data, num);
+    }
+
+    /// Checks the header and, with `deep`, every element, so a listpack read from an
+    /// untrusted source can be walked without going out of bounds.
+    pub fn validate_integrity(&self, deep: bool) -> bool {
+        let size = self.data.len();
+        if size < LP_HDR_SIZE + 1 {
+            return false;
+        }
+        if self.bytes() != size || self.data[size - 1] != LP_EOF {
+            return false;
+        }
+        if !deep {
+            return true;
+        }
+        let mut count = 0usize;
+        let mut p = LP_HDR_SIZE;
+        while p < size - 1 {
+            if self.data[p] == LP_EOF {
+                return false;
+            }
+            let encoded = match lp_current_encoded_size(&self.data, p) {
+                Some(encoded) => encoded,
+                None => return false,
+            };
+            let backlen_size = lp_backlen_size(encoded);
+            let end = p + encoded + backlen_size;
+            if end > size - 1 {
+                return false;
+            }
+            match lp_decode_backlen(&self.data, end - 1) {
+                Some((len, n)) if len == encoded && n == backlen_size => {}
+                _ => return false,
+            }
+            p = end;
+            count += 1;
+        }
+        let num = lp_get_num_elements(&self.data);
+        p == size - 1 && (num == LP_HDR_NUMELE_UNKNOWN || num as usize == count)
+    }
+}
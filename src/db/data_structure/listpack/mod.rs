@@ -1,11 +1,38 @@
-mod listpack;
+pub mod iter;
+pub mod lib;
+mod list_pack;
 
+pub use list_pack::ListPack;
+mod test;
+
+/// total bytes (u32) + number of elements (u16)
 const LP_HDR_SIZE: usize = 6;
 const LP_HDR_NUMELE_UNKNOWN: u16 = u16::MAX;
 const LP_MAX_INT_ENCODING_LEN: usize = 9;
 const LP_MAX_BACKLEN_SIZE: usize = 5;
-const LP_ENCODING_INT: usize = 0;
-const LP_ENCODING_STRING: usize = 0;
+const LP_EOF: u8 = 0xff;
+
+/// insert positions relative to an existing element
+pub const LP_BEFORE: i32 = 0;
+pub const LP_AFTER: i32 = 1;
+pub const LP_REPLACE: i32 = 2;
+
+/// integer encodings
+const LP_ENCODING_7BIT_UINT: u8 = 0;
+const LP_ENCODING_7BIT_UINT_MASK: u8 = 0x80;
+const LP_ENCODING_13BIT_INT: u8 = 0xc0;
+const LP_ENCODING_13BIT_INT_MASK: u8 = 0xe0;
+const LP_ENCODING_16BIT_INT: u8 = 0xf1;
+const LP_ENCODING_24BIT_INT: u8 = 0xf2;
+const LP_ENCODING_32BIT_INT: u8 = 0xf3;
+const LP_ENCODING_64BIT_INT: u8 = 0xf4;
+
+/// string encodings
+const LP_ENCODING_6BIT_STR: u8 = 0x80;
+const LP_ENCODING_6BIT_STR_MASK: u8 = 0xc0;
+const LP_ENCODING_12BIT_STR: u8 = 0xe0;
+const LP_ENCODING_12BIT_STR_MASK: u8 = 0xf0;
+const LP_ENCODING_32BIT_STR: u8 = 0xf0;
 
 pub fn lp_set_total_bytes(p: &mut [u8], v: u32) {
     let bytes = v.to_le_bytes();
@@ -17,3 +44,11 @@ pub fn lp_set_num_elements(p: &mut [u8], v: u32) {
     p[4] = bytes[0];
     p[5] = bytes[1];
 }
+
+pub fn lp_get_total_bytes(p: &[u8]) -> u32 {
+    u32::from_le_bytes(p[..4].try_into().unwrap())
+}
+
+pub fn lp_get_num_elements(p: &[u8]) -> u16 {
+    u16::from_le_bytes([p[4], p[5]])
+}
@@ -0,0 +1,213 @@
+#[cfg(test)]
+mod test {
+    use crate::db::data_structure::listpack::lib::{lp_string_to_int64, LpEntry};
+    use crate::db::data_structure::listpack::ListPack;
+    use crate::db::data_structure::listpack::{LP_AFTER, LP_BEFORE, LP_HDR_SIZE};
+    use rand::Rng;
+
+    fn create() -> ListPack {
+        let mut lp = ListPack::new();
+        lp.append(b"hello");
+        lp.append(b"foo");
+        lp.append(b"quux");
+        lp.append(b"1024");
+        lp
+    }
+
+    fn to_strings(lp: &ListPack) -> Vec<String> {
+        lp.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn empty() {
+        let lp = ListPack::new();
+        assert_eq!(lp.bytes(), LP_HDR_SIZE + 1);
+        assert_eq!(lp.len(), 0);
+        assert!(lp.first().is_none());
+        assert!(lp.last().is_none());
+        assert!(lp.seek(0).is_none());
+        assert!(lp.validate_integrity(true));
+    }
+
+    #[test]
+    fn integer_encoding() {
+        let values = [
+            0i64, 1, 127, 128, -1, -4096, 4095, 4096, -4097, 32767, -32768, 32768, 8388607, -8388608, 8388608,
+            i32::MAX as i64, i32::MIN as i64, i32::MAX as i64 + 1, i64::MAX, i64::MIN,
+        ];
+        let mut lp = ListPack::new();
+        for v in values {
+            lp.append_integer(v);
+        }
+        assert!(lp.validate_integrity(true));
+        let decoded: Vec<LpEntry> = lp.iter().collect();
+        assert_eq!(decoded, values.iter().map(|v| LpEntry::Int(*v)).collect::<Vec<_>>());
+        // the smallest encodings
+        assert_eq!(ListPack::new().append_integer(127), LP_HDR_SIZE);
+        let mut lp = ListPack::new();
+        lp.append_integer(127);
+        assert_eq!(lp.bytes(), LP_HDR_SIZE + 1 + 2);
+        let mut lp = ListPack::new();
+        lp.append_integer(-1);
+        assert_eq!(lp.bytes(), LP_HDR_SIZE + 1 + 3);
+
+        assert_eq!(lp_string_to_int64(b"-10"), Some(-10));
+        assert_eq!(lp_string_to_int64(b"010"), None);
+        assert_eq!(lp_string_to_int64(b"+1"), None);
+        assert_eq!(lp_string_to_int64(b"-0"), None);
+        assert_eq!(lp_string_to_int64(b"99999999999999999999"), None);
+    }
+
+    #[test]
+    fn string_encoding() {
+        let mut lp = ListPack::new();
+        let values = ["".to_string(), "a".repeat(63), "b".repeat(64), "c".repeat(4095), "d".repeat(4096), "e".repeat(70000)];
+        for v in &values {
+            lp.append(v.as_bytes());
+        }
+        assert!(lp.validate_integrity(true));
+        assert_eq!(to_strings(&lp), values);
+        // walk backward over the multi byte backlens
+        let mut p = lp.last();
+        let mut back = vec![];
+        while let Some(pos) = p {
+            back.push(lp.get(pos).to_string());
+            p = lp.prev(pos);
+        }
+        back.reverse();
+        assert_eq!(back, values);
+    }
+
+    #[test]
+    fn navigate() {
+        let lp = create();
+        assert_eq!(lp.len(), 4);
+        let first = lp.first().unwrap();
+        assert_eq!(lp.get(first), LpEntry::Str(b"hello".to_vec()));
+        let second = lp.next(first).unwrap();
+        assert_eq!(lp.prev(second), Some(first));
+        let last = lp.last().unwrap();
+        assert_eq!(lp.get(last), LpEntry::Int(1024));
+        assert!(lp.next(last).is_none());
+        assert_eq!(lp.seek(-1), Some(last));
+        assert_eq!(lp.seek(-4), Some(first));
+        assert_eq!(lp.get(lp.seek(2).unwrap()).to_string(), "quux");
+        assert!(lp.seek(4).is_none());
+        assert!(lp.seek(-5).is_none());
+    }
+
+    #[test]
+    fn insert_replace_delete() {
+        let mut lp = create();
+        let p = lp.seek(1).unwrap();
+        let p = lp.insert(b"before", p, LP_BEFORE);
+        lp.insert(b"after", p, LP_AFTER);
+        assert_eq!(to_strings(&lp), ["hello", "before", "after", "foo", "quux", "1024"]);
+        let p = lp.seek(0).unwrap();
+        lp.replace(p, b"a much longer replacement");
+        lp.prepend(b"0");
+        assert_eq!(to_strings(&lp), ["0", "a much longer replacement", "before", "after", "foo", "quux", "1024"]);
+
+        let p = lp.seek(2).unwrap();
+        let next = lp.delete(p).unwrap();
+        assert_eq!(lp.get(next).to_string(), "after");
+        assert!(lp.delete(lp.last().unwrap()).is_none());
+        assert_eq!(lp.delete_range(1, 2), 2);
+        assert_eq!(to_strings(&lp), ["0", "foo", "quux"]);
+        assert_eq!(lp.delete_range(-2, 10), 2);
+        assert_eq!(lp.delete_range(5, 1), 0);
+        assert_eq!(to_strings(&lp), ["0"]);
+        assert_eq!(lp.len(), 1);
+        assert!(lp.validate_integrity(true));
+    }
+
+    #[test]
+    fn find_and_compare() {
+        let mut lp = ListPack::new();
+        for (f, v) in [("a", "1"), ("1", "a"), ("b", "2")] {
+            lp.append(f.as_bytes());
+            lp.append(v.as_bytes());
+        }
+        let first = lp.first().unwrap();
+        let p = lp.find(first, b"a", 1).unwrap();
+        assert_eq!(p, first);
+        let p = lp.find(first, b"1", 1).unwrap();
+        assert_eq!(lp.get(lp.next(p).unwrap()).to_string(), "a");
+        assert!(lp.find(first, b"2", 1).is_none());
+        assert!(lp.find(first, b"01", 0).is_none());
+        assert!(lp.compare(lp.seek(-1).unwrap(), b"2"));
+        assert!(!lp.compare(lp.seek(-1).unwrap(), b"02"));
+        assert_eq!(lp.pairs().count(), 3);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = create();
+        let b = create();
+        a.merge(&b);
+        assert_eq!(a.len(), 8);
+        assert!(a.validate_integrity(true));
+        assert_eq!(to_strings(&a)[4..], to_strings(&b)[..]);
+    }
+
+    #[test]
+    fn validate() {
+        let lp = create();
+        let mut bytes = lp.as_bytes().to_vec();
+        assert!(ListPack::from_bytes(bytes.clone()).validate_integrity(true));
+        // wrong element count
+        bytes[4] = 5;
+        assert!(ListPack::from_bytes(bytes.clone()).validate_integrity(false));
+        assert!(!ListPack::from_bytes(bytes.clone()).validate_integrity(true));
+        // string length running past the end
+        let mut bytes = lp.as_bytes().to_vec();
+        bytes[LP_HDR_SIZE] = 0x80 | 60;
+        assert!(!ListPack::from_bytes(bytes).validate_integrity(true));
+        // missing EOF
+        let mut bytes = lp.as_bytes().to_vec();
+        bytes.pop();
+        assert!(!ListPack::from_bytes(bytes).validate_integrity(false));
+    }
+
+    #[test]
+    fn random_against_vec() {
+        let mut rng = rand::rng();
+        let mut lp = ListPack::new();
+        let mut model: Vec<String> = vec![];
+        for _ in 0..2000 {
+            let value = if rng.random_bool(0.5) {
+                rng.random_range(-100000i64..100000).to_string()
+            } else {
+                "x".repeat(rng.random_range(0..200))
+            };
+            match rng.random_range(0..4) {
+                0 => {
+                    lp.append(value.as_bytes());
+                    model.push(value);
+                }
+                1 => {
+                    let index = rng.random_range(0..=model.len());
+                    match lp.seek(index as i64) {
+                        Some(p) => lp.insert(value.as_bytes(), p, LP_BEFORE),
+                        None => lp.append(value.as_bytes()),
+                    };
+                    model.insert(index, value);
+                }
+                2 if !model.is_empty() => {
+                    let index = rng.random_range(0..model.len());
+                    lp.delete(lp.seek(index as i64).unwrap());
+                    model.remove(index);
+                }
+                _ if !model.is_empty() => {
+                    let index = rng.random_range(0..model.len());
+                    lp.replace(lp.seek(index as i64).unwrap(), value.as_bytes());
+                    model[index] = value;
+                }
+                _ => {}
+            }
+            assert_eq!(lp.len(), model.len());
+        }
+        assert!(lp.validate_integrity(true));
+        assert_eq!(to_strings(&lp), model);
+    }
+}
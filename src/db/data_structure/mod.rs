@@ -4,5 +4,6 @@ pub mod intset;
 pub mod listpack;
 pub mod quicklist;
 pub mod skiplist;
+pub mod stream;
 pub mod ziplist;
 pub mod zset;
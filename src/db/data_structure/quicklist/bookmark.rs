@@ -1,6 +1,54 @@
-use crate::db::data_structure::quicklist::quicklist::QuickListNode;
+use std::ptr::NonNull;
 
+use crate::db::data_structure::quicklist::quicklist::{QuickList, QuickListNode};
+use crate::db::data_structure::quicklist::QL_BM_BITS;
+
+/// A named reference to a node, it moves to the next node when its node is deleted
+/// so a long iteration can be resumed later.
 pub struct QuickListBookmark {
-    node: QuickListNode,
-    name: String,
+    pub(crate) node: NonNull<QuickListNode>,
+    pub(crate) name: String,
+}
+
+const QL_MAX_BM: usize = (1 << QL_BM_BITS) - 1;
+
+impl QuickList {
+    /// Creates or updates the bookmark `name`, fails once the bookmark limit is reached.
+    pub fn bookmark_create(&mut self, name: &str, node: NonNull<QuickListNode>) -> bool {
+        if let Some(bm) = self.bookmarks.iter_mut().find(|bm| bm.name == name) {
+            bm.node = node;
+            return true;
+        }
+        if self.bookmarks.len() >= QL_MAX_BM {
+            return false;
+        }
+        self.bookmarks.push(QuickListBookmark { node, name: name.to_string() });
+        true
+    }
+
+    pub fn bookmark_find(&self, name: &str) -> Option<NonNull<QuickListNode>> {
+        self.bookmarks.iter().find(|bm| bm.name == name).map(|bm| bm.node)
+    }
+
+    pub fn bookmark_delete(&mut self, name: &str) -> bool {
+        match self.bookmarks.iter().position(|bm| bm.name == name) {
+            Some(i) => {
+                self.bookmarks.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn bookmarks_clear(&mut self) {
+        self.bookmarks.clear();
+    }
+
+    /// Moves the bookmarks of a deleted `node` to `next`, or drops them at the tail.
+    pub(crate) fn update_bookmark(&mut self, node: NonNull<QuickListNode>, next: Option<NonNull<QuickListNode>>) {
+        match next {
+            Some(next) => self.bookmarks.iter_mut().filter(|bm| bm.node == node).for_each(|bm| bm.node = next),
+            None => self.bookmarks.retain(|bm| bm.node != node),
+        }
+    }
 }
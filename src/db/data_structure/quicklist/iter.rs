@@ -1 +1,115 @@
+use std::marker::PhantomData;
+use std::ptr::NonNull;
 
+use crate::db::data_structure::listpack::ListPack;
+use crate::db::data_structure::quicklist::quicklist::{QuickList, QuickListNode};
+
+/// Position in a node, compressed nodes are read from a decompressed copy.
+struct Cursor {
+    node: NonNull<QuickListNode>,
+    copy: Option<ListPack>,
+    pos: usize,
+}
+
+impl Cursor {
+    fn new(node: NonNull<QuickListNode>, from_tail: bool) -> Self {
+        let (copy, pos) = unsafe { (*node.as_ptr()).reader(from_tail) };
+        Self { node, copy, pos }
+    }
+
+    fn lp(&self) -> &ListPack {
+        match &self.copy {
+            Some(lp) => lp,
+            None => unsafe { (*self.node.as_ptr()).entry() },
+        }
+    }
+
+    fn value(&self) -> Vec<u8> {
+        unsafe { (*self.node.as_ptr()).value_at(self.lp(), self.pos) }
+    }
+
+    /// Moves to the next element, crossing into the following node at the end of this one.
+    fn step(&mut self, forward: bool) -> bool {
+        let node = unsafe { &*self.node.as_ptr() };
+        let pos = if node.count == 1 {
+            None
+        } else if forward {
+            self.lp().next(self.pos)
+        } else {
+            self.lp().prev(self.pos)
+        };
+        if let Some(pos) = pos {
+            self.pos = pos;
+            return true;
+        }
+        match if forward { node.next } else { node.prev } {
+            Some(next) => {
+                *self = Cursor::new(next, !forward);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct QuickListIter<'a> {
+    front: Option<Cursor>,
+    back: Option<Cursor>,
+    len: usize,
+    _marker: PhantomData<&'a QuickList>,
+}
+
+impl QuickList {
+    pub fn iter(&self) -> QuickListIter<'_> {
+        QuickListIter {
+            front: None,
+            back: None,
+            len: self.count() as usize,
+            _marker: PhantomData,
+        }
+        .init(self.head, self.tail)
+    }
+}
+
+impl QuickListIter<'_> {
+    fn init(mut self, head: Option<NonNull<QuickListNode>>, tail: Option<NonNull<QuickListNode>>) -> Self {
+        if self.len > 0 {
+            self.front = head.map(|node| Cursor::new(node, false));
+            self.back = tail.map(|node| Cursor::new(node, true));
+        }
+        self
+    }
+
+    fn advance(&mut self, forward: bool) -> Option<Vec<u8>> {
+        if self.len == 0 {
+            return None;
+        }
+        let cursor = if forward { self.front.as_mut()? } else { self.back.as_mut()? };
+        let value = cursor.value();
+        self.len -= 1;
+        if self.len > 0 {
+            cursor.step(forward);
+        }
+        Some(value)
+    }
+}
+
+impl Iterator for QuickListIter<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.advance(true)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl DoubleEndedIterator for QuickListIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.advance(false)
+    }
+}
+
+impl ExactSizeIterator for QuickListIter<'_> {}
@@ -1,13 +1,14 @@
-mod bookmark;
-mod iter;
+pub mod bookmark;
+pub mod iter;
 mod lib;
 pub mod quicklist;
+mod test;
 
 const QL_FILL_BITS: i32 = 16;
 const QL_COMP_BITS: u32 = 16;
 const QL_BM_BITS: u32 = 4;
-const COMPRESS_MAX: u32 = 1 << QL_COMP_BITS - 1;
-const FILL_MAX: i32 = 1 << (QL_FILL_BITS - 1) - 1;
+const COMPRESS_MAX: u32 = (1 << QL_COMP_BITS) - 1;
+const FILL_MAX: i32 = (1 << (QL_FILL_BITS - 1)) - 1;
 
 /// quicklist node encodings
 const QUICKLIST_NODE_ENCODING_RAW: u32 = 1;
@@ -16,6 +17,10 @@ const QUICKLIST_NODE_ENCODING_LZF: u32 = 2;
 ///quicklist compression disable
 const QUICKLIST_NOCOMPRESS: u32 = 0;
 
+/// ends of the quicklist
+pub const QUICKLIST_HEAD: i32 = 0;
+pub const QUICKLIST_TAIL: i32 = -1;
+
 /// quicklist node container formats
 const QUICKLIST_NODE_CONTAINER_PLAIN: u32 = 1;
 const QUICKLIST_NODE_CONTAINER_PACKED: u32 = 2;
@@ -60,7 +65,7 @@ pub fn quicklist_node_exceed_limit(fill: i32, new_sz: usize, new_count: u32) ->
     if sz_limit != usize::MAX {
         return new_sz > sz_limit;
     } else if count_limit != u32::MAX {
-        if !new_sz <= SIZE_SAFETY_LIMIT {
+        if new_sz > SIZE_SAFETY_LIMIT {
             return true;
         }
         return new_count > count_limit;
@@ -69,8 +74,8 @@ pub fn quicklist_node_exceed_limit(fill: i32, new_sz: usize, new_count: u32) ->
 }
 
 pub fn is_large_element(sz: usize, fill: i32) -> bool {
-    if fill > 0 {
-        !sz <= SIZE_SAFETY_LIMIT
+    if fill >= 0 {
+        sz > SIZE_SAFETY_LIMIT
     } else {
         sz > quicklist_node_neg_fill_limit(fill)
     }
@@ -1,21 +1,25 @@
 use std::ptr::NonNull;
 
+use crate::db::data_structure::listpack::ListPack;
+use crate::db::data_structure::listpack::{LP_AFTER, LP_BEFORE};
+use crate::db::data_structure::quicklist::bookmark::QuickListBookmark;
 use crate::db::data_structure::quicklist::lib::QuickListLzf;
 use crate::db::data_structure::quicklist::{
     is_large_element, quicklist_node_exceed_limit, COMPRESS_MAX, FILL_MAX, MIN_COMPRESS_BYTES,
-    MIN_COMPRESS_IMPROVE, QUICKLIST_NODE_CONTAINER_PACKED, QUICKLIST_NODE_CONTAINER_PLAIN,
+    MIN_COMPRESS_IMPROVE, QUICKLIST_HEAD, QUICKLIST_NODE_CONTAINER_PACKED, QUICKLIST_NODE_CONTAINER_PLAIN,
     QUICKLIST_NODE_ENCODING_LZF, QUICKLIST_NODE_ENCODING_RAW, SIZE_ESTIMATE_OVERHEAD,
 };
-use crate::db::data_structure::ziplist::ziplist::ZipList;
 
 pub struct QuickListNode {
-    prev: Option<NonNull<QuickListNode>>,
-    next: Option<NonNull<QuickListNode>>,
-    entry: ZipList,
-    /// entry size in bytes
+    pub(crate) prev: Option<NonNull<QuickListNode>>,
+    pub(crate) next: Option<NonNull<QuickListNode>>,
+    /// the listpack, the element itself for a plain node, or the `QuickListLzf`
+    /// bytes once compressed
+    entry: ListPack,
+    /// entry size in bytes, the uncompressed size while compressed
     sz: usize,
     /// count of items in listpack
-    count: u32,
+    pub(crate) count: u32,
     /// RAW==1 or LZF==2
     encoding: u32,
     /// PLAIN==1 or PACKED==2
@@ -26,8 +30,6 @@ pub struct QuickListNode {
     attempted_compress: u32,
     /// prevent compression of entry that will be used later
     dont_compress: usize,
-    /// more bits to steal for future usage
-    extra: u32,
 }
 
 impl QuickListNode {
@@ -35,7 +37,7 @@ impl QuickListNode {
         Self {
             prev: None,
             next: None,
-            entry: ZipList::new(),
+            entry: ListPack::new(),
             sz: 0,
             count: 0,
             encoding: QUICKLIST_NODE_ENCODING_RAW,
@@ -43,7 +45,6 @@ impl QuickListNode {
             recompress: 0,
             attempted_compress: 0,
             dont_compress: 0,
-            extra: 0,
         }
     }
 
@@ -57,7 +58,8 @@ impl QuickListNode {
             return;
         }
 
-        let compress = match lzf::compress(&self.entry.data) {
+        self.attempted_compress = 1;
+        let compress = match lzf::compress(self.entry.as_bytes()) {
             Ok(lzf) => lzf,
             Err(_) => return,
         };
@@ -66,39 +68,48 @@ impl QuickListNode {
         if lzf.sz == 0 || (lzf.sz + MIN_COMPRESS_IMPROVE) >= self.sz {
             return;
         }
-        self.entry = ZipList::create(lzf.to_u8());
+        self.entry = ListPack::from_bytes(lzf.to_u8());
         self.encoding = QUICKLIST_NODE_ENCODING_LZF;
     }
 
     pub fn get_lzf(&self) -> QuickListLzf {
-        let lzf = QuickListLzf::from_u8(&self.entry.data);
-        lzf
+        QuickListLzf::from_u8(self.entry.as_bytes())
     }
 
     pub fn decompress(&mut self) {
         self.recompress = 0;
-        let lzf = QuickListLzf::from_u8(&self.entry.data);
-        let decompress = lzf::decompress(&lzf.compressed, lzf.sz).unwrap();
+        let lzf = QuickListLzf::from_u8(self.entry.as_bytes());
+        let decompress = lzf::decompress(&lzf.compressed, self.sz).unwrap();
         let len = decompress.len();
         if len == 0 {
             return;
         }
         self.sz = len;
-        self.entry = ZipList::create(decompress);
+        self.entry = ListPack::from_bytes(decompress);
         self.encoding = QUICKLIST_NODE_ENCODING_RAW;
     }
 
-    pub fn _allow_insert(&self, fill: i32, sz: usize) -> bool {
-        let new_sz = sz + SIZE_ESTIMATE_OVERHEAD;
+    /// A decompressed copy of a compressed node, for readers that can't modify it.
+    fn decompressed_copy(&self) -> Option<ListPack> {
+        if self.encoding != QUICKLIST_NODE_ENCODING_LZF {
+            return None;
+        }
+        let lzf = self.get_lzf();
+        Some(ListPack::from_bytes(lzf::decompress(&lzf.compressed, self.sz).unwrap()))
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.encoding == QUICKLIST_NODE_ENCODING_LZF
+    }
 
+    pub fn _allow_insert(&self, fill: i32, sz: usize) -> bool {
         if self.is_plain() || is_large_element(sz, fill) {
             return false;
         }
 
-        if quicklist_node_exceed_limit(fill, new_sz, self.count + 1) {
-            return false;
-        }
-        return true;
+        // estimate how many bytes the new entry adds to the listpack
+        let new_sz = self.sz + sz + SIZE_ESTIMATE_OVERHEAD;
+        !quicklist_node_exceed_limit(fill, new_sz, self.count + 1)
     }
 
     pub fn _allow_merge(
@@ -114,15 +125,13 @@ impl QuickListNode {
              {
                 return false;
             }
+            // one listpack header and EOF go away in the merge
             let merge_sz = (*a.unwrap().as_ptr()).sz + (*b.unwrap().as_ptr()).sz - 7;
-            if quicklist_node_exceed_limit(
+            !quicklist_node_exceed_limit(
                 fill,
                 merge_sz,
                 (*a.unwrap().as_ptr()).count + (*b.unwrap().as_ptr()).count,
-            ) {
-                return false;
-            }
-            return true;
+            )
         }
     }
 
@@ -130,15 +139,51 @@ impl QuickListNode {
         self.container == QUICKLIST_NODE_CONTAINER_PLAIN
     }
 
-    pub fn create_node(container: i32, value: Vec<u8>, sz: usize) {
+    /// A node holding `value`, plain nodes keep a single large element as is
+    /// instead of in a listpack.
+    pub fn create_node(container: u32, value: &[u8]) -> Self {
         let mut new_node = QuickListNode::create();
-        new_node.container = container as u32;
-        if container == QUICKLIST_NODE_CONTAINER_PLAIN as i32 {
-            //new_node.entry = Vec::with_capacity(sz);
-            new_node.entry = ZipList::create(value);
+        new_node.container = container;
+        if container == QUICKLIST_NODE_CONTAINER_PLAIN {
+            new_node.entry = ListPack::from_bytes(value.to_vec());
+        } else {
+            new_node.entry.append(value);
+        }
+        new_node.count = 1;
+        new_node.update_sz();
+        new_node
+    }
+
+    fn update_sz(&mut self) {
+        self.sz = if self.is_plain() { self.entry.as_bytes().len() } else { self.entry.bytes() };
+    }
+
+    /// The value at `offset` in `lp`, the node's listpack or its decompressed copy.
+    pub(crate) fn value_at(&self, lp: &ListPack, pos: usize) -> Vec<u8> {
+        if self.is_plain() {
+            lp.as_bytes().to_vec()
         } else {
+            lp.get(pos).to_bytes()
         }
     }
+
+    /// The listpack to read from and the position of its first or last element.
+    pub(crate) fn reader(&self, from_tail: bool) -> (Option<ListPack>, usize) {
+        let copy = self.decompressed_copy();
+        let lp = copy.as_ref().unwrap_or(&self.entry);
+        let pos = if self.is_plain() {
+            0
+        } else if from_tail {
+            lp.last().unwrap()
+        } else {
+            lp.first().unwrap()
+        };
+        (copy, pos)
+    }
+
+    pub(crate) fn entry(&self) -> &ListPack {
+        &self.entry
+    }
 }
 
 pub fn decompress_node(node: Option<NonNull<QuickListNode>>) {
@@ -166,14 +211,12 @@ pub fn compress_node(node: Option<NonNull<QuickListNode>>) {
     }
 }
 
-pub struct QuickListBookMark {
-    node: Option<NonNull<QuickListNode>>,
-    name: String,
-}
-
+/// A doubly linked list of listpacks. `fill` bounds the size of every listpack
+/// (positive: number of entries, negative: -1 to -5 for 4kb to 64kb) and all
+/// nodes further than `compress` nodes from both ends are LZF compressed.
 pub struct QuickList {
-    head: Option<NonNull<QuickListNode>>,
-    tail: Option<NonNull<QuickListNode>>,
+    pub(crate) head: Option<NonNull<QuickListNode>>,
+    pub(crate) tail: Option<NonNull<QuickListNode>>,
     /// total count of all entries in all listpacks
     count: u64,
     /// number of quicklist Nodes
@@ -182,8 +225,7 @@ pub struct QuickList {
     fill: i32,
     /// depth of end nodes not to compress
     compress: u32,
-    bookmark_count: u32,
-    bookmarks: Vec<QuickListBookMark>,
+    pub(crate) bookmarks: Vec<QuickListBookmark>,
 }
 
 impl QuickList {
@@ -195,7 +237,6 @@ impl QuickList {
             len: 0,
             fill: -2,
             compress: 0,
-            bookmark_count: 0,
             bookmarks: vec![],
         }
     }
@@ -224,7 +265,7 @@ impl QuickList {
         self.fill = fill;
     }
 
-    pub fn set_options(&mut self, depth: i32, fill: i32) {
+    pub fn set_options(&mut self, fill: i32, depth: i32) {
         self.set_fill(fill);
         self.set_compress_depth(depth);
     }
@@ -233,21 +274,28 @@ impl QuickList {
         self.count
     }
 
+    /// Number of nodes.
+    pub fn node_count(&self) -> u64 {
+        self.len
+    }
+
     pub fn release(&mut self) {
         let mut current = self.head;
-        let mut len = self.len;
-        unsafe {
-            while len > 0 {
-                len -= 1;
-                let next = (*current.unwrap().as_ptr()).next;
-                (*current.unwrap().as_ptr()).entry = ZipList::new();
-                self.count -= (*current.unwrap().as_ptr()).count as u64;
-                self.len -= 1;
-                current = next;
+        while let Some(node) = current {
+            unsafe {
+                current = (*node.as_ptr()).next;
+                drop(Box::from_raw(node.as_ptr()));
             }
         }
+        self.head = None;
+        self.tail = None;
+        self.count = 0;
+        self.len = 0;
+        self.bookmarks.clear();
     }
 
+    /// Decompresses the nodes within `compress` of both ends and compresses the first
+    /// nodes past them and `node`.
     pub fn _compress(&self, node: Option<NonNull<QuickListNode>>) {
         if self.len == 0 {
             return;
@@ -258,7 +306,7 @@ impl QuickList {
                     && (*self.tail.unwrap().as_ptr()).recompress == 0
             );
         }
-        if self.compress != 0 || self.len < (self.compress * 2) as u64 {
+        if self.compress == 0 || self.len < (self.compress * 2) as u64 {
             return;
         }
         let mut forward = self.head;
@@ -358,4 +406,360 @@ impl QuickList {
     ) {
         self._insert_node(old_node, new_node, true);
     }
+
+    fn new_node(&self, value: &[u8]) -> Option<NonNull<QuickListNode>> {
+        let container = if is_large_element(value.len(), self.fill) {
+            QUICKLIST_NODE_CONTAINER_PLAIN
+        } else {
+            QUICKLIST_NODE_CONTAINER_PACKED
+        };
+        let node = Box::new(QuickListNode::create_node(container, value));
+        Some(unsafe { NonNull::new_unchecked(Box::into_raw(node)) })
+    }
+
+    fn allow_insert(&self, node: Option<NonNull<QuickListNode>>, sz: usize) -> bool {
+        match node {
+            Some(node) => unsafe { (*node.as_ptr())._allow_insert(self.fill, sz) },
+            None => false,
+        }
+    }
+
+    /// Runs `f` on the decompressed listpack of `node` and compresses it again.
+    fn update_node<R>(&mut self, node: NonNull<QuickListNode>, f: impl FnOnce(&mut ListPack) -> R) -> R {
+        decompress_node_for_use(Some(node));
+        let res = unsafe {
+            let n = &mut *node.as_ptr();
+            let res = f(&mut n.entry);
+            n.count = n.entry.len() as u32;
+            n.update_sz();
+            res
+        };
+        self.compress(Some(node));
+        res
+    }
+
+    /// Unlinks and frees `node` without touching `count`.
+    fn unlink_node(&mut self, node: NonNull<QuickListNode>) {
+        unsafe {
+            let prev = (*node.as_ptr()).prev;
+            let next = (*node.as_ptr()).next;
+            match prev {
+                Some(prev) => (*prev.as_ptr()).next = next,
+                None => self.head = next,
+            }
+            match next {
+                Some(next) => (*next.as_ptr()).prev = prev,
+                None => self.tail = prev,
+            }
+            self.update_bookmark(node, next);
+            self.len -= 1;
+            drop(Box::from_raw(node.as_ptr()));
+        }
+        // nodes that moved within the compress depth are decompressed
+        self._compress(None);
+    }
+
+    fn del_node(&mut self, node: NonNull<QuickListNode>) {
+        unsafe {
+            self.count -= (*node.as_ptr()).count as u64;
+        }
+        self.unlink_node(node);
+    }
+
+    /// Adds `value` at the head (`QUICKLIST_HEAD`) or the tail.
+    pub fn push(&mut self, value: &[u8], position: i32) {
+        if position == QUICKLIST_HEAD {
+            self.push_head(value);
+        } else {
+            self.push_tail(value);
+        }
+    }
+
+    pub fn push_head(&mut self, value: &[u8]) {
+        if self.allow_insert(self.head, value.len()) {
+            self.update_node(self.head.unwrap(), |lp| lp.prepend(value));
+        } else {
+            let node = self.new_node(value);
+            self.insert_node_before(self.head, node);
+        }
+        self.count += 1;
+    }
+
+    pub fn push_tail(&mut self, value: &[u8]) {
+        if self.allow_insert(self.tail, value.len()) {
+            self.update_node(self.tail.unwrap(), |lp| lp.append(value));
+        } else {
+            let node = self.new_node(value);
+            self.insert_node_after(self.tail, node);
+        }
+        self.count += 1;
+    }
+
+    /// Removes and returns the element at the head (`QUICKLIST_HEAD`) or the tail.
+    pub fn pop(&mut self, position: i32) -> Option<Vec<u8>> {
+        let from_tail = position != QUICKLIST_HEAD;
+        let node = if from_tail { self.tail? } else { self.head? };
+        let offset = if from_tail { unsafe { (*node.as_ptr()).count - 1 } } else { 0 };
+        let value = self.node_value(node, offset as usize);
+        self.del_index(node, offset as usize);
+        Some(value)
+    }
+
+    fn node_value(&self, node: NonNull<QuickListNode>, offset: usize) -> Vec<u8> {
+        unsafe {
+            let n = &*node.as_ptr();
+            let copy = n.decompressed_copy();
+            let lp = copy.as_ref().unwrap_or(&n.entry);
+            if n.is_plain() {
+                return lp.as_bytes().to_vec();
+            }
+            n.value_at(lp, lp.seek(offset as i64).unwrap())
+        }
+    }
+
+    /// Deletes the element at `offset` of `node`, and the node once it is empty.
+    fn del_index(&mut self, node: NonNull<QuickListNode>, offset: usize) {
+        unsafe {
+            if (*node.as_ptr()).is_plain() || (*node.as_ptr()).count == 1 {
+                self.del_node(node);
+                return;
+            }
+        }
+        self.update_node(node, |lp| {
+            let p = lp.seek(offset as i64).unwrap();
+            lp.delete(p);
+        });
+        self.count -= 1;
+    }
+
+    /// The node holding the element at `index` and its offset in the node.
+    /// Negative indexes count from the tail.
+    fn locate(&self, index: i64) -> Option<(NonNull<QuickListNode>, usize)> {
+        let forward = index >= 0;
+        let idx = if forward { index } else { -index - 1 } as u64;
+        if idx >= self.count {
+            return None;
+        }
+        let mut accum = 0u64;
+        let mut n = if forward { self.head } else { self.tail };
+        unsafe {
+            while let Some(node) = n {
+                let count = (*node.as_ptr()).count as u64;
+                if accum + count > idx {
+                    let offset = if forward { idx - accum } else { count - 1 - (idx - accum) };
+                    return Some((node, offset as usize));
+                }
+                accum += count;
+                n = if forward { (*node.as_ptr()).next } else { (*node.as_ptr()).prev };
+            }
+        }
+        None
+    }
+
+    /// The element at `index`, negative indexes count from the tail.
+    pub fn index(&self, index: i64) -> Option<Vec<u8>> {
+        let (node, offset) = self.locate(index)?;
+        Some(self.node_value(node, offset))
+    }
+
+    pub fn insert_before(&mut self, index: i64, value: &[u8]) -> bool {
+        self.insert(index, value, false)
+    }
+
+    pub fn insert_after(&mut self, index: i64, value: &[u8]) -> bool {
+        self.insert(index, value, true)
+    }
+
+    /// Inserts next to the element at `index`. A full node passes the element to a
+    /// neighbour with room, or is split in two around it.
+    fn insert(&mut self, index: i64, value: &[u8], after: bool) -> bool {
+        let (node, offset) = match self.locate(index) {
+            Some(found) => found,
+            None => return false,
+        };
+        let sz = value.len();
+        let (count, prev, next) = unsafe { ((*node.as_ptr()).count as usize, (*node.as_ptr()).prev, (*node.as_ptr()).next) };
+        let at_tail = after && offset == count - 1;
+        let at_head = !after && offset == 0;
+        let whence = if after { LP_AFTER } else { LP_BEFORE };
+
+        if self.allow_insert(Some(node), sz) {
+            self.update_node(node, |lp| {
+                let p = lp.seek(offset as i64).unwrap();
+                lp.insert(value, p, whence);
+            });
+        } else if at_tail && self.allow_insert(next, sz) {
+            self.update_node(next.unwrap(), |lp| lp.prepend(value));
+        } else if at_head && self.allow_insert(prev, sz) {
+            self.update_node(prev.unwrap(), |lp| lp.append(value));
+        } else if at_tail || at_head {
+            let new_node = self.new_node(value);
+            self._insert_node(Some(node), new_node, after);
+        } else {
+            let split = if after { offset + 1 } else { offset };
+            self.split_node(node, split);
+            if self.allow_insert(Some(node), sz) {
+                self.update_node(node, |lp| lp.append(value));
+            } else {
+                let new_node = self.new_node(value);
+                self.insert_node_after(Some(node), new_node);
+            }
+            self.count += 1;
+            self.merge_nodes(node);
+            return true;
+        }
+        self.count += 1;
+        true
+    }
+
+    /// Moves the elements from `split` on into a new node after `node`.
+    fn split_node(&mut self, node: NonNull<QuickListNode>, split: usize) {
+        let tail = self.update_node(node, |lp| {
+            let mut tail = lp.clone();
+            tail.delete_range(0, split);
+            lp.delete_range(split as i64, usize::MAX);
+            tail
+        });
+        let mut new_node = QuickListNode::create();
+        new_node.count = tail.len() as u32;
+        new_node.entry = tail;
+        new_node.update_sz();
+        let new_node = Some(unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(new_node))) });
+        self.insert_node_after(Some(node), new_node);
+    }
+
+    /// Merges `center` with its neighbours while the result fits in one node.
+    fn merge_nodes(&mut self, mut center: NonNull<QuickListNode>) {
+        unsafe {
+            let prev = (*center.as_ptr()).prev;
+            if QuickListNode::_allow_merge(prev, Some(center), self.fill) {
+                center = self.listpack_merge(prev.unwrap(), center);
+            }
+            let next = (*center.as_ptr()).next;
+            if QuickListNode::_allow_merge(Some(center), next, self.fill) {
+                self.listpack_merge(center, next.unwrap());
+            }
+        }
+    }
+
+    /// Appends the elements of `b` to `a` and removes `b`.
+    fn listpack_merge(&mut self, a: NonNull<QuickListNode>, b: NonNull<QuickListNode>) -> NonNull<QuickListNode> {
+        decompress_node(Some(b));
+        let other = unsafe { std::mem::take(&mut (*b.as_ptr()).entry) };
+        self.update_node(a, |lp| lp.merge(&other));
+        self.unlink_node(b);
+        a
+    }
+
+    /// Replaces the element at `index`.
+    pub fn replace_at_index(&mut self, index: i64, value: &[u8]) -> bool {
+        let index = if index < 0 { index + self.count as i64 } else { index };
+        let (node, offset) = match self.locate(index) {
+            Some(found) => found,
+            None => return false,
+        };
+        let (plain, sz, count) = unsafe { ((*node.as_ptr()).is_plain(), (*node.as_ptr()).sz, (*node.as_ptr()).count) };
+        let large = is_large_element(value.len(), self.fill);
+        if !plain && !large
+            && !quicklist_node_exceed_limit(self.fill, sz + value.len() + SIZE_ESTIMATE_OVERHEAD, count)
+        {
+            self.update_node(node, |lp| {
+                let p = lp.seek(offset as i64).unwrap();
+                lp.replace(p, value);
+            });
+        } else if plain && large {
+            decompress_node_for_use(Some(node));
+            unsafe {
+                (*node.as_ptr()).entry = ListPack::from_bytes(value.to_vec());
+                (*node.as_ptr()).update_sz();
+            }
+            self.compress(Some(node));
+        } else {
+            self.insert_after(index, value);
+            let (node, offset) = self.locate(index).unwrap();
+            self.del_index(node, offset);
+        }
+        true
+    }
+
+    /// Deletes up to `count` elements starting at `start`, returning how many were
+    /// deleted.
+    pub fn del_range(&mut self, start: i64, count: i64) -> u64 {
+        if count <= 0 {
+            return 0;
+        }
+        let available = if start >= 0 { self.count as i64 - start } else { -start };
+        let mut extent = count.min(available).max(0) as u64;
+        let deleted = extent;
+        let (mut node, mut offset) = match self.locate(start) {
+            Some(found) => found,
+            None => return 0,
+        };
+        while extent > 0 {
+            let (node_count, next) = unsafe { ((*node.as_ptr()).count as u64, (*node.as_ptr()).next) };
+            let del = (node_count - offset as u64).min(extent);
+            if offset == 0 && del == node_count {
+                self.del_node(node);
+            } else {
+                self.update_node(node, |lp| lp.delete_range(offset as i64, del as usize));
+                self.count -= del;
+            }
+            extent -= del;
+            match next {
+                Some(next) => node = next,
+                None => break,
+            }
+            offset = 0;
+        }
+        deleted
+    }
+
+    /// Moves the tail element to the head.
+    pub fn rotate(&mut self) {
+        if self.count <= 1 {
+            return;
+        }
+        if let Some(value) = self.pop(-1) {
+            self.push_head(&value);
+        }
+    }
+
+    /// Checks the links, counts and that the nodes within `compress` of both ends
+    /// are not compressed.
+    pub fn validate(&self) -> bool {
+        let mut count = 0u64;
+        let mut len = 0u64;
+        let mut prev = None;
+        let mut n = self.head;
+        unsafe {
+            while let Some(node) = n {
+                let node_ref = &*node.as_ptr();
+                if node_ref.prev != prev || node_ref.count == 0 {
+                    return false;
+                }
+                let in_depth = len < self.compress as u64 || self.len - len <= self.compress as u64;
+                if in_depth && node_ref.is_compressed() {
+                    return false;
+                }
+                if !node_ref.is_plain() {
+                    let copy = node_ref.decompressed_copy();
+                    let lp = copy.as_ref().unwrap_or(&node_ref.entry);
+                    if !lp.validate_integrity(true) || lp.len() != node_ref.count as usize || lp.bytes() != node_ref.sz {
+                        return false;
+                    }
+                }
+                count += node_ref.count as u64;
+                len += 1;
+                prev = n;
+                n = node_ref.next;
+            }
+        }
+        self.tail == prev && count == self.count && len == self.len
+    }
+}
+
+impl Drop for QuickList {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::db::data_structure::quicklist::quicklist::QuickList;
+    use crate::db::data_structure::quicklist::{QUICKLIST_HEAD, QUICKLIST_TAIL};
+    use rand::Rng;
+
+    const FILLS: [i32; 5] = [-2, -1, 1, 2, 4];
+    const DEPTHS: [i32; 3] = [0, 1, 2];
+
+    fn random_value(rng: &mut impl Rng) -> Vec<u8> {
+        match rng.random_range(0..10) {
+            0..=3 => rng.random_range(-100000i64..100000).to_string().into_bytes(),
+            4..=8 => vec![b'a' + rng.random_range(0..26u8); rng.random_range(1..120)],
+            // larger than any listpack may get, stored in a plain node
+            _ => vec![b'p'; rng.random_range(9000..10000)],
+        }
+    }
+
+    fn check(ql: &QuickList, model: &VecDeque<Vec<u8>>) {
+        assert!(ql.validate());
+        assert_eq!(ql.count() as usize, model.len());
+        assert_eq!(ql.iter().len(), model.len());
+        assert!(ql.iter().eq(model.iter().cloned()));
+        assert!(ql.iter().rev().eq(model.iter().rev().cloned()));
+    }
+
+    #[test]
+    fn push_pop_index() {
+        let mut ql = QuickList::new(4, 1);
+        for i in 0..100 {
+            ql.push_tail(format!("{}", i).as_bytes());
+        }
+        assert_eq!(ql.count(), 100);
+        assert_eq!(ql.node_count(), 25);
+        assert_eq!(ql.index(0), Some(b"0".to_vec()));
+        assert_eq!(ql.index(-1), Some(b"99".to_vec()));
+        assert_eq!(ql.index(57), Some(b"57".to_vec()));
+        assert_eq!(ql.index(100), None);
+        assert_eq!(ql.index(-101), None);
+        assert_eq!(ql.pop(QUICKLIST_HEAD), Some(b"0".to_vec()));
+        assert_eq!(ql.pop(QUICKLIST_TAIL), Some(b"99".to_vec()));
+        assert!(ql.validate());
+    }
+
+    #[test]
+    fn interior_nodes_compressed() {
+        let mut ql = QuickList::new(-2, 1);
+        let value = vec![b'v'; 1000];
+        for _ in 0..40 {
+            ql.push_tail(&value);
+        }
+        assert!(ql.node_count() > 3);
+        assert!(ql.validate());
+        unsafe {
+            let head = ql.head.unwrap();
+            let second = (*head.as_ptr()).next.unwrap();
+            assert!(!(*head.as_ptr()).is_compressed());
+            assert!((*second.as_ptr()).is_compressed());
+        }
+        assert_eq!(ql.index(20), Some(value.clone()));
+        assert_eq!(ql.iter().count(), 40);
+    }
+
+    #[test]
+    fn insert_splits_and_merges() {
+        let mut ql = QuickList::new(4, 0);
+        for i in 0..8 {
+            ql.push_tail(format!("{}", i).as_bytes());
+        }
+        assert!(ql.insert_after(1, b"a"));
+        assert!(ql.insert_before(0, b"b"));
+        assert!(!ql.insert_before(100, b"c"));
+        let values: Vec<Vec<u8>> = ql.iter().collect();
+        let expect: Vec<&[u8]> = vec![b"b", b"0", b"1", b"a", b"2", b"3", b"4", b"5", b"6", b"7"];
+        assert_eq!(values, expect);
+        assert!(ql.validate());
+
+        assert_eq!(ql.del_range(2, 3), 3);
+        assert_eq!(ql.del_range(-2, 10), 2);
+        let values: Vec<Vec<u8>> = ql.iter().collect();
+        let expect: Vec<&[u8]> = vec![b"b", b"0", b"3", b"4", b"5"];
+        assert_eq!(values, expect);
+        assert!(ql.validate());
+    }
+
+    #[test]
+    fn bookmarks_follow_deleted_node() {
+        let mut ql = QuickList::new(1, 0);
+        for i in 0..3 {
+            ql.push_tail(format!("{}", i).as_bytes());
+        }
+        let head = ql.head.unwrap();
+        assert!(ql.bookmark_create("bm", head));
+        ql.pop(QUICKLIST_HEAD);
+        assert_eq!(ql.bookmark_find("bm"), ql.head);
+        ql.del_range(0, 3);
+        assert!(ql.bookmark_find("bm").is_none());
+    }
+
+    #[test]
+    fn random_against_vecdeque() {
+        let mut rng = rand::rng();
+        for fill in FILLS {
+            for depth in DEPTHS {
+                let mut ql = QuickList::new(fill, depth);
+                let mut model: VecDeque<Vec<u8>> = VecDeque::new();
+                for round in 0..600 {
+                    let value = random_value(&mut rng);
+                    let len = model.len() as i64;
+                    match rng.random_range(0..9) {
+                        0 => {
+                            ql.push_head(&value);
+                            model.push_front(value);
+                        }
+                        1 | 2 => {
+                            ql.push_tail(&value);
+                            model.push_back(value);
+                        }
+                        3 => {
+                            let head = rng.random_bool(0.5);
+                            let pos = if head { QUICKLIST_HEAD } else { QUICKLIST_TAIL };
+                            let expect = if head { model.pop_front() } else { model.pop_back() };
+                            assert_eq!(ql.pop(pos), expect);
+                        }
+                        4 if len > 0 => {
+                            let index = rng.random_range(-len..len);
+                            let i = if index < 0 { index + len } else { index } as usize;
+                            assert_eq!(ql.index(index).as_ref(), model.get(i));
+                        }
+                        5 if len > 0 => {
+                            let index = rng.random_range(0..len);
+                            if rng.random_bool(0.5) {
+                                assert!(ql.insert_before(index, &value));
+                                model.insert(index as usize, value);
+                            } else {
+                                assert!(ql.insert_after(index, &value));
+                                model.insert(index as usize + 1, value);
+                            }
+                        }
+                        6 if len > 0 => {
+                            let index = rng.random_range(-len..len);
+                            let i = if index < 0 { index + len } else { index } as usize;
+                            assert!(ql.replace_at_index(index, &value));
+                            model[i] = value;
+                        }
+                        7 if len > 0 => {
+                            let start = rng.random_range(-len..len);
+                            let count = rng.random_range(1..8);
+                            let i = if start < 0 { start + len } else { start } as usize;
+                            let end = (i + count as usize).min(model.len());
+                            assert_eq!(ql.del_range(start, count), (end - i) as u64);
+                            model.drain(i..end);
+                        }
+                        8 => {
+                            ql.rotate();
+                            if let Some(value) = model.pop_back() {
+                                model.push_front(value);
+                            }
+                        }
+                        _ => {}
+                    }
+                    if round % 50 == 0 {
+                        check(&ql, &model);
+                    }
+                }
+                check(&ql, &model);
+            }
+        }
+    }
+}
@@ -23,7 +23,7 @@ pub fn sds_cmp(s1: &str, s2: &str) -> i32 {
     let order = s1[..min_len].cmp(&s2[..min_len]);
     match order {
         Ordering::Less => -1,
-        Ordering::Equal => 0,
+        Ordering::Equal => (l1 as i64 - l2 as i64).signum() as i32,
         Ordering::Greater => 1,
     }
 }
@@ -150,7 +150,7 @@ impl SkipList {
             if update[0] == self.head.unwrap() {
                 (*x).backward = None;
             } else {
-                Some(update[0]);
+                (*x).backward = Some(update[0]);
             }
             if (*x).level[0].forward.is_some() {
                 let forward = (*x).level[0].forward.unwrap();
@@ -169,7 +169,8 @@ impl SkipList {
             for i in 0..self.level {
                 if let Some(forward) = (*update[i].as_ptr()).level[i].forward {
                     if forward == x {
-                        (*update[i].as_ptr()).level[i].span += (*x.as_ptr()).level[i].span - 1;
+                        (*update[i].as_ptr()).level[i].span =
+                            (*update[i].as_ptr()).level[i].span + (*x.as_ptr()).level[i].span - 1;
                         (*update[i].as_ptr()).level[i].forward = (*x.as_ptr()).level[i].forward;
                     } else {
                         (*update[i].as_ptr()).level[i].span -= 1;
@@ -219,6 +220,7 @@ impl SkipList {
                 x = forward;
                 return if score == (*x.as_ptr()).score && sds_cmp(&(*x.as_ptr()).elem, elem) == 0 {
                     self.delete_node(x, &update);
+                    drop(Box::from_raw(x.as_ptr()));
                     true
                 } else {
                     false
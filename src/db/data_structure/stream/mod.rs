@@ -0,0 +1,903 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Bound;
+
+use crate::db::data_structure::listpack::lib::{lp_string_to_int64, LpEntry};
+use crate::db::data_structure::listpack::ListPack;
+
+mod test;
+
+/// The fields of one stream entry, in insertion order
+pub type StreamFields = Vec<(String, String)>;
+
+/// Entries a node may hold, deleted ones included, before a new node is started
+const STREAM_NODE_MAX_ENTRIES: i64 = 100;
+/// Bytes a node may grow to before a new node is started
+const STREAM_NODE_MAX_BYTES: usize = 4096;
+
+const STREAM_ITEM_FLAG_NONE: i64 = 0;
+/// Deleted by XDEL or trimming, skipped when reading the node
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+/// Same fields as the master entry, only the values are stored
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Marks `StreamCG::entries_read` as unknown, e.g. after XGROUP SETID without ENTRIESREAD
+pub const SCG_INVALID_ENTRIES_READ: i64 = -1;
+
+/// Stream item ID: a 128 bit number composed of a milliseconds time and a sequence
+/// counter. IDs generated in the same millisecond (or in a past millisecond if the
+/// clock jumped backward) use the millisecond time of the latest generated ID and
+/// an incremented sequence.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamID {
+    /// Unix time in milliseconds
+    pub ms: u64,
+    /// Sequence number
+    pub seq: u64,
+}
+
+impl StreamID {
+    pub const MIN: StreamID = StreamID { ms: 0, seq: 0 };
+    pub const MAX: StreamID = StreamID { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == StreamID::MIN
+    }
+
+    /// The smallest ID greater than this one, `None` for the last possible ID
+    pub fn incr(&self) -> Option<StreamID> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamID::new(self.ms, seq)),
+            None => self.ms.checked_add(1).map(|ms| StreamID::new(ms, 0)),
+        }
+    }
+
+    /// The greatest ID smaller than this one, `None` for 0-0
+    pub fn decr(&self) -> Option<StreamID> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamID::new(self.ms, seq)),
+            None => self.ms.checked_sub(1).map(|ms| StreamID::new(ms, u64::MAX)),
+        }
+    }
+
+    /// Parses `<ms>-<seq>`, or a bare `<ms>` in which case the sequence is `missing_seq`
+    pub fn parse(s: &str, missing_seq: u64) -> Option<StreamID> {
+        match s.split_once('-') {
+            Some((ms, seq)) => Some(StreamID::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamID::new(s.parse().ok()?, missing_seq)),
+        }
+    }
+}
+
+impl fmt::Display for StreamID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID argument of XADD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamIdSpec {
+    /// `*`: fully auto generated
+    Auto,
+    /// `<ms>-*`: explicit time, auto generated sequence
+    AutoSeq(u64),
+    /// `<ms>-<seq>`
+    Explicit(StreamID),
+}
+
+/// How XADD and XTRIM evict old entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    /// Keeps at most this many entries
+    MaxLen(usize),
+    /// Evicts the entries with an ID lower than this one
+    MinId(StreamID),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamTrim {
+    pub strategy: TrimStrategy,
+    /// `~`: only whole nodes are evicted, so a few more entries than asked may be kept
+    pub approx: bool,
+    /// Max number of entries evicted at once, 0 for no limit
+    pub limit: usize,
+}
+
+/// Pending entry: delivered to a consumer of a group but not acknowledged yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamNACK {
+    /// Last time this message was delivered, unix time in milliseconds
+    pub delivery_time: i64,
+    /// Number of times this message was delivered
+    pub delivery_count: u64,
+    /// The consumer this message was delivered to in the last delivery
+    pub consumer: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamConsumer {
+    pub name: String,
+    /// Last time this consumer attempted an interaction (read/claim)
+    pub seen_time: i64,
+    /// Last time this consumer was successful (read/claim), -1 if never
+    pub active_time: i64,
+    /// IDs of the messages delivered to this consumer and not acknowledged yet
+    pub pel: BTreeSet<StreamID>,
+}
+
+impl StreamConsumer {
+    fn new(name: &str, now: i64) -> Self {
+        Self {
+            name: name.to_string(),
+            seen_time: now,
+            active_time: -1,
+            pel: BTreeSet::new(),
+        }
+    }
+}
+
+/// Consumer group
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamCG {
+    /// Last delivered (not acknowledged) ID for this group. Consumers asking for
+    /// more messages with `>` get entries greater than this one.
+    pub last_id: StreamID,
+    /// Logical "read counter" of the last entry delivered to the group, or
+    /// `SCG_INVALID_ENTRIES_READ` when it is unknown
+    pub entries_read: i64,
+    /// Pending entries list of the whole group
+    pub pel: BTreeMap<StreamID, StreamNACK>,
+    pub consumers: BTreeMap<String, StreamConsumer>,
+}
+
+/// An entry returned to a consumer. The fields are `None` when the entry was
+/// deleted while still pending.
+pub type DeliveredEntry = (StreamID, Option<StreamFields>);
+
+/// Append-only log of field-value entries ordered by ID. The entries are stored in
+/// listpack nodes, kept in a B-tree keyed by master ID: the ID of the first entry
+/// added to the node. A node starts with a master entry, followed by the entries:
+///
+/// ```text
+/// master entry: <count> <deleted> <num-fields> <field> ... <field> <0>
+/// entry:        <flags> <ms-diff> <seq-diff> <num-fields> <field> <value> ... <lp-count>
+/// SAMEFIELDS:   <flags> <ms-diff> <seq-diff> <value> ... <lp-count>
+/// ```
+///
+/// IDs are stored as a difference with the master ID, and the field names are left
+/// out when they are the ones of the master entry. Deleted entries are only flagged,
+/// the node is freed once all of its entries are deleted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    nodes: BTreeMap<StreamID, ListPack>,
+    /// Number of entries, deleted ones excluded
+    length: usize,
+    /// ID of the last entry ever added, which may have been deleted since
+    pub last_id: StreamID,
+    /// The largest ID deleted by XDEL
+    pub max_deleted_entry_id: StreamID,
+    /// Number of entries added over the lifetime of the stream
+    pub entries_added: u64,
+    pub cgroups: BTreeMap<String, StreamCG>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// ID of the first entry, 0-0 when empty
+    pub fn first_id(&self) -> StreamID {
+        self.first_entry().map(|(id, _)| id).unwrap_or_default()
+    }
+
+    pub fn first_entry(&self) -> Option<(StreamID, StreamFields)> {
+        node_range(&self.nodes, StreamID::MIN, StreamID::MAX, false).next()
+    }
+
+    pub fn last_entry(&self) -> Option<(StreamID, StreamFields)> {
+        node_range(&self.nodes, StreamID::MIN, StreamID::MAX, true).next()
+    }
+
+    pub fn get(&self, id: &StreamID) -> Option<StreamFields> {
+        node_lookup(&self.nodes, id)
+    }
+
+    /// The listpack nodes and their master IDs, for the RDB and MEMORY USAGE
+    pub fn nodes(&self) -> impl ExactSizeIterator<Item = (&StreamID, &ListPack)> {
+        self.nodes.iter()
+    }
+
+    /// Adds a node read from an RDB. Returns false when it is not a well formed node
+    /// holding entries, or does not come after the nodes added so far.
+    pub fn load_node(&mut self, master: StreamID, lp: ListPack) -> bool {
+        if !lp.validate_integrity(true) {
+            return false;
+        }
+        let mut entries = vec![];
+        if decode_node(&master, &lp, &mut entries).is_none() {
+            return false;
+        }
+        let (count, deleted) = node_counts(&lp);
+        let live = entries.iter().filter(|entry| !entry.is_deleted()).count();
+        let ordered = entries.first().is_some_and(|entry| entry.id >= master)
+            && entries.windows(2).all(|pair| pair[0].id < pair[1].id);
+        let after_last = self.nodes.last_key_value().map_or(true, |(last, _)| *last < master);
+        if count <= 0 || live as i64 != count || (entries.len() - live) as i64 != deleted || !ordered || !after_last {
+            return false;
+        }
+        self.nodes.insert(master, lp);
+        self.length += live;
+        true
+    }
+
+    /// Resolves the ID of the next entry to add, failing when it would not be
+    /// greater than every ID generated so far
+    pub fn next_id(&self, spec: StreamIdSpec, now: u64) -> crate::Result<StreamID> {
+        let exhausted = "ERR The stream has exhausted the last possible ID, unable to add more items";
+        let too_small = "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+        match spec {
+            StreamIdSpec::Auto => {
+                if now > self.last_id.ms {
+                    Ok(StreamID::new(now, 0))
+                } else {
+                    self.last_id.incr().ok_or_else(|| exhausted.into())
+                }
+            }
+            StreamIdSpec::AutoSeq(ms) => {
+                if ms == 0 && self.last_id.is_zero() {
+                    return Ok(StreamID::new(0, 1));
+                }
+                match ms.cmp(&self.last_id.ms) {
+                    std::cmp::Ordering::Greater => Ok(StreamID::new(ms, 0)),
+                    std::cmp::Ordering::Equal => match self.last_id.seq.checked_add(1) {
+                        Some(seq) => Ok(StreamID::new(ms, seq)),
+                        None => Err(too_small.into()),
+                    },
+                    std::cmp::Ordering::Less => Err(too_small.into()),
+                }
+            }
+            StreamIdSpec::Explicit(id) => {
+                if id.is_zero() {
+                    return Err("ERR The ID specified in XADD must be greater than 0-0".into());
+                }
+                if id <= self.last_id {
+                    return Err(too_small.into());
+                }
+                Ok(id)
+            }
+        }
+    }
+
+    /// Appends an entry, `id` must come from `next_id`. The entry goes to the last
+    /// node, or to a new one when that node is full.
+    pub fn append(&mut self, id: StreamID, fields: StreamFields) {
+        debug_assert!(id > self.last_id || (self.last_id.is_zero() && self.entries_added == 0));
+        let size: usize = fields.iter().map(|(field, value)| field.len() + value.len()).sum();
+        let full = self.nodes.last_key_value().map_or(true, |(_, lp)| {
+            let (count, deleted) = node_counts(lp);
+            lp.bytes() + size >= STREAM_NODE_MAX_BYTES || count + deleted >= STREAM_NODE_MAX_ENTRIES
+        });
+        if full {
+            self.nodes.insert(id, new_node(&fields));
+        }
+        let (master, lp) = self.nodes.iter_mut().next_back().expect("the stream has a node");
+        let master_fields = node_master_fields(lp);
+        let same_fields = master_fields.len() == fields.len()
+            && master_fields.iter().zip(&fields).all(|(master_field, (field, _))| master_field.eq_bytes(field.as_bytes()));
+        let flags = if same_fields { STREAM_ITEM_FLAG_SAMEFIELDS } else { STREAM_ITEM_FLAG_NONE };
+        lp.append_integer(flags);
+        lp.append_integer(id.ms.wrapping_sub(master.ms) as i64);
+        lp.append_integer(id.seq.wrapping_sub(master.seq) as i64);
+        if !same_fields {
+            lp.append_integer(fields.len() as i64);
+        }
+        for (field, value) in &fields {
+            if !same_fields {
+                lp.append(field.as_bytes());
+            }
+            lp.append(value.as_bytes());
+        }
+        // the number of elements of the entry, so it can be walked backward
+        let mut lp_count = fields.len() + 3;
+        if !same_fields {
+            lp_count += fields.len() + 1;
+        }
+        lp.append_integer(lp_count as i64);
+        let (count, deleted) = node_counts(lp);
+        set_node_counts(lp, count + 1, deleted);
+        self.length += 1;
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    /// XDEL. Returns false if there is no entry with this ID.
+    pub fn delete(&mut self, id: &StreamID) -> bool {
+        let Some((&master, lp)) = self.nodes.range_mut(..=*id).next_back() else { return false };
+        let Some(entry) = node_entries(&master, lp).into_iter().find(|entry| entry.id == *id && !entry.is_deleted()) else {
+            return false;
+        };
+        entry.mark_deleted(lp);
+        let (count, deleted) = node_counts(lp);
+        if count == 1 {
+            self.nodes.remove(&master);
+        } else {
+            set_node_counts(lp, count - 1, deleted + 1);
+        }
+        self.length -= 1;
+        if *id > self.max_deleted_entry_id {
+            self.max_deleted_entry_id = *id;
+        }
+        true
+    }
+
+    /// Evicts entries from the head of the stream, a node at a time while it can go as
+    /// a whole. With an approximate trim that is all, otherwise the entries of the
+    /// next node are flagged as deleted one by one. Returns the number of deleted entries.
+    pub fn trim(&mut self, trim: &StreamTrim) -> usize {
+        let mut deleted = 0;
+        while let Some((&master, lp)) = self.nodes.iter_mut().next() {
+            if trim.limit != 0 && deleted >= trim.limit {
+                break;
+            }
+            let entries = node_entries(&master, lp);
+            let count = node_counts(lp).0 as usize;
+            let whole = match trim.strategy {
+                TrimStrategy::MaxLen(max_len) => self.length - count >= max_len,
+                TrimStrategy::MinId(min_id) => entries.last().map_or(true, |entry| entry.id < min_id),
+            };
+            if whole {
+                if trim.limit != 0 && deleted + count > trim.limit {
+                    break;
+                }
+                self.nodes.remove(&master);
+                self.length -= count;
+                deleted += count;
+                continue;
+            }
+            if trim.approx {
+                break;
+            }
+            let mut evicted = 0;
+            for entry in entries.iter().filter(|entry| !entry.is_deleted()) {
+                let evict = match trim.strategy {
+                    TrimStrategy::MaxLen(max_len) => self.length - evicted > max_len,
+                    TrimStrategy::MinId(min_id) => entry.id < min_id,
+                };
+                if !evict || (trim.limit != 0 && deleted + evicted >= trim.limit) {
+                    break;
+                }
+                entry.mark_deleted(lp);
+                evicted += 1;
+            }
+            let (count, node_deleted) = node_counts(lp);
+            set_node_counts(lp, count - evicted as i64, node_deleted + evicted as i64);
+            self.length -= evicted;
+            deleted += evicted;
+            break;
+        }
+        deleted
+    }
+
+    /// Entries with an ID in `start..=end`, in reverse order when `rev` is set
+    pub fn range(&self, start: StreamID, end: StreamID, count: Option<usize>, rev: bool) -> Vec<(StreamID, StreamFields)> {
+        if start > end {
+            return vec![];
+        }
+        node_range(&self.nodes, start, end, rev).take(count.unwrap_or(usize::MAX)).collect()
+    }
+
+    /// Entries with an ID strictly greater than `id`, used by XREAD
+    pub fn entries_after(&self, id: StreamID, count: Option<usize>) -> Vec<(StreamID, StreamFields)> {
+        match id.incr() {
+            Some(start) => self.range(start, StreamID::MAX, count, false),
+            None => vec![],
+        }
+    }
+
+    /// Whether an entry with an ID of at least `start` was deleted by XDEL, in which
+    /// case read counters can no longer be derived by counting entries.
+    fn range_has_tombstones(&self, start: StreamID) -> bool {
+        self.length != 0 && !self.max_deleted_entry_id.is_zero() && self.max_deleted_entry_id >= start
+    }
+
+    /// The logical read counter of `id`, that is how many entries were added up to it,
+    /// or `SCG_INVALID_ENTRIES_READ` if XDEL made it impossible to tell.
+    pub fn estimate_distance_from_first_ever_entry(&self, id: StreamID) -> i64 {
+        let entries_added = self.entries_added as i64;
+        if entries_added == 0 {
+            return 0;
+        }
+        if self.length == 0 && id <= self.last_id {
+            return entries_added;
+        }
+        match id.cmp(&self.last_id) {
+            std::cmp::Ordering::Equal => return entries_added,
+            std::cmp::Ordering::Greater => return SCG_INVALID_ENTRIES_READ,
+            std::cmp::Ordering::Less => {}
+        }
+        let first_id = self.first_id();
+        if self.max_deleted_entry_id.is_zero() || self.max_deleted_entry_id < first_id {
+            let length = self.length as i64;
+            match id.cmp(&first_id) {
+                std::cmp::Ordering::Less => return entries_added - length,
+                std::cmp::Ordering::Equal => return entries_added - length + 1,
+                std::cmp::Ordering::Greater => {}
+            }
+        }
+        SCG_INVALID_ENTRIES_READ
+    }
+
+    /// Number of entries the group still has to read, `None` when it can't be computed
+    pub fn group_lag(&self, group: &StreamCG) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = if group.entries_read != SCG_INVALID_ENTRIES_READ && !self.range_has_tombstones(group.last_id) {
+            group.entries_read
+        } else {
+            self.estimate_distance_from_first_ever_entry(group.last_id)
+        };
+        if entries_read == SCG_INVALID_ENTRIES_READ {
+            None
+        } else {
+            Some((self.entries_added as i64 - entries_read).max(0) as u64)
+        }
+    }
+
+    /// XGROUP CREATE. Returns false if the group already exists.
+    pub fn create_group(&mut self, name: &str, last_id: StreamID, entries_read: Option<i64>) -> bool {
+        if self.cgroups.contains_key(name) {
+            return false;
+        }
+        let entries_read = entries_read.unwrap_or_else(|| self.estimate_distance_from_first_ever_entry(last_id));
+        self.cgroups.insert(
+            name.to_string(),
+            StreamCG {
+                last_id,
+                entries_read,
+                pel: BTreeMap::new(),
+                consumers: BTreeMap::new(),
+            },
+        );
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.cgroups.remove(name).is_some()
+    }
+
+    /// XGROUP SETID
+    pub fn set_group_id(&mut self, name: &str, last_id: StreamID, entries_read: Option<i64>) -> bool {
+        let entries_read = entries_read.unwrap_or(SCG_INVALID_ENTRIES_READ);
+        match self.cgroups.get_mut(name) {
+            Some(group) => {
+                group.last_id = last_id;
+                group.entries_read = entries_read;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// XGROUP CREATECONSUMER. Returns `None` when the group does not exist and false
+    /// if the consumer already exists.
+    pub fn create_consumer(&mut self, group: &str, consumer: &str, now: i64) -> Option<bool> {
+        let group = self.cgroups.get_mut(group)?;
+        if group.consumers.contains_key(consumer) {
+            return Some(false);
+        }
+        group.consumers.insert(consumer.to_string(), StreamConsumer::new(consumer, now));
+        Some(true)
+    }
+
+    /// XGROUP DELCONSUMER. Returns the number of pending messages the consumer had,
+    /// which are dropped from the group PEL as well.
+    pub fn delete_consumer(&mut self, group: &str, consumer: &str) -> Option<usize> {
+        let group = self.cgroups.get_mut(group)?;
+        let Some(consumer) = group.consumers.remove(consumer) else { return Some(0) };
+        for id in &consumer.pel {
+            group.pel.remove(id);
+        }
+        Some(consumer.pel.len())
+    }
+
+    fn consumer_mut<'a>(group: &'a mut StreamCG, name: &str, now: i64) -> &'a mut StreamConsumer {
+        let consumer = group
+            .consumers
+            .entry(name.to_string())
+            .or_insert_with(|| StreamConsumer::new(name, now));
+        consumer.seen_time = now;
+        consumer
+    }
+
+    /// XREADGROUP with `>`: delivers entries never delivered to the group, adding them
+    /// to the PEL of `consumer` unless `noack` is set. Returns `None` when the group
+    /// does not exist.
+    pub fn read_group_new(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+        noack: bool,
+        now: i64,
+    ) -> Option<Vec<(StreamID, StreamFields)>> {
+        let last_id = self.cgroups.get(group)?.last_id;
+        let entries = self.entries_after(last_id, count);
+        let mut entries_read = self.cgroups[group].entries_read;
+        for (id, _) in &entries {
+            entries_read = if entries_read != SCG_INVALID_ENTRIES_READ && !self.range_has_tombstones(*id) {
+                entries_read + 1
+            } else {
+                self.estimate_distance_from_first_ever_entry(*id)
+            };
+        }
+
+        let cg = self.cgroups.get_mut(group)?;
+        Self::consumer_mut(cg, consumer, now);
+        if let Some((id, _)) = entries.last() {
+            cg.last_id = *id;
+            cg.entries_read = entries_read;
+        }
+        if entries.is_empty() {
+            return Some(entries);
+        }
+        if !noack {
+            for (id, _) in &entries {
+                // the entry may still be pending for another consumer after XGROUP SETID
+                if let Some(old) = cg.pel.get(id) {
+                    if let Some(owner) = cg.consumers.get_mut(&old.consumer) {
+                        owner.pel.remove(id);
+                    }
+                }
+                cg.pel.insert(
+                    *id,
+                    StreamNACK {
+                        delivery_time: now,
+                        delivery_count: 1,
+                        consumer: consumer.to_string(),
+                    },
+                );
+            }
+        }
+        let c = Self::consumer_mut(cg, consumer, now);
+        c.active_time = now;
+        if !noack {
+            c.pel.extend(entries.iter().map(|(id, _)| *id));
+        }
+        Some(entries)
+    }
+
+    /// XREADGROUP with an explicit ID: re-delivers the pending entries of `consumer`
+    /// with an ID greater than `start`. Returns `None` when the group does not exist.
+    pub fn read_group_history(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        start: StreamID,
+        count: Option<usize>,
+        now: i64,
+    ) -> Option<Vec<DeliveredEntry>> {
+        let cg = self.cgroups.get_mut(group)?;
+        let c = Self::consumer_mut(cg, consumer, now);
+        let ids: Vec<StreamID> = c
+            .pel
+            .range((Bound::Excluded(start), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .copied()
+            .collect();
+        for id in &ids {
+            if let Some(nack) = cg.pel.get_mut(id) {
+                nack.delivery_time = now;
+                nack.delivery_count += 1;
+            }
+        }
+        Some(ids.into_iter().map(|id| (id, node_lookup(&self.nodes, &id))).collect())
+    }
+
+    /// XACK. Returns the number of entries removed from the PEL.
+    pub fn ack(&mut self, group: &str, ids: &[StreamID]) -> Option<usize> {
+        let cg = self.cgroups.get_mut(group)?;
+        let mut acked = 0;
+        for id in ids {
+            if let Some(nack) = cg.pel.remove(id) {
+                if let Some(consumer) = cg.consumers.get_mut(&nack.consumer) {
+                    consumer.pel.remove(id);
+                }
+                acked += 1;
+            }
+        }
+        Some(acked)
+    }
+
+    /// Drops a pending entry whose stream entry no longer exists
+    fn drop_pending(cg: &mut StreamCG, id: &StreamID) {
+        if let Some(nack) = cg.pel.remove(id) {
+            if let Some(consumer) = cg.consumers.get_mut(&nack.consumer) {
+                consumer.pel.remove(id);
+            }
+        }
+    }
+
+    /// Transfers the pending entry `id` to `consumer`
+    fn transfer_pending(cg: &mut StreamCG, id: StreamID, consumer: &str, now: i64) {
+        if let Some(nack) = cg.pel.get_mut(&id) {
+            if nack.consumer != consumer {
+                let old = std::mem::replace(&mut nack.consumer, consumer.to_string());
+                if let Some(old) = cg.consumers.get_mut(&old) {
+                    old.pel.remove(&id);
+                }
+            }
+        }
+        let c = Self::consumer_mut(cg, consumer, now);
+        c.pel.insert(id);
+        c.active_time = now;
+    }
+
+    /// XCLAIM. Changes the ownership of the pending entries idle for at least
+    /// `min_idle` ms. Entries deleted from the stream are dropped from the PEL and
+    /// not returned. Returns `None` when the group does not exist.
+    pub fn claim(&mut self, group: &str, consumer: &str, min_idle: i64, ids: &[StreamID], opts: &ClaimOptions, now: i64) -> Option<Vec<DeliveredEntry>> {
+        let cg = self.cgroups.get_mut(group)?;
+        if let Some(last_id) = opts.last_id {
+            if last_id > cg.last_id {
+                cg.last_id = last_id;
+            }
+        }
+        let delivery_time = match (opts.idle, opts.time) {
+            (Some(idle), _) => now - idle,
+            (None, Some(time)) => time,
+            (None, None) => now,
+        };
+        Self::consumer_mut(cg, consumer, now);
+        let mut claimed = vec![];
+        for id in ids {
+            let fields = node_lookup(&self.nodes, id);
+            let exists = fields.is_some();
+            if !cg.pel.contains_key(id) {
+                if !(opts.force && exists) {
+                    continue;
+                }
+                cg.pel.insert(
+                    *id,
+                    StreamNACK {
+                        delivery_time: now,
+                        delivery_count: 0,
+                        consumer: consumer.to_string(),
+                    },
+                );
+            }
+            if !exists {
+                Self::drop_pending(cg, id);
+                continue;
+            }
+            let nack = &cg.pel[id];
+            if min_idle > 0 && now - nack.delivery_time < min_idle {
+                continue;
+            }
+            Self::transfer_pending(cg, *id, consumer, now);
+            let nack = cg.pel.get_mut(id).unwrap();
+            nack.delivery_time = delivery_time;
+            match opts.retry_count {
+                Some(count) => nack.delivery_count = count,
+                None if !opts.just_id => nack.delivery_count += 1,
+                None => {}
+            }
+            let fields = if opts.just_id { None } else { fields };
+            claimed.push((*id, fields));
+        }
+        Some(claimed)
+    }
+
+    /// XAUTOCLAIM. Scans the group PEL from `start` and claims up to `count` entries
+    /// idle for at least `min_idle` ms, examining at most `count * 10` entries.
+    /// Returns the ID to resume the scan from (0-0 when done), the claimed entries and
+    /// the IDs of pending entries that no longer exist in the stream.
+    #[allow(clippy::too_many_arguments)]
+    pub fn auto_claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle: i64,
+        start: StreamID,
+        count: usize,
+        just_id: bool,
+        now: i64,
+    ) -> Option<(StreamID, Vec<DeliveredEntry>, Vec<StreamID>)> {
+        let cg = self.cgroups.get_mut(group)?;
+        Self::consumer_mut(cg, consumer, now);
+        let mut attempts = count.saturating_mul(10);
+        let mut claimed = vec![];
+        let mut deleted = vec![];
+        let mut next = StreamID::MIN;
+        let candidates: Vec<StreamID> = cg.pel.range(start..).map(|(id, _)| *id).collect();
+        let mut iter = candidates.into_iter();
+        for id in iter.by_ref() {
+            if attempts == 0 || claimed.len() >= count {
+                next = id;
+                break;
+            }
+            attempts -= 1;
+            let Some(fields) = node_lookup(&self.nodes, &id) else {
+                Self::drop_pending(cg, &id);
+                deleted.push(id);
+                continue;
+            };
+            if min_idle > 0 && now - cg.pel[&id].delivery_time < min_idle {
+                continue;
+            }
+            Self::transfer_pending(cg, id, consumer, now);
+            let nack = cg.pel.get_mut(&id).unwrap();
+            nack.delivery_time = now;
+            if !just_id {
+                nack.delivery_count += 1;
+            }
+            let fields = if just_id { None } else { Some(fields) };
+            claimed.push((id, fields));
+        }
+        Some((next, claimed, deleted))
+    }
+}
+
+/// An entry of a listpack node
+struct NodeEntry {
+    id: StreamID,
+    flags: i64,
+    /// Position of the flags element in the listpack
+    flags_pos: usize,
+    fields: StreamFields,
+}
+
+impl NodeEntry {
+    fn is_deleted(&self) -> bool {
+        self.flags & STREAM_ITEM_FLAG_DELETED != 0
+    }
+
+    /// Flags the entry as deleted. The flags keep their one byte encoding, so the
+    /// positions of the other entries do not move.
+    fn mark_deleted(&self, lp: &mut ListPack) {
+        lp.replace(self.flags_pos, (self.flags | STREAM_ITEM_FLAG_DELETED).to_string().as_bytes());
+    }
+}
+
+fn lp_integer(entry: LpEntry) -> i64 {
+    match entry {
+        LpEntry::Int(v) => v,
+        LpEntry::Str(s) => lp_string_to_int64(&s).unwrap_or(0),
+    }
+}
+
+/// A node whose master entry has the fields of `fields` and no entry yet
+fn new_node(fields: &StreamFields) -> ListPack {
+    let mut lp = ListPack::new();
+    lp.append_integer(0);
+    lp.append_integer(0);
+    lp.append_integer(fields.len() as i64);
+    for (field, _) in fields {
+        lp.append(field.as_bytes());
+    }
+    lp.append_integer(0);
+    lp
+}
+
+/// The count of valid and deleted entries of a node, from its master entry
+fn node_counts(lp: &ListPack) -> (i64, i64) {
+    let mut iter = lp.iter();
+    let count = iter.next().map_or(0, lp_integer);
+    (count, iter.next().map_or(0, lp_integer))
+}
+
+fn set_node_counts(lp: &mut ListPack, count: i64, deleted: i64) {
+    let Some(count_pos) = lp.first() else { return };
+    // rewriting the count may move the deleted field, so it goes first
+    if let Some(deleted_pos) = lp.next(count_pos) {
+        lp.replace(deleted_pos, deleted.to_string().as_bytes());
+    }
+    lp.replace(count_pos, count.to_string().as_bytes());
+}
+
+fn node_master_fields(lp: &ListPack) -> Vec<LpEntry> {
+    let mut iter = lp.iter().skip(2);
+    let num = iter.next().map_or(0, lp_integer);
+    iter.take(num.max(0) as usize).collect()
+}
+
+/// Every entry of a node, deleted ones included
+fn node_entries(master: &StreamID, lp: &ListPack) -> Vec<NodeEntry> {
+    let mut entries = vec![];
+    let _ = decode_node(master, lp, &mut entries);
+    entries
+}
+
+/// Decodes the entries of a node into `entries`, returning `None` at the first
+/// malformed one, which only a node loaded from a corrupt RDB can hold
+fn decode_node(master: &StreamID, lp: &ListPack, entries: &mut Vec<NodeEntry>) -> Option<()> {
+    let mut pos = lp.first();
+    let mut next = || {
+        let p = pos?;
+        pos = lp.next(p);
+        Some((p, lp.get(p)))
+    };
+    // count and deleted
+    next()?;
+    next()?;
+    let num = lp_integer(next()?.1);
+    let mut master_fields = vec![];
+    for _ in 0..num {
+        master_fields.push(next()?.1.to_string());
+    }
+    // the master entry terminator
+    next()?;
+    while let Some((flags_pos, flags)) = next() {
+        let flags = lp_integer(flags);
+        let ms = master.ms.wrapping_add(lp_integer(next()?.1) as u64);
+        let seq = master.seq.wrapping_add(lp_integer(next()?.1) as u64);
+        let fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields.iter().map(|field| Some((field.clone(), next()?.1.to_string()))).collect::<Option<_>>()?
+        } else {
+            let num = lp_integer(next()?.1);
+            (0..num).map(|_| Some((next()?.1.to_string(), next()?.1.to_string()))).collect::<Option<_>>()?
+        };
+        // lp-count
+        next()?;
+        entries.push(NodeEntry { id: StreamID::new(ms, seq), flags, flags_pos, fields });
+    }
+    Some(())
+}
+
+/// The entries with an ID in `start..=end` (`start` not greater than `end`)
+fn node_range(
+    nodes: &BTreeMap<StreamID, ListPack>,
+    start: StreamID,
+    end: StreamID,
+    rev: bool,
+) -> Box<dyn Iterator<Item = (StreamID, StreamFields)> + '_> {
+    // the node holding `start` is the last one with a master ID not greater than it
+    let from = nodes.range(..=start).next_back().map_or(start, |(master, _)| *master);
+    let nodes = nodes.range(from..=end);
+    let live = move |(master, lp): (&StreamID, &ListPack)| {
+        node_entries(master, lp)
+            .into_iter()
+            .filter(move |entry| !entry.is_deleted() && entry.id >= start && entry.id <= end)
+            .map(|entry| (entry.id, entry.fields))
+    };
+    if rev {
+        Box::new(nodes.rev().flat_map(move |node| live(node).rev()))
+    } else {
+        Box::new(nodes.flat_map(live))
+    }
+}
+
+fn node_lookup(nodes: &BTreeMap<StreamID, ListPack>, id: &StreamID) -> Option<StreamFields> {
+    node_range(nodes, *id, *id, false).next().map(|(_, fields)| fields)
+}
+
+/// Options of XCLAIM
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClaimOptions {
+    /// IDLE: set the idle time of the claimed entries, in ms
+    pub idle: Option<i64>,
+    /// TIME: set the delivery time of the claimed entries, unix time in ms
+    pub time: Option<i64>,
+    /// RETRYCOUNT: set the delivery count of the claimed entries
+    pub retry_count: Option<u64>,
+    /// FORCE: create pending entries for existing entries that were never delivered
+    pub force: bool,
+    /// JUSTID: return only the IDs and don't increment the delivery count
+    pub just_id: bool,
+    /// LASTID: move the group last delivered ID forward
+    pub last_id: Option<StreamID>,
+}
@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod stream_test {
+    use crate::db::data_structure::stream::{
+        ClaimOptions, Stream, StreamID, StreamIdSpec, StreamTrim, TrimStrategy, SCG_INVALID_ENTRIES_READ,
+    };
+
+    fn fields(v: &str) -> Vec<(String, String)> {
+        vec![("f".to_string(), v.to_string())]
+    }
+
+    fn add(s: &mut Stream, spec: StreamIdSpec, now: u64) -> StreamID {
+        let id = s.next_id(spec, now).unwrap();
+        s.append(id, fields(&id.to_string()));
+        id
+    }
+
+    #[test]
+    fn stream_ids() {
+        let mut s = Stream::new();
+        assert_eq!(add(&mut s, StreamIdSpec::Auto, 100), StreamID::new(100, 0));
+        assert_eq!(add(&mut s, StreamIdSpec::Auto, 100), StreamID::new(100, 1));
+        // the clock went backward
+        assert_eq!(add(&mut s, StreamIdSpec::Auto, 90), StreamID::new(100, 2));
+        assert_eq!(add(&mut s, StreamIdSpec::AutoSeq(100), 0), StreamID::new(100, 3));
+        assert_eq!(add(&mut s, StreamIdSpec::AutoSeq(200), 0), StreamID::new(200, 0));
+        assert!(s.next_id(StreamIdSpec::AutoSeq(150), 0).is_err());
+        assert!(s.next_id(StreamIdSpec::Explicit(StreamID::new(200, 0)), 0).is_err());
+        assert!(Stream::new().next_id(StreamIdSpec::Explicit(StreamID::MIN), 0).is_err());
+        assert_eq!(Stream::new().next_id(StreamIdSpec::AutoSeq(0), 0).unwrap(), StreamID::new(0, 1));
+
+        assert_eq!(StreamID::parse("5", 0), Some(StreamID::new(5, 0)));
+        assert_eq!(StreamID::parse("5", u64::MAX), Some(StreamID::new(5, u64::MAX)));
+        assert_eq!(StreamID::parse("5-3", 0), Some(StreamID::new(5, 3)));
+        assert_eq!(StreamID::parse("5-x", 0), None);
+        assert_eq!(StreamID::new(5, u64::MAX).incr(), Some(StreamID::new(6, 0)));
+        assert_eq!(StreamID::new(6, 0).decr(), Some(StreamID::new(5, u64::MAX)));
+        assert_eq!(StreamID::MAX.incr(), None);
+    }
+
+    #[test]
+    fn stream_range_delete_trim() {
+        let mut s = Stream::new();
+        for i in 1..=10 {
+            add(&mut s, StreamIdSpec::Explicit(StreamID::new(i, 0)), 0);
+        }
+        let range = s.range(StreamID::new(3, 0), StreamID::new(6, 0), None, false);
+        assert_eq!(range.iter().map(|(id, _)| id.ms).collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+        let range = s.range(StreamID::MIN, StreamID::MAX, Some(2), true);
+        assert_eq!(range.iter().map(|(id, _)| id.ms).collect::<Vec<_>>(), vec![10, 9]);
+        assert_eq!(s.entries_after(StreamID::new(8, 0), None).len(), 2);
+
+        assert!(s.delete(&StreamID::new(5, 0)));
+        assert!(!s.delete(&StreamID::new(5, 0)));
+        assert_eq!(s.max_deleted_entry_id, StreamID::new(5, 0));
+        assert_eq!(s.len(), 9);
+
+        let trim = StreamTrim { strategy: TrimStrategy::MaxLen(5), approx: false, limit: 0 };
+        assert_eq!(s.trim(&trim), 4);
+        assert_eq!(s.first_id(), StreamID::new(6, 0));
+        // every entry is in one node, `~` only evicts whole nodes
+        let trim = StreamTrim { strategy: TrimStrategy::MinId(StreamID::new(9, 0)), approx: true, limit: 0 };
+        assert_eq!(s.trim(&trim), 0);
+        let trim = StreamTrim { strategy: TrimStrategy::MinId(StreamID::new(9, 0)), approx: false, limit: 2 };
+        assert_eq!(s.trim(&trim), 2);
+        assert_eq!(s.first_id(), StreamID::new(8, 0));
+        assert_eq!(s.entries_added, 10);
+        assert_eq!(s.last_id, StreamID::new(10, 0));
+    }
+
+    #[test]
+    fn stream_consumer_groups() {
+        let mut s = Stream::new();
+        for i in 1..=5 {
+            add(&mut s, StreamIdSpec::Explicit(StreamID::new(i, 0)), 0);
+        }
+        assert!(s.create_group("g", StreamID::MIN, None));
+        assert!(!s.create_group("g", StreamID::MIN, None));
+        assert_eq!(s.group_lag(&s.cgroups["g"]), Some(5));
+
+        let read = s.read_group_new("g", "alice", Some(2), false, 1000).unwrap();
+        assert_eq!(read.len(), 2);
+        let read = s.read_group_new("g", "bob", None, false, 1000).unwrap();
+        assert_eq!(read.len(), 3);
+        assert!(s.read_group_new("g", "bob", None, false, 1000).unwrap().is_empty());
+        assert!(s.read_group_new("nogroup", "bob", None, false, 1000).is_none());
+        let group = &s.cgroups["g"];
+        assert_eq!(group.last_id, StreamID::new(5, 0));
+        assert_eq!(group.entries_read, 5);
+        assert_eq!(group.pel.len(), 5);
+        assert_eq!(s.group_lag(group), Some(0));
+
+        assert_eq!(s.ack("g", &[StreamID::new(1, 0), StreamID::new(1, 0), StreamID::new(9, 0)]), Some(1));
+        let history = s.read_group_history("g", "alice", StreamID::MIN, None, 2000).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(s.cgroups["g"].pel[&StreamID::new(2, 0)].delivery_count, 2);
+
+        // bob's entries are idle for 1000ms at time 2000
+        let claimed = s
+            .claim("g", "alice", 500, &[StreamID::new(3, 0), StreamID::new(2, 0)], &ClaimOptions::default(), 2000)
+            .unwrap();
+        assert_eq!(claimed.iter().map(|(id, _)| id.ms).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(s.cgroups["g"].pel[&StreamID::new(3, 0)].consumer, "alice");
+        assert!(!s.cgroups["g"].consumers["bob"].pel.contains(&StreamID::new(3, 0)));
+
+        assert!(s.delete(&StreamID::new(5, 0)));
+        let (next, claimed, deleted) = s.auto_claim("g", "carol", 500, StreamID::MIN, 1, false, 3000).unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(next, StreamID::new(3, 0));
+        assert!(deleted.is_empty());
+        let (next, claimed, deleted) = s.auto_claim("g", "carol", 500, next, 10, true, 3000).unwrap();
+        assert_eq!(next, StreamID::MIN);
+        assert_eq!(claimed.len(), 2);
+        assert_eq!(deleted, vec![StreamID::new(5, 0)]);
+        assert_eq!(s.cgroups["g"].pel.len(), 3);
+        assert_eq!(s.cgroups["g"].consumers["carol"].pel.len(), 3);
+
+        assert_eq!(s.delete_consumer("g", "carol"), Some(3));
+        assert!(s.cgroups["g"].pel.is_empty());
+        assert!(s.set_group_id("g", StreamID::MIN, None));
+        assert_eq!(s.cgroups["g"].entries_read, SCG_INVALID_ENTRIES_READ);
+        assert!(s.destroy_group("g"));
+        assert!(s.cgroups.is_empty());
+    }
+
+    #[test]
+    fn stream_nodes() {
+        let mut s = Stream::new();
+        for i in 1..=250 {
+            let id = StreamID::new(i, 0);
+            // a field name other than the master entry one is stored with the entry
+            let fields = if i % 10 == 0 { vec![("g".to_string(), i.to_string())] } else { fields(&i.to_string()) };
+            s.append(id, fields);
+        }
+        assert_eq!(s.nodes().len(), 3);
+        assert_eq!(s.nodes().map(|(master, _)| master.ms).collect::<Vec<_>>(), vec![1, 101, 201]);
+        assert_eq!(s.get(&StreamID::new(120, 0)), Some(vec![("g".to_string(), "120".to_string())]));
+        assert_eq!(s.get(&StreamID::new(121, 0)), Some(fields("121")));
+        let range = s.range(StreamID::new(99, 0), StreamID::new(102, 0), None, true);
+        assert_eq!(range.iter().map(|(id, _)| id.ms).collect::<Vec<_>>(), vec![102, 101, 100, 99]);
+
+        // deleting every entry of a node frees it
+        for i in 201..=250 {
+            assert!(s.delete(&StreamID::new(i, 0)));
+        }
+        assert_eq!(s.nodes().len(), 2);
+        assert_eq!(s.last_entry().unwrap().0, StreamID::new(200, 0));
+        assert_eq!(s.len(), 200);
+
+        let trim = StreamTrim { strategy: TrimStrategy::MaxLen(150), approx: true, limit: 0 };
+        assert_eq!(s.trim(&trim), 0);
+        let trim = StreamTrim { strategy: TrimStrategy::MaxLen(90), approx: true, limit: 0 };
+        assert_eq!(s.trim(&trim), 100);
+        assert_eq!(s.first_id(), StreamID::new(101, 0));
+        let trim = StreamTrim { strategy: TrimStrategy::MaxLen(90), approx: false, limit: 0 };
+        assert_eq!(s.trim(&trim), 10);
+        assert_eq!(s.first_id(), StreamID::new(111, 0));
+        assert_eq!(s.len(), 90);
+        assert_eq!(s.range(StreamID::MIN, StreamID::MAX, None, false).len(), 90);
+
+        let mut copy = Stream::new();
+        for (master, lp) in s.nodes() {
+            assert!(copy.load_node(*master, lp.clone()));
+        }
+        assert_eq!(copy.len(), 90);
+        assert!(!copy.load_node(StreamID::new(1, 0), s.nodes().next().unwrap().1.clone()));
+    }
+}
@@ -1 +1,73 @@
+use crate::db::data_structure::dict::dict::{Dict, Value};
+use crate::db::data_structure::listpack::lib::LpEntry;
+use crate::db::data_structure::listpack::ListPack;
+use crate::db::data_structure::listpack::LP_BEFORE;
+use crate::db::data_structure::skiplist::skiplist::{SkipList, ZSet};
 
+/// Small sorted sets are stored in a listpack as member/score pairs, ordered by
+/// score and then by member, like the skiplist.
+
+/// Scores that are whole numbers take the integer encoding of the listpack.
+pub fn zzl_score_to_string(score: f64) -> String {
+    if score.fract() == 0.0 && score.abs() < (1u64 << 53) as f64 {
+        (score as i64).to_string()
+    } else {
+        format!("{:e}", score)
+    }
+}
+
+pub fn zzl_get_score(lp: &ListPack, p: usize) -> f64 {
+    match lp.get(p) {
+        LpEntry::Int(v) => v as f64,
+        LpEntry::Str(s) => std::str::from_utf8(&s).ok().and_then(|s| s.parse().ok()).unwrap_or(0f64),
+    }
+}
+
+pub fn zzl_len(lp: &ListPack) -> usize {
+    lp.len() / 2
+}
+
+/// Position of `ele` and its score.
+pub fn zzl_find(lp: &ListPack, ele: &str) -> Option<(usize, f64)> {
+    let pos = lp.find(lp.first()?, ele.as_bytes(), 1)?;
+    let score = zzl_get_score(lp, lp.next(pos)?);
+    Some((pos, score))
+}
+
+/// Inserts a member that is not in the listpack yet at its ordered position.
+pub fn zzl_insert(lp: &mut ListPack, ele: &str, score: f64) {
+    let score_str = zzl_score_to_string(score);
+    let mut p = lp.first();
+    while let Some(pos) = p {
+        let sp = lp.next(pos).expect("zset listpack without score");
+        let s = zzl_get_score(lp, sp);
+        if s > score || (s == score && lp.get(pos).to_string().as_str() > ele) {
+            let pos = lp.insert(ele.as_bytes(), pos, LP_BEFORE);
+            let next = lp.next(pos).unwrap();
+            lp.insert(score_str.as_bytes(), next, LP_BEFORE);
+            return;
+        }
+        p = lp.next(sp);
+    }
+    lp.append(ele.as_bytes());
+    lp.append(score_str.as_bytes());
+}
+
+/// Deletes the member at `p` together with its score.
+pub fn zzl_delete(lp: &mut ListPack, p: usize) {
+    lp.delete_range_with_entry(p, 2);
+}
+
+pub fn zzl_to_zset(lp: &ListPack) -> ZSet {
+    let mut zset = ZSet { dict: Dict::create(), zsl: SkipList::new() };
+    let mut p = lp.first();
+    while let Some(pos) = p {
+        let sp = lp.next(pos).expect("zset listpack without score");
+        let ele = lp.get(pos).to_string();
+        let score = zzl_get_score(lp, sp);
+        zset.zsl.insert(score, ele.clone());
+        let _ = zset.dict.add_raw(ele, Value::F(score));
+        p = lp.next(sp);
+    }
+    zset
+}
@@ -2,13 +2,34 @@ use crate::db::data_structure::dict::dict::{Dict, DictEntry, Value};
 use crate::db::kvstore::kvstore::KvStore;
 use crate::db::object::{RedisObject, RedisValue};
 
+use std::collections::VecDeque;
 use std::ptr::NonNull;
+use std::time::Duration;
 use tokio::select;
+use tokio::time::{Instant, MissedTickBehavior};
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 use tracing::{debug};
+use crate::db::blocked::{BlockRequest, BlockedClients};
 use crate::db::db_engine::{RDbCommand};
+use crate::db::evict::touch_object;
+use crate::db::expire::active_expire_enabled;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_KEY_MISS};
+use crate::server::latency::{latency_add_sample_if_needed, LATENCY_EVENT_EXPIRE_CYCLE};
+use crate::server::slowlog::{slowlog_push_entry_if_needed, CallContext};
+use crate::server::stats::{incr_keyspace_hits, incr_keyspace_misses};
 use crate::db::kvstore::iter::KvStoreIterator;
-use crate::parser::cmd::command::{CommandStrategy};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::stream::StreamCmd;
+use crate::parser::frame::Frame;
+use crate::persistence::rdb::rdb_dump_db;
+use crate::server::replication;
+use crate::cluster::cluster::{cluster_enabled, key_hash_slot};
+
+/// How many times per second each DB task runs its periodic jobs (active expiry)
+pub(crate) const SERVER_CRON_HZ: u64 = 10;
+/// Time budget of the incremental rehashing done by each cron run
+const INCREMENTAL_REHASHING_THRESHOLD_US: u64 = 1000;
 
 pub enum KeyStatus {
     KeyValid = 0,
@@ -16,9 +37,25 @@ pub enum KeyStatus {
     KeyDeleted,
 }
 
+/// Dict of the KvStores of a DB holding `key`: its hash slot in cluster mode, the only
+/// dict otherwise
 pub fn get_key_slot(key: &str) -> usize {
-    //key_hash_slot(key)
-    0
+    if cluster_enabled() {
+        key_hash_slot(key) as usize
+    } else {
+        0
+    }
+}
+
+/// Key counts of a DB, as reported by INFO keyspace
+#[derive(Debug, Clone, Default)]
+pub struct KeyspaceInfo {
+    pub keys: u64,
+    pub expires: u64,
+    /// Running average of the TTL of the keys with one, in milliseconds
+    pub avg_ttl: i64,
+    /// Dicts of the keyspace and expires being rehashed
+    pub rehashing_dicts: usize,
 }
 
 pub struct RedisDb {
@@ -27,12 +64,12 @@ pub struct RedisDb {
     /// Timeout of keys with a timeout set
     pub expires: KvStore,
     /// Keys with clients waiting for data (BLPOP)
-    pub blocking_keys: Dict,
+    pub blocking_keys: BlockedClients,
     /// Keys with clients waiting for data,
     /// and should be unblocked if key is deleted (XREADEDGROUP)
     pub blocking_keys_unblock_on_nokey: Dict,
     /// Blocked keys that received a PUSH
-    pub read_keys: Dict,
+    pub read_keys: VecDeque<String>,
     /// WATCHED keys for MULTI/EXEC CAS
     pub watched_keys: Dict,
     /// Database ID
@@ -54,9 +91,9 @@ impl RedisDb {
         Self {
             kvs: KvStore::create(slot_count_bits, flag),
             expires: KvStore::create(slot_count_bits, flag),
-            blocking_keys: Dict::create(),
+            blocking_keys: BlockedClients::default(),
             blocking_keys_unblock_on_nokey: Dict::create(),
-            read_keys: Dict::create(),
+            read_keys: VecDeque::new(),
             watched_keys: Dict::create(),
             id,
             avg_ttl: 0,
@@ -69,35 +106,148 @@ impl RedisDb {
     }
 
     pub async fn run(&mut self) {
+        let mut cron = tokio::time::interval(Duration::from_millis(1000 / SERVER_CRON_HZ));
+        cron.set_missed_tick_behavior(MissedTickBehavior::Skip);
         loop {
+            let block_deadline = self.blocking_keys.next_deadline();
             select! {
-                Some((sender, redis_cmd)) = self.receiver.recv() => {
-                    debug!("apply command {:?}", redis_cmd);
-                    let frame = redis_cmd.apply(self);
-                    let _ = sender.send(frame);
+                Some((sender, redis_cmd, call)) = self.receiver.recv() => {
+                    self.serve(sender, redis_cmd, call);
                 }
                 Some(db_cmd) = self.db_rx.recv() => {
                     match db_cmd {
-                        RDbCommand::DbIter(sender) => {
-                            let iter = self.db_iter();
-                            let _ = sender.send(iter);
+                        RDbCommand::Dump(sender) => {
+                            let _ = sender.send(rdb_dump_db(self));
+                        }
+                        RDbCommand::RdbData { key, value, expire } => {
+                            let de = self.add(key, value);
+                            if let (Some(de), Some(when)) = (de, expire) {
+                                let key = unsafe { de.as_ref().get_key().to_string() };
+                                self.set_expire(&key, when);
+                            }
+                        }
+                        RDbCommand::Block { client_id, cmd, sender } => {
+                            match BlockRequest::from_command(cmd, self) {
+                                Ok(Some(req)) => self.block_for_keys(client_id, req, sender),
+                                Ok(None) => {
+                                    let _ = sender.send(Err("ERR not a blocking command".into()));
+                                }
+                                Err(err) => {
+                                    let _ = sender.send(Err(err));
+                                }
+                            }
+                        }
+                        RDbCommand::Unblock { client_id, error, sender } => {
+                            let _ = sender.send(self.unblock_client(client_id, error));
+                        }
+                        RDbCommand::MemoryOverhead(sender) => {
+                            let _ = sender.send(self.memory_overhead());
+                        }
+                        RDbCommand::Keyspace(sender) => {
+                            let _ = sender.send(self.keyspace_info());
+                        }
+                        RDbCommand::ExistingKeys { keys, sender } => {
+                            let _ = sender.send(self.existing_keys(&keys));
+                        }
+                        RDbCommand::Empty(sender) => {
+                            self.empty();
+                            let _ = sender.send(());
+                        }
+                        RDbCommand::Snapshot { sender, resume } => {
+                            let _ = sender.send(rdb_dump_db(self));
+                            // writes wait until every DB is dumped and the replica registered
+                            let _ = resume.await;
                         }
-                        RDbCommand::RdbData { key, value } => {
-                             let _ = self.add(key, value);
+                        RDbCommand::Drain(sender) => {
+                            while let Ok((sender, redis_cmd, call)) = self.receiver.try_recv() {
+                                self.serve(sender, redis_cmd, call);
+                            }
+                            let _ = sender.send(());
                         }
                     }
                 }
+                _ = sleep_until_deadline(block_deadline), if block_deadline.is_some() => {
+                    self.handle_blocked_clients_timeout();
+                }
+                _ = cron.tick() => {
+                    if active_expire_enabled() {
+                        let start = Instant::now();
+                        self.active_expire_cycle();
+                        latency_add_sample_if_needed(LATENCY_EVENT_EXPIRE_CYCLE, start.elapsed());
+                    }
+                    self.kvs.increment_rehash(INCREMENTAL_REHASHING_THRESHOLD_US);
+                    self.expires.increment_rehash(INCREMENTAL_REHASHING_THRESHOLD_US);
+                    self.eviction_cron();
+                }
                 else => break,
             }
+            if !self.read_keys.is_empty() {
+                self.handle_clients_blocked_on_keys();
+            }
         }
     }
 
-    pub fn find(&self, key: &RedisObject) -> Option<&mut RedisObject> {
+    /// Runs a command sent by a client and answers it through `sender`.
+    fn serve(&mut self, sender: oneshot::Sender<crate::Result<Frame>>, redis_cmd: RedisCommand, call: CallContext) {
+        debug!("apply command {:?}", redis_cmd);
+        let is_write = redis_cmd.is_write();
+        let is_xadd = matches!(redis_cmd, RedisCommand::Stream(StreamCmd::XAdd { .. }));
+        let frame = if !self.perform_evictions() && redis_cmd.is_denyoom() {
+            Err("OOM command not allowed when used memory > 'maxmemory'.".into())
+        } else {
+            let start = Instant::now();
+            let frame = redis_cmd.apply(self);
+            slowlog_push_entry_if_needed(&call, start.elapsed());
+            frame
+        };
+        match &frame {
+            Ok(Frame::Bulk(id)) if is_xadd => replication::propagate(self.id, &replication::xadd_with_id(&call.argv, id)),
+            Ok(reply) if is_write && !matches!(reply, Frame::Error(_)) => {
+                let absolute = replication::with_absolute_ttl(&call.argv, |key| self.get_expire(key));
+                replication::propagate(self.id, absolute.as_ref().unwrap_or(&call.argv))
+            }
+            _ => {}
+        }
+        let _ = sender.send(frame);
+    }
+
+    /// Looks `key` up, deleting it first if its TTL elapsed, and records the access
+    /// for eviction
+    pub fn find(&mut self, key: &RedisObject) -> Option<&mut RedisObject> {
+        let o = self.find_no_touch(key)?;
+        touch_object(o);
+        Some(o)
+    }
+
+    /// `find` for commands that only read the key: counts the keyspace hit or miss,
+    /// notifying `keymiss` on a miss.
+    pub fn lookup_read(&mut self, key: &RedisObject) -> Option<&mut RedisObject> {
+        let id = self.id;
+        match self.find(key) {
+            Some(o) => {
+                incr_keyspace_hits();
+                Some(o)
+            }
+            None => {
+                incr_keyspace_misses();
+                if let RedisValue::String(k) = &key.ptr {
+                    notify_keyspace_event(NOTIFY_KEY_MISS, "keymiss", k, id);
+                }
+                None
+            }
+        }
+    }
+
+    /// Looks `key` up without counting it as an access (OBJECT IDLETIME, MEMORY USAGE)
+    pub fn find_no_touch(&mut self, key: &RedisObject) -> Option<&mut RedisObject> {
         let k = match &key.ptr {
             RedisValue::String(s) => s,
             _ => return None,
         };
-        let de = self.kvs.dict_find(0, k);
+        if self.expire_if_needed(k) {
+            return None;
+        }
+        let de = self.kvs.dict_find(get_key_slot(k) as i32, k);
         if let Some(mut de) = de {
             unsafe {
                 let val = de.as_mut().get_val();
@@ -127,9 +277,12 @@ impl RedisDb {
             _ => { "" }
         };
         let slot = get_key_slot(&key) as i32;
-        let de = self.kvs.dict_delete(slot, key);
-        if de.is_some() {
-            self.expires.dict_delete(slot, key);
+        // the unlinked entries are owned by the caller of dict_delete
+        if let Some(de) = self.kvs.dict_delete(slot, key) {
+            unsafe { drop(Box::from_raw(de.as_ptr())); }
+            if let Some(de) = self.expires.dict_delete(slot, key) {
+                unsafe { drop(Box::from_raw(de.as_ptr())); }
+            }
         }
     }
 
@@ -146,9 +299,59 @@ impl RedisDb {
         self.kvs.kvstore_size()
     }
 
+    pub fn keyspace_info(&self) -> KeyspaceInfo {
+        KeyspaceInfo {
+            keys: self.kvs.kvstore_size(),
+            expires: self.expires.kvstore_size(),
+            avg_ttl: self.avg_ttl,
+            rehashing_dicts: self.kvs.dict_rehashing_count() + self.expires.dict_rehashing_count(),
+        }
+    }
+
+    /// How many of `keys` the DB holds
+    pub fn existing_keys(&mut self, keys: &[String]) -> usize {
+        keys.iter().filter(|key| self.find_no_touch(&RedisObject::create_string_object(key.to_string())).is_some()).count()
+    }
+
+    /// Keys in hash slot `slot`, the size of its dict in cluster mode (CLUSTER COUNTKEYSINSLOT)
+    pub fn count_keys_in_slot(&self, slot: usize) -> u64 {
+        if cluster_enabled() {
+            self.kvs.dict_size(slot)
+        } else {
+            0
+        }
+    }
+
+    /// Up to `count` keys of hash slot `slot` (CLUSTER GETKEYSINSLOT)
+    pub fn keys_in_slot(&mut self, slot: usize, count: usize) -> Vec<String> {
+        if !cluster_enabled() || count == 0 || self.kvs.get_dict(slot).is_none() {
+            return vec![];
+        }
+        let mut keys = Vec::with_capacity(count.min(self.kvs.dict_size(slot) as usize));
+        let mut iter = self.kvs.get_dict_safe_iterator(slot);
+        for de in iter.by_ref().take(count) {
+            keys.push(unsafe { (*de).get_key().to_string() });
+        }
+        iter._release_dict_iterator();
+        keys
+    }
+
+    /// Removes every key of the DB
+    pub fn empty(&mut self) {
+        self.kvs.empty(None);
+        self.expires.empty(None);
+        self.avg_ttl = 0;
+    }
+
     pub fn db_iter(&mut self) -> KvStoreIterator {
         self.kvs.iter()
     }
 }
 
-
+/// Sleeps until `deadline`, or forever when there is none.
+async fn sleep_until_deadline(deadline: Option<std::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(Instant::from_std(deadline)).await,
+        None => std::future::pending().await,
+    }
+}
@@ -1,14 +1,39 @@
+use bytes::BytesMut;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
 
-use crate::db::db::RedisDb;
-use crate::db::kvstore::iter::KvStoreIterator;
+use crate::cluster::cluster::{cluster_enabled, CLUSTER_SLOT_BITS};
+use crate::db::db::{KeyspaceInfo, RedisDb};
 use crate::db::kvstore::KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
+use crate::db::memory::DbOverhead;
 use crate::db::object::RedisObject;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::frame::Frame;
 use crate::MpscSender;
 
 pub enum RDbCommand {
-    DbIter(std::sync::mpsc::Sender<KvStoreIterator>),
-    RdbData { key: RedisObject, value: RedisObject },
+    /// Dumps the keys of the DB, with their TTLs, as a section of an RDB (SAVE)
+    Dump(std::sync::mpsc::Sender<crate::Result<BytesMut>>),
+    /// A key loaded from an RDB, `expire` being its absolute expire time in milliseconds
+    RdbData { key: RedisObject, value: RedisObject, expire: Option<i64> },
+    /// A blocking command from client `client_id`, answered through `sender` once served
+    Block { client_id: u64, cmd: RedisCommand, sender: oneshot::Sender<crate::Result<Frame>> },
+    /// Unblocks client `client_id`, `sender` receives whether it was blocked in this DB
+    Unblock { client_id: u64, error: bool, sender: oneshot::Sender<bool> },
+    /// Reports the memory taken by the hash tables of the DB (MEMORY STATS)
+    MemoryOverhead(oneshot::Sender<DbOverhead>),
+    /// Reports the key counts of the DB (INFO keyspace)
+    Keyspace(oneshot::Sender<KeyspaceInfo>),
+    /// Reports how many of `keys` the DB holds, for keys of a slot being migrated
+    ExistingKeys { keys: Vec<String>, sender: oneshot::Sender<usize> },
+    /// Removes every key of the DB (DEBUG RELOAD)
+    Empty(oneshot::Sender<()>),
+    /// Dumps the keys of the DB as a section of an RDB, then serves nothing until
+    /// `resume` fires or is dropped (full resync)
+    Snapshot { sender: oneshot::Sender<crate::Result<BytesMut>>, resume: oneshot::Receiver<()> },
+    /// Serves the commands already queued, then answers. Used by SHUTDOWN before the
+    /// final save
+    Drain(oneshot::Sender<()>),
 }
 
 #[derive(Debug)]
@@ -19,7 +44,8 @@ pub struct DbHandler {
 
 impl DbHandler {
     pub fn new(db_num: u32) -> Self {
-        let slot_count_bits = 4;
+        // a dict per hash slot in cluster mode, CLUSTER COUNTKEYSINSLOT reads their sizes
+        let slot_count_bits = if cluster_enabled() { CLUSTER_SLOT_BITS } else { 0 };
         let flag = KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
         let mut db_list = vec![];
         let mut sender_list = vec![];
@@ -49,4 +75,69 @@ impl DbHandler {
     pub fn get_size(&self) -> usize {
         self.sender.len()
     }
+
+    /// Hash table overhead of every DB, by index
+    pub async fn memory_overhead(&self) -> crate::Result<Vec<DbOverhead>> {
+        let mut dbs = Vec::with_capacity(self.db_sender.len());
+        for db_sender in &self.db_sender {
+            let (sender, receiver) = oneshot::channel();
+            db_sender.send(RDbCommand::MemoryOverhead(sender)).await?;
+            dbs.push(receiver.await?);
+        }
+        Ok(dbs)
+    }
+
+    /// Waits until every DB served the commands sent to it so far.
+    pub async fn drain(&self) -> crate::Result<()> {
+        for db_sender in &self.db_sender {
+            let (sender, receiver) = oneshot::channel();
+            db_sender.send(RDbCommand::Drain(sender)).await?;
+            receiver.await?;
+        }
+        Ok(())
+    }
+
+    /// Removes every key of every DB, before an RDB is loaded.
+    pub async fn empty(&self) -> crate::Result<()> {
+        for db_sender in &self.db_sender {
+            let (sender, receiver) = oneshot::channel();
+            db_sender.send(RDbCommand::Empty(sender)).await?;
+            receiver.await?;
+        }
+        Ok(())
+    }
+
+    /// Dumps every DB at one point of the command stream: each DB stays frozen after
+    /// its dump until the returned senders are dropped.
+    pub async fn snapshot(&self) -> crate::Result<(Vec<BytesMut>, Vec<oneshot::Sender<()>>)> {
+        let mut sections = Vec::with_capacity(self.db_sender.len());
+        let mut resumes = Vec::with_capacity(self.db_sender.len());
+        for db_sender in &self.db_sender {
+            let (sender, receiver) = oneshot::channel();
+            let (resume, resume_rx) = oneshot::channel();
+            db_sender.send(RDbCommand::Snapshot { sender, resume: resume_rx }).await?;
+            resumes.push(resume);
+            sections.push(receiver.await??);
+        }
+        Ok((sections, resumes))
+    }
+
+    /// How many of `keys` DB `index` holds
+    pub async fn existing_keys(&self, index: usize, keys: Vec<String>) -> crate::Result<usize> {
+        let db_sender = self.db_sender.get(index).ok_or("ERR invalid DB index")?;
+        let (sender, receiver) = oneshot::channel();
+        db_sender.send(RDbCommand::ExistingKeys { keys, sender }).await?;
+        Ok(receiver.await?)
+    }
+
+    /// Key counts of every DB, by index
+    pub async fn keyspace(&self) -> crate::Result<Vec<KeyspaceInfo>> {
+        let mut dbs = Vec::with_capacity(self.db_sender.len());
+        for db_sender in &self.db_sender {
+            let (sender, receiver) = oneshot::channel();
+            db_sender.send(RDbCommand::Keyspace(sender)).await?;
+            dbs.push(receiver.await?);
+        }
+        Ok(dbs)
+    }
 }
\ No newline at end of file
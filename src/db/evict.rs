@@ -0,0 +1,468 @@
+use std::sync::atomic::{AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use rand::Rng;
+
+use crate::config::ServerConfig;
+use crate::db::data_structure::dict::dict::Value;
+use crate::db::db::{get_key_slot, RedisDb};
+use crate::db::expire::mstime;
+use crate::db::kvstore::kvstore::KvStore;
+use crate::db::object::{RedisObject, LRU_CLOCK_MAX};
+use crate::server::notify::{notify_keyspace_event, NOTIFY_EVICTED};
+use crate::server::replication;
+use crate::server::zmalloc::used_memory;
+
+/// LRU clock resolution in ms
+pub const LRU_CLOCK_RESOLUTION: i64 = 1000;
+/// Access counter of new objects under an LFU policy, so they are not evicted
+/// before getting a chance to be accessed
+pub const LFU_INIT_VAL: u32 = 5;
+/// Keys kept as eviction candidates between evictions
+const EVPOOL_SIZE: usize = 16;
+
+/// What to do when `maxmemory` is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxMemoryPolicy {
+    /// Refuse commands that could use more memory
+    NoEviction = 0,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    /// Keys with a TTL, the ones closest to expiring first
+    VolatileTtl,
+}
+
+const POLICIES: [(MaxMemoryPolicy, &str); 8] = [
+    (MaxMemoryPolicy::NoEviction, "noeviction"),
+    (MaxMemoryPolicy::AllKeysLru, "allkeys-lru"),
+    (MaxMemoryPolicy::VolatileLru, "volatile-lru"),
+    (MaxMemoryPolicy::AllKeysLfu, "allkeys-lfu"),
+    (MaxMemoryPolicy::VolatileLfu, "volatile-lfu"),
+    (MaxMemoryPolicy::AllKeysRandom, "allkeys-random"),
+    (MaxMemoryPolicy::VolatileRandom, "volatile-random"),
+    (MaxMemoryPolicy::VolatileTtl, "volatile-ttl"),
+];
+
+impl MaxMemoryPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        POLICIES.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)).map(|(p, _)| *p)
+    }
+
+    pub fn name(&self) -> &'static str {
+        POLICIES[*self as usize].1
+    }
+
+    pub fn is_lfu(&self) -> bool {
+        matches!(self, MaxMemoryPolicy::AllKeysLfu | MaxMemoryPolicy::VolatileLfu)
+    }
+
+    /// Only keys with a TTL can be evicted.
+    pub fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            MaxMemoryPolicy::VolatileLru
+                | MaxMemoryPolicy::VolatileLfu
+                | MaxMemoryPolicy::VolatileRandom
+                | MaxMemoryPolicy::VolatileTtl
+        )
+    }
+
+    fn is_random(&self) -> bool {
+        matches!(self, MaxMemoryPolicy::AllKeysRandom | MaxMemoryPolicy::VolatileRandom)
+    }
+}
+
+/// Runtime eviction settings, set from the config at startup and by CONFIG SET
+static MAXMEMORY: AtomicU64 = AtomicU64::new(0);
+static MAXMEMORY_POLICY: AtomicU32 = AtomicU32::new(MaxMemoryPolicy::NoEviction as u32);
+static MAXMEMORY_SAMPLES: AtomicUsize = AtomicUsize::new(5);
+static LFU_LOG_FACTOR: AtomicI32 = AtomicI32::new(10);
+static LFU_DECAY_TIME: AtomicU64 = AtomicU64::new(1);
+/// Number of keys evicted because of `maxmemory`
+static STAT_EVICTEDKEYS: AtomicU64 = AtomicU64::new(0);
+
+/// Applies the eviction settings of the config file.
+pub fn load_eviction_config(config: &ServerConfig) -> crate::Result<()> {
+    set_maxmemory(config.maxmemory);
+    let policy = MaxMemoryPolicy::from_name(&config.maxmemory_policy)
+        .ok_or_else(|| format!("invalid maxmemory-policy '{}'", config.maxmemory_policy))?;
+    set_maxmemory_policy(policy);
+    set_maxmemory_samples(config.maxmemory_samples);
+    set_lfu_log_factor(config.lfu_log_factor);
+    set_lfu_decay_time(config.lfu_decay_time);
+    Ok(())
+}
+
+/// The memory limit in bytes, 0 for none
+pub fn maxmemory() -> u64 {
+    MAXMEMORY.load(Ordering::Relaxed)
+}
+
+pub fn set_maxmemory(bytes: u64) {
+    MAXMEMORY.store(bytes, Ordering::Relaxed);
+}
+
+pub fn maxmemory_policy() -> MaxMemoryPolicy {
+    POLICIES[MAXMEMORY_POLICY.load(Ordering::Relaxed) as usize].0
+}
+
+pub fn set_maxmemory_policy(policy: MaxMemoryPolicy) {
+    MAXMEMORY_POLICY.store(policy as u32, Ordering::Relaxed);
+}
+
+pub fn maxmemory_samples() -> usize {
+    MAXMEMORY_SAMPLES.load(Ordering::Relaxed)
+}
+
+pub fn set_maxmemory_samples(samples: usize) {
+    MAXMEMORY_SAMPLES.store(samples.max(1), Ordering::Relaxed);
+}
+
+pub fn lfu_log_factor() -> i32 {
+    LFU_LOG_FACTOR.load(Ordering::Relaxed)
+}
+
+pub fn set_lfu_log_factor(factor: i32) {
+    LFU_LOG_FACTOR.store(factor.max(0), Ordering::Relaxed);
+}
+
+/// Minutes after which the LFU counter of a key not accessed is decremented
+pub fn lfu_decay_time() -> u64 {
+    LFU_DECAY_TIME.load(Ordering::Relaxed)
+}
+
+pub fn set_lfu_decay_time(minutes: u64) {
+    LFU_DECAY_TIME.store(minutes, Ordering::Relaxed);
+}
+
+pub fn evicted_keys() -> u64 {
+    STAT_EVICTEDKEYS.load(Ordering::Relaxed)
+}
+
+pub fn reset_evicted_keys() {
+    STAT_EVICTEDKEYS.store(0, Ordering::Relaxed);
+}
+
+/// The LRU clock: unix time in `LRU_CLOCK_RESOLUTION` units, wrapping at `LRU_CLOCK_MAX`.
+pub fn lru_clock() -> u32 {
+    ((mstime() / LRU_CLOCK_RESOLUTION) & LRU_CLOCK_MAX as i64) as u32
+}
+
+/// Milliseconds since the object was last accessed, with the LRU clock resolution.
+pub fn estimate_object_idle_time(o: &RedisObject) -> u64 {
+    let lruclock = lru_clock();
+    let ticks = if lruclock >= o.lru {
+        lruclock - o.lru
+    } else {
+        lruclock + (LRU_CLOCK_MAX - o.lru)
+    };
+    ticks as u64 * LRU_CLOCK_RESOLUTION as u64
+}
+
+/// Under an LFU policy the 24 `lru` bits hold the last decrement time in minutes
+/// (16 bits) and a logarithmic access counter (8 bits).
+pub fn lfu_get_time_in_minutes() -> u32 {
+    ((mstime() / 1000 / 60) & 65535) as u32
+}
+
+/// Minutes elapsed since `ldt`, handling the wrap of the 16 bits clock.
+fn lfu_time_elapsed(ldt: u32) -> u32 {
+    let now = lfu_get_time_in_minutes();
+    if now >= ldt {
+        now - ldt
+    } else {
+        65535 - ldt + now
+    }
+}
+
+/// Increments the counter with a probability that gets lower as it grows, so 8 bits
+/// are enough for millions of accesses.
+pub fn lfu_log_incr(counter: u8) -> u8 {
+    if counter == 255 {
+        return 255;
+    }
+    let r: f64 = rand::rng().random();
+    let baseval = (counter as f64 - LFU_INIT_VAL as f64).max(0.0);
+    let p = 1.0 / (baseval * lfu_log_factor() as f64 + 1.0);
+    if r < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// The counter of `o` decremented by one for every `lfu-decay-time` minutes since
+/// it was last decremented. The object is not updated.
+pub fn lfu_decr_and_return(o: &RedisObject) -> u8 {
+    let ldt = o.lru >> 8;
+    let counter = (o.lru & 255) as u8;
+    let decay_time = lfu_decay_time();
+    let num_periods = if decay_time > 0 { lfu_time_elapsed(ldt) as u64 / decay_time } else { 0 };
+    if num_periods > 0 {
+        return if num_periods > counter as u64 { 0 } else { counter - num_periods as u8 };
+    }
+    counter
+}
+
+/// The `lru` bits of a new object.
+pub fn object_initial_lru() -> u32 {
+    if maxmemory_policy().is_lfu() {
+        (lfu_get_time_in_minutes() << 8) | LFU_INIT_VAL
+    } else {
+        lru_clock()
+    }
+}
+
+/// Records an access to `o`: bumps its access counter under an LFU policy, or its
+/// access time otherwise.
+pub fn touch_object(o: &mut RedisObject) {
+    if maxmemory_policy().is_lfu() {
+        let counter = lfu_log_incr(lfu_decr_and_return(o));
+        o.lru = (lfu_get_time_in_minutes() << 8) | counter as u32;
+    } else {
+        o.lru = lru_clock();
+    }
+}
+
+struct EvictionPoolEntry {
+    /// Higher is a better candidate: idle time, inverted frequency or inverted TTL
+    idle: u64,
+    /// DB the key belongs to
+    dbid: i32,
+    key: String,
+}
+
+/// The best eviction candidates seen so far, sorted by ascending idle score. Every
+/// eviction samples a few more keys into the pool and evicts the best one, which
+/// approximates evicting the least recently (or frequently) used key of all.
+struct EvictionPool {
+    entries: Vec<EvictionPoolEntry>,
+}
+
+impl EvictionPool {
+    const fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    fn insert(&mut self, dbid: i32, key: &str, idle: u64) {
+        if self.entries.iter().any(|e| e.dbid == dbid && e.key == key) {
+            return;
+        }
+        let entry = EvictionPoolEntry { idle, dbid, key: key.to_string() };
+        let pos = self.entries.partition_point(|e| e.idle < idle);
+        if self.entries.len() == EVPOOL_SIZE {
+            if pos == 0 {
+                // worse than every candidate of a full pool
+                return;
+            }
+            self.entries.remove(0);
+            self.entries.insert(pos - 1, entry);
+        } else {
+            self.entries.insert(pos, entry);
+        }
+    }
+
+    /// Samples `maxmemory-samples` keys of `sample_from` (the keyspace or the keys with
+    /// a TTL) of DB `dbid` into the pool.
+    fn populate(&mut self, dbid: i32, kvs: &KvStore, sample_from: &KvStore, policy: MaxMemoryPolicy) {
+        for _ in 0..maxmemory_samples() {
+            let didx = sample_from.get_fair_random_dict_index() as i32;
+            let Some(de) = sample_from.get_fair_random_key(didx) else { continue };
+            let (key, value) = unsafe { (de.as_ref().get_key(), de.as_ref().value()) };
+            let idle = if policy.is_random() {
+                rand::rng().random()
+            } else if policy == MaxMemoryPolicy::VolatileTtl {
+                match value {
+                    Value::S64(when) => u64::MAX - *when as u64,
+                    _ => continue,
+                }
+            } else {
+                let o = match kvs.dict_find(get_key_slot(key) as i32, key) {
+                    Some(de) => match unsafe { de.as_ref().value() } {
+                        Value::Val(o) => o,
+                        _ => continue,
+                    },
+                    None => continue,
+                };
+                if policy.is_lfu() {
+                    255 - lfu_decr_and_return(o) as u64
+                } else {
+                    estimate_object_idle_time(o)
+                }
+            };
+            self.insert(dbid, key, idle);
+        }
+    }
+
+    fn pop_best(&mut self) -> Option<(i32, String)> {
+        self.entries.pop().map(|e| (e.dbid, e.key))
+    }
+}
+
+/// Eviction state shared by the DB tasks. `used_memory()` is the memory of the whole
+/// process, so the candidates of every DB compete in one pool and a single DB evicts
+/// at a time.
+struct Evictor {
+    pool: EvictionPool,
+    /// Keys each DB can evict under the current policy, as last seen by its task
+    evictable: Vec<u64>,
+    /// Keys picked from the pool by another DB, evicted by the task owning them
+    handed_off: Vec<Vec<String>>,
+}
+
+impl Evictor {
+    const fn new() -> Self {
+        Self { pool: EvictionPool::new(), evictable: Vec::new(), handed_off: Vec::new() }
+    }
+
+    fn publish(&mut self, dbid: i32, evictable: u64) {
+        let dbid = dbid as usize;
+        if self.evictable.len() <= dbid {
+            self.evictable.resize(dbid + 1, 0);
+        }
+        self.evictable[dbid] = evictable;
+    }
+
+    fn hand_off(&mut self, dbid: i32, key: String) {
+        let dbid = dbid as usize;
+        if self.handed_off.len() <= dbid {
+            self.handed_off.resize_with(dbid + 1, Vec::new);
+        }
+        self.handed_off[dbid].push(key);
+    }
+
+    fn take_handed_off(&mut self, dbid: i32) -> Vec<String> {
+        self.handed_off.get_mut(dbid as usize).map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Whether a DB other than `dbid` has keys to evict.
+    fn others_evictable(&self, dbid: i32) -> bool {
+        self.evictable.iter().enumerate().any(|(id, n)| id != dbid as usize && *n > 0)
+    }
+}
+
+static EVICTOR: Mutex<Evictor> = Mutex::new(Evictor::new());
+
+impl RedisDb {
+    /// Evicts keys following `maxmemory-policy` until the used memory is back under
+    /// `maxmemory`. Returns false when that is not possible, either because the policy
+    /// is noeviction or no DB has anything left to evict.
+    ///
+    /// Each DB runs in its own task and only deletes its own keys. When the best
+    /// candidate belongs to another DB it is handed off to that DB, which evicts it on
+    /// its next cron run, and the eviction is considered in progress.
+    pub fn perform_evictions(&mut self) -> bool {
+        let limit = maxmemory();
+        if limit == 0 || used_memory() as u64 <= limit {
+            return true;
+        }
+        let policy = maxmemory_policy();
+        if policy == MaxMemoryPolicy::NoEviction {
+            return false;
+        }
+        let mut evictor = EVICTOR.lock().unwrap();
+        // the memory is checked again on every step, another DB may have evicted while
+        // this one was waiting for the lock
+        for key in evictor.take_handed_off(self.id) {
+            if used_memory() as u64 <= limit {
+                return true;
+            }
+            if self.kvs.dict_find(get_key_slot(&key) as i32, &key).is_some() {
+                self.evict(&key);
+            }
+        }
+        while used_memory() as u64 > limit {
+            let sample_from = if policy.is_volatile() { &self.expires } else { &self.kvs };
+            evictor.publish(self.id, sample_from.kvstore_size());
+            evictor.pool.populate(self.id, &self.kvs, sample_from, policy);
+            match evictor.pool.pop_best() {
+                Some((dbid, key)) if dbid == self.id => {
+                    // the key may be gone since it was sampled
+                    if sample_from.dict_find(get_key_slot(&key) as i32, &key).is_some() {
+                        self.evict(&key);
+                    }
+                }
+                Some((dbid, key)) => {
+                    evictor.hand_off(dbid, key);
+                    return true;
+                }
+                None => return evictor.others_evictable(self.id),
+            }
+        }
+        true
+    }
+
+    /// Run by the cron of the DB: tells the other DBs whether this one has keys to
+    /// evict and evicts the keys handed off to it.
+    pub fn eviction_cron(&mut self) {
+        let policy = maxmemory_policy();
+        if maxmemory() == 0 || policy == MaxMemoryPolicy::NoEviction {
+            return;
+        }
+        let sample_from = if policy.is_volatile() { &self.expires } else { &self.kvs };
+        EVICTOR.lock().unwrap().publish(self.id, sample_from.kvstore_size());
+        self.perform_evictions();
+    }
+
+    fn evict(&mut self, key: &str) {
+        self.delete(&RedisObject::create_string_object(key.to_string()));
+        STAT_EVICTEDKEYS.fetch_add(1, Ordering::Relaxed);
+        notify_keyspace_event(NOTIFY_EVICTED, "evicted", key, self.id);
+        replication::propagate_del(self.id, key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::evict::{lfu_log_incr, EvictionPool, Evictor, EVPOOL_SIZE, LFU_INIT_VAL};
+
+    #[test]
+    fn pool_keeps_best_candidates() {
+        let mut pool = EvictionPool::new();
+        for i in 0..40u64 {
+            pool.insert(0, &format!("k{}", i), (i * 7) % 40);
+        }
+        assert_eq!(pool.entries.len(), EVPOOL_SIZE);
+        assert!(pool.entries.windows(2).all(|w| w[0].idle <= w[1].idle));
+        assert_eq!(pool.entries[0].idle, 40 - EVPOOL_SIZE as u64);
+        assert_eq!(pool.pop_best().unwrap(), (0, "k17".to_string()));
+    }
+
+    #[test]
+    fn pool_spans_dbs() {
+        let mut evictor = Evictor::new();
+        evictor.pool.insert(0, "k", 10);
+        evictor.pool.insert(3, "k", 20);
+        evictor.pool.insert(3, "k", 20);
+        assert_eq!(evictor.pool.entries.len(), 2);
+
+        // the best candidate is in DB 3, so DB 0 hands it off
+        let (dbid, key) = evictor.pool.pop_best().unwrap();
+        assert_eq!(dbid, 3);
+        evictor.hand_off(dbid, key);
+        assert!(evictor.take_handed_off(0).is_empty());
+        assert_eq!(evictor.take_handed_off(3), vec!["k".to_string()]);
+        assert!(evictor.take_handed_off(3).is_empty());
+
+        // an empty DB can count on the others while they have keys to evict
+        assert!(!evictor.others_evictable(0));
+        evictor.publish(0, 0);
+        evictor.publish(2, 5);
+        assert!(evictor.others_evictable(0));
+        assert!(!evictor.others_evictable(2));
+    }
+
+    #[test]
+    fn lfu_counter_grows_logarithmically() {
+        let mut counter = LFU_INIT_VAL as u8;
+        for _ in 0..1000 {
+            counter = lfu_log_incr(counter);
+        }
+        // with the default log factor of 10, 1000 hits give a counter around 18
+        assert!(counter > 10 && counter < 40, "counter {}", counter);
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::db::data_structure::dict::dict::Value;
+use crate::db::db::{get_key_slot, RedisDb};
+use crate::db::object::RedisObject;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_EXPIRED};
+use crate::server::replication;
+
+/// Keys sampled per round of the active expire cycle
+const ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP: usize = 20;
+/// Keep sampling while more than this percentage of the sampled keys were expired
+const ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE: usize = 10;
+/// Time budget of one active expire cycle
+const ACTIVE_EXPIRE_CYCLE_TIME_LIMIT: Duration = Duration::from_millis(25);
+
+/// Whether the DB tasks run the active expire cycle, toggled by DEBUG SET-ACTIVE-EXPIRE
+static ACTIVE_EXPIRE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Number of keys deleted because their TTL elapsed
+static STAT_EXPIREDKEYS: AtomicU64 = AtomicU64::new(0);
+
+pub fn expired_keys() -> u64 {
+    STAT_EXPIREDKEYS.load(Ordering::Relaxed)
+}
+
+pub fn reset_expired_keys() {
+    STAT_EXPIREDKEYS.store(0, Ordering::Relaxed);
+}
+
+pub fn active_expire_enabled() -> bool {
+    ACTIVE_EXPIRE_ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_active_expire(enabled: bool) {
+    ACTIVE_EXPIRE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Unix time in milliseconds
+pub fn mstime() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+impl RedisDb {
+    /// Sets the absolute unix time in milliseconds at which `key` expires. The key
+    /// must exist.
+    pub fn set_expire(&mut self, key: &str, when: i64) {
+        let slot = get_key_slot(key) as i32;
+        let de = match self.expires.dict_find(slot, key) {
+            Some(de) => Some(de),
+            None => self.expires.dict_add_raw(slot, key.to_string()),
+        };
+        if let Some(mut de) = de {
+            unsafe { de.as_mut().val = Some(Value::S64(when)) }
+        }
+    }
+
+    /// The absolute unix time in milliseconds at which `key` expires, if it has a TTL
+    pub fn get_expire(&self, key: &str) -> Option<i64> {
+        let de = self.expires.dict_find(get_key_slot(key) as i32, key)?;
+        unsafe {
+            match de.as_ref().value() {
+                Value::S64(when) => Some(*when),
+                _ => None,
+            }
+        }
+    }
+
+    /// Removes the TTL of `key`, returns false if it had none
+    pub fn remove_expire(&mut self, key: &str) -> bool {
+        match self.expires.dict_delete(get_key_slot(key) as i32, key) {
+            Some(de) => {
+                unsafe { drop(Box::from_raw(de.as_ptr())); }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deletes `key` if its TTL elapsed, notifying `expired`. Called on every lookup so
+    /// commands never see a logically expired key.
+    pub fn expire_if_needed(&mut self, key: &str) -> bool {
+        match self.get_expire(key) {
+            Some(when) if when <= mstime() => {
+                self.delete_expired_key(key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn delete_expired_key(&mut self, key: &str) {
+        self.delete(&RedisObject::create_string_object(key.to_string()));
+        STAT_EXPIREDKEYS.fetch_add(1, Ordering::Relaxed);
+        notify_keyspace_event(NOTIFY_EXPIRED, "expired", key, self.id);
+        replication::propagate_del(self.id, key);
+    }
+
+    /// Samples keys with a TTL and deletes the expired ones, repeating while a large
+    /// share of the sample was expired and the time budget allows. Runs periodically
+    /// from the DB task so keys that are never looked up again still expire.
+    pub fn active_expire_cycle(&mut self) {
+        let start = Instant::now();
+        loop {
+            let size = self.expires.kvstore_size() as usize;
+            if size == 0 {
+                self.avg_ttl = 0;
+                return;
+            }
+            let num = size.min(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP);
+            let now = mstime();
+            let mut expired = Vec::new();
+            let (mut ttl_sum, mut ttl_samples) = (0i64, 0i64);
+            for _ in 0..num {
+                let didx = self.expires.get_fair_random_dict_index() as i32;
+                let Some(de) = self.expires.get_fair_random_key(didx) else { continue };
+                unsafe {
+                    if let Value::S64(when) = de.as_ref().value() {
+                        if *when <= now {
+                            expired.push(de.as_ref().get_key().to_string());
+                        } else {
+                            ttl_sum += *when - now;
+                            ttl_samples += 1;
+                        }
+                    }
+                }
+            }
+            // a running average of the TTL of the sampled keys, for INFO keyspace
+            if ttl_samples > 0 {
+                let avg_ttl = ttl_sum / ttl_samples;
+                if self.avg_ttl == 0 {
+                    self.avg_ttl = avg_ttl;
+                }
+                self.avg_ttl = (self.avg_ttl / 50) * 49 + avg_ttl / 50;
+            }
+            expired.sort_unstable();
+            expired.dedup();
+            for key in &expired {
+                self.delete_expired_key(key);
+            }
+            if expired.len() * 100 <= num * ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE
+                || start.elapsed() >= ACTIVE_EXPIRE_CYCLE_TIME_LIMIT
+            {
+                return;
+            }
+        }
+    }
+}
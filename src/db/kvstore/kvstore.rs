@@ -2,6 +2,7 @@ use std::mem::size_of;
 use crate::db::data_structure::adlist::adlist::{LinkList, Node};
 use crate::db::data_structure::dict::dict::{Dict, DictEntry, Value};
 use crate::db::data_structure::dict::lib::{entry_mem_usage, DictScanFunction};
+use crate::db::data_structure::dict::stats::{dict_combine_stats, dict_get_stats_msg, DictStats};
 use crate::db::kvstore::iter::{KvStoreDictIterator, KvStoreIterator};
 use crate::db::kvstore::lib::{KvStoreExpandShouldSkipDictIndex, KvStoreScanShouldSkipDict};
 use crate::db::kvstore::{
@@ -12,6 +13,7 @@ use std::ptr::NonNull;
 use std::time::Instant;
 use crate::db::data_structure::dict::iter_mut::DictIterMut;
 use crate::db::object::RedisObject;
+use crate::server::latency::{latency_add_sample_if_needed, LATENCY_EVENT_REHASH};
 
 #[derive(Clone)]
 pub struct KvStoreMetadata {
@@ -128,9 +130,7 @@ impl KvStore {
             self.bucket_count = 0;
             self.overhead_hashtable_rehashing = 0;
             self.overhead_hashtable_lut = 0;
-            if !self.dict_size_index.is_empty() {
-                self.dict_size_index = Vec::new();
-            }
+            self.dict_size_index.fill(0);
         }
     }
 
@@ -308,14 +308,14 @@ impl KvStore {
         true
     }
 
-    // pub fn get_fair_random_dict_index(&self) -> usize {
-    //     let target = if self.kvstore_size() > 0 {
-    //         rand::rng().random::<u64>() % self.kvstore_size() + 1
-    //     } else {
-    //         0
-    //     };
-    //     self.find_dict_index_by_key_index(target)
-    // }
+    pub fn get_fair_random_dict_index(&self) -> usize {
+        let target = if self.kvstore_size() > 0 {
+            rand::rng().random::<u64>() % self.kvstore_size() + 1
+        } else {
+            0
+        };
+        self.find_dict_index_by_key_index(target)
+    }
 
     pub fn get_next_non_empty_dict_index(&self, didx: usize) -> i32 {
         if self.num_dicts == 1 {
@@ -413,7 +413,8 @@ impl KvStore {
         unsafe {
             let dict = self.get_dict(0).unwrap().as_mut();
             let dict_iter = DictIterMut::new(dict);
-            let next_didx = self.get_first_non_empty_dict_index() as i32;
+            // dict 0 is walked first, carry on with the non-empty dicts after it
+            let next_didx = self.get_next_non_empty_dict_index(0);
 
             KvStoreIterator {
                 kvs: self,
@@ -440,23 +441,28 @@ impl KvStore {
         }
     }
 
-    pub fn increment_rehash(&self, threshold_us: u64) -> u64 {
+    /// Rehashes the dicts being rehashed for about `threshold_us` microseconds, dropping
+    /// the ones done from the rehashing list. Returns the time spent.
+    pub fn increment_rehash(&mut self, threshold_us: u64) -> u64 {
         if self.rehashing.length() == 0 {
             return 0;
         }
         let mut elapsed_us = 0;
         let start = Instant::now();
         unsafe {
-            while self.rehashing.list_first().is_some() {
-                let mut node = self.rehashing.list_first();
-                let _ = (*(*node.as_mut().unwrap().as_ptr()).value().as_ptr())
-                    .rehash_microseconds(threshold_us - elapsed_us);
-                elapsed_us = start.elapsed().as_secs();
-                if elapsed_us > threshold_us {
+            while let Some(node) = self.rehashing.list_first() {
+                let d = (*node.as_ptr()).value().as_ptr();
+                let _ = (*d).rehash_microseconds(threshold_us - elapsed_us);
+                if !(*d).dict_is_rehashing() {
+                    self.rehashing.pop_head();
+                }
+                elapsed_us = start.elapsed().as_micros() as u64;
+                if elapsed_us >= threshold_us {
                     break;
                 }
             }
         }
+        latency_add_sample_if_needed(LATENCY_EVENT_REHASH, start.elapsed());
         elapsed_us
     }
 
@@ -472,6 +478,27 @@ impl KvStore {
         self.rehashing.length()
     }
 
+    /// Appends the hash table stats of all the dicts, combined, to `buf`.
+    pub fn get_stats(&self, buf: &mut String, buf_size: usize, full: bool) {
+        let mut main_ht_stats = DictStats::new(0);
+        let mut rehash_ht_stats = None;
+        for didx in 0..self.num_dicts as usize {
+            let Some(d) = self.get_dict(didx) else { continue };
+            let d = unsafe { d.as_ref() };
+            dict_combine_stats(&mut d.get_stats_ht(0, full), &mut main_ht_stats);
+            if d.dict_is_rehashing() {
+                let into = rehash_ht_stats.get_or_insert_with(|| DictStats::new(1));
+                dict_combine_stats(&mut d.get_stats_ht(1, full), into);
+            }
+        }
+        let l = dict_get_stats_msg(buf, buf_size, &main_ht_stats, full);
+        if let Some(stats) = rehash_ht_stats {
+            if buf_size > l {
+                dict_get_stats_msg(buf, buf_size - l, &stats, full);
+            }
+        }
+    }
+
     pub fn dict_size(&self, didx: usize) -> u64 {
         let d = self.get_dict(didx);
         if d.is_none() {
@@ -588,11 +615,12 @@ impl KvStore {
             }
             let ret = (*d.unwrap().as_ptr()).generic_delete(key);
             match ret {
-                Ok(ret) => {
+                Ok(Some(ret)) => {
                     self.cumulative_key_count_add(didx, -1);
                     self.free_dict_if_needed(didx as usize);
-                    ret
+                    Some(ret)
                 }
+                Ok(None) => None,
                 Err(_) => None,
             }
         }
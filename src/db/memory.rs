@@ -0,0 +1,273 @@
+use std::mem::size_of;
+use std::ptr::NonNull;
+
+use crate::db::data_structure::adlist::adlist::Node as ListNode;
+use crate::db::data_structure::dict::dict::{Dict, DictEntry, Value};
+use crate::db::data_structure::listpack::ListPack;
+use crate::db::data_structure::skiplist::skiplist::{Level, Node, ZSet};
+use crate::db::data_structure::stream::{Stream, StreamCG, StreamConsumer, StreamID, StreamNACK};
+use crate::db::db::RedisDb;
+use crate::db::object::{ListObject, RedisObject, RedisValue};
+use crate::server::zmalloc::{peak_memory, rss_memory, used_memory};
+
+/// Number of elements MEMORY USAGE samples from aggregate values by default
+pub const OBJ_COMPUTE_SIZE_DEF_SAMPLES: usize = 5;
+
+/// Sums the sizes yielded by `sizes`, looking at `samples` of them at most (0 for
+/// all) and scaling the average of the sampled ones up to `len` elements.
+fn sampled_size(sizes: impl Iterator<Item = usize>, len: usize, samples: usize) -> usize {
+    let limit = if samples == 0 { usize::MAX } else { samples };
+    let (mut sum, mut seen) = (0usize, 0usize);
+    for size in sizes.take(limit) {
+        sum += size;
+        seen += 1;
+    }
+    if seen == 0 {
+        return 0;
+    }
+    (sum as f64 / seen as f64 * len as f64) as usize
+}
+
+fn string_size(s: &str) -> usize {
+    size_of::<String>() + s.len()
+}
+
+/// Heap size of a dict value, the entry itself is counted by the table.
+fn value_size(v: Option<&Value>, samples: usize) -> usize {
+    match v {
+        Some(Value::Val(o)) => object_compute_size(o, samples),
+        Some(Value::Sds(s)) => s.len(),
+        _ => 0,
+    }
+}
+
+/// The tables, entries and sampled keys and values of a dict.
+fn dict_size(d: &Dict, samples: usize) -> usize {
+    let len = d.dict_size() as usize;
+    let table = d.dict_buckets() as usize * size_of::<Option<NonNull<DictEntry>>>();
+    let entries = sampled_size(
+        d.iter().map(|de| unsafe { size_of::<DictEntry>() + (*de).key.len() + value_size((*de).val.as_ref(), samples) }),
+        len,
+        samples,
+    );
+    table + entries
+}
+
+fn zset_size(zs: &ZSet, samples: usize) -> usize {
+    let len = zs.zsl.length as usize;
+    let node_size = |node: NonNull<Node>| unsafe {
+        let node = node.as_ref();
+        size_of::<Node>() + node.level.capacity() * size_of::<Level>() + node.get_elem().len()
+    };
+    let mut nodes = vec![];
+    let mut x = zs.zsl.head.and_then(|head| unsafe { head.as_ref().level[0].forward });
+    let limit = if samples == 0 { len } else { samples };
+    while let Some(node) = x {
+        if nodes.len() >= limit {
+            break;
+        }
+        nodes.push(node_size(node));
+        x = unsafe { node.as_ref().level[0].forward };
+    }
+    let header = zs.zsl.head.map_or(0, node_size);
+    dict_size(&zs.dict, samples) + header + sampled_size(nodes.into_iter(), len, samples)
+}
+
+fn stream_size(s: &Stream, samples: usize) -> usize {
+    // a B-tree keeps its entries in nodes of up to 11 slots, count them as half full
+    let btree_slot = |entry: usize| entry * 2;
+    let mut size = size_of::<Stream>();
+    size += sampled_size(
+        s.nodes().map(|(_, lp)| btree_slot(size_of::<StreamID>() + size_of::<ListPack>()) + lp.bytes()),
+        s.nodes().len(),
+        samples,
+    );
+    for (name, cg) in &s.cgroups {
+        size += string_size(name) + size_of::<StreamCG>();
+        size += cg.pel.values().map(|nack| btree_slot(size_of::<StreamID>() + size_of::<StreamNACK>()) + nack.consumer.len()).sum::<usize>();
+        for (name, consumer) in &cg.consumers {
+            size += string_size(name) + size_of::<StreamConsumer>() + consumer.pel.len() * btree_slot(size_of::<StreamID>());
+        }
+    }
+    size
+}
+
+/// Estimates the memory used by a value, from the encoding of its type. Aggregate
+/// values sample `samples` elements (0 for all of them) to size the rest.
+pub fn object_compute_size(o: &RedisObject, samples: usize) -> usize {
+    let base = size_of::<RedisObject>();
+    base + match &o.ptr {
+        RedisValue::String(s) => s.len(),
+        RedisValue::List(ListObject::LinkList(list)) => {
+            let node = size_of::<ListNode<String>>();
+            sampled_size(list.iter().map(|s| node + s.len()), list.length(), samples)
+        }
+        RedisValue::ListPack(lp) => lp.bytes(),
+        RedisValue::Set(is) => is.blob_len(),
+        RedisValue::Hash(d) => dict_size(d, samples),
+        RedisValue::SortSet(zs) => zset_size(zs, samples),
+        RedisValue::Stream(s) => stream_size(s, samples),
+    }
+}
+
+/// Memory taken by the hash tables of one DB on top of the data itself
+#[derive(Debug, Clone, Default)]
+pub struct DbOverhead {
+    pub id: i32,
+    pub keys: u64,
+    pub expires: u64,
+    pub overhead_hashtable_main: usize,
+    pub overhead_hashtable_expires: usize,
+    pub overhead_hashtable_lut: usize,
+    pub overhead_hashtable_rehashing: usize,
+}
+
+impl RedisDb {
+    /// MEMORY USAGE of `key`: its value, the key itself and its entry in the keyspace.
+    pub fn memory_usage(&mut self, key: &str, samples: usize) -> Option<usize> {
+        let key_obj = RedisObject::create_string_object(key.to_string());
+        let o = self.find_no_touch(&key_obj)?;
+        Some(object_compute_size(o, samples) + size_of::<DictEntry>() + key.len())
+    }
+
+    pub fn memory_overhead(&self) -> DbOverhead {
+        DbOverhead {
+            id: self.id,
+            keys: self.kvs.kvstore_size(),
+            expires: self.expires.kvstore_size(),
+            overhead_hashtable_main: self.kvs.mem_usge(),
+            overhead_hashtable_expires: self.expires.mem_usge(),
+            overhead_hashtable_lut: self.kvs.kvstore_overhead_hashtable_lut()
+                + self.expires.kvstore_overhead_hashtable_lut(),
+            overhead_hashtable_rehashing: self.kvs.kvstore_overhead_hashtable_rehashing()
+                + self.expires.kvstore_overhead_hashtable_rehashing(),
+        }
+    }
+}
+
+/// Where the memory of the server goes, as reported by MEMORY STATS
+#[derive(Debug, Clone, Default)]
+pub struct MemoryOverhead {
+    pub peak_allocated: usize,
+    pub total_allocated: usize,
+    pub startup_allocated: usize,
+    pub overhead_total: usize,
+    pub dataset: usize,
+    pub dataset_perc: f64,
+    pub peak_perc: f64,
+    pub keys: u64,
+    pub bytes_per_key: usize,
+    pub overhead_db_hashtable_lut: usize,
+    pub overhead_db_hashtable_rehashing: usize,
+    pub rss: Option<usize>,
+    pub dbs: Vec<DbOverhead>,
+}
+
+impl MemoryOverhead {
+    /// Puts the overhead of the non-empty DBs together with the allocator counters,
+    /// `startup` being the memory used when the server was ready.
+    pub fn compute(startup: usize, dbs: Vec<DbOverhead>) -> Self {
+        let total = used_memory();
+        let peak = peak_memory().max(total);
+        let dbs: Vec<DbOverhead> = dbs.into_iter().filter(|db| db.keys > 0).collect();
+        let overhead_total = startup
+            + dbs.iter().map(|db| db.overhead_hashtable_main + db.overhead_hashtable_expires).sum::<usize>();
+        let keys = dbs.iter().map(|db| db.keys).sum::<u64>();
+        let net = total.saturating_sub(startup);
+        let dataset = total.saturating_sub(overhead_total);
+        Self {
+            peak_allocated: peak,
+            total_allocated: total,
+            startup_allocated: startup,
+            overhead_total,
+            dataset,
+            dataset_perc: if net > 0 { dataset as f64 * 100.0 / net as f64 } else { 0.0 },
+            peak_perc: total as f64 * 100.0 / peak as f64,
+            keys,
+            bytes_per_key: if keys > 0 { net / keys as usize } else { 0 },
+            overhead_db_hashtable_lut: dbs.iter().map(|db| db.overhead_hashtable_lut).sum(),
+            overhead_db_hashtable_rehashing: dbs.iter().map(|db| db.overhead_hashtable_rehashing).sum(),
+            rss: rss_memory(),
+            dbs,
+        }
+    }
+
+    /// Ratio between the resident set size and the allocated memory.
+    pub fn fragmentation(&self) -> Option<f64> {
+        self.rss.map(|rss| rss as f64 / self.total_allocated.max(1) as f64)
+    }
+
+    /// The MEMORY DOCTOR report: a short list of the memory problems found, if any.
+    pub fn doctor_report(&self) -> String {
+        if self.total_allocated < 1024 * 1024 * 5 {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions. Please, leave for your mission on Earth and fill it with some data. The new Sam and I will be back to our programming as soon as I finished rebooting.".to_string();
+        }
+        let mut issues = vec![];
+        if self.peak_allocated as f64 / self.total_allocated as f64 > 1.5 {
+            issues.push(format!(
+                " * Peak memory: In the past this instance used more than 150% the memory that is currently using. \
+                 The allocator is normally not able to release memory after a peak, so you can expect to see a big \
+                 fragmentation ratio, however this is actually harmless and is only due to the memory peak. \
+                 Peak was {} bytes, now {} bytes.",
+                self.peak_allocated, self.total_allocated
+            ));
+        }
+        if let (Some(rss), Some(frag)) = (self.rss, self.fragmentation()) {
+            if frag > 1.4 && rss - self.total_allocated > 1024 * 1024 * 10 {
+                issues.push(format!(
+                    " * High total RSS: This instance has a memory fragmentation and RSS overhead greater than 1.4 \
+                     ({:.2}). The process uses {} bytes more than what was allocated.",
+                    frag,
+                    rss - self.total_allocated
+                ));
+            }
+        }
+        if self.keys > 0 && self.dataset_perc < 50.0 {
+            issues.push(format!(
+                " * High overhead: The keyspace hash tables take more memory than the data itself, \
+                 only {:.2}% of the memory is used by the dataset.",
+                self.dataset_perc
+            ));
+        }
+        if issues.is_empty() {
+            return "Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base.".to_string();
+        }
+        format!(
+            "Sam, I detected a few issues in this instance memory implants:\n\n{}\n\nI'm here to keep you safe, Sam. I want to help you.\n",
+            issues.join("\n\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::memory::object_compute_size;
+    use crate::db::object::{RedisObject, RedisValue};
+
+    #[test]
+    fn compute_size_grows_with_content() {
+        let small = RedisObject::create_string_object("a".to_string());
+        let big = RedisObject::create_string_object("a".repeat(1000));
+        assert_eq!(object_compute_size(&big, 5) - object_compute_size(&small, 5), 999);
+
+        let mut lp = RedisObject::create_hash_object();
+        let empty = object_compute_size(&lp, 5);
+        if let RedisValue::ListPack(l) = &mut lp.ptr {
+            l.append(b"field");
+            l.append(b"value");
+        }
+        assert!(object_compute_size(&lp, 5) > empty);
+    }
+
+    #[test]
+    fn sampling_scales_to_all_elements() {
+        let mut list = RedisObject::create_list_object();
+        if let RedisValue::List(crate::db::object::ListObject::LinkList(l)) = &mut list.ptr {
+            for _ in 0..100 {
+                l.add_node_tail("x".repeat(10));
+            }
+        }
+        // every element has the same size, so sampling a few gives the exact total
+        assert_eq!(object_compute_size(&list, 5), object_compute_size(&list, 0));
+    }
+}
@@ -4,4 +4,8 @@ pub mod kvstore;
 pub mod crc;
 pub mod data_structure;
 pub mod object;
-pub mod db_engine;
\ No newline at end of file
+pub mod db_engine;
+pub mod blocked;
+pub mod expire;
+pub mod evict;
+pub mod memory;
@@ -1,9 +1,13 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use serde::{Deserialize, Serialize};
+use crate::config::ServerConfig;
 use crate::db::data_structure::dict::dict::Dict;
 use crate::db::data_structure::intset::intset::IntSet;
+use crate::db::data_structure::listpack::ListPack;
 use crate::db::data_structure::skiplist::skiplist::{SkipList, ZSet};
-use crate::db::data_structure::ziplist::ziplist::ZipList;
+use crate::db::data_structure::stream::Stream;
 use crate::db::data_structure::adlist::adlist::LinkList;
+use crate::db::evict::object_initial_lru;
 
 /// A redis object, that is a type able to hold a string / list / set
 
@@ -20,6 +24,8 @@ pub const OBJ_ZSET: u32 = 3;
 pub const OBJ_HASH: u32 = 4;
 /// Max number of basic object types
 const OBJ_TYPE_BASIC_MAX: u32 = 5;
+/// Stream object
+pub const OBJ_STREAM: u32 = 6;
 
 /// Raw representation
 pub const OBJ_ENCODING_RAW: u32 = 0;
@@ -41,15 +47,127 @@ pub const OBJ_ENCODING_SKIPLIST: u32 = 7;
 pub const OBJ_ENCODING_EMBSTR: u32 = 8;
 /// Encoded as linked list of listpacks
 const OBJ_ENCODING_QUICKLIST: u32 = 9;
+/// Encoded as a B-tree of entries
+pub const OBJ_ENCODING_STREAM: u32 = 10;
+/// Encoded as listpack
+pub const OBJ_ENCODING_LISTPACK: u32 = 11;
 
-const LRU_BITS: u32 = 24;
+pub(crate) const LRU_BITS: u32 = 24;
 /// Max value of obj->lru
-const LRU_CLOCK_MAX: u32 = (1 << LRU_BITS) - 1;
+pub(crate) const LRU_CLOCK_MAX: u32 = (1 << LRU_BITS) - 1;
+
+/// Thresholds past which small encodings convert to the general ones, changeable at runtime
+static HASH_MAX_LISTPACK_ENTRIES: AtomicUsize = AtomicUsize::new(512);
+static HASH_MAX_LISTPACK_VALUE: AtomicUsize = AtomicUsize::new(64);
+static SET_MAX_INTSET_ENTRIES: AtomicUsize = AtomicUsize::new(512);
+static SET_MAX_LISTPACK_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static SET_MAX_LISTPACK_VALUE: AtomicUsize = AtomicUsize::new(64);
+static ZSET_MAX_LISTPACK_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static ZSET_MAX_LISTPACK_VALUE: AtomicUsize = AtomicUsize::new(64);
+
+/// Applies the encoding thresholds of the config file.
+pub fn load_encoding_config(config: &ServerConfig) {
+    set_hash_max_listpack_entries(config.hash_max_listpack_entries);
+    set_hash_max_listpack_value(config.hash_max_listpack_value);
+    set_set_max_intset_entries(config.set_max_intset_entries);
+    set_set_max_listpack_entries(config.set_max_listpack_entries);
+    set_set_max_listpack_value(config.set_max_listpack_value);
+    set_zset_max_listpack_entries(config.zset_max_listpack_entries);
+    set_zset_max_listpack_value(config.zset_max_listpack_value);
+}
+
+pub fn hash_max_listpack_entries() -> usize {
+    HASH_MAX_LISTPACK_ENTRIES.load(Ordering::Relaxed)
+}
+
+pub fn set_hash_max_listpack_entries(n: usize) {
+    HASH_MAX_LISTPACK_ENTRIES.store(n, Ordering::Relaxed);
+}
+
+pub fn hash_max_listpack_value() -> usize {
+    HASH_MAX_LISTPACK_VALUE.load(Ordering::Relaxed)
+}
+
+pub fn set_hash_max_listpack_value(n: usize) {
+    HASH_MAX_LISTPACK_VALUE.store(n, Ordering::Relaxed);
+}
+
+pub fn set_max_intset_entries() -> usize {
+    SET_MAX_INTSET_ENTRIES.load(Ordering::Relaxed)
+}
+
+pub fn set_set_max_intset_entries(n: usize) {
+    SET_MAX_INTSET_ENTRIES.store(n, Ordering::Relaxed);
+}
+
+pub fn set_max_listpack_entries() -> usize {
+    SET_MAX_LISTPACK_ENTRIES.load(Ordering::Relaxed)
+}
+
+pub fn set_set_max_listpack_entries(n: usize) {
+    SET_MAX_LISTPACK_ENTRIES.store(n, Ordering::Relaxed);
+}
+
+pub fn set_max_listpack_value() -> usize {
+    SET_MAX_LISTPACK_VALUE.load(Ordering::Relaxed)
+}
+
+pub fn set_set_max_listpack_value(n: usize) {
+    SET_MAX_LISTPACK_VALUE.store(n, Ordering::Relaxed);
+}
+
+pub fn zset_max_listpack_entries() -> usize {
+    ZSET_MAX_LISTPACK_ENTRIES.load(Ordering::Relaxed)
+}
+
+pub fn set_zset_max_listpack_entries(n: usize) {
+    ZSET_MAX_LISTPACK_ENTRIES.store(n, Ordering::Relaxed);
+}
+
+pub fn zset_max_listpack_value() -> usize {
+    ZSET_MAX_LISTPACK_VALUE.load(Ordering::Relaxed)
+}
+
+pub fn set_zset_max_listpack_value(n: usize) {
+    ZSET_MAX_LISTPACK_VALUE.store(n, Ordering::Relaxed);
+}
 
 const OBJ_SHARED_REFCOUNT: i32 = i32::MAX;
 const OBJ_STATIC_REFCOUNT: i32 = i32::MAX - 1;
 const OBJ_FIRST_SPECIAL_REFCOUNT: i32 = OBJ_STATIC_REFCOUNT;
 
+/// Name of an object type, as reported by TYPE
+pub fn str_type(object_type: u32) -> &'static str {
+    match object_type {
+        OBJ_STRING => "string",
+        OBJ_LIST => "list",
+        OBJ_SET => "set",
+        OBJ_ZSET => "zset",
+        OBJ_HASH => "hash",
+        OBJ_STREAM => "stream",
+        _ => "unknown",
+    }
+}
+
+/// Name of an encoding, as reported by OBJECT ENCODING
+pub fn str_encoding(encoding: u32) -> &'static str {
+    match encoding {
+        OBJ_ENCODING_RAW => "raw",
+        OBJ_ENCODING_INT => "int",
+        OBJ_ENCODING_HT => "hashtable",
+        OBJ_ENCODING_ZIPMAP => "zipmap",
+        OBJ_ENCODING_LINKEDLIST => "linkedlist",
+        OBJ_ENCODING_ZIPLIST => "ziplist",
+        OBJ_ENCODING_INTSET => "intset",
+        OBJ_ENCODING_SKIPLIST => "skiplist",
+        OBJ_ENCODING_EMBSTR => "embstr",
+        OBJ_ENCODING_QUICKLIST => "quicklist",
+        OBJ_ENCODING_STREAM => "stream",
+        OBJ_ENCODING_LISTPACK => "listpack",
+        _ => "unknown",
+    }
+}
+
 #[derive(Clone)]
 pub enum RedisValue {
     String(String),
@@ -57,12 +175,14 @@ pub enum RedisValue {
     Hash(Dict),
     SortSet(ZSet),
     Set(IntSet),
+    Stream(Box<Stream>),
+    /// The compact encoding of small hashes, sets and sorted sets
+    ListPack(ListPack),
 }
 
 #[derive(Clone)]
 pub enum ListObject {
     LinkList(LinkList<String>),
-    ZipList(ZipList),
 }
 
 #[derive(Clone)]
@@ -71,8 +191,8 @@ pub struct RedisObject {
     pub object_type: u32,
     /// object encoding
     pub encoding: u32,
-    /// object last visit time
-    lru: u32,
+    /// object last visit time, or access frequency under an LFU policy, see `db::evict`
+    pub(crate) lru: u32,
     /// object reference count
     ref_count: i32,
     /// actual object
@@ -88,7 +208,7 @@ impl RedisObject {
         Self {
             object_type,
             encoding: OBJ_ENCODING_RAW,
-            lru: 0,
+            lru: object_initial_lru(),
             ref_count: 1,
             ptr,
         }
@@ -117,10 +237,24 @@ impl RedisObject {
     //     o
     // }
 
+    pub fn create_list_object() -> Self {
+        let list = LinkList::create();
+        let mut o = RedisObject::create(OBJ_LIST, RedisValue::List(ListObject::LinkList(list)));
+        o.encoding = OBJ_ENCODING_LINKEDLIST;
+        o
+    }
+
     pub fn create_hash_object() -> Self {
-        let zp = ZipList::new();
-        let mut o = RedisObject::create(OBJ_HASH, RedisValue::List(ListObject::ZipList(zp)));
-        o.encoding = OBJ_ENCODING_ZIPLIST;
+        let lp = ListPack::new();
+        let mut o = RedisObject::create(OBJ_HASH, RedisValue::ListPack(lp));
+        o.encoding = OBJ_ENCODING_LISTPACK;
+        o
+    }
+
+    pub fn create_set_listpack_object() -> Self {
+        let lp = ListPack::new();
+        let mut o = RedisObject::create(OBJ_SET, RedisValue::ListPack(lp));
+        o.encoding = OBJ_ENCODING_LISTPACK;
         o
     }
 
@@ -131,6 +265,13 @@ impl RedisObject {
         o
     }
 
+    pub fn create_zset_listpack_object() -> Self {
+        let lp = ListPack::new();
+        let mut o = RedisObject::create(OBJ_ZSET, RedisValue::ListPack(lp));
+        o.encoding = OBJ_ENCODING_LISTPACK;
+        o
+    }
+
     pub fn create_zset_object() -> Self {
         let dict = Dict::create();
         let zsl = SkipList::new();
@@ -140,6 +281,16 @@ impl RedisObject {
         o
     }
 
+    pub fn create_stream_object() -> Self {
+        let mut o = RedisObject::create(OBJ_STREAM, RedisValue::Stream(Box::new(Stream::new())));
+        o.encoding = OBJ_ENCODING_STREAM;
+        o
+    }
+
+    pub fn ref_count(&self) -> i32 {
+        self.ref_count
+    }
+
     pub fn incr_ref_count(&mut self) {
         if self.ref_count > OBJ_FIRST_SPECIAL_REFCOUNT {
             self.ref_count += 1;
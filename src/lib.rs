@@ -1,6 +1,7 @@
 use tokio::sync::{mpsc, oneshot};
 use crate::parser::frame::Frame;
 use parser::cmd::command::RedisCommand;
+use server::slowlog::CallContext;
 
 pub mod parser;
 pub mod client;
@@ -9,12 +10,13 @@ pub mod db;
 pub mod server;
 pub mod error;
 mod persistence;
-mod config;
+pub mod config;
+pub mod util;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T> = std::result::Result<T, Error>;
-type MpscSender = mpsc::Sender<(oneshot::Sender<Result<Frame>>, RedisCommand)>;
-type MpscReceiver = mpsc::Receiver<(oneshot::Sender<Result<Frame>>, RedisCommand)>;
+type MpscSender = mpsc::Sender<(oneshot::Sender<Result<Frame>>, RedisCommand, CallContext)>;
+type MpscReceiver = mpsc::Receiver<(oneshot::Sender<Result<Frame>>, RedisCommand, CallContext)>;
 
 pub const DEFAULT_PORT: u16 = 8000;
 pub const DB_SIZE: usize = 256;
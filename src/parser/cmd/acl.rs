@@ -0,0 +1,218 @@
+use bytes::Bytes;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::object::help_frame;
+use crate::parser::frame::Frame;
+use crate::server::acl::{
+    acl_load, acl_log_entries, acl_log_reset, acl_save, delete_users, get_user, set_user, users, ACL_CATEGORIES,
+    COMMAND_TABLE,
+};
+use crate::server::clients::KillFilter;
+use crate::server::server::Handler;
+
+const ACL_HELP: &[&str] = &[
+    "ACL <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CAT [<category>]",
+    "    List all commands that belong to <category>, or all command categories",
+    "    when no category is specified.",
+    "DELUSER <username> [<username> ...]",
+    "    Delete a list of users.",
+    "GETUSER <username>",
+    "    Get the user's details.",
+    "LIST",
+    "    Show users details in config file format.",
+    "LOAD",
+    "    Reload users from the ACL file.",
+    "LOG [<count> | RESET]",
+    "    Show the ACL log entries.",
+    "SAVE",
+    "    Save the current config to the ACL file.",
+    "SETUSER <username> <attribute> [<attribute> ...]",
+    "    Create or modify a user with the specified attributes.",
+    "USERS",
+    "    List all the registered usernames.",
+    "WHOAMI",
+    "    Return the current connection username.",
+    "HELP",
+    "    Print this help.",
+];
+
+#[derive(Debug, Clone)]
+pub enum AclCmd {
+    /// Creates the user or changes it with the given rules
+    SetUser { username: String, rules: Vec<String> },
+    /// Returns the flags, passwords and permissions of a user
+    GetUser { username: String },
+    /// Removes users, closing their connections
+    DelUser { usernames: Vec<String> },
+    /// Returns every user in the ACL file format
+    List,
+    /// Returns the names of the users
+    Users,
+    /// Returns the user of the current connection
+    WhoAmI,
+    /// Returns the categories, or the commands of one
+    Cat { category: Option<String> },
+    /// Returns the `count` newest denials, all of them if None
+    Log { count: Option<usize> },
+    /// Removes every entry of the ACL log
+    LogReset,
+    /// Writes the users to the ACL file
+    Save,
+    /// Replaces the users with the ones of the ACL file
+    Load,
+    Help,
+}
+
+impl AclCmd {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let args = match self {
+            AclCmd::SetUser { username, rules } => [vec!["setuser".to_string(), username], rules].concat(),
+            AclCmd::GetUser { username } => vec!["getuser".to_string(), username],
+            AclCmd::DelUser { usernames } => [vec!["deluser".to_string()], usernames].concat(),
+            AclCmd::List => vec!["list".to_string()],
+            AclCmd::Users => vec!["users".to_string()],
+            AclCmd::WhoAmI => vec!["whoami".to_string()],
+            AclCmd::Cat { category } => [vec!["cat".to_string()], category.into_iter().collect()].concat(),
+            AclCmd::Log { count } => [vec!["log".to_string()], count.map(|count| count.to_string()).into_iter().collect()].concat(),
+            AclCmd::LogReset => vec!["log".to_string(), "reset".to_string()],
+            AclCmd::Save => vec!["save".to_string()],
+            AclCmd::Load => vec!["load".to_string()],
+            AclCmd::Help => vec!["help".to_string()],
+        };
+        frame.push_bulk(Bytes::from("acl".as_bytes()));
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    pub fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        if name != "acl" {
+            return Err(CommandError::ParseError(-14).into());
+        }
+        let sub = frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr("acl".to_string()))?.to_string().to_lowercase();
+        let args: Vec<String> = match &frame {
+            Frame::Array(args) => args.iter().skip(2).map(|arg| arg.to_string()).collect(),
+            _ => vec![],
+        };
+        let username = || -> crate::Result<String> {
+            Ok(args.first().ok_or(CommandError::ArgsErr(format!("acl {}", sub)))?.clone())
+        };
+        let cmd = match &sub[..] {
+            "setuser" => AclCmd::SetUser { username: username()?, rules: args[1..].to_vec() },
+            "getuser" => AclCmd::GetUser { username: username()? },
+            "deluser" => {
+                username()?;
+                AclCmd::DelUser { usernames: args }
+            }
+            "list" => AclCmd::List,
+            "users" => AclCmd::Users,
+            "whoami" => AclCmd::WhoAmI,
+            "cat" => AclCmd::Cat { category: args.first().map(|category| category.to_lowercase()) },
+            "log" => match args.first() {
+                None => AclCmd::Log { count: None },
+                Some(arg) if arg.eq_ignore_ascii_case("reset") => AclCmd::LogReset,
+                Some(arg) => AclCmd::Log {
+                    count: Some(arg.parse().map_err(|_| "ERR value is out of range, must be positive")?),
+                },
+            },
+            "save" => AclCmd::Save,
+            "load" => AclCmd::Load,
+            "help" => AclCmd::Help,
+            _ => return Err(CommandError::NotSupport(format!("acl {}", sub)).into()),
+        };
+        Ok(RedisCommand::Acl(cmd))
+    }
+
+    /// Users are server-wide and the connection may need to know who it is, so these
+    /// are answered by the connection handler.
+    pub async fn apply(&self, handler: &mut Handler) -> crate::Result<Frame> {
+        let bulk = |s: String| Frame::Bulk(Bytes::from(s.into_bytes()));
+        match self {
+            AclCmd::SetUser { username, rules } => {
+                set_user(username, rules)?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            AclCmd::GetUser { username } => {
+                let Some(user) = get_user(username) else { return Ok(Frame::Null) };
+                Ok(Frame::Array(vec![
+                    bulk("flags".to_string()),
+                    Frame::Array(user.flags().into_iter().map(|flag| bulk(flag.to_string())).collect()),
+                    bulk("passwords".to_string()),
+                    Frame::Array(user.password_hashes().into_iter().map(bulk).collect()),
+                    bulk("commands".to_string()),
+                    bulk(user.commands_string()),
+                    bulk("keys".to_string()),
+                    bulk(user.keys_string()),
+                    bulk("channels".to_string()),
+                    bulk(user.channels_string()),
+                ]))
+            }
+            AclCmd::DelUser { usernames } => {
+                let deleted = delete_users(usernames)?;
+                // clients authenticated as a removed user are closed, this one included
+                for username in usernames {
+                    let filter = KillFilter { user: Some(username.clone()), skip_me: false, ..Default::default() };
+                    handler.clients().kill(&filter, handler.client().id);
+                }
+                Ok(Frame::Integer(deleted as i64))
+            }
+            AclCmd::List => Ok(Frame::Array(users().iter().map(|user| bulk(user.describe())).collect())),
+            AclCmd::Users => Ok(Frame::Array(users().into_iter().map(|user| bulk(user.name)).collect())),
+            AclCmd::WhoAmI => Ok(bulk(handler.client().user())),
+            AclCmd::Cat { category: None } => {
+                Ok(Frame::Array(ACL_CATEGORIES.iter().map(|category| bulk(category.to_string())).collect()))
+            }
+            AclCmd::Cat { category: Some(category) } => {
+                if !ACL_CATEGORIES.contains(&category.as_str()) {
+                    return Err(format!("ERR Unknown category '{}'", category).into());
+                }
+                let commands = COMMAND_TABLE.iter().filter(|spec| spec.categories.contains(&category.as_str()));
+                Ok(Frame::Array(commands.map(|spec| bulk(spec.name.to_string())).collect()))
+            }
+            AclCmd::Log { count } => {
+                let now = crate::db::expire::mstime();
+                let entries = acl_log_entries(*count).into_iter().map(|entry| {
+                    Frame::Array(vec![
+                        bulk("count".to_string()),
+                        Frame::Integer(entry.count as i64),
+                        bulk("reason".to_string()),
+                        bulk(entry.reason.reason().to_string()),
+                        bulk("context".to_string()),
+                        bulk("toplevel".to_string()),
+                        bulk("object".to_string()),
+                        bulk(entry.object),
+                        bulk("username".to_string()),
+                        bulk(entry.username),
+                        bulk("age-seconds".to_string()),
+                        bulk(format!("{:.3}", (now - entry.created) as f64 / 1000.0)),
+                        bulk("client-info".to_string()),
+                        bulk(entry.client_info),
+                        bulk("entry-id".to_string()),
+                        Frame::Integer(entry.entry_id as i64),
+                        bulk("timestamp-created".to_string()),
+                        Frame::Integer(entry.created),
+                        bulk("timestamp-last-updated".to_string()),
+                        Frame::Integer(entry.last_updated),
+                    ])
+                });
+                Ok(Frame::Array(entries.collect()))
+            }
+            AclCmd::LogReset => {
+                acl_log_reset();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            AclCmd::Save => {
+                acl_save()?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            AclCmd::Load => {
+                acl_load()?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            AclCmd::Help => Ok(help_frame(ACL_HELP)),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use bytes::Bytes;
+use crate::cluster::cluster::{
+    add_slots, cluster_enabled, cluster_info, del_slots, describe_nodes, key_hash_slot, meet, myself, nodes_slots,
+    owns_slot, save_config, set_slot, SetSlot, CLUSTER_SLOTS,
+};
+use crate::db::db::RedisDb;
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+use crate::server::server::Handler;
+
+#[derive(Debug, Clone)]
+pub enum ClusterCmd {
+    /// Hash slot of a key
+    KeySlot { key: String },
+    /// Number of keys in a slot, read from the dict of the slot
+    CountKeysInSlot { slot: usize },
+    /// Up to `count` keys of a slot
+    GetKeysInSlot { slot: usize, count: usize },
+    /// Slot ranges with the node serving them
+    Slots,
+    /// Nodes with their slot ranges
+    Shards,
+    /// The slot map in the format of the cluster config file
+    Nodes,
+    /// ID of this node
+    MyId,
+    /// State of the cluster and slot counts
+    Info,
+    /// Adds the node listening at ip and port to the slot map
+    Meet { ip: String, port: u16 },
+    /// Gives slots to this node
+    AddSlots { slots: Vec<usize> },
+    AddSlotsRange { ranges: Vec<(usize, usize)> },
+    /// Leaves slots unassigned
+    DelSlots { slots: Vec<usize> },
+    DelSlotsRange { ranges: Vec<(usize, usize)> },
+    /// Migrates, imports or gives away a slot
+    SetSlot { slot: usize, change: SetSlot },
+    /// Writes the slot map to the cluster config file
+    SaveConfig,
+    /// Lets the next command use a key of a slot being imported (ASKING)
+    Asking,
+}
+
+fn parse_slot(slot: &str) -> crate::Result<usize> {
+    slot.parse::<usize>().ok().filter(|&slot| slot < CLUSTER_SLOTS).ok_or_else(|| "ERR Invalid or out of range slot".into())
+}
+
+fn parse_ranges(args: &[String]) -> crate::Result<Vec<(usize, usize)>> {
+    if args.is_empty() || args.len() % 2 != 0 {
+        return Err(CommandError::ArgsErr("cluster".to_string()).into());
+    }
+    args.chunks(2)
+        .map(|pair| {
+            let (start, end) = (parse_slot(&pair[0])?, parse_slot(&pair[1])?);
+            if start > end {
+                return Err(format!("ERR start slot number {} is greater than end slot number {}", start, end).into());
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+fn expand_ranges(ranges: &[(usize, usize)]) -> Vec<usize> {
+    ranges.iter().flat_map(|&(start, end)| start..=end).collect()
+}
+
+impl CommandStrategy for ClusterCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let cluster = |sub: &str, rest: Vec<String>| [vec!["cluster".to_string(), sub.to_string()], rest].concat();
+        let slots = |slots: Vec<usize>| slots.into_iter().map(|slot| slot.to_string()).collect::<Vec<_>>();
+        let ranges = |ranges: Vec<(usize, usize)>| {
+            ranges.into_iter().flat_map(|(start, end)| [start.to_string(), end.to_string()]).collect::<Vec<_>>()
+        };
+        let args = match self {
+            ClusterCmd::KeySlot { key } => cluster("keyslot", vec![key]),
+            ClusterCmd::CountKeysInSlot { slot } => cluster("countkeysinslot", vec![slot.to_string()]),
+            ClusterCmd::GetKeysInSlot { slot, count } => cluster("getkeysinslot", vec![slot.to_string(), count.to_string()]),
+            ClusterCmd::Slots => cluster("slots", vec![]),
+            ClusterCmd::Shards => cluster("shards", vec![]),
+            ClusterCmd::Nodes => cluster("nodes", vec![]),
+            ClusterCmd::MyId => cluster("myid", vec![]),
+            ClusterCmd::Info => cluster("info", vec![]),
+            ClusterCmd::Meet { ip, port } => cluster("meet", vec![ip, port.to_string()]),
+            ClusterCmd::AddSlots { slots: s } => cluster("addslots", slots(s)),
+            ClusterCmd::AddSlotsRange { ranges: r } => cluster("addslotsrange", ranges(r)),
+            ClusterCmd::DelSlots { slots: s } => cluster("delslots", slots(s)),
+            ClusterCmd::DelSlotsRange { ranges: r } => cluster("delslotsrange", ranges(r)),
+            ClusterCmd::SetSlot { slot, change } => {
+                let change = match change {
+                    SetSlot::Migrating(id) => vec!["migrating".to_string(), id],
+                    SetSlot::Importing(id) => vec!["importing".to_string(), id],
+                    SetSlot::Stable => vec!["stable".to_string()],
+                    SetSlot::Node(id) => vec!["node".to_string(), id],
+                };
+                cluster("setslot", [vec![slot.to_string()], change].concat())
+            }
+            ClusterCmd::SaveConfig => cluster("saveconfig", vec![]),
+            ClusterCmd::Asking => vec!["asking".to_string()],
+        };
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let mut args = vec![];
+        let mut idx = 1;
+        while let Some(arg) = frame.get_frame_by_index(idx) {
+            args.push(arg.to_string());
+            idx += 1;
+        }
+        if name == "asking" {
+            return match args.is_empty() {
+                true => Ok(RedisCommand::Cluster(ClusterCmd::Asking)),
+                false => Err(CommandError::ArgsErr("asking".to_string()).into()),
+            };
+        }
+        if name != "cluster" || args.is_empty() {
+            return Err(CommandError::ArgsErr("cluster".to_string()).into());
+        }
+        let sub = args[0].to_lowercase();
+        let cmd = match (&sub[..], &args[1..]) {
+            ("keyslot", [key]) => ClusterCmd::KeySlot { key: key.clone() },
+            ("countkeysinslot", [slot]) => ClusterCmd::CountKeysInSlot { slot: parse_slot(slot)? },
+            ("getkeysinslot", [slot, count]) => {
+                let count = count.parse::<usize>().map_err(|_| "ERR Invalid number of keys")?;
+                ClusterCmd::GetKeysInSlot { slot: parse_slot(slot)?, count }
+            }
+            ("slots", []) => ClusterCmd::Slots,
+            ("shards", []) => ClusterCmd::Shards,
+            ("nodes", []) => ClusterCmd::Nodes,
+            ("myid", []) => ClusterCmd::MyId,
+            ("info", []) => ClusterCmd::Info,
+            ("meet", [ip, port]) => {
+                let port = port.parse::<u16>().map_err(|_| format!("ERR Invalid base port specified: {}", port))?;
+                ClusterCmd::Meet { ip: ip.clone(), port }
+            }
+            ("addslots", slots) if !slots.is_empty() => {
+                ClusterCmd::AddSlots { slots: slots.iter().map(|slot| parse_slot(slot)).collect::<crate::Result<_>>()? }
+            }
+            ("delslots", slots) if !slots.is_empty() => {
+                ClusterCmd::DelSlots { slots: slots.iter().map(|slot| parse_slot(slot)).collect::<crate::Result<_>>()? }
+            }
+            ("addslotsrange", ranges) => ClusterCmd::AddSlotsRange { ranges: parse_ranges(ranges)? },
+            ("delslotsrange", ranges) => ClusterCmd::DelSlotsRange { ranges: parse_ranges(ranges)? },
+            ("setslot", [slot, rest @ ..]) => {
+                let slot = parse_slot(slot)?;
+                let change = match (rest.first().map(|action| action.to_lowercase()).as_deref(), &rest[1.min(rest.len())..]) {
+                    (Some("migrating"), [id]) => SetSlot::Migrating(id.clone()),
+                    (Some("importing"), [id]) => SetSlot::Importing(id.clone()),
+                    (Some("stable"), []) => SetSlot::Stable,
+                    (Some("node"), [id]) => SetSlot::Node(id.clone()),
+                    _ => return Err("ERR Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP".into()),
+                };
+                ClusterCmd::SetSlot { slot, change }
+            }
+            ("saveconfig", []) => ClusterCmd::SaveConfig,
+            ("keyslot" | "countkeysinslot" | "getkeysinslot" | "slots" | "shards" | "nodes" | "myid" | "info" | "meet"
+            | "addslots" | "delslots" | "setslot" | "saveconfig", _) => {
+                return Err(CommandError::ArgsErr(format!("cluster|{}", sub)).into());
+            }
+            _ => return Err(CommandError::NotSupport(format!("cluster {}", sub)).into()),
+        };
+        Ok(RedisCommand::Cluster(cmd))
+    }
+
+    /// Only COUNTKEYSINSLOT and GETKEYSINSLOT look at a DB, the other subcommands go
+    /// through `apply_server`.
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            ClusterCmd::CountKeysInSlot { slot } => Ok(Frame::Integer(db.count_keys_in_slot(slot) as i64)),
+            ClusterCmd::GetKeysInSlot { slot, count } => Ok(Frame::Array(
+                db.keys_in_slot(slot, count).into_iter().map(|key| Frame::Bulk(Bytes::from(key.into_bytes()))).collect(),
+            )),
+            _ => Err(CommandError::ParseError(-9).into()),
+        }
+    }
+}
+
+impl ClusterCmd {
+    pub async fn apply_server(&self, handler: &mut Handler) -> crate::Result<Frame> {
+        if !cluster_enabled() {
+            return Err("ERR This instance has cluster support disabled".into());
+        }
+        let ok = || Ok(Frame::Simple("OK".to_string()));
+        match self {
+            // the keys of a cluster node are all in DB 0
+            ClusterCmd::CountKeysInSlot { .. } | ClusterCmd::GetKeysInSlot { .. } => {
+                handler.apply_in_db(0, RedisCommand::Cluster(self.clone())).await
+            }
+            ClusterCmd::KeySlot { key } => Ok(Frame::Integer(key_hash_slot(key) as i64)),
+            ClusterCmd::Slots => Ok(slots_frame()?),
+            ClusterCmd::Shards => Ok(shards_frame()?),
+            ClusterCmd::Nodes => Ok(bulk(&describe_nodes()?)),
+            ClusterCmd::MyId => Ok(bulk(&myself()?.id)),
+            ClusterCmd::Info => {
+                let info = cluster_info()?;
+                let state = if info.slots_assigned == CLUSTER_SLOTS { "ok" } else { "fail" };
+                Ok(bulk(&format!(
+                    "cluster_state:{}\r\n\
+                     cluster_slots_assigned:{}\r\n\
+                     cluster_slots_ok:{}\r\n\
+                     cluster_slots_pfail:0\r\n\
+                     cluster_slots_fail:0\r\n\
+                     cluster_known_nodes:{}\r\n\
+                     cluster_size:{}\r\n\
+                     cluster_current_epoch:0\r\n\
+                     cluster_my_epoch:0\r\n",
+                    state, info.slots_assigned, info.slots_assigned, info.known_nodes, info.size
+                )))
+            }
+            ClusterCmd::Meet { ip, port } => meet(ip, *port).await.and_then(|_| ok()),
+            ClusterCmd::AddSlots { slots } => add_slots(slots).and_then(|_| ok()),
+            ClusterCmd::AddSlotsRange { ranges } => add_slots(&expand_ranges(ranges)).and_then(|_| ok()),
+            ClusterCmd::DelSlots { slots } => del_slots(slots).and_then(|_| ok()),
+            ClusterCmd::DelSlotsRange { ranges } => del_slots(&expand_ranges(ranges)).and_then(|_| ok()),
+            ClusterCmd::SetSlot { slot, change } => {
+                if let SetSlot::Node(id) = change {
+                    if owns_slot(*slot)? && *id != myself()?.id {
+                        let keys = handler.apply_in_db(0, RedisCommand::Cluster(ClusterCmd::CountKeysInSlot { slot: *slot })).await?;
+                        if !matches!(keys, Frame::Integer(0)) {
+                            return Err(format!("ERR Can't assign hashslot {} to a different node while I still hold keys for this hash slot.", slot).into());
+                        }
+                    }
+                }
+                set_slot(*slot, change.clone()).and_then(|_| ok())
+            }
+            ClusterCmd::SaveConfig => save_config().and_then(|_| ok()),
+            ClusterCmd::Asking => {
+                handler.set_asking();
+                ok()
+            }
+        }
+    }
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::from(s.to_string().into_bytes()))
+}
+
+/// CLUSTER SLOTS: `[start, end, [ip, port, id]]` for each range of slots of a node
+fn slots_frame() -> crate::Result<Frame> {
+    let mut ranges = vec![];
+    for (node, node_ranges) in nodes_slots()? {
+        for (start, end) in node_ranges {
+            let owner = Frame::Array(vec![bulk(&node.ip), Frame::Integer(node.port as i64), bulk(&node.id)]);
+            ranges.push((start, Frame::Array(vec![Frame::Integer(start as i64), Frame::Integer(end as i64), owner])));
+        }
+    }
+    ranges.sort_by_key(|(start, _)| *start);
+    Ok(Frame::Array(ranges.into_iter().map(|(_, range)| range).collect()))
+}
+
+/// CLUSTER SHARDS: the slot ranges of each node, a node being its own shard
+fn shards_frame() -> crate::Result<Frame> {
+    let shards = nodes_slots()?
+        .into_iter()
+        .map(|(node, ranges)| {
+            let slots = ranges.iter().flat_map(|&(start, end)| [Frame::Integer(start as i64), Frame::Integer(end as i64)]).collect();
+            let node = Frame::Array(vec![
+                bulk("id"),
+                bulk(&node.id),
+                bulk("port"),
+                Frame::Integer(node.port as i64),
+                bulk("ip"),
+                bulk(&node.ip),
+                bulk("endpoint"),
+                bulk(&node.ip),
+                bulk("role"),
+                bulk("master"),
+                bulk("replication-offset"),
+                Frame::Integer(0),
+                bulk("health"),
+                bulk("online"),
+            ]);
+            Frame::Array(vec![bulk("slots"), Frame::Array(slots), bulk("nodes"), Frame::Array(vec![node])])
+        })
+        .collect();
+    Ok(Frame::Array(shards))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cluster::cluster::SetSlot;
+    use crate::parser::cmd::cluster::ClusterCmd;
+    use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+
+    #[test]
+    fn cmd_to_frame() {
+        let id = "a".repeat(40);
+        let cmds = vec![
+            ClusterCmd::KeySlot { key: "{user}.name".to_string() },
+            ClusterCmd::CountKeysInSlot { slot: 42 },
+            ClusterCmd::GetKeysInSlot { slot: 42, count: 10 },
+            ClusterCmd::Slots,
+            ClusterCmd::Shards,
+            ClusterCmd::Nodes,
+            ClusterCmd::MyId,
+            ClusterCmd::Info,
+            ClusterCmd::Meet { ip: "127.0.0.1".to_string(), port: 7001 },
+            ClusterCmd::AddSlots { slots: vec![1, 2, 3] },
+            ClusterCmd::AddSlotsRange { ranges: vec![(0, 100), (200, 300)] },
+            ClusterCmd::DelSlots { slots: vec![16383] },
+            ClusterCmd::DelSlotsRange { ranges: vec![(5, 5)] },
+            ClusterCmd::SetSlot { slot: 7, change: SetSlot::Migrating(id.clone()) },
+            ClusterCmd::SetSlot { slot: 7, change: SetSlot::Importing(id.clone()) },
+            ClusterCmd::SetSlot { slot: 7, change: SetSlot::Stable },
+            ClusterCmd::SetSlot { slot: 7, change: SetSlot::Node(id) },
+            ClusterCmd::SaveConfig,
+            ClusterCmd::Asking,
+        ];
+        for cmd in cmds {
+            let expect = format!("{:?}", cmd);
+            let frame = RedisCommand::Cluster(cmd).into_frame();
+            match RedisCommand::from_frame("", frame).unwrap() {
+                RedisCommand::Cluster(cmd) => assert_eq!(format!("{:?}", cmd), expect),
+                other => panic!("unexpected command {:?}", other),
+            }
+        }
+    }
+}
@@ -6,7 +6,21 @@ use crate::parser::cmd::string::StringCmd;
 use crate::db::db::RedisDb;
 use crate::db::kvstore::iter::KvStoreIterator;
 use crate::db::object::RedisObject;
+use crate::parser::cmd::acl::AclCmd;
+use crate::parser::cmd::cluster::ClusterCmd;
 use crate::parser::cmd::conn::ConnCmd;
+use crate::parser::cmd::debug::DebugCmd;
+use crate::parser::cmd::generic::GenericCmd;
+use crate::parser::cmd::latency::LatencyCmd;
+use crate::parser::cmd::list::ListCmd;
+use crate::parser::cmd::memory::MemoryCmd;
+use crate::parser::cmd::object::ObjectCmd;
+use crate::parser::cmd::pubsub::PubSubCmd;
+use crate::parser::cmd::replication::ReplicationCmd;
+use crate::parser::cmd::server::ServerCmd;
+use crate::parser::cmd::set::SetCmd;
+use crate::parser::cmd::slowlog::SlowlogCmd;
+use crate::parser::cmd::stream::{StreamCmd, XGroupCmd};
 use crate::parser::cmd::zset::SortedCmd;
 
 pub trait CommandStrategy {
@@ -19,11 +33,23 @@ pub trait CommandStrategy {
 #[derive(Debug)]
 pub enum RedisCommand {
     Connection(ConnCmd),
+    Generic(GenericCmd),
     String(StringCmd),
     List(ListCmd),
     Set(SetCmd),
     SortSet(SortedCmd),
     Hash(HashCmd),
+    Stream(StreamCmd),
+    PubSub(PubSubCmd),
+    Server(ServerCmd),
+    Memory(MemoryCmd),
+    Object(ObjectCmd),
+    Debug(DebugCmd),
+    Slowlog(SlowlogCmd),
+    Latency(LatencyCmd),
+    Acl(AclCmd),
+    Replication(ReplicationCmd),
+    Cluster(ClusterCmd),
 }
 
 impl CommandStrategy for RedisCommand {
@@ -32,8 +58,21 @@ impl CommandStrategy for RedisCommand {
             RedisCommand::Hash(cmd) => cmd.into_frame(),
             RedisCommand::String(cmd) => cmd.into_frame(),
             RedisCommand::Connection(cmd) => cmd.into_frame(),
+            RedisCommand::Generic(cmd) => cmd.into_frame(),
             RedisCommand::SortSet(cmd) => cmd.into_frame(),
-            _ => unimplemented!()
+            RedisCommand::List(cmd) => cmd.into_frame(),
+            RedisCommand::Set(cmd) => cmd.into_frame(),
+            RedisCommand::Stream(cmd) => cmd.into_frame(),
+            RedisCommand::PubSub(cmd) => cmd.into_frame(),
+            RedisCommand::Server(cmd) => cmd.into_frame(),
+            RedisCommand::Memory(cmd) => cmd.into_frame(),
+            RedisCommand::Object(cmd) => cmd.into_frame(),
+            RedisCommand::Debug(cmd) => cmd.into_frame(),
+            RedisCommand::Slowlog(cmd) => cmd.into_frame(),
+            RedisCommand::Latency(cmd) => cmd.into_frame(),
+            RedisCommand::Acl(cmd) => cmd.into_frame(),
+            RedisCommand::Replication(cmd) => cmd.into_frame(),
+            RedisCommand::Cluster(cmd) => cmd.into_frame(),
         }
     }
 
@@ -45,8 +84,29 @@ impl CommandStrategy for RedisCommand {
                 HashCmd::from_frame(&cmd_name, frame)?,
             "append" | "set" | "get" | "setex" | "setnx" | "setpx" | "setxx" | "strlen" =>
                 StringCmd::from_frame(&cmd_name, frame)?,
-            "select" | "echo" | "ping" | "quit" => ConnCmd::from_frame(&cmd_name, frame)?,
-            "zadd" | "zcard" | "zscore" => SortedCmd::from_frame(&cmd_name, frame)?,
+            "del" | "expire" | "pexpire" | "pexpireat" | "ttl" | "pttl" | "persist" =>
+                GenericCmd::from_frame(&cmd_name, frame)?,
+            "auth" | "hello" | "select" | "echo" | "ping" | "quit" | "reset" | "client" => ConnCmd::from_frame(&cmd_name, frame)?,
+            "zadd" | "zcard" | "zscore" | "zpopmin" | "zpopmax" | "zmpop" | "bzpopmin" | "bzpopmax" | "bzmpop" =>
+                SortedCmd::from_frame(&cmd_name, frame)?,
+            "lpush" | "rpush" | "lpop" | "rpop" | "llen" | "lrange" | "lmove" | "lmpop" | "blpop" | "brpop" | "blmove" | "blmpop" =>
+                ListCmd::from_frame(&cmd_name, frame)?,
+            "sadd" | "srem" | "scard" | "sismember" | "smembers" =>
+                SetCmd::from_frame(&cmd_name, frame)?,
+            "xadd" | "xlen" | "xrange" | "xrevrange" | "xdel" | "xtrim" | "xread" | "xreadgroup" | "xack" | "xgroup"
+            | "xpending" | "xclaim" | "xautoclaim" | "xinfo" =>
+                StreamCmd::from_frame(&cmd_name, frame)?,
+            "subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe" | "publish" | "ssubscribe" | "sunsubscribe" | "spublish" | "pubsub" =>
+                PubSubCmd::from_frame(&cmd_name, frame)?,
+            "config" | "info" | "shutdown" => ServerCmd::from_frame(&cmd_name, frame)?,
+            "memory" => MemoryCmd::from_frame(&cmd_name, frame)?,
+            "object" => ObjectCmd::from_frame(&cmd_name, frame)?,
+            "debug" => DebugCmd::from_frame(&cmd_name, frame)?,
+            "slowlog" => SlowlogCmd::from_frame(&cmd_name, frame)?,
+            "latency" => LatencyCmd::from_frame(&cmd_name, frame)?,
+            "acl" => AclCmd::from_frame(&cmd_name, frame)?,
+            "replicaof" | "slaveof" | "replconf" | "psync" | "wait" | "role" => ReplicationCmd::from_frame(&cmd_name, frame)?,
+            "cluster" | "asking" => ClusterCmd::from_frame(&cmd_name, frame)?,
             _ => return Err(CommandError::ParseError(-101).into()),
         };
         Ok(command)
@@ -54,14 +114,124 @@ impl CommandStrategy for RedisCommand {
 
     fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
         match self {
+            RedisCommand::Generic(cmd) => cmd.apply(db),
             RedisCommand::Hash(cmd) => cmd.apply(db),
             RedisCommand::String(cmd) => cmd.apply(db),
             RedisCommand::SortSet(cmd) => cmd.apply(db),
+            RedisCommand::List(cmd) => cmd.apply(db),
+            RedisCommand::Set(cmd) => cmd.apply(db),
+            RedisCommand::Stream(cmd) => cmd.apply(db),
+            RedisCommand::Memory(cmd) => cmd.apply(db),
+            RedisCommand::Object(cmd) => cmd.apply(db),
+            RedisCommand::Debug(cmd) => cmd.apply(db),
+            RedisCommand::Cluster(cmd) => cmd.apply(db),
             _ => unimplemented!()
         }
     }
 }
 
+impl RedisCommand {
+    /// Commands that may use more memory, refused once `maxmemory` is reached and
+    /// nothing can be evicted.
+    pub fn is_denyoom(&self) -> bool {
+        matches!(
+            self,
+            RedisCommand::String(
+                StringCmd::Append { .. }
+                    | StringCmd::Set { .. }
+                    | StringCmd::SetEX { .. }
+                    | StringCmd::SetPX { .. }
+                    | StringCmd::SetPXAt { .. }
+                    | StringCmd::SetNX { .. }
+                    | StringCmd::SetXX { .. }
+            ) | RedisCommand::Hash(HashCmd::HSet { .. })
+                | RedisCommand::List(ListCmd::LPush { .. } | ListCmd::RPush { .. } | ListCmd::LMove { .. } | ListCmd::BLMove { .. })
+                | RedisCommand::Set(SetCmd::SAdd { .. })
+                | RedisCommand::SortSet(SortedCmd::ZAdd { .. })
+                | RedisCommand::Stream(
+                    StreamCmd::XAdd { .. }
+                        | StreamCmd::XGroup(XGroupCmd::Create { .. } | XGroupCmd::CreateConsumer { .. })
+                )
+        )
+    }
+
+    /// Commands that change the keyspace, held back by CLIENT PAUSE WRITE.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            RedisCommand::String(
+                StringCmd::Append { .. }
+                    | StringCmd::Set { .. }
+                    | StringCmd::SetEX { .. }
+                    | StringCmd::SetPX { .. }
+                    | StringCmd::SetPXAt { .. }
+                    | StringCmd::SetNX { .. }
+                    | StringCmd::SetXX { .. }
+                    | StringCmd::Incr { .. }
+                    | StringCmd::IncrBy { .. }
+                    | StringCmd::Decr { .. }
+                    | StringCmd::DecrBy { .. }
+            ) | RedisCommand::Generic(
+                GenericCmd::Del { .. }
+                    | GenericCmd::Expire { .. }
+                    | GenericCmd::PExpire { .. }
+                    | GenericCmd::PExpireAt { .. }
+                    | GenericCmd::Persist { .. }
+            ) | RedisCommand::Hash(HashCmd::HSet { .. } | HashCmd::HDel { .. })
+                | RedisCommand::List(
+                    ListCmd::LPush { .. }
+                        | ListCmd::RPush { .. }
+                        | ListCmd::LPop { .. }
+                        | ListCmd::RPop { .. }
+                        | ListCmd::LSet { .. }
+                        | ListCmd::LInsert { .. }
+                        | ListCmd::LMove { .. }
+                        | ListCmd::LMPop { .. }
+                        | ListCmd::BLPop { .. }
+                        | ListCmd::BRPop { .. }
+                        | ListCmd::BLMove { .. }
+                        | ListCmd::BLMPop { .. }
+                )
+                | RedisCommand::Set(SetCmd::SAdd { .. } | SetCmd::SRem { .. })
+                | RedisCommand::SortSet(
+                    SortedCmd::ZAdd { .. }
+                        | SortedCmd::ZInterStore { .. }
+                        | SortedCmd::ZPopMin { .. }
+                        | SortedCmd::ZPopMax { .. }
+                        | SortedCmd::ZMPop { .. }
+                        | SortedCmd::BZPopMin { .. }
+                        | SortedCmd::BZPopMax { .. }
+                        | SortedCmd::BZMPop { .. }
+                )
+                | RedisCommand::Stream(
+                    StreamCmd::XAdd { .. }
+                        | StreamCmd::XDel { .. }
+                        | StreamCmd::XTrim { .. }
+                        | StreamCmd::XReadGroup { .. }
+                        | StreamCmd::XAck { .. }
+                        | StreamCmd::XGroup(_)
+                        | StreamCmd::XClaim { .. }
+                        | StreamCmd::XAutoClaim { .. }
+                )
+        )
+    }
+
+    /// Blocking commands are parked by the DB task until their keys hold data,
+    /// see `RedisDb::block_for_keys`.
+    pub fn is_blocking(&self) -> bool {
+        matches!(
+            self,
+            RedisCommand::List(ListCmd::BLPop { .. })
+                | RedisCommand::List(ListCmd::BRPop { .. })
+                | RedisCommand::List(ListCmd::BLMove { .. })
+                | RedisCommand::List(ListCmd::BLMPop { .. })
+                | RedisCommand::SortSet(SortedCmd::BZPopMin { .. })
+                | RedisCommand::SortSet(SortedCmd::BZPopMax { .. })
+                | RedisCommand::SortSet(SortedCmd::BZMPop { .. })
+        ) || matches!(self, RedisCommand::Stream(cmd) if cmd.is_blocking())
+    }
+}
+
 pub fn parse_frame(frame_vec: Vec<Frame>) -> crate::Result<Vec<RedisCommand>> {
     let mut cmd_vec= Vec::with_capacity(1024);
     for frame in frame_vec {
@@ -71,42 +241,6 @@ pub fn parse_frame(frame_vec: Vec<Frame>) -> crate::Result<Vec<RedisCommand>> {
     Ok(cmd_vec)
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
-pub enum ListCmd {
-    /// Prepends one or more elements to a list. Creates the key if it doesn't exist
-    LPush,
-    /// Appends one or more elements to a list. Creates the key if it doesn't exist
-    RPush,
-    /// Returns the first elements in a list after removing it. Deletes the list if the last element was popped
-    LPop,
-    /// Returns and removes the last elements of a list. Deletes the list if the last element was popped
-    RPop,
-    /// Sets the value of an element in a list by its index
-    LSet,
-    /// Inserts an element before or after another element in a list
-    LInsert,
-    /// Returns the length of a list
-    LLen,
-}
-
-#[allow(dead_code)]
-#[derive(Debug)]
-pub enum SetCmd {
-    /// Returns the number of members in a set
-    SCard,
-    /// Adds one or more members to a set
-    SAdd,
-    /// Iterates over members of a set
-    SSCan,
-    /// Returns the union of multiple sets
-    SUnion,
-    /// Returns the intersect of multiple sets
-    SInter,
-    /// Returns the number of members of the intersect of multiple sets
-    SInterCard,
-}
-
 pub fn get_command_name(frame: &Frame) -> crate::Result<String> {
     match frame.get_frame_by_index(0).ok_or("frame is empty")? {
         Frame::Simple(s) => Ok(s.clone()),
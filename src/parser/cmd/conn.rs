@@ -1,30 +1,65 @@
+use std::time::Duration;
 use bytes::Bytes;
 use crate::parser::cmd::command::{RedisCommand};
-use crate::parser::cmd::conn::ConnCmd::{Echo, Ping, Quit, Select};
+use crate::parser::cmd::conn::ConnCmd::{Auth, Client, Echo, Hello, Ping, Quit, Reset, Select};
 use crate::parser::cmd::error::CommandError;
 use crate::parser::frame::Frame;
+use crate::server::acl::get_user;
+use crate::server::clients::{ClientType, KillFilter, PauseMode, ReplyMode, DEFAULT_USER};
+use crate::server::replication::is_replica;
+use crate::cluster::cluster::cluster_enabled;
 use crate::server::server::Handler;
 
 #[derive(Debug, Clone)]
 pub enum ConnCmd {
-    /// Authenticates the connection
-    Auth,
+    /// Authenticates the connection, as the default user if no username is given
+    Auth { username: Option<String>, password: String },
     /// A container for client connection commands
-    Client,
+    Client(ClientCmd),
     /// Returns the given string
     Echo { msg: String },
-    /// Handshakes with the Redis server
-    Hello,
+    /// Handshakes with the Redis server, optionally authenticating and naming the connection
+    Hello { protover: Option<u64>, auth: Option<(String, String)>, setname: Option<String> },
     /// Returns the server's liveliness response
     Ping {msg: Option<String> },
     /// Closes the connection
     Quit,
-    /// Resets the connection
+    /// Puts the connection back in the state of a new one: no subscriptions, DB 0,
+    /// replies on and the default user
     Reset,
     /// Changes the selected database
     Select { index: usize },
 }
 
+#[derive(Debug, Clone)]
+pub enum ClientCmd {
+    /// Unblocks a client blocked by a blocking command from a different connection
+    Unblock { id: u64, error: bool },
+    /// Lists the connected clients, optionally only those of a type or with the given ids
+    List { client_type: Option<ClientType>, ids: Vec<u64> },
+    /// Returns the CLIENT LIST line of the current connection
+    Info,
+    /// Names the current connection
+    SetName { name: String },
+    /// Returns the name of the current connection
+    GetName,
+    /// Returns the id of the current connection
+    Id,
+    /// Closes the connections matching the filter. The legacy form `CLIENT KILL addr`
+    /// replies OK or an error instead of the number of clients killed.
+    Kill { filter: KillFilter, legacy: bool },
+    /// Holds back the commands of all clients for `timeout_ms`
+    Pause { timeout_ms: u64, mode: PauseMode },
+    /// Resumes the clients paused by CLIENT PAUSE
+    Unpause,
+    /// Turns the replies to the current connection on or off
+    Reply { mode: ReplyMode },
+    /// Sets the library name or version of the current connection
+    SetInfo { attr: String, value: String },
+    /// Protects the current connection from client eviction
+    NoEvict { on: bool },
+}
+
 impl  ConnCmd {
     pub fn into_frame(self) -> Frame {
         let mut frame = Frame::Array(vec![]);
@@ -46,6 +81,41 @@ impl  ConnCmd {
                 frame.push_bulk(Bytes::from(index.to_string().into_bytes()));
                 frame
             }
+            Auth {username, password} => {
+                frame.push_bulk(Bytes::from("auth".as_bytes()));
+                if let Some(username) = username {
+                    frame.push_bulk(Bytes::from(username.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(password.into_bytes()));
+                frame
+            }
+            Hello {protover, auth, setname} => {
+                frame.push_bulk(Bytes::from("hello".as_bytes()));
+                if let Some(protover) = protover {
+                    frame.push_bulk(Bytes::from(protover.to_string().into_bytes()));
+                }
+                if let Some((username, password)) = auth {
+                    frame.push_bulk(Bytes::from("auth".as_bytes()));
+                    frame.push_bulk(Bytes::from(username.into_bytes()));
+                    frame.push_bulk(Bytes::from(password.into_bytes()));
+                }
+                if let Some(name) = setname {
+                    frame.push_bulk(Bytes::from("setname".as_bytes()));
+                    frame.push_bulk(Bytes::from(name.into_bytes()));
+                }
+                frame
+            }
+            Reset => {
+                frame.push_bulk(Bytes::from("reset".as_bytes()));
+                frame
+            }
+            Client(cmd) => {
+                frame.push_bulk(Bytes::from("client".as_bytes()));
+                for arg in cmd.into_args() {
+                    frame.push_bulk(Bytes::from(arg.into_bytes()));
+                }
+                frame
+            }
             _ => Frame::Null
         }
     }
@@ -71,11 +141,170 @@ impl  ConnCmd {
             "quit" => {
                 Ok(RedisCommand::Connection(Quit))
             }
+            "reset" => {
+                Ok(RedisCommand::Connection(Reset))
+            }
+            "auth" => {
+                let args: Vec<String> = match &frame {
+                    Frame::Array(args) => args.iter().skip(1).map(|arg| arg.to_string()).collect(),
+                    _ => vec![],
+                };
+                match &args[..] {
+                    [password] => Ok(RedisCommand::Connection(Auth {username: None, password: password.clone()})),
+                    [username, password] => Ok(RedisCommand::Connection(Auth {username: Some(username.clone()), password: password.clone()})),
+                    _ => Err("ERR syntax error".into()),
+                }
+            }
+            "hello" => {
+                let args: Vec<String> = match &frame {
+                    Frame::Array(args) => args.iter().skip(1).map(|arg| arg.to_string()).collect(),
+                    _ => vec![],
+                };
+                let protover = match args.first() {
+                    Some(protover) => Some(protover.parse::<u64>().map_err(|_| "ERR Protocol version is not an integer or out of range")?),
+                    None => None,
+                };
+                let mut auth = None;
+                let mut setname = None;
+                let mut i = 1;
+                while i < args.len() {
+                    match &args[i].to_lowercase()[..] {
+                        "auth" if i + 2 < args.len() => {
+                            auth = Some((args[i + 1].clone(), args[i + 2].clone()));
+                            i += 3;
+                        }
+                        "setname" if i + 1 < args.len() => {
+                            setname = Some(args[i + 1].clone());
+                            i += 2;
+                        }
+                        _ => return Err(format!("ERR Syntax error in HELLO option '{}'", args[i]).into()),
+                    }
+                }
+                Ok(RedisCommand::Connection(Hello {protover, auth, setname}))
+            }
+            "client" => {
+                let sub = frame.get_frame_by_index(1).ok_or("command error 'client'")?.to_string().to_lowercase();
+                let arg = |index: usize| -> crate::Result<String> {
+                    Ok(frame.get_frame_by_index(index).ok_or(CommandError::ArgsErr(format!("client {}", sub)))?.to_string())
+                };
+                let args: Vec<String> = match &frame {
+                    Frame::Array(args) => args.iter().skip(2).map(|arg| arg.to_string()).collect(),
+                    _ => vec![],
+                };
+                let cmd = match &sub[..] {
+                    "unblock" => {
+                        let id: u64 = arg(2)?.parse()?;
+                        let error = match frame.get_frame_by_index(3) {
+                            None => false,
+                            Some(reason) => match &reason.to_string().to_lowercase()[..] {
+                                "timeout" => false,
+                                "error" => true,
+                                _ => return Err("ERR CLIENT UNBLOCK reason should be TIMEOUT or ERROR".into()),
+                            },
+                        };
+                        ClientCmd::Unblock {id, error}
+                    }
+                    "list" => {
+                        let mut client_type = None;
+                        let mut ids = vec![];
+                        let mut i = 0;
+                        while i < args.len() {
+                            match &args[i].to_lowercase()[..] {
+                                "type" if i + 1 < args.len() => {
+                                    let name = &args[i + 1];
+                                    client_type = Some(ClientType::from_name(name).ok_or_else(|| format!("ERR Unknown client type '{}'", name))?);
+                                    i += 2;
+                                }
+                                "id" if i + 1 < args.len() => {
+                                    for id in &args[i + 1..] {
+                                        ids.push(id.parse::<u64>().ok().filter(|id| *id > 0).ok_or("ERR Invalid client ID")?);
+                                    }
+                                    i = args.len();
+                                }
+                                _ => return Err("ERR syntax error".into()),
+                            }
+                        }
+                        ClientCmd::List {client_type, ids}
+                    }
+                    "info" => ClientCmd::Info,
+                    "setname" => ClientCmd::SetName {name: arg(2)?},
+                    "getname" => ClientCmd::GetName,
+                    "id" => ClientCmd::Id,
+                    "kill" => {
+                        if args.len() == 1 {
+                            let filter = KillFilter {addr: Some(args[0].clone()), skip_me: false, ..Default::default()};
+                            ClientCmd::Kill {filter, legacy: true}
+                        } else {
+                            if args.is_empty() || args.len() % 2 != 0 {
+                                return Err("ERR syntax error".into());
+                            }
+                            let mut filter = KillFilter {skip_me: true, ..Default::default()};
+                            for pair in args.chunks(2) {
+                                let value = &pair[1];
+                                match &pair[0].to_lowercase()[..] {
+                                    "id" => filter.id = Some(value.parse::<u64>().ok().filter(|id| *id > 0).ok_or("ERR client-id should be greater than 0")?),
+                                    "addr" => filter.addr = Some(value.clone()),
+                                    "laddr" => filter.laddr = Some(value.clone()),
+                                    "user" => filter.user = Some(value.clone()),
+                                    "type" => filter.client_type = Some(ClientType::from_name(value).ok_or_else(|| format!("ERR Unknown client type '{}'", value))?),
+                                    "maxage" => filter.max_age = Some(value.parse::<u64>().map_err(|_| "ERR syntax error")?),
+                                    "skipme" => filter.skip_me = match &value.to_lowercase()[..] {
+                                        "yes" => true,
+                                        "no" => false,
+                                        _ => return Err("ERR syntax error".into()),
+                                    },
+                                    _ => return Err("ERR syntax error".into()),
+                                }
+                            }
+                            ClientCmd::Kill {filter, legacy: false}
+                        }
+                    }
+                    "pause" => {
+                        let timeout_ms = arg(2)?.parse::<u64>().map_err(|_| "ERR timeout is not an integer or out of range")?;
+                        let mode = match frame.get_frame_by_index(3) {
+                            None => PauseMode::All,
+                            Some(mode) => match &mode.to_string().to_lowercase()[..] {
+                                "write" => PauseMode::Write,
+                                "all" => PauseMode::All,
+                                _ => return Err("ERR syntax error".into()),
+                            },
+                        };
+                        ClientCmd::Pause {timeout_ms, mode}
+                    }
+                    "unpause" => ClientCmd::Unpause,
+                    "reply" => {
+                        let mode = match &arg(2)?.to_lowercase()[..] {
+                            "on" => ReplyMode::On,
+                            "off" => ReplyMode::Off,
+                            "skip" => ReplyMode::Skip,
+                            _ => return Err("ERR syntax error".into()),
+                        };
+                        ClientCmd::Reply {mode}
+                    }
+                    "setinfo" => {
+                        let attr = arg(2)?.to_lowercase();
+                        if attr != "lib-name" && attr != "lib-ver" {
+                            return Err(format!("ERR Unrecognized option '{}'", attr).into());
+                        }
+                        ClientCmd::SetInfo {attr, value: arg(3)?}
+                    }
+                    "no-evict" => {
+                        let on = match &arg(2)?.to_lowercase()[..] {
+                            "on" => true,
+                            "off" => false,
+                            _ => return Err("ERR syntax error".into()),
+                        };
+                        ClientCmd::NoEvict {on}
+                    }
+                    _ => return Err(CommandError::NotSupport(format!("client {}", sub)).into()),
+                };
+                Ok(RedisCommand::Connection(Client(cmd)))
+            }
             _ => Err(CommandError::ParseError(-4).into())
         }
     }
 
-    pub fn apply(&self, handler: &mut Handler) -> crate::Result<Frame> {
+    pub async fn apply(&self, handler: &mut Handler) -> crate::Result<Frame> {
         match self {
             Echo {msg} => {
                 Ok(Frame::Simple(msg.clone()))
@@ -88,10 +317,267 @@ impl  ConnCmd {
                 }
             }
             Select {index} => {
+                if *index != 0 && cluster_enabled() {
+                    return Err("ERR SELECT is not allowed in cluster mode".into());
+                }
                 handler.change_db(*index)?;
                 Ok(Frame::Simple(format!("change db{}", index)))
             }
+            Auth {username, password} => {
+                let username = match username {
+                    Some(username) => username.clone(),
+                    None if get_user(DEFAULT_USER).is_some_and(|user| user.is_nopass()) => {
+                        return Err("ERR AUTH <password> called without any password configured for the default user. Are you sure your client is configured correctly?".into());
+                    }
+                    None => DEFAULT_USER.to_string(),
+                };
+                handler.authenticate(&username, password)?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            Hello {protover, auth, setname} => {
+                if protover.is_some_and(|protover| protover != 2) {
+                    return Err("NOPROTO sorry, this protocol version is not supported.".into());
+                }
+                match auth {
+                    Some((username, password)) => handler.authenticate(username, password)?,
+                    None if !handler.is_authenticated() => {
+                        return Err("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into());
+                    }
+                    None => {}
+                }
+                if let Some(name) = setname {
+                    ClientCmd::SetName {name: name.clone()}.apply(handler).await?;
+                }
+                let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string().into_bytes()));
+                Ok(Frame::Array(vec![
+                    bulk("server"),
+                    bulk("redis"),
+                    bulk("version"),
+                    bulk(env!("CARGO_PKG_VERSION")),
+                    bulk("proto"),
+                    Frame::Integer(2),
+                    bulk("id"),
+                    Frame::Integer(handler.client().id as i64),
+                    bulk("mode"),
+                    bulk(if cluster_enabled() { "cluster" } else { "standalone" }),
+                    bulk("role"),
+                    bulk(if is_replica() { "replica" } else { "master" }),
+                    bulk("modules"),
+                    Frame::Array(vec![]),
+                ]))
+            }
+            Reset => {
+                handler.reset()?;
+                Ok(Frame::Simple("RESET".to_string()))
+            }
+            Client(cmd) => cmd.apply(handler).await,
             _ => Err(CommandError::ParseError(-3).into())
         }
     }
+}
+
+impl ClientCmd {
+    /// The arguments after `client`
+    fn into_args(self) -> Vec<String> {
+        match self {
+            ClientCmd::Unblock {id, error} => {
+                let mut args = vec!["unblock".to_string(), id.to_string()];
+                if error {
+                    args.push("error".to_string());
+                }
+                args
+            }
+            ClientCmd::List {client_type, ids} => {
+                let mut args = vec!["list".to_string()];
+                if let Some(client_type) = client_type {
+                    args.push("type".to_string());
+                    args.push(client_type.name().to_string());
+                }
+                if !ids.is_empty() {
+                    args.push("id".to_string());
+                    args.extend(ids.iter().map(|id| id.to_string()));
+                }
+                args
+            }
+            ClientCmd::Info => vec!["info".to_string()],
+            ClientCmd::SetName {name} => vec!["setname".to_string(), name],
+            ClientCmd::GetName => vec!["getname".to_string()],
+            ClientCmd::Id => vec!["id".to_string()],
+            ClientCmd::Kill {filter, legacy: true} => vec!["kill".to_string(), filter.addr.unwrap_or_default()],
+            ClientCmd::Kill {filter, legacy: false} => {
+                let mut args = vec!["kill".to_string()];
+                let mut push = |name: &str, value: Option<String>| {
+                    if let Some(value) = value {
+                        args.push(name.to_string());
+                        args.push(value);
+                    }
+                };
+                push("id", filter.id.map(|id| id.to_string()));
+                push("addr", filter.addr);
+                push("laddr", filter.laddr);
+                push("user", filter.user);
+                push("type", filter.client_type.map(|client_type| client_type.name().to_string()));
+                push("maxage", filter.max_age.map(|age| age.to_string()));
+                push("skipme", Some(if filter.skip_me { "yes" } else { "no" }.to_string()));
+                args
+            }
+            ClientCmd::Pause {timeout_ms, mode} => {
+                let mode = match mode {
+                    PauseMode::Write => "write",
+                    PauseMode::All => "all",
+                };
+                vec!["pause".to_string(), timeout_ms.to_string(), mode.to_string()]
+            }
+            ClientCmd::Unpause => vec!["unpause".to_string()],
+            ClientCmd::Reply {mode} => {
+                let mode = match mode {
+                    ReplyMode::On => "on",
+                    ReplyMode::Off => "off",
+                    ReplyMode::Skip => "skip",
+                };
+                vec!["reply".to_string(), mode.to_string()]
+            }
+            ClientCmd::SetInfo {attr, value} => vec!["setinfo".to_string(), attr, value],
+            ClientCmd::NoEvict {on} => vec!["no-evict".to_string(), if on { "on" } else { "off" }.to_string()],
+        }
+    }
+
+    async fn apply(&self, handler: &mut Handler) -> crate::Result<Frame> {
+        match self {
+            ClientCmd::Unblock {id, error} => {
+                let unblocked = handler.unblock_client(*id, *error).await?;
+                Ok(Frame::Integer(unblocked as i64))
+            }
+            ClientCmd::List {client_type, ids} => {
+                let mut list = String::new();
+                for client in handler.clients().list() {
+                    if client_type.is_some_and(|client_type| client.client_type() != client_type) {
+                        continue;
+                    }
+                    if !ids.is_empty() && !ids.contains(&client.id) {
+                        continue;
+                    }
+                    list.push_str(&client.info_line());
+                    list.push('\n');
+                }
+                Ok(Frame::Bulk(Bytes::from(list.into_bytes())))
+            }
+            ClientCmd::Info => Ok(Frame::Bulk(Bytes::from(format!("{}\n", handler.client().info_line()).into_bytes()))),
+            ClientCmd::SetName {name} => {
+                if name.chars().any(|c| !('!'..='~').contains(&c)) {
+                    return Err("ERR Client names cannot contain spaces, newlines or special characters.".into());
+                }
+                handler.client().set_name(name.clone());
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ClientCmd::GetName => {
+                let name = handler.client().name();
+                if name.is_empty() {
+                    Ok(Frame::Null)
+                } else {
+                    Ok(Frame::Bulk(Bytes::from(name.into_bytes())))
+                }
+            }
+            ClientCmd::Id => Ok(Frame::Integer(handler.client().id as i64)),
+            ClientCmd::Kill {filter, legacy} => {
+                let killed = handler.clients().kill(filter, handler.client().id);
+                if !legacy {
+                    return Ok(Frame::Integer(killed as i64));
+                }
+                if killed == 0 {
+                    return Err("ERR No such client".into());
+                }
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ClientCmd::Pause {timeout_ms, mode} => {
+                handler.clients().pause(*mode, Duration::from_millis(*timeout_ms));
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ClientCmd::Unpause => {
+                handler.clients().unpause();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ClientCmd::Reply {mode} => {
+                // OFF and SKIP are not replied to
+                handler.set_reply_mode(*mode);
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ClientCmd::SetInfo {attr, value} => {
+                if value.chars().any(|c| !('!'..='~').contains(&c)) {
+                    return Err(format!("ERR {} cannot contain spaces, newlines or special characters.", attr).into());
+                }
+                if attr == "lib-name" {
+                    handler.client().set_lib_name(value.clone());
+                } else {
+                    handler.client().set_lib_ver(value.clone());
+                }
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ClientCmd::NoEvict {on} => {
+                handler.client().set_no_evict(*on);
+                Ok(Frame::Simple("OK".to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+    use crate::parser::cmd::conn::{ClientCmd, ConnCmd};
+    use crate::server::acl::get_user;
+use crate::server::clients::{ClientType, KillFilter, PauseMode, ReplyMode, DEFAULT_USER};
+
+    #[test]
+    fn conn_cmd_to_frame() {
+        let cmds = vec![
+            ConnCmd::Auth { username: None, password: "secret".to_string() },
+            ConnCmd::Auth { username: Some("alice".to_string()), password: "secret".to_string() },
+            ConnCmd::Hello { protover: None, auth: None, setname: None },
+            ConnCmd::Hello {
+                protover: Some(2),
+                auth: Some(("alice".to_string(), "secret".to_string())),
+                setname: Some("worker".to_string()),
+            },
+        ];
+        for cmd in cmds {
+            let expect = format!("{:?}", cmd);
+            let frame = cmd.into_frame();
+            match RedisCommand::from_frame("", frame).unwrap() {
+                RedisCommand::Connection(cmd) => assert_eq!(format!("{:?}", cmd), expect),
+                other => panic!("unexpected command {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn cmd_to_frame() {
+        let cmds = vec![
+            ClientCmd::Unblock { id: 3, error: true },
+            ClientCmd::List { client_type: None, ids: vec![] },
+            ClientCmd::List { client_type: Some(ClientType::PubSub), ids: vec![1, 2] },
+            ClientCmd::Info,
+            ClientCmd::SetName { name: "worker".to_string() },
+            ClientCmd::GetName,
+            ClientCmd::Id,
+            ClientCmd::Kill { filter: KillFilter { addr: Some("127.0.0.1:5000".to_string()), ..Default::default() }, legacy: true },
+            ClientCmd::Kill {
+                filter: KillFilter { id: Some(7), client_type: Some(ClientType::Normal), max_age: Some(60), ..Default::default() },
+                legacy: false,
+            },
+            ClientCmd::Pause { timeout_ms: 1000, mode: PauseMode::Write },
+            ClientCmd::Unpause,
+            ClientCmd::Reply { mode: ReplyMode::Skip },
+            ClientCmd::SetInfo { attr: "lib-name".to_string(), value: "redis-rs".to_string() },
+            ClientCmd::NoEvict { on: true },
+        ];
+        for cmd in cmds {
+            let expect = format!("{:?}", cmd);
+            let frame = ConnCmd::Client(cmd).into_frame();
+            match RedisCommand::from_frame("", frame).unwrap() {
+                RedisCommand::Connection(ConnCmd::Client(cmd)) => assert_eq!(format!("{:?}", cmd), expect),
+                other => panic!("unexpected command {:?}", other),
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::mem::size_of;
+use std::time::Duration;
+use bytes::Bytes;
+use crate::db::data_structure::dict::dict::{DictEntry, Value};
+use crate::db::db::RedisDb;
+use crate::db::evict::estimate_object_idle_time;
+use crate::db::expire::set_active_expire;
+use crate::db::memory::{object_compute_size, OBJ_COMPUTE_SIZE_DEF_SAMPLES};
+use crate::db::object::{str_encoding, str_type, RedisObject, RedisValue};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::object::help_frame;
+use crate::parser::frame::Frame;
+use crate::persistence::rdb::rdb_saved_object_len;
+use crate::server::server::Handler;
+
+/// Size limit of the HTSTATS reports
+const HTSTATS_BUF_SIZE: usize = 4096;
+
+const DEBUG_HELP: &[&str] = &[
+    "DEBUG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "HTSTATS <dbid> [full]",
+    "    Return hash table statistics of the specified Redis database.",
+    "HTSTATS-KEY <key> [full]",
+    "    Like HTSTATS but for the hash table stored at <key>'s value.",
+    "JMAP",
+    "    Return a histogram of the values of the current database by type and",
+    "    encoding, with their count and estimated size.",
+    "OBJECT <key>",
+    "    Show low level info about the <key> and associated value.",
+    "RELOAD",
+    "    Save the current database on disk, empty it and reload it back.",
+    "SET-ACTIVE-EXPIRE <0|1>",
+    "    Setting it to 0 disables expiring keys in background when they are not",
+    "    accessed (otherwise the Redis behavior). Setting it to 1 reenables back the",
+    "    default.",
+    "SLEEP <seconds>",
+    "    Stop the client for <seconds>. Decimals allowed.",
+    "HELP",
+    "    Print this help.",
+];
+
+#[derive(Debug, Clone)]
+pub enum DebugCmd {
+    /// Shows low level info about a key and its value
+    Object { key: String },
+    /// Hash table statistics of the keyspace of DB `db`
+    HtStats { db: usize, full: bool },
+    /// Hash table statistics of the value stored at a key
+    HtStatsKey { key: String, full: bool },
+    /// Histogram of the values of the DB by type and encoding
+    Jmap,
+    /// Saves the DB, empties it and loads it back from the RDB file
+    Reload,
+    /// Stops the client for `seconds`
+    Sleep { seconds: f64 },
+    /// Enables or disables the active expire cycle
+    SetActiveExpire { enabled: bool },
+    Help,
+}
+
+impl CommandStrategy for DebugCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let full = |full: bool| if full { vec!["full".to_string()] } else { vec![] };
+        let args = match self {
+            DebugCmd::Object { key } => vec!["object".to_string(), key],
+            DebugCmd::HtStats { db, full: f } => [vec!["htstats".to_string(), db.to_string()], full(f)].concat(),
+            DebugCmd::HtStatsKey { key, full: f } => [vec!["htstats-key".to_string(), key], full(f)].concat(),
+            DebugCmd::Jmap => vec!["jmap".to_string()],
+            DebugCmd::Reload => vec!["reload".to_string()],
+            DebugCmd::Sleep { seconds } => vec!["sleep".to_string(), seconds.to_string()],
+            DebugCmd::SetActiveExpire { enabled } => {
+                vec!["set-active-expire".to_string(), (enabled as u8).to_string()]
+            }
+            DebugCmd::Help => vec!["help".to_string()],
+        };
+        frame.push_bulk(Bytes::from("debug".as_bytes()));
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        if name != "debug" {
+            return Err(CommandError::ParseError(-11).into());
+        }
+        let sub = frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr("debug".to_string()))?.to_string().to_lowercase();
+        let arg = |idx: usize| -> crate::Result<String> {
+            Ok(frame.get_frame_by_index(idx).ok_or(CommandError::ArgsErr(format!("debug {}", sub)))?.to_string())
+        };
+        let full = || match frame.get_frame_by_index(3) {
+            Some(opt) => opt.to_string().eq_ignore_ascii_case("full"),
+            None => false,
+        };
+        let cmd = match &sub[..] {
+            "object" => DebugCmd::Object { key: arg(2)? },
+            "htstats" => {
+                let db = arg(2)?.parse::<usize>().map_err(|_| "ERR value is not an integer or out of range")?;
+                DebugCmd::HtStats { db, full: full() }
+            }
+            "htstats-key" => DebugCmd::HtStatsKey { key: arg(2)?, full: full() },
+            "jmap" => DebugCmd::Jmap,
+            "reload" => DebugCmd::Reload,
+            "sleep" => {
+                let seconds = arg(2)?.parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0)
+                    .ok_or("ERR value is not a valid float")?;
+                DebugCmd::Sleep { seconds }
+            }
+            "set-active-expire" => {
+                let enabled = arg(2)?.parse::<i64>().map_err(|_| "ERR value is not an integer or out of range")?;
+                DebugCmd::SetActiveExpire { enabled: enabled != 0 }
+            }
+            "help" => DebugCmd::Help,
+            _ => return Err(CommandError::NotSupport(format!("debug {}", sub)).into()),
+        };
+        Ok(RedisCommand::Debug(cmd))
+    }
+
+    /// Subcommands looking at a DB, RELOAD, SLEEP and SET-ACTIVE-EXPIRE go through
+    /// `apply_server`. HTSTATS lands here once routed to the DB it asks for.
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            DebugCmd::Object { key } => {
+                let o = db.find_no_touch(&RedisObject::create_string_object(key)).ok_or("ERR no such key")?;
+                let info = format!(
+                    "Value at:{:p} refcount:{} encoding:{} serializedlength:{} lru:{} lru_seconds_idle:{}",
+                    o as *const RedisObject,
+                    o.ref_count(),
+                    str_encoding(o.encoding),
+                    rdb_saved_object_len(o)?,
+                    o.lru,
+                    estimate_object_idle_time(o) / 1000
+                );
+                Ok(Frame::Simple(info))
+            }
+            DebugCmd::HtStats { full, .. } => {
+                let mut buf = String::new();
+                buf.push_str("[Dictionary HT]\n");
+                db.kvs.get_stats(&mut buf, HTSTATS_BUF_SIZE, full);
+                buf.push_str("[Expires HT]\n");
+                db.expires.get_stats(&mut buf, HTSTATS_BUF_SIZE, full);
+                Ok(Frame::Bulk(Bytes::from(buf.into_bytes())))
+            }
+            DebugCmd::HtStatsKey { key, full } => {
+                let o = db.find_no_touch(&RedisObject::create_string_object(key)).ok_or("ERR no such key")?;
+                let d = match &o.ptr {
+                    RedisValue::Hash(d) => d,
+                    RedisValue::SortSet(zs) => &zs.dict,
+                    _ => return Err("ERR The value stored at the specified key is not represented using an hash table".into()),
+                };
+                let mut buf = String::new();
+                d.get_stats(&mut buf, HTSTATS_BUF_SIZE, full);
+                Ok(Frame::Bulk(Bytes::from(buf.into_bytes())))
+            }
+            DebugCmd::Jmap => Ok(Frame::Bulk(Bytes::from(jmap_histogram(db).into_bytes()))),
+            DebugCmd::Help => Ok(help_frame(DEBUG_HELP)),
+            _ => Err(CommandError::ParseError(-11).into()),
+        }
+    }
+}
+
+impl DebugCmd {
+    /// Whether the command needs the whole server rather than the selected DB.
+    pub fn is_server_wide(&self) -> bool {
+        matches!(
+            self,
+            DebugCmd::HtStats { .. } | DebugCmd::Reload | DebugCmd::Sleep { .. } | DebugCmd::SetActiveExpire { .. }
+        )
+    }
+
+    pub async fn apply_server(&self, handler: &Handler) -> crate::Result<Frame> {
+        match self {
+            DebugCmd::HtStats { db, .. } => handler.apply_in_db(*db, RedisCommand::Debug(self.clone())).await,
+            DebugCmd::Reload => {
+                handler.reload_db().await?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            // only this client waits, the DBs keep serving the others
+            DebugCmd::Sleep { seconds } => {
+                tokio::time::sleep(Duration::from_secs_f64(*seconds)).await;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            DebugCmd::SetActiveExpire { enabled } => {
+                set_active_expire(*enabled);
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            _ => Err(CommandError::ParseError(-11).into()),
+        }
+    }
+}
+
+/// A `jmap -histo` like report of the DB: the values grouped by type and encoding,
+/// largest first, with their count and estimated size including the keyspace entry.
+fn jmap_histogram(db: &mut RedisDb) -> String {
+    let mut classes: HashMap<String, (usize, usize)> = HashMap::new();
+    for de in db.kvs.iter() {
+        let (key, val) = unsafe { ((*de).get_key(), (*de).value()) };
+        if let Value::Val(o) = val {
+            let class = classes.entry(format!("{} ({})", str_type(o.object_type), str_encoding(o.encoding))).or_default();
+            class.0 += 1;
+            class.1 += object_compute_size(o, OBJ_COMPUTE_SIZE_DEF_SAMPLES) + size_of::<DictEntry>() + key.len();
+        }
+    }
+    let mut classes: Vec<_> = classes.into_iter().collect();
+    classes.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+
+    let mut report = String::new();
+    writeln!(report, " num     #instances         #bytes  class name").unwrap();
+    writeln!(report, "----------------------------------------------").unwrap();
+    let (mut instances, mut bytes) = (0, 0);
+    for (i, (name, (count, size))) in classes.iter().enumerate() {
+        writeln!(report, "{:>4}: {:>14} {:>14}  {}", i + 1, count, size, name).unwrap();
+        instances += count;
+        bytes += size;
+    }
+    writeln!(report, "Total {:>14} {:>14}", instances, bytes).unwrap();
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::db::RedisDb;
+    use crate::db::kvstore::KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
+    use crate::db::object::RedisObject;
+    use crate::parser::cmd::command::CommandStrategy;
+    use crate::parser::cmd::debug::DebugCmd;
+
+    #[test]
+    fn inspects_the_db() -> crate::Result<()> {
+        let mut db = RedisDb::create(0, KVSTORE_ALLOCATE_DICTS_ON_DEMAND, 0);
+        let string = |s: &str| RedisObject::create_string_object(s.to_string());
+        db.add(string("a"), string("v1"));
+        db.add(string("b"), string("v2"));
+        db.add(string("h"), RedisObject::create_hash_object());
+
+        let object = (DebugCmd::Object { key: "a".to_string() }).apply(&mut db)?.to_string();
+        assert!(object.contains(" refcount:1 encoding:raw serializedlength:3 "), "{}", object);
+        assert!((DebugCmd::Object { key: "missing".to_string() }).apply(&mut db).is_err());
+
+        let stats = (DebugCmd::HtStats { db: 0, full: false }).apply(&mut db)?.to_string();
+        assert!(stats.starts_with("[Dictionary HT]\n"), "{}", stats);
+        assert!(stats.contains("[Expires HT]\n"), "{}", stats);
+        // a listpack hash has no hash table to report
+        assert!((DebugCmd::HtStatsKey { key: "h".to_string(), full: false }).apply(&mut db).is_err());
+
+        let jmap = DebugCmd::Jmap.apply(&mut db)?.to_string();
+        let strings = jmap.lines().find(|line| line.ends_with("string (raw)")).unwrap();
+        assert_eq!(strings.split_whitespace().nth(1), Some("2"));
+        assert!(jmap.lines().any(|line| line.ends_with("hash (listpack)")));
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use bytes::Bytes;
+use crate::db::db::RedisDb;
+use crate::db::expire::mstime;
+use crate::db::object::RedisObject;
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_GENERIC};
+use crate::server::REDIS_SERVER;
+
+#[derive(Debug)]
+pub enum GenericCmd {
+    /// Deletes one or more keys
+    Del { keys: Vec<String> },
+    /// Sets the expiration time of a key in seconds
+    Expire { key: String, seconds: i64 },
+    /// Sets the expiration time of a key in milliseconds
+    PExpire { key: String, millis: i64 },
+    /// Sets the expiration time of a key as a unix time in milliseconds
+    PExpireAt { key: String, when: i64 },
+    /// Returns the expiration time in seconds of a key
+    Ttl { key: String },
+    /// Returns the expiration time in milliseconds of a key
+    PTtl { key: String },
+    /// Removes the expiration time of a key
+    Persist { key: String },
+}
+
+impl CommandStrategy for GenericCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let (name, args) = match self {
+            GenericCmd::Del { keys } => ("del", keys),
+            GenericCmd::Expire { key, seconds } => ("expire", vec![key, seconds.to_string()]),
+            GenericCmd::PExpire { key, millis } => ("pexpire", vec![key, millis.to_string()]),
+            GenericCmd::PExpireAt { key, when } => ("pexpireat", vec![key, when.to_string()]),
+            GenericCmd::Ttl { key } => ("ttl", vec![key]),
+            GenericCmd::PTtl { key } => ("pttl", vec![key]),
+            GenericCmd::Persist { key } => ("persist", vec![key]),
+        };
+        frame.push_bulk(Bytes::from(name.as_bytes()));
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let key = || -> crate::Result<String> {
+            Ok(frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr(name.to_string()))?.to_string())
+        };
+        let ttl = || -> crate::Result<i64> {
+            let ttl = frame.get_frame_by_index(2).ok_or(CommandError::ArgsErr(name.to_string()))?.to_string();
+            ttl.parse().map_err(|_| "ERR value is not an integer or out of range".into())
+        };
+        let cmd = match name {
+            "del" => {
+                let keys: Vec<String> = (1..frame.get_len())
+                    .filter_map(|i| frame.get_frame_by_index(i).map(|f| f.to_string()))
+                    .collect();
+                if keys.is_empty() {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                GenericCmd::Del { keys }
+            }
+            "expire" => GenericCmd::Expire { key: key()?, seconds: ttl()? },
+            "pexpire" => GenericCmd::PExpire { key: key()?, millis: ttl()? },
+            "pexpireat" => GenericCmd::PExpireAt { key: key()?, when: ttl()? },
+            "ttl" => GenericCmd::Ttl { key: key()? },
+            "pttl" => GenericCmd::PTtl { key: key()? },
+            "persist" => GenericCmd::Persist { key: key()? },
+            _ => return Err(CommandError::ParseError(-6).into()),
+        };
+        Ok(RedisCommand::Generic(cmd))
+    }
+
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            GenericCmd::Del { keys } => {
+                let mut deleted = 0;
+                for key in keys {
+                    let key_obj = RedisObject::create_string_object(key.clone());
+                    if db.find(&key_obj).is_some() {
+                        db.delete(&key_obj);
+                        deleted += 1;
+                        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                        notify_keyspace_event(NOTIFY_GENERIC, "del", &key, db.id);
+                    }
+                }
+                Ok(Frame::Integer(deleted))
+            }
+            GenericCmd::Expire { key, seconds } => Self::expire_generic(db, key, mstime().saturating_add(seconds.saturating_mul(1000))),
+            GenericCmd::PExpire { key, millis } => Self::expire_generic(db, key, mstime().saturating_add(millis)),
+            GenericCmd::PExpireAt { key, when } => Self::expire_generic(db, key, when),
+            GenericCmd::Ttl { key } => Ok(Frame::Integer(Self::ttl_generic(db, &key, 1000))),
+            GenericCmd::PTtl { key } => Ok(Frame::Integer(Self::ttl_generic(db, &key, 1))),
+            GenericCmd::Persist { key } => {
+                let key_obj = RedisObject::create_string_object(key.clone());
+                if db.find(&key_obj).is_some() && db.remove_expire(&key) {
+                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                    notify_keyspace_event(NOTIFY_GENERIC, "persist", &key, db.id);
+                    Ok(Frame::Integer(1))
+                } else {
+                    Ok(Frame::Integer(0))
+                }
+            }
+        }
+    }
+}
+
+impl GenericCmd {
+    /// EXPIRE, PEXPIRE and PEXPIREAT, `when` being the unix time in milliseconds. A time
+    /// that is already in the past deletes the key right away, as Redis does, instead
+    /// of leaving it for the expire cycle.
+    fn expire_generic(db: &mut RedisDb, key: String, when: i64) -> crate::Result<Frame> {
+        let key_obj = RedisObject::create_string_object(key.clone());
+        if db.find(&key_obj).is_none() {
+            return Ok(Frame::Integer(0));
+        }
+        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+        if when <= mstime() {
+            db.delete(&key_obj);
+            notify_keyspace_event(NOTIFY_GENERIC, "del", &key, db.id);
+        } else {
+            db.set_expire(&key, when);
+            notify_keyspace_event(NOTIFY_GENERIC, "expire", &key, db.id);
+        }
+        Ok(Frame::Integer(1))
+    }
+
+    /// Remaining TTL in `unit` milliseconds, -2 if the key does not exist and -1 if it
+    /// has no TTL
+    fn ttl_generic(db: &mut RedisDb, key: &str, unit: i64) -> i64 {
+        let key_obj = RedisObject::create_string_object(key.to_string());
+        if db.lookup_read(&key_obj).is_none() {
+            return -2;
+        }
+        match db.get_expire(key) {
+            Some(when) => {
+                let ttl = (when - mstime()).max(0);
+                (ttl + unit / 2) / unit
+            }
+            None => -1,
+        }
+    }
+}
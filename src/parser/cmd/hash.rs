@@ -5,10 +5,10 @@ use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
 use crate::parser::cmd::error::CommandError;
 
 use crate::db::db::RedisDb;
-use crate::db::object::{OBJ_ENCODING_HT, OBJ_ENCODING_ZIPLIST, RedisObject, RedisValue, ListObject};
-use crate::db::data_structure::ziplist::lib::{Content};
+use crate::db::object::{OBJ_ENCODING_HT, OBJ_ENCODING_LISTPACK, RedisObject, RedisValue, hash_max_listpack_entries, hash_max_listpack_value};
 use crate::parser::frame::Frame;
-use crate::server::{REDIS_CONFIG, REDIS_SERVER};
+use crate::server::notify::{notify_keyspace_event, NOTIFY_HASH};
+use crate::server::REDIS_SERVER;
 
 #[derive(Debug)]
 pub enum HashCmd {
@@ -75,7 +75,7 @@ impl CommandStrategy for HashCmd {
         match self {
             HashCmd::HGet { key, field } => {
                 let key = RedisObject::create_string_object(key);
-                let value = db.find(&key);
+                let value = db.lookup_read(&key);
                 if let Some(val) = value {
                     let val = Self::hash_get(val, &field);
                     if let Some(val) = val {
@@ -88,25 +88,28 @@ impl CommandStrategy for HashCmd {
                 }
             }
             HashCmd::HDel { key, field } => {
-                let key = RedisObject::create_string_object(key);
-                let mut value = db.find(&key);
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let value = db.find(&key_obj);
                 if let Some(val) = value {
-                    Self::hash_delete(val, &field);
+                    if Self::hash_delete(val, &field) {
+                        notify_keyspace_event(NOTIFY_HASH, "hdel", &key, db.id);
+                    }
                     Ok(Frame::Simple("ok".to_string()))
                 } else {
                     Ok(Frame::Null)
                 }
             }
             HashCmd::HSet { key, field, value } => {
-                let key = RedisObject::create_string_object(key);
-                let val = db.find(&key);
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let val = db.find(&key_obj);
                 if let Some(v) = val {
                     Self::hash_set(v, field, value);
                 } else {
                     let mut ht = RedisObject::create_hash_object();
                     Self::hash_set(&mut ht, field, value);
-                    db.add(key, ht);
+                    db.add(key_obj, ht);
                 }
+                notify_keyspace_event(NOTIFY_HASH, "hset", &key, db.id);
                 Ok(Frame::Simple("OK".to_string()))
             }
             HashCmd::HScan => todo!()
@@ -130,26 +133,21 @@ impl HashCmd {
                     ht.add_raw(field, Value::Sds(value)).ok();
                 }
             }
-        } else if o.encoding == OBJ_ENCODING_ZIPLIST {
-            let zp = match &mut o.ptr {
-                RedisValue::List(list) => {
-                    match list {
-                        ListObject::ZipList(zp) => zp,
-                        _ => return,
-                    }
-                }
+        } else if o.encoding == OBJ_ENCODING_LISTPACK {
+            let lp = match &mut o.ptr {
+                RedisValue::ListPack(lp) => lp,
                 _ => return,
             };
-            let pos = zp.zip_index(0);
-            if let Ok(pos) = zp.find(&field, pos) {
-                let next = zp.next_entry_position(pos);
-                let _ = zp.replace(next, &value);
+            let pos = lp.first().and_then(|first| lp.find(first, field.as_bytes(), 1));
+            if let Some(pos) = pos {
+                let next = lp.next(pos).unwrap();
+                lp.replace(next, value.as_bytes());
             } else {
-                let _ = zp.push(&field, false);
-                let _ = zp.push(&value, false);
+                lp.append(field.as_bytes());
+                lp.append(value.as_bytes());
             }
-            let len = zp.entry_num() as usize;
-            if len > REDIS_CONFIG.get().unwrap().hash_max_ziplist_entries {
+            let len = lp.len() / 2;
+            if len > hash_max_listpack_entries() {
                 hash_type_convert(o);
             }
         }
@@ -173,90 +171,71 @@ impl HashCmd {
             } else {
                 None
             }
-        } else if o.encoding == OBJ_ENCODING_ZIPLIST {
-            let zp = match &mut o.ptr {
-                RedisValue::List(list) => {
-                    match list {
-                        ListObject::ZipList(zp) => zp,
-                        _ => return None,
-                    }
-                }
+        } else if o.encoding == OBJ_ENCODING_LISTPACK {
+            let lp = match &o.ptr {
+                RedisValue::ListPack(lp) => lp,
                 _ => return None,
             };
-            let pos = zp.zip_index(0);
-            if let Ok(pos) = zp.find(field, pos) {
-                let next = zp.next_entry_position(pos);
-                return if let Some(entry) = zp.zip_get_entry(next) {
-                    match entry {
-                        Content::Char(s) => Some(s.clone()),
-                        Content::Integer(v) => Some(v.to_string())
-                    }
-                } else {
-                    None
-                }
-            }
-            None
+            let pos = lp.find(lp.first()?, field.as_bytes(), 1)?;
+            Some(lp.get(lp.next(pos)?).to_string())
         } else {
-            todo!()
+            None
         }
     }
 
     fn hash_delete(o: &mut RedisObject, field: &str) -> bool {
-        let deleted = false;
-        if o.encoding == OBJ_ENCODING_HT {
+        let deleted = if o.encoding == OBJ_ENCODING_HT {
+            match &mut o.ptr {
+                RedisValue::Hash(ht) => matches!(ht.generic_delete(field), Ok(Some(_))),
+                _ => false,
+            }
+        } else if o.encoding == OBJ_ENCODING_LISTPACK {
             match &mut o.ptr {
-                RedisValue::Hash(ht) => {
-                    ht.generic_delete(field).ok();
-                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty(); }
-                    true
+                RedisValue::ListPack(lp) => {
+                    match lp.first().and_then(|first| lp.find(first, field.as_bytes(), 1)) {
+                        Some(pos) => {
+                            lp.delete_range_with_entry(pos, 2);
+                            true
+                        }
+                        None => false,
+                    }
                 }
-                _ => deleted,
+                _ => false,
             }
         } else {
-            deleted
+            false
+        };
+        if deleted {
+            unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty(); }
         }
+        deleted
     }
 }
 
-fn hash_type_try_conversion(o: &mut RedisObject, field: &String, value: &String) {
-    if o.encoding == OBJ_ENCODING_ZIPLIST {
-        unsafe {
-            if field.len() > REDIS_CONFIG.get().unwrap().hash_max_ziplist_value || value.len() > REDIS_CONFIG.get().unwrap().hash_max_ziplist_value {
-                hash_type_convert(o);
-            }
+fn hash_type_try_conversion(o: &mut RedisObject, field: &str, value: &str) {
+    if o.encoding == OBJ_ENCODING_LISTPACK {
+        let max_value = hash_max_listpack_value();
+        if field.len() > max_value || value.len() > max_value {
+            hash_type_convert(o);
         }
     }
 }
 
 fn hash_type_convert(o: &mut RedisObject) {
-    if o.encoding != OBJ_ENCODING_ZIPLIST {
+    if o.encoding != OBJ_ENCODING_LISTPACK {
         return;
     }
-    match &mut o.ptr {
-        RedisValue::List(list) => {
-            match list {
-                ListObject::ZipList(zp) => {
-                    let mut iter = zp.hash_iter();
-                    let mut dict = Dict::create();
-                    while let Some(_) = iter.next() {
-                        let field = match zp.zip_get_entry(iter.field_pos).unwrap() {
-                            Content::Char(s) => s,
-                            Content::Integer(v) => v.to_string(),
-                        };
-                        let value = match zp.zip_get_entry(iter.value_pos).unwrap() {
-                            Content::Char(s) => s,
-                            Content::Integer(v) => v.to_string(),
-                        };
-                        if let Err(e) = dict.add_raw(field, Value::Sds(value)) {
-                            println!("Err: {e}");
-                        }
-                    }
-                    info!("ZipList convert to Dict");
-                    o.encoding = OBJ_ENCODING_HT;
-                    o.ptr = RedisValue::Hash(dict);
+    match &o.ptr {
+        RedisValue::ListPack(lp) => {
+            let mut dict = Dict::create();
+            for (field, value) in lp.pairs() {
+                if let Err(e) = dict.add_raw(field.to_string(), Value::Sds(value.to_string())) {
+                    println!("Err: {e}");
                 }
-                _ => return,
             }
+            info!("ListPack convert to Dict");
+            o.encoding = OBJ_ENCODING_HT;
+            o.ptr = RedisValue::Hash(dict);
         }
         _ => panic!("Error type"),
     }
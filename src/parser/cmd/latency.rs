@@ -0,0 +1,216 @@
+use bytes::Bytes;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::object::help_frame;
+use crate::parser::frame::Frame;
+use crate::server::latency::{latency_doctor, latency_event, latency_events, latency_graph, latency_reset};
+use crate::server::stats::command_stats;
+
+const LATENCY_HELP: &[&str] = &[
+    "LATENCY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "DOCTOR",
+    "    Return a human readable latency analysis report.",
+    "GRAPH <event>",
+    "    Return an ASCII latency graph for the <event> class.",
+    "HISTORY <event>",
+    "    Return time-latency samples for the <event> class.",
+    "LATEST",
+    "    Return the latest latency samples for all events.",
+    "RESET [<event> ...]",
+    "    Reset latency data of one or more <event> classes.",
+    "    (default: reset all data for all event classes)",
+    "HISTOGRAM [COMMAND ...]",
+    "    Return a cumulative distribution of latencies in the format of a histogram for the specified command names.",
+    "    If no commands are specified then all histograms are replied.",
+    "HELP",
+    "    Print this help.",
+];
+
+#[derive(Debug, Clone)]
+pub enum LatencyCmd {
+    /// Returns a report with advice on the sampled events
+    Doctor,
+    /// Returns an ASCII chart of the samples of an event
+    Graph { event: String },
+    /// Returns the samples of an event
+    History { event: String },
+    /// Returns the latest sample of every event
+    Latest,
+    /// Removes the samples of the events, or of all of them
+    Reset { events: Vec<String> },
+    /// Returns the latency distribution of the commands, or of all of them
+    Histogram { commands: Vec<String> },
+    Help,
+}
+
+impl LatencyCmd {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let args = match self {
+            LatencyCmd::Doctor => vec!["doctor".to_string()],
+            LatencyCmd::Graph { event } => vec!["graph".to_string(), event],
+            LatencyCmd::History { event } => vec!["history".to_string(), event],
+            LatencyCmd::Latest => vec!["latest".to_string()],
+            LatencyCmd::Reset { events } => [vec!["reset".to_string()], events].concat(),
+            LatencyCmd::Histogram { commands } => [vec!["histogram".to_string()], commands].concat(),
+            LatencyCmd::Help => vec!["help".to_string()],
+        };
+        frame.push_bulk(Bytes::from("latency".as_bytes()));
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    pub fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        if name != "latency" {
+            return Err(CommandError::ParseError(-13).into());
+        }
+        let sub = frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr("latency".to_string()))?.to_string().to_lowercase();
+        let event = || -> crate::Result<String> {
+            Ok(frame.get_frame_by_index(2).ok_or(CommandError::ArgsErr(format!("latency {}", sub)))?.to_string())
+        };
+        let rest = || -> Vec<String> {
+            let Frame::Array(args) = &frame else { return vec![] };
+            args.iter().skip(2).map(|arg| arg.to_string()).collect()
+        };
+        let cmd = match &sub[..] {
+            "doctor" => LatencyCmd::Doctor,
+            "graph" => LatencyCmd::Graph { event: event()? },
+            "history" => LatencyCmd::History { event: event()? },
+            "latest" => LatencyCmd::Latest,
+            "reset" => LatencyCmd::Reset { events: rest() },
+            "histogram" => LatencyCmd::Histogram { commands: rest() },
+            "help" => LatencyCmd::Help,
+            _ => return Err(CommandError::NotSupport(format!("latency {}", sub)).into()),
+        };
+        Ok(RedisCommand::Latency(cmd))
+    }
+
+    /// The latency monitor is server-wide, so the connection answers it rather than a DB
+    pub fn apply(&self) -> crate::Result<Frame> {
+        match self {
+            LatencyCmd::Doctor => Ok(Frame::Bulk(Bytes::from(latency_doctor().into_bytes()))),
+            LatencyCmd::Graph { event } => {
+                let ts = latency_event(event)
+                    .ok_or_else(|| format!("ERR No samples available for event '{}'", event))?;
+                Ok(Frame::Bulk(Bytes::from(latency_graph(event, &ts).into_bytes())))
+            }
+            LatencyCmd::History { event } => {
+                let samples = latency_event(event).map(|ts| ts.history()).unwrap_or_default();
+                let samples = samples.into_iter().map(|s| {
+                    Frame::Array(vec![Frame::Integer(s.time), Frame::Integer(s.latency as i64)])
+                });
+                Ok(Frame::Array(samples.collect()))
+            }
+            LatencyCmd::Latest => {
+                let events = latency_events().into_iter().filter_map(|(name, ts)| {
+                    let latest = ts.latest()?;
+                    Some(Frame::Array(vec![
+                        Frame::Bulk(Bytes::from(name.into_bytes())),
+                        Frame::Integer(latest.time),
+                        Frame::Integer(latest.latency as i64),
+                        Frame::Integer(ts.max() as i64),
+                    ]))
+                });
+                Ok(Frame::Array(events.collect()))
+            }
+            LatencyCmd::Reset { events } => Ok(Frame::Integer(latency_reset(events) as i64)),
+            LatencyCmd::Histogram { commands } => {
+                let commands: Vec<String> = commands.iter().map(|cmd| cmd.to_lowercase()).collect();
+                let mut reply = vec![];
+                for (name, stats) in command_stats() {
+                    if !commands.is_empty() && !commands.contains(&name) {
+                        continue;
+                    }
+                    // cumulative counts of calls by power of two buckets, in microseconds
+                    let mut buckets = vec![];
+                    let mut cumulative = 0;
+                    for v in stats.latency.iter_log(1, 2.0) {
+                        if v.count_since_last_iteration() == 0 {
+                            continue;
+                        }
+                        cumulative += v.count_since_last_iteration();
+                        buckets.push(Frame::Integer(v.value_iterated_to() as i64));
+                        buckets.push(Frame::Integer(cumulative as i64));
+                    }
+                    reply.push(Frame::Bulk(Bytes::from(name.into_bytes())));
+                    reply.push(Frame::Array(vec![
+                        Frame::Bulk(Bytes::from("calls".as_bytes())),
+                        Frame::Integer(stats.calls as i64),
+                        Frame::Bulk(Bytes::from("histogram_usec".as_bytes())),
+                        Frame::Array(buckets),
+                    ]));
+                }
+                Ok(Frame::Array(reply))
+            }
+            LatencyCmd::Help => Ok(help_frame(LATENCY_HELP)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::parser::cmd::latency::LatencyCmd;
+    use crate::parser::frame::Frame;
+    use crate::server::latency::{latency_add_sample_if_needed, set_latency_monitor_threshold};
+    use crate::server::stats::record_command;
+
+    #[test]
+    fn monitors_events_over_the_threshold() -> crate::Result<()> {
+        let event = "test-event";
+        // the monitor is off by default
+        latency_add_sample_if_needed(event, Duration::from_millis(500));
+        set_latency_monitor_threshold(100);
+        latency_add_sample_if_needed(event, Duration::from_millis(50));
+        assert!(LatencyCmd::Graph { event: event.to_string() }.apply().is_err());
+
+        latency_add_sample_if_needed(event, Duration::from_millis(200));
+        latency_add_sample_if_needed(event, Duration::from_millis(300));
+        let latest = match LatencyCmd::Latest.apply()? {
+            Frame::Array(events) => events.into_iter().find(|e| e.get_frame_by_index(0).is_some_and(|name| *name == event)),
+            frame => panic!("unexpected LATENCY LATEST reply {:?}", frame),
+        };
+        match latest {
+            Some(Frame::Array(latest)) => assert!(matches!(latest[..], [_, _, Frame::Integer(300), Frame::Integer(300)])),
+            latest => panic!("unexpected LATENCY LATEST entry {:?}", latest),
+        }
+        // both samples are from the same second, only the worst is kept
+        match (LatencyCmd::History { event: event.to_string() }).apply()? {
+            Frame::Array(samples) => assert_eq!(samples.len(), 1),
+            frame => panic!("unexpected LATENCY HISTORY reply {:?}", frame),
+        }
+        assert!(LatencyCmd::Graph { event: event.to_string() }.apply()?.to_string().contains(event));
+        assert!(LatencyCmd::Doctor.apply()?.to_string().contains(event));
+
+        assert!(matches!((LatencyCmd::Reset { events: vec![event.to_string()] }).apply()?, Frame::Integer(1)));
+        match (LatencyCmd::History { event: event.to_string() }).apply()? {
+            Frame::Array(samples) => assert!(samples.is_empty()),
+            frame => panic!("unexpected LATENCY HISTORY reply {:?}", frame),
+        }
+        set_latency_monitor_threshold(0);
+        Ok(())
+    }
+
+    #[test]
+    fn histogram_counts_calls() -> crate::Result<()> {
+        for _ in 0..3 {
+            record_command("latency-test", Instant::now(), &Frame::Null);
+        }
+        match (LatencyCmd::Histogram { commands: vec!["LATENCY-TEST".to_string()] }).apply()? {
+            Frame::Array(reply) => match &reply[..] {
+                [name, Frame::Array(stats)] => {
+                    assert_eq!(*name, "latency-test");
+                    assert!(matches!(stats[1], Frame::Integer(3)));
+                    // the counts are cumulative, the last bucket holds every call
+                    assert!(matches!(&stats[3], Frame::Array(buckets) if matches!(buckets.last(), Some(Frame::Integer(3)))));
+                }
+                reply => panic!("unexpected LATENCY HISTOGRAM reply {:?}", reply),
+            },
+            frame => panic!("unexpected LATENCY HISTOGRAM reply {:?}", frame),
+        }
+        Ok(())
+    }
+}
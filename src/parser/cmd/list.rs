@@ -0,0 +1,532 @@
+use bytes::Bytes;
+use crate::db::blocked::BlockOp;
+use crate::db::data_structure::adlist::adlist::LinkList;
+use crate::db::db::RedisDb;
+use crate::db::object::{ListObject, RedisObject, RedisValue, OBJ_LIST};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::error::CommandError::ObjectTypeError;
+use crate::parser::frame::Frame;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_GENERIC, NOTIFY_LIST};
+use crate::server::REDIS_SERVER;
+
+/// The end of a list an element is popped from or pushed to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    pub fn parse(s: &str) -> crate::Result<ListEnd> {
+        match &s.to_lowercase()[..] {
+            "left" => Ok(ListEnd::Left),
+            "right" => Ok(ListEnd::Right),
+            _ => Err(CommandError::ArgsErr(s.to_string()).into()),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListEnd::Left => "left",
+            ListEnd::Right => "right",
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum ListCmd {
+    /// Prepends one or more elements to a list. Creates the key if it doesn't exist
+    LPush { key: String, values: Vec<String> },
+    /// Appends one or more elements to a list. Creates the key if it doesn't exist
+    RPush { key: String, values: Vec<String> },
+    /// Returns the first elements in a list after removing it. Deletes the list if the last element was popped
+    LPop { key: String, count: Option<usize> },
+    /// Returns and removes the last elements of a list. Deletes the list if the last element was popped
+    RPop { key: String, count: Option<usize> },
+    /// Sets the value of an element in a list by its index
+    LSet,
+    /// Inserts an element before or after another element in a list
+    LInsert,
+    /// Returns the length of a list
+    LLen { key: String },
+    /// Returns a range of elements from a list
+    LRange { key: String, start: i64, stop: i64 },
+    /// Returns an element after popping it from one list and pushing it to another
+    LMove { source: String, destination: String, from: ListEnd, to: ListEnd },
+    /// Returns multiple elements from the first non-empty list after removing them
+    LMPop { keys: Vec<String>, end: ListEnd, count: usize },
+    /// Removes and returns the first element in a list. Blocks until an element is available otherwise
+    BLPop { keys: Vec<String>, timeout: f64 },
+    /// Removes and returns the last element in a list. Blocks until an element is available otherwise
+    BRPop { keys: Vec<String>, timeout: f64 },
+    /// Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise
+    BLMove { source: String, destination: String, from: ListEnd, to: ListEnd, timeout: f64 },
+    /// Pops the first elements from one of multiple lists. Blocks until an element is available otherwise
+    BLMPop { keys: Vec<String>, end: ListEnd, count: usize, timeout: f64 },
+}
+
+impl CommandStrategy for ListCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        match self {
+            ListCmd::LPush { key, values } => {
+                frame.push_bulk(Bytes::from("lpush".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                for value in values {
+                    frame.push_bulk(Bytes::from(value.into_bytes()));
+                }
+            }
+            ListCmd::RPush { key, values } => {
+                frame.push_bulk(Bytes::from("rpush".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                for value in values {
+                    frame.push_bulk(Bytes::from(value.into_bytes()));
+                }
+            }
+            ListCmd::LPop { key, count } => {
+                frame.push_bulk(Bytes::from("lpop".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                if let Some(count) = count {
+                    frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+                }
+            }
+            ListCmd::RPop { key, count } => {
+                frame.push_bulk(Bytes::from("rpop".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                if let Some(count) = count {
+                    frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+                }
+            }
+            ListCmd::LLen { key } => {
+                frame.push_bulk(Bytes::from("llen".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+            }
+            ListCmd::LRange { key, start, stop } => {
+                frame.push_bulk(Bytes::from("lrange".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                frame.push_bulk(Bytes::from(start.to_string().into_bytes()));
+                frame.push_bulk(Bytes::from(stop.to_string().into_bytes()));
+            }
+            ListCmd::LMove { source, destination, from, to } => {
+                frame.push_bulk(Bytes::from("lmove".as_bytes()));
+                frame.push_bulk(Bytes::from(source.into_bytes()));
+                frame.push_bulk(Bytes::from(destination.into_bytes()));
+                frame.push_bulk(Bytes::from(from.as_str().as_bytes()));
+                frame.push_bulk(Bytes::from(to.as_str().as_bytes()));
+            }
+            ListCmd::LMPop { keys, end, count } => {
+                frame.push_bulk(Bytes::from("lmpop".as_bytes()));
+                frame.push_bulk(Bytes::from(keys.len().to_string().into_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(end.as_str().as_bytes()));
+                frame.push_bulk(Bytes::from("count".as_bytes()));
+                frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+            }
+            ListCmd::BLPop { keys, timeout } => {
+                frame.push_bulk(Bytes::from("blpop".as_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+            }
+            ListCmd::BRPop { keys, timeout } => {
+                frame.push_bulk(Bytes::from("brpop".as_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+            }
+            ListCmd::BLMove { source, destination, from, to, timeout } => {
+                frame.push_bulk(Bytes::from("blmove".as_bytes()));
+                frame.push_bulk(Bytes::from(source.into_bytes()));
+                frame.push_bulk(Bytes::from(destination.into_bytes()));
+                frame.push_bulk(Bytes::from(from.as_str().as_bytes()));
+                frame.push_bulk(Bytes::from(to.as_str().as_bytes()));
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+            }
+            ListCmd::BLMPop { keys, end, count, timeout } => {
+                frame.push_bulk(Bytes::from("blmpop".as_bytes()));
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+                frame.push_bulk(Bytes::from(keys.len().to_string().into_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(end.as_str().as_bytes()));
+                frame.push_bulk(Bytes::from("count".as_bytes()));
+                frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+            }
+            _ => return Frame::Null,
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let len = frame.get_len();
+        let arg = |i: usize| -> crate::Result<String> {
+            Ok(frame.get_frame_by_index(i).ok_or(format!("command error '{}'", name))?.to_string())
+        };
+        let cmd = match name {
+            "lpush" | "rpush" => {
+                let key = arg(1)?;
+                if len < 3 {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                let values = (2..len).map(arg).collect::<crate::Result<Vec<_>>>()?;
+                if name == "lpush" {
+                    ListCmd::LPush { key, values }
+                } else {
+                    ListCmd::RPush { key, values }
+                }
+            }
+            "lpop" | "rpop" => {
+                let key = arg(1)?;
+                let count = if len > 2 { Some(arg(2)?.parse()?) } else { None };
+                if name == "lpop" {
+                    ListCmd::LPop { key, count }
+                } else {
+                    ListCmd::RPop { key, count }
+                }
+            }
+            "llen" => ListCmd::LLen { key: arg(1)? },
+            "lrange" => {
+                let key = arg(1)?;
+                let start = arg(2)?.parse()?;
+                let stop = arg(3)?.parse()?;
+                ListCmd::LRange { key, start, stop }
+            }
+            "lmove" => {
+                let source = arg(1)?;
+                let destination = arg(2)?;
+                let from = ListEnd::parse(&arg(3)?)?;
+                let to = ListEnd::parse(&arg(4)?)?;
+                ListCmd::LMove { source, destination, from, to }
+            }
+            "lmpop" => {
+                let (keys, end, count) = parse_mpop_args(name, &frame, 1)?;
+                ListCmd::LMPop { keys, end, count }
+            }
+            "blpop" | "brpop" => {
+                if len < 3 {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                let keys = (1..len - 1).map(arg).collect::<crate::Result<Vec<_>>>()?;
+                let timeout = parse_timeout(&arg(len - 1)?)?;
+                if name == "blpop" {
+                    ListCmd::BLPop { keys, timeout }
+                } else {
+                    ListCmd::BRPop { keys, timeout }
+                }
+            }
+            "blmove" => {
+                let source = arg(1)?;
+                let destination = arg(2)?;
+                let from = ListEnd::parse(&arg(3)?)?;
+                let to = ListEnd::parse(&arg(4)?)?;
+                let timeout = parse_timeout(&arg(5)?)?;
+                ListCmd::BLMove { source, destination, from, to, timeout }
+            }
+            "blmpop" => {
+                let timeout = parse_timeout(&arg(1)?)?;
+                let (keys, end, count) = parse_mpop_args(name, &frame, 2)?;
+                ListCmd::BLMPop { keys, end, count, timeout }
+            }
+            _ => return Err(CommandError::ParseError(-5).into()),
+        };
+        Ok(RedisCommand::List(cmd))
+    }
+
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            ListCmd::LPush { key, values } => {
+                let len = Self::push_generic(db, &key, values, ListEnd::Left)?;
+                Ok(Frame::Integer(len as i64))
+            }
+            ListCmd::RPush { key, values } => {
+                let len = Self::push_generic(db, &key, values, ListEnd::Right)?;
+                Ok(Frame::Integer(len as i64))
+            }
+            ListCmd::LPop { key, count } => Self::pop_command(db, &key, ListEnd::Left, count),
+            ListCmd::RPop { key, count } => Self::pop_command(db, &key, ListEnd::Right, count),
+            ListCmd::LLen { key } => {
+                let key = RedisObject::create_string_object(key);
+                match db.lookup_read(&key) {
+                    Some(o) => Ok(Frame::Integer(Self::list_len(o)? as i64)),
+                    None => Ok(Frame::Integer(0)),
+                }
+            }
+            ListCmd::LRange { key, start, stop } => {
+                let key = RedisObject::create_string_object(key);
+                let o = match db.lookup_read(&key) {
+                    Some(o) => o,
+                    None => return Ok(Frame::Array(vec![])),
+                };
+                let list = Self::list_ref(o)?;
+                let len = list.length() as i64;
+                let start = if start < 0 { (len + start).max(0) } else { start };
+                let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+                let mut frame = Frame::array();
+                if start <= stop {
+                    for value in list.iter().skip(start as usize).take((stop - start + 1) as usize) {
+                        frame.push_bulk(Bytes::from(value.clone().into_bytes()));
+                    }
+                }
+                Ok(frame)
+            }
+            ListCmd::LMove { source, destination, from, to } => {
+                let op = BlockOp::ListMove { destination, from, to };
+                Ok(op.serve(db, &source).unwrap_or(Ok(Frame::Null))?)
+            }
+            ListCmd::LMPop { keys, end, count } => {
+                let op = BlockOp::ListMPop { end, count };
+                Self::serve_first(db, &keys, op)
+            }
+            // Outside of the DB blocking path (see `RedisDb::block_for_keys`) the blocking
+            // variants behave like their non-blocking counterparts and never wait.
+            ListCmd::BLPop { keys, .. } => Self::serve_first(db, &keys, BlockOp::ListPop { end: ListEnd::Left }),
+            ListCmd::BRPop { keys, .. } => Self::serve_first(db, &keys, BlockOp::ListPop { end: ListEnd::Right }),
+            ListCmd::BLMove { source, destination, from, to, .. } => {
+                let op = BlockOp::ListMove { destination, from, to };
+                Self::serve_first(db, &[source], op)
+            }
+            ListCmd::BLMPop { keys, end, count, .. } => Self::serve_first(db, &keys, BlockOp::ListMPop { end, count }),
+            _ => Err(CommandError::NotSupport(format!("{:?}", self)).into()),
+        }
+    }
+}
+
+impl ListCmd {
+    fn list_ref(o: &RedisObject) -> crate::Result<&LinkList<String>> {
+        if o.object_type != OBJ_LIST {
+            return Err(ObjectTypeError(-5).into());
+        }
+        match &o.ptr {
+            RedisValue::List(ListObject::LinkList(list)) => Ok(list),
+            _ => Err(ObjectTypeError(-5).into()),
+        }
+    }
+
+    pub fn list_len(o: &RedisObject) -> crate::Result<usize> {
+        Ok(Self::list_ref(o)?.length())
+    }
+
+    pub fn list_push(o: &mut RedisObject, value: String, end: ListEnd) -> crate::Result<()> {
+        if o.object_type != OBJ_LIST {
+            return Err(ObjectTypeError(-6).into());
+        }
+        match &mut o.ptr {
+            RedisValue::List(ListObject::LinkList(list)) => {
+                match end {
+                    ListEnd::Left => list.add_node_head(value),
+                    ListEnd::Right => list.add_node_tail(value),
+                }
+                Ok(())
+            }
+            _ => Err(ObjectTypeError(-6).into()),
+        }
+    }
+
+    pub fn list_pop(o: &mut RedisObject, end: ListEnd) -> crate::Result<Option<String>> {
+        if o.object_type != OBJ_LIST {
+            return Err(ObjectTypeError(-7).into());
+        }
+        match &mut o.ptr {
+            RedisValue::List(ListObject::LinkList(list)) => match end {
+                ListEnd::Left => Ok(list.pop_head()),
+                ListEnd::Right => Ok(list.pop_tail()),
+            },
+            _ => Err(ObjectTypeError(-7).into()),
+        }
+    }
+
+    /// Pushes `values` to the list at `key`, creating it if needed, and wakes up
+    /// clients blocked on the key. Returns the length of the list after the push.
+    pub fn push_generic(db: &mut RedisDb, key: &str, values: Vec<String>, end: ListEnd) -> crate::Result<usize> {
+        let key_obj = RedisObject::create_string_object(key.to_string());
+        let len = if let Some(o) = db.find(&key_obj) {
+            Self::list_len(o)?;
+            for value in values {
+                Self::list_push(o, value, end)?;
+            }
+            Self::list_len(o)?
+        } else {
+            let mut o = RedisObject::create_list_object();
+            for value in values {
+                Self::list_push(&mut o, value, end)?;
+            }
+            let len = Self::list_len(&o)?;
+            db.add(key_obj, o);
+            len
+        };
+        db.signal_key_as_ready(key);
+        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty(); }
+        let event = match end {
+            ListEnd::Left => "lpush",
+            ListEnd::Right => "rpush",
+        };
+        notify_keyspace_event(NOTIFY_LIST, event, key, db.id);
+        Ok(len)
+    }
+
+    /// Pops up to `count` elements from the list at `key` and deletes the key once it
+    /// is empty. Returns `None` when the key does not exist.
+    pub fn pop_generic(db: &mut RedisDb, key: &str, end: ListEnd, count: usize) -> crate::Result<Option<Vec<String>>> {
+        let key_obj = RedisObject::create_string_object(key.to_string());
+        let (values, empty) = match db.find(&key_obj) {
+            Some(o) => {
+                let mut values = Vec::with_capacity(count);
+                while values.len() < count {
+                    match Self::list_pop(o, end)? {
+                        Some(value) => values.push(value),
+                        None => break,
+                    }
+                }
+                (values, Self::list_len(o)? == 0)
+            }
+            None => return Ok(None),
+        };
+        if values.is_empty() {
+            return Ok(None);
+        }
+        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty(); }
+        let event = match end {
+            ListEnd::Left => "lpop",
+            ListEnd::Right => "rpop",
+        };
+        notify_keyspace_event(NOTIFY_LIST, event, key, db.id);
+        if empty {
+            db.delete(&key_obj);
+            notify_keyspace_event(NOTIFY_GENERIC, "del", key, db.id);
+        }
+        Ok(Some(values))
+    }
+
+    /// Atomically pops an element from `source` and pushes it to `destination`.
+    /// Returns `None` when `source` does not exist.
+    pub fn move_generic(db: &mut RedisDb, source: &str, destination: &str, from: ListEnd, to: ListEnd) -> crate::Result<Option<String>> {
+        // check the destination type first so a failed move never loses the element
+        let dst_obj = RedisObject::create_string_object(destination.to_string());
+        if let Some(o) = db.find(&dst_obj) {
+            Self::list_len(o)?;
+        }
+        let value = match Self::pop_generic(db, source, from, 1)? {
+            Some(mut values) => values.remove(0),
+            None => return Ok(None),
+        };
+        Self::push_generic(db, destination, vec![value.clone()], to)?;
+        Ok(Some(value))
+    }
+
+    fn pop_command(db: &mut RedisDb, key: &str, end: ListEnd, count: Option<usize>) -> crate::Result<Frame> {
+        let values = Self::pop_generic(db, key, end, count.unwrap_or(1))?;
+        match (values, count) {
+            (None, _) => Ok(Frame::Null),
+            (Some(mut values), None) => Ok(Frame::Bulk(Bytes::from(values.remove(0).into_bytes()))),
+            (Some(values), Some(_)) => {
+                let mut frame = Frame::array();
+                for value in values {
+                    frame.push_bulk(Bytes::from(value.into_bytes()));
+                }
+                Ok(frame)
+            }
+        }
+    }
+
+    fn serve_first(db: &mut RedisDb, keys: &[String], op: BlockOp) -> crate::Result<Frame> {
+        for key in keys {
+            if let Some(res) = op.serve(db, key) {
+                return res;
+            }
+        }
+        Ok(Frame::Null)
+    }
+}
+
+/// Parses the blocking timeout argument, given in seconds with an optional fraction.
+pub fn parse_timeout(s: &str) -> crate::Result<f64> {
+    let timeout: f64 = s.parse().map_err(|_| "ERR timeout is not a float or out of range")?;
+    if timeout < 0f64 || !timeout.is_finite() {
+        return Err("ERR timeout is negative".into());
+    }
+    Ok(timeout)
+}
+
+/// Parses the `numkeys key [key ...] LEFT|RIGHT [COUNT count]` tail shared by LMPOP and BLMPOP.
+fn parse_mpop_args(name: &str, frame: &Frame, start: usize) -> crate::Result<(Vec<String>, ListEnd, usize)> {
+    let arg = |i: usize| -> crate::Result<String> {
+        Ok(frame.get_frame_by_index(i).ok_or(format!("command error '{}'", name))?.to_string())
+    };
+    let num_keys: usize = arg(start)?.parse()?;
+    if num_keys == 0 {
+        return Err("ERR numkeys should be greater than 0".into());
+    }
+    let keys = (start + 1..start + 1 + num_keys).map(arg).collect::<crate::Result<Vec<_>>>()?;
+    let end = ListEnd::parse(&arg(start + 1 + num_keys)?)?;
+    let count = parse_count(name, frame, start + 2 + num_keys)?;
+    Ok((keys, end, count))
+}
+
+/// Parses an optional trailing `COUNT count` argument starting at `index`.
+pub fn parse_count(name: &str, frame: &Frame, index: usize) -> crate::Result<usize> {
+    match frame.get_frame_by_index(index) {
+        None => Ok(1),
+        Some(opt) if opt.to_string().eq_ignore_ascii_case("count") => {
+            let count: usize = frame
+                .get_frame_by_index(index + 1)
+                .ok_or(format!("command error '{}'", name))?
+                .to_string()
+                .parse()?;
+            if count == 0 {
+                return Err("ERR count should be greater than 0".into());
+            }
+            Ok(count)
+        }
+        Some(_) => Err(CommandError::ArgsErr(name.to_string()).into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::cmd::command::{get_command_name, CommandStrategy, RedisCommand};
+    use crate::parser::cmd::list::{ListCmd, ListEnd};
+
+    #[test]
+    fn cmd_to_frame() -> crate::Result<()> {
+        let cmd = ListCmd::BLMPop {
+            keys: vec!["queue1".to_string(), "queue2".to_string()],
+            end: ListEnd::Right,
+            count: 2,
+            timeout: 0.5,
+        };
+        let frame = cmd.into_frame();
+        let cmd_type = get_command_name(&frame)?;
+        let cmd = ListCmd::from_frame(&cmd_type, frame)?;
+        match cmd {
+            RedisCommand::List(ListCmd::BLMPop { keys, end, count, timeout }) => {
+                assert_eq!(keys, vec!["queue1".to_string(), "queue2".to_string()]);
+                assert_eq!(end, ListEnd::Right);
+                assert_eq!(count, 2);
+                assert_eq!(timeout, 0.5);
+            }
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn blpop_timeout_is_last_argument() -> crate::Result<()> {
+        let frame = ListCmd::BLPop { keys: vec!["a".to_string(), "b".to_string()], timeout: 1.5 }.into_frame();
+        let cmd = ListCmd::from_frame("blpop", frame)?;
+        match cmd {
+            RedisCommand::List(ListCmd::BLPop { keys, timeout }) => {
+                assert_eq!(keys.len(), 2);
+                assert_eq!(timeout, 1.5);
+            }
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use bytes::Bytes;
+use crate::db::db::RedisDb;
+use crate::db::memory::{MemoryOverhead, OBJ_COMPUTE_SIZE_DEF_SAMPLES};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+use crate::server::server::Handler;
+
+#[derive(Debug, Clone)]
+pub enum MemoryCmd {
+    /// Estimates the memory usage of a key, sampling `samples` elements of aggregates
+    Usage { key: String, samples: usize },
+    /// Returns details about memory usage
+    Stats,
+    /// Outputs a memory problems report
+    Doctor,
+}
+
+impl CommandStrategy for MemoryCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let args = match self {
+            MemoryCmd::Usage { key, samples } => {
+                vec!["memory".to_string(), "usage".to_string(), key, "samples".to_string(), samples.to_string()]
+            }
+            MemoryCmd::Stats => vec!["memory".to_string(), "stats".to_string()],
+            MemoryCmd::Doctor => vec!["memory".to_string(), "doctor".to_string()],
+        };
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        if name != "memory" {
+            return Err(CommandError::ParseError(-9).into());
+        }
+        let sub = frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr("memory".to_string()))?.to_string().to_lowercase();
+        let cmd = match &sub[..] {
+            "usage" => {
+                let key = frame.get_frame_by_index(2).ok_or(CommandError::ArgsErr("memory usage".to_string()))?.to_string();
+                let samples = match frame.get_frame_by_index(3) {
+                    Some(opt) if opt.to_string().eq_ignore_ascii_case("samples") => frame
+                        .get_frame_by_index(4)
+                        .and_then(|n| n.to_string().parse::<usize>().ok())
+                        .ok_or("ERR value is out of range, must be positive")?,
+                    Some(_) => return Err("ERR syntax error".into()),
+                    None => OBJ_COMPUTE_SIZE_DEF_SAMPLES,
+                };
+                MemoryCmd::Usage { key, samples }
+            }
+            "stats" => MemoryCmd::Stats,
+            "doctor" => MemoryCmd::Doctor,
+            _ => return Err(CommandError::NotSupport(format!("memory {}", sub)).into()),
+        };
+        Ok(RedisCommand::Memory(cmd))
+    }
+
+    /// Only MEMORY USAGE looks at a DB, the other subcommands go through `apply_server`.
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            MemoryCmd::Usage { key, samples } => match db.memory_usage(&key, samples) {
+                Some(size) => Ok(Frame::Integer(size as i64)),
+                None => Ok(Frame::Null),
+            },
+            _ => Err(CommandError::ParseError(-9).into()),
+        }
+    }
+}
+
+impl MemoryCmd {
+    /// Whether the command needs the whole server rather than the selected DB.
+    pub fn is_server_wide(&self) -> bool {
+        !matches!(self, MemoryCmd::Usage { .. })
+    }
+
+    pub async fn apply_server(&self, handler: &Handler) -> crate::Result<Frame> {
+        let mh = handler.memory_overhead().await?;
+        match self {
+            MemoryCmd::Stats => Ok(memory_stats_frame(&mh)),
+            MemoryCmd::Doctor => Ok(Frame::Bulk(Bytes::from(mh.doctor_report().into_bytes()))),
+            MemoryCmd::Usage { .. } => Err(CommandError::ParseError(-9).into()),
+        }
+    }
+}
+
+fn memory_stats_frame(mh: &MemoryOverhead) -> Frame {
+    let mut items = vec![];
+    let mut field = |name: &str, value: Frame| {
+        items.push(Frame::Bulk(Bytes::from(name.to_string().into_bytes())));
+        items.push(value);
+    };
+    let int = |v: usize| Frame::Integer(v as i64);
+    let double = |v: f64| Frame::Bulk(Bytes::from(format!("{:.17}", v).into_bytes()));
+
+    field("peak.allocated", int(mh.peak_allocated));
+    field("total.allocated", int(mh.total_allocated));
+    field("startup.allocated", int(mh.startup_allocated));
+    field("overhead.total", int(mh.overhead_total));
+    for db in &mh.dbs {
+        let stats = Frame::Array(vec![
+            Frame::Bulk(Bytes::from("overhead.hashtable.main".as_bytes())),
+            int(db.overhead_hashtable_main),
+            Frame::Bulk(Bytes::from("overhead.hashtable.expires".as_bytes())),
+            int(db.overhead_hashtable_expires),
+        ]);
+        field(&format!("db.{}", db.id), stats);
+    }
+    field("overhead.db.hashtable.lut", int(mh.overhead_db_hashtable_lut));
+    field("overhead.db.hashtable.rehashing", int(mh.overhead_db_hashtable_rehashing));
+    field("keys.count", Frame::Integer(mh.keys as i64));
+    field("keys.bytes-per-key", int(mh.bytes_per_key));
+    field("dataset.bytes", int(mh.dataset));
+    field("dataset.percentage", double(mh.dataset_perc));
+    field("peak.percentage", double(mh.peak_perc));
+    if let (Some(rss), Some(frag)) = (mh.rss, mh.fragmentation()) {
+        field("fragmentation", double(frag));
+        field("fragmentation.bytes", Frame::Integer(rss as i64 - mh.total_allocated as i64));
+    }
+    Frame::Array(items)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::db::RedisDb;
+    use crate::db::kvstore::KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
+    use crate::db::object::RedisObject;
+    use crate::parser::cmd::command::CommandStrategy;
+    use crate::parser::cmd::memory::MemoryCmd;
+    use crate::parser::frame::Frame;
+
+    fn usage(db: &mut RedisDb, key: &str) -> Option<i64> {
+        match (MemoryCmd::Usage { key: key.to_string(), samples: 0 }).apply(db).unwrap() {
+            Frame::Integer(size) => Some(size),
+            Frame::Null => None,
+            frame => panic!("unexpected MEMORY USAGE reply {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn usage_grows_with_the_value() {
+        let mut db = RedisDb::create(0, KVSTORE_ALLOCATE_DICTS_ON_DEMAND, 0);
+        let string = |s: String| RedisObject::create_string_object(s);
+        db.add(string("k1".to_string()), string("v".repeat(10)));
+        db.add(string("k2".to_string()), string("v".repeat(10_000)));
+
+        let small = usage(&mut db, "k1").unwrap();
+        let big = usage(&mut db, "k2").unwrap();
+        // the key and its overhead are counted too
+        assert!(small > 10);
+        assert_eq!(big - small, 9_990);
+        assert_eq!(usage(&mut db, "missing"), None);
+    }
+}
@@ -3,4 +3,18 @@ pub mod hash;
 pub mod error;
 pub mod string;
 pub mod conn;
-pub mod zset;
\ No newline at end of file
+pub mod generic;
+pub mod zset;
+pub mod list;
+pub mod pubsub;
+pub mod server;
+pub mod set;
+pub mod stream;
+pub mod memory;
+pub mod object;
+pub mod debug;
+pub mod slowlog;
+pub mod latency;
+pub mod acl;
+pub mod replication;
+pub mod cluster;
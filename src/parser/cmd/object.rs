@@ -0,0 +1,152 @@
+use bytes::Bytes;
+use crate::db::db::RedisDb;
+use crate::db::evict::{estimate_object_idle_time, lfu_decr_and_return, maxmemory_policy};
+use crate::db::object::{str_encoding, RedisObject};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+
+const OBJECT_HELP: &[&str] = &[
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+#[derive(Debug, Clone)]
+pub enum ObjectCmd {
+    /// Returns the internal encoding of the value stored at a key
+    Encoding { key: String },
+    /// Returns the logarithmic access frequency counter of a key
+    Freq { key: String },
+    /// Returns the time since the last access to a key, in seconds
+    IdleTime { key: String },
+    /// Returns the reference count of the value stored at a key
+    RefCount { key: String },
+    Help,
+}
+
+impl CommandStrategy for ObjectCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let (sub, key) = match self {
+            ObjectCmd::Encoding { key } => ("encoding", Some(key)),
+            ObjectCmd::Freq { key } => ("freq", Some(key)),
+            ObjectCmd::IdleTime { key } => ("idletime", Some(key)),
+            ObjectCmd::RefCount { key } => ("refcount", Some(key)),
+            ObjectCmd::Help => ("help", None),
+        };
+        frame.push_bulk(Bytes::from("object".as_bytes()));
+        frame.push_bulk(Bytes::from(sub.as_bytes()));
+        if let Some(key) = key {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        if name != "object" {
+            return Err(CommandError::ParseError(-10).into());
+        }
+        let sub = frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr("object".to_string()))?.to_string().to_lowercase();
+        let key = || -> crate::Result<String> {
+            Ok(frame.get_frame_by_index(2).ok_or(CommandError::ArgsErr(format!("object {}", sub)))?.to_string())
+        };
+        let cmd = match &sub[..] {
+            "encoding" => ObjectCmd::Encoding { key: key()? },
+            "freq" => ObjectCmd::Freq { key: key()? },
+            "idletime" => ObjectCmd::IdleTime { key: key()? },
+            "refcount" => ObjectCmd::RefCount { key: key()? },
+            "help" => ObjectCmd::Help,
+            _ => return Err(CommandError::NotSupport(format!("object {}", sub)).into()),
+        };
+        Ok(RedisCommand::Object(cmd))
+    }
+
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            ObjectCmd::Encoding { key } => match db.find_no_touch(&RedisObject::create_string_object(key)) {
+                Some(o) => Ok(Frame::Bulk(Bytes::from(str_encoding(o.encoding).as_bytes()))),
+                None => Ok(Frame::Null),
+            },
+            ObjectCmd::Freq { key } => {
+                if !maxmemory_policy().is_lfu() {
+                    return Err("ERR An LFU maxmemory policy is not selected, access frequency not tracked. \
+                        Please note that when switching between policies at runtime LRU and LFU data will \
+                        take some time to adjust.".into());
+                }
+                match db.find_no_touch(&RedisObject::create_string_object(key)) {
+                    Some(o) => Ok(Frame::Integer(lfu_decr_and_return(o) as i64)),
+                    None => Ok(Frame::Null),
+                }
+            }
+            ObjectCmd::IdleTime { key } => {
+                if maxmemory_policy().is_lfu() {
+                    return Err("ERR An LRU maxmemory policy is not selected, access time not tracked. \
+                        Please note that when switching between policies at runtime LRU and LFU data will \
+                        take some time to adjust.".into());
+                }
+                match db.find_no_touch(&RedisObject::create_string_object(key)) {
+                    Some(o) => Ok(Frame::Integer((estimate_object_idle_time(o) / 1000) as i64)),
+                    None => Ok(Frame::Null),
+                }
+            }
+            ObjectCmd::RefCount { key } => match db.find_no_touch(&RedisObject::create_string_object(key)) {
+                Some(o) => Ok(Frame::Integer(o.ref_count() as i64)),
+                None => Ok(Frame::Null),
+            },
+            ObjectCmd::Help => Ok(help_frame(OBJECT_HELP)),
+        }
+    }
+}
+
+/// The reply of a HELP subcommand, one simple string per line
+pub fn help_frame(lines: &[&str]) -> Frame {
+    Frame::Array(lines.iter().map(|line| Frame::Simple(line.to_string())).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::db::RedisDb;
+    use crate::db::kvstore::KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
+    use crate::db::object::RedisObject;
+    use crate::parser::cmd::command::CommandStrategy;
+    use crate::parser::cmd::object::ObjectCmd;
+    use crate::parser::cmd::set::{set_type_add, set_type_create};
+    use crate::parser::frame::Frame;
+
+    #[test]
+    fn reports_the_object_of_a_key() -> crate::Result<()> {
+        let mut db = RedisDb::create(0, KVSTORE_ALLOCATE_DICTS_ON_DEMAND, 0);
+        let key = |key: &str| RedisObject::create_string_object(key.to_string());
+        db.add(key("str"), RedisObject::create_string_object("v".to_string()));
+        db.add(key("hash"), RedisObject::create_hash_object());
+        let mut set = set_type_create("1", 1);
+        set_type_add(&mut set, "1");
+        db.add(key("set"), set);
+
+        let encoding = |db: &mut RedisDb, key: &str| (ObjectCmd::Encoding { key: key.to_string() }).apply(db).unwrap().to_string();
+        assert_eq!(encoding(&mut db, "str"), "raw");
+        assert_eq!(encoding(&mut db, "hash"), "listpack");
+        assert_eq!(encoding(&mut db, "set"), "intset");
+        assert!(matches!((ObjectCmd::Encoding { key: "missing".to_string() }).apply(&mut db)?, Frame::Null));
+
+        assert!(matches!((ObjectCmd::RefCount { key: "str".to_string() }).apply(&mut db)?, Frame::Integer(1)));
+        // just created, and OBJECT itself does not touch the key
+        assert!(matches!((ObjectCmd::IdleTime { key: "str".to_string() }).apply(&mut db)?, Frame::Integer(0)));
+        // FREQ needs an LFU policy
+        assert!((ObjectCmd::Freq { key: "str".to_string() }).apply(&mut db).is_err());
+        Ok(())
+    }
+}
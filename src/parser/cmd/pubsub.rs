@@ -0,0 +1,184 @@
+use bytes::Bytes;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+use crate::server::pubsub::SubscriptionKind;
+use crate::server::server::Handler;
+
+#[derive(Debug, Clone)]
+pub enum PubSubCmd {
+    /// Listens for messages published to channels
+    Subscribe { channels: Vec<String> },
+    /// Stops listening to messages posted to channels, all of them when empty
+    Unsubscribe { channels: Vec<String> },
+    /// Listens for messages published to channels that match one or more patterns
+    PSubscribe { patterns: Vec<String> },
+    /// Stops listening to messages published to channels that match patterns
+    PUnsubscribe { patterns: Vec<String> },
+    /// Posts a message to a channel
+    Publish { channel: String, message: String },
+    /// Listens for messages published to shard channels
+    SSubscribe { channels: Vec<String> },
+    /// Stops listening to messages posted to shard channels
+    SUnsubscribe { channels: Vec<String> },
+    /// Posts a message to a shard channel
+    SPublish { channel: String, message: String },
+    /// Returns the active channels
+    Channels { pattern: Option<String> },
+    /// Returns a count of subscribers to channels
+    NumSub { channels: Vec<String> },
+    /// Returns a count of unique pattern subscriptions
+    NumPat,
+    /// Returns the active shard channels
+    ShardChannels { pattern: Option<String> },
+    /// Returns the count of subscribers of shard channels
+    ShardNumSub { channels: Vec<String> },
+}
+
+impl PubSubCmd {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let (name, args) = match self {
+            PubSubCmd::Subscribe { channels } => (vec!["subscribe"], channels),
+            PubSubCmd::Unsubscribe { channels } => (vec!["unsubscribe"], channels),
+            PubSubCmd::PSubscribe { patterns } => (vec!["psubscribe"], patterns),
+            PubSubCmd::PUnsubscribe { patterns } => (vec!["punsubscribe"], patterns),
+            PubSubCmd::Publish { channel, message } => (vec!["publish"], vec![channel, message]),
+            PubSubCmd::SSubscribe { channels } => (vec!["ssubscribe"], channels),
+            PubSubCmd::SUnsubscribe { channels } => (vec!["sunsubscribe"], channels),
+            PubSubCmd::SPublish { channel, message } => (vec!["spublish"], vec![channel, message]),
+            PubSubCmd::Channels { pattern } => (vec!["pubsub", "channels"], pattern.into_iter().collect()),
+            PubSubCmd::NumSub { channels } => (vec!["pubsub", "numsub"], channels),
+            PubSubCmd::NumPat => (vec!["pubsub", "numpat"], vec![]),
+            PubSubCmd::ShardChannels { pattern } => (vec!["pubsub", "shardchannels"], pattern.into_iter().collect()),
+            PubSubCmd::ShardNumSub { channels } => (vec!["pubsub", "shardnumsub"], channels),
+        };
+        for part in name {
+            frame.push_bulk(Bytes::from(part.as_bytes()));
+        }
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    pub fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let args = |from: usize| -> Vec<String> {
+            (from..frame.get_len())
+                .filter_map(|i| frame.get_frame_by_index(i).map(|f| f.to_string()))
+                .collect()
+        };
+        let cmd = match name {
+            "subscribe" | "psubscribe" | "ssubscribe" => {
+                let names = args(1);
+                if names.is_empty() {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                match name {
+                    "subscribe" => PubSubCmd::Subscribe { channels: names },
+                    "psubscribe" => PubSubCmd::PSubscribe { patterns: names },
+                    _ => PubSubCmd::SSubscribe { channels: names },
+                }
+            }
+            "unsubscribe" => PubSubCmd::Unsubscribe { channels: args(1) },
+            "punsubscribe" => PubSubCmd::PUnsubscribe { patterns: args(1) },
+            "sunsubscribe" => PubSubCmd::SUnsubscribe { channels: args(1) },
+            "publish" | "spublish" => {
+                let mut rest = args(1);
+                if rest.len() != 2 {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                let message = rest.pop().unwrap();
+                let channel = rest.pop().unwrap();
+                if name == "publish" {
+                    PubSubCmd::Publish { channel, message }
+                } else {
+                    PubSubCmd::SPublish { channel, message }
+                }
+            }
+            "pubsub" => {
+                let sub = frame.get_frame_by_index(1).ok_or("command error 'pubsub'")?.to_string().to_lowercase();
+                let mut rest = args(2);
+                match &sub[..] {
+                    "channels" | "shardchannels" => {
+                        if rest.len() > 1 {
+                            return Err(CommandError::ArgsErr(format!("pubsub {}", sub)).into());
+                        }
+                        let pattern = rest.pop();
+                        if sub == "channels" {
+                            PubSubCmd::Channels { pattern }
+                        } else {
+                            PubSubCmd::ShardChannels { pattern }
+                        }
+                    }
+                    "numsub" => PubSubCmd::NumSub { channels: rest },
+                    "shardnumsub" => PubSubCmd::ShardNumSub { channels: rest },
+                    "numpat" => PubSubCmd::NumPat,
+                    _ => return Err(CommandError::NotSupport(format!("pubsub {}", sub)).into()),
+                }
+            }
+            _ => return Err(CommandError::ParseError(-5).into()),
+        };
+        Ok(RedisCommand::PubSub(cmd))
+    }
+
+    /// Commands a connection in subscriber mode may still run
+    pub fn allowed_in_subscriber_mode(&self) -> bool {
+        matches!(
+            self,
+            PubSubCmd::Subscribe { .. }
+                | PubSubCmd::Unsubscribe { .. }
+                | PubSubCmd::PSubscribe { .. }
+                | PubSubCmd::PUnsubscribe { .. }
+                | PubSubCmd::SSubscribe { .. }
+                | PubSubCmd::SUnsubscribe { .. }
+        )
+    }
+
+    /// (Un)subscribing replies once per channel, so this returns every frame to write.
+    pub fn apply(self, handler: &mut Handler) -> crate::Result<Vec<Frame>> {
+        let frames = match self {
+            PubSubCmd::Subscribe { channels } => handler.subscribe(SubscriptionKind::Channel, channels),
+            PubSubCmd::PSubscribe { patterns } => handler.subscribe(SubscriptionKind::Pattern, patterns),
+            PubSubCmd::SSubscribe { channels } => handler.subscribe(SubscriptionKind::Shard, channels),
+            PubSubCmd::Unsubscribe { channels } => handler.unsubscribe(SubscriptionKind::Channel, channels),
+            PubSubCmd::PUnsubscribe { patterns } => handler.unsubscribe(SubscriptionKind::Pattern, patterns),
+            PubSubCmd::SUnsubscribe { channels } => handler.unsubscribe(SubscriptionKind::Shard, channels),
+            PubSubCmd::Publish { channel, message } => {
+                let receivers = handler.pubsub().publish(&channel, &message);
+                vec![Frame::Integer(receivers as i64)]
+            }
+            PubSubCmd::SPublish { channel, message } => {
+                let receivers = handler.pubsub().publish_shard(&channel, &message);
+                vec![Frame::Integer(receivers as i64)]
+            }
+            PubSubCmd::Channels { pattern } => {
+                let channels = handler.pubsub().channels(SubscriptionKind::Channel, pattern.as_deref());
+                vec![bulk_array(channels)]
+            }
+            PubSubCmd::ShardChannels { pattern } => {
+                let channels = handler.pubsub().channels(SubscriptionKind::Shard, pattern.as_deref());
+                vec![bulk_array(channels)]
+            }
+            PubSubCmd::NumSub { channels } => vec![num_sub(handler, SubscriptionKind::Channel, channels)],
+            PubSubCmd::ShardNumSub { channels } => vec![num_sub(handler, SubscriptionKind::Shard, channels)],
+            PubSubCmd::NumPat => vec![Frame::Integer(handler.pubsub().num_pat() as i64)],
+        };
+        Ok(frames)
+    }
+}
+
+fn bulk_array(values: Vec<String>) -> Frame {
+    Frame::Array(values.into_iter().map(|v| Frame::Bulk(Bytes::from(v.into_bytes()))).collect())
+}
+
+fn num_sub(handler: &Handler, kind: SubscriptionKind, channels: Vec<String>) -> Frame {
+    let pubsub = handler.pubsub();
+    let mut frame = Frame::Array(vec![]);
+    for channel in channels {
+        let count = pubsub.num_sub(kind, &channel);
+        frame.push_bulk(Bytes::from(channel.into_bytes()));
+        frame.push_int(count as i64);
+    }
+    frame
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use crate::cluster::cluster::cluster_enabled;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+use crate::server::replication::{
+    is_replica, master_repl_offset, replicaof_master, replicaof_no_one, replication_info, wait_for_replicas,
+};
+use crate::server::server::Handler;
+
+#[derive(Debug, Clone)]
+pub enum ReplicationCmd {
+    /// Follows the master at host and port, or with `None` (NO ONE) stops following one
+    ReplicaOf { master: Option<(String, u16)> },
+    /// Sent by a replica during the handshake, e.g. listening-port or capa
+    ReplConf { options: Vec<(String, String)> },
+    /// Asks for the stream of writes from `offset` of history `replid`, `?` and -1 for
+    /// a full resync
+    Psync { replid: String, offset: i64 },
+    /// Blocks until `numreplicas` replicas acknowledged the writes done so far, or for
+    /// `timeout` milliseconds at most, 0 waiting forever
+    Wait { numreplicas: i64, timeout: i64 },
+    /// Role of the server in the replication, with its master or replicas
+    Role,
+}
+
+impl ReplicationCmd {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let args = match self {
+            ReplicationCmd::ReplicaOf { master: Some((host, port)) } => vec!["replicaof".to_string(), host, port.to_string()],
+            ReplicationCmd::ReplicaOf { master: None } => vec!["replicaof".to_string(), "no".to_string(), "one".to_string()],
+            ReplicationCmd::ReplConf { options } => {
+                [vec!["replconf".to_string()], options.into_iter().flat_map(|(option, value)| [option, value]).collect()].concat()
+            }
+            ReplicationCmd::Psync { replid, offset } => vec!["psync".to_string(), replid, offset.to_string()],
+            ReplicationCmd::Wait { numreplicas, timeout } => vec!["wait".to_string(), numreplicas.to_string(), timeout.to_string()],
+            ReplicationCmd::Role => vec!["role".to_string()],
+        };
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    pub fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let mut args = vec![];
+        let mut idx = 1;
+        while let Some(arg) = frame.get_frame_by_index(idx) {
+            args.push(arg.to_string());
+            idx += 1;
+        }
+        let cmd = match (name, &args[..]) {
+            ("replicaof" | "slaveof", [host, port]) => {
+                if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+                    ReplicationCmd::ReplicaOf { master: None }
+                } else {
+                    let port = port.parse::<u16>().map_err(|_| "ERR Invalid master port")?;
+                    ReplicationCmd::ReplicaOf { master: Some((host.clone(), port)) }
+                }
+            }
+            ("replconf", args) if args.len() % 2 == 0 => {
+                let options = args.chunks(2).map(|pair| (pair[0].to_lowercase(), pair[1].clone())).collect();
+                ReplicationCmd::ReplConf { options }
+            }
+            ("psync", [replid, offset]) => {
+                let offset = offset.parse::<i64>().map_err(|_| CommandError::ArgsErr("psync".to_string()))?;
+                ReplicationCmd::Psync { replid: replid.clone(), offset }
+            }
+            ("wait", [numreplicas, timeout]) => {
+                let numreplicas = numreplicas.parse::<i64>().map_err(|_| "ERR value is not an integer or out of range")?;
+                let timeout = timeout.parse::<i64>().map_err(|_| "ERR timeout is not an integer or out of range")?;
+                if timeout < 0 {
+                    return Err("ERR timeout is negative".into());
+                }
+                ReplicationCmd::Wait { numreplicas, timeout }
+            }
+            ("role", []) => ReplicationCmd::Role,
+            _ => return Err(CommandError::ArgsErr(name.to_string()).into()),
+        };
+        Ok(RedisCommand::Replication(cmd))
+    }
+
+    /// REPLICAOF, REPLCONF, WAIT and ROLE, answered by the connection handler. PSYNC turns the
+    /// connection into a replica and is served by `Handler::sync_replica`.
+    pub async fn apply(&self, handler: &mut Handler) -> crate::Result<Frame> {
+        match self {
+            ReplicationCmd::ReplicaOf { .. } if cluster_enabled() => Err("ERR REPLICAOF not allowed in cluster mode.".into()),
+            ReplicationCmd::ReplicaOf { master: None } => {
+                replicaof_no_one();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ReplicationCmd::ReplicaOf { master: Some((host, port)) } => match replicaof_master(host.clone(), *port) {
+                true => Ok(Frame::Simple("OK".to_string())),
+                false => Ok(Frame::Simple("OK Already connected to specified master".to_string())),
+            },
+            ReplicationCmd::ReplConf { options } => {
+                for (option, value) in options {
+                    match &option[..] {
+                        "listening-port" => {
+                            let port = value.parse().map_err(|_| format!("ERR value is not a valid port '{}'", value))?;
+                            handler.set_replica_listening_port(port);
+                        }
+                        // the RDB is always sent in one piece, nothing to adapt to
+                        "capa" => {}
+                        // read by the replica link, a client sending them gets no ack
+                        "ack" | "getack" => {}
+                        _ => return Err(format!("ERR Unrecognized REPLCONF option: {}", option).into()),
+                    }
+                }
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ReplicationCmd::Psync { .. } => Err("ERR PSYNC is served by the connection".into()),
+            ReplicationCmd::Wait { numreplicas, timeout } => {
+                if is_replica() {
+                    return Err("ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.".into());
+                }
+                let numreplicas = (*numreplicas).max(0) as usize;
+                let timeout = (*timeout > 0).then(|| Duration::from_millis(*timeout as u64));
+                let offset = master_repl_offset();
+                let client = handler.client().clone();
+                let acked = tokio::select! {
+                    acked = wait_for_replicas(numreplicas, offset, timeout) => acked,
+                    // the connection went away, its reply goes nowhere
+                    _ = client.killed() => 0,
+                };
+                Ok(Frame::Integer(acked as i64))
+            }
+            ReplicationCmd::Role => Ok(role()),
+        }
+    }
+}
+
+/// The reply of ROLE: the offset and replicas of a master, or the master, link state
+/// and offset of a replica
+fn role() -> Frame {
+    let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string().into_bytes()));
+    let info = replication_info();
+    match info.master {
+        Some(master) => Frame::Array(vec![
+            bulk("slave"),
+            bulk(&master.host),
+            Frame::Integer(master.port as i64),
+            bulk(master.state.name()),
+            Frame::Integer(info.master_repl_offset as i64),
+        ]),
+        None => {
+            let replicas = info
+                .replicas
+                .iter()
+                .map(|replica| {
+                    Frame::Array(vec![bulk(&replica.ip), bulk(&replica.port.to_string()), bulk(&replica.ack_offset.to_string())])
+                })
+                .collect();
+            Frame::Array(vec![bulk("master"), Frame::Integer(info.master_repl_offset as i64), Frame::Array(replicas)])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+    use crate::parser::cmd::replication::ReplicationCmd;
+
+    #[test]
+    fn cmd_to_frame() {
+        let cmds = vec![
+            ReplicationCmd::ReplicaOf { master: Some(("127.0.0.1".to_string(), 6379)) },
+            ReplicationCmd::ReplicaOf { master: None },
+            ReplicationCmd::ReplConf { options: vec![("listening-port".to_string(), "6380".to_string())] },
+            ReplicationCmd::ReplConf {
+                options: vec![("capa".to_string(), "eof".to_string()), ("capa".to_string(), "psync2".to_string())],
+            },
+            ReplicationCmd::Psync { replid: "?".to_string(), offset: -1 },
+            ReplicationCmd::Wait { numreplicas: 1, timeout: 100 },
+            ReplicationCmd::Role,
+        ];
+        for cmd in cmds {
+            let expect = format!("{:?}", cmd);
+            let frame = RedisCommand::Replication(cmd).into_frame();
+            match RedisCommand::from_frame("", frame).unwrap() {
+                RedisCommand::Replication(cmd) => assert_eq!(format!("{:?}", cmd), expect),
+                other => panic!("unexpected command {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use bytes::Bytes;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::object::help_frame;
+use crate::parser::frame::Frame;
+use crate::server::config::{config_get, config_reset_stat, config_rewrite, config_set};
+use crate::server::info::{gen_redis_info_string, info_sections};
+use crate::server::server::Handler;
+use crate::server::shutdown::{abort_shutdown, ShutdownFlags};
+use crate::server::REDIS_SERVER;
+
+const CONFIG_HELP: &[&str] = &[
+    "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "GET <pattern>",
+    "    Return parameters matching the glob-like <pattern> and their values.",
+    "SET <directive> <value>",
+    "    Set the configuration <directive> to <value>.",
+    "RESETSTAT",
+    "    Reset statistics reported by the INFO command.",
+    "REWRITE",
+    "    Rewrite the configuration file.",
+    "HELP",
+    "    Print this help.",
+];
+
+#[derive(Debug, Clone)]
+pub enum ServerCmd {
+    /// A container for server configuration commands
+    Config(ConfigCmd),
+    /// Returns information and statistics about the server
+    Info { sections: Vec<String> },
+    /// Saves, stops the server and exits, or with `abort` cancels a shutdown waiting
+    /// on replicas
+    Shutdown { save: Option<bool>, now: bool, force: bool, abort: bool },
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfigCmd {
+    /// Returns the parameters matching any of the glob patterns and their values
+    Get { patterns: Vec<String> },
+    /// Sets parameters at runtime, all of them or none
+    Set { pairs: Vec<(String, String)> },
+    /// Writes the parameters back to the config file
+    Rewrite,
+    /// Resets the statistics reported by INFO
+    ResetStat,
+    Help,
+}
+
+impl ServerCmd {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let config = |sub: &str| vec!["config".to_string(), sub.to_string()];
+        let args = match self {
+            ServerCmd::Config(ConfigCmd::Get { patterns }) => [config("get"), patterns].concat(),
+            ServerCmd::Config(ConfigCmd::Set { pairs }) => {
+                [config("set"), pairs.into_iter().flat_map(|(parameter, value)| [parameter, value]).collect()].concat()
+            }
+            ServerCmd::Config(ConfigCmd::Rewrite) => config("rewrite"),
+            ServerCmd::Config(ConfigCmd::ResetStat) => config("resetstat"),
+            ServerCmd::Config(ConfigCmd::Help) => config("help"),
+            ServerCmd::Info { sections } => [vec!["info".to_string()], sections].concat(),
+            ServerCmd::Shutdown { save, now, force, abort } => {
+                let mut args = vec!["shutdown".to_string()];
+                match save {
+                    Some(true) => args.push("save".to_string()),
+                    Some(false) => args.push("nosave".to_string()),
+                    None => {}
+                }
+                for (set, flag) in [(now, "now"), (force, "force"), (abort, "abort")] {
+                    if set {
+                        args.push(flag.to_string());
+                    }
+                }
+                args
+            }
+        };
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    pub fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        match name {
+            "config" => {
+                let sub = frame.get_frame_by_index(1).ok_or("command error 'config'")?.to_string().to_lowercase();
+                let mut args = vec![];
+                let mut idx = 2;
+                while let Some(arg) = frame.get_frame_by_index(idx) {
+                    args.push(arg.to_string());
+                    idx += 1;
+                }
+                let cmd = match &sub[..] {
+                    "get" if !args.is_empty() => ConfigCmd::Get { patterns: args },
+                    "set" if !args.is_empty() && args.len() % 2 == 0 => {
+                        let pairs = args.chunks(2).map(|pair| (pair[0].to_lowercase(), pair[1].clone())).collect();
+                        ConfigCmd::Set { pairs }
+                    }
+                    "get" | "set" => return Err(CommandError::ArgsErr(format!("config {}", sub)).into()),
+                    "rewrite" => ConfigCmd::Rewrite,
+                    "resetstat" => ConfigCmd::ResetStat,
+                    "help" => ConfigCmd::Help,
+                    _ => return Err(CommandError::NotSupport(format!("config {}", sub)).into()),
+                };
+                Ok(RedisCommand::Server(ServerCmd::Config(cmd)))
+            }
+            "info" => {
+                let mut sections = vec![];
+                let mut idx = 1;
+                while let Some(section) = frame.get_frame_by_index(idx) {
+                    sections.push(section.to_string());
+                    idx += 1;
+                }
+                Ok(RedisCommand::Server(ServerCmd::Info { sections }))
+            }
+            "shutdown" => {
+                let (mut save, mut now, mut force, mut abort) = (None, false, false, false);
+                let mut idx = 1;
+                while let Some(arg) = frame.get_frame_by_index(idx) {
+                    match &arg.to_string().to_lowercase()[..] {
+                        "save" if save.is_none() => save = Some(true),
+                        "nosave" if save.is_none() => save = Some(false),
+                        "now" => now = true,
+                        "force" => force = true,
+                        "abort" => abort = true,
+                        _ => return Err("ERR syntax error".into()),
+                    }
+                    idx += 1;
+                }
+                // ABORT takes no other flag
+                if abort && (save.is_some() || now || force) {
+                    return Err("ERR syntax error".into());
+                }
+                Ok(RedisCommand::Server(ServerCmd::Shutdown { save, now, force, abort }))
+            }
+            _ => Err(CommandError::ParseError(-7).into()),
+        }
+    }
+
+    /// Runtime parameters and server stats live outside the DB tasks, so these are
+    /// answered by the connection handler directly.
+    pub async fn apply(&self, handler: &Handler) -> crate::Result<Frame> {
+        match self {
+            ServerCmd::Info { sections } => {
+                let info = gen_redis_info_string(handler, &info_sections(sections)).await?;
+                Ok(Frame::Bulk(Bytes::from(info.into_bytes())))
+            }
+            ServerCmd::Config(ConfigCmd::Get { patterns }) => {
+                let mut frame = Frame::Array(vec![]);
+                for (parameter, value) in config_get(patterns) {
+                    frame.push_bulk(Bytes::from(parameter.as_bytes()));
+                    frame.push_bulk(Bytes::from(value.into_bytes()));
+                }
+                Ok(frame)
+            }
+            ServerCmd::Config(ConfigCmd::Set { pairs }) => {
+                config_set(pairs)?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ServerCmd::Config(ConfigCmd::Rewrite) => {
+                config_rewrite()?;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ServerCmd::Config(ConfigCmd::ResetStat) => {
+                config_reset_stat();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            ServerCmd::Config(ConfigCmd::Help) => Ok(help_frame(CONFIG_HELP)),
+            ServerCmd::Shutdown { abort: true, .. } => match abort_shutdown() {
+                true => Ok(Frame::Simple("OK".to_string())),
+                false => Err("ERR No shutdown in progress.".into()),
+            },
+            // on success the server is stopping, the handler closes without a reply
+            ServerCmd::Shutdown { save, now, force, .. } => {
+                let flags = ShutdownFlags { save: *save, now: *now, force: *force };
+                unsafe { REDIS_SERVER.get() }.ok_or("ERR server is not running")?.prepare_for_shutdown(flags).await?;
+                Ok(Frame::Null)
+            }
+        }
+    }
+}
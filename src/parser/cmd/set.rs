@@ -0,0 +1,347 @@
+use bytes::Bytes;
+use crate::db::data_structure::dict::dict::Dict;
+use crate::db::data_structure::listpack::lib::lp_string_to_int64;
+use crate::db::data_structure::listpack::ListPack;
+use crate::db::db::RedisDb;
+use crate::db::object::{OBJ_ENCODING_HT, OBJ_ENCODING_INTSET, OBJ_ENCODING_LISTPACK, OBJ_SET, RedisObject, RedisValue, set_max_intset_entries, set_max_listpack_entries, set_max_listpack_value};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::frame::Frame;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_GENERIC, NOTIFY_SET};
+use crate::server::REDIS_SERVER;
+
+#[derive(Debug)]
+pub enum SetCmd {
+    /// Adds one or more members to a set
+    SAdd { key: String, members: Vec<String> },
+    /// Removes one or more members from a set
+    SRem { key: String, members: Vec<String> },
+    /// Returns the number of members in a set
+    SCard { key: String },
+    /// Determines whether a member belongs to a set
+    SIsMember { key: String, member: String },
+    /// Returns all members of a set
+    SMembers { key: String },
+}
+
+impl CommandStrategy for SetCmd {
+    fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        let (name, args) = match self {
+            SetCmd::SAdd { key, members } => ("sadd", [vec![key], members].concat()),
+            SetCmd::SRem { key, members } => ("srem", [vec![key], members].concat()),
+            SetCmd::SCard { key } => ("scard", vec![key]),
+            SetCmd::SIsMember { key, member } => ("sismember", vec![key, member]),
+            SetCmd::SMembers { key } => ("smembers", vec![key]),
+        };
+        frame.push_bulk(Bytes::from(name.as_bytes()));
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let arg = |i: usize| -> crate::Result<String> {
+            Ok(frame.get_frame_by_index(i).ok_or(CommandError::ArgsErr(name.to_string()))?.to_string())
+        };
+        let members = || -> crate::Result<Vec<String>> {
+            let members: Vec<String> = (2..frame.get_len())
+                .filter_map(|i| frame.get_frame_by_index(i).map(|f| f.to_string()))
+                .collect();
+            if members.is_empty() {
+                return Err(CommandError::ArgsErr(name.to_string()).into());
+            }
+            Ok(members)
+        };
+        let cmd = match name {
+            "sadd" => SetCmd::SAdd { key: arg(1)?, members: members()? },
+            "srem" => SetCmd::SRem { key: arg(1)?, members: members()? },
+            "scard" => SetCmd::SCard { key: arg(1)? },
+            "sismember" => SetCmd::SIsMember { key: arg(1)?, member: arg(2)? },
+            "smembers" => SetCmd::SMembers { key: arg(1)? },
+            _ => return Err(CommandError::ParseError(-8).into()),
+        };
+        Ok(RedisCommand::Set(cmd))
+    }
+
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        match self {
+            SetCmd::SAdd { key, members } => {
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let o = match db.find(&key_obj) {
+                    Some(o) => o,
+                    None => {
+                        db.add(key_obj.clone(), set_type_create(&members[0], members.len()));
+                        db.find(&key_obj).unwrap()
+                    }
+                };
+                if o.object_type != OBJ_SET {
+                    return Err(CommandError::ObjectTypeError(-8).into());
+                }
+                let added = members.iter().filter(|m| set_type_add(o, m)).count();
+                if added > 0 {
+                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                    notify_keyspace_event(NOTIFY_SET, "sadd", &key, db.id);
+                }
+                Ok(Frame::Integer(added as i64))
+            }
+            SetCmd::SRem { key, members } => {
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let (removed, empty) = match db.find(&key_obj) {
+                    Some(o) => {
+                        if o.object_type != OBJ_SET {
+                            return Err(CommandError::ObjectTypeError(-8).into());
+                        }
+                        let removed = members.iter().filter(|m| set_type_remove(o, m)).count();
+                        (removed, set_type_size(o) == 0)
+                    }
+                    None => return Ok(Frame::Integer(0)),
+                };
+                if removed > 0 {
+                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                    notify_keyspace_event(NOTIFY_SET, "srem", &key, db.id);
+                    if empty {
+                        db.delete(&key_obj);
+                        notify_keyspace_event(NOTIFY_GENERIC, "del", &key, db.id);
+                    }
+                }
+                Ok(Frame::Integer(removed as i64))
+            }
+            SetCmd::SCard { key } => {
+                let key_obj = RedisObject::create_string_object(key);
+                match db.lookup_read(&key_obj) {
+                    Some(o) if o.object_type != OBJ_SET => Err(CommandError::ObjectTypeError(-8).into()),
+                    Some(o) => Ok(Frame::Integer(set_type_size(o) as i64)),
+                    None => Ok(Frame::Integer(0)),
+                }
+            }
+            SetCmd::SIsMember { key, member } => {
+                let key_obj = RedisObject::create_string_object(key);
+                match db.lookup_read(&key_obj) {
+                    Some(o) if o.object_type != OBJ_SET => Err(CommandError::ObjectTypeError(-8).into()),
+                    Some(o) => Ok(Frame::Integer(set_type_is_member(o, &member) as i64)),
+                    None => Ok(Frame::Integer(0)),
+                }
+            }
+            SetCmd::SMembers { key } => {
+                let key_obj = RedisObject::create_string_object(key);
+                let mut frame = Frame::array();
+                match db.lookup_read(&key_obj) {
+                    Some(o) if o.object_type != OBJ_SET => return Err(CommandError::ObjectTypeError(-8).into()),
+                    Some(o) => {
+                        for member in set_type_members(o) {
+                            frame.push_bulk(Bytes::from(member.into_bytes()));
+                        }
+                    }
+                    None => {}
+                }
+                Ok(frame)
+            }
+        }
+    }
+}
+
+/// Picks the smallest encoding able to hold `value`, `size_hint` is the number of
+/// members about to be added.
+pub fn set_type_create(value: &str, size_hint: usize) -> RedisObject {
+    if lp_string_to_int64(value.as_bytes()).is_some() && size_hint <= set_max_intset_entries() {
+        RedisObject::create_intset_object()
+    } else if size_hint <= set_max_listpack_entries() {
+        RedisObject::create_set_listpack_object()
+    } else {
+        let mut o = RedisObject::create_intset_object();
+        set_type_convert(&mut o, OBJ_ENCODING_HT);
+        o
+    }
+}
+
+/// Adds `value`, moving to a larger encoding when it no longer fits. Returns
+/// false if it was already a member.
+pub fn set_type_add(o: &mut RedisObject, value: &str) -> bool {
+    if o.encoding == OBJ_ENCODING_INTSET {
+        if let Some(v) = lp_string_to_int64(value.as_bytes()) {
+            let is = match &mut o.ptr {
+                RedisValue::Set(is) => is,
+                _ => return false,
+            };
+            let mut success = false;
+            is.add(v, &mut success);
+            if success && is.get_length() as usize > set_max_intset_entries() {
+                let len = is.get_length() as usize;
+                let enc = if len <= set_max_listpack_entries() { OBJ_ENCODING_LISTPACK } else { OBJ_ENCODING_HT };
+                set_type_convert(o, enc);
+            }
+            return success;
+        }
+        let len = set_type_size(o);
+        let enc = if len < set_max_listpack_entries() && value.len() <= set_max_listpack_value() {
+            OBJ_ENCODING_LISTPACK
+        } else {
+            OBJ_ENCODING_HT
+        };
+        set_type_convert(o, enc);
+    }
+    if o.encoding == OBJ_ENCODING_LISTPACK {
+        let lp = match &mut o.ptr {
+            RedisValue::ListPack(lp) => lp,
+            _ => return false,
+        };
+        if lp.first().and_then(|first| lp.find(first, value.as_bytes(), 0)).is_some() {
+            return false;
+        }
+        if lp.len() < set_max_listpack_entries() && value.len() <= set_max_listpack_value() {
+            lp.append(value.as_bytes());
+            return true;
+        }
+        set_type_convert(o, OBJ_ENCODING_HT);
+    }
+    match &mut o.ptr {
+        RedisValue::Hash(ht) => {
+            if ht.find(value).is_some() {
+                return false;
+            }
+            ht.add_raw_without_value(value.to_string()).is_ok()
+        }
+        _ => false,
+    }
+}
+
+pub fn set_type_remove(o: &mut RedisObject, value: &str) -> bool {
+    match &mut o.ptr {
+        RedisValue::Set(is) => match lp_string_to_int64(value.as_bytes()) {
+            Some(v) if is.find(v) => {
+                is.remove(v);
+                true
+            }
+            _ => false,
+        },
+        RedisValue::ListPack(lp) => match lp.first().and_then(|first| lp.find(first, value.as_bytes(), 0)) {
+            Some(pos) => {
+                lp.delete(pos);
+                true
+            }
+            None => false,
+        },
+        RedisValue::Hash(ht) => matches!(ht.generic_delete(value), Ok(Some(_))),
+        _ => false,
+    }
+}
+
+pub fn set_type_is_member(o: &mut RedisObject, value: &str) -> bool {
+    match &mut o.ptr {
+        RedisValue::Set(is) => lp_string_to_int64(value.as_bytes()).map_or(false, |v| is.find(v)),
+        RedisValue::ListPack(lp) => lp.first().and_then(|first| lp.find(first, value.as_bytes(), 0)).is_some(),
+        RedisValue::Hash(ht) => ht.find(value).is_some(),
+        _ => false,
+    }
+}
+
+pub fn set_type_size(o: &RedisObject) -> usize {
+    match &o.ptr {
+        RedisValue::Set(is) => is.get_length() as usize,
+        RedisValue::ListPack(lp) => lp.len(),
+        RedisValue::Hash(ht) => ht.dict_size() as usize,
+        _ => 0,
+    }
+}
+
+pub fn set_type_members(o: &RedisObject) -> Vec<String> {
+    match &o.ptr {
+        RedisValue::Set(is) => (0..is.get_length() as usize).filter_map(|i| is.get(i)).map(|v| v.to_string()).collect(),
+        RedisValue::ListPack(lp) => lp.iter().map(|e| e.to_string()).collect(),
+        RedisValue::Hash(ht) => ht.iter().map(|entry| unsafe { (*entry).get_key().to_string() }).collect(),
+        _ => vec![],
+    }
+}
+
+/// Moves the set to the listpack or hash table encoding.
+pub fn set_type_convert(o: &mut RedisObject, enc: u32) {
+    let members = set_type_members(o);
+    if enc == OBJ_ENCODING_LISTPACK {
+        let mut lp = ListPack::new();
+        for member in members {
+            lp.append(member.as_bytes());
+        }
+        o.ptr = RedisValue::ListPack(lp);
+    } else {
+        let mut ht = Dict::create();
+        for member in members {
+            let _ = ht.add_raw_without_value(member);
+        }
+        // a set in the hash table encoding is a dict of keys without values
+        o.ptr = RedisValue::Hash(ht);
+    }
+    o.encoding = enc;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::db::RedisDb;
+    use crate::db::kvstore::KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
+    use crate::db::object::{RedisObject, OBJ_ENCODING_HT, OBJ_ENCODING_INTSET, OBJ_ENCODING_LISTPACK};
+    use crate::parser::cmd::command::CommandStrategy;
+    use crate::parser::cmd::set::{set_type_add, set_type_create, set_type_is_member, set_type_remove, set_type_size, SetCmd};
+    use crate::parser::frame::Frame;
+
+    #[test]
+    fn encoding_conversions() {
+        let mut set = set_type_create("1", 1);
+        assert_eq!(set.encoding, OBJ_ENCODING_INTSET);
+        assert!(set_type_add(&mut set, "1"));
+        assert!(set_type_add(&mut set, "2"));
+        assert!(!set_type_add(&mut set, "2"));
+        assert_eq!(set.encoding, OBJ_ENCODING_INTSET);
+
+        // a non integer member moves the intset to a listpack, keeping the members
+        assert!(set_type_add(&mut set, "a"));
+        assert_eq!(set.encoding, OBJ_ENCODING_LISTPACK);
+        assert!(!set_type_add(&mut set, "a"));
+        assert!(set_type_is_member(&mut set, "1"));
+        assert!(set_type_remove(&mut set, "2"));
+        assert_eq!(set_type_size(&set), 2);
+
+        // past set-max-listpack-entries it becomes a hash table
+        for i in 0..200 {
+            set_type_add(&mut set, &format!("m{}", i));
+        }
+        assert_eq!(set.encoding, OBJ_ENCODING_HT);
+        assert_eq!(set_type_size(&set), 202);
+        assert!(set_type_is_member(&mut set, "m199"));
+        assert!(set_type_is_member(&mut set, "a"));
+
+        // and so does a member longer than set-max-listpack-value
+        let mut set = set_type_create("a", 1);
+        assert_eq!(set.encoding, OBJ_ENCODING_LISTPACK);
+        assert!(set_type_add(&mut set, "a"));
+        assert!(set_type_add(&mut set, &"x".repeat(100)));
+        assert_eq!(set.encoding, OBJ_ENCODING_HT);
+        assert!(set_type_is_member(&mut set, "a"));
+    }
+
+    #[test]
+    fn read_commands() -> crate::Result<()> {
+        let mut db = RedisDb::create(0, KVSTORE_ALLOCATE_DICTS_ON_DEMAND, 0);
+        let mut set = set_type_create("a", 2);
+        set_type_add(&mut set, "a");
+        set_type_add(&mut set, "b");
+        db.add(RedisObject::create_string_object("s".to_string()), set);
+        db.add(RedisObject::create_string_object("str".to_string()), RedisObject::create_string_object("v".to_string()));
+
+        let ismember = |key: &str, member: &str| SetCmd::SIsMember { key: key.to_string(), member: member.to_string() };
+        assert!(matches!(ismember("s", "a").apply(&mut db)?, Frame::Integer(1)));
+        assert!(matches!(ismember("s", "c").apply(&mut db)?, Frame::Integer(0)));
+        assert!(matches!(ismember("missing", "a").apply(&mut db)?, Frame::Integer(0)));
+        assert!(matches!(SetCmd::SCard { key: "s".to_string() }.apply(&mut db)?, Frame::Integer(2)));
+        assert!(SetCmd::SCard { key: "str".to_string() }.apply(&mut db).is_err());
+        match (SetCmd::SMembers { key: "s".to_string() }).apply(&mut db)? {
+            Frame::Array(members) => {
+                let mut members: Vec<String> = members.iter().map(|member| member.to_string()).collect();
+                members.sort();
+                assert_eq!(members, ["a", "b"]);
+            }
+            frame => panic!("unexpected SMEMBERS reply {:?}", frame),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use bytes::Bytes;
+use crate::parser::cmd::command::RedisCommand;
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::object::help_frame;
+use crate::parser::frame::Frame;
+use crate::server::slowlog::{slowlog_get, slowlog_len, slowlog_reset};
+
+/// Entries returned by SLOWLOG GET without a count
+const SLOWLOG_GET_DEFAULT_COUNT: i64 = 10;
+
+const SLOWLOG_HELP: &[&str] = &[
+    "SLOWLOG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "GET [<count>]",
+    "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+    "    Entries are made of:",
+    "    id, timestamp, time in microseconds, arguments array, client IP and port,",
+    "    client name",
+    "LEN",
+    "    Return the length of the slowlog.",
+    "RESET",
+    "    Reset the slowlog.",
+    "HELP",
+    "    Print this help.",
+];
+
+#[derive(Debug, Clone)]
+pub enum SlowlogCmd {
+    /// Returns the `count` newest entries, all of them for -1
+    Get { count: i64 },
+    /// Returns the number of entries
+    Len,
+    /// Removes every entry
+    Reset,
+    Help,
+}
+
+impl SlowlogCmd {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::Array(vec![]);
+        frame.push_bulk(Bytes::from("slowlog".as_bytes()));
+        match self {
+            SlowlogCmd::Get { count } => {
+                frame.push_bulk(Bytes::from("get".as_bytes()));
+                frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+            }
+            SlowlogCmd::Len => frame.push_bulk(Bytes::from("len".as_bytes())),
+            SlowlogCmd::Reset => frame.push_bulk(Bytes::from("reset".as_bytes())),
+            SlowlogCmd::Help => frame.push_bulk(Bytes::from("help".as_bytes())),
+        }
+        frame
+    }
+
+    pub fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        if name != "slowlog" {
+            return Err(CommandError::ParseError(-12).into());
+        }
+        let sub = frame.get_frame_by_index(1).ok_or(CommandError::ArgsErr("slowlog".to_string()))?.to_string().to_lowercase();
+        let cmd = match &sub[..] {
+            "get" => {
+                let count = match frame.get_frame_by_index(2) {
+                    Some(count) => count.to_string().parse::<i64>().ok().filter(|count| *count >= -1)
+                        .ok_or("ERR count should be greater than or equal to -1")?,
+                    None => SLOWLOG_GET_DEFAULT_COUNT,
+                };
+                SlowlogCmd::Get { count }
+            }
+            "len" => SlowlogCmd::Len,
+            "reset" => SlowlogCmd::Reset,
+            "help" => SlowlogCmd::Help,
+            _ => return Err(CommandError::NotSupport(format!("slowlog {}", sub)).into()),
+        };
+        Ok(RedisCommand::Slowlog(cmd))
+    }
+
+    /// The slow log is server-wide, so the connection answers it rather than a DB
+    pub fn apply(&self) -> crate::Result<Frame> {
+        match self {
+            SlowlogCmd::Get { count } => {
+                let entries = slowlog_get(*count).into_iter().map(|entry| {
+                    Frame::Array(vec![
+                        Frame::Integer(entry.id as i64),
+                        Frame::Integer(entry.time),
+                        Frame::Integer(entry.duration_us as i64),
+                        Frame::Array(entry.argv.into_iter().map(Frame::Bulk).collect()),
+                        Frame::Bulk(Bytes::from(entry.peer_id.into_bytes())),
+                        Frame::Bulk(Bytes::from(entry.client_name.into_bytes())),
+                    ])
+                });
+                Ok(Frame::Array(entries.collect()))
+            }
+            SlowlogCmd::Len => Ok(Frame::Integer(slowlog_len() as i64)),
+            SlowlogCmd::Reset => {
+                slowlog_reset();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            SlowlogCmd::Help => Ok(help_frame(SLOWLOG_HELP)),
+        }
+    }
+}
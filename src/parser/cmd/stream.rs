@@ -0,0 +1,1185 @@
+use std::fmt;
+
+use bytes::Bytes;
+use crate::db::blocked::BlockOp;
+use crate::db::data_structure::stream::{
+    ClaimOptions, DeliveredEntry, Stream, StreamFields, StreamID, StreamIdSpec, StreamTrim, TrimStrategy,
+    SCG_INVALID_ENTRIES_READ,
+};
+use crate::db::db::RedisDb;
+use crate::db::expire::mstime;
+use crate::db::object::{RedisObject, RedisValue, OBJ_STREAM};
+use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::error::CommandError::ObjectTypeError;
+use crate::parser::frame::Frame;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_STREAM};
+use crate::server::REDIS_SERVER;
+
+/// The ID argument of XREAD, XREADGROUP and XGROUP CREATE/SETID
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamReadId {
+    /// `$`: the last ID of the stream at the time of the call
+    Last,
+    /// `>`: entries never delivered to the group
+    New,
+    Id(StreamID),
+}
+
+impl StreamReadId {
+    fn parse(s: &str) -> crate::Result<StreamReadId> {
+        match s {
+            "$" => Ok(StreamReadId::Last),
+            ">" => Ok(StreamReadId::New),
+            _ => Ok(StreamReadId::Id(parse_id(s, 0)?)),
+        }
+    }
+
+    /// Resolves `$` against the current content of `stream`
+    fn resolve(&self, stream: Option<&Stream>) -> StreamID {
+        match self {
+            StreamReadId::Id(id) => *id,
+            _ => stream.map(|s| s.last_id).unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for StreamReadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamReadId::Last => write!(f, "$"),
+            StreamReadId::New => write!(f, ">"),
+            StreamReadId::Id(id) => id.fmt(f),
+        }
+    }
+}
+
+/// The extended form of XPENDING
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingRange {
+    pub min_idle: Option<i64>,
+    pub start: StreamID,
+    pub end: StreamID,
+    pub count: usize,
+    pub consumer: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum StreamCmd {
+    /// Appends a new message to a stream. Creates the key if it doesn't exist
+    XAdd { key: String, nomkstream: bool, trim: Option<StreamTrim>, id: StreamIdSpec, fields: StreamFields },
+    /// Return the number of messages in a stream
+    XLen { key: String },
+    /// Returns the messages from a stream within a range of IDs
+    XRange { key: String, start: StreamID, end: StreamID, count: Option<usize> },
+    /// Returns the messages from a stream within a range of IDs in reverse order
+    XRevRange { key: String, end: StreamID, start: StreamID, count: Option<usize> },
+    /// Returns the number of messages after removing them from a stream
+    XDel { key: String, ids: Vec<StreamID> },
+    /// Deletes messages from the beginning of a stream
+    XTrim { key: String, trim: StreamTrim },
+    /// Returns messages from multiple streams with IDs greater than the ones requested.
+    /// Blocks until a message is available otherwise
+    XRead { count: Option<usize>, block: Option<u64>, keys: Vec<String>, ids: Vec<StreamReadId> },
+    /// Returns new or historical messages from a stream for a consumer in a group.
+    /// Blocks until a message is available otherwise
+    XReadGroup {
+        group: String,
+        consumer: String,
+        count: Option<usize>,
+        block: Option<u64>,
+        noack: bool,
+        keys: Vec<String>,
+        ids: Vec<StreamReadId>,
+    },
+    /// Returns the number of messages that were successfully acknowledged by the consumer group member of a stream
+    XAck { key: String, group: String, ids: Vec<StreamID> },
+    /// A container for consumer groups commands
+    XGroup(XGroupCmd),
+    /// Returns the information and entries from a stream consumer group's pending entries list
+    XPending { key: String, group: String, range: Option<PendingRange> },
+    /// Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered to a consumer group member
+    XClaim { key: String, group: String, consumer: String, min_idle: i64, ids: Vec<StreamID>, opts: ClaimOptions },
+    /// Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to a consumer group member
+    XAutoClaim { key: String, group: String, consumer: String, min_idle: i64, start: StreamID, count: usize, just_id: bool },
+    /// A container for stream introspection commands
+    XInfo(XInfoCmd),
+}
+
+#[derive(Debug)]
+pub enum XGroupCmd {
+    /// Creates a consumer group
+    Create { key: String, group: String, id: StreamReadId, mkstream: bool, entries_read: Option<i64> },
+    /// Sets the last-delivered ID of a consumer group
+    SetId { key: String, group: String, id: StreamReadId, entries_read: Option<i64> },
+    /// Destroys a consumer group
+    Destroy { key: String, group: String },
+    /// Creates a consumer in a consumer group
+    CreateConsumer { key: String, group: String, consumer: String },
+    /// Deletes a consumer from a consumer group
+    DelConsumer { key: String, group: String, consumer: String },
+}
+
+#[derive(Debug)]
+pub enum XInfoCmd {
+    /// Returns information about a stream
+    Stream { key: String },
+    /// Returns a list of the consumer groups of a stream
+    Groups { key: String },
+    /// Returns a list of the consumers in a consumer group
+    Consumers { key: String, group: String },
+}
+
+impl CommandStrategy for StreamCmd {
+    fn into_frame(self) -> Frame {
+        let args: Vec<String> = match self {
+            StreamCmd::XAdd { key, nomkstream, trim, id, fields } => {
+                let mut args = vec!["xadd".to_string(), key];
+                if nomkstream {
+                    args.push("nomkstream".to_string());
+                }
+                if let Some(trim) = trim {
+                    push_trim_args(&mut args, &trim);
+                }
+                args.push(match id {
+                    StreamIdSpec::Auto => "*".to_string(),
+                    StreamIdSpec::AutoSeq(ms) => format!("{}-*", ms),
+                    StreamIdSpec::Explicit(id) => id.to_string(),
+                });
+                for (field, value) in fields {
+                    args.push(field);
+                    args.push(value);
+                }
+                args
+            }
+            StreamCmd::XLen { key } => vec!["xlen".to_string(), key],
+            StreamCmd::XRange { key, start, end, count } => {
+                let mut args = vec!["xrange".to_string(), key, start.to_string(), end.to_string()];
+                push_count_arg(&mut args, count);
+                args
+            }
+            StreamCmd::XRevRange { key, end, start, count } => {
+                let mut args = vec!["xrevrange".to_string(), key, end.to_string(), start.to_string()];
+                push_count_arg(&mut args, count);
+                args
+            }
+            StreamCmd::XDel { key, ids } => {
+                let mut args = vec!["xdel".to_string(), key];
+                args.extend(ids.iter().map(|id| id.to_string()));
+                args
+            }
+            StreamCmd::XTrim { key, trim } => {
+                let mut args = vec!["xtrim".to_string(), key];
+                push_trim_args(&mut args, &trim);
+                args
+            }
+            StreamCmd::XRead { count, block, keys, ids } => {
+                let mut args = vec!["xread".to_string()];
+                push_count_arg(&mut args, count);
+                if let Some(block) = block {
+                    args.push("block".to_string());
+                    args.push(block.to_string());
+                }
+                args.push("streams".to_string());
+                args.extend(keys);
+                args.extend(ids.iter().map(|id| id.to_string()));
+                args
+            }
+            StreamCmd::XReadGroup { group, consumer, count, block, noack, keys, ids } => {
+                let mut args = vec!["xreadgroup".to_string(), "group".to_string(), group, consumer];
+                push_count_arg(&mut args, count);
+                if let Some(block) = block {
+                    args.push("block".to_string());
+                    args.push(block.to_string());
+                }
+                if noack {
+                    args.push("noack".to_string());
+                }
+                args.push("streams".to_string());
+                args.extend(keys);
+                args.extend(ids.iter().map(|id| id.to_string()));
+                args
+            }
+            StreamCmd::XAck { key, group, ids } => {
+                let mut args = vec!["xack".to_string(), key, group];
+                args.extend(ids.iter().map(|id| id.to_string()));
+                args
+            }
+            StreamCmd::XGroup(cmd) => match cmd {
+                XGroupCmd::Create { key, group, id, mkstream, entries_read } => {
+                    let mut args = vec!["xgroup".to_string(), "create".to_string(), key, group, id.to_string()];
+                    if mkstream {
+                        args.push("mkstream".to_string());
+                    }
+                    if let Some(entries_read) = entries_read {
+                        args.push("entriesread".to_string());
+                        args.push(entries_read.to_string());
+                    }
+                    args
+                }
+                XGroupCmd::SetId { key, group, id, entries_read } => {
+                    let mut args = vec!["xgroup".to_string(), "setid".to_string(), key, group, id.to_string()];
+                    if let Some(entries_read) = entries_read {
+                        args.push("entriesread".to_string());
+                        args.push(entries_read.to_string());
+                    }
+                    args
+                }
+                XGroupCmd::Destroy { key, group } => vec!["xgroup".to_string(), "destroy".to_string(), key, group],
+                XGroupCmd::CreateConsumer { key, group, consumer } => {
+                    vec!["xgroup".to_string(), "createconsumer".to_string(), key, group, consumer]
+                }
+                XGroupCmd::DelConsumer { key, group, consumer } => {
+                    vec!["xgroup".to_string(), "delconsumer".to_string(), key, group, consumer]
+                }
+            },
+            StreamCmd::XPending { key, group, range } => {
+                let mut args = vec!["xpending".to_string(), key, group];
+                if let Some(range) = range {
+                    if let Some(min_idle) = range.min_idle {
+                        args.push("idle".to_string());
+                        args.push(min_idle.to_string());
+                    }
+                    args.push(range.start.to_string());
+                    args.push(range.end.to_string());
+                    args.push(range.count.to_string());
+                    args.extend(range.consumer);
+                }
+                args
+            }
+            StreamCmd::XClaim { key, group, consumer, min_idle, ids, opts } => {
+                let mut args = vec!["xclaim".to_string(), key, group, consumer, min_idle.to_string()];
+                args.extend(ids.iter().map(|id| id.to_string()));
+                for (name, value) in [("idle", opts.idle), ("time", opts.time)] {
+                    if let Some(value) = value {
+                        args.push(name.to_string());
+                        args.push(value.to_string());
+                    }
+                }
+                if let Some(retry_count) = opts.retry_count {
+                    args.push("retrycount".to_string());
+                    args.push(retry_count.to_string());
+                }
+                if opts.force {
+                    args.push("force".to_string());
+                }
+                if opts.just_id {
+                    args.push("justid".to_string());
+                }
+                if let Some(last_id) = opts.last_id {
+                    args.push("lastid".to_string());
+                    args.push(last_id.to_string());
+                }
+                args
+            }
+            StreamCmd::XAutoClaim { key, group, consumer, min_idle, start, count, just_id } => {
+                let mut args = vec![
+                    "xautoclaim".to_string(),
+                    key,
+                    group,
+                    consumer,
+                    min_idle.to_string(),
+                    start.to_string(),
+                    "count".to_string(),
+                    count.to_string(),
+                ];
+                if just_id {
+                    args.push("justid".to_string());
+                }
+                args
+            }
+            StreamCmd::XInfo(cmd) => match cmd {
+                XInfoCmd::Stream { key } => vec!["xinfo".to_string(), "stream".to_string(), key],
+                XInfoCmd::Groups { key } => vec!["xinfo".to_string(), "groups".to_string(), key],
+                XInfoCmd::Consumers { key, group } => vec!["xinfo".to_string(), "consumers".to_string(), key, group],
+            },
+        };
+        let mut frame = Frame::array();
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+
+    fn from_frame(name: &str, frame: Frame) -> crate::Result<RedisCommand> {
+        let args: Vec<String> = (0..frame.get_len())
+            .filter_map(|i| frame.get_frame_by_index(i).map(|f| f.to_string()))
+            .collect();
+        let arg = |i: usize| -> crate::Result<&str> {
+            args.get(i).map(|s| s.as_str()).ok_or_else(|| CommandError::ArgsErr(name.to_string()).into())
+        };
+        let cmd = match name {
+            "xadd" => {
+                let key = arg(1)?.to_string();
+                let mut i = 2;
+                let mut nomkstream = false;
+                let mut trim = None;
+                loop {
+                    match &arg(i)?.to_lowercase()[..] {
+                        "nomkstream" => {
+                            nomkstream = true;
+                            i += 1;
+                        }
+                        "maxlen" | "minid" => trim = Some(parse_trim(&args, &mut i)?),
+                        _ => break,
+                    }
+                }
+                let id = parse_id_spec(arg(i)?)?;
+                let rest = &args[i + 1..];
+                if rest.is_empty() || rest.len() % 2 != 0 {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                let fields = rest.chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect();
+                StreamCmd::XAdd { key, nomkstream, trim, id, fields }
+            }
+            "xlen" => StreamCmd::XLen { key: arg(1)?.to_string() },
+            "xrange" | "xrevrange" => {
+                let key = arg(1)?.to_string();
+                let (first, second) = (arg(2)?, arg(3)?);
+                let count = match args.len() {
+                    4 => None,
+                    6 if args[4].eq_ignore_ascii_case("count") => Some(parse_usize(&args[5])?),
+                    _ => return Err("ERR syntax error".into()),
+                };
+                if name == "xrange" {
+                    let (start, end) = (parse_range_start(first)?, parse_range_end(second)?);
+                    StreamCmd::XRange { key, start, end, count }
+                } else {
+                    let (end, start) = (parse_range_end(first)?, parse_range_start(second)?);
+                    StreamCmd::XRevRange { key, end, start, count }
+                }
+            }
+            "xdel" => {
+                let key = arg(1)?.to_string();
+                arg(2)?;
+                let ids = args[2..].iter().map(|s| parse_id(s, 0)).collect::<crate::Result<Vec<_>>>()?;
+                StreamCmd::XDel { key, ids }
+            }
+            "xtrim" => {
+                let key = arg(1)?.to_string();
+                let mut i = 2;
+                let trim = parse_trim(&args, &mut i)?;
+                if i != args.len() {
+                    return Err("ERR syntax error".into());
+                }
+                StreamCmd::XTrim { key, trim }
+            }
+            "xread" | "xreadgroup" => {
+                let mut i = 1;
+                let mut group = None;
+                let mut count = None;
+                let mut block = None;
+                let mut noack = false;
+                loop {
+                    match &arg(i)?.to_lowercase()[..] {
+                        "count" => {
+                            count = Some(parse_usize(arg(i + 1)?)?);
+                            i += 2;
+                        }
+                        "block" => {
+                            let ms: i64 = arg(i + 1)?.parse().map_err(|_| "ERR timeout is not an integer or out of range")?;
+                            if ms < 0 {
+                                return Err("ERR timeout is negative".into());
+                            }
+                            block = Some(ms as u64);
+                            i += 2;
+                        }
+                        "group" if name == "xreadgroup" => {
+                            group = Some((arg(i + 1)?.to_string(), arg(i + 2)?.to_string()));
+                            i += 3;
+                        }
+                        "noack" if name == "xreadgroup" => {
+                            noack = true;
+                            i += 1;
+                        }
+                        "streams" => {
+                            i += 1;
+                            break;
+                        }
+                        _ => return Err("ERR syntax error".into()),
+                    }
+                }
+                let rest = &args[i..];
+                if rest.is_empty() || rest.len() % 2 != 0 {
+                    return Err("ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.".into());
+                }
+                let (keys, ids) = rest.split_at(rest.len() / 2);
+                let ids = ids.iter().map(|s| StreamReadId::parse(s)).collect::<crate::Result<Vec<_>>>()?;
+                let keys = keys.to_vec();
+                match group {
+                    Some((group, consumer)) => {
+                        if ids.contains(&StreamReadId::Last) {
+                            return Err("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.".into());
+                        }
+                        StreamCmd::XReadGroup { group, consumer, count, block, noack, keys, ids }
+                    }
+                    None if name == "xreadgroup" => return Err("ERR Missing GROUP option for XREADGROUP".into()),
+                    None => {
+                        if ids.contains(&StreamReadId::New) {
+                            return Err("ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.".into());
+                        }
+                        StreamCmd::XRead { count, block, keys, ids }
+                    }
+                }
+            }
+            "xack" => {
+                let key = arg(1)?.to_string();
+                let group = arg(2)?.to_string();
+                arg(3)?;
+                let ids = args[3..].iter().map(|s| parse_id(s, 0)).collect::<crate::Result<Vec<_>>>()?;
+                StreamCmd::XAck { key, group, ids }
+            }
+            "xgroup" => {
+                let sub = arg(1)?.to_lowercase();
+                let key = arg(2)?.to_string();
+                let group = arg(3)?.to_string();
+                let cmd = match &sub[..] {
+                    "create" | "setid" => {
+                        let id = StreamReadId::parse(arg(4)?)?;
+                        if id == StreamReadId::New {
+                            return Err("ERR Invalid stream ID specified as stream command argument".into());
+                        }
+                        let mut mkstream = false;
+                        let mut entries_read = None;
+                        let mut i = 5;
+                        while i < args.len() {
+                            match &args[i].to_lowercase()[..] {
+                                "mkstream" if sub == "create" => {
+                                    mkstream = true;
+                                    i += 1;
+                                }
+                                "entriesread" => {
+                                    let value: i64 = arg(i + 1)?.parse().map_err(|_| "ERR value is not an integer or out of range")?;
+                                    if value < 0 && value != SCG_INVALID_ENTRIES_READ {
+                                        return Err("ERR value for ENTRIESREAD must be positive or -1".into());
+                                    }
+                                    entries_read = Some(value);
+                                    i += 2;
+                                }
+                                _ => return Err("ERR syntax error".into()),
+                            }
+                        }
+                        if sub == "create" {
+                            XGroupCmd::Create { key, group, id, mkstream, entries_read }
+                        } else {
+                            XGroupCmd::SetId { key, group, id, entries_read }
+                        }
+                    }
+                    "destroy" => XGroupCmd::Destroy { key, group },
+                    "createconsumer" => XGroupCmd::CreateConsumer { key, group, consumer: arg(4)?.to_string() },
+                    "delconsumer" => XGroupCmd::DelConsumer { key, group, consumer: arg(4)?.to_string() },
+                    _ => return Err(CommandError::NotSupport(format!("xgroup {}", sub)).into()),
+                };
+                StreamCmd::XGroup(cmd)
+            }
+            "xpending" => {
+                let key = arg(1)?.to_string();
+                let group = arg(2)?.to_string();
+                let range = if args.len() > 3 {
+                    let mut i = 3;
+                    let mut min_idle = None;
+                    if args[i].eq_ignore_ascii_case("idle") {
+                        min_idle = Some(parse_ms(arg(i + 1)?)?);
+                        i += 2;
+                    }
+                    let start = parse_range_start(arg(i)?)?;
+                    let end = parse_range_end(arg(i + 1)?)?;
+                    let count = parse_usize(arg(i + 2)?)?;
+                    let consumer = args.get(i + 3).cloned();
+                    if args.len() > i + 4 {
+                        return Err("ERR syntax error".into());
+                    }
+                    Some(PendingRange { min_idle, start, end, count, consumer })
+                } else {
+                    None
+                };
+                StreamCmd::XPending { key, group, range }
+            }
+            "xclaim" => {
+                let key = arg(1)?.to_string();
+                let group = arg(2)?.to_string();
+                let consumer = arg(3)?.to_string();
+                let min_idle = parse_ms(arg(4)?)?;
+                let mut i = 5;
+                let mut ids = vec![];
+                while let Some(id) = args.get(i).and_then(|s| parse_id(s, 0).ok()) {
+                    ids.push(id);
+                    i += 1;
+                }
+                if ids.is_empty() {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                let mut opts = ClaimOptions::default();
+                while i < args.len() {
+                    match &args[i].to_lowercase()[..] {
+                        "idle" => opts.idle = Some(parse_ms(arg(i + 1)?)?),
+                        "time" => opts.time = Some(parse_ms(arg(i + 1)?)?),
+                        "retrycount" => opts.retry_count = Some(parse_usize(arg(i + 1)?)? as u64),
+                        "lastid" => opts.last_id = Some(parse_id(arg(i + 1)?, 0)?),
+                        "force" => {
+                            opts.force = true;
+                            i += 1;
+                            continue;
+                        }
+                        "justid" => {
+                            opts.just_id = true;
+                            i += 1;
+                            continue;
+                        }
+                        _ => return Err(format!("ERR Unrecognized XCLAIM option '{}'", args[i]).into()),
+                    }
+                    i += 2;
+                }
+                StreamCmd::XClaim { key, group, consumer, min_idle, ids, opts }
+            }
+            "xautoclaim" => {
+                let key = arg(1)?.to_string();
+                let group = arg(2)?.to_string();
+                let consumer = arg(3)?.to_string();
+                let min_idle = parse_ms(arg(4)?)?;
+                let start = parse_range_start(arg(5)?)?;
+                let mut count = 100;
+                let mut just_id = false;
+                let mut i = 6;
+                while i < args.len() {
+                    match &args[i].to_lowercase()[..] {
+                        "count" => {
+                            count = parse_usize(arg(i + 1)?)?;
+                            if count == 0 {
+                                return Err("ERR COUNT must be > 0".into());
+                            }
+                            i += 2;
+                        }
+                        "justid" => {
+                            just_id = true;
+                            i += 1;
+                        }
+                        _ => return Err("ERR syntax error".into()),
+                    }
+                }
+                StreamCmd::XAutoClaim { key, group, consumer, min_idle, start, count, just_id }
+            }
+            "xinfo" => {
+                let sub = arg(1)?.to_lowercase();
+                let key = arg(2)?.to_string();
+                let cmd = match &sub[..] {
+                    "stream" if args.len() == 3 => XInfoCmd::Stream { key },
+                    "groups" => XInfoCmd::Groups { key },
+                    "consumers" => XInfoCmd::Consumers { key, group: arg(3)?.to_string() },
+                    _ => return Err(CommandError::NotSupport(args[1..].join(" ")).into()),
+                };
+                StreamCmd::XInfo(cmd)
+            }
+            _ => return Err(CommandError::ParseError(-8).into()),
+        };
+        Ok(RedisCommand::Stream(cmd))
+    }
+
+    fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
+        let now = mstime();
+        match self {
+            StreamCmd::XAdd { key, nomkstream, trim, id, fields } => {
+                let key_obj = RedisObject::create_string_object(key.clone());
+                if db.find(&key_obj).is_none() {
+                    if nomkstream {
+                        return Ok(Frame::Null);
+                    }
+                    db.add(key_obj.clone(), RedisObject::create_stream_object());
+                }
+                let s = Self::stream_mut(db.find(&key_obj).unwrap())?;
+                let id = s.next_id(id, now as u64)?;
+                s.append(id, fields);
+                let trimmed = trim.map_or(0, |trim| s.trim(&trim));
+                notify_keyspace_event(NOTIFY_STREAM, "xadd", &key, db.id);
+                if trimmed > 0 {
+                    notify_keyspace_event(NOTIFY_STREAM, "xtrim", &key, db.id);
+                }
+                db.signal_key_as_ready(&key);
+                Self::incr_dirty();
+                Ok(bulk(id.to_string()))
+            }
+            StreamCmd::XLen { key } => {
+                let len = Self::lookup(db, &key)?.map_or(0, |s| s.len());
+                Ok(Frame::Integer(len as i64))
+            }
+            StreamCmd::XRange { key, start, end, count } => {
+                let entries = Self::lookup(db, &key)?.map_or(vec![], |s| s.range(start, end, count, false));
+                Ok(entries_frame(entries))
+            }
+            StreamCmd::XRevRange { key, end, start, count } => {
+                let entries = Self::lookup(db, &key)?.map_or(vec![], |s| s.range(start, end, count, true));
+                Ok(entries_frame(entries))
+            }
+            StreamCmd::XDel { key, ids } => {
+                let Some(s) = Self::lookup(db, &key)? else { return Ok(Frame::Integer(0)) };
+                let deleted = ids.iter().filter(|id| s.delete(id)).count();
+                if deleted > 0 {
+                    notify_keyspace_event(NOTIFY_STREAM, "xdel", &key, db.id);
+                    Self::incr_dirty();
+                }
+                Ok(Frame::Integer(deleted as i64))
+            }
+            StreamCmd::XTrim { key, trim } => {
+                let Some(s) = Self::lookup(db, &key)? else { return Ok(Frame::Integer(0)) };
+                let deleted = s.trim(&trim);
+                if deleted > 0 {
+                    notify_keyspace_event(NOTIFY_STREAM, "xtrim", &key, db.id);
+                    Self::incr_dirty();
+                }
+                Ok(Frame::Integer(deleted as i64))
+            }
+            StreamCmd::XRead { count, keys, ids, .. } => {
+                let mut frame = Frame::array();
+                for (key, id) in keys.iter().zip(ids) {
+                    if let Some(res) = Self::read_key(db, key, id, count)? {
+                        push_frame(&mut frame, res);
+                    }
+                }
+                Ok(null_if_empty(frame))
+            }
+            StreamCmd::XReadGroup { group, consumer, count, noack, keys, ids, .. } => {
+                let mut frame = Frame::array();
+                for (key, id) in keys.iter().zip(ids) {
+                    if let Some(res) = Self::read_group_key(db, key, &group, &consumer, id, count, noack)? {
+                        push_frame(&mut frame, res);
+                    }
+                }
+                Ok(null_if_empty(frame))
+            }
+            StreamCmd::XAck { key, group, ids } => {
+                let acked = match Self::lookup(db, &key)? {
+                    Some(s) => s.ack(&group, &ids).unwrap_or(0),
+                    None => 0,
+                };
+                if acked > 0 {
+                    Self::incr_dirty();
+                }
+                Ok(Frame::Integer(acked as i64))
+            }
+            StreamCmd::XGroup(cmd) => Self::xgroup(db, cmd, now),
+            StreamCmd::XPending { key, group, range } => {
+                let s = Self::lookup(db, &key)?.ok_or_else(|| no_group(&key, &group))?;
+                let cg = s.cgroups.get(&group).ok_or_else(|| no_group(&key, &group))?;
+                let mut frame = Frame::array();
+                match range {
+                    None => {
+                        frame.push_int(cg.pel.len() as i64);
+                        match (cg.pel.keys().next(), cg.pel.keys().next_back()) {
+                            (Some(min), Some(max)) => {
+                                frame.push_bulk(Bytes::from(min.to_string().into_bytes()));
+                                frame.push_bulk(Bytes::from(max.to_string().into_bytes()));
+                                let mut consumers = Frame::array();
+                                for consumer in cg.consumers.values().filter(|c| !c.pel.is_empty()) {
+                                    let mut c = Frame::array();
+                                    c.push_bulk(Bytes::from(consumer.name.clone().into_bytes()));
+                                    c.push_bulk(Bytes::from(consumer.pel.len().to_string().into_bytes()));
+                                    push_frame(&mut consumers, c);
+                                }
+                                push_frame(&mut frame, consumers);
+                            }
+                            _ => {
+                                for _ in 0..3 {
+                                    push_frame(&mut frame, Frame::Null);
+                                }
+                            }
+                        }
+                    }
+                    Some(range) => {
+                        let ids: Box<dyn Iterator<Item = &StreamID>> = match &range.consumer {
+                            Some(consumer) => match cg.consumers.get(consumer) {
+                                Some(c) => Box::new(c.pel.range(range.start..=range.end)),
+                                None => Box::new(std::iter::empty()),
+                            },
+                            None => Box::new(cg.pel.range(range.start..=range.end).map(|(id, _)| id)),
+                        };
+                        if range.start <= range.end {
+                            for id in ids {
+                                if frame.get_len() >= range.count {
+                                    break;
+                                }
+                                let nack = &cg.pel[id];
+                                let idle = (now - nack.delivery_time).max(0);
+                                if range.min_idle.map_or(false, |min_idle| idle < min_idle) {
+                                    continue;
+                                }
+                                let mut entry = Frame::array();
+                                entry.push_bulk(Bytes::from(id.to_string().into_bytes()));
+                                entry.push_bulk(Bytes::from(nack.consumer.clone().into_bytes()));
+                                entry.push_int(idle);
+                                entry.push_int(nack.delivery_count as i64);
+                                push_frame(&mut frame, entry);
+                            }
+                        }
+                    }
+                }
+                Ok(frame)
+            }
+            StreamCmd::XClaim { key, group, consumer, min_idle, ids, opts } => {
+                let s = Self::lookup(db, &key)?.ok_or_else(|| no_group(&key, &group))?;
+                let claimed = s
+                    .claim(&group, &consumer, min_idle, &ids, &opts, now)
+                    .ok_or_else(|| no_group(&key, &group))?;
+                Self::incr_dirty();
+                Ok(delivered_frame(claimed, opts.just_id))
+            }
+            StreamCmd::XAutoClaim { key, group, consumer, min_idle, start, count, just_id } => {
+                let s = Self::lookup(db, &key)?.ok_or_else(|| no_group(&key, &group))?;
+                let (next, claimed, deleted) = s
+                    .auto_claim(&group, &consumer, min_idle, start, count, just_id, now)
+                    .ok_or_else(|| no_group(&key, &group))?;
+                Self::incr_dirty();
+                let mut frame = Frame::array();
+                frame.push_bulk(Bytes::from(next.to_string().into_bytes()));
+                push_frame(&mut frame, delivered_frame(claimed, just_id));
+                push_frame(&mut frame, Frame::Array(deleted.iter().map(|id| bulk(id.to_string())).collect()));
+                Ok(frame)
+            }
+            StreamCmd::XInfo(cmd) => Self::xinfo(db, cmd, now),
+        }
+    }
+}
+
+impl StreamCmd {
+    fn stream_mut(o: &mut RedisObject) -> crate::Result<&mut Stream> {
+        if o.object_type != OBJ_STREAM {
+            return Err(ObjectTypeError(-8).into());
+        }
+        match &mut o.ptr {
+            RedisValue::Stream(s) => Ok(s),
+            _ => Err(ObjectTypeError(-8).into()),
+        }
+    }
+
+    /// The stream at `key`, `None` if the key does not exist
+    fn lookup<'a>(db: &'a mut RedisDb, key: &str) -> crate::Result<Option<&'a mut Stream>> {
+        let key_obj = RedisObject::create_string_object(key.to_string());
+        match db.find(&key_obj) {
+            Some(o) => Ok(Some(Self::stream_mut(o)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn incr_dirty() {
+        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty(); }
+    }
+
+    /// Whether the command may wait for data, see `RedisDb::block_for_keys`. XREADGROUP
+    /// only blocks when it asks for new messages on every stream.
+    pub fn is_blocking(&self) -> bool {
+        match self {
+            StreamCmd::XRead { block, .. } => block.is_some(),
+            StreamCmd::XReadGroup { block, ids, .. } => block.is_some() && ids.iter().all(|id| *id == StreamReadId::New),
+            _ => false,
+        }
+    }
+
+    /// Turns XREAD / XREADGROUP BLOCK into what the DB task waits on: the keys, the
+    /// timeout in seconds and the read. `$` is resolved here, so only entries added
+    /// after the call are returned.
+    pub fn block_request(self, db: &mut RedisDb) -> crate::Result<(Vec<String>, f64, BlockOp)> {
+        match self {
+            StreamCmd::XRead { count, block, keys, ids } => {
+                let mut streams = Vec::with_capacity(keys.len());
+                for (key, id) in keys.iter().zip(ids) {
+                    let s = Self::lookup(db, key)?;
+                    streams.push((key.clone(), id.resolve(s.as_deref())));
+                }
+                let timeout = block.unwrap_or(0) as f64 / 1000f64;
+                Ok((keys, timeout, BlockOp::XRead { streams, count }))
+            }
+            StreamCmd::XReadGroup { group, consumer, count, block, noack, keys, .. } => {
+                let timeout = block.unwrap_or(0) as f64 / 1000f64;
+                Ok((keys, timeout, BlockOp::XReadGroup { group, consumer, count, noack }))
+            }
+            _ => Err("ERR not a blocking command".into()),
+        }
+    }
+
+    /// `[key, [entries]]` with the entries of `key` after `id`, `None` if there are none
+    pub fn read_key(db: &mut RedisDb, key: &str, id: StreamReadId, count: Option<usize>) -> crate::Result<Option<Frame>> {
+        let Some(s) = Self::lookup(db, key)? else { return Ok(None) };
+        let entries = s.entries_after(id.resolve(Some(s)), count);
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Frame::Array(vec![bulk(key.to_string()), entries_frame(entries)])))
+    }
+
+    /// `[key, [entries]]` as delivered to `consumer`. New entries (`>`) return `None`
+    /// when there are none; the history of the consumer is always returned.
+    pub fn read_group_key(
+        db: &mut RedisDb,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        id: StreamReadId,
+        count: Option<usize>,
+        noack: bool,
+    ) -> crate::Result<Option<Frame>> {
+        let no_group = || -> crate::Error {
+            format!("NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option", key, group).into()
+        };
+        let s = Self::lookup(db, key)?.ok_or_else(no_group)?;
+        let now = mstime();
+        let entries = match id {
+            StreamReadId::New => {
+                let entries = s.read_group_new(group, consumer, count, noack, now).ok_or_else(no_group)?;
+                if entries.is_empty() {
+                    return Ok(None);
+                }
+                if !noack {
+                    Self::incr_dirty();
+                }
+                entries_frame(entries)
+            }
+            id => {
+                let start = id.resolve(Some(s));
+                let entries = s.read_group_history(group, consumer, start, count, now).ok_or_else(no_group)?;
+                delivered_frame(entries, false)
+            }
+        };
+        Ok(Some(Frame::Array(vec![bulk(key.to_string()), entries])))
+    }
+
+    fn xgroup(db: &mut RedisDb, cmd: XGroupCmd, now: i64) -> crate::Result<Frame> {
+        let require_key = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+        match cmd {
+            XGroupCmd::Create { key, group, id, mkstream, entries_read } => {
+                let key_obj = RedisObject::create_string_object(key.clone());
+                if db.find(&key_obj).is_none() {
+                    if !mkstream {
+                        return Err(require_key.into());
+                    }
+                    db.add(key_obj.clone(), RedisObject::create_stream_object());
+                }
+                let s = Self::stream_mut(db.find(&key_obj).unwrap())?;
+                let id = id.resolve(Some(s));
+                if !s.create_group(&group, id, entries_read) {
+                    return Err("BUSYGROUP Consumer Group name already exists".into());
+                }
+                notify_keyspace_event(NOTIFY_STREAM, "xgroup-create", &key, db.id);
+                Self::incr_dirty();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            XGroupCmd::SetId { key, group, id, entries_read } => {
+                let s = Self::lookup(db, &key)?.ok_or(require_key)?;
+                let id = id.resolve(Some(s));
+                if !s.set_group_id(&group, id, entries_read) {
+                    return Err(format!("NOGROUP No such consumer group '{}' for key name '{}'", group, key).into());
+                }
+                notify_keyspace_event(NOTIFY_STREAM, "xgroup-setid", &key, db.id);
+                Self::incr_dirty();
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            XGroupCmd::Destroy { key, group } => {
+                let s = Self::lookup(db, &key)?.ok_or(require_key)?;
+                if !s.destroy_group(&group) {
+                    return Ok(Frame::Integer(0));
+                }
+                notify_keyspace_event(NOTIFY_STREAM, "xgroup-destroy", &key, db.id);
+                Self::incr_dirty();
+                Ok(Frame::Integer(1))
+            }
+            XGroupCmd::CreateConsumer { key, group, consumer } => {
+                let s = Self::lookup(db, &key)?.ok_or(require_key)?;
+                let created = s
+                    .create_consumer(&group, &consumer, now)
+                    .ok_or_else(|| format!("NOGROUP No such consumer group '{}' for key name '{}'", group, key))?;
+                if created {
+                    notify_keyspace_event(NOTIFY_STREAM, "xgroup-createconsumer", &key, db.id);
+                    Self::incr_dirty();
+                }
+                Ok(Frame::Integer(created as i64))
+            }
+            XGroupCmd::DelConsumer { key, group, consumer } => {
+                let s = Self::lookup(db, &key)?.ok_or(require_key)?;
+                let pending = s
+                    .delete_consumer(&group, &consumer)
+                    .ok_or_else(|| format!("NOGROUP No such consumer group '{}' for key name '{}'", group, key))?;
+                notify_keyspace_event(NOTIFY_STREAM, "xgroup-delconsumer", &key, db.id);
+                Self::incr_dirty();
+                Ok(Frame::Integer(pending as i64))
+            }
+        }
+    }
+
+    fn xinfo(db: &mut RedisDb, cmd: XInfoCmd, now: i64) -> crate::Result<Frame> {
+        let no_key = "ERR no such key";
+        match cmd {
+            XInfoCmd::Stream { key } => {
+                let s = Self::lookup(db, &key)?.ok_or(no_key)?;
+                let mut frame = Frame::array();
+                let mut field = |name: &str, value: Frame| {
+                    push_frame(&mut frame, bulk(name.to_string()));
+                    push_frame(&mut frame, value);
+                };
+                field("length", Frame::Integer(s.len() as i64));
+                field("last-generated-id", bulk(s.last_id.to_string()));
+                field("max-deleted-entry-id", bulk(s.max_deleted_entry_id.to_string()));
+                field("entries-added", Frame::Integer(s.entries_added as i64));
+                field("recorded-first-entry-id", bulk(s.first_id().to_string()));
+                field("groups", Frame::Integer(s.cgroups.len() as i64));
+                field("first-entry", s.first_entry().map_or(Frame::Null, entry_frame));
+                field("last-entry", s.last_entry().map_or(Frame::Null, entry_frame));
+                Ok(frame)
+            }
+            XInfoCmd::Groups { key } => {
+                let s = Self::lookup(db, &key)?.ok_or(no_key)?;
+                let mut frame = Frame::array();
+                for (name, cg) in &s.cgroups {
+                    let entries_read = if cg.entries_read == SCG_INVALID_ENTRIES_READ {
+                        Frame::Null
+                    } else {
+                        Frame::Integer(cg.entries_read)
+                    };
+                    let lag = s.group_lag(cg).map_or(Frame::Null, |lag| Frame::Integer(lag as i64));
+                    push_frame(&mut frame, Frame::Array(vec![
+                        bulk("name".to_string()),
+                        bulk(name.clone()),
+                        bulk("consumers".to_string()),
+                        Frame::Integer(cg.consumers.len() as i64),
+                        bulk("pending".to_string()),
+                        Frame::Integer(cg.pel.len() as i64),
+                        bulk("last-delivered-id".to_string()),
+                        bulk(cg.last_id.to_string()),
+                        bulk("entries-read".to_string()),
+                        entries_read,
+                        bulk("lag".to_string()),
+                        lag,
+                    ]));
+                }
+                Ok(frame)
+            }
+            XInfoCmd::Consumers { key, group } => {
+                let s = Self::lookup(db, &key)?.ok_or_else(|| no_group(&key, &group))?;
+                let cg = s.cgroups.get(&group).ok_or_else(|| no_group(&key, &group))?;
+                let mut frame = Frame::array();
+                for consumer in cg.consumers.values() {
+                    let inactive = if consumer.active_time < 0 { -1 } else { (now - consumer.active_time).max(0) };
+                    push_frame(&mut frame, Frame::Array(vec![
+                        bulk("name".to_string()),
+                        bulk(consumer.name.clone()),
+                        bulk("pending".to_string()),
+                        Frame::Integer(consumer.pel.len() as i64),
+                        bulk("idle".to_string()),
+                        Frame::Integer((now - consumer.seen_time).max(0)),
+                        bulk("inactive".to_string()),
+                        Frame::Integer(inactive),
+                    ]));
+                }
+                Ok(frame)
+            }
+        }
+    }
+}
+
+fn no_group(key: &str, group: &str) -> crate::Error {
+    format!("NOGROUP No such key '{}' or consumer group '{}'", key, group).into()
+}
+
+fn bulk(s: String) -> Frame {
+    Frame::Bulk(Bytes::from(s.into_bytes()))
+}
+
+fn push_frame(frame: &mut Frame, value: Frame) {
+    if let Frame::Array(vec) = frame {
+        vec.push(value);
+    }
+}
+
+fn null_if_empty(frame: Frame) -> Frame {
+    match frame {
+        Frame::Array(vec) if vec.is_empty() => Frame::Null,
+        frame => frame,
+    }
+}
+
+/// `[id, [field, value, ...]]`
+fn entry_frame((id, fields): (StreamID, StreamFields)) -> Frame {
+    let mut values = Frame::array();
+    for (field, value) in fields {
+        values.push_bulk(Bytes::from(field.into_bytes()));
+        values.push_bulk(Bytes::from(value.into_bytes()));
+    }
+    Frame::Array(vec![bulk(id.to_string()), values])
+}
+
+fn entries_frame(entries: Vec<(StreamID, StreamFields)>) -> Frame {
+    Frame::Array(entries.into_iter().map(entry_frame).collect())
+}
+
+/// Entries delivered from a PEL: a deleted entry is replied as `[id, nil]`, and only
+/// the IDs are replied with JUSTID.
+fn delivered_frame(entries: Vec<DeliveredEntry>, just_id: bool) -> Frame {
+    Frame::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| match fields {
+                _ if just_id => bulk(id.to_string()),
+                Some(fields) => entry_frame((id, fields)),
+                None => Frame::Array(vec![bulk(id.to_string()), Frame::Null]),
+            })
+            .collect(),
+    )
+}
+
+fn push_count_arg(args: &mut Vec<String>, count: Option<usize>) {
+    if let Some(count) = count {
+        args.push("count".to_string());
+        args.push(count.to_string());
+    }
+}
+
+fn push_trim_args(args: &mut Vec<String>, trim: &StreamTrim) {
+    match trim.strategy {
+        TrimStrategy::MaxLen(max_len) => {
+            args.push("maxlen".to_string());
+            args.push(if trim.approx { "~" } else { "=" }.to_string());
+            args.push(max_len.to_string());
+        }
+        TrimStrategy::MinId(min_id) => {
+            args.push("minid".to_string());
+            args.push(if trim.approx { "~" } else { "=" }.to_string());
+            args.push(min_id.to_string());
+        }
+    }
+    if trim.limit > 0 {
+        args.push("limit".to_string());
+        args.push(trim.limit.to_string());
+    }
+}
+
+/// Parses `MAXLEN|MINID [=|~] threshold [LIMIT count]` starting at `*i`, leaving `*i`
+/// on the first argument after it.
+fn parse_trim(args: &[String], i: &mut usize) -> crate::Result<StreamTrim> {
+    let syntax = || -> crate::Error { "ERR syntax error".into() };
+    let strategy_name = args.get(*i).ok_or_else(syntax)?.to_lowercase();
+    *i += 1;
+    let mut approx = false;
+    match args.get(*i).map(|s| s.as_str()) {
+        Some("~") => {
+            approx = true;
+            *i += 1;
+        }
+        Some("=") => *i += 1,
+        _ => {}
+    }
+    let threshold = args.get(*i).ok_or_else(syntax)?;
+    *i += 1;
+    let strategy = match &strategy_name[..] {
+        "maxlen" => {
+            let max_len: i64 = threshold.parse().map_err(|_| "ERR value is not an integer or out of range")?;
+            if max_len < 0 {
+                return Err("ERR The MAXLEN argument must be >= 0.".into());
+            }
+            TrimStrategy::MaxLen(max_len as usize)
+        }
+        "minid" => TrimStrategy::MinId(parse_id(threshold, 0)?),
+        _ => return Err(syntax()),
+    };
+    let mut limit = 0;
+    if args.get(*i).map_or(false, |s| s.eq_ignore_ascii_case("limit")) {
+        limit = parse_usize(args.get(*i + 1).ok_or_else(syntax)?)?;
+        *i += 2;
+        if !approx {
+            return Err("ERR syntax error, LIMIT cannot be used without the special ~ option".into());
+        }
+    }
+    Ok(StreamTrim { strategy, approx, limit })
+}
+
+fn parse_id(s: &str, missing_seq: u64) -> crate::Result<StreamID> {
+    StreamID::parse(s, missing_seq).ok_or_else(|| "ERR Invalid stream ID specified as stream command argument".into())
+}
+
+fn parse_id_spec(s: &str) -> crate::Result<StreamIdSpec> {
+    if s == "*" {
+        return Ok(StreamIdSpec::Auto);
+    }
+    if let Some(ms) = s.strip_suffix("-*") {
+        let ms = ms.parse().map_err(|_| "ERR Invalid stream ID specified as stream command argument")?;
+        return Ok(StreamIdSpec::AutoSeq(ms));
+    }
+    Ok(StreamIdSpec::Explicit(parse_id(s, 0)?))
+}
+
+/// Start of an interval: `-`, an ID, or `(ID` for an exclusive bound
+fn parse_range_start(s: &str) -> crate::Result<StreamID> {
+    match s {
+        "-" => Ok(StreamID::MIN),
+        "+" => Ok(StreamID::MAX),
+        _ => match s.strip_prefix('(') {
+            Some(id) => parse_id(id, 0)?.incr().ok_or_else(|| "ERR invalid start ID for the interval".into()),
+            None => parse_id(s, 0),
+        },
+    }
+}
+
+/// End of an interval: `+`, an ID (a bare time includes every sequence), or `(ID`
+fn parse_range_end(s: &str) -> crate::Result<StreamID> {
+    match s {
+        "-" => Ok(StreamID::MIN),
+        "+" => Ok(StreamID::MAX),
+        _ => match s.strip_prefix('(') {
+            Some(id) => parse_id(id, u64::MAX)?.decr().ok_or_else(|| "ERR invalid end ID for the interval".into()),
+            None => parse_id(s, u64::MAX),
+        },
+    }
+}
+
+fn parse_usize(s: &str) -> crate::Result<usize> {
+    s.parse().map_err(|_| "ERR value is not an integer or out of range".into())
+}
+
+fn parse_ms(s: &str) -> crate::Result<i64> {
+    match s.parse::<i64>() {
+        Ok(ms) if ms >= 0 => Ok(ms),
+        _ => Err("ERR Invalid min-idle-time argument for XCLAIM".into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+    use crate::parser::frame::Frame;
+    use bytes::Bytes;
+
+    fn parse(args: &[&str]) -> crate::Result<RedisCommand> {
+        let frame = Frame::Array(args.iter().map(|a| Frame::Bulk(Bytes::from(a.to_string()))).collect());
+        RedisCommand::from_frame("", frame)
+    }
+
+    #[test]
+    fn cmd_to_frame() -> crate::Result<()> {
+        let cmds: Vec<&[&str]> = vec![
+            &["xadd", "s", "nomkstream", "maxlen", "~", "10", "limit", "5", "*", "f", "v"],
+            &["xadd", "s", "minid", "=", "5-0", "7-*", "f", "v", "g", "w"],
+            &["xrange", "s", "(1-0", "5", "count", "2"],
+            &["xrevrange", "s", "+", "-"],
+            &["xread", "count", "2", "block", "100", "streams", "a", "b", "$", "0-0"],
+            &["xreadgroup", "group", "g", "c", "noack", "streams", "a", ">"],
+            &["xgroup", "create", "s", "g", "$", "mkstream", "entriesread", "3"],
+            &["xpending", "s", "g", "idle", "10", "-", "+", "10", "c"],
+            &["xclaim", "s", "g", "c", "10", "1-0", "2-0", "retrycount", "3", "force", "justid"],
+            &["xautoclaim", "s", "g", "c", "10", "0-0", "count", "5"],
+            &["xinfo", "consumers", "s", "g"],
+        ];
+        for args in cmds {
+            let cmd = parse(args)?;
+            let expect = format!("{:?}", cmd);
+            let cmd = RedisCommand::from_frame("", cmd.into_frame())?;
+            assert_eq!(format!("{:?}", cmd), expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(parse(&["xadd", "s", "*", "f"]).is_err());
+        assert!(parse(&["xadd", "s", "maxlen", "10", "limit", "5", "*", "f", "v"]).is_err());
+        assert!(parse(&["xread", "streams", "a", "b", "$"]).is_err());
+        assert!(parse(&["xread", "streams", "a", ">"]).is_err());
+        assert!(parse(&["xreadgroup", "group", "g", "c", "streams", "a", "$"]).is_err());
+        assert!(parse(&["xrange", "s", "(-", "+"]).is_err());
+    }
+}
@@ -7,6 +7,8 @@ use crate::parser::cmd::error::CommandError;
 use crate::parser::cmd::error::CommandError::ObjectTypeError;
 use crate::parser::cmd::string::StringCmd::Strlen;
 use crate::parser::frame::Frame;
+use crate::db::expire::mstime;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_GENERIC, NOTIFY_STRING};
 use crate::server::REDIS_SERVER;
 
 #[allow(dead_code)]
@@ -18,8 +20,11 @@ pub enum StringCmd {
     Get { key: String},
     /// Sets the string value of a key, ignoring its type. The key is created if it doesn't exist
     Set {key: String, value: String},
-    SetEX { key: String, ttl: i128 },
-    SetPX { key: String, ttl: i128 },
+    /// Sets the string value and expiration time (ttl in milliseconds) of a key
+    SetEX { key: String, ttl: i128, value: String },
+    SetPX { key: String, ttl: i128, value: String },
+    /// Sets the string value of a key and the unix time in milliseconds at which it expires
+    SetPXAt { key: String, when: i64, value: String },
     SetNX { key: String, value: String },
     SetXX { key: String, value: String },
     /// Returns the length of a string value
@@ -43,15 +48,24 @@ impl CommandStrategy for StringCmd {
                 frame.push_bulk(Bytes::from(key.into_bytes()));
                 frame.push_bulk(Bytes::from(field.into_bytes()));
             }
-            StringCmd::SetEX {key, ttl} => {
+            StringCmd::SetEX {key, ttl, value} => {
                 frame.push_bulk(Bytes::from("setex".as_bytes()));
                 frame.push_bulk(Bytes::from(key.into_bytes()));
-                frame.push_bulk(Bytes::from(ttl.to_string().into_bytes()));
+                frame.push_bulk(Bytes::from((ttl / 1000).to_string().into_bytes()));
+                frame.push_bulk(Bytes::from(value.into_bytes()));
             }
-            StringCmd::SetPX {key, ttl} => {
+            StringCmd::SetPX {key, ttl, value} => {
                 frame.push_bulk(Bytes::from("setpx".as_bytes()));
                 frame.push_bulk(Bytes::from(key.into_bytes()));
                 frame.push_bulk(Bytes::from(ttl.to_string().into_bytes()));
+                frame.push_bulk(Bytes::from(value.into_bytes()));
+            }
+            StringCmd::SetPXAt {key, when, value} => {
+                frame.push_bulk(Bytes::from("set".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                frame.push_bulk(Bytes::from(value.into_bytes()));
+                frame.push_bulk(Bytes::from("pxat".as_bytes()));
+                frame.push_bulk(Bytes::from(when.to_string().into_bytes()));
             }
             StringCmd::SetNX {key, value} => {
                 frame.push_bulk(Bytes::from("setnx".as_bytes()));
@@ -91,12 +105,14 @@ impl CommandStrategy for StringCmd {
             "setex" => {
                 let key = frame.get_frame_by_index(1).ok_or("command error 'setex'")?.to_string();
                 let ttl: i128 = frame.get_frame_by_index(2).ok_or("command error 'setex'")?.to_string().parse()?;
-                Ok(RedisCommand::String(StringCmd::SetEX {key, ttl: ttl * 1000}))
+                let value = frame.get_frame_by_index(3).ok_or("command error 'setex'")?.to_string();
+                Ok(RedisCommand::String(StringCmd::SetEX {key, ttl: ttl * 1000, value}))
             }
             "setpx" => {
                 let key = frame.get_frame_by_index(1).ok_or("command error 'setpx'")?.to_string();
                 let ttl: i128 = frame.get_frame_by_index(2).ok_or("command error 'setpx'")?.to_string().parse()?;
-                Ok(RedisCommand::String(StringCmd::SetPX {key, ttl}))
+                let value = frame.get_frame_by_index(3).ok_or("command error 'setpx'")?.to_string();
+                Ok(RedisCommand::String(StringCmd::SetPX {key, ttl, value}))
             }
             "setnx" => {
                 let key = frame.get_frame_by_index(1).ok_or("command error 'setnx'")?.to_string();
@@ -115,7 +131,24 @@ impl CommandStrategy for StringCmd {
             "set" => {
                 let key = frame.get_frame_by_index(1).ok_or("command error 'set'")?.to_string();
                 let value = frame.get_frame_by_index(2).ok_or("command error 'set'")?.to_string();
-                Ok(RedisCommand::String(StringCmd::Set {key, value}))
+                if frame.get_len() == 3 {
+                    return Ok(RedisCommand::String(StringCmd::Set {key, value}));
+                }
+                // SET key value EX seconds | PX milliseconds | EXAT unix-seconds | PXAT unix-milliseconds
+                if frame.get_len() != 5 {
+                    return Err("ERR syntax error".into());
+                }
+                let option = frame.get_frame_by_index(3).ok_or("command error 'set'")?.to_string().to_lowercase();
+                let n: i64 = frame.get_frame_by_index(4).ok_or("command error 'set'")?.to_string().parse()
+                    .map_err(|_| "ERR value is not an integer or out of range")?;
+                let cmd = match option.as_str() {
+                    "ex" => StringCmd::SetEX {key, ttl: n as i128 * 1000, value},
+                    "px" => StringCmd::SetPX {key, ttl: n as i128, value},
+                    "exat" => StringCmd::SetPXAt {key, when: n.saturating_mul(1000), value},
+                    "pxat" => StringCmd::SetPXAt {key, when: n, value},
+                    _ => return Err("ERR syntax error".into()),
+                };
+                Ok(RedisCommand::String(cmd))
             }
             "strlen" => {
                 let s = frame.get_frame_by_index(1).ok_or("command error 'strlen'")?.to_string();
@@ -128,29 +161,28 @@ impl CommandStrategy for StringCmd {
     fn apply(self, db: &mut RedisDb) -> crate::Result<Frame> {
         match self {
             StringCmd::Append { key, field } => {
-                let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let o = db.find(&key_obj);
                 if let Some(o) = o {
                     match &mut o.ptr {
                         RedisValue::String(s) => {
                             s.push_str(&field);
-                            unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
-                            Ok(Frame::Simple("OK".to_string()))
                         }
                         _ => {
-                            Err(ObjectTypeError(-1).into())
+                            return Err(ObjectTypeError(-1).into())
                         }
                     }
                 } else {
                     let value = RedisObject::create_string_object(field);
-                    db.add(key, value);
-                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
-                    Ok(Frame::Simple("OK".to_string()))
+                    db.add(key_obj, value);
                 }
+                unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                notify_keyspace_event(NOTIFY_STRING, "append", &key, db.id);
+                Ok(Frame::Simple("OK".to_string()))
             },
             StringCmd::Get {key} => {
                 let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
+                let o = db.lookup_read(&key);
                 if let Some(o) = o {
                     match &o.ptr {
                         RedisValue::String(s) => {
@@ -163,53 +195,45 @@ impl CommandStrategy for StringCmd {
                 }
             }
             StringCmd::Set { key, value} => {
-                let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
-                if let Some(o) = o {
-                    match &mut o.ptr {
-                        RedisValue::String(s) => {
-                            *s = value;
-                            unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
-                            Ok(Frame::Simple("OK".to_string()))
-                        }
-                        _ => Err(ObjectTypeError(-4).into())
-                    }
-                } else {
-                    let v = RedisObject::create_string_object(value);
-                    db.add(key, v);
-                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
-                    Ok(Frame::Simple("OK".to_string()))
-                }
+                Self::set_generic(db, key, value, None)?;
+                Ok(Frame::Simple("OK".to_string()))
             }
             StringCmd::SetNX {key, value} => {
-                let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let o = db.find(&key_obj);
                 if let Some(_o) = o {
                     Ok(Frame::Simple("key exists".to_string()))
                 } else {
                     let value = RedisObject::create_string_object(value);
-                    db.add(key, value);
+                    db.add(key_obj, value);
                     unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                    notify_keyspace_event(NOTIFY_STRING, "set", &key, db.id);
                     Ok(Frame::Simple("OK".to_string()))
                 }
             }
             StringCmd::SetXX {key, value} => {
-                let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
+                let key_obj = RedisObject::create_string_object(key.clone());
+                let o = db.find(&key_obj);
                 if let Some(_o) = o {
-                    let value = RedisObject::create_string_object(value);
-                    db.set_val(&key, value);
-                    unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                    Self::set_generic(db, key, value, None)?;
                     Ok(Frame::Simple("OK".to_string()))
                 } else {
                     Ok(Frame::Simple("key not exists".to_string()))
                 }
             }
-            StringCmd::SetPX {..} => {
-                Err(CommandError::NotSupport("setpx".to_string()).into())
+            StringCmd::SetPX {key, ttl, value} | StringCmd::SetEX {key, ttl, value} => {
+                if ttl <= 0 {
+                    return Err("ERR invalid expire time in 'set' command".into());
+                }
+                Self::set_generic(db, key, value, Some(mstime() + ttl as i64))?;
+                Ok(Frame::Simple("OK".to_string()))
             }
-            StringCmd::SetEX {..} => {
-                Err(CommandError::NotSupport("setex".to_string()).into())
+            StringCmd::SetPXAt {key, when, value} => {
+                if when <= 0 {
+                    return Err("ERR invalid expire time in 'set' command".into());
+                }
+                Self::set_generic(db, key, value, Some(when))?;
+                Ok(Frame::Simple("OK".to_string()))
             }
             StringCmd::Strlen { s } => {
                 let len = s.len();
@@ -218,4 +242,29 @@ impl CommandStrategy for StringCmd {
             _ => Err(CommandError::ParseError(-2).into())
         }
     }
+}
+
+impl StringCmd {
+    /// SET and its variants: overwrites the value (discarding any previous TTL) and
+    /// sets the new expire time if there is one.
+    fn set_generic(db: &mut RedisDb, key: String, value: String, expire: Option<i64>) -> crate::Result<()> {
+        let key_obj = RedisObject::create_string_object(key.clone());
+        match db.find(&key_obj) {
+            Some(o) => match &mut o.ptr {
+                RedisValue::String(s) => *s = value,
+                _ => db.set_val(&key_obj, RedisObject::create_string_object(value)),
+            },
+            None => {
+                db.add(key_obj, RedisObject::create_string_object(value));
+            }
+        }
+        db.remove_expire(&key);
+        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+        notify_keyspace_event(NOTIFY_STRING, "set", &key, db.id);
+        if let Some(when) = expire {
+            db.set_expire(&key, when);
+            notify_keyspace_event(NOTIFY_GENERIC, "expire", &key, db.id);
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
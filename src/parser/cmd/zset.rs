@@ -1,10 +1,14 @@
 use bytes::Bytes;
 use crate::db::data_structure::dict::dict::Value;
 use crate::db::db::RedisDb;
-use crate::db::object::{OBJ_ENCODING_SKIPLIST, RedisObject, RedisValue};
+use crate::db::blocked::BlockOp;
+use crate::db::data_structure::zset::{zzl_delete, zzl_find, zzl_get_score, zzl_insert, zzl_len, zzl_to_zset};
+use crate::db::object::{OBJ_ENCODING_LISTPACK, OBJ_ENCODING_SKIPLIST, OBJ_ZSET, RedisObject, RedisValue, zset_max_listpack_entries, zset_max_listpack_value};
 use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
 use crate::parser::cmd::error::CommandError;
+use crate::parser::cmd::list::{parse_count, parse_timeout};
 use crate::parser::frame::Frame;
+use crate::server::notify::{notify_keyspace_event, NOTIFY_GENERIC, NOTIFY_ZSET};
 use crate::server::REDIS_SERVER;
 
 #[allow(dead_code)]
@@ -24,6 +28,18 @@ pub enum SortedCmd {
     ZInterCard,
     /// Stores the intersect of multiple sorted sets in a key
     ZInterStore,
+    /// Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped
+    ZPopMin {key: String, count: Option<usize>},
+    /// Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped
+    ZPopMax {key: String, count: Option<usize>},
+    /// Returns the highest- or lowest-scoring members from one or more sorted sets after removing them
+    ZMPop {keys: Vec<String>, max: bool, count: usize},
+    /// Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise
+    BZPopMin {keys: Vec<String>, timeout: f64},
+    /// Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise
+    BZPopMax {keys: Vec<String>, timeout: f64},
+    /// Removes and returns a member by score from one or more sorted sets. Blocks until a member is available otherwise
+    BZMPop {keys: Vec<String>, max: bool, count: usize, timeout: f64},
 }
 
 impl CommandStrategy for SortedCmd {
@@ -54,6 +70,61 @@ impl CommandStrategy for SortedCmd {
                 frame.push_bulk(Bytes::from(member.into_bytes()));
                 frame
             }
+            SortedCmd::ZPopMin { key, count } => {
+                frame.push_bulk(Bytes::from("zpopmin".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                if let Some(count) = count {
+                    frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+                }
+                frame
+            }
+            SortedCmd::ZPopMax { key, count } => {
+                frame.push_bulk(Bytes::from("zpopmax".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+                if let Some(count) = count {
+                    frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+                }
+                frame
+            }
+            SortedCmd::ZMPop { keys, max, count } => {
+                frame.push_bulk(Bytes::from("zmpop".as_bytes()));
+                frame.push_bulk(Bytes::from(keys.len().to_string().into_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(if max { "max" } else { "min" }.as_bytes()));
+                frame.push_bulk(Bytes::from("count".as_bytes()));
+                frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+                frame
+            }
+            SortedCmd::BZPopMin { keys, timeout } => {
+                frame.push_bulk(Bytes::from("bzpopmin".as_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+                frame
+            }
+            SortedCmd::BZPopMax { keys, timeout } => {
+                frame.push_bulk(Bytes::from("bzpopmax".as_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+                frame
+            }
+            SortedCmd::BZMPop { keys, max, count, timeout } => {
+                frame.push_bulk(Bytes::from("bzmpop".as_bytes()));
+                frame.push_bulk(Bytes::from(timeout.to_string().into_bytes()));
+                frame.push_bulk(Bytes::from(keys.len().to_string().into_bytes()));
+                for key in keys {
+                    frame.push_bulk(Bytes::from(key.into_bytes()));
+                }
+                frame.push_bulk(Bytes::from(if max { "max" } else { "min" }.as_bytes()));
+                frame.push_bulk(Bytes::from("count".as_bytes()));
+                frame.push_bulk(Bytes::from(count.to_string().into_bytes()));
+                frame
+            }
             _ => Frame::Null,
         }
     }
@@ -85,6 +156,43 @@ impl CommandStrategy for SortedCmd {
                 let member = frame.get_frame_by_index(2).ok_or("command error 'zscore'")?.to_string();
                 Ok(RedisCommand::SortSet(SortedCmd::ZScore {key, member}))
             }
+            "zpopmin" | "zpopmax" => {
+                let key = frame.get_frame_by_index(1).ok_or(format!("command error '{}'", name))?.to_string();
+                let count = match frame.get_frame_by_index(2) {
+                    Some(count) => Some(count.to_string().parse()?),
+                    None => None,
+                };
+                if name == "zpopmin" {
+                    Ok(RedisCommand::SortSet(SortedCmd::ZPopMin {key, count}))
+                } else {
+                    Ok(RedisCommand::SortSet(SortedCmd::ZPopMax {key, count}))
+                }
+            }
+            "zmpop" => {
+                let (keys, max, count) = parse_mpop_args(name, &frame, 1)?;
+                Ok(RedisCommand::SortSet(SortedCmd::ZMPop {keys, max, count}))
+            }
+            "bzpopmin" | "bzpopmax" => {
+                let len = frame.get_len();
+                if len < 3 {
+                    return Err(CommandError::ArgsErr(name.to_string()).into());
+                }
+                let mut keys = Vec::with_capacity(len - 2);
+                for i in 1..len - 1 {
+                    keys.push(frame.get_frame_by_index(i).ok_or(format!("command error '{}'", name))?.to_string());
+                }
+                let timeout = parse_timeout(&frame.get_frame_by_index(len - 1).ok_or(format!("command error '{}'", name))?.to_string())?;
+                if name == "bzpopmin" {
+                    Ok(RedisCommand::SortSet(SortedCmd::BZPopMin {keys, timeout}))
+                } else {
+                    Ok(RedisCommand::SortSet(SortedCmd::BZPopMax {keys, timeout}))
+                }
+            }
+            "bzmpop" => {
+                let timeout = parse_timeout(&frame.get_frame_by_index(1).ok_or("command error 'bzmpop'")?.to_string())?;
+                let (keys, max, count) = parse_mpop_args(name, &frame, 2)?;
+                Ok(RedisCommand::SortSet(SortedCmd::BZMPop {keys, max, count, timeout}))
+            }
             _ => Err(CommandError::ParseError(-1).into())
         }
     }
@@ -102,30 +210,32 @@ impl CommandStrategy for SortedCmd {
                         Self::zadd(o, arg.clone(), score, ele);
                     }
                 } else {
-                    let mut z_obj = RedisObject::create_zset_object();
+                    let mut z_obj = RedisObject::create_zset_listpack_object();
                     for i in (0..len - 1).step_by(2) {
                         let score: f64 = values[i].clone().parse()?;
                         let ele = values[i + 1].clone();
                         Self::zadd(&mut z_obj, arg.clone(), score, ele);
                     }
-                    let o = db.add(key, z_obj);
+                    let o = db.add(key.clone(), z_obj);
                     if o.is_none() {
                         return Err(CommandError::ExecuteFail("zadd".to_string()).into());
                     }
                 }
+                if let RedisValue::String(key) = &key.ptr {
+                    db.signal_key_as_ready(key);
+                    let event = if arg.as_deref() == Some("incr") { "zincr" } else { "zadd" };
+                    notify_keyspace_event(NOTIFY_ZSET, event, key, db.id);
+                }
                 Ok(Frame::Simple((len >> 1).to_string()))
             }
             SortedCmd::ZCard {key} => {
                 let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
+                let o = db.lookup_read(&key);
                 if let Some(o) = o {
-                    if o.encoding == OBJ_ENCODING_SKIPLIST {
-                        match &o.ptr {
-                            RedisValue::SortSet(zset) => Ok(Frame::Simple(zset.zsl.length.to_string())),
-                            _ => Ok(Frame::Null),
-                        }
-                    } else {
-                        Ok(Frame::Null)
+                    match &o.ptr {
+                        RedisValue::SortSet(zset) => Ok(Frame::Simple(zset.zsl.length.to_string())),
+                        RedisValue::ListPack(lp) if o.object_type == OBJ_ZSET => Ok(Frame::Simple(zzl_len(lp).to_string())),
+                        _ => Ok(Frame::Null),
                     }
                 } else {
                    Ok(Frame::Null)
@@ -133,7 +243,7 @@ impl CommandStrategy for SortedCmd {
             }
             SortedCmd::ZScore {key, member} => {
                 let key = RedisObject::create_string_object(key);
-                let o = db.find(&key);
+                let o = db.lookup_read(&key);
                 if let Some(o) = o {
                     match &mut o.ptr {
                         RedisValue::SortSet(zset) => {
@@ -150,12 +260,23 @@ impl CommandStrategy for SortedCmd {
                                 Err(CommandError::NotExist(format!("{} not exist", member)).into())
                             }
                         }
+                        RedisValue::ListPack(lp) if o.object_type == OBJ_ZSET => match zzl_find(lp, &member) {
+                            Some((_, score)) => Ok(Frame::Simple(score.to_string())),
+                            None => Err(CommandError::NotExist(format!("{} not exist", member)).into()),
+                        },
                         _ => Ok(Frame::Null)
                     }
                 } else {
                     Ok(Frame::Null)
                 }
             }
+            SortedCmd::ZPopMin {key, count} => Self::pop_command(db, &key, false, count),
+            SortedCmd::ZPopMax {key, count} => Self::pop_command(db, &key, true, count),
+            SortedCmd::ZMPop {keys, max, count} => Self::serve_first(db, &keys, BlockOp::ZMPop {max, count}),
+            // Outside of the DB blocking path the blocking variants never wait.
+            SortedCmd::BZPopMin {keys, ..} => Self::serve_first(db, &keys, BlockOp::ZPop {max: false}),
+            SortedCmd::BZPopMax {keys, ..} => Self::serve_first(db, &keys, BlockOp::ZPop {max: true}),
+            SortedCmd::BZMPop {keys, max, count, ..} => Self::serve_first(db, &keys, BlockOp::ZMPop {max, count}),
             _ => todo!()
         }
     }
@@ -163,6 +284,36 @@ impl CommandStrategy for SortedCmd {
 
 impl SortedCmd {
     pub fn zadd(o: &mut RedisObject, arg: Option<String>, mut score: f64, ele: String) {
+        if o.encoding == OBJ_ENCODING_LISTPACK {
+            let lp = match &mut o.ptr {
+                RedisValue::ListPack(lp) => lp,
+                _ => return,
+            };
+            let arg = arg.as_deref().unwrap_or("");
+            if let Some((pos, cur_score)) = zzl_find(lp, &ele) {
+                if arg.eq("nx") {
+                    return;
+                }
+                if arg.eq("incr") {
+                    score += cur_score;
+                }
+                if (arg.eq("lt") && score >= cur_score) || (arg.eq("gt") && score <= cur_score) {
+                    return;
+                }
+                if score != cur_score {
+                    zzl_delete(lp, pos);
+                    zzl_insert(lp, &ele, score);
+                }
+                unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                return;
+            }
+            if zzl_len(lp) < zset_max_listpack_entries() && ele.len() <= zset_max_listpack_value() {
+                zzl_insert(lp, &ele, score);
+                unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+                return;
+            }
+            zset_convert(o);
+        }
         if o.encoding == OBJ_ENCODING_SKIPLIST {
             let zs = match &mut o.ptr {
                 RedisValue::SortSet(zset) => zset,
@@ -207,4 +358,115 @@ impl SortedCmd {
         }
         unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
     }
-}
\ No newline at end of file
+
+    /// Pops up to `count` members with the lowest (or highest when `max` is set) scores
+    /// from the sorted set at `key`, deleting the key once it is empty. Returns `None`
+    /// when the key does not exist.
+    pub fn zset_pop(db: &mut RedisDb, key: &str, max: bool, count: usize) -> crate::Result<Option<Vec<(String, f64)>>> {
+        let key_obj = RedisObject::create_string_object(key.to_string());
+        let (members, empty) = match db.find(&key_obj) {
+            Some(o) => {
+                if o.object_type != OBJ_ZSET {
+                    return Err(CommandError::ObjectTypeError(-5).into());
+                }
+                let mut members = Vec::with_capacity(count);
+                match &mut o.ptr {
+                    RedisValue::SortSet(zs) => {
+                        unsafe {
+                            while members.len() < count {
+                                let node = if max {
+                                    zs.zsl.tail
+                                } else {
+                                    (*zs.zsl.head.unwrap().as_ptr()).level[0].forward
+                                };
+                                let node = match node {
+                                    Some(node) => node,
+                                    None => break,
+                                };
+                                let ele = node.as_ref().get_elem();
+                                let score = node.as_ref().get_score();
+                                zs.zsl.delete(score, &ele);
+                                let _ = zs.dict.generic_delete(&ele);
+                                members.push((ele, score));
+                            }
+                        }
+                        let empty = zs.zsl.length == 0;
+                        (members, empty)
+                    }
+                    RedisValue::ListPack(lp) => {
+                        while members.len() < count {
+                            let pos = match if max { lp.seek(-2) } else { lp.first() } {
+                                Some(pos) => pos,
+                                None => break,
+                            };
+                            let ele = lp.get(pos).to_string();
+                            let score = zzl_get_score(lp, lp.next(pos).unwrap());
+                            zzl_delete(lp, pos);
+                            members.push((ele, score));
+                        }
+                        (members, lp.is_empty())
+                    }
+                    _ => return Err(CommandError::ObjectTypeError(-5).into()),
+                }
+            }
+            None => return Ok(None),
+        };
+        if members.is_empty() {
+            return Ok(None);
+        }
+        unsafe { REDIS_SERVER.get_mut().unwrap().incr_dirty();}
+        notify_keyspace_event(NOTIFY_ZSET, if max { "zpopmax" } else { "zpopmin" }, key, db.id);
+        if empty {
+            db.delete(&key_obj);
+            notify_keyspace_event(NOTIFY_GENERIC, "del", key, db.id);
+        }
+        Ok(Some(members))
+    }
+
+    fn pop_command(db: &mut RedisDb, key: &str, max: bool, count: Option<usize>) -> crate::Result<Frame> {
+        let mut frame = Frame::array();
+        if let Some(members) = Self::zset_pop(db, key, max, count.unwrap_or(1))? {
+            for (member, score) in members {
+                frame.push_bulk(Bytes::from(member.into_bytes()));
+                frame.push_bulk(Bytes::from(score.to_string().into_bytes()));
+            }
+        }
+        Ok(frame)
+    }
+
+    fn serve_first(db: &mut RedisDb, keys: &[String], op: BlockOp) -> crate::Result<Frame> {
+        for key in keys {
+            if let Some(res) = op.serve(db, key) {
+                return res;
+            }
+        }
+        Ok(Frame::Null)
+    }
+}
+
+/// Moves a listpack encoded sorted set to the skiplist encoding.
+fn zset_convert(o: &mut RedisObject) {
+    if let RedisValue::ListPack(lp) = &o.ptr {
+        o.ptr = RedisValue::SortSet(zzl_to_zset(lp));
+        o.encoding = OBJ_ENCODING_SKIPLIST;
+    }
+}
+
+/// Parses the `numkeys key [key ...] MIN|MAX [COUNT count]` tail shared by ZMPOP and BZMPOP.
+fn parse_mpop_args(name: &str, frame: &Frame, start: usize) -> crate::Result<(Vec<String>, bool, usize)> {
+    let num_keys: usize = frame.get_frame_by_index(start).ok_or(format!("command error '{}'", name))?.to_string().parse()?;
+    if num_keys == 0 {
+        return Err("ERR numkeys should be greater than 0".into());
+    }
+    let mut keys = Vec::with_capacity(num_keys);
+    for i in start + 1..start + 1 + num_keys {
+        keys.push(frame.get_frame_by_index(i).ok_or(format!("command error '{}'", name))?.to_string());
+    }
+    let max = match &frame.get_frame_by_index(start + 1 + num_keys).ok_or(format!("command error '{}'", name))?.to_string().to_lowercase()[..] {
+        "min" => false,
+        "max" => true,
+        _ => return Err(CommandError::ArgsErr(name.to_string()).into()),
+    };
+    let count = parse_count(name, frame, start + 2 + num_keys)?;
+    Ok((keys, max, count))
+}
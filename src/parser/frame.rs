@@ -10,7 +10,7 @@ use std::string::FromUtf8Error;
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
     Array(Vec<Frame>),
@@ -36,7 +36,7 @@ impl Frame {
         }
     }
 
-    pub fn push_int(&mut self, value: u64) {
+    pub fn push_int(&mut self, value: i64) {
         match self {
             Frame::Array(vec) => {
                 vec.push(Frame::Integer(value));
@@ -55,6 +55,10 @@ impl Frame {
                 get_line(src)?;
                 Ok(())
             }
+            b':' => {
+                get_line(src)?;
+                Ok(())
+            }
             b'$' => {
                 if b'-' == peek_u8(src)? {
                     // Skip '-1\r\n'
@@ -98,9 +102,9 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let len = get_decimal(src)?;
+                let value = get_signed_decimal(src)?;
 
-                Ok(Frame::Integer(len))
+                Ok(Frame::Integer(value))
             }
             b'$' => {
                 if b'-' == peek_u8(src)? {
@@ -157,6 +161,19 @@ impl Frame {
         }
     }
 
+    /// Bytes of the frame once written to a connection
+    pub fn encoded_len(&self) -> usize {
+        // type byte and CRLF around the length or value
+        let header = |n: usize| 1 + n.to_string().len() + 2;
+        match self {
+            Frame::Simple(data) | Frame::Error(data) => 1 + data.len() + 2,
+            Frame::Integer(data) => 1 + data.to_string().len() + 2,
+            Frame::Bulk(data) => header(data.len()) + data.len() + 2,
+            Frame::Null => 5,
+            Frame::Array(data) => header(data.len()) + data.iter().map(Frame::encoded_len).sum::<usize>(),
+        }
+    }
+
     pub fn get_index_after(&self, index: usize) -> Frame {
         match self {
             Frame::Array(array) => Frame::Array(array[index..].to_vec()),
@@ -211,6 +228,13 @@ fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
+fn get_signed_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    use atoi::atoi;
+
+    let line = get_line(src)?;
+    atoi::<i64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     // Scan the bytes directly
     let start = src.position() as usize;
@@ -25,6 +25,10 @@ const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
 
 /// DB number of the following keys.
 const RDB_OPCODE_SELECTDB: u8 = 0;
+/// Auxiliary field, a key and a value both saved as strings.
+const RDB_OPCODE_AUX: u8 = 250;
+/// Absolute unix time in milliseconds at which the following key expires.
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
 /// End of the RDB file.
 const RDB_OPCODE_EOF: u8 = 254;
 const RDB_6BITLEN: u8 = 0;
@@ -33,4 +37,5 @@ const RDB_32BITLEN: u8 = 0x80;
 const RDB_64BITLEN: u8 = 0x81;
 const RDB_ENCVAL: u8 = 3;
 
-const RDB_OPCODE_SLOT_INFO: u8 = 244;
\ No newline at end of file
+/// Size of a stream ID saved as raw bytes, the milliseconds then the sequence
+const STREAM_ID_LEN: usize = 16;
\ No newline at end of file
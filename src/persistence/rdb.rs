@@ -1,24 +1,32 @@
 use std::io::Write;
 use std::io::Read;
-use bytes::{Buf, BufMut, BytesMut};
+use std::time::Instant;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc::Sender;
 
-use crate::db::data_structure::dict::dict::Value;
+use crate::db::data_structure::dict::dict::{Dict, Value};
+use crate::db::data_structure::intset::intset::IntSet;
+use crate::db::data_structure::listpack::ListPack;
+use crate::db::data_structure::stream::{Stream, StreamCG, StreamConsumer, StreamID, StreamNACK};
+use crate::db::db::RedisDb;
 use crate::db::db_engine::RDbCommand;
+use crate::db::expire::mstime;
 use crate::persistence::error::PersistError;
+use crate::server::latency::{latency_add_sample_if_needed, LATENCY_EVENT_RDB_SAVE};
 use crate::db::object::{*};
-use crate::parser::cmd::hash::HashCmd;
-use crate::parser::cmd::zset::SortedCmd;
+use crate::parser::cmd::set::{set_type_add, set_type_convert};
 use crate::persistence::{*};
 use crate::{Result};
 
 pub enum RdbCommand {
-    Save { db_id: u32, sender: std::sync::mpsc::Sender<Result<()>> },
+    Save { sender: std::sync::mpsc::Sender<Result<()>> },
     Load { sender: std::sync::mpsc::Sender<Result<()>> },
+    /// Loads an RDB received from a master rather than the file
+    LoadPayload { payload: Bytes, sender: std::sync::mpsc::Sender<Result<Vec<(String, String)>>> },
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct RdbHandler {
     sender: std::sync::mpsc::Sender<RdbCommand>,
 }
@@ -31,12 +39,15 @@ impl RdbHandler {
         std::thread::spawn(move || {
             loop {
                 match rx.recv() {
-                    Ok(RdbCommand::Save { db_id, sender }) => {
-                        let _ = sender.send(rdb.save(db_id));
+                    Ok(RdbCommand::Save { sender }) => {
+                        let _ = sender.send(rdb.save());
                     }
                     Ok(RdbCommand::Load {sender}) => {
                         let _ = sender.send(rdb.load());
                     }
+                    Ok(RdbCommand::LoadPayload { payload, sender }) => {
+                        let _ = sender.send(rdb.load_buf(BytesMut::from(&payload[..]), false));
+                    }
                     Err(e) => {
                         tracing::error!("rdb channel err: {:?}", e);
                         break;
@@ -48,10 +59,14 @@ impl RdbHandler {
         Self { sender: tx }
     }
 
-    pub fn save(&self, db_id: u32) -> Result<()> {
+    /// Saves every DB to the RDB file.
+    pub fn save(&self) -> Result<()> {
+        let start = Instant::now();
         let (tx, rx) = std::sync::mpsc::channel::<Result<()>>();
-        let _ = self.sender.send(RdbCommand::Save { db_id, sender: tx });
-        rx.recv().map_err(|e| PersistError::RdbErr(e.to_string()))?
+        let _ = self.sender.send(RdbCommand::Save { sender: tx });
+        let result = rx.recv().map_err(|e| PersistError::RdbErr(e.to_string()))?;
+        latency_add_sample_if_needed(LATENCY_EVENT_RDB_SAVE, start.elapsed());
+        result
     }
 
     pub fn load(&self) -> Result<()> {
@@ -59,6 +74,48 @@ impl RdbHandler {
         let _ = self.sender.send(RdbCommand::Load { sender: tx });
         rx.recv().map_err(|e| PersistError::RdbErr(e.to_string()))?
     }
+
+    /// Loads `payload`, an RDB sent by the master on a full resync, into the DBs.
+    /// Returns the aux fields of the payload.
+    pub fn load_payload(&self, payload: Bytes) -> Result<Vec<(String, String)>> {
+        let (tx, rx) = std::sync::mpsc::channel::<Result<Vec<(String, String)>>>();
+        let _ = self.sender.send(RdbCommand::LoadPayload { payload, sender: tx });
+        rx.recv().map_err(|e| PersistError::RdbErr(e.to_string()))?
+    }
+}
+
+
+/// The keys of `db` as a section of an RDB, from SELECTDB to EOF, taken by the DB task
+/// itself so the section matches one point of the command stream (full resync).
+pub fn rdb_dump_db(db: &mut RedisDb) -> Result<BytesMut> {
+    let mut buf = BytesMut::with_capacity(1024 * 8);
+    buf.put_u8(RDB_OPCODE_SELECTDB);
+    Rdb::rdb_save_len(&mut buf, db.id as u64)?;
+    Rdb::rdb_save_entries(&mut buf, db)?;
+    buf.put_u8(RDB_OPCODE_EOF);
+    Ok(buf)
+}
+
+/// An RDB made of the `aux` fields and the `sections` of `rdb_dump_db`, one per DB
+pub fn rdb_payload(aux: &[(&str, String)], sections: &[BytesMut]) -> Result<Bytes> {
+    let mut buf = BytesMut::with_capacity(sections.iter().map(|section| section.len()).sum::<usize>() + 64);
+    buf.extend_from_slice(b"RDB");
+    for (key, value) in aux {
+        buf.put_u8(RDB_OPCODE_AUX);
+        Rdb::rdb_save_string(&mut buf, key)?;
+        Rdb::rdb_save_string(&mut buf, value)?;
+    }
+    for section in sections {
+        buf.extend_from_slice(section);
+    }
+    Ok(buf.freeze())
+}
+
+/// Length of `object` once serialized in the RDB file (DEBUG OBJECT).
+pub fn rdb_saved_object_len(object: &RedisObject) -> Result<usize> {
+    let mut buf = BytesMut::new();
+    Rdb::rdb_save_object(&mut buf, object)?;
+    Ok(buf.len())
 }
 
 
@@ -71,53 +128,45 @@ impl Rdb {
         Self { db_sender, }
     }
 
-    pub fn save(&mut self, db_id: u32) -> Result<()> {
-        let tmp_path = "./tmp.rdb".to_string();
-        let mut tmp_file = std::fs::File::options()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&tmp_path).map_err(|_| PersistError::FileError(-101))?;
-        let mut buf = BytesMut::with_capacity(1024 * 8);
-
-        buf.extend_from_slice(b"RDB");
-        buf.put_u8(RDB_OPCODE_SELECTDB);
-        Self::rdb_save_len(&mut buf, db_id as u64)?;
-        let (tx, rx) = std::sync::mpsc::channel();
-        let cmd = RDbCommand::DbIter(tx);
-        let sender = self.db_sender[db_id as usize].clone();
-        tokio::runtime::Builder::new_current_thread()
+    /// Writes a section per DB to a temporary file, then renames it over dump.rdb.
+    pub fn save(&mut self) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
-            .build()?
-            .block_on(async {
-                let _ = sender.send(cmd).await;
+            .build()?;
+        let mut sections = Vec::with_capacity(self.db_sender.len());
+        for sender in &self.db_sender {
+            let (tx, rx) = std::sync::mpsc::channel();
+            runtime.block_on(async {
+                let _ = sender.send(RDbCommand::Dump(tx)).await;
             });
-        unsafe {
-            match rx.recv() {
-                Ok(iter) => {
-                    for dict in iter {
-                        let key = (*dict).get_key();
-                        let value = (*dict).get_val();
-                        match value {
-                            Value::Val(robj) => {
-                                Self::rdb_save_key_value_pair(&mut buf, key, robj)?;
-                            },
-                            _ => {}
-                        }
-                    }
-                    buf.put_u8(RDB_OPCODE_EOF);
-                }
-                _ => { }
-            }
-        };
+            sections.push(rx.recv().map_err(|e| PersistError::RdbErr(e.to_string()))??);
+        }
+        let buf = rdb_payload(&[], &sections)?;
 
-        tmp_file.write_all(&mut buf).map_err(|_| PersistError::FileError(-102))?;
+        let tmp_path = "./tmp.rdb".to_string();
+        let mut tmp_file = std::fs::File::create(&tmp_path).map_err(|_| PersistError::FileError(-101))?;
+        tmp_file.write_all(&buf).map_err(|_| PersistError::FileError(-102))?;
         let rdb_path = "./dump.rdb".to_string();
         std::fs::rename(tmp_path, rdb_path).map_err(|_| PersistError::FileError(-103))?;
 
         Ok(())
     }
 
+    /// Saves the key value pairs of `db`, each preceded by its expire time if it has a TTL.
+    fn rdb_save_entries(buf: &mut BytesMut, db: &mut RedisDb) -> Result<()> {
+        for entry in db.db_iter() {
+            let (key, value) = unsafe { ((*entry).get_key(), (*entry).get_val()) };
+            if let Value::Val(robj) = value {
+                if let Some(when) = db.get_expire(key) {
+                    buf.put_u8(RDB_OPCODE_EXPIRETIME_MS);
+                    buf.put_i64_le(when);
+                }
+                Self::rdb_save_key_value_pair(buf, key, robj)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn load(&mut self) -> Result<()> {
         let rdb_path = "./dump.rdb".to_string();
         let mut file = std::fs::File::options()
@@ -126,60 +175,66 @@ impl Rdb {
             .map_err(|_| PersistError::FileError(-104))?;
         let mut buf_vec = Vec::with_capacity(1024 * 8);
         file.read_to_end(&mut buf_vec).map_err(|_| PersistError::FileError(-105))?;
-        let mut buf = BytesMut::from(&buf_vec[..]);
-        let head = buf.split_to(3);
-        if head != b"RDB"[..] {
+        self.load_buf(BytesMut::from(&buf_vec[..]), true).map(|_| ())
+    }
+
+    /// Loads the keys of `buf` and returns its aux fields. With `skip_expired` the keys
+    /// whose TTL elapsed are dropped rather than loaded.
+    fn load_buf(&mut self, mut buf: BytesMut, skip_expired: bool) -> Result<Vec<(String, String)>> {
+        if !buf.starts_with(b"RDB") {
             return Err(PersistError::DecodeErr("flag not rdb".to_string()).into());
         }
+        buf.advance(3);
+        let mut aux = vec![];
+        while buf.first() == Some(&RDB_OPCODE_AUX) {
+            buf.advance(1);
+            let key = Self::load_string(&mut buf)?;
+            let value = Self::load_string(&mut buf)?;
+            aux.push((key, value));
+        }
+        Self::rdb_ensure(&buf, 1)?;
         let db_id_flag = buf.get_u8();
         if db_id_flag != RDB_OPCODE_SELECTDB {
             return Err(PersistError::DecodeErr("db_id_flag error".to_string()).into());
         }
         let db_id = Self::rdb_load_len(&mut buf)?;
         println!("db_id: {}", db_id);
-        let sender = self.db_sender[db_id as usize].clone();
-        
+        let mut sender = self.db_sender.get(db_id as usize).ok_or(PersistError::DecodeErr("db_id out of range".to_string()))?.clone();
+        let mut expire = None;
+
         // 解析数据并在当前异步上下文中发送命令
         loop {
             if buf.is_empty() {
                 break;
             }
             match buf.get_u8() {
+                // the payload of a full resync holds a section per DB, each ended by EOF
+                RDB_OPCODE_EOF if buf.first() == Some(&RDB_OPCODE_SELECTDB) => {
+                    buf.advance(1);
+                    let db_id = Self::rdb_load_len(&mut buf)?;
+                    sender = self.db_sender.get(db_id as usize).ok_or(PersistError::DecodeErr("db_id out of range".to_string()))?.clone();
+                }
                 RDB_OPCODE_EOF => break,
-                RDB_TYPE_STRING => {
-                    let s = Self::load_string(&mut buf)?;
-                    let key = RedisObject::create_string_object(s);
-                    let value = Self::rdb_load_object(RDB_TYPE_STRING, &mut buf)?;
-                    let cmd = RDbCommand::RdbData {key, value};
-                    tokio::runtime::Builder::new_current_thread()
-                        .enable_all()
-                        .build()?
-                        .block_on(async {
-                            let _ = sender.send(cmd).await.map_err(|_| PersistError::FileError(-106));
-                        });
-                },
-                RDB_TYPE_HASH => {
-                    let s = Self::load_string(&mut buf)?;
-                    let key = RedisObject::create_string_object(s);
-                    let value = Self::rdb_load_object(RDB_TYPE_HASH, &mut buf)?;
-                    let cmd = RDbCommand::RdbData {key, value};
-                    tokio::runtime::Builder::new_current_thread()
-                        .enable_all()
-                        .build()?
-                        .block_on(async {
-                            let _ = sender.send(cmd).await.map_err(|_| PersistError::FileError(-107));
-                        });
+                RDB_OPCODE_EXPIRETIME_MS => {
+                    expire = Some(Self::rdb_load_millisecond_time(&mut buf)?);
                 }
-                RDB_TYPE_ZSET_2 => {
+                obj_type @ (RDB_TYPE_STRING | RDB_TYPE_HASH | RDB_TYPE_LIST | RDB_TYPE_ZSET_2 | RDB_TYPE_SET
+                | RDB_TYPE_SET_INTSET | RDB_TYPE_SET_LISTPACK | RDB_TYPE_HASH_LISTPACK | RDB_TYPE_ZSET_LISTPACK
+                | RDB_TYPE_STREAM_LISTPACKS_3) => {
                     let s = Self::load_string(&mut buf)?;
                     let key = RedisObject::create_string_object(s);
-                    let value = Self::rdb_load_object(RDB_TYPE_ZSET_2, &mut buf)?;
-                    let cmd = RDbCommand::RdbData {key, value};
+                    let value = Self::rdb_load_object(obj_type, &mut buf)?;
+                    let expire = expire.take();
+                    // a replica keeps the keys its master did not expire yet, it is sent their DEL
+                    if skip_expired && expire.is_some_and(|when| when <= mstime()) {
+                        continue;
+                    }
+                    let cmd = RDbCommand::RdbData {key, value, expire};
                     tokio::runtime::Builder::new_current_thread()
                         .enable_all()
                         .build()?
                         .block_on(async {
-                            let _ = sender.send(cmd).await.map_err(|_| PersistError::FileError(-108));
+                            let _ = sender.send(cmd).await.map_err(|_| PersistError::FileError(-106));
                         });
                 }
                 _ => {
@@ -188,7 +243,7 @@ impl Rdb {
             }
         }
 
-        Ok(())
+        Ok(aux)
     }
 
     #[inline(always)]
@@ -197,9 +252,16 @@ impl Rdb {
             OBJ_STRING => {
                 buf.put_u8(RDB_TYPE_STRING);
             }
+            OBJ_LIST => {
+                match object.encoding {
+                    OBJ_ENCODING_LINKEDLIST => buf.put_u8(RDB_TYPE_LIST),
+                    _ => return Err(PersistError::EncodeErr("Unknown list encoding".to_string()).into())
+                }
+            }
             OBJ_SET => {
                 match object.encoding {
                     OBJ_ENCODING_INTSET => buf.put_u8(RDB_TYPE_SET_INTSET),
+                    OBJ_ENCODING_LISTPACK => buf.put_u8(RDB_TYPE_SET_LISTPACK),
                     OBJ_ENCODING_HT => buf.put_u8(RDB_TYPE_SET),
                     _ => return Err(PersistError::EncodeErr("Unknown set encoding".to_string()).into())
                 }
@@ -207,13 +269,15 @@ impl Rdb {
             OBJ_ZSET => {
                 match object.encoding {
                     OBJ_ENCODING_SKIPLIST => buf.put_u8(RDB_TYPE_ZSET_2),
+                    OBJ_ENCODING_LISTPACK => buf.put_u8(RDB_TYPE_ZSET_LISTPACK),
                     _ => return Err(PersistError::EncodeErr("Unknown sorted set encoding".to_string()).into())
                 }
             }
+            OBJ_STREAM => buf.put_u8(RDB_TYPE_STREAM_LISTPACKS_3),
             OBJ_HASH => {
                 match object.encoding {
                     OBJ_ENCODING_HT => buf.put_u8(RDB_TYPE_HASH),
-                    OBJ_ENCODING_ZIPLIST => buf.put_u8(RDB_TYPE_HASH_ZIPLIST),
+                    OBJ_ENCODING_LISTPACK => buf.put_u8(RDB_TYPE_HASH_LISTPACK),
                     _ => return Err(PersistError::EncodeErr("Unknown hash encoding".to_string()).into())
                 }
             }
@@ -225,7 +289,12 @@ impl Rdb {
     }
 
     fn rdb_save_string(buf: &mut BytesMut, s: &str) -> Result<usize> {
-        let s_vec = s.as_bytes();
+        Self::rdb_save_raw(buf, s.as_bytes())
+    }
+
+    /// Length prefixed blob, used for strings and for the serialized intsets and
+    /// listpacks.
+    fn rdb_save_raw(buf: &mut BytesMut, s_vec: &[u8]) -> Result<usize> {
         let len = s_vec.len();
         Self::rdb_save_len(buf, len as u64)?;
         buf.put_slice(s_vec);
@@ -234,7 +303,7 @@ impl Rdb {
 
     #[inline(always)]
     fn rdb_save_len(buf: &mut BytesMut, len: u64) -> Result<usize> {
-        let mut nwritten = 0;
+        let nwritten;
         if len < 1 << 6 {
             buf.put_u8(len as u8 | RDB_6BITLEN << 6);
             nwritten = 1;
@@ -263,13 +332,11 @@ impl Rdb {
     }
 
     fn rdb_save_object(buf: &mut BytesMut, object: &RedisObject) -> Result<()> {
-        let mut nwritten = 0;
         match object.object_type {
             OBJ_STRING => {
                 match &object.ptr {
                     RedisValue::String(s) => {
-                        let n = Self::rdb_save_string(buf, s)?;
-                        nwritten += n;
+                        Self::rdb_save_string(buf, s)?;
                     }
                     _ => {
                         return Err(PersistError::TypeErr("err object type, expect string".to_string()).into())
@@ -281,51 +348,207 @@ impl Rdb {
                     RedisValue::Hash(ht) => {
                         let ht_iter = ht.iter();
                         let size = ht.dict_size();
-                        nwritten += Self::rdb_save_len(buf, size as u64)?;
+                        Self::rdb_save_len(buf, size as u64)?;
                         unsafe {
                             for entry in ht_iter {
                                 let field = (*entry).get_key();
                                 println!("save field: {}", field);
-                                nwritten += Self::rdb_save_string(buf, field)?;
-                                let value = (*entry).value();
-                                match value {
-                                    Value::Sds(s) => {
-                                        println!("save value: {}", s);
-                                        nwritten += Self::rdb_save_string(buf, s)?
-                                    },
-                                    _ => {}
+                                Self::rdb_save_string(buf, field)?;
+                                if let Value::Sds(s) = (*entry).value() {
+                                    println!("save value: {}", s);
+                                    Self::rdb_save_string(buf, s)?;
                                 }
                             }
                         }
                     }
+                    RedisValue::ListPack(lp) => { Self::rdb_save_raw(buf, lp.as_bytes())?; }
                     _ => {}
                 }
             }
+            OBJ_SET => {
+                match &object.ptr {
+                    RedisValue::Set(is) => { Self::rdb_save_raw(buf, &is.to_blob())?; }
+                    RedisValue::ListPack(lp) => { Self::rdb_save_raw(buf, lp.as_bytes())?; }
+                    RedisValue::Hash(ht) => {
+                        Self::rdb_save_len(buf, ht.dict_size() as u64)?;
+                        unsafe {
+                            for entry in ht.iter() {
+                                Self::rdb_save_string(buf, (*entry).get_key())?;
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(PersistError::TypeErr("err object type, expect set".to_string()).into())
+                    }
+                }
+            }
+            OBJ_LIST => {
+                match &object.ptr {
+                    RedisValue::List(ListObject::LinkList(list)) => {
+                        Self::rdb_save_len(buf, list.length() as u64)?;
+                        for value in list.iter() {
+                            Self::rdb_save_string(buf, value)?;
+                        }
+                    }
+                    _ => {
+                        return Err(PersistError::TypeErr("err object type, expect list".to_string()).into())
+                    }
+                }
+            }
             OBJ_ZSET => {
                 match &object.ptr {
                     RedisValue::SortSet(zset) => {
                         let zsl = &zset.zsl;
                         let len = zsl.length;
-                        nwritten += Self::rdb_save_len(buf, len)?;
+                        Self::rdb_save_len(buf, len)?;
                         let mut zn = zsl.tail;
                         unsafe {
                             while let Some(node) = zn {
-                                nwritten += Self::rdb_save_string(buf, &node.as_ref().get_elem())?;
+                                Self::rdb_save_string(buf, &node.as_ref().get_elem())?;
                                 buf.put_f64(node.as_ref().get_score());
                                 zn = node.as_ref().back_ward();
                             }
                         }
                     }
+                    RedisValue::ListPack(lp) => { Self::rdb_save_raw(buf, lp.as_bytes())?; }
                     _ => {
                         return Err(PersistError::TypeErr("err object type, expect zset".to_string()).into())
                     }
                 }
             }
+            OBJ_STREAM => {
+                match &object.ptr {
+                    RedisValue::Stream(s) => Self::rdb_save_stream(buf, s)?,
+                    _ => {
+                        return Err(PersistError::TypeErr("err object type, expect stream".to_string()).into())
+                    }
+                }
+            }
             _ => { }
         }
         Ok(())
     }
 
+    /// The RDB_TYPE_STREAM_LISTPACKS_3 layout: the listpack nodes with their master
+    /// IDs, the stream metadata, then every consumer group with its PEL and consumers.
+    fn rdb_save_stream(buf: &mut BytesMut, s: &Stream) -> Result<()> {
+        Self::rdb_save_len(buf, s.nodes().len() as u64)?;
+        for (master, lp) in s.nodes() {
+            Self::rdb_save_len(buf, STREAM_ID_LEN as u64)?;
+            Self::rdb_save_stream_id(buf, master);
+            Self::rdb_save_raw(buf, lp.as_bytes())?;
+        }
+        Self::rdb_save_len(buf, s.len() as u64)?;
+        Self::rdb_save_len_stream_id(buf, &s.last_id)?;
+        Self::rdb_save_len_stream_id(buf, &s.first_id())?;
+        Self::rdb_save_len_stream_id(buf, &s.max_deleted_entry_id)?;
+        Self::rdb_save_len(buf, s.entries_added)?;
+        Self::rdb_save_len(buf, s.cgroups.len() as u64)?;
+        for (name, cg) in &s.cgroups {
+            Self::rdb_save_string(buf, name)?;
+            Self::rdb_save_len_stream_id(buf, &cg.last_id)?;
+            Self::rdb_save_len(buf, cg.entries_read as u64)?;
+            Self::rdb_save_len(buf, cg.pel.len() as u64)?;
+            for (id, nack) in &cg.pel {
+                Self::rdb_save_stream_id(buf, id);
+                buf.put_i64_le(nack.delivery_time);
+                Self::rdb_save_len(buf, nack.delivery_count)?;
+            }
+            Self::rdb_save_len(buf, cg.consumers.len() as u64)?;
+            for consumer in cg.consumers.values() {
+                Self::rdb_save_string(buf, &consumer.name)?;
+                buf.put_i64_le(consumer.seen_time);
+                buf.put_i64_le(consumer.active_time);
+                Self::rdb_save_len(buf, consumer.pel.len() as u64)?;
+                for id in &consumer.pel {
+                    Self::rdb_save_stream_id(buf, id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A stream ID as 16 big endian bytes, the way the node keys and PELs store it
+    fn rdb_save_stream_id(buf: &mut BytesMut, id: &StreamID) {
+        buf.put_u64(id.ms);
+        buf.put_u64(id.seq);
+    }
+
+    /// A stream ID as two lengths, the way the stream metadata stores it
+    fn rdb_save_len_stream_id(buf: &mut BytesMut, id: &StreamID) -> Result<()> {
+        Self::rdb_save_len(buf, id.ms)?;
+        Self::rdb_save_len(buf, id.seq)?;
+        Ok(())
+    }
+
+    fn rdb_load_stream_id(buf: &mut BytesMut) -> Result<StreamID> {
+        Self::rdb_ensure(buf, STREAM_ID_LEN)?;
+        let ms = buf.get_u64();
+        Ok(StreamID::new(ms, buf.get_u64()))
+    }
+
+    fn rdb_load_len_stream_id(buf: &mut BytesMut) -> Result<StreamID> {
+        let ms = Self::rdb_load_len(buf)?;
+        Ok(StreamID::new(ms, Self::rdb_load_len(buf)?))
+    }
+
+    fn rdb_load_stream(buf: &mut BytesMut) -> Result<Stream> {
+        let mut s = Stream::new();
+        let nodes = Self::rdb_load_len(buf)?;
+        for _ in 0..nodes {
+            let mut master = BytesMut::from(&Self::rdb_load_raw(buf)?[..]);
+            if master.len() != STREAM_ID_LEN {
+                return Err(PersistError::DecodeErr("stream node key is not a stream ID".to_string()).into());
+            }
+            let master = Self::rdb_load_stream_id(&mut master)?;
+            let lp = ListPack::from_bytes(Self::rdb_load_raw(buf)?);
+            if !s.load_node(master, lp) {
+                return Err(PersistError::DecodeErr("invalid stream node listpack".to_string()).into());
+            }
+        }
+        if Self::rdb_load_len(buf)? != s.len() as u64 {
+            return Err(PersistError::DecodeErr("stream length does not match its nodes".to_string()).into());
+        }
+        s.last_id = Self::rdb_load_len_stream_id(buf)?;
+        // the first entry is read from the nodes
+        Self::rdb_load_len_stream_id(buf)?;
+        s.max_deleted_entry_id = Self::rdb_load_len_stream_id(buf)?;
+        s.entries_added = Self::rdb_load_len(buf)?;
+        let ngroups = Self::rdb_load_len(buf)?;
+        for _ in 0..ngroups {
+            let name = Self::load_string(buf)?;
+            let last_id = Self::rdb_load_len_stream_id(buf)?;
+            let entries_read = Self::rdb_load_len(buf)? as i64;
+            let mut cg = StreamCG { last_id, entries_read, pel: Default::default(), consumers: Default::default() };
+            let npel = Self::rdb_load_len(buf)?;
+            for _ in 0..npel {
+                let id = Self::rdb_load_stream_id(buf)?;
+                let delivery_time = Self::rdb_load_millisecond_time(buf)?;
+                let delivery_count = Self::rdb_load_len(buf)?;
+                // the owner is set while loading the consumers
+                cg.pel.insert(id, StreamNACK { delivery_time, delivery_count, consumer: String::new() });
+            }
+            let nconsumers = Self::rdb_load_len(buf)?;
+            for _ in 0..nconsumers {
+                let name = Self::load_string(buf)?;
+                let seen_time = Self::rdb_load_millisecond_time(buf)?;
+                let active_time = Self::rdb_load_millisecond_time(buf)?;
+                let mut consumer = StreamConsumer { name, seen_time, active_time, pel: Default::default() };
+                let npel = Self::rdb_load_len(buf)?;
+                for _ in 0..npel {
+                    let id = Self::rdb_load_stream_id(buf)?;
+                    let nack = cg.pel.get_mut(&id)
+                        .ok_or_else(|| PersistError::LoadErr("consumer PEL entry not in the group PEL".to_string()))?;
+                    nack.consumer.clone_from(&consumer.name);
+                    consumer.pel.insert(id);
+                }
+                cg.consumers.insert(consumer.name.clone(), consumer);
+            }
+            s.cgroups.insert(name, cg);
+        }
+        Ok(s)
+    }
+
     fn rdb_load_object(obj_type: u8, buf: &mut BytesMut) -> Result<RedisObject> {
         match obj_type {
             RDB_TYPE_STRING => {
@@ -336,45 +559,114 @@ impl Rdb {
             RDB_TYPE_HASH => {
                 let hash_size = Self::rdb_load_len(buf)?;
                 let mut object = RedisObject::create_hash_object();
+                // loading is not a change to the dataset, so the object is built
+                // directly instead of through HSET
+                let mut dict = Dict::create();
                 for _ in 0..hash_size {
                     let key = Self::load_string(buf)?;
                     let value = Self::load_string(buf)?;
-                    HashCmd::hash_set(&mut object, key, value);
+                    let _ = dict.add_raw(key, Value::Sds(value));
+                }
+                object.ptr = RedisValue::Hash(dict);
+                object.encoding = OBJ_ENCODING_HT;
+                Ok(object)
+            }
+            RDB_TYPE_LIST => {
+                let mut object = RedisObject::create_list_object();
+                let len = Self::rdb_load_len(buf)?;
+                if let RedisValue::List(ListObject::LinkList(list)) = &mut object.ptr {
+                    for _ in 0..len {
+                        list.add_node_tail(Self::load_string(buf)?);
+                    }
                 }
                 Ok(object)
             }
             RDB_TYPE_ZSET_2 => {
                 let mut object = RedisObject::create_zset_object();
                 let len = Self::rdb_load_len(buf)?;
+                if let RedisValue::SortSet(zset) = &mut object.ptr {
+                    for _ in 0..len {
+                        let ele = Self::load_string(buf)?;
+                        Self::rdb_ensure(buf, 8)?;
+                        let score = buf.get_f64();
+                        zset.zsl.insert(score, ele.clone());
+                        let _ = zset.dict.add_raw(ele, Value::F(score));
+                    }
+                }
+                Ok(object)
+            }
+            RDB_TYPE_SET => {
+                let len = Self::rdb_load_len(buf)?;
+                let mut object = RedisObject::create_set_listpack_object();
+                set_type_convert(&mut object, OBJ_ENCODING_HT);
                 for _ in 0..len {
-                    let ele = Self::load_string(buf)?;
-                    let score = buf.get_f64();
-                    SortedCmd::zadd(&mut object, None, score, ele);
+                    set_type_add(&mut object, &Self::load_string(buf)?);
                 }
                 Ok(object)
             }
+            RDB_TYPE_SET_INTSET => {
+                let blob = Self::rdb_load_raw(buf)?;
+                let is = IntSet::from_blob(&blob).ok_or(PersistError::DecodeErr("invalid intset".to_string()))?;
+                let mut object = RedisObject::create_intset_object();
+                object.ptr = RedisValue::Set(is);
+                Ok(object)
+            }
+            RDB_TYPE_HASH_LISTPACK | RDB_TYPE_SET_LISTPACK | RDB_TYPE_ZSET_LISTPACK => {
+                let lp = ListPack::from_bytes(Self::rdb_load_raw(buf)?);
+                if !lp.validate_integrity(true) {
+                    return Err(PersistError::DecodeErr("invalid listpack".to_string()).into());
+                }
+                let mut object = match obj_type {
+                    RDB_TYPE_HASH_LISTPACK => RedisObject::create_hash_object(),
+                    RDB_TYPE_SET_LISTPACK => RedisObject::create_set_listpack_object(),
+                    _ => RedisObject::create_zset_listpack_object(),
+                };
+                if obj_type != RDB_TYPE_SET_LISTPACK && lp.len() % 2 != 0 {
+                    return Err(PersistError::DecodeErr("listpack with an odd number of elements".to_string()).into());
+                }
+                object.ptr = RedisValue::ListPack(lp);
+                Ok(object)
+            }
+            RDB_TYPE_STREAM_LISTPACKS_3 => {
+                let mut object = RedisObject::create_stream_object();
+                object.ptr = RedisValue::Stream(Box::new(Self::rdb_load_stream(buf)?));
+                Ok(object)
+            }
             _ => {
                 Err(PersistError::TypeErr("obj_type".to_string()).into())
             }
         }
     }
 
+    /// Fails unless `buf` holds at least `len` more bytes, so a truncated file is an
+    /// error rather than a panic
+    fn rdb_ensure(buf: &BytesMut, len: usize) -> Result<()> {
+        if buf.remaining() < len {
+            return Err(PersistError::LoadErr("unexpected end of the rdb file".to_string()).into());
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     fn rdb_load_len(buf: &mut BytesMut) -> Result<u64> {
+        Self::rdb_ensure(buf, 1)?;
         let len_type = buf.get_u8();
         let len = match (len_type & 0xC0) >> 6 {
             RDB_ENCVAL | RDB_6BITLEN => {
                 (len_type & 0x3F) as u64
             }
             RDB_14BITLEN => {
+                Self::rdb_ensure(buf, 1)?;
                 let mut res = ((len_type & 0x3F) as u64) << 8;
                 res |= buf.get_u8() as u64;
                 res
             }
-            RDB_32BITLEN => {
+            _ if len_type == RDB_32BITLEN => {
+                Self::rdb_ensure(buf, 4)?;
                 buf.get_u32() as u64
             }
-            RDB_64BITLEN => {
+            _ if len_type == RDB_64BITLEN => {
+                Self::rdb_ensure(buf, 8)?;
                 buf.get_u64()
             }
             _ => {
@@ -385,8 +677,25 @@ impl Rdb {
         Ok(len)
     }
 
+    /// A unix time in milliseconds, as 8 little endian bytes
+    fn rdb_load_millisecond_time(buf: &mut BytesMut) -> Result<i64> {
+        Self::rdb_ensure(buf, 8)?;
+        Ok(buf.get_i64_le())
+    }
+
+    fn rdb_load_raw(buf: &mut BytesMut) -> Result<Vec<u8>> {
+        let len = Self::rdb_load_len(buf)?;
+        if len > buf.len() as u64 {
+            return Err(PersistError::LoadErr("blob longer than the rdb file".to_string()).into());
+        }
+        Ok(buf.split_to(len as usize).to_vec())
+    }
+
     fn load_string(buf: &mut BytesMut) -> Result<String> {
         let len = Self::rdb_load_len(buf)?;
+        if len > buf.len() as u64 {
+            return Err(PersistError::LoadErr("string longer than the rdb file".to_string()).into());
+        }
         let s = String::from_utf8(buf
             .split_to(len as usize)
             .to_vec())
@@ -396,3 +705,93 @@ impl Rdb {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use crate::db::db::RedisDb;
+    use crate::db::db_engine::RDbCommand;
+    use crate::db::expire::mstime;
+    use crate::db::kvstore::KVSTORE_ALLOCATE_DICTS_ON_DEMAND;
+    use crate::db::data_structure::stream::{Stream, StreamID};
+    use crate::db::object::{RedisObject, RedisValue};
+    use crate::persistence::rdb::{rdb_dump_db, rdb_payload, Rdb};
+    use crate::persistence::RDB_TYPE_STREAM_LISTPACKS_3;
+
+    fn string_value(object: &RedisObject) -> String {
+        match &object.ptr {
+            RedisValue::String(s) => s.clone(),
+            _ => panic!("not a string object"),
+        }
+    }
+
+    /// The key, value and expire time of every key `buf` holds
+    fn load(buf: &[u8], skip_expired: bool) -> Vec<(String, String, Option<i64>)> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        Rdb::create(vec![tx]).load_buf(BytesMut::from(buf), skip_expired).unwrap();
+        let mut keys = vec![];
+        while let Ok(cmd) = rx.try_recv() {
+            if let RDbCommand::RdbData { key, value, expire } = cmd {
+                keys.push((string_value(&key), string_value(&value), expire));
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn expire() {
+        let mut db = RedisDb::create(0, KVSTORE_ALLOCATE_DICTS_ON_DEMAND, 0);
+        let string = |s: &str| RedisObject::create_string_object(s.to_string());
+        let when = mstime() + 100_000;
+        db.add(string("volatile"), string("v1"));
+        db.set_expire("volatile", when);
+        db.add(string("persistent"), string("v2"));
+        db.add(string("stale"), string("v3"));
+        db.set_expire("stale", mstime() - 1000);
+        let payload = rdb_payload(&[], &[rdb_dump_db(&mut db).unwrap()]).unwrap();
+
+        let keys = load(&payload, true);
+        assert_eq!(keys, vec![
+            ("persistent".to_string(), "v2".to_string(), None),
+            ("volatile".to_string(), "v1".to_string(), Some(when)),
+        ]);
+        // a replica loads the keys its master did not delete yet
+        assert_eq!(load(&payload, false).len(), 3);
+
+        let mut truncated = payload.to_vec();
+        let opcode = truncated.iter().position(|&b| b == super::RDB_OPCODE_EXPIRETIME_MS).unwrap();
+        truncated.truncate(opcode + 4);
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        assert!(Rdb::create(vec![tx]).load_buf(BytesMut::from(&truncated[..]), true).is_err());
+    }
+
+    #[test]
+    fn stream() {
+        let mut s = Stream::new();
+        for i in 1..=150 {
+            s.append(StreamID::new(i, 0), vec![("f".to_string(), i.to_string())]);
+        }
+        s.delete(&StreamID::new(3, 0));
+        s.create_group("g", StreamID::MIN, None);
+        s.read_group_new("g", "alice", Some(2), false, 1000);
+        let mut object = RedisObject::create_stream_object();
+        object.ptr = RedisValue::Stream(Box::new(s.clone()));
+        let mut buf = BytesMut::new();
+        Rdb::rdb_save_object(&mut buf, &object).unwrap();
+        let saved = buf.clone();
+
+        let loaded = Rdb::rdb_load_object(RDB_TYPE_STREAM_LISTPACKS_3, &mut buf).unwrap();
+        assert!(buf.is_empty());
+        match loaded.ptr {
+            RedisValue::Stream(loaded) => assert_eq!(*loaded, s),
+            _ => panic!("not a stream"),
+        }
+        // a truncated dump is an error rather than a panic
+        for len in 0..saved.len() {
+            let mut truncated = BytesMut::from(&saved[..len]);
+            assert!(Rdb::rdb_load_object(RDB_TYPE_STREAM_LISTPACKS_3, &mut truncated).is_err());
+        }
+    }
+}
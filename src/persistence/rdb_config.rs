@@ -1,5 +1,9 @@
+use std::sync::Mutex;
+
 use serde::{Deserialize, Serialize};
 
+use crate::config::ServerConfig;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RdbConfig {
     save_param: Vec<SaveParam>,
@@ -32,4 +36,45 @@ impl RdbConfig {
     pub fn get_save_params(&self) -> &Vec<SaveParam> {
         &self.save_param
     }
-}
\ No newline at end of file
+}
+/// Save rules in effect, changeable at runtime with CONFIG SET save
+static SAVE_PARAMS: Mutex<Vec<SaveParam>> = Mutex::new(Vec::new());
+
+/// Applies the save rules of the config file, `save` taking precedence over `rdb_config`.
+pub fn load_save_config(config: &ServerConfig) -> crate::Result<()> {
+    let params = match &config.save {
+        Some(save) => parse_save_params(save).ok_or_else(|| format!("invalid save '{}'", save))?,
+        None => config.get_param().clone(),
+    };
+    set_save_params(params);
+    Ok(())
+}
+
+pub fn save_params() -> Vec<SaveParam> {
+    SAVE_PARAMS.lock().unwrap().clone()
+}
+
+pub fn set_save_params(params: Vec<SaveParam>) {
+    *SAVE_PARAMS.lock().unwrap() = params;
+}
+
+/// Parses `<seconds> <changes>` pairs, e.g. "3600 1 300 100". An empty string
+/// disables saving.
+pub fn parse_save_params(s: &str) -> Option<Vec<SaveParam>> {
+    let args: Vec<&str> = s.split_whitespace().collect();
+    if args.len() % 2 != 0 {
+        return None;
+    }
+    args.chunks(2)
+        .map(|pair| Some(SaveParam { seconds: pair[0].parse().ok()?, changes: pair[1].parse().ok()? }))
+        .collect()
+}
+
+/// The save rules as `parse_save_params` reads them
+pub fn save_params_string() -> String {
+    save_params()
+        .iter()
+        .map(|param| format!("{} {}", param.seconds, param.changes))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
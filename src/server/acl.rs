@@ -0,0 +1,898 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::config::ServerConfig;
+use crate::db::expire::mstime;
+use crate::server::clients::DEFAULT_USER;
+use crate::util::{sha256_hex, string_match};
+
+/// Entries of the ACL LOG within this many milliseconds of each other are merged
+const ACL_LOG_GROUPING_MAX_TIME_DELTA: i64 = 60000;
+
+static ACLLOG_MAX_LEN: AtomicUsize = AtomicUsize::new(128);
+/// Last `requirepass` set, the default user only keeps its hash
+static REQUIREPASS: Mutex<String> = Mutex::new(String::new());
+static ACL: Mutex<Acl> = Mutex::new(Acl { users: BTreeMap::new(), log: VecDeque::new(), next_log_id: 0, file: None });
+
+/// The categories ACL rules can allow or deny with `+@<category>` / `-@<category>`
+pub const ACL_CATEGORIES: &[&str] = &[
+    "keyspace", "read", "write", "set", "sortedset", "list", "hash", "string", "bitmap", "hyperloglog", "geo",
+    "stream", "pubsub", "admin", "fast", "slow", "blocking", "dangerous", "connection", "transaction", "scripting",
+];
+
+/// Where the keys of a command are in its arguments, the command name being 0
+#[derive(Debug, Clone, Copy)]
+enum KeySpec {
+    None,
+    /// From `first` to `last` every `step`. A negative `last` counts from the end, -1
+    /// being the last argument.
+    Range { first: usize, last: isize, step: usize },
+    /// The number of keys at `numkeys`, the keys right after it
+    NumKeys { numkeys: usize },
+    /// Half of the arguments after STREAMS, the other half being the ids
+    Streams,
+}
+
+/// Where the channels of a pub/sub command are in its arguments
+#[derive(Debug, Clone, Copy)]
+enum ChannelSpec {
+    None,
+    /// Every argument is a channel
+    All,
+    /// The first argument is the channel
+    First,
+    /// Every argument is a pattern, checked literally against the user's patterns
+    Patterns,
+}
+
+/// What ACL rules know of a command
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub categories: &'static [&'static str],
+    /// Whether the command is a container, `+<command>|<subcommand>` rules applying to it
+    subcommands: bool,
+    keys: KeySpec,
+    channels: ChannelSpec,
+}
+
+const fn cmd(name: &'static str, categories: &'static [&'static str], keys: KeySpec) -> CommandSpec {
+    CommandSpec { name, categories, subcommands: false, keys, channels: ChannelSpec::None }
+}
+
+const fn container(name: &'static str, categories: &'static [&'static str], keys: KeySpec) -> CommandSpec {
+    CommandSpec { name, categories, subcommands: true, keys, channels: ChannelSpec::None }
+}
+
+const fn pubsub(name: &'static str, categories: &'static [&'static str], channels: ChannelSpec) -> CommandSpec {
+    CommandSpec { name, categories, subcommands: false, keys: KeySpec::None, channels }
+}
+
+const KEY: KeySpec = KeySpec::Range { first: 1, last: 1, step: 1 };
+const KEYS: KeySpec = KeySpec::Range { first: 1, last: -1, step: 1 };
+/// Keys followed by a timeout, as in BLPOP
+const KEYS_TIMEOUT: KeySpec = KeySpec::Range { first: 1, last: -2, step: 1 };
+const TWO_KEYS: KeySpec = KeySpec::Range { first: 1, last: 2, step: 1 };
+/// The key after a subcommand, as in XGROUP CREATE
+const SUB_KEY: KeySpec = KeySpec::Range { first: 2, last: 2, step: 1 };
+
+pub static COMMAND_TABLE: &[CommandSpec] = &[
+    // connection
+    cmd("auth", &["fast", "connection"], KeySpec::None),
+    cmd("hello", &["fast", "connection"], KeySpec::None),
+    cmd("select", &["fast", "connection"], KeySpec::None),
+    cmd("echo", &["fast", "connection"], KeySpec::None),
+    cmd("ping", &["fast", "connection"], KeySpec::None),
+    cmd("quit", &["fast", "connection"], KeySpec::None),
+    cmd("reset", &["fast", "connection"], KeySpec::None),
+    container("client", &["slow", "connection"], KeySpec::None),
+    // keyspace
+    cmd("del", &["keyspace", "write", "slow"], KEYS),
+    cmd("expire", &["keyspace", "write", "fast"], KEY),
+    cmd("pexpire", &["keyspace", "write", "fast"], KEY),
+    cmd("pexpireat", &["keyspace", "write", "fast"], KEY),
+    cmd("persist", &["keyspace", "write", "fast"], KEY),
+    cmd("ttl", &["keyspace", "read", "fast"], KEY),
+    cmd("pttl", &["keyspace", "read", "fast"], KEY),
+    // string
+    cmd("append", &["write", "string", "fast"], KEY),
+    cmd("set", &["write", "string", "slow"], KEY),
+    cmd("setex", &["write", "string", "slow"], KEY),
+    cmd("setpx", &["write", "string", "slow"], KEY),
+    cmd("setnx", &["write", "string", "fast"], KEY),
+    cmd("setxx", &["write", "string", "slow"], KEY),
+    cmd("get", &["read", "string", "fast"], KEY),
+    cmd("strlen", &["read", "string", "fast"], KEY),
+    // hash
+    cmd("hset", &["write", "hash", "fast"], KEY),
+    cmd("hget", &["read", "hash", "fast"], KEY),
+    cmd("hdel", &["write", "hash", "fast"], KEY),
+    // list
+    cmd("lpush", &["write", "list", "fast"], KEY),
+    cmd("rpush", &["write", "list", "fast"], KEY),
+    cmd("lpop", &["write", "list", "fast"], KEY),
+    cmd("rpop", &["write", "list", "fast"], KEY),
+    cmd("llen", &["read", "list", "fast"], KEY),
+    cmd("lrange", &["read", "list", "slow"], KEY),
+    cmd("lmove", &["write", "list", "slow"], TWO_KEYS),
+    cmd("lmpop", &["write", "list", "slow"], KeySpec::NumKeys { numkeys: 1 }),
+    cmd("blpop", &["write", "list", "slow", "blocking"], KEYS_TIMEOUT),
+    cmd("brpop", &["write", "list", "slow", "blocking"], KEYS_TIMEOUT),
+    cmd("blmove", &["write", "list", "slow", "blocking"], TWO_KEYS),
+    cmd("blmpop", &["write", "list", "slow", "blocking"], KeySpec::NumKeys { numkeys: 2 }),
+    // set
+    cmd("sadd", &["write", "set", "fast"], KEY),
+    cmd("srem", &["write", "set", "fast"], KEY),
+    cmd("scard", &["read", "set", "fast"], KEY),
+    cmd("sismember", &["read", "set", "fast"], KEY),
+    cmd("smembers", &["read", "set", "slow"], KEY),
+    // sorted set
+    cmd("zadd", &["write", "sortedset", "fast"], KEY),
+    cmd("zcard", &["read", "sortedset", "fast"], KEY),
+    cmd("zscore", &["read", "sortedset", "fast"], KEY),
+    cmd("zpopmin", &["write", "sortedset", "fast"], KEY),
+    cmd("zpopmax", &["write", "sortedset", "fast"], KEY),
+    cmd("zmpop", &["write", "sortedset", "slow"], KeySpec::NumKeys { numkeys: 1 }),
+    cmd("bzpopmin", &["write", "sortedset", "fast", "blocking"], KEYS_TIMEOUT),
+    cmd("bzpopmax", &["write", "sortedset", "fast", "blocking"], KEYS_TIMEOUT),
+    cmd("bzmpop", &["write", "sortedset", "slow", "blocking"], KeySpec::NumKeys { numkeys: 2 }),
+    // stream
+    cmd("xadd", &["write", "stream", "fast"], KEY),
+    cmd("xlen", &["read", "stream", "fast"], KEY),
+    cmd("xrange", &["read", "stream", "slow"], KEY),
+    cmd("xrevrange", &["read", "stream", "slow"], KEY),
+    cmd("xdel", &["write", "stream", "fast"], KEY),
+    cmd("xtrim", &["write", "stream", "slow"], KEY),
+    cmd("xread", &["read", "stream", "slow", "blocking"], KeySpec::Streams),
+    cmd("xreadgroup", &["write", "stream", "slow", "blocking"], KeySpec::Streams),
+    cmd("xack", &["write", "stream", "fast"], KEY),
+    container("xgroup", &["write", "stream", "slow"], SUB_KEY),
+    cmd("xpending", &["read", "stream", "slow"], KEY),
+    cmd("xclaim", &["write", "stream", "fast"], KEY),
+    cmd("xautoclaim", &["write", "stream", "fast"], KEY),
+    container("xinfo", &["read", "stream", "slow"], SUB_KEY),
+    // pub/sub
+    pubsub("subscribe", &["pubsub", "slow"], ChannelSpec::All),
+    pubsub("ssubscribe", &["pubsub", "slow"], ChannelSpec::All),
+    pubsub("psubscribe", &["pubsub", "slow"], ChannelSpec::Patterns),
+    pubsub("unsubscribe", &["pubsub", "slow"], ChannelSpec::None),
+    pubsub("sunsubscribe", &["pubsub", "slow"], ChannelSpec::None),
+    pubsub("punsubscribe", &["pubsub", "slow"], ChannelSpec::None),
+    pubsub("publish", &["pubsub", "fast"], ChannelSpec::First),
+    pubsub("spublish", &["pubsub", "fast"], ChannelSpec::First),
+    container("pubsub", &["pubsub", "slow"], KeySpec::None),
+    // server
+    container("acl", &["slow"], KeySpec::None),
+    container("config", &["admin", "slow", "dangerous"], KeySpec::None),
+    cmd("info", &["slow", "dangerous"], KeySpec::None),
+    cmd("shutdown", &["admin", "slow", "dangerous"], KeySpec::None),
+    container("memory", &["read", "slow"], SUB_KEY),
+    container("object", &["keyspace", "read", "slow"], SUB_KEY),
+    container("debug", &["admin", "slow", "dangerous"], KeySpec::None),
+    container("slowlog", &["admin", "slow", "dangerous"], KeySpec::None),
+    container("latency", &["admin", "slow", "dangerous"], KeySpec::None),
+    // replication
+    cmd("replicaof", &["admin", "slow", "dangerous"], KeySpec::None),
+    cmd("slaveof", &["admin", "slow", "dangerous"], KeySpec::None),
+    cmd("replconf", &["admin", "slow", "dangerous"], KeySpec::None),
+    cmd("psync", &["admin", "slow", "dangerous"], KeySpec::None),
+    cmd("wait", &["slow", "connection"], KeySpec::None),
+    cmd("role", &["admin", "fast", "dangerous"], KeySpec::None),
+    // cluster
+    container("cluster", &["slow"], KeySpec::None),
+    cmd("asking", &["fast", "connection"], KeySpec::None),
+];
+
+pub fn command_spec(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE.iter().find(|spec| spec.name == name)
+}
+
+/// The keys among `args`, the command name included, e.g. to route them to the node
+/// serving their slot
+pub fn command_keys(args: &[String]) -> Vec<&str> {
+    let name = args.first().map(|name| name.to_lowercase()).unwrap_or_default();
+    command_spec(&name).map_or_else(Vec::new, |spec| spec.keys(args))
+}
+
+impl CommandSpec {
+    /// The keys among `args`, the command name included
+    fn keys<'a>(&self, args: &'a [String]) -> Vec<&'a str> {
+        let range = match self.keys {
+            KeySpec::None => return vec![],
+            KeySpec::Range { first, last, step } => {
+                let last = if last < 0 { args.len() as isize + last } else { last };
+                (first, last.min(args.len() as isize - 1), step)
+            }
+            KeySpec::NumKeys { numkeys } => {
+                let Some(count) = args.get(numkeys).and_then(|n| n.parse::<usize>().ok()) else { return vec![] };
+                (numkeys + 1, (numkeys + count).min(args.len() - 1) as isize, 1)
+            }
+            KeySpec::Streams => {
+                let Some(pos) = args.iter().position(|arg| arg.eq_ignore_ascii_case("streams")) else { return vec![] };
+                let count = (args.len() - pos - 1) / 2;
+                (pos + 1, (pos + count) as isize, 1)
+            }
+        };
+        let (first, last, step) = range;
+        if last < first as isize {
+            return vec![];
+        }
+        (first..=last as usize).step_by(step).map(|i| args[i].as_str()).collect()
+    }
+
+    fn channels<'a>(&self, args: &'a [String]) -> Vec<&'a str> {
+        match self.channels {
+            ChannelSpec::None => vec![],
+            ChannelSpec::All | ChannelSpec::Patterns => args.iter().skip(1).map(String::as_str).collect(),
+            ChannelSpec::First => args.get(1).map(String::as_str).into_iter().collect(),
+        }
+    }
+}
+
+/// What a `+` or `-` command rule applies to
+#[derive(Debug, Clone, PartialEq)]
+enum RuleTarget {
+    All,
+    Category(String),
+    Command(String),
+    Subcommand(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CommandRule {
+    allow: bool,
+    target: RuleTarget,
+}
+
+impl CommandRule {
+    fn matches(&self, spec: &CommandSpec, sub: Option<&str>) -> bool {
+        match &self.target {
+            RuleTarget::All => true,
+            RuleTarget::Category(category) => spec.categories.contains(&category.as_str()),
+            RuleTarget::Command(name) => name == spec.name,
+            RuleTarget::Subcommand(name, subcommand) => name == spec.name && sub == Some(subcommand.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for CommandRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.allow { '+' } else { '-' };
+        match &self.target {
+            RuleTarget::All => write!(f, "{}@all", sign),
+            RuleTarget::Category(category) => write!(f, "{}@{}", sign, category),
+            RuleTarget::Command(name) => write!(f, "{}{}", sign, name),
+            RuleTarget::Subcommand(name, sub) => write!(f, "{}{}|{}", sign, name, sub),
+        }
+    }
+}
+
+/// A key pattern with the access it grants: `~` read and write, `%R~` read, `%W~` write
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+impl fmt::Display for KeyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.read, self.write) {
+            (true, true) => write!(f, "~{}", self.pattern),
+            (true, false) => write!(f, "%R~{}", self.pattern),
+            _ => write!(f, "%W~{}", self.pattern),
+        }
+    }
+}
+
+/// An ACL user: its passwords and what it may run and access
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    /// SHA-256 hex digests of the passwords
+    passwords: BTreeSet<String>,
+    /// Applied in order, the last one matching a command decides
+    commands: Vec<CommandRule>,
+    all_keys: bool,
+    keys: Vec<KeyPattern>,
+    all_channels: bool,
+    channels: Vec<String>,
+}
+
+impl User {
+    /// A new user is disabled and may do nothing until rules say otherwise.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: vec![CommandRule { allow: false, target: RuleTarget::All }],
+            all_keys: false,
+            keys: vec![],
+            all_channels: false,
+            channels: vec![],
+        }
+    }
+
+    /// The default user, which every connection starts as
+    fn default_user() -> Self {
+        let mut user = User::new(DEFAULT_USER);
+        for rule in ["on", "nopass", "~*", "&*", "+@all"] {
+            user.apply_rule(rule).unwrap();
+        }
+        user
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether the user may be used without a password
+    pub fn is_nopass(&self) -> bool {
+        self.nopass
+    }
+
+    /// Applies one ACL SETUSER rule, e.g. "on", ">secret", "~cache:*" or "+@read".
+    pub fn apply_rule(&mut self, rule: &str) -> Result<(), String> {
+        let lower = rule.to_lowercase();
+        match &lower[..] {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.apply_rule("~*")?,
+            "resetkeys" => {
+                self.all_keys = false;
+                self.keys.clear();
+            }
+            "allchannels" => self.apply_rule("&*")?,
+            "resetchannels" => {
+                self.all_channels = false;
+                self.channels.clear();
+            }
+            "allcommands" => self.apply_rule("+@all")?,
+            "nocommands" => self.apply_rule("-@all")?,
+            "reset" => {
+                for rule in ["resetpass", "resetkeys", "resetchannels", "off", "-@all"] {
+                    self.apply_rule(rule)?;
+                }
+            }
+            _ => self.apply_pattern_rule(rule)?,
+        }
+        Ok(())
+    }
+
+    fn apply_pattern_rule(&mut self, rule: &str) -> Result<(), String> {
+        if let Some(password) = rule.strip_prefix('>') {
+            self.passwords.insert(sha256_hex(password.as_bytes()));
+            self.nopass = false;
+        } else if let Some(password) = rule.strip_prefix('<') {
+            if !self.passwords.remove(&sha256_hex(password.as_bytes())) {
+                return Err("The password you are trying to remove from the user does not exist".to_string());
+            }
+        } else if let Some(hash) = rule.strip_prefix('#') {
+            if hash.len() != 64 || !hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+                return Err(
+                    "The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters"
+                        .to_string(),
+                );
+            }
+            self.passwords.insert(hash.to_string());
+            self.nopass = false;
+        } else if let Some(hash) = rule.strip_prefix('!') {
+            if !self.passwords.remove(hash) {
+                return Err("The password you are trying to remove from the user does not exist".to_string());
+            }
+        } else if rule.starts_with('~') || rule.starts_with('%') {
+            self.add_key_pattern(rule)?;
+        } else if let Some(pattern) = rule.strip_prefix('&') {
+            if pattern == "*" {
+                self.all_channels = true;
+                self.channels.clear();
+            } else if !self.all_channels && !self.channels.iter().any(|p| p == pattern) {
+                self.channels.push(pattern.to_string());
+            }
+        } else if let Some(target) = rule.strip_prefix('+') {
+            self.add_command_rule(true, target)?;
+        } else if let Some(target) = rule.strip_prefix('-') {
+            self.add_command_rule(false, target)?;
+        } else {
+            return Err("Syntax error".to_string());
+        }
+        Ok(())
+    }
+
+    fn add_key_pattern(&mut self, rule: &str) -> Result<(), String> {
+        let (read, write, pattern) = match rule.strip_prefix('~') {
+            Some(pattern) => (true, true, pattern),
+            None => {
+                let (flags, pattern) = rule[1..].split_once('~').ok_or_else(|| "Syntax error".to_string())?;
+                let flags = flags.to_uppercase();
+                if flags.is_empty() || !flags.chars().all(|c| c == 'R' || c == 'W') {
+                    return Err("Syntax error".to_string());
+                }
+                (flags.contains('R'), flags.contains('W'), pattern)
+            }
+        };
+        if pattern == "*" && read && write {
+            self.all_keys = true;
+            self.keys.clear();
+            return Ok(());
+        }
+        if self.all_keys {
+            return Err("Adding a pattern after the * pattern (or the 'allkeys' flag) is not valid and does not have any effect. Try 'resetkeys' to start with an empty list of patterns".to_string());
+        }
+        match self.keys.iter_mut().find(|key| key.pattern == pattern) {
+            Some(key) => {
+                key.read |= read;
+                key.write |= write;
+            }
+            None => self.keys.push(KeyPattern { pattern: pattern.to_string(), read, write }),
+        }
+        Ok(())
+    }
+
+    fn add_command_rule(&mut self, allow: bool, target: &str) -> Result<(), String> {
+        let target = target.to_lowercase();
+        let target = if let Some(category) = target.strip_prefix('@') {
+            if category == "all" {
+                RuleTarget::All
+            } else if ACL_CATEGORIES.contains(&category) {
+                RuleTarget::Category(category.to_string())
+            } else {
+                return Err("Unknown command or category name in ACL".to_string());
+            }
+        } else if let Some((name, sub)) = target.split_once('|') {
+            match command_spec(name) {
+                Some(spec) if spec.subcommands && !sub.is_empty() && !sub.contains('|') => {
+                    RuleTarget::Subcommand(name.to_string(), sub.to_string())
+                }
+                _ => return Err("Unknown command or category name in ACL".to_string()),
+            }
+        } else {
+            command_spec(&target).ok_or_else(|| "Unknown command or category name in ACL".to_string())?;
+            RuleTarget::Command(target)
+        };
+        // +@all and -@all override everything before them
+        if target == RuleTarget::All {
+            self.commands.clear();
+        } else {
+            self.commands.retain(|rule| rule.target != target);
+        }
+        self.commands.push(CommandRule { allow, target });
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&sha256_hex(password.as_bytes()))
+    }
+
+    fn can_run(&self, spec: &CommandSpec, sub: Option<&str>) -> bool {
+        let mut allowed = false;
+        for rule in self.commands.iter().filter(|rule| rule.matches(spec, sub)) {
+            allowed = rule.allow;
+        }
+        allowed
+    }
+
+    fn can_access_key(&self, key: &str, read: bool, write: bool) -> bool {
+        self.all_keys
+            || self.keys.iter().any(|pattern| {
+                (!read || pattern.read) && (!write || pattern.write) && string_match(&pattern.pattern, key, false)
+            })
+    }
+
+    fn can_access_channel(&self, channel: &str, literal: bool) -> bool {
+        self.all_channels
+            || self.channels.iter().any(|pattern| {
+                if literal {
+                    pattern == channel
+                } else {
+                    string_match(pattern, channel, false)
+                }
+            })
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    pub fn password_hashes(&self) -> Vec<String> {
+        self.passwords.iter().cloned().collect()
+    }
+
+    pub fn commands_string(&self) -> String {
+        self.commands.iter().map(|rule| rule.to_string()).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn keys_string(&self) -> String {
+        if self.all_keys {
+            return "~*".to_string();
+        }
+        self.keys.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn channels_string(&self) -> String {
+        if self.all_channels {
+            return "&*".to_string();
+        }
+        self.channels.iter().map(|pattern| format!("&{}", pattern)).collect::<Vec<_>>().join(" ")
+    }
+
+    /// The user as ACL LIST and the ACL file describe it
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().iter().map(|flag| flag.to_string()));
+        parts.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        let keys = self.keys_string();
+        if !keys.is_empty() {
+            parts.push(keys);
+        }
+        let channels = self.channels_string();
+        parts.push(if channels.is_empty() { "resetchannels".to_string() } else { channels });
+        parts.push(self.commands_string());
+        parts.join(" ")
+    }
+}
+
+/// Why ACL denied something, as reported by ACL LOG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclDenial {
+    Command,
+    Key,
+    Channel,
+    Auth,
+}
+
+impl AclDenial {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AclDenial::Command => "command",
+            AclDenial::Key => "key",
+            AclDenial::Channel => "channel",
+            AclDenial::Auth => "auth",
+        }
+    }
+}
+
+/// A denied command or failed authentication
+#[derive(Debug, Clone)]
+pub struct AclLogEntry {
+    pub count: u64,
+    pub reason: AclDenial,
+    /// The command, key or channel denied
+    pub object: String,
+    pub username: String,
+    pub client_info: String,
+    pub entry_id: u64,
+    /// Unix time in milliseconds
+    pub created: i64,
+    pub last_updated: i64,
+}
+
+struct Acl {
+    users: BTreeMap<String, User>,
+    /// Newest first
+    log: VecDeque<AclLogEntry>,
+    next_log_id: u64,
+    file: Option<String>,
+}
+
+/// The ACL state, the default user being created on first use.
+fn acl() -> MutexGuard<'static, Acl> {
+    let mut acl = ACL.lock().unwrap();
+    if !acl.users.contains_key(DEFAULT_USER) {
+        acl.users.insert(DEFAULT_USER.to_string(), User::default_user());
+    }
+    acl
+}
+
+/// Applies `requirepass`, then loads the users of `aclfile` if one is set.
+pub fn load_acl_config(config: &ServerConfig) -> crate::Result<()> {
+    set_acllog_max_len(config.acllog_max_len);
+    set_requirepass(&config.requirepass);
+    acl().file.clone_from(&config.aclfile);
+    if config.aclfile.is_some() {
+        acl_load()?;
+    }
+    Ok(())
+}
+
+/// Sets the password of the default user, an empty one letting anyone in.
+pub fn set_requirepass(password: &str) {
+    let mut acl = acl();
+    let user = acl.users.get_mut(DEFAULT_USER).unwrap();
+    user.apply_rule("resetpass").unwrap();
+    let rule = if password.is_empty() { "nopass".to_string() } else { format!(">{}", password) };
+    user.apply_rule(&rule).unwrap();
+    *REQUIREPASS.lock().unwrap() = password.to_string();
+}
+
+pub fn requirepass() -> String {
+    REQUIREPASS.lock().unwrap().clone()
+}
+
+pub fn acllog_max_len() -> usize {
+    ACLLOG_MAX_LEN.load(Ordering::Relaxed)
+}
+
+/// Sets the entries kept by ACL LOG, dropping the oldest ones past it.
+pub fn set_acllog_max_len(len: usize) {
+    ACLLOG_MAX_LEN.store(len, Ordering::Relaxed);
+    acl().log.truncate(len);
+}
+
+pub fn get_user(name: &str) -> Option<User> {
+    acl().users.get(name).cloned()
+}
+
+pub fn users() -> Vec<User> {
+    acl().users.values().cloned().collect()
+}
+
+/// Creates the user if needed and applies `rules` to it, all of them or none.
+pub fn set_user(name: &str, rules: &[String]) -> crate::Result<()> {
+    let mut acl = acl();
+    let mut user = acl.users.get(name).cloned().unwrap_or_else(|| User::new(name));
+    for rule in rules {
+        user.apply_rule(rule).map_err(|err| format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, err))?;
+    }
+    acl.users.insert(name.to_string(), user);
+    Ok(())
+}
+
+/// Removes the users, returning how many existed. The default user can not be removed.
+pub fn delete_users(names: &[String]) -> crate::Result<usize> {
+    if names.iter().any(|name| name == DEFAULT_USER) {
+        return Err("ERR The 'default' user cannot be removed".into());
+    }
+    let mut acl = acl();
+    Ok(names.iter().filter(|name| acl.users.remove(name.as_str()).is_some()).count())
+}
+
+/// The user `username` if it exists, is enabled and `password` is one of its passwords
+pub fn authenticate(username: &str, password: &str) -> bool {
+    acl().users.get(username).is_some_and(|user| user.enabled && user.check_password(password))
+}
+
+/// Checks that `username` may run the command in `args`, on its keys and channels.
+/// On denial returns the reason and what was denied.
+pub fn check_command(username: &str, args: &[String]) -> Result<(), (AclDenial, String)> {
+    let name = args.first().map(|name| name.to_lowercase()).unwrap_or_default();
+    // a command missing from the table can't be checked, it is denied to everyone
+    let Some(spec) = command_spec(&name) else { return Err((AclDenial::Command, name)) };
+    let acl = acl();
+    let Some(user) = acl.users.get(username) else { return Err((AclDenial::Command, name)) };
+    let sub = args.get(1).filter(|_| spec.subcommands).map(|sub| sub.to_lowercase());
+    if !user.can_run(spec, sub.as_deref()) {
+        let object = match sub {
+            Some(sub) => format!("{}|{}", name, sub),
+            None => name,
+        };
+        return Err((AclDenial::Command, object));
+    }
+    let read = spec.categories.contains(&"read");
+    let write = spec.categories.contains(&"write");
+    if let Some(key) = spec.keys(args).into_iter().find(|key| !user.can_access_key(key, read, write)) {
+        return Err((AclDenial::Key, key.to_string()));
+    }
+    let literal = matches!(spec.channels, ChannelSpec::Patterns);
+    if let Some(channel) = spec.channels(args).into_iter().find(|channel| !user.can_access_channel(channel, literal)) {
+        return Err((AclDenial::Channel, channel.to_string()));
+    }
+    Ok(())
+}
+
+/// The error replied to a denied command
+pub fn denial_message(username: &str, denial: AclDenial, object: &str) -> String {
+    match denial {
+        AclDenial::Command => format!("NOPERM User {} has no permissions to run the '{}' command", username, object),
+        AclDenial::Key => "NOPERM No permissions to access a key".to_string(),
+        AclDenial::Channel => "NOPERM No permissions to access a channel".to_string(),
+        AclDenial::Auth => "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+    }
+}
+
+/// Adds a denial to the ACL LOG, merged with a recent one for the same thing.
+pub fn acl_log(denial: AclDenial, object: &str, username: &str, client_info: String) {
+    let now = mstime();
+    let max_len = acllog_max_len();
+    let mut acl = acl();
+    let similar = acl.log.iter().position(|entry| {
+        entry.reason == denial
+            && entry.object == object
+            && entry.username == username
+            && now - entry.last_updated < ACL_LOG_GROUPING_MAX_TIME_DELTA
+    });
+    let entry = match similar.and_then(|i| acl.log.remove(i)) {
+        Some(mut entry) => {
+            entry.count += 1;
+            entry.last_updated = now;
+            entry.client_info = client_info;
+            entry
+        }
+        None => {
+            let entry_id = acl.next_log_id;
+            acl.next_log_id += 1;
+            AclLogEntry {
+                count: 1,
+                reason: denial,
+                object: object.to_string(),
+                username: username.to_string(),
+                client_info,
+                entry_id,
+                created: now,
+                last_updated: now,
+            }
+        }
+    };
+    acl.log.push_front(entry);
+    acl.log.truncate(max_len);
+}
+
+/// The `count` newest ACL LOG entries, all of them if None
+pub fn acl_log_entries(count: Option<usize>) -> Vec<AclLogEntry> {
+    let acl = acl();
+    acl.log.iter().take(count.unwrap_or(usize::MAX)).cloned().collect()
+}
+
+pub fn acl_log_reset() {
+    acl().log.clear();
+}
+
+fn acl_file() -> crate::Result<String> {
+    acl().file.clone().ok_or_else(|| {
+        "ERR This Redis instance is not configured to use an ACL file. You may want to specify users via the \
+         ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) \
+         in order to store users in the Redis configuration."
+            .into()
+    })
+}
+
+/// Parses ACL file content: one `user <name> <rules...>` per line, blank lines and
+/// lines starting with # being skipped.
+/// The users of an ACL file, `default` standing for the default user when the file
+/// has no line for it.
+fn parse_acl_file(content: &str, default: User) -> crate::Result<BTreeMap<String, User>> {
+    let mut users = BTreeMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args[0] != "user" || args.len() < 2 {
+            return Err(format!("ERR /{}: line should start with user keyword", i + 1).into());
+        }
+        let mut user = User::new(args[1]);
+        for rule in &args[2..] {
+            user.apply_rule(rule).map_err(|err| format!("ERR /{}: {}. ", i + 1, err))?;
+        }
+        if users.insert(args[1].to_string(), user).is_some() {
+            return Err(format!("ERR /{}: duplicate user '{}' found", i + 1, args[1]).into());
+        }
+    }
+    users.entry(DEFAULT_USER.to_string()).or_insert(default);
+    Ok(users)
+}
+
+/// Replaces the users with the ones of the ACL file, all of them or none.
+pub fn acl_load() -> crate::Result<()> {
+    let path = acl_file()?;
+    let content = fs::read_to_string(&path).map_err(|err| format!("ERR Error loading ACL file {}: {}", path, err))?;
+    // without a line for it the default user is kept, along with its requirepass
+    let default = get_user(DEFAULT_USER).unwrap_or_else(User::default_user);
+    let users = parse_acl_file(&content, default)?;
+    acl().users = users;
+    Ok(())
+}
+
+/// Writes every user to the ACL file, through a temporary file so a failed save keeps
+/// the previous one.
+pub fn acl_save() -> crate::Result<()> {
+    let path = acl_file()?;
+    let content: String = users().iter().map(|user| user.describe() + "\n").collect();
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, &path)).map_err(|err| format!("ERR Error saving ACL file {}: {}", path, err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::server::acl::{check_command, command_spec, parse_acl_file, AclDenial, User, DEFAULT_USER};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn user(rules: &str) -> User {
+        let mut user = User::new("alice");
+        for rule in rules.split_whitespace() {
+            user.apply_rule(rule).unwrap();
+        }
+        user
+    }
+
+    #[test]
+    fn command_rules() {
+        let alice = user("on >secret -@all +@read +client|id -get");
+        let run = |line: &str| {
+            let args = args(line);
+            let spec = command_spec(&args[0]).unwrap();
+            alice.can_run(spec, args.get(1).filter(|_| spec.subcommands).map(String::as_str))
+        };
+        assert!(run("strlen k"));
+        assert!(!run("get k"));
+        assert!(!run("set k v"));
+        assert!(run("client id"));
+        assert!(!run("client kill 1.2.3.4:5"));
+        assert_eq!(alice.commands_string(), "-@all +@read +client|id -get");
+        assert!(alice.check_password("secret"));
+        assert!(!alice.check_password("wrong"));
+
+        // +@all drops the rules before it
+        let bob = user("-get +@all");
+        assert_eq!(bob.commands_string(), "+@all");
+        assert!(User::new("carol").apply_rule("+nosuchcommand").is_err());
+        assert!(User::new("carol").apply_rule("#abc").is_err());
+
+        // a command the table doesn't know is denied even to the default user
+        assert_eq!(check_command(DEFAULT_USER, &args("nosuchcommand k")), Err((AclDenial::Command, "nosuchcommand".to_string())));
+    }
+
+    #[test]
+    fn key_and_channel_patterns() {
+        let alice = user("~cache:* %R~ro:* %W~wo:* &news.*");
+        assert!(alice.can_access_key("cache:1", true, false));
+        assert!(alice.can_access_key("ro:1", true, false));
+        assert!(!alice.can_access_key("ro:1", false, true));
+        assert!(alice.can_access_key("wo:1", false, true));
+        assert!(!alice.can_access_key("wo:1", true, false));
+        assert!(!alice.can_access_key("other", true, false));
+        assert!(alice.can_access_channel("news.sport", false));
+        assert!(!alice.can_access_channel("news.*x", true));
+        assert_eq!(alice.keys_string(), "~cache:* %R~ro:* %W~wo:*");
+
+        let spec = command_spec("blmpop").unwrap();
+        assert_eq!(spec.keys(&args("blmpop 0 2 a b left count 1")), vec!["a", "b"]);
+        let spec = command_spec("xread").unwrap();
+        assert_eq!(spec.keys(&args("xread count 1 streams s1 s2 0 0")), vec!["s1", "s2"]);
+        let spec = command_spec("blpop").unwrap();
+        assert_eq!(spec.keys(&args("blpop a b 0")), vec!["a", "b"]);
+        let spec = command_spec("del").unwrap();
+        assert!(spec.keys(&args("del")).is_empty());
+    }
+
+    #[test]
+    fn acl_file() {
+        let mut default = User::default_user();
+        default.apply_rule("resetpass").unwrap();
+        default.apply_rule(">requirepass").unwrap();
+        let users = parse_acl_file("# users\nuser alice on #2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b ~* +@all\n", default.clone()).unwrap();
+        assert!(users["alice"].check_password("secret"));
+        // the default user is kept, with its password, when the file has none
+        assert!(!users["default"].is_nopass());
+        assert!(users["default"].check_password("requirepass"));
+        assert_eq!(users["alice"].describe(), "user alice on #2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b ~* resetchannels +@all");
+        let users = parse_acl_file("user default on nopass ~* +@all\n", default.clone()).unwrap();
+        assert!(users["default"].is_nopass());
+        assert!(parse_acl_file("user alice on\nuser alice off\n", default.clone()).is_err());
+        assert!(parse_acl_file("alice on\n", default).is_err());
+    }
+}
@@ -0,0 +1,648 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::config::ServerConfig;
+use crate::util::memtoull;
+
+/// The ACL user every connection starts as
+pub const DEFAULT_USER: &str = "default";
+
+static MAXCLIENTS: AtomicUsize = AtomicUsize::new(10000);
+static CLIENT_IDLE_TIMEOUT: AtomicU64 = AtomicU64::new(0);
+static TCP_KEEPALIVE: AtomicU64 = AtomicU64::new(300);
+static CLIENT_QUERY_BUFFER_LIMIT: AtomicUsize = AtomicUsize::new(1024 * 1024 * 1024);
+static CLIENT_OUTPUT_BUFFER_LIMITS: Mutex<OutputBufferLimits> = Mutex::new(OutputBufferLimits {
+    normal: OutputBufferLimit { hard: 0, soft: 0, soft_seconds: 0 },
+    replica: OutputBufferLimit { hard: 256 * 1024 * 1024, soft: 64 * 1024 * 1024, soft_seconds: 60 },
+    pubsub: OutputBufferLimit { hard: 32 * 1024 * 1024, soft: 8 * 1024 * 1024, soft_seconds: 60 },
+});
+
+/// Applies the client settings of the config file.
+pub fn load_clients_config(config: &ServerConfig) -> crate::Result<()> {
+    set_maxclients(config.maxclients);
+    set_client_idle_timeout(config.timeout);
+    set_tcp_keepalive(config.tcp_keepalive);
+    set_client_query_buffer_limit(config.client_query_buffer_limit);
+    let limits = parse_output_buffer_limits(&config.client_output_buffer_limit)
+        .ok_or_else(|| format!("invalid client-output-buffer-limit '{}'", config.client_output_buffer_limit))?;
+    for (class, limit) in limits {
+        set_client_output_buffer_limit(class, limit);
+    }
+    Ok(())
+}
+
+/// Connections past this many are refused
+pub fn maxclients() -> usize {
+    MAXCLIENTS.load(Ordering::Relaxed)
+}
+
+pub fn set_maxclients(max: usize) {
+    MAXCLIENTS.store(max, Ordering::Relaxed);
+}
+
+/// Seconds after which an idle client is closed, 0 keeps them forever
+pub fn client_idle_timeout() -> u64 {
+    CLIENT_IDLE_TIMEOUT.load(Ordering::Relaxed)
+}
+
+pub fn set_client_idle_timeout(secs: u64) {
+    CLIENT_IDLE_TIMEOUT.store(secs, Ordering::Relaxed);
+}
+
+/// Seconds of silence before TCP keepalive probes are sent to a client, 0 disables them
+pub fn tcp_keepalive() -> u64 {
+    TCP_KEEPALIVE.load(Ordering::Relaxed)
+}
+
+pub fn set_tcp_keepalive(secs: u64) {
+    TCP_KEEPALIVE.store(secs, Ordering::Relaxed);
+}
+
+/// Bytes a client may have sent without them forming a whole command
+pub fn client_query_buffer_limit() -> usize {
+    CLIENT_QUERY_BUFFER_LIMIT.load(Ordering::Relaxed)
+}
+
+pub fn set_client_query_buffer_limit(bytes: usize) {
+    CLIENT_QUERY_BUFFER_LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+/// How many bytes of replies a client may leave unread. Past `hard` the client is closed
+/// at once, past `soft` once it stayed over it for `soft_seconds`. 0 disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputBufferLimit {
+    pub hard: u64,
+    pub soft: u64,
+    pub soft_seconds: u64,
+}
+
+impl OutputBufferLimit {
+    /// Whether `omem` bytes pending break the limit. `since` tracks when the soft limit
+    /// was first reached, and is cleared once back under it.
+    fn reached(&self, omem: u64, since: &mut Option<Instant>) -> bool {
+        if self.hard > 0 && omem >= self.hard {
+            return true;
+        }
+        if self.soft == 0 || omem < self.soft {
+            *since = None;
+            return false;
+        }
+        since.get_or_insert_with(Instant::now).elapsed().as_secs() >= self.soft_seconds
+    }
+}
+
+/// The output buffer limits of each class of clients
+#[derive(Debug)]
+struct OutputBufferLimits {
+    normal: OutputBufferLimit,
+    replica: OutputBufferLimit,
+    pubsub: OutputBufferLimit,
+}
+
+impl OutputBufferLimits {
+    fn class(&mut self, client_type: ClientType) -> &mut OutputBufferLimit {
+        match client_type {
+            ClientType::Normal | ClientType::Master => &mut self.normal,
+            ClientType::Replica => &mut self.replica,
+            ClientType::PubSub => &mut self.pubsub,
+        }
+    }
+}
+
+/// The limit of the class of `client_type`, masters sharing the one of normal clients
+pub fn client_output_buffer_limit(client_type: ClientType) -> OutputBufferLimit {
+    *CLIENT_OUTPUT_BUFFER_LIMITS.lock().unwrap().class(client_type)
+}
+
+pub fn set_client_output_buffer_limit(client_type: ClientType, limit: OutputBufferLimit) {
+    *CLIENT_OUTPUT_BUFFER_LIMITS.lock().unwrap().class(client_type) = limit;
+}
+
+/// Parses `<class> <hard> <soft> <soft seconds>` groups, e.g. "pubsub 32mb 8mb 60".
+/// The class is normal, replica (or slave) or pubsub.
+pub fn parse_output_buffer_limits(s: &str) -> Option<Vec<(ClientType, OutputBufferLimit)>> {
+    let args: Vec<&str> = s.split_whitespace().collect();
+    if args.len() % 4 != 0 {
+        return None;
+    }
+    args.chunks(4)
+        .map(|group| {
+            let client_type = ClientType::from_name(group[0]).filter(|client_type| *client_type != ClientType::Master)?;
+            let limit = OutputBufferLimit {
+                hard: memtoull(group[1])?,
+                soft: memtoull(group[2])?,
+                soft_seconds: group[3].parse().ok()?,
+            };
+            Some((client_type, limit))
+        })
+        .collect()
+}
+
+/// The output buffer limits as `parse_output_buffer_limits` reads them
+pub fn output_buffer_limits_string() -> String {
+    [ClientType::Normal, ClientType::Replica, ClientType::PubSub]
+        .iter()
+        .map(|client_type| {
+            let limit = client_output_buffer_limit(*client_type);
+            format!("{} {} {} {}", client_type.name(), limit.hard, limit.soft, limit.soft_seconds)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Client kinds, as filtered by CLIENT LIST TYPE and CLIENT KILL TYPE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientType {
+    Normal,
+    Master,
+    Replica,
+    PubSub,
+}
+
+impl ClientType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match &name.to_lowercase()[..] {
+            "normal" => Some(ClientType::Normal),
+            "master" => Some(ClientType::Master),
+            "replica" | "slave" => Some(ClientType::Replica),
+            "pubsub" => Some(ClientType::PubSub),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientType::Normal => "normal",
+            ClientType::Master => "master",
+            ClientType::Replica => "replica",
+            ClientType::PubSub => "pubsub",
+        }
+    }
+}
+
+/// Commands held back by CLIENT PAUSE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    Write,
+    All,
+}
+
+/// Whether the replies are sent, set with CLIENT REPLY. Skip drops the reply of the
+/// next command only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyMode {
+    On,
+    Off,
+    Skip,
+}
+
+/// The user of the client, what it set with CLIENT SETNAME and CLIENT SETINFO, and its last command
+#[derive(Debug, Default)]
+struct ClientMeta {
+    /// The ACL user the client is authenticated as
+    user: String,
+    name: String,
+    lib_name: String,
+    lib_ver: String,
+    last_cmd: String,
+}
+
+/// A connected client as seen by the rest of the server. The handler of the connection
+/// keeps it up to date, the registry hands it to CLIENT LIST and CLIENT KILL.
+#[derive(Debug)]
+pub struct ClientInfo {
+    pub id: u64,
+    /// Peer address as ip:port
+    pub addr: String,
+    /// Local address the client connected to
+    pub laddr: String,
+    created: Instant,
+    /// Milliseconds after `created` of the last command
+    last_interaction: AtomicU64,
+    meta: Mutex<ClientMeta>,
+    db: AtomicUsize,
+    sub: AtomicUsize,
+    psub: AtomicUsize,
+    ssub: AtomicUsize,
+    qbuf: AtomicUsize,
+    qbuf_free: AtomicUsize,
+    obuf: AtomicUsize,
+    /// Bytes of replies not written to the socket yet, queued pub/sub messages included
+    omem: AtomicUsize,
+    /// Since when `omem` is over the soft output buffer limit
+    soft_limit_reached: Mutex<Option<Instant>>,
+    tot_cmds: AtomicU64,
+    blocked: AtomicBool,
+    no_evict: AtomicBool,
+    killed: AtomicBool,
+    kill: Notify,
+    /// Set once the connection became a replica (PSYNC) or the link to the master
+    repl_role: OnceLock<ClientType>,
+}
+
+impl ClientInfo {
+    pub fn new(id: u64, addr: String, laddr: String) -> Self {
+        Self {
+            id,
+            addr,
+            laddr,
+            created: Instant::now(),
+            last_interaction: AtomicU64::new(0),
+            meta: Mutex::new(ClientMeta { user: DEFAULT_USER.to_string(), ..Default::default() }),
+            db: AtomicUsize::new(0),
+            sub: AtomicUsize::new(0),
+            psub: AtomicUsize::new(0),
+            ssub: AtomicUsize::new(0),
+            qbuf: AtomicUsize::new(0),
+            qbuf_free: AtomicUsize::new(0),
+            obuf: AtomicUsize::new(0),
+            omem: AtomicUsize::new(0),
+            soft_limit_reached: Mutex::new(None),
+            tot_cmds: AtomicU64::new(0),
+            blocked: AtomicBool::new(false),
+            no_evict: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+            kill: Notify::new(),
+            repl_role: OnceLock::new(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.meta.lock().unwrap().name.clone()
+    }
+
+    pub fn user(&self) -> String {
+        self.meta.lock().unwrap().user.clone()
+    }
+
+    pub fn set_user(&self, user: String) {
+        self.meta.lock().unwrap().user = user;
+    }
+
+    pub fn set_name(&self, name: String) {
+        self.meta.lock().unwrap().name = name;
+    }
+
+    pub fn set_lib_name(&self, lib_name: String) {
+        self.meta.lock().unwrap().lib_name = lib_name;
+    }
+
+    pub fn set_lib_ver(&self, lib_ver: String) {
+        self.meta.lock().unwrap().lib_ver = lib_ver;
+    }
+
+    /// Records a command of the client, `cmd` being its lowercase name.
+    pub fn command_received(&self, cmd: String) {
+        self.last_interaction.store(self.created.elapsed().as_millis() as u64, Ordering::Relaxed);
+        self.tot_cmds.fetch_add(1, Ordering::Relaxed);
+        self.meta.lock().unwrap().last_cmd = cmd;
+    }
+
+    pub fn set_db(&self, db: usize) {
+        self.db.store(db, Ordering::Relaxed);
+    }
+
+    pub fn set_subscriptions(&self, sub: usize, psub: usize, ssub: usize) {
+        self.sub.store(sub, Ordering::Relaxed);
+        self.psub.store(psub, Ordering::Relaxed);
+        self.ssub.store(ssub, Ordering::Relaxed);
+    }
+
+    /// Bytes of the query buffer in use and free, and bytes waiting in the output buffer
+    pub fn set_buffers(&self, qbuf: usize, qbuf_free: usize, obuf: usize) {
+        self.qbuf.store(qbuf, Ordering::Relaxed);
+        self.qbuf_free.store(qbuf_free, Ordering::Relaxed);
+        self.obuf.store(obuf, Ordering::Relaxed);
+    }
+
+    pub fn set_blocked(&self, blocked: bool) {
+        self.blocked.store(blocked, Ordering::Relaxed);
+    }
+
+    /// Marks the connection as a replica or as the link to the master, `ClientType::Replica`
+    /// or `ClientType::Master`.
+    pub fn set_repl_role(&self, role: ClientType) {
+        let _ = self.repl_role.set(role);
+    }
+
+    /// Bytes of replies queued and not written to the socket yet
+    pub fn pending_output(&self) -> usize {
+        self.omem.load(Ordering::Relaxed)
+    }
+
+    pub fn set_no_evict(&self, no_evict: bool) {
+        self.no_evict.store(no_evict, Ordering::Relaxed);
+    }
+
+    pub fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    pub fn idle(&self) -> Duration {
+        self.created.elapsed().saturating_sub(Duration::from_millis(self.last_interaction.load(Ordering::Relaxed)))
+    }
+
+    pub fn client_type(&self) -> ClientType {
+        if let Some(role) = self.repl_role.get() {
+            return *role;
+        }
+        let subscriptions = self.sub.load(Ordering::Relaxed) + self.psub.load(Ordering::Relaxed) + self.ssub.load(Ordering::Relaxed);
+        if subscriptions > 0 { ClientType::PubSub } else { ClientType::Normal }
+    }
+
+    /// Counts `bytes` of replies waiting to be written. Closes the client and returns
+    /// false, leaving the bytes out, if this takes it over its output buffer limit.
+    pub fn add_output(&self, bytes: usize) -> bool {
+        if self.killed.load(Ordering::Relaxed) {
+            return false;
+        }
+        let omem = (self.omem.fetch_add(bytes, Ordering::Relaxed) + bytes) as u64;
+        let limit = client_output_buffer_limit(self.client_type());
+        let reached = limit.reached(omem, &mut self.soft_limit_reached.lock().unwrap());
+        if reached {
+            warn!(
+                "client id={} addr={} closed for overcoming of output buffer limits ({} bytes pending)",
+                self.id, self.addr, omem
+            );
+            self.omem.fetch_sub(bytes, Ordering::Relaxed);
+            self.kill();
+            return false;
+        }
+        true
+    }
+
+    /// Counts `bytes` of replies as written to the socket.
+    pub fn output_written(&self, bytes: usize) {
+        self.omem.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Asks the handler of the client to close the connection.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+        self.kill.notify_one();
+    }
+
+    /// Resolves once the client was killed.
+    pub async fn killed(&self) {
+        if !self.killed.load(Ordering::Relaxed) {
+            self.kill.notified().await;
+        }
+    }
+
+    /// Resolves once the client stayed idle longer than `timeout`. The setting is read
+    /// again every second so CONFIG SET applies to connected clients too.
+    pub async fn idle_timed_out(&self) {
+        loop {
+            let timeout = client_idle_timeout();
+            let wait = if timeout == 0 {
+                Duration::from_secs(1)
+            } else {
+                match Duration::from_secs(timeout).checked_sub(self.idle()) {
+                    Some(left) if !left.is_zero() => left.min(Duration::from_secs(1)),
+                    _ => return,
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Flags of the client as listed by CLIENT LIST: S replica, M master, b blocked,
+    /// P pub/sub subscriber, e no-evict, N none of them.
+    fn flags(&self) -> String {
+        let mut flags = String::new();
+        match self.repl_role.get() {
+            Some(ClientType::Replica) => flags.push('S'),
+            Some(ClientType::Master) => flags.push('M'),
+            _ => {}
+        }
+        if self.blocked.load(Ordering::Relaxed) {
+            flags.push('b');
+        }
+        if self.client_type() == ClientType::PubSub {
+            flags.push('P');
+        }
+        if self.no_evict.load(Ordering::Relaxed) {
+            flags.push('e');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+
+    /// The description of the client replied by CLIENT LIST and CLIENT INFO
+    pub fn info_line(&self) -> String {
+        let meta = self.meta.lock().unwrap();
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} sub={} psub={} ssub={} multi=-1 \
+             qbuf={} qbuf-free={} obl={} oll=0 omem={} tot-cmds={} cmd={} user={} resp=2 lib-name={} lib-ver={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            meta.name,
+            self.age().as_secs(),
+            self.idle().as_secs(),
+            self.flags(),
+            self.db.load(Ordering::Relaxed),
+            self.sub.load(Ordering::Relaxed),
+            self.psub.load(Ordering::Relaxed),
+            self.ssub.load(Ordering::Relaxed),
+            self.qbuf.load(Ordering::Relaxed),
+            self.qbuf_free.load(Ordering::Relaxed),
+            self.obuf.load(Ordering::Relaxed),
+            self.omem.load(Ordering::Relaxed),
+            self.tot_cmds.load(Ordering::Relaxed),
+            if meta.last_cmd.is_empty() { "NULL" } else { &meta.last_cmd },
+            meta.user,
+            meta.lib_name,
+            meta.lib_ver
+        );
+        line
+    }
+}
+
+/// Which clients CLIENT KILL closes. Every filter set must match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    pub laddr: Option<String>,
+    pub user: Option<String>,
+    pub client_type: Option<ClientType>,
+    /// Only clients older than this many seconds
+    pub max_age: Option<u64>,
+    /// Whether the client sending the command is spared
+    pub skip_me: bool,
+}
+
+impl KillFilter {
+    fn matches(&self, client: &ClientInfo, me: u64) -> bool {
+        !(self.skip_me && client.id == me)
+            && self.id.map_or(true, |id| client.id == id)
+            && self.addr.as_ref().map_or(true, |addr| &client.addr == addr)
+            && self.laddr.as_ref().map_or(true, |laddr| &client.laddr == laddr)
+            && self.user.as_ref().map_or(true, |user| *user == client.user())
+            && self.client_type.map_or(true, |client_type| client.client_type() == client_type)
+            && self.max_age.map_or(true, |max_age| client.age().as_secs() >= max_age)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClientPause {
+    mode: PauseMode,
+    end: Instant,
+}
+
+/// The connected clients and the CLIENT PAUSE state, shared by all the handlers
+#[derive(Debug, Default)]
+pub struct ClientRegistry {
+    clients: Mutex<BTreeMap<u64, Arc<ClientInfo>>>,
+    pause: Mutex<Option<ClientPause>>,
+    unpaused: Notify,
+}
+
+impl ClientRegistry {
+    pub fn register(&self, client: Arc<ClientInfo>) {
+        self.clients.lock().unwrap().insert(client.id, client);
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// The connected clients, by id
+    pub fn list(&self) -> Vec<Arc<ClientInfo>> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Kills the clients matching `filter`, `me` being the id of the client asking.
+    /// Returns how many were killed.
+    pub fn kill(&self, filter: &KillFilter, me: u64) -> usize {
+        let clients = self.clients.lock().unwrap();
+        let mut killed = 0;
+        for client in clients.values().filter(|client| filter.matches(client, me)) {
+            client.kill();
+            killed += 1;
+        }
+        killed
+    }
+
+    /// Holds back the commands covered by `mode` for `timeout`. A pause already in
+    /// place is only extended, and a WRITE pause does not weaken an ALL one.
+    pub fn pause(&self, mode: PauseMode, timeout: Duration) {
+        let end = Instant::now() + timeout;
+        let mut pause = self.pause.lock().unwrap();
+        *pause = Some(match *pause {
+            Some(current) if current.end > Instant::now() => ClientPause {
+                mode: if current.mode == PauseMode::All { PauseMode::All } else { mode },
+                end: current.end.max(end),
+            },
+            _ => ClientPause { mode, end },
+        });
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock().unwrap() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// Whether a pause holds back a command, a write one if `write`
+    pub fn is_paused(&self, write: bool) -> bool {
+        match *self.pause.lock().unwrap() {
+            Some(pause) => pause.end > Instant::now() && (write || pause.mode == PauseMode::All),
+            None => false,
+        }
+    }
+
+    /// Waits until no pause holds back a command, a write one if `write`.
+    pub async fn wait_unpaused(&self, write: bool) {
+        loop {
+            let unpaused = self.unpaused.notified();
+            let end = match *self.pause.lock().unwrap() {
+                Some(pause) if pause.end > Instant::now() && (write || pause.mode == PauseMode::All) => pause.end,
+                _ => return,
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(end) => {}
+                _ = unpaused => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::server::clients::{
+        parse_output_buffer_limits, ClientInfo, ClientRegistry, ClientType, KillFilter, OutputBufferLimit, PauseMode,
+    };
+
+    #[test]
+    fn kill_filters() {
+        let registry = ClientRegistry::default();
+        for id in 1..=3 {
+            registry.register(Arc::new(ClientInfo::new(id, format!("127.0.0.1:{}", 5000 + id), "127.0.0.1:8000".to_string())));
+        }
+        registry.list()[2].set_subscriptions(1, 0, 0);
+
+        let by_addr = KillFilter { addr: Some("127.0.0.1:5002".to_string()), skip_me: true, ..Default::default() };
+        assert_eq!(registry.kill(&by_addr, 1), 1);
+        let pubsub = KillFilter { client_type: Some(ClientType::PubSub), skip_me: true, ..Default::default() };
+        assert_eq!(registry.kill(&pubsub, 1), 1);
+        let everyone_else = KillFilter { user: Some("default".to_string()), skip_me: true, ..Default::default() };
+        assert_eq!(registry.kill(&everyone_else, 1), 2);
+        let no_such_user = KillFilter { user: Some("nobody".to_string()), ..Default::default() };
+        assert_eq!(registry.kill(&no_such_user, 1), 0);
+        assert_eq!(registry.kill(&KillFilter::default(), 1), 3);
+    }
+
+    #[tokio::test]
+    async fn pause() {
+        let registry = ClientRegistry::default();
+        registry.pause(PauseMode::Write, Duration::from_secs(60));
+        assert!(registry.is_paused(true));
+        assert!(!registry.is_paused(false));
+        // a WRITE pause does not weaken an ALL one
+        registry.pause(PauseMode::All, Duration::from_secs(60));
+        registry.pause(PauseMode::Write, Duration::from_secs(1));
+        assert!(registry.is_paused(false));
+        registry.unpause();
+        assert!(!registry.is_paused(true));
+
+        registry.pause(PauseMode::All, Duration::from_millis(50));
+        tokio::time::timeout(Duration::from_secs(5), registry.wait_unpaused(false)).await.unwrap();
+    }
+
+    #[test]
+    fn output_buffer_limits() {
+        let limits = parse_output_buffer_limits("normal 0 0 0 pubsub 32mb 8mb 60").unwrap();
+        assert_eq!(limits[1], (ClientType::PubSub, OutputBufferLimit { hard: 32 << 20, soft: 8 << 20, soft_seconds: 60 }));
+        assert!(parse_output_buffer_limits("pubsub 32mb 8mb").is_none());
+        assert!(parse_output_buffer_limits("master 0 0 0").is_none());
+
+        let limit = OutputBufferLimit { hard: 100, soft: 50, soft_seconds: 60 };
+        let mut since = None;
+        assert!(!limit.reached(10, &mut since));
+        // over the soft limit, not for long enough yet
+        assert!(!limit.reached(60, &mut since));
+        assert!(since.is_some());
+        assert!(!limit.reached(10, &mut since));
+        assert!(since.is_none());
+        assert!(limit.reached(100, &mut since));
+
+        let no_grace = OutputBufferLimit { hard: 0, soft: 50, soft_seconds: 0 };
+        assert!(no_grace.reached(50, &mut None));
+        assert!(!OutputBufferLimit { hard: 0, soft: 0, soft_seconds: 0 }.reached(u64::MAX, &mut None));
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use crate::config::ServerConfig;
+use crate::db::evict::{
+    lfu_decay_time, lfu_log_factor, maxmemory, maxmemory_policy, maxmemory_samples, reset_evicted_keys,
+    set_lfu_decay_time, set_lfu_log_factor, set_maxmemory, set_maxmemory_policy, set_maxmemory_samples,
+    MaxMemoryPolicy,
+};
+use crate::db::expire::reset_expired_keys;
+use crate::db::object::{
+    hash_max_listpack_entries, hash_max_listpack_value, set_hash_max_listpack_entries, set_hash_max_listpack_value,
+    set_max_intset_entries, set_max_listpack_entries, set_max_listpack_value, set_set_max_intset_entries,
+    set_set_max_listpack_entries, set_set_max_listpack_value, set_zset_max_listpack_entries,
+    set_zset_max_listpack_value, zset_max_listpack_entries, zset_max_listpack_value,
+};
+use crate::persistence::rdb_config::{parse_save_params, save_params_string, set_save_params};
+use crate::server::acl::{acllog_max_len, requirepass, set_acllog_max_len, set_requirepass};
+use crate::server::clients::{
+    client_idle_timeout, client_query_buffer_limit, maxclients, output_buffer_limits_string,
+    parse_output_buffer_limits, set_client_idle_timeout, set_client_output_buffer_limit,
+    set_client_query_buffer_limit, set_maxclients, set_tcp_keepalive, tcp_keepalive,
+};
+use crate::server::latency::{latency_monitor_threshold, set_latency_monitor_threshold};
+use crate::server::notify::{keyspace_events, keyspace_events_flags_to_string, keyspace_events_string_to_flags, set_keyspace_events};
+use crate::server::replication::{
+    masterauth, masteruser, repl_backlog_size, replica_read_only, replicaof_string, set_masterauth, set_masteruser,
+    set_repl_backlog_size, set_replica_read_only,
+};
+use crate::server::slowlog::{set_slowlog_log_slower_than, set_slowlog_max_len, slowlog_log_slower_than, slowlog_max_len};
+use crate::server::shutdown::{set_shutdown_timeout, shutdown_timeout};
+use crate::server::stats::reset_stats;
+use crate::server::REDIS_CONFIG;
+use crate::util::{memtoull, string_match, yesnotoi};
+
+/// Serializes CONFIG SET and CONFIG REWRITE so each sees and leaves a consistent set of values
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+/// Values of the parameters once the config file was applied, see `record_startup_config`
+static STARTUP_VALUES: OnceLock<BTreeMap<&'static str, String>> = OnceLock::new();
+
+/// How a value is checked before it is applied
+#[derive(Debug, Clone, Copy)]
+pub enum ParamType {
+    /// An integer within `min..=max`
+    Int { min: i64, max: i64 },
+    /// A byte count with an optional unit, e.g. "100mb"
+    Memory,
+    /// Free-form, checked by the apply callback
+    String,
+}
+
+/// A configuration parameter, read by CONFIG GET and changed by CONFIG SET
+pub struct ConfigParam {
+    pub name: &'static str,
+    /// Older name also accepted, e.g. the ziplist one of a listpack threshold
+    pub alias: Option<&'static str>,
+    pub kind: ParamType,
+    /// Only set by the config file, CONFIG SET refuses it
+    pub immutable: bool,
+    /// The value in effect, in a form `apply` accepts back
+    pub get: fn() -> String,
+    /// Applies a value that passed the type check
+    pub apply: fn(&str) -> Result<(), String>,
+}
+
+impl ConfigParam {
+    /// Key of the parameter in the config file
+    fn file_key(name: &str) -> String {
+        name.replace('-', "_")
+    }
+}
+
+const fn param(name: &'static str, kind: ParamType, get: fn() -> String, apply: fn(&str) -> Result<(), String>) -> ConfigParam {
+    ConfigParam { name, alias: None, kind, immutable: false, get, apply }
+}
+
+const fn aliased(
+    name: &'static str,
+    alias: &'static str,
+    kind: ParamType,
+    get: fn() -> String,
+    apply: fn(&str) -> Result<(), String>,
+) -> ConfigParam {
+    ConfigParam { name, alias: Some(alias), kind, immutable: false, get, apply }
+}
+
+const fn immutable(name: &'static str, get: fn() -> String) -> ConfigParam {
+    ConfigParam { name, alias: None, kind: ParamType::String, immutable: true, get, apply: |_| Err("can't set immutable config".to_string()) }
+}
+
+const fn int(min: i64, max: i64) -> ParamType {
+    ParamType::Int { min, max }
+}
+
+/// Parses a value that passed the type check of an `Int` parameter.
+fn num<T: FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| "argument couldn't be parsed into an integer".to_string())
+}
+
+fn memory_error() -> String {
+    "argument must be a memory value".to_string()
+}
+
+/// A setting of the config file the server started with
+fn startup(value: fn(&ServerConfig) -> Option<String>) -> String {
+    REDIS_CONFIG.get().and_then(value).unwrap_or_default()
+}
+
+const I64_MAX: i64 = i64::MAX;
+const I32_MAX: i64 = i32::MAX as i64;
+
+static CONFIG_PARAMS: &[ConfigParam] = &[
+    param(
+        "notify-keyspace-events",
+        ParamType::String,
+        || keyspace_events_flags_to_string(keyspace_events()),
+        |v| keyspace_events_string_to_flags(v).map(set_keyspace_events).ok_or_else(|| "Invalid event class character".to_string()),
+    ),
+    param("maxmemory", ParamType::Memory, || maxmemory().to_string(), |v| memtoull(v).map(set_maxmemory).ok_or_else(memory_error)),
+    param(
+        "maxmemory-policy",
+        ParamType::String,
+        || maxmemory_policy().name().to_string(),
+        |v| MaxMemoryPolicy::from_name(v).map(set_maxmemory_policy).ok_or_else(|| "argument must be one of the maxmemory policies".to_string()),
+    ),
+    param("maxmemory-samples", int(1, 64), || maxmemory_samples().to_string(), |v| num(v).map(set_maxmemory_samples)),
+    param("lfu-log-factor", int(0, I32_MAX), || lfu_log_factor().to_string(), |v| num(v).map(set_lfu_log_factor)),
+    param("lfu-decay-time", int(0, I64_MAX), || lfu_decay_time().to_string(), |v| num(v).map(set_lfu_decay_time)),
+    param(
+        "slowlog-log-slower-than",
+        int(-1, I64_MAX),
+        || slowlog_log_slower_than().to_string(),
+        |v| num(v).map(set_slowlog_log_slower_than),
+    ),
+    param("slowlog-max-len", int(0, I64_MAX), || slowlog_max_len().to_string(), |v| num(v).map(set_slowlog_max_len)),
+    param(
+        "latency-monitor-threshold",
+        int(0, I64_MAX),
+        || latency_monitor_threshold().to_string(),
+        |v| num(v).map(set_latency_monitor_threshold),
+    ),
+    param("timeout", int(0, I32_MAX), || client_idle_timeout().to_string(), |v| num(v).map(set_client_idle_timeout)),
+    param("tcp-keepalive", int(0, I32_MAX), || tcp_keepalive().to_string(), |v| num(v).map(set_tcp_keepalive)),
+    param("maxclients", int(1, I32_MAX), || maxclients().to_string(), |v| num(v).map(set_maxclients)),
+    param(
+        "client-query-buffer-limit",
+        ParamType::Memory,
+        || client_query_buffer_limit().to_string(),
+        |v| memtoull(v).map(|n| set_client_query_buffer_limit(n as usize)).ok_or_else(memory_error),
+    ),
+    param("client-output-buffer-limit", ParamType::String, output_buffer_limits_string, |v| {
+        let limits = parse_output_buffer_limits(v).ok_or("Wrong number of arguments in buffer limit configuration")?;
+        for (class, limit) in limits {
+            set_client_output_buffer_limit(class, limit);
+        }
+        Ok(())
+    }),
+    param("requirepass", ParamType::String, requirepass, |v| {
+        set_requirepass(v);
+        Ok(())
+    }),
+    param("acllog-max-len", int(0, I64_MAX), || acllog_max_len().to_string(), |v| num(v).map(set_acllog_max_len)),
+    aliased(
+        "hash-max-listpack-entries",
+        "hash-max-ziplist-entries",
+        int(0, I64_MAX),
+        || hash_max_listpack_entries().to_string(),
+        |v| num(v).map(set_hash_max_listpack_entries),
+    ),
+    aliased(
+        "hash-max-listpack-value",
+        "hash-max-ziplist-value",
+        int(0, I64_MAX),
+        || hash_max_listpack_value().to_string(),
+        |v| num(v).map(set_hash_max_listpack_value),
+    ),
+    param(
+        "set-max-intset-entries",
+        int(0, I64_MAX),
+        || set_max_intset_entries().to_string(),
+        |v| num(v).map(set_set_max_intset_entries),
+    ),
+    param(
+        "set-max-listpack-entries",
+        int(0, I64_MAX),
+        || set_max_listpack_entries().to_string(),
+        |v| num(v).map(set_set_max_listpack_entries),
+    ),
+    param(
+        "set-max-listpack-value",
+        int(0, I64_MAX),
+        || set_max_listpack_value().to_string(),
+        |v| num(v).map(set_set_max_listpack_value),
+    ),
+    aliased(
+        "zset-max-listpack-entries",
+        "zset-max-ziplist-entries",
+        int(0, I64_MAX),
+        || zset_max_listpack_entries().to_string(),
+        |v| num(v).map(set_zset_max_listpack_entries),
+    ),
+    aliased(
+        "zset-max-listpack-value",
+        "zset-max-ziplist-value",
+        int(0, I64_MAX),
+        || zset_max_listpack_value().to_string(),
+        |v| num(v).map(set_zset_max_listpack_value),
+    ),
+    param("shutdown-timeout", int(0, I64_MAX), || shutdown_timeout().to_string(), |v| num(v).map(set_shutdown_timeout)),
+    param("save", ParamType::String, save_params_string, |v| {
+        parse_save_params(v).map(set_save_params).ok_or_else(|| "Invalid save parameters".to_string())
+    }),
+    aliased(
+        "replica-read-only",
+        "slave-read-only",
+        ParamType::String,
+        || if replica_read_only() { "yes" } else { "no" }.to_string(),
+        |v| yesnotoi(v).map(set_replica_read_only).ok_or_else(|| "argument must be 'yes' or 'no'".to_string()),
+    ),
+    param("masteruser", ParamType::String, masteruser, |v| {
+        set_masteruser(v.to_string());
+        Ok(())
+    }),
+    param("masterauth", ParamType::String, masterauth, |v| {
+        set_masterauth(v.to_string());
+        Ok(())
+    }),
+    param(
+        "repl-backlog-size",
+        ParamType::Memory,
+        || repl_backlog_size().to_string(),
+        |v| memtoull(v).map(set_repl_backlog_size).ok_or_else(memory_error),
+    ),
+    immutable("bind", || startup(|config| Some(config.bind.clone()))),
+    immutable("port", || startup(|config| Some(config.port.to_string()))),
+    immutable("dir", || std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_default()),
+    immutable("loglevel", || startup(|config| Some(config.loglevel.clone()))),
+    immutable("pidfile", || startup(|config| config.pidfile.clone())),
+    immutable("databases", || startup(|config| Some(config.db_num.to_string()))),
+    immutable("aclfile", || startup(|config| config.aclfile.clone())),
+    immutable("metrics-addr", || startup(|config| config.metrics_addr.clone())),
+    immutable("tls-port", || startup(|config| Some(config.tls_port.to_string()))),
+    immutable("tls-cert-file", || startup(|config| config.tls_cert_file.clone())),
+    immutable("tls-key-file", || startup(|config| config.tls_key_file.clone())),
+    immutable("tls-ca-cert-file", || startup(|config| config.tls_ca_cert_file.clone())),
+    immutable("tls-auth-clients", || startup(|config| Some(config.tls_auth_clients.clone()))),
+    immutable("cluster-enabled", || startup(|config| Some(config.cluster_enabled.clone()))),
+    immutable("cluster-config-file", || startup(|config| Some(config.cluster_config_file.clone()))),
+    immutable("unixsocket", || startup(|config| config.unixsocket.clone())),
+    immutable("unixsocketperm", || startup(|config| Some(config.unixsocketperm.clone()))),
+    // changed with REPLICAOF
+    immutable("replicaof", replicaof_string),
+];
+
+/// The parameter called `name` or `alias`, case insensitive
+pub fn lookup_param(name: &str) -> Option<&'static ConfigParam> {
+    CONFIG_PARAMS
+        .iter()
+        .find(|param| param.name.eq_ignore_ascii_case(name) || param.alias.is_some_and(|alias| alias.eq_ignore_ascii_case(name)))
+}
+
+/// Remembers the values the config file set, so CONFIG REWRITE only adds the
+/// parameters changed since. Called once the settings are loaded.
+pub fn record_startup_config() {
+    let _ = STARTUP_VALUES.set(CONFIG_PARAMS.iter().map(|param| (param.name, (param.get)())).collect());
+}
+
+/// The parameters matching any of `patterns` and their values. An alias is reported
+/// under the name that matched.
+pub fn config_get(patterns: &[String]) -> Vec<(&'static str, String)> {
+    let mut values = vec![];
+    for param in CONFIG_PARAMS {
+        for name in [Some(param.name), param.alias].into_iter().flatten() {
+            if patterns.iter().any(|pattern| string_match(pattern, name, true)) {
+                values.push((name, (param.get)()));
+            }
+        }
+    }
+    values
+}
+
+fn check_type(kind: ParamType, value: &str) -> Result<(), String> {
+    match kind {
+        ParamType::Int { min, max } => match value.parse::<i64>() {
+            Ok(n) if n >= min && n <= max => Ok(()),
+            Ok(_) => Err(format!("argument must be between {} and {} inclusive", min, max)),
+            Err(_) => Err("argument couldn't be parsed into an integer".to_string()),
+        },
+        ParamType::Memory => memtoull(value).map(|_| ()).ok_or_else(memory_error),
+        ParamType::String => Ok(()),
+    }
+}
+
+fn set_failed(name: &str, err: &str) -> String {
+    format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, err)
+}
+
+/// Applies every `(parameter, value)` pair or none of them: all of them are checked
+/// first, and the ones already applied are restored if a later one is refused.
+pub fn config_set(pairs: &[(String, String)]) -> Result<(), String> {
+    let _guard = CONFIG_LOCK.lock().unwrap();
+    let mut params: Vec<(&ConfigParam, &str, &str)> = Vec::with_capacity(pairs.len());
+    for (name, value) in pairs {
+        let param = lookup_param(name)
+            .ok_or_else(|| format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name))?;
+        if param.immutable {
+            return Err(set_failed(name, "can't set immutable config"));
+        }
+        if params.iter().any(|(other, ..)| std::ptr::eq(*other, param)) {
+            return Err(set_failed(name, "duplicate parameter"));
+        }
+        check_type(param.kind, value).map_err(|err| set_failed(name, &err))?;
+        params.push((param, name, value));
+    }
+    let old_values: Vec<String> = params.iter().map(|(param, ..)| (param.get)()).collect();
+    for (i, (param, name, value)) in params.iter().enumerate() {
+        if let Err(err) = (param.apply)(value) {
+            for ((param, ..), old) in params[..=i].iter().zip(&old_values).rev() {
+                let _ = (param.apply)(old);
+            }
+            return Err(set_failed(name, &err));
+        }
+    }
+    Ok(())
+}
+
+/// Zeroes the statistics INFO reports.
+pub fn config_reset_stat() {
+    reset_stats();
+    reset_evicted_keys();
+    reset_expired_keys();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Json,
+    Toml,
+}
+
+/// A top-level setting CONFIG REWRITE writes
+struct RewriteEntry {
+    /// Keys the file may hold the setting under, the first one used when adding it
+    keys: Vec<String>,
+    /// The value, already formatted for the file
+    value: String,
+    /// Whether to add the setting if the file lacks it
+    append: bool,
+}
+
+/// Writes the values in effect back to the config file the server loaded, keeping its
+/// comments and layout.
+pub fn config_rewrite() -> Result<(), String> {
+    let _guard = CONFIG_LOCK.lock().unwrap();
+    let path = REDIS_CONFIG
+        .get()
+        .and_then(|config| config.config_file.clone())
+        .ok_or("ERR The server is running without a config file")?;
+    let rewrite = || -> crate::Result<()> {
+        let content = std::fs::read_to_string(&path)?;
+        let format = if path.ends_with(".toml") || (!path.ends_with(".json") && content.parse::<toml::Table>().is_ok()) {
+            FileFormat::Toml
+        } else {
+            FileFormat::Json
+        };
+        let startup = STARTUP_VALUES.get();
+        let entries: Vec<RewriteEntry> = CONFIG_PARAMS
+            .iter()
+            .filter(|param| !param.immutable)
+            .map(|param| {
+                let value = (param.get)();
+                let append = startup.and_then(|startup| startup.get(param.name)) != Some(&value);
+                let value = match param.kind {
+                    ParamType::Int { .. } | ParamType::Memory => memtoull(&value).map_or(value, |n| n.to_string()),
+                    ParamType::String => serde_json::to_string(&value).unwrap(),
+                };
+                let keys = [Some(param.name), param.alias].into_iter().flatten().map(ConfigParam::file_key).collect();
+                RewriteEntry { keys, value, append }
+            })
+            .collect();
+        let rewritten = rewrite_config(&content, format, &entries)?;
+        // replace the file in one step so a failed write leaves the old one intact
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, rewritten)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    };
+    rewrite().map_err(|err| format!("ERR Rewriting config file: {}", err))
+}
+
+/// Byte offset where the comment of `line` starts, its length if it has none. Quoted
+/// strings are skipped.
+fn code_end(line: &str, format: FileFormat) -> usize {
+    let bytes = line.as_bytes();
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_string => i += 1,
+            b'"' => in_string = !in_string,
+            b'#' if !in_string => return i,
+            b'/' if !in_string && format == FileFormat::Json && matches!(bytes.get(i + 1), Some(b'/' | b'*')) => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    line.len()
+}
+
+/// Key of a `"key": value` (JSON) or `key = value` (TOML) line and where its value starts
+fn parse_key(line: &str, format: FileFormat) -> Option<(&str, usize)> {
+    let start = line.len() - line.trim_start().len();
+    let (key, rest) = match format {
+        FileFormat::Json => {
+            let rest = line[start..].strip_prefix('"')?;
+            let end = rest.find('"')?;
+            (&rest[..end], start + 1 + end + 1)
+        }
+        FileFormat::Toml => {
+            let end = line[start..].find(|c: char| c.is_whitespace() || c == '=')? + start;
+            (&line[start..end], end)
+        }
+    };
+    let sep = if format == FileFormat::Json { ':' } else { '=' };
+    let after = line[rest..].trim_start().strip_prefix(sep)?;
+    Some((key, line.len() - after.len()))
+}
+
+/// Replaces the values of `entries` in `content`, then adds the missing ones that should
+/// be. Only top-level settings are touched: those before the first table of a TOML file,
+/// or in the outermost object of a JSON one.
+fn rewrite_config(content: &str, format: FileFormat, entries: &[RewriteEntry]) -> Result<String, String> {
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut found = vec![false; entries.len()];
+    let mut depth = 0;
+    // line and byte offset of the brace closing the JSON object
+    let mut object_end = None;
+    let mut first_table = None;
+    let mut indent = "    ".to_string();
+    for (i, line) in lines.iter_mut().enumerate() {
+        let top_level = match format {
+            FileFormat::Json => depth == 1,
+            FileFormat::Toml => {
+                if first_table.is_none() && line.trim_start().starts_with('[') {
+                    first_table = Some(i);
+                }
+                first_table.is_none()
+            }
+        };
+        if top_level {
+            if let Some((key, value_start)) = parse_key(line, format) {
+                if let Some(n) = entries.iter().position(|entry| entry.keys.iter().any(|k| k == key)) {
+                    found[n] = true;
+                    let end = code_end(line, format);
+                    let value = line[value_start..end].trim_end();
+                    let value = value.strip_suffix(',').unwrap_or(value).trim_end();
+                    let start = value_start + (value.len() - value.trim_start().len());
+                    let end = value_start + value.len();
+                    let sep = if start == value_start { " " } else { "" };
+                    line.replace_range(start..end, &format!("{}{}", sep, entries[n].value));
+                }
+                indent = line[..line.len() - line.trim_start().len()].to_string();
+            }
+        }
+        if format == FileFormat::Json {
+            let mut in_string = false;
+            let mut escaped = false;
+            for (offset, c) in line[..code_end(line, format)].char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if in_string => escaped = true,
+                    '"' => in_string = !in_string,
+                    '{' | '[' if !in_string => depth += 1,
+                    '}' | ']' if !in_string => {
+                        depth -= 1;
+                        if depth == 0 && object_end.is_none() {
+                            object_end = Some((i, offset));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    let missing: Vec<&RewriteEntry> = entries.iter().zip(&found).filter(|(entry, found)| entry.append && !**found).map(|(entry, _)| entry).collect();
+    if !missing.is_empty() {
+        match format {
+            FileFormat::Toml => {
+                // after the last setting, before the blank lines leading to the first table
+                let mut at = first_table.unwrap_or(lines.len());
+                while at > 0 && lines[at - 1].trim().is_empty() {
+                    at -= 1;
+                }
+                let mut added: Vec<String> = missing.iter().map(|entry| format!("{} = {}", entry.keys[0], entry.value)).collect();
+                if first_table == Some(at) {
+                    added.push(String::new());
+                }
+                lines.splice(at..at, added);
+            }
+            FileFormat::Json => {
+                let (i, offset) = object_end.ok_or("can't find the end of the JSON object")?;
+                let (before, after) = lines[i].split_at(offset);
+                let (before, after) = (before.to_string(), after.to_string());
+                let mut at = i;
+                if before.trim().is_empty() {
+                    lines[i] = format!("{}{}", before, after);
+                } else {
+                    lines[i] = before;
+                    lines.insert(i + 1, after);
+                    at = i + 1;
+                }
+                // the last value before the new ones needs a comma
+                for line in lines[..at].iter_mut().rev() {
+                    let code = line[..code_end(line, format)].trim_end();
+                    if code.is_empty() {
+                        continue;
+                    }
+                    if !code.ends_with('{') && !code.ends_with(',') {
+                        line.insert(code.len(), ',');
+                    }
+                    break;
+                }
+                let added = missing.iter().enumerate().map(|(n, entry)| {
+                    let comma = if n + 1 < missing.len() { "," } else { "" };
+                    format!("{}\"{}\": {}{}", indent, entry.keys[0], entry.value, comma)
+                });
+                lines.splice(at..at, added);
+            }
+        }
+    }
+    let mut rewritten = lines.join("\n");
+    if content.ends_with('\n') || content.is_empty() {
+        rewritten.push('\n');
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::ServerConfig;
+    use crate::server::config::{rewrite_config, FileFormat, RewriteEntry};
+
+    fn entry(key: &str, value: &str, append: bool) -> RewriteEntry {
+        RewriteEntry { keys: vec![key.to_string(), format!("{}_alias", key)], value: value.to_string(), append }
+    }
+
+    #[test]
+    fn rewrite_json() {
+        let content = r#"{
+    // memory limit
+    "maxmemory": 100, // bytes
+    "maxmemory_policy": "noeviction",
+    "rdb_config": {
+        "maxmemory": 5,
+        "save_param": [],
+        "save_param_len": 1,
+        "rdb_file_name": "dump.rdb"
+    },
+    "save_alias": "1 1"
+}
+"#;
+        let entries = [
+            entry("maxmemory", "1048576", false),
+            entry("maxmemory_policy", "\"allkeys-lru\"", false),
+            entry("save", "\"60 10\"", true),
+            entry("timeout", "30", true),
+            entry("tcp_keepalive", "300", false),
+        ];
+        let rewritten = rewrite_config(content, FileFormat::Json, &entries).unwrap();
+        assert_eq!(
+            rewritten,
+            r#"{
+    // memory limit
+    "maxmemory": 1048576, // bytes
+    "maxmemory_policy": "allkeys-lru",
+    "rdb_config": {
+        "maxmemory": 5,
+        "save_param": [],
+        "save_param_len": 1,
+        "rdb_file_name": "dump.rdb"
+    },
+    "save_alias": "60 10",
+    "timeout": 30
+}
+"#
+        );
+        let config: ServerConfig = serde_json::from_reader(json_comments::StripComments::new(rewritten.as_bytes())).unwrap();
+        assert_eq!(config.maxmemory, 1048576);
+        assert_eq!(config.timeout, 30);
+
+        let rewritten = rewrite_config("{}", FileFormat::Json, &entries).unwrap();
+        assert_eq!(rewritten, "{\n    \"save\": \"60 10\",\n    \"timeout\": 30\n}");
+    }
+
+    #[test]
+    fn rewrite_toml() {
+        let content = r#"# memory limit
+maxmemory = 100 # bytes
+timeout=5
+
+[rdb_config]
+maxmemory = 5
+"#;
+        let entries = [
+            entry("maxmemory", "1048576", false),
+            entry("timeout", "30", true),
+            entry("save", "\"60 10\"", true),
+            entry("tcp_keepalive", "300", false),
+        ];
+        let rewritten = rewrite_config(content, FileFormat::Toml, &entries).unwrap();
+        assert_eq!(
+            rewritten,
+            r#"# memory limit
+maxmemory = 1048576 # bytes
+timeout= 30
+save = "60 10"
+
+[rdb_config]
+maxmemory = 5
+"#
+        );
+        let rewritten = rewrite_config("", FileFormat::Toml, &entries).unwrap();
+        assert_eq!(rewritten, "timeout = 30\nsave = \"60 10\"\n");
+    }
+}
@@ -1,22 +1,135 @@
 use crate::parser::frame::Frame;
+use crate::server::clients::client_query_buffer_limit;
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use std::io::{self, Cursor, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter, ReadBuf};
+use tokio::net::{TcpStream, UnixStream};
+use tokio_rustls::{client, server, TlsStream};
 use tracing::{debug, info};
 
+/// Transport a connection talks over: TCP, TLS or a Unix socket
 #[derive(Debug)]
-pub struct Connection {
-    pub(crate) stream: BufWriter<TcpStream>,
+pub enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    Unix(UnixStream),
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Self {
+        Stream::Tcp(stream)
+    }
+}
+
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Self {
+        Stream::Unix(stream)
+    }
+}
+
+impl From<server::TlsStream<TcpStream>> for Stream {
+    fn from(stream: server::TlsStream<TcpStream>) -> Self {
+        Stream::Tls(Box::new(TlsStream::Server(stream)))
+    }
+}
+
+impl From<client::TlsStream<TcpStream>> for Stream {
+    fn from(stream: client::TlsStream<TcpStream>) -> Self {
+        Stream::Tls(Box::new(TlsStream::Client(stream)))
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Reads and writes frames over any transport, `Stream` unless told otherwise.
+#[derive(Debug)]
+pub struct Connection<S = Stream> {
+    pub(crate) stream: BufWriter<S>,
     buffer: BytesMut,
 }
 
 impl Connection {
-    pub fn new(socket: TcpStream) -> Self {
+    pub fn write_value(frame: &Frame, bytes: &mut Vec<u8>) {
+        match frame {
+            Frame::Simple(data) => {
+                bytes.extend_from_slice(b"+");
+                bytes.extend_from_slice(data.as_bytes());
+                bytes.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(data) => {
+                bytes.extend_from_slice(b"-");
+                bytes.extend_from_slice(data.as_bytes());
+                bytes.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(data) => {
+                bytes.extend_from_slice(b":");
+                bytes.extend_from_slice(data.to_string().as_bytes());
+                bytes.extend_from_slice(b"\r\n");
+            }
+            Frame::Bulk(data) => {
+                bytes.extend_from_slice(b"$");
+                bytes.extend_from_slice(data.len().to_string().as_bytes());
+                bytes.extend_from_slice(b"\r\n");
+                bytes.extend_from_slice(data);
+                bytes.extend_from_slice(b"\r\n");
+            }
+            Frame::Null => {
+                bytes.extend_from_slice(b"$-1\r\n");
+            }
+            Frame::Array(data) => {
+                bytes.extend_from_slice(b"*");
+                bytes.extend_from_slice(data.len().to_string().as_bytes());
+                bytes.extend_from_slice(b"\r\n");
+                for item in data {
+                    Self::write_value(item, bytes);
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(socket: impl Into<S>) -> Self {
         Self {
-            stream: BufWriter::new(socket),
+            stream: BufWriter::new(socket.into()),
             buffer: BytesMut::with_capacity(4 * 1024),
         }
     }
@@ -27,13 +140,35 @@ impl Connection {
                 debug!("read frame [{:?}]", frame);
                 return Ok(Some(frame))
             }
-            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+            if 0 == self.read_buf().await? {
                 return if self.buffer.is_empty() {
                     Ok(None)
                 } else {
                     Err("connection reset by peer".into())
                 }
             }
+            let limit = client_query_buffer_limit();
+            if self.buffer.len() > limit {
+                return Err(format!("closing client that reached max query buffer length of {} bytes", limit).into());
+            }
+        }
+    }
+
+    /// Resolves once the peer closes the connection. Anything the peer sends in the
+    /// meantime is kept in the read buffer for the next `read_frame`.
+    pub async fn wait_closed(&mut self) -> crate::Result<()> {
+        loop {
+            if 0 == self.read_buf().await? {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn read_buf(&mut self) -> io::Result<usize> {
+        match self.stream.read_buf(&mut self.buffer).await {
+            // most clients close TLS connections without a close_notify, that is a close too
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            res => res,
         }
     }
 
@@ -55,49 +190,48 @@ impl Connection {
         }
     }
 
-    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        debug!("write frame [{:?}]", frame);
-        let mut bytes = vec![];
-        Self::write_value(frame, &mut bytes);
-        self.stream.write_all(bytes.as_mut_slice()).await?;
+    /// Bytes of the read buffer in use and free, and bytes not flushed yet
+    pub fn buffer_usage(&self) -> (usize, usize, usize) {
+        (self.buffer.len(), self.buffer.capacity() - self.buffer.len(), self.stream.buffer().len())
+    }
+
+    /// Writes bytes already encoded, such as the replication stream.
+    pub async fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stream.write_all(bytes).await?;
         self.stream.flush().await
     }
 
-    pub fn write_value(frame: &Frame, bytes: &mut Vec<u8>) {
-        match frame {
-            Frame::Simple(data) => {
-                bytes.extend_from_slice(b"+");
-                bytes.extend_from_slice(data.as_bytes());
-                bytes.extend_from_slice(b"\r\n");
+    /// Reads the RDB of a full resync, sent as `$<len>\r\n` and the payload with no
+    /// trailing CRLF. Newlines the master sends to keep the link alive are skipped.
+    pub async fn read_rdb_payload(&mut self) -> crate::Result<Bytes> {
+        let len = loop {
+            while self.buffer.first() == Some(&b'\n') {
+                self.buffer.advance(1);
             }
-            Frame::Error(data) => {
-                bytes.extend_from_slice(b"-");
-                bytes.extend_from_slice(data.as_bytes());
-                bytes.extend_from_slice(b"\r\n");
-            }
-            Frame::Integer(data) => {
-                bytes.extend_from_slice(b":");
-                bytes.extend_from_slice(data.to_string().as_bytes());
-                bytes.extend_from_slice(b"\r\n");
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = std::str::from_utf8(&self.buffer[..end])?;
+                let len = line.strip_prefix('$').and_then(|len| len.parse::<usize>().ok());
+                let len = len.ok_or_else(|| format!("bad RDB payload header '{}'", line))?;
+                self.buffer.advance(end + 2);
+                break len;
             }
-            Frame::Bulk(data) => {
-                bytes.extend_from_slice(b"$");
-                bytes.extend_from_slice(data.len().to_string().as_bytes());
-                bytes.extend_from_slice(b"\r\n");
-                bytes.extend_from_slice(data);
-                bytes.extend_from_slice(b"\r\n");
-            }
-            Frame::Null => {
-                bytes.extend_from_slice(b"$-1\r\n");
+            if 0 == self.read_buf().await? {
+                return Err("connection closed while reading the RDB payload".into());
             }
-            Frame::Array(data) => {
-                bytes.extend_from_slice(b"*");
-                bytes.extend_from_slice(data.len().to_string().as_bytes());
-                bytes.extend_from_slice(b"\r\n");
-                for item in data {
-                    Self::write_value(item, bytes);
-                }
+        };
+        while self.buffer.len() < len {
+            if 0 == self.read_buf().await? {
+                return Err("connection closed while reading the RDB payload".into());
             }
         }
+        Ok(self.buffer.split_to(len).freeze())
     }
-}
\ No newline at end of file
+
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        debug!("write frame [{:?}]", frame);
+        let mut bytes = vec![];
+        Connection::write_value(frame, &mut bytes);
+        self.stream.write_all(bytes.as_mut_slice()).await?;
+        self.stream.flush().await
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt::Write as _;
+use std::sync::atomic::Ordering;
+
+use crate::db::db::SERVER_CRON_HZ;
+use crate::db::evict::{evicted_keys, maxmemory, maxmemory_policy};
+use crate::db::expire::{expired_keys, mstime};
+use crate::server::clients::maxclients;
+use crate::server::pubsub::SubscriptionKind;
+use crate::server::replication::{self, repl_backlog_size, replica_read_only, LinkState};
+use crate::server::server::Handler;
+use crate::server::stats::{
+    blocked_clients, command_stats, connected_clients, instantaneous_ops_per_sec, keyspace_hits, keyspace_misses,
+    rejected_connections, total_commands_processed, total_connections_received, total_error_replies,
+};
+use crate::cluster::cluster::cluster_enabled;
+use crate::server::{REDIS_CONFIG, REDIS_SERVER};
+use crate::util::bytes_to_human;
+
+/// Sections returned by a bare INFO, or INFO default
+const DEFAULT_SECTIONS: [&str; 9] =
+    ["server", "clients", "memory", "persistence", "stats", "replication", "cpu", "cluster", "keyspace"];
+/// Every section, in the order they are reported
+const ALL_SECTIONS: [&str; 10] =
+    ["server", "clients", "memory", "persistence", "stats", "replication", "cpu", "commandstats", "cluster", "keyspace"];
+
+/// The sections asked for by INFO arguments: "default" (also when there are none),
+/// "all"/"everything", or section names. Unknown names are ignored.
+pub fn info_sections(args: &[String]) -> Vec<&'static str> {
+    if args.is_empty() {
+        return DEFAULT_SECTIONS.to_vec();
+    }
+    let args: Vec<String> = args.iter().map(|arg| arg.to_lowercase()).collect();
+    let wanted = |section: &str| {
+        args.iter().any(|arg| match &arg[..] {
+            "all" | "everything" => true,
+            "default" => DEFAULT_SECTIONS.contains(&section),
+            name => name == section,
+        })
+    };
+    ALL_SECTIONS.into_iter().filter(|section| wanted(section)).collect()
+}
+
+/// Builds the INFO reply for `sections`, as returned by `info_sections`.
+pub async fn gen_redis_info_string(handler: &Handler, sections: &[&str]) -> crate::Result<String> {
+    let mut info = String::new();
+    for section in sections {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        match *section {
+            "server" => server_info(&mut info),
+            "clients" => clients_info(&mut info),
+            "memory" => memory_info(&mut info, handler).await?,
+            "persistence" => persistence_info(&mut info),
+            "stats" => stats_info(&mut info, handler),
+            "replication" => replication_info(&mut info),
+            "cpu" => cpu_info(&mut info),
+            "commandstats" => commandstats_info(&mut info),
+            "cluster" => {
+                let _ = write!(info, "# Cluster\r\ncluster_enabled:{}\r\n", cluster_enabled() as u8);
+            }
+            "keyspace" => keyspace_info(&mut info, handler).await?,
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+fn server_info(info: &mut String) {
+    let server = unsafe { REDIS_SERVER.get().unwrap() };
+    let uptime = server.start_time.elapsed().as_secs();
+    info.push_str("# Server\r\n");
+    let _ = write!(
+        info,
+        "redis_version:{}\r\n\
+         redis_mode:{}\r\n\
+         os:{} {}\r\n\
+         arch_bits:{}\r\n\
+         multiplexing_api:tokio\r\n\
+         process_id:{}\r\n\
+         run_id:{}\r\n\
+         tcp_port:{}\r\n\
+         server_time_usec:{}\r\n\
+         uptime_in_seconds:{}\r\n\
+         uptime_in_days:{}\r\n\
+         hz:{}\r\n\
+         config_file:{}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        if cluster_enabled() { "cluster" } else { "standalone" },
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        usize::BITS,
+        std::process::id(),
+        server.run_id,
+        server.port,
+        mstime() * 1000,
+        uptime,
+        uptime / (3600 * 24),
+        SERVER_CRON_HZ,
+        REDIS_CONFIG.get().and_then(|config| config.config_file.as_deref()).unwrap_or_default()
+    );
+}
+
+fn clients_info(info: &mut String) {
+    info.push_str("# Clients\r\n");
+    let _ = write!(
+        info,
+        "connected_clients:{}\r\nmaxclients:{}\r\nblocked_clients:{}\r\n",
+        connected_clients(),
+        maxclients(),
+        blocked_clients()
+    );
+}
+
+async fn memory_info(info: &mut String, handler: &Handler) -> crate::Result<()> {
+    let mh = handler.memory_overhead().await?;
+    let rss = mh.rss.unwrap_or(0);
+    info.push_str("# Memory\r\n");
+    let _ = write!(
+        info,
+        "used_memory:{}\r\n\
+         used_memory_human:{}\r\n\
+         used_memory_rss:{}\r\n\
+         used_memory_rss_human:{}\r\n\
+         used_memory_peak:{}\r\n\
+         used_memory_peak_human:{}\r\n\
+         used_memory_peak_perc:{:.2}%\r\n\
+         used_memory_overhead:{}\r\n\
+         used_memory_startup:{}\r\n\
+         used_memory_dataset:{}\r\n\
+         used_memory_dataset_perc:{:.2}%\r\n\
+         maxmemory:{}\r\n\
+         maxmemory_human:{}\r\n\
+         maxmemory_policy:{}\r\n\
+         mem_fragmentation_ratio:{:.2}\r\n\
+         mem_allocator:libc\r\n",
+        mh.total_allocated,
+        bytes_to_human(mh.total_allocated as u64),
+        rss,
+        bytes_to_human(rss as u64),
+        mh.peak_allocated,
+        bytes_to_human(mh.peak_allocated as u64),
+        mh.peak_perc,
+        mh.overhead_total,
+        mh.startup_allocated,
+        mh.dataset,
+        mh.dataset_perc,
+        maxmemory(),
+        bytes_to_human(maxmemory()),
+        maxmemory_policy().name(),
+        mh.fragmentation().unwrap_or(0.0)
+    );
+    Ok(())
+}
+
+fn persistence_info(info: &mut String) {
+    let server = unsafe { REDIS_SERVER.get().unwrap() };
+    info.push_str("# Persistence\r\n");
+    let _ = write!(
+        info,
+        "loading:0\r\n\
+         rdb_changes_since_last_save:{}\r\n\
+         rdb_bgsave_in_progress:{}\r\n\
+         rdb_last_save_time:{}\r\n\
+         rdb_last_bgsave_status:{}\r\n\
+         rdb_last_bgsave_time_sec:{}\r\n\
+         aof_enabled:0\r\n",
+        server.dirty.load(Ordering::Relaxed),
+        server.rdb_bgsave_in_progress.load(Ordering::Relaxed) as u8,
+        server.lastsave.load(Ordering::Relaxed),
+        if server.last_bgsave_ok.load(Ordering::Relaxed) { "ok" } else { "err" },
+        server.last_bgsave_time_sec.load(Ordering::Relaxed)
+    );
+}
+
+fn stats_info(info: &mut String, handler: &Handler) {
+    let (channels, patterns) = {
+        let pubsub = handler.pubsub();
+        (pubsub.channels(SubscriptionKind::Channel, None).len(), pubsub.num_pat())
+    };
+    info.push_str("# Stats\r\n");
+    let _ = write!(
+        info,
+        "total_connections_received:{}\r\n\
+         total_commands_processed:{}\r\n\
+         rejected_connections:{}\r\n\
+         instantaneous_ops_per_sec:{}\r\n\
+         expired_keys:{}\r\n\
+         evicted_keys:{}\r\n\
+         keyspace_hits:{}\r\n\
+         keyspace_misses:{}\r\n\
+         pubsub_channels:{}\r\n\
+         pubsub_patterns:{}\r\n\
+         total_error_replies:{}\r\n",
+        total_connections_received(),
+        total_commands_processed(),
+        rejected_connections(),
+        instantaneous_ops_per_sec(),
+        expired_keys(),
+        evicted_keys(),
+        keyspace_hits(),
+        keyspace_misses(),
+        channels,
+        patterns,
+        total_error_replies()
+    );
+}
+
+fn replication_info(info: &mut String) {
+    let repl = replication::replication_info();
+    info.push_str("# Replication\r\n");
+    let _ = write!(info, "role:{}\r\n", if repl.master.is_some() { "slave" } else { "master" });
+    if let Some(master) = &repl.master {
+        let up = master.state == LinkState::Connected;
+        let _ = write!(
+            info,
+            "master_host:{}\r\n\
+             master_port:{}\r\n\
+             master_link_status:{}\r\n\
+             master_last_io_seconds_ago:{}\r\n\
+             master_sync_in_progress:{}\r\n\
+             slave_repl_offset:{}\r\n\
+             slave_read_only:{}\r\n",
+            master.host,
+            master.port,
+            if up { "up" } else { "down" },
+            if up { master.last_io_secs as i64 } else { -1 },
+            (master.state == LinkState::Transfer) as u8,
+            repl.master_repl_offset,
+            replica_read_only() as u8
+        );
+        if !up {
+            let _ = write!(info, "master_link_down_since_seconds:{}\r\n", master.down_since_secs);
+        }
+    }
+    let _ = write!(info, "connected_slaves:{}\r\n", repl.replicas.len());
+    for (i, replica) in repl.replicas.iter().enumerate() {
+        let state = if replica.online { "online" } else { "wait_bgsave" };
+        let _ = write!(
+            info,
+            "slave{}:ip={},port={},state={},offset={},lag={}\r\n",
+            i, replica.ip, replica.port, state, replica.ack_offset, replica.lag
+        );
+    }
+    let (backlog_size, backlog_first_byte, backlog_histlen) = repl.backlog.unwrap_or((0, 0, 0));
+    let _ = write!(
+        info,
+        "master_replid:{}\r\n\
+         master_replid2:{}\r\n\
+         master_repl_offset:{}\r\n\
+         second_repl_offset:{}\r\n\
+         repl_backlog_active:{}\r\n\
+         repl_backlog_size:{}\r\n\
+         repl_backlog_first_byte_offset:{}\r\n\
+         repl_backlog_histlen:{}\r\n",
+        repl.replid,
+        if repl.replid2.is_empty() { "0".repeat(40) } else { repl.replid2.clone() },
+        repl.master_repl_offset,
+        repl.second_replid_offset.map_or(-1, |offset| offset as i64),
+        repl.backlog.is_some() as u8,
+        if repl.backlog.is_some() { backlog_size } else { repl_backlog_size() as usize },
+        backlog_first_byte,
+        backlog_histlen
+    );
+}
+
+fn cpu_info(info: &mut String) {
+    let (sys, user) = cpu_usage();
+    info.push_str("# CPU\r\n");
+    let _ = write!(info, "used_cpu_sys:{:.6}\r\nused_cpu_user:{:.6}\r\n", sys, user);
+}
+
+/// System and user CPU time used by the process, in seconds
+#[cfg(unix)]
+fn cpu_usage() -> (f64, f64) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return (0.0, 0.0);
+    }
+    let secs = |tv: libc::timeval| tv.tv_sec as f64 + tv.tv_usec as f64 / 1_000_000.0;
+    (secs(usage.ru_stime), secs(usage.ru_utime))
+}
+
+#[cfg(not(unix))]
+fn cpu_usage() -> (f64, f64) {
+    (0.0, 0.0)
+}
+
+fn commandstats_info(info: &mut String) {
+    info.push_str("# Commandstats\r\n");
+    for (name, stats) in command_stats() {
+        let _ = write!(
+            info,
+            "cmdstat_{}:calls={},usec={},usec_per_call={:.2},rejected_calls=0,failed_calls={}\r\n",
+            name,
+            stats.calls,
+            stats.usec,
+            stats.usec as f64 / stats.calls.max(1) as f64,
+            stats.failed_calls
+        );
+    }
+}
+
+async fn keyspace_info(info: &mut String, handler: &Handler) -> crate::Result<()> {
+    info.push_str("# Keyspace\r\n");
+    for (id, db) in handler.keyspace().await?.iter().enumerate() {
+        if db.keys > 0 {
+            let _ = write!(info, "db{}:keys={},expires={},avg_ttl={}\r\n", id, db.keys, db.expires, db.avg_ttl);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::server::info::info_sections;
+
+    #[test]
+    fn sections() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(!info_sections(&[]).contains(&"commandstats"));
+        assert_eq!(info_sections(&args(&["all"])).len(), 10);
+        assert_eq!(info_sections(&args(&["Memory", "keyspace"])), vec!["memory", "keyspace"]);
+        assert_eq!(info_sections(&args(&["default", "commandstats"])).len(), 10);
+        assert!(info_sections(&args(&["nosuchsection"])).is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::db::expire::mstime;
+
+/// Samples kept per event, one per second at most
+const LATENCY_TS_LEN: usize = 160;
+/// Rows of the LATENCY GRAPH chart
+const LATENCY_GRAPH_ROWS: usize = 4;
+
+/// Incremental rehashing of the dicts of a kvstore, from the DB cron
+pub const LATENCY_EVENT_REHASH: &str = "rehash";
+/// One run of the active expire cycle
+pub const LATENCY_EVENT_EXPIRE_CYCLE: &str = "expire-cycle";
+/// Writing a DB to the RDB file
+pub const LATENCY_EVENT_RDB_SAVE: &str = "rdb-save";
+
+static LATENCY_MONITOR_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+static LATENCY_EVENTS: Mutex<BTreeMap<String, LatencyTimeSeries>> = Mutex::new(BTreeMap::new());
+
+/// Worst latency of an event within one second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySample {
+    /// Unix time in seconds
+    pub time: i64,
+    /// Latency in milliseconds
+    pub latency: u64,
+}
+
+/// The latest samples of an event, with its all time high
+#[derive(Debug, Clone)]
+pub struct LatencyTimeSeries {
+    idx: usize,
+    max: u64,
+    samples: [Option<LatencySample>; LATENCY_TS_LEN],
+}
+
+impl LatencyTimeSeries {
+    fn new() -> Self {
+        Self { idx: 0, max: 0, samples: [None; LATENCY_TS_LEN] }
+    }
+
+    /// Adds a sample, only raising the last one if it is from the same second.
+    fn add_sample(&mut self, time: i64, latency: u64) {
+        self.max = self.max.max(latency);
+        let prev = (self.idx + LATENCY_TS_LEN - 1) % LATENCY_TS_LEN;
+        if let Some(last) = &mut self.samples[prev] {
+            if last.time == time {
+                last.latency = last.latency.max(latency);
+                return;
+            }
+        }
+        self.samples[self.idx] = Some(LatencySample { time, latency });
+        self.idx = (self.idx + 1) % LATENCY_TS_LEN;
+    }
+
+    /// The samples, oldest first
+    pub fn history(&self) -> Vec<LatencySample> {
+        (0..LATENCY_TS_LEN).filter_map(|i| self.samples[(self.idx + i) % LATENCY_TS_LEN]).collect()
+    }
+
+    pub fn latest(&self) -> Option<LatencySample> {
+        self.samples[(self.idx + LATENCY_TS_LEN - 1) % LATENCY_TS_LEN]
+    }
+
+    /// All time high, in milliseconds
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+}
+
+/// Threshold in milliseconds, 0 disables the latency monitor
+pub fn latency_monitor_threshold() -> u64 {
+    LATENCY_MONITOR_THRESHOLD.load(Ordering::Relaxed)
+}
+
+pub fn set_latency_monitor_threshold(ms: u64) {
+    LATENCY_MONITOR_THRESHOLD.store(ms, Ordering::Relaxed);
+}
+
+/// Records `duration` for `event` if the monitor is enabled and it reached the threshold.
+pub fn latency_add_sample_if_needed(event: &str, duration: Duration) {
+    let threshold = latency_monitor_threshold();
+    let latency = duration.as_millis() as u64;
+    if threshold == 0 || latency < threshold {
+        return;
+    }
+    latency_add_sample(event, mstime() / 1000, latency);
+}
+
+fn latency_add_sample(event: &str, time: i64, latency: u64) {
+    let mut events = LATENCY_EVENTS.lock().unwrap();
+    events.entry(event.to_string()).or_insert_with(LatencyTimeSeries::new).add_sample(time, latency);
+}
+
+/// Every event sampled so far, by name
+pub fn latency_events() -> Vec<(String, LatencyTimeSeries)> {
+    LATENCY_EVENTS.lock().unwrap().iter().map(|(name, ts)| (name.clone(), ts.clone())).collect()
+}
+
+pub fn latency_event(event: &str) -> Option<LatencyTimeSeries> {
+    LATENCY_EVENTS.lock().unwrap().get(event).cloned()
+}
+
+/// Removes the samples of `events`, or of all of them if empty. Returns the number
+/// of events reset.
+pub fn latency_reset(events: &[String]) -> usize {
+    let mut all = LATENCY_EVENTS.lock().unwrap();
+    if events.is_empty() {
+        let count = all.len();
+        all.clear();
+        return count;
+    }
+    events.iter().filter(|event| all.remove(event.as_str()).is_some()).count()
+}
+
+/// An ASCII chart of the samples of `event`, as replied to LATENCY GRAPH.
+pub fn latency_graph(event: &str, ts: &LatencyTimeSeries) -> String {
+    let samples = ts.history();
+    let (min, max) = samples.iter().fold((u64::MAX, 0), |(min, max), s| (min.min(s.latency), max.max(s.latency)));
+    let mut graph = String::new();
+    let _ = writeln!(graph, "{} - high {} ms, low {} ms (all time high {} ms)", event, max, min, ts.max());
+    let _ = writeln!(graph, "{}", "-".repeat(80));
+
+    // each row is two steps high: '_' fills its lower half, '|' all of it
+    let steps = LATENCY_GRAPH_ROWS * 2;
+    let heights: Vec<usize> = samples
+        .iter()
+        .map(|s| if max == min { steps } else { 1 + ((s.latency - min) * (steps as u64 - 1) / (max - min)) as usize })
+        .collect();
+    for row in (0..LATENCY_GRAPH_ROWS).rev() {
+        let line: String = heights
+            .iter()
+            .map(|h| match h.saturating_sub(row * 2) {
+                0 => ' ',
+                1 => '_',
+                _ => '|',
+            })
+            .collect();
+        let _ = writeln!(graph, "{}", line.trim_end());
+    }
+
+    // how long ago each sample was taken, written top to bottom under its column
+    let now = mstime() / 1000;
+    let labels: Vec<Vec<char>> = samples.iter().map(|s| relative_time(now - s.time).chars().collect()).collect();
+    let label_rows = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+    for row in 0..label_rows {
+        let line: String = labels.iter().map(|label| label.get(row).copied().unwrap_or(' ')).collect();
+        let _ = writeln!(graph, "{}", line.trim_end());
+    }
+    graph
+}
+
+/// `secs` as a short label: 15s, 3m, 2h or 1d
+fn relative_time(secs: i64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+/// Advice on the sampled events, as replied to LATENCY DOCTOR.
+pub fn latency_doctor() -> String {
+    let mut report = String::new();
+    if latency_monitor_threshold() == 0 {
+        report.push_str(
+            "Latency monitoring is disabled in this Redis instance. You may use \
+             \"CONFIG SET latency-monitor-threshold <milliseconds>.\" in order to enable it.\n",
+        );
+        return report;
+    }
+    let events = latency_events();
+    if events.is_empty() {
+        report.push_str("No latency spike was observed during the lifetime of this Redis instance.\n");
+        return report;
+    }
+    let _ = writeln!(report, "Latency spikes were observed for {} event(s):\n", events.len());
+    for (i, (name, ts)) in events.iter().enumerate() {
+        let samples = ts.history();
+        let count = samples.len() as u64;
+        let avg = samples.iter().map(|s| s.latency).sum::<u64>() / count.max(1);
+        let mad = samples.iter().map(|s| s.latency.abs_diff(avg)).sum::<u64>() / count.max(1);
+        let period = match (samples.first(), samples.last()) {
+            (Some(first), Some(last)) if count > 1 => (last.time - first.time) / (count as i64 - 1),
+            _ => 0,
+        };
+        let _ = writeln!(
+            report,
+            "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {} sec). Worst all time event {}ms.",
+            i + 1,
+            name,
+            count,
+            avg,
+            mad,
+            period,
+            ts.max()
+        );
+    }
+    report.push_str("\nI have a few advices for you:\n\n");
+    let threshold = latency_monitor_threshold();
+    for (name, _) in &events {
+        let advice = match name.as_str() {
+            LATENCY_EVENT_REHASH => {
+                "- Large hash tables are being rehashed incrementally. Spikes are expected right after \
+                 the keyspace grows or shrinks a lot, and stop once the tables reached their size."
+            }
+            LATENCY_EVENT_EXPIRE_CYCLE => {
+                "- Many keys are expiring at the same time. Consider adding some randomness to the TTL \
+                 of keys set together, so they do not expire in the same second."
+            }
+            LATENCY_EVENT_RDB_SAVE => {
+                "- Saving the RDB file is slow. Consider a less frequent save policy, or a faster disk \
+                 for the directory holding the RDB file."
+            }
+            _ => continue,
+        };
+        let _ = writeln!(report, "{}", advice);
+    }
+    if threshold < 100 {
+        let _ = writeln!(
+            report,
+            "- The latency monitor threshold is set to {}ms, spikes under 100ms are often not worth \
+             investigating. Consider raising it.",
+            threshold
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use crate::server::latency::{LatencyTimeSeries, LATENCY_TS_LEN};
+
+    #[test]
+    fn time_series() {
+        let mut ts = LatencyTimeSeries::new();
+        ts.add_sample(100, 20);
+        // same second, only the worst one is kept
+        ts.add_sample(100, 50);
+        ts.add_sample(100, 10);
+        ts.add_sample(101, 30);
+        assert_eq!(ts.history().len(), 2);
+        assert_eq!(ts.history()[0].latency, 50);
+        assert_eq!(ts.latest().unwrap().latency, 30);
+        assert_eq!(ts.max(), 50);
+
+        for time in 0..LATENCY_TS_LEN as i64 * 2 {
+            ts.add_sample(1000 + time, 5);
+        }
+        let history = ts.history();
+        assert_eq!(history.len(), LATENCY_TS_LEN);
+        assert!(history.windows(2).all(|w| w[0].time < w[1].time));
+        assert_eq!(ts.max(), 50);
+    }
+}
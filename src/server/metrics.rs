@@ -0,0 +1,287 @@
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info};
+
+use crate::db::db::KeyspaceInfo;
+use crate::db::db_engine::DbHandler;
+use crate::db::evict::{evicted_keys, maxmemory};
+use crate::db::expire::expired_keys;
+use crate::server::clients::maxclients;
+use crate::server::stats::{
+    blocked_clients, command_stats, connected_clients, keyspace_hits, keyspace_misses, total_commands_processed,
+    rejected_connections, total_connections_received, total_error_replies, CommandStats,
+};
+use crate::server::zmalloc::{peak_memory, rss_memory, used_memory};
+use crate::server::REDIS_SERVER;
+
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Requests with a longer head are refused
+const MAX_REQUEST_HEAD: usize = 8192;
+/// Upper bounds of the command duration histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 14] =
+    [0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// Serves the metrics of the server on `addr` until the process exits.
+pub async fn run_metrics_server(addr: String, db_handler: Arc<DbHandler>) {
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(cause = %err, "failed to bind metrics endpoint on {}", addr);
+            return;
+        }
+    };
+    info!("serving metrics on http://{}/metrics", addr);
+    serve(listener, move || {
+        let db_handler = db_handler.clone();
+        async move { render_metrics(&db_handler).await }
+    })
+    .await
+}
+
+/// Answers `GET /metrics` on `listener` with the text built by `render`, and 404 to
+/// anything else. One request per connection.
+pub async fn serve<F, Fut>(listener: TcpListener, render: F)
+where
+    F: Fn() -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = crate::Result<String>> + Send,
+{
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                error!(cause = %err, "metrics endpoint failed to accept");
+                continue;
+            }
+        };
+        let render = render.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_request(socket, render).await {
+                debug!(cause = %err, "metrics request failed");
+            }
+        });
+    }
+}
+
+async fn handle_request<F, Fut>(mut socket: TcpStream, render: F) -> crate::Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = crate::Result<String>>,
+{
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return write_response(&mut socket, "431 Request Header Fields Too Large", "").await;
+        }
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+    // the query string, if any, is ignored
+    let path = path.split('?').next().unwrap_or_default();
+    match (method, path) {
+        ("GET", "/metrics") => match render().await {
+            Ok(body) => write_response(&mut socket, "200 OK", &body).await,
+            Err(err) => write_response(&mut socket, "500 Internal Server Error", &format!("{}\n", err)).await,
+        },
+        ("GET", _) => write_response(&mut socket, "404 Not Found", "Not Found\n").await,
+        _ => write_response(&mut socket, "405 Method Not Allowed", "").await,
+    }
+}
+
+async fn write_response(socket: &mut TcpStream, status: &str, body: &str) -> crate::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+/// All the metrics of the server, in the Prometheus text format.
+pub async fn render_metrics(db_handler: &DbHandler) -> crate::Result<String> {
+    let keyspace = db_handler.keyspace().await?;
+    let server = unsafe { REDIS_SERVER.get().ok_or("ERR server not ready")? };
+    let mut out = String::new();
+
+    gauge(&mut out, "redis_uptime_in_seconds", "Seconds since the server started", server.start_time.elapsed().as_secs());
+    gauge(&mut out, "redis_connected_clients", "Clients connected", connected_clients());
+    gauge(&mut out, "redis_blocked_clients", "Clients blocked on a blocking command", blocked_clients());
+    gauge(&mut out, "redis_max_clients", "Maximum number of connected clients", maxclients());
+    counter(&mut out, "redis_rejected_connections_total", "Connections refused because of maxclients", rejected_connections());
+    counter(&mut out, "redis_connections_received_total", "Connections accepted", total_connections_received());
+    counter(&mut out, "redis_commands_processed_total", "Commands processed", total_commands_processed());
+    counter(&mut out, "redis_error_replies_total", "Error replies sent", total_error_replies());
+    counter(&mut out, "redis_keyspace_hits_total", "Successful key lookups", keyspace_hits());
+    counter(&mut out, "redis_keyspace_misses_total", "Failed key lookups", keyspace_misses());
+    counter(&mut out, "redis_expired_keys_total", "Keys removed once expired", expired_keys());
+    counter(&mut out, "redis_evicted_keys_total", "Keys evicted by the maxmemory policy", evicted_keys());
+
+    gauge(&mut out, "redis_memory_used_bytes", "Memory allocated by the server", used_memory());
+    gauge(&mut out, "redis_memory_used_peak_bytes", "Peak of the memory allocated", peak_memory());
+    gauge(&mut out, "redis_memory_used_rss_bytes", "Resident set size of the process", rss_memory().unwrap_or(0));
+    gauge(&mut out, "redis_memory_max_bytes", "The maxmemory setting, 0 for none", maxmemory());
+
+    gauge(&mut out, "redis_rdb_changes_since_last_save", "Changes since the last save", server.dirty.load(Ordering::Relaxed));
+    gauge(
+        &mut out,
+        "redis_rdb_bgsave_in_progress",
+        "Whether a save is running",
+        server.rdb_bgsave_in_progress.load(Ordering::Relaxed) as u8,
+    );
+    gauge(
+        &mut out,
+        "redis_rdb_last_save_timestamp_seconds",
+        "Unix time of the last successful save",
+        server.lastsave.load(Ordering::Relaxed),
+    );
+    gauge(
+        &mut out,
+        "redis_rdb_last_bgsave_status",
+        "Whether the last save succeeded",
+        server.last_bgsave_ok.load(Ordering::Relaxed) as u8,
+    );
+    gauge(
+        &mut out,
+        "redis_rdb_last_bgsave_duration_sec",
+        "Duration of the last save, -1 if none happened yet",
+        server.last_bgsave_time_sec.load(Ordering::Relaxed),
+    );
+    gauge(&mut out, "redis_aof_enabled", "Whether the append only file is enabled", 0);
+
+    write_keyspace_metrics(&mut out, &keyspace);
+    write_command_metrics(&mut out, &command_stats());
+    Ok(out)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = write!(out, "# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Key counts and rehashing dicts of the DBs holding keys, labelled by DB index
+fn write_keyspace_metrics(out: &mut String, keyspace: &[KeyspaceInfo]) {
+    let dbs: Vec<_> = keyspace.iter().enumerate().filter(|(_, db)| db.keys > 0).collect();
+    header(out, "redis_db_keys", "gauge", "Keys of the DB");
+    for (id, db) in &dbs {
+        let _ = writeln!(out, "redis_db_keys{{db=\"db{}\"}} {}", id, db.keys);
+    }
+    header(out, "redis_db_keys_expiring", "gauge", "Keys of the DB with a timeout");
+    for (id, db) in &dbs {
+        let _ = writeln!(out, "redis_db_keys_expiring{{db=\"db{}\"}} {}", id, db.expires);
+    }
+    header(out, "redis_db_avg_ttl_seconds", "gauge", "Average TTL of the keys with a timeout");
+    for (id, db) in &dbs {
+        let _ = writeln!(out, "redis_db_avg_ttl_seconds{{db=\"db{}\"}} {}", id, db.avg_ttl as f64 / 1000.0);
+    }
+    header(out, "redis_db_rehashing_dicts", "gauge", "Dicts of the DB being rehashed");
+    for (id, db) in &dbs {
+        let _ = writeln!(out, "redis_db_rehashing_dicts{{db=\"db{}\"}} {}", id, db.rehashing_dicts);
+    }
+}
+
+/// Calls, failures and duration histogram of every command called so far
+fn write_command_metrics(out: &mut String, stats: &[(String, CommandStats)]) {
+    header(out, "redis_commands_total", "counter", "Calls of the command");
+    for (name, stats) in stats {
+        let _ = writeln!(out, "redis_commands_total{{cmd=\"{}\"}} {}", escape_label(name), stats.calls);
+    }
+    header(out, "redis_commands_failed_calls_total", "counter", "Calls of the command that replied an error");
+    for (name, stats) in stats {
+        let _ = writeln!(out, "redis_commands_failed_calls_total{{cmd=\"{}\"}} {}", escape_label(name), stats.failed_calls);
+    }
+    header(out, "redis_commands_duration_seconds", "histogram", "Duration of the calls of the command");
+    for (name, stats) in stats {
+        let name = escape_label(name);
+        for bound in LATENCY_BUCKETS {
+            let count = stats.latency.count_between(0, (bound * 1_000_000.0) as u64);
+            let _ = writeln!(out, "redis_commands_duration_seconds_bucket{{cmd=\"{}\",le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "redis_commands_duration_seconds_bucket{{cmd=\"{}\",le=\"+Inf\"}} {}", name, stats.calls);
+        let _ = writeln!(out, "redis_commands_duration_seconds_sum{{cmd=\"{}\"}} {}", name, stats.usec as f64 / 1_000_000.0);
+        let _ = writeln!(out, "redis_commands_duration_seconds_count{{cmd=\"{}\"}} {}", name, stats.calls);
+    }
+}
+
+/// Escapes a label value as the text format requires
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::server::metrics::{serve, write_command_metrics};
+    use crate::server::stats::CommandStats;
+
+    async fn get(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn serves_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, || async { Ok("redis_connected_clients 1\n".to_string()) }));
+
+        let response = get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(response.ends_with("\r\n\r\nredis_connected_clients 1\n"));
+
+        let response = get(addr, "GET /other HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = get(addr, "POST /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn command_histogram() {
+        let mut stats = CommandStats::default();
+        for usec in [50, 300, 2_000_000] {
+            stats.calls += 1;
+            stats.usec += usec;
+            stats.latency.record(usec).unwrap();
+        }
+        let mut out = String::new();
+        write_command_metrics(&mut out, &[("get".to_string(), stats)]);
+        assert!(out.contains("# TYPE redis_commands_duration_seconds histogram\n"));
+        assert!(out.contains("redis_commands_total{cmd=\"get\"} 3\n"));
+        assert!(out.contains("redis_commands_duration_seconds_bucket{cmd=\"get\",le=\"0.0001\"} 1\n"));
+        assert!(out.contains("redis_commands_duration_seconds_bucket{cmd=\"get\",le=\"0.001\"} 2\n"));
+        assert!(out.contains("redis_commands_duration_seconds_bucket{cmd=\"get\",le=\"1\"} 2\n"));
+        assert!(out.contains("redis_commands_duration_seconds_bucket{cmd=\"get\",le=\"5\"} 3\n"));
+        assert!(out.contains("redis_commands_duration_seconds_bucket{cmd=\"get\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("redis_commands_duration_seconds_sum{cmd=\"get\"} 2.00035\n"));
+    }
+}
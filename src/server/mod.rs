@@ -1,6 +1,19 @@
 pub mod connection;
 pub mod server;
-mod shutdown;
+pub mod pubsub;
+pub mod notify;
+pub mod shutdown;
+pub mod zmalloc;
+pub mod stats;
+pub mod info;
+pub mod metrics;
+pub mod slowlog;
+pub mod latency;
+pub mod clients;
+pub mod acl;
+pub mod tls;
+pub mod config;
+pub mod replication;
 
 use std::sync::{OnceLock};
 use crate::config::ServerConfig;
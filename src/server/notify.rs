@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::server::REDIS_SERVER;
+
+/// Keyspace changes notification classes, see `notify-keyspace-events`
+pub const NOTIFY_KEYSPACE: u32 = 1 << 0; /* K */
+pub const NOTIFY_KEYEVENT: u32 = 1 << 1; /* E */
+pub const NOTIFY_GENERIC: u32 = 1 << 2; /* g */
+pub const NOTIFY_STRING: u32 = 1 << 3; /* $ */
+pub const NOTIFY_LIST: u32 = 1 << 4; /* l */
+pub const NOTIFY_SET: u32 = 1 << 5; /* s */
+pub const NOTIFY_HASH: u32 = 1 << 6; /* h */
+pub const NOTIFY_ZSET: u32 = 1 << 7; /* z */
+pub const NOTIFY_EXPIRED: u32 = 1 << 8; /* x */
+pub const NOTIFY_EVICTED: u32 = 1 << 9; /* e */
+pub const NOTIFY_STREAM: u32 = 1 << 10; /* t */
+pub const NOTIFY_KEY_MISS: u32 = 1 << 11; /* m (excluded from NOTIFY_ALL on purpose) */
+pub const NOTIFY_ALL: u32 = NOTIFY_GENERIC
+    | NOTIFY_STRING
+    | NOTIFY_LIST
+    | NOTIFY_SET
+    | NOTIFY_HASH
+    | NOTIFY_ZSET
+    | NOTIFY_EXPIRED
+    | NOTIFY_EVICTED
+    | NOTIFY_STREAM; /* A flag */
+
+/// Current `notify-keyspace-events` flags, read on every write so it can change at runtime
+static NOTIFY_KEYSPACE_EVENTS: AtomicU32 = AtomicU32::new(0);
+
+pub fn keyspace_events() -> u32 {
+    NOTIFY_KEYSPACE_EVENTS.load(Ordering::Relaxed)
+}
+
+pub fn set_keyspace_events(flags: u32) {
+    NOTIFY_KEYSPACE_EVENTS.store(flags, Ordering::Relaxed);
+}
+
+/// Turns a `notify-keyspace-events` string such as "Kx" into flags. Returns `None` if
+/// the string contains an unknown class.
+pub fn keyspace_events_string_to_flags(classes: &str) -> Option<u32> {
+    let mut flags = 0;
+    for c in classes.chars() {
+        flags |= match c {
+            'A' => NOTIFY_ALL,
+            'g' => NOTIFY_GENERIC,
+            '$' => NOTIFY_STRING,
+            'l' => NOTIFY_LIST,
+            's' => NOTIFY_SET,
+            'h' => NOTIFY_HASH,
+            'z' => NOTIFY_ZSET,
+            'x' => NOTIFY_EXPIRED,
+            'e' => NOTIFY_EVICTED,
+            'K' => NOTIFY_KEYSPACE,
+            'E' => NOTIFY_KEYEVENT,
+            't' => NOTIFY_STREAM,
+            'm' => NOTIFY_KEY_MISS,
+            _ => return None,
+        };
+    }
+    Some(flags)
+}
+
+/// The inverse of `keyspace_events_string_to_flags`, used by CONFIG GET
+pub fn keyspace_events_flags_to_string(flags: u32) -> String {
+    let mut res = String::new();
+    if flags & NOTIFY_ALL == NOTIFY_ALL {
+        res.push('A');
+    } else {
+        for (flag, c) in [
+            (NOTIFY_GENERIC, 'g'),
+            (NOTIFY_STRING, '$'),
+            (NOTIFY_LIST, 'l'),
+            (NOTIFY_SET, 's'),
+            (NOTIFY_HASH, 'h'),
+            (NOTIFY_ZSET, 'z'),
+            (NOTIFY_EXPIRED, 'x'),
+            (NOTIFY_EVICTED, 'e'),
+            (NOTIFY_STREAM, 't'),
+        ] {
+            if flags & flag != 0 {
+                res.push(c);
+            }
+        }
+    }
+    if flags & NOTIFY_KEYSPACE != 0 {
+        res.push('K');
+    }
+    if flags & NOTIFY_KEYEVENT != 0 {
+        res.push('E');
+    }
+    if flags & NOTIFY_KEY_MISS != 0 {
+        res.push('m');
+    }
+    res
+}
+
+/// Publishes `__keyspace@<db>__:<key>` with the event name and/or
+/// `__keyevent@<db>__:<event>` with the key name, depending on the configured flags.
+/// `class` is the notification class of the event (NOTIFY_STRING, NOTIFY_GENERIC, ...).
+/// Nothing is allocated unless the class is enabled.
+pub fn notify_keyspace_event(class: u32, event: &str, key: &str, dbid: i32) {
+    let flags = keyspace_events();
+    if flags & class == 0 {
+        return;
+    }
+    let server = unsafe { REDIS_SERVER.get() };
+    let Some(server) = server else { return };
+    let pubsub = server.pubsub.lock().unwrap();
+    if flags & NOTIFY_KEYSPACE != 0 {
+        let channel = format!("__keyspace@{}__:{}", dbid, key);
+        pubsub.publish(&channel, event);
+    }
+    if flags & NOTIFY_KEYEVENT != 0 {
+        let channel = format!("__keyevent@{}__:{}", dbid, event);
+        pubsub.publish(&channel, key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags_round_trip() {
+        assert_eq!(keyspace_events_string_to_flags(""), Some(0));
+        assert_eq!(keyspace_events_string_to_flags("Ex"), Some(NOTIFY_KEYEVENT | NOTIFY_EXPIRED));
+        assert_eq!(keyspace_events_string_to_flags("KA"), Some(NOTIFY_KEYSPACE | NOTIFY_ALL));
+        assert_eq!(keyspace_events_string_to_flags("Kq"), None);
+
+        for classes in ["", "Ex", "g$K", "AKE", "lshzK", "Km"] {
+            let flags = keyspace_events_string_to_flags(classes).unwrap();
+            let s = keyspace_events_flags_to_string(flags);
+            assert_eq!(keyspace_events_string_to_flags(&s), Some(flags));
+        }
+        assert_eq!(keyspace_events_flags_to_string(NOTIFY_ALL | NOTIFY_KEYSPACE), "AK");
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+use crate::parser::frame::Frame;
+use crate::server::clients::ClientInfo;
+use crate::util::string_match;
+
+pub type MessageReceiver = mpsc::UnboundedReceiver<Frame>;
+
+/// Where a subscribed connection receives its messages. Queued messages count in the
+/// output buffer of the client until its handler writes them.
+#[derive(Debug, Clone)]
+pub struct MessageSender {
+    sender: mpsc::UnboundedSender<Frame>,
+    client: Arc<ClientInfo>,
+}
+
+impl MessageSender {
+    /// Queues `frame`, false if the client is gone or went over its output buffer limit.
+    pub fn send(&self, frame: Frame) -> bool {
+        if !self.client.add_output(frame.encoded_len()) {
+            return false;
+        }
+        self.sender.send(frame).is_ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubscriptionKind {
+    /// SUBSCRIBE / PUBLISH
+    Channel,
+    /// PSUBSCRIBE, matched against every PUBLISH
+    Pattern,
+    /// SSUBSCRIBE / SPUBLISH
+    Shard,
+}
+
+impl SubscriptionKind {
+    pub fn subscribe_msg(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Channel => "subscribe",
+            SubscriptionKind::Pattern => "psubscribe",
+            SubscriptionKind::Shard => "ssubscribe",
+        }
+    }
+
+    pub fn unsubscribe_msg(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Channel => "unsubscribe",
+            SubscriptionKind::Pattern => "punsubscribe",
+            SubscriptionKind::Shard => "sunsubscribe",
+        }
+    }
+}
+
+/// Server-wide pub/sub registry. Channels are not scoped to a DB, so this lives next
+/// to the DB tasks rather than inside them: subscribers are keyed by client id and
+/// receive messages through their own unbounded queue.
+#[derive(Debug, Default)]
+pub struct PubSub {
+    channels: HashMap<String, HashMap<u64, MessageSender>>,
+    patterns: HashMap<String, HashMap<u64, MessageSender>>,
+    shard_channels: HashMap<String, HashMap<u64, MessageSender>>,
+}
+
+impl PubSub {
+    fn table(&mut self, kind: SubscriptionKind) -> &mut HashMap<String, HashMap<u64, MessageSender>> {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    /// Returns false if the client was already subscribed
+    pub fn subscribe(&mut self, kind: SubscriptionKind, name: &str, id: u64, sender: &MessageSender) -> bool {
+        self.table(kind)
+            .entry(name.to_string())
+            .or_default()
+            .insert(id, sender.clone())
+            .is_none()
+    }
+
+    /// Returns false if the client was not subscribed
+    pub fn unsubscribe(&mut self, kind: SubscriptionKind, name: &str, id: u64) -> bool {
+        let table = self.table(kind);
+        match table.get_mut(name) {
+            Some(clients) => {
+                let removed = clients.remove(&id).is_some();
+                if clients.is_empty() {
+                    table.remove(name);
+                }
+                removed
+            }
+            None => false,
+        }
+    }
+
+    /// Delivers `message` to the subscribers of `channel` and of every matching pattern,
+    /// returning the number of clients that received it.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut receivers = 0;
+        if let Some(clients) = self.channels.get(channel) {
+            let frame = message_frame(&["message", channel, message]);
+            for sender in clients.values() {
+                if sender.send(frame.clone()) {
+                    receivers += 1;
+                }
+            }
+        }
+        for (pattern, clients) in &self.patterns {
+            if !string_match(pattern, channel, false) {
+                continue;
+            }
+            let frame = message_frame(&["pmessage", pattern, channel, message]);
+            for sender in clients.values() {
+                if sender.send(frame.clone()) {
+                    receivers += 1;
+                }
+            }
+        }
+        receivers
+    }
+
+    /// SPUBLISH: shard channels are never matched against patterns
+    pub fn publish_shard(&self, channel: &str, message: &str) -> usize {
+        let mut receivers = 0;
+        if let Some(clients) = self.shard_channels.get(channel) {
+            let frame = message_frame(&["smessage", channel, message]);
+            for sender in clients.values() {
+                if sender.send(frame.clone()) {
+                    receivers += 1;
+                }
+            }
+        }
+        receivers
+    }
+
+    /// Active channels (with at least one subscriber), optionally filtered by a glob pattern
+    pub fn channels(&self, kind: SubscriptionKind, pattern: Option<&str>) -> Vec<String> {
+        let table = match kind {
+            SubscriptionKind::Shard => &self.shard_channels,
+            _ => &self.channels,
+        };
+        table
+            .keys()
+            .filter(|channel| pattern.map_or(true, |p| string_match(p, channel, false)))
+            .cloned()
+            .collect()
+    }
+
+    pub fn num_sub(&self, kind: SubscriptionKind, channel: &str) -> usize {
+        let table = match kind {
+            SubscriptionKind::Shard => &self.shard_channels,
+            _ => &self.channels,
+        };
+        table.get(channel).map_or(0, |clients| clients.len())
+    }
+
+    /// Number of unique patterns subscribed to
+    pub fn num_pat(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+fn message_frame(parts: &[&str]) -> Frame {
+    Frame::Array(
+        parts
+            .iter()
+            .map(|part| Frame::Bulk(Bytes::from(part.to_string().into_bytes())))
+            .collect(),
+    )
+}
+
+/// The subscriptions of one connection. A connection with at least one subscription
+/// is in subscriber mode.
+#[derive(Debug)]
+pub struct Subscriptions {
+    pub channels: HashSet<String>,
+    pub patterns: HashSet<String>,
+    pub shard_channels: HashSet<String>,
+    pub sender: MessageSender,
+    pub receiver: MessageReceiver,
+}
+
+impl Subscriptions {
+    pub fn new(client: Arc<ClientInfo>) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            shard_channels: HashSet::new(),
+            sender: MessageSender { sender, client },
+            receiver,
+        }
+    }
+
+    pub fn get_mut(&mut self, kind: SubscriptionKind) -> &mut HashSet<String> {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    /// The count reported in (un)subscribe replies. Shard subscriptions are counted
+    /// separately from channels and patterns, as in Redis.
+    pub fn count(&self, kind: SubscriptionKind) -> usize {
+        match kind {
+            SubscriptionKind::Shard => self.shard_channels.len(),
+            _ => self.channels.len() + self.patterns.len(),
+        }
+    }
+
+    pub fn is_subscriber(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty() || !self.shard_channels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn publish_to_channels_and_patterns() {
+        let mut pubsub = PubSub::default();
+        let mut s1 = Subscriptions::new(Arc::new(ClientInfo::new(1, String::new(), String::new())));
+        let mut s2 = Subscriptions::new(Arc::new(ClientInfo::new(2, String::new(), String::new())));
+        assert!(pubsub.subscribe(SubscriptionKind::Channel, "news.sport", 1, &s1.sender));
+        assert!(!pubsub.subscribe(SubscriptionKind::Channel, "news.sport", 1, &s1.sender));
+        assert!(pubsub.subscribe(SubscriptionKind::Pattern, "news.*", 2, &s2.sender));
+
+        assert_eq!(pubsub.publish("news.sport", "goal"), 2);
+        assert_eq!(pubsub.publish("news.tech", "rust"), 1);
+        assert_eq!(pubsub.publish("weather", "rain"), 0);
+        assert_eq!(pubsub.publish_shard("news.sport", "goal"), 0);
+
+        assert!(s1.receiver.try_recv().is_ok());
+        assert!(s1.receiver.try_recv().is_err());
+        assert!(s2.receiver.try_recv().is_ok());
+        assert!(s2.receiver.try_recv().is_ok());
+
+        assert_eq!(pubsub.channels(SubscriptionKind::Channel, Some("news.*")), vec!["news.sport".to_string()]);
+        assert_eq!(pubsub.num_sub(SubscriptionKind::Channel, "news.sport"), 1);
+        assert_eq!(pubsub.num_pat(), 1);
+
+        assert!(pubsub.unsubscribe(SubscriptionKind::Channel, "news.sport", 1));
+        assert!(!pubsub.unsubscribe(SubscriptionKind::Channel, "news.sport", 1));
+        assert!(pubsub.channels(SubscriptionKind::Channel, None).is_empty());
+    }
+}
@@ -0,0 +1,897 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::task::AbortHandle;
+use tokio::time;
+use tracing::{error, info};
+
+use crate::config::ServerConfig;
+use crate::parser::cmd::command::{get_command_name, CommandStrategy, RedisCommand};
+use crate::parser::frame::Frame;
+use crate::server::clients::{ClientInfo, ClientType};
+use crate::server::connection::{Connection, Stream};
+use crate::server::server::next_client_id;
+use crate::server::slowlog::CallContext;
+use crate::server::{REDIS_CONFIG, REDIS_SERVER};
+use crate::util::yesnotoi;
+use crate::DEFAULT_PORT;
+
+/// Whether clients of a replica are refused writes
+static REPLICA_READ_ONLY: AtomicBool = AtomicBool::new(true);
+/// User and password a replica authenticates to its master with
+static MASTER_AUTH: Mutex<(Option<String>, String)> = Mutex::new((None, String::new()));
+static REPL_BACKLOG_SIZE: AtomicU64 = AtomicU64::new(1024 * 1024);
+static REPLICATION: Mutex<Replication> = Mutex::new(Replication {
+    replid: String::new(),
+    replid2: String::new(),
+    second_replid_offset: None,
+    master_repl_offset: 0,
+    selected_db: None,
+    backlog: None,
+    master: None,
+    replicas: Vec::new(),
+});
+/// Woken whenever a replica acknowledges an offset, for WAIT
+static ACK_NOTIFY: Notify = Notify::const_new();
+
+/// Time a replica waits before connecting to its master again
+const REPL_RETRY_PERIOD: Duration = Duration::from_secs(1);
+/// How often a replica reports its offset with REPLCONF ACK
+const REPL_ACK_PERIOD: Duration = Duration::from_secs(1);
+/// How often a master with replicas sends them a PING, so they can tell a silent
+/// master from a dead link
+const REPL_PING_PERIOD: Duration = Duration::from_secs(10);
+/// A replica drops a link its master did not send anything on for this long
+const REPL_TIMEOUT: Duration = Duration::from_secs(60);
+/// Smallest backlog `repl-backlog-size` may set
+const REPL_BACKLOG_MIN_SIZE: u64 = 16 * 1024;
+/// Key of the RDB aux field holding the DB the stream is in when the RDB is taken
+const REPL_STREAM_DB: &str = "repl-stream-db";
+
+/// Progress of the link of a replica with its master
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// Connecting, or waiting to connect again after the link broke
+    Connect,
+    /// Handshake done, the RDB of the full resync is transferred and loaded
+    Transfer,
+    /// Applying the stream of writes of the master
+    Connected,
+}
+
+impl LinkState {
+    /// Name of the state as reported by ROLE
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkState::Connect => "connect",
+            LinkState::Transfer => "sync",
+            LinkState::Connected => "connected",
+        }
+    }
+}
+
+/// The master of a replica and the task following it
+struct MasterLink {
+    host: String,
+    port: u16,
+    state: LinkState,
+    /// When the master last sent something
+    last_io: Instant,
+    /// When the link went down, for a replica not connected
+    down_since: Instant,
+    task: AbortHandle,
+}
+
+/// A replica fed with the stream of writes
+struct ReplicaLink {
+    client: Arc<ClientInfo>,
+    /// Port the replica serves clients on, sent with REPLCONF listening-port
+    listening_port: u16,
+    /// Set once the RDB of its full resync, or the backlog of a partial one, was
+    /// written to it
+    online: bool,
+    /// Offset of the stream the replica last acknowledged with REPLCONF ACK
+    ack_offset: u64,
+    last_ack: Instant,
+    sender: mpsc::UnboundedSender<Bytes>,
+}
+
+/// The last `repl-backlog-size` bytes of the stream in a circular buffer. A replica
+/// that reconnects resumes from it (PSYNC) as long as the bytes it missed are still held.
+struct Backlog {
+    buf: Vec<u8>,
+    /// Where the next byte goes
+    idx: usize,
+    /// Bytes of the stream held, up to the size of `buf`
+    histlen: usize,
+}
+
+impl Backlog {
+    fn new(size: usize) -> Self {
+        Backlog { buf: vec![0; size], idx: 0, histlen: 0 }
+    }
+
+    fn feed(&mut self, mut bytes: &[u8]) {
+        let size = self.buf.len();
+        if bytes.len() > size {
+            bytes = &bytes[bytes.len() - size..];
+        }
+        let first = bytes.len().min(size - self.idx);
+        self.buf[self.idx..self.idx + first].copy_from_slice(&bytes[..first]);
+        self.buf[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+        self.idx = (self.idx + bytes.len()) % size;
+        self.histlen = (self.histlen + bytes.len()).min(size);
+    }
+
+    /// The last `len` bytes fed, None if fewer are held
+    fn tail(&self, len: usize) -> Option<Vec<u8>> {
+        if len > self.histlen {
+            return None;
+        }
+        let size = self.buf.len();
+        let start = (self.idx + size - len) % size;
+        let mut bytes = Vec::with_capacity(len);
+        if start + len <= size {
+            bytes.extend_from_slice(&self.buf[start..start + len]);
+        } else {
+            bytes.extend_from_slice(&self.buf[start..]);
+            bytes.extend_from_slice(&self.buf[..len - (size - start)]);
+        }
+        Some(bytes)
+    }
+
+    /// A backlog of `size` bytes holding as much of this one as fits
+    fn resize(&self, size: usize) -> Backlog {
+        let mut backlog = Backlog::new(size);
+        backlog.feed(&self.tail(self.histlen.min(size)).unwrap_or_default());
+        backlog
+    }
+}
+
+struct Replication {
+    /// ID of the history of writes of this server, the one of its master on a replica
+    replid: String,
+    /// ID of the history followed before the last promotion or change of master, so
+    /// replicas of the previous master can go on with a partial resync
+    replid2: String,
+    /// First offset that is not part of the `replid2` history
+    second_replid_offset: Option<u64>,
+    /// Bytes of stream fed to the replicas so far
+    master_repl_offset: u64,
+    /// DB of the last write fed, a SELECT goes first when the next one is in another
+    selected_db: Option<i32>,
+    /// Created along with the first replica, then kept so replicas can reconnect
+    backlog: Option<Backlog>,
+    /// Set on a replica
+    master: Option<MasterLink>,
+    replicas: Vec<ReplicaLink>,
+}
+
+impl Replication {
+    /// Whether writes make it into the stream. A replica passes on the stream of its
+    /// master even with no replica of its own, so its offset follows the master.
+    fn is_feeding(&self) -> bool {
+        !self.replicas.is_empty() || self.master.is_some() || self.backlog.is_some()
+    }
+
+    fn feed(&mut self, bytes: Bytes) {
+        self.master_repl_offset += bytes.len() as u64;
+        let backlog_size = repl_backlog_size() as usize;
+        self.backlog.get_or_insert_with(|| Backlog::new(backlog_size)).feed(&bytes);
+        // a replica over its output buffer limit is closed
+        self.replicas.retain(|replica| replica.client.add_output(bytes.len()) && replica.sender.send(bytes.clone()).is_ok());
+    }
+
+    /// Closes the connections of the replicas so they resync with the history this
+    /// server follows now.
+    fn disconnect_replicas(&mut self) {
+        for replica in self.replicas.drain(..) {
+            replica.client.kill();
+        }
+    }
+
+    /// Starts a new history from the current offset, keeping the previous one as
+    /// `replid2` (a replica promoted, or one whose master changed its history).
+    fn shift_replid(&mut self, replid: String) {
+        self.replid2 = std::mem::replace(&mut self.replid, replid);
+        self.second_replid_offset = Some(self.master_repl_offset + 1);
+    }
+
+    /// Forgets the history of this server, whose data is about to be replaced by a
+    /// full resync.
+    fn clear_history(&mut self) {
+        self.replid = new_replid();
+        self.replid2.clear();
+        self.second_replid_offset = None;
+        self.master_repl_offset = 0;
+        self.backlog = None;
+        self.disconnect_replicas();
+    }
+
+    fn set_link_state(&mut self, state: LinkState) {
+        if let Some(master) = &mut self.master {
+            if state != LinkState::Connected && master.state == LinkState::Connected {
+                master.down_since = Instant::now();
+            }
+            master.state = state;
+            master.last_io = Instant::now();
+        }
+    }
+
+    fn replicas_acked(&self, offset: u64) -> usize {
+        self.replicas.iter().filter(|replica| replica.online && replica.ack_offset >= offset).count()
+    }
+}
+
+fn new_replid() -> String {
+    (0..40).map(|_| char::from_digit(rand::random_range(0..16), 16).unwrap()).collect()
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
+
+/// Parses `replicaof` as set in the config file, "<host> <port>".
+pub fn parse_replicaof(value: &str) -> Option<(String, u16)> {
+    let mut parts = value.split_whitespace();
+    let host = parts.next()?.to_string();
+    let port = parts.next()?.parse().ok()?;
+    parts.next().is_none().then_some((host, port))
+}
+
+/// Applies the replication settings and, with `replicaof` set, starts following the master.
+pub fn load_replication_config(config: &ServerConfig) -> crate::Result<()> {
+    REPLICATION.lock().unwrap().replid = new_replid();
+    let read_only = yesnotoi(&config.replica_read_only)
+        .ok_or_else(|| format!("invalid replica-read-only '{}'", config.replica_read_only))?;
+    set_replica_read_only(read_only);
+    set_masteruser(config.masteruser.clone());
+    set_masterauth(config.masterauth.clone());
+    set_repl_backlog_size(config.repl_backlog_size);
+    tokio::spawn(replication_cron());
+    if let Some(replicaof) = &config.replicaof {
+        let (host, port) = parse_replicaof(replicaof).ok_or_else(|| format!("invalid replicaof '{}'", replicaof))?;
+        replicaof_master(host, port);
+    }
+    Ok(())
+}
+
+pub fn replica_read_only() -> bool {
+    REPLICA_READ_ONLY.load(Ordering::Relaxed)
+}
+
+pub fn set_replica_read_only(read_only: bool) {
+    REPLICA_READ_ONLY.store(read_only, Ordering::Relaxed);
+}
+
+pub fn masteruser() -> String {
+    MASTER_AUTH.lock().unwrap().0.clone().unwrap_or_default()
+}
+
+/// An empty user authenticates as the default one
+pub fn set_masteruser(user: String) {
+    MASTER_AUTH.lock().unwrap().0 = if user.is_empty() { None } else { Some(user) };
+}
+
+pub fn masterauth() -> String {
+    MASTER_AUTH.lock().unwrap().1.clone()
+}
+
+pub fn set_masterauth(password: String) {
+    MASTER_AUTH.lock().unwrap().1 = password;
+}
+
+pub fn repl_backlog_size() -> u64 {
+    REPL_BACKLOG_SIZE.load(Ordering::Relaxed)
+}
+
+/// Resizes the backlog, keeping the most recent part of the stream that fits.
+pub fn set_repl_backlog_size(size: u64) {
+    let size = size.max(REPL_BACKLOG_MIN_SIZE);
+    REPL_BACKLOG_SIZE.store(size, Ordering::Relaxed);
+    let mut repl = REPLICATION.lock().unwrap();
+    if let Some(backlog) = &mut repl.backlog {
+        if backlog.buf.len() != size as usize {
+            *backlog = backlog.resize(size as usize);
+        }
+    }
+}
+
+/// Whether this server follows a master
+pub fn is_replica() -> bool {
+    REPLICATION.lock().unwrap().master.is_some()
+}
+
+/// The master followed as "<host> <port>", empty on a primary
+pub fn replicaof_string() -> String {
+    REPLICATION.lock().unwrap().master.as_ref().map(|master| format!("{} {}", master.host, master.port)).unwrap_or_default()
+}
+
+/// Makes this server a replica of `host:port` (REPLICAOF). Returns false if it
+/// already follows that master. Its data and history are kept, so the new master
+/// may continue from them if it shares them (a replica of the same master promoted).
+pub fn replicaof_master(host: String, port: u16) -> bool {
+    let mut repl = REPLICATION.lock().unwrap();
+    if repl.master.as_ref().is_some_and(|master| master.host == host && master.port == port) {
+        return false;
+    }
+    if let Some(master) = repl.master.take() {
+        master.task.abort();
+    }
+    repl.disconnect_replicas();
+    info!("connecting to MASTER {}:{}", host, port);
+    let task = tokio::spawn(run_replication(host.clone(), port)).abort_handle();
+    let now = Instant::now();
+    repl.master = Some(MasterLink { host, port, state: LinkState::Connect, last_io: now, down_since: now, task });
+    true
+}
+
+/// Stops following the master and serves writes again (REPLICAOF NO ONE). The data
+/// and the backlog are kept and the history of writes goes on from this server under
+/// a new ID, the former one becoming `replid2`: the other replicas of the former
+/// master can continue from this one with a partial resync.
+pub fn replicaof_no_one() {
+    let mut repl = REPLICATION.lock().unwrap();
+    if let Some(master) = repl.master.take() {
+        master.task.abort();
+        repl.shift_replid(new_replid());
+        repl.disconnect_replicas();
+        info!("MASTER MODE enabled");
+    }
+}
+
+/// Feeds a write applied by DB `db_id` to the replicas, after a SELECT when the
+/// previous write was in another DB. A replica passes on the stream of its master as
+/// is, its own writes (replica-read-only no, expired keys) stay local.
+pub fn propagate(db_id: i32, argv: &Frame) {
+    let mut repl = REPLICATION.lock().unwrap();
+    if !repl.is_feeding() || repl.master.is_some() {
+        return;
+    }
+    let mut bytes = vec![];
+    if repl.selected_db != Some(db_id) {
+        let select = Frame::Array(vec![bulk("SELECT"), bulk(&db_id.to_string())]);
+        Connection::write_value(&select, &mut bytes);
+        repl.selected_db = Some(db_id);
+    }
+    Connection::write_value(argv, &mut bytes);
+    repl.feed(Bytes::from(bytes));
+}
+
+/// Feeds the deletion of `key`, expired or evicted, so the replicas drop it at the
+/// same point of the stream.
+pub fn propagate_del(db_id: i32, key: &str) {
+    propagate(db_id, &Frame::Array(vec![bulk("DEL"), bulk(key)]));
+}
+
+/// Feeds a command of no DB, such as PING or REPLCONF GETACK. Only a master with
+/// replicas or a backlog sends them.
+fn feed_command(args: &[&str]) {
+    let mut repl = REPLICATION.lock().unwrap();
+    if repl.master.is_some() || (repl.replicas.is_empty() && repl.backlog.is_none()) {
+        return;
+    }
+    let mut bytes = vec![];
+    Connection::write_value(&Frame::Array(args.iter().map(|arg| bulk(arg)).collect()), &mut bytes);
+    repl.feed(Bytes::from(bytes));
+}
+
+/// Pings the replicas every `REPL_PING_PERIOD`.
+async fn replication_cron() {
+    let mut interval = time::interval(REPL_PING_PERIOD);
+    loop {
+        interval.tick().await;
+        if !REPLICATION.lock().unwrap().replicas.is_empty() {
+            feed_command(&["PING"]);
+        }
+    }
+}
+
+/// XADD with the ID the primary generated in place of `*` or `<ms>-*`, so the replicas
+/// store the entry under the same ID.
+pub fn xadd_with_id(argv: &Frame, id: &Bytes) -> Frame {
+    let mut argv = argv.clone();
+    if let Frame::Array(args) = &mut argv {
+        // the options after the key never end with "*", the ID is the first argument that does
+        if let Some(arg) = args.iter_mut().skip(2).find(|arg| matches!(arg, Frame::Bulk(b) if b.ends_with(b"*"))) {
+            *arg = Frame::Bulk(id.clone());
+        }
+    }
+    argv
+}
+
+/// `argv` with its relative TTL replaced by the absolute one the key got, so the
+/// replicas, and a replay of the backlog later on, expire the key at the same moment
+/// as the primary: EXPIRE and PEXPIRE become PEXPIREAT, or DEL when the TTL was
+/// already elapsed, and SETEX, SETPX and SET EX|PX become SET PXAT. `expire_of`
+/// returns the unix time in milliseconds at which a key expires. None when `argv`
+/// has no relative TTL.
+pub fn with_absolute_ttl(argv: &Frame, expire_of: impl FnOnce(&str) -> Option<i64>) -> Option<Frame> {
+    let Frame::Array(args) = argv else { return None };
+    let arg = |i: usize| args.get(i).map(|arg| arg.to_string()).unwrap_or_default();
+    let name = arg(0).to_lowercase();
+    let value = match name.as_str() {
+        "expire" | "pexpire" => None,
+        "setex" | "setpx" => Some(arg(3)),
+        "set" if args.len() == 5 && matches!(&arg(3).to_lowercase()[..], "ex" | "px") => Some(arg(2)),
+        _ => return None,
+    };
+    let key = arg(1);
+    match (expire_of(&key), value) {
+        (Some(when), None) => Some(Frame::Array(vec![bulk("PEXPIREAT"), bulk(&key), bulk(&when.to_string())])),
+        (None, None) => Some(Frame::Array(vec![bulk("DEL"), bulk(&key)])),
+        (Some(when), Some(value)) => {
+            Some(Frame::Array(vec![bulk("SET"), bulk(&key), bulk(&value), bulk("PXAT"), bulk(&when.to_string())]))
+        }
+        (None, Some(_)) => None,
+    }
+}
+
+/// Refuses a full resync the server can't serve, such as one asked from a replica
+/// that is not connected to its own master.
+pub fn check_can_sync() -> crate::Result<()> {
+    match &REPLICATION.lock().unwrap().master {
+        Some(master) if master.state != LinkState::Connected => {
+            Err("NOMASTERLINK Can't SYNC while not connected with my master".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Where the stream fed to a replica starts: the replication ID, the offset and the
+/// DB the stream is in.
+pub struct SyncPoint {
+    pub replid: String,
+    pub offset: u64,
+    pub stream_db: Option<i32>,
+    /// The stream of writes from `offset` on
+    pub receiver: mpsc::UnboundedReceiver<Bytes>,
+}
+
+fn register_replica(repl: &mut Replication, client: Arc<ClientInfo>, listening_port: u16) -> SyncPoint {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let offset = repl.master_repl_offset;
+    repl.replicas.push(ReplicaLink { client, listening_port, online: false, ack_offset: 0, last_ack: Instant::now(), sender });
+    SyncPoint { replid: repl.replid.clone(), offset, stream_db: repl.selected_db, receiver }
+}
+
+/// Starts feeding `client` the stream for a full resync. Called while every DB is
+/// frozen on its snapshot, so the snapshot and the stream meet at the returned offset.
+pub fn add_replica(client: Arc<ClientInfo>, listening_port: u16) -> SyncPoint {
+    let mut repl = REPLICATION.lock().unwrap();
+    if repl.backlog.is_none() {
+        repl.backlog = Some(Backlog::new(repl_backlog_size() as usize));
+    }
+    register_replica(&mut repl, client, listening_port)
+}
+
+/// Serves PSYNC `replid` `offset` from the backlog when it still holds the stream from
+/// `offset` on, in the history `replid` or the one before the last change of ID.
+/// Returns where the stream goes on and the part of it the replica missed.
+pub fn try_partial_resync(client: Arc<ClientInfo>, listening_port: u16, replid: &str, offset: i64) -> Option<(SyncPoint, Vec<u8>)> {
+    let mut repl = REPLICATION.lock().unwrap();
+    let known = replid == repl.replid
+        || (replid == repl.replid2 && repl.second_replid_offset.is_some_and(|second| offset as u64 <= second));
+    // offsets of PSYNC are those of the next byte wanted, the first one being 1
+    if !known || offset < 1 || offset as u64 > repl.master_repl_offset + 1 {
+        return None;
+    }
+    let missing = (repl.master_repl_offset + 1 - offset as u64) as usize;
+    let backlog = repl.backlog.as_ref()?.tail(missing)?;
+    Some((register_replica(&mut repl, client, listening_port), backlog))
+}
+
+/// Marks the replica `client_id` as done with its resync.
+pub fn set_replica_online(client_id: u64) {
+    let mut repl = REPLICATION.lock().unwrap();
+    if let Some(replica) = repl.replicas.iter_mut().find(|replica| replica.client.id == client_id) {
+        replica.online = true;
+    }
+}
+
+pub fn remove_replica(client_id: u64) {
+    REPLICATION.lock().unwrap().replicas.retain(|replica| replica.client.id != client_id);
+}
+
+/// Records the offset replica `client_id` reports with REPLCONF ACK.
+pub fn replica_ack(client_id: u64, offset: u64) {
+    let mut repl = REPLICATION.lock().unwrap();
+    if let Some(replica) = repl.replicas.iter_mut().find(|replica| replica.client.id == client_id) {
+        replica.ack_offset = replica.ack_offset.max(offset);
+        replica.last_ack = Instant::now();
+    }
+    ACK_NOTIFY.notify_waiters();
+}
+
+/// The offset in `REPLCONF ACK <offset>`, as sent by a replica on its link
+pub fn parse_replconf_ack(frame: &Frame) -> Option<u64> {
+    let arg = |idx| frame.get_frame_by_index(idx).map(|arg| arg.to_string());
+    match (arg(0), arg(1)) {
+        (Some(name), Some(option)) if name.eq_ignore_ascii_case("replconf") && option.eq_ignore_ascii_case("ack") => {
+            arg(2)?.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// Replicas still syncing or that did not acknowledge the whole stream yet, waited
+/// on by a shutdown
+pub fn lagging_replicas() -> usize {
+    let repl = REPLICATION.lock().unwrap();
+    repl.replicas.iter().filter(|replica| !replica.online || replica.ack_offset < repl.master_repl_offset).count()
+}
+
+pub fn master_repl_offset() -> u64 {
+    REPLICATION.lock().unwrap().master_repl_offset
+}
+
+/// Waits until `numreplicas` replicas acknowledged the stream up to `offset`, or until
+/// `timeout` elapsed (WAIT). Returns how many did.
+pub async fn wait_for_replicas(numreplicas: usize, offset: u64, timeout: Option<Duration>) -> usize {
+    let deadline = timeout.map(|timeout| time::Instant::now() + timeout);
+    let mut acks_requested = false;
+    loop {
+        // created before the check so an ACK in between still wakes it
+        let notified = ACK_NOTIFY.notified();
+        let acked = REPLICATION.lock().unwrap().replicas_acked(offset);
+        if acked >= numreplicas {
+            return acked;
+        }
+        if !acks_requested {
+            feed_command(&["REPLCONF", "GETACK", "*"]);
+            acks_requested = true;
+        }
+        tokio::select! {
+            _ = notified => {}
+            _ = time::sleep_until(deadline.unwrap_or_else(time::Instant::now)), if deadline.is_some() => {
+                return REPLICATION.lock().unwrap().replicas_acked(offset);
+            }
+        }
+    }
+}
+
+/// The aux field of an RDB telling a replica which DB the stream is in
+pub fn stream_db_aux(stream_db: Option<i32>) -> Option<(&'static str, String)> {
+    stream_db.map(|db| (REPL_STREAM_DB, db.to_string()))
+}
+
+/// A replica fed by this server, as reported by INFO replication and ROLE
+pub struct ReplicaInfo {
+    pub ip: String,
+    pub port: u16,
+    pub online: bool,
+    pub ack_offset: u64,
+    /// Seconds since the last REPLCONF ACK
+    pub lag: u64,
+}
+
+/// The master of a replica, as reported by INFO replication and ROLE
+pub struct MasterInfo {
+    pub host: String,
+    pub port: u16,
+    pub state: LinkState,
+    pub last_io_secs: u64,
+    pub down_since_secs: u64,
+}
+
+pub struct ReplicationInfo {
+    pub replid: String,
+    pub replid2: String,
+    pub second_replid_offset: Option<u64>,
+    pub master_repl_offset: u64,
+    /// Size of the backlog, offset of its first byte and bytes held, if there is one
+    pub backlog: Option<(usize, u64, usize)>,
+    pub master: Option<MasterInfo>,
+    pub replicas: Vec<ReplicaInfo>,
+}
+
+pub fn replication_info() -> ReplicationInfo {
+    let repl = REPLICATION.lock().unwrap();
+    ReplicationInfo {
+        replid: repl.replid.clone(),
+        replid2: repl.replid2.clone(),
+        second_replid_offset: repl.second_replid_offset,
+        master_repl_offset: repl.master_repl_offset,
+        backlog: repl
+            .backlog
+            .as_ref()
+            .map(|backlog| (backlog.buf.len(), repl.master_repl_offset + 1 - backlog.histlen as u64, backlog.histlen)),
+        master: repl.master.as_ref().map(|master| MasterInfo {
+            host: master.host.clone(),
+            port: master.port,
+            state: master.state,
+            last_io_secs: master.last_io.elapsed().as_secs(),
+            down_since_secs: master.down_since.elapsed().as_secs(),
+        }),
+        replicas: repl
+            .replicas
+            .iter()
+            .map(|replica| ReplicaInfo {
+                ip: replica.client.addr.rsplit_once(':').map_or(replica.client.addr.clone(), |(ip, _)| ip.to_string()),
+                port: replica.listening_port,
+                online: replica.online,
+                ack_offset: replica.ack_offset,
+                lag: replica.last_ack.elapsed().as_secs(),
+            })
+            .collect(),
+    }
+}
+
+fn set_link_state(state: LinkState) {
+    REPLICATION.lock().unwrap().set_link_state(state);
+}
+
+/// Follows the master until the task is aborted, connecting again whenever the link breaks.
+async fn run_replication(host: String, port: u16) {
+    loop {
+        if let Err(err) = sync_with_master(&host, port).await {
+            error!(cause = %err, "replication with MASTER {}:{} failed", host, port);
+        }
+        set_link_state(LinkState::Connect);
+        time::sleep(REPL_RETRY_PERIOD).await;
+    }
+}
+
+/// Unregisters the client of the link to the master once the link is gone.
+struct MasterClient(Arc<ClientInfo>);
+
+impl Drop for MasterClient {
+    fn drop(&mut self) {
+        if let Some(server) = unsafe { REDIS_SERVER.get() } {
+            server.clients.unregister(self.0.id);
+        }
+    }
+}
+
+async fn send_command(conn: &mut Connection, args: &[&str]) -> crate::Result<Frame> {
+    conn.write_frame(&Frame::Array(args.iter().map(|arg| bulk(arg)).collect())).await?;
+    conn.read_frame().await?.ok_or_else(|| "connection closed by MASTER".into())
+}
+
+fn expect_ok(reply: Frame, step: &str) -> crate::Result<()> {
+    match reply {
+        Frame::Simple(_) => Ok(()),
+        reply => Err(format!("MASTER replied to {} with {}", step, reply).into()),
+    }
+}
+
+/// How the master answered PSYNC
+enum Resync {
+    /// An RDB follows, then the stream from `offset` of history `replid`
+    Full { replid: String, offset: u64 },
+    /// The stream goes on from the offset of this server, under a new ID if the
+    /// master changed its history
+    Partial { replid: Option<String> },
+}
+
+/// Handshake with the master: PING, AUTH if `masterauth` is set, REPLCONF and PSYNC
+/// asking to continue the history of this server.
+async fn handshake(conn: &mut Connection) -> crate::Result<Resync> {
+    match send_command(conn, &["PING"]).await? {
+        // a master with requirepass refuses the PING until AUTH
+        Frame::Error(err) if !err.starts_with("NOAUTH") => return Err(format!("MASTER replied to PING with {}", err).into()),
+        _ => {}
+    }
+    let password = masterauth();
+    if !password.is_empty() {
+        let user = masteruser();
+        let args = if user.is_empty() { vec!["AUTH", &password] } else { vec!["AUTH", &user, &password] };
+        expect_ok(send_command(conn, &args).await?, "AUTH")?;
+    }
+    let port = REDIS_CONFIG.get().map_or(DEFAULT_PORT, |config| config.port).to_string();
+    expect_ok(send_command(conn, &["REPLCONF", "listening-port", &port]).await?, "REPLCONF")?;
+    expect_ok(send_command(conn, &["REPLCONF", "capa", "eof", "capa", "psync2"]).await?, "REPLCONF")?;
+    let (replid, offset) = {
+        let repl = REPLICATION.lock().unwrap();
+        (repl.replid.clone(), (repl.master_repl_offset + 1).to_string())
+    };
+    let reply = send_command(conn, &["PSYNC", &replid, &offset]).await?;
+    let line = match &reply {
+        Frame::Simple(line) => line.clone(),
+        reply => return Err(format!("MASTER replied to PSYNC with {}", reply).into()),
+    };
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    match parts[..] {
+        ["FULLRESYNC", replid, offset] => Ok(Resync::Full { replid: replid.to_string(), offset: offset.parse()? }),
+        ["CONTINUE"] => Ok(Resync::Partial { replid: None }),
+        ["CONTINUE", replid] => Ok(Resync::Partial { replid: Some(replid.to_string()) }),
+        _ => Err(format!("unexpected reply to PSYNC '{}'", line).into()),
+    }
+}
+
+/// Replaces the data of this server with the RDB the master sends for a full resync.
+/// Returns the DB the stream is in.
+async fn load_master_rdb(conn: &mut Connection, replid: String, offset: u64) -> crate::Result<Option<i32>> {
+    {
+        let mut repl = REPLICATION.lock().unwrap();
+        // until the RDB is loaded the data matches no history to continue from
+        repl.clear_history();
+        repl.set_link_state(LinkState::Transfer);
+    }
+    let payload = conn.read_rdb_payload().await?;
+    let server = unsafe { REDIS_SERVER.get() }.ok_or("server not started")?;
+    server.db_handler.empty().await?;
+    let rdb_handler = server.rdb_handler.clone();
+    let aux = tokio::task::spawn_blocking(move || rdb_handler.load_payload(payload)).await??;
+    // the keys of the RDB reach the DBs ahead of the stream
+    server.db_handler.drain().await?;
+    let stream_db = aux.iter().find(|(key, _)| key == REPL_STREAM_DB).and_then(|(_, db)| db.parse::<i32>().ok());
+    let mut repl = REPLICATION.lock().unwrap();
+    repl.replid = replid;
+    repl.master_repl_offset = offset;
+    repl.selected_db = stream_db;
+    repl.backlog = Some(Backlog::new(repl_backlog_size() as usize));
+    Ok(stream_db)
+}
+
+async fn send_ack(conn: &mut Connection) -> crate::Result<()> {
+    let offset = master_repl_offset().to_string();
+    conn.write_frame(&Frame::Array(vec![bulk("REPLCONF"), bulk("ACK"), bulk(&offset)])).await?;
+    Ok(())
+}
+
+/// Connects to the master, resyncs with it and applies its stream of writes until
+/// the link breaks.
+async fn sync_with_master(host: &str, port: u16) -> crate::Result<()> {
+    let socket = TcpStream::connect((host, port)).await?;
+    let (addr, laddr) = (socket.peer_addr()?.to_string(), socket.local_addr()?.to_string());
+    let mut conn = Connection::<Stream>::new(socket);
+    match handshake(&mut conn).await? {
+        Resync::Full { replid, offset } => {
+            info!("full resync from MASTER {}:{}, replid {} offset {}", host, port, replid, offset);
+            load_master_rdb(&mut conn, replid, offset).await?;
+            info!("MASTER <-> REPLICA sync: finished with success");
+        }
+        Resync::Partial { replid } => {
+            let mut repl = REPLICATION.lock().unwrap();
+            if let Some(replid) = replid.filter(|replid| *replid != repl.replid) {
+                // the master was promoted, its replicas follow its new history
+                repl.shift_replid(replid);
+                repl.disconnect_replicas();
+            }
+            info!("partial resync with MASTER {}:{} from offset {}", host, port, repl.master_repl_offset);
+        }
+    }
+    set_link_state(LinkState::Connected);
+    let server = unsafe { REDIS_SERVER.get() }.ok_or("server not started")?;
+    let client = Arc::new(ClientInfo::new(next_client_id(), addr, laddr));
+    client.set_repl_role(ClientType::Master);
+    server.clients.register(client.clone());
+    let _guard = MasterClient(client.clone());
+    let mut db = REPLICATION.lock().unwrap().selected_db.unwrap_or(0) as usize;
+    let mut ack = time::interval(REPL_ACK_PERIOD);
+    loop {
+        let frame = tokio::select! {
+            res = time::timeout(REPL_TIMEOUT, conn.read_frame()) => {
+                res.map_err(|_| "timeout, no data from MASTER")??.ok_or("connection closed by MASTER")?
+            }
+            _ = ack.tick() => {
+                send_ack(&mut conn).await?;
+                continue;
+            }
+        };
+        let name = get_command_name(&frame)?.to_lowercase();
+        client.command_received(name.clone());
+        let getack = name == "replconf" && frame.get_frame_by_index(1).is_some_and(|arg| arg.to_string().eq_ignore_ascii_case("getack"));
+        match &name[..] {
+            "select" => {
+                db = frame.get_frame_by_index(1).and_then(|index| index.to_string().parse().ok()).ok_or("invalid SELECT from MASTER")?;
+                client.set_db(db);
+            }
+            "ping" | "replconf" => {}
+            _ => {
+                let cmd = RedisCommand::from_frame("", frame.clone())?;
+                let sender = server.db_handler.get_sender(db).ok_or("MASTER selected an invalid DB")?;
+                let (reply, receiver) = oneshot::channel();
+                sender.send((reply, cmd, CallContext { argv: frame.clone(), client: client.clone() })).await?;
+                if let Err(err) = receiver.await? {
+                    error!(cause = %err, "failed to apply a command of the MASTER");
+                }
+            }
+        }
+        // passed on as received, so the offsets of this server and of its replicas
+        // follow the ones of the master
+        let mut bytes = vec![];
+        Connection::write_value(&frame, &mut bytes);
+        {
+            let mut repl = REPLICATION.lock().unwrap();
+            if name == "select" {
+                repl.selected_db = Some(db as i32);
+            }
+            repl.feed(Bytes::from(bytes));
+            repl.set_link_state(LinkState::Connected);
+        }
+        if getack {
+            send_ack(&mut conn).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::parser::frame::Frame;
+    use crate::server::replication::{parse_replconf_ack, parse_replicaof, with_absolute_ttl, xadd_with_id, Backlog};
+
+    #[test]
+    fn replicaof() {
+        assert_eq!(parse_replicaof("127.0.0.1 6379"), Some(("127.0.0.1".to_string(), 6379)));
+        assert_eq!(parse_replicaof("localhost"), None);
+        assert_eq!(parse_replicaof("localhost 70000"), None);
+        assert_eq!(parse_replicaof("localhost 6379 1"), None);
+    }
+
+    #[test]
+    fn xadd_id() {
+        let id = Bytes::from("1-0");
+        let rewrite = |args: &[&str]| {
+            let argv = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+            match xadd_with_id(&argv, &id) {
+                Frame::Array(args) => args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(" "),
+                frame => panic!("unexpected frame {:?}", frame),
+            }
+        };
+        assert_eq!(rewrite(&["xadd", "s", "*", "f", "*"]), "xadd s 1-0 f *");
+        assert_eq!(rewrite(&["xadd", "s", "maxlen", "~", "10", "*", "f", "v"]), "xadd s maxlen ~ 10 1-0 f v");
+        assert_eq!(rewrite(&["xadd", "s", "1-*", "f", "v"]), "xadd s 1-0 f v");
+        assert_eq!(rewrite(&["xadd", "s", "5-1", "f", "v"]), "xadd s 5-1 f v");
+    }
+
+    #[test]
+    fn absolute_ttl() {
+        let rewrite = |args: &[&str], when: Option<i64>| {
+            let argv = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+            with_absolute_ttl(&argv, |key| {
+                assert_eq!(key, "k");
+                when
+            })
+            .map(|frame| match frame {
+                Frame::Array(args) => args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(" "),
+                frame => panic!("unexpected frame {:?}", frame),
+            })
+        };
+        let when = Some(1_700_000_000_000);
+        assert_eq!(rewrite(&["expire", "k", "10"], when).unwrap(), "PEXPIREAT k 1700000000000");
+        assert_eq!(rewrite(&["PEXPIRE", "k", "10000"], when).unwrap(), "PEXPIREAT k 1700000000000");
+        assert_eq!(rewrite(&["expire", "k", "-1"], None).unwrap(), "DEL k");
+        assert_eq!(rewrite(&["setex", "k", "10", "v"], when).unwrap(), "SET k v PXAT 1700000000000");
+        assert_eq!(rewrite(&["setpx", "k", "10000", "v"], when).unwrap(), "SET k v PXAT 1700000000000");
+        assert_eq!(rewrite(&["set", "k", "v", "EX", "10"], when).unwrap(), "SET k v PXAT 1700000000000");
+        assert_eq!(rewrite(&["set", "k", "v", "pxat", "1700000000000"], when), None);
+        assert_eq!(rewrite(&["set", "k", "v"], None), None);
+        assert_eq!(rewrite(&["pexpireat", "k", "1700000000000"], when), None);
+    }
+
+    #[test]
+    fn backlog() {
+        let mut backlog = Backlog::new(8);
+        backlog.feed(b"abcde");
+        assert_eq!(backlog.tail(0), Some(vec![]));
+        assert_eq!(backlog.tail(3), Some(b"cde".to_vec()));
+        assert_eq!(backlog.tail(6), None);
+        // wraps around, only the last 8 bytes are kept
+        backlog.feed(b"fghij");
+        assert_eq!(backlog.histlen, 8);
+        assert_eq!(backlog.tail(8), Some(b"cdefghij".to_vec()));
+        backlog.feed(b"0123456789");
+        assert_eq!(backlog.tail(8), Some(b"23456789".to_vec()));
+        let resized = backlog.resize(4);
+        assert_eq!(resized.tail(4), Some(b"6789".to_vec()));
+        let resized = backlog.resize(16);
+        assert_eq!(resized.histlen, 8);
+        assert_eq!(resized.tail(8), Some(b"23456789".to_vec()));
+    }
+
+    #[test]
+    fn replconf_ack() {
+        let frame = |args: &[&str]| Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        assert_eq!(parse_replconf_ack(&frame(&["REPLCONF", "ACK", "42"])), Some(42));
+        assert_eq!(parse_replconf_ack(&frame(&["replconf", "getack", "*"])), None);
+        assert_eq!(parse_replconf_ack(&frame(&["PING"])), None);
+    }
+}
@@ -1,39 +1,141 @@
-use std::future::Future;
-use std::sync::{Arc};
-use std::sync::atomic::AtomicU64;
-use std::time::Duration;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{mpsc, Semaphore, broadcast, oneshot};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::os::unix::fs::PermissionsExt;
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, broadcast, oneshot};
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::time;
-use tracing::{error, info};
+use tokio_rustls::{rustls, TlsAcceptor};
+use tracing::{debug, error, info};
 
 use crate::config::ServerConfig;
-use crate::parser::cmd::command::{CommandStrategy, RedisCommand};
+use crate::parser::cmd::command::{get_command_name, CommandStrategy, RedisCommand};
 use crate::parser::cmd::conn::{*};
-use crate::server::connection::Connection;
-use crate::db::db_engine::DbHandler;
+use crate::parser::cmd::replication::ReplicationCmd;
+use crate::parser::cmd::server::ServerCmd;
+use crate::server::acl::{acl_log, check_command, command_keys, denial_message, get_user, load_acl_config, AclDenial};
+use crate::server::config::record_startup_config;
+use crate::server::clients::{load_clients_config, maxclients, tcp_keepalive, ClientInfo, ClientRegistry, ClientType, PauseMode, ReplyMode, DEFAULT_USER};
+use crate::server::connection::{Connection, Stream};
+use crate::cluster::cluster::{cluster_enabled, load_cluster_config, route_keys, KeyRoute};
+use crate::db::db::KeyspaceInfo;
+use crate::db::db_engine::{DbHandler, RDbCommand};
+use crate::db::expire::mstime;
+use crate::db::evict::load_eviction_config;
+use crate::db::memory::MemoryOverhead;
+use crate::db::object::load_encoding_config;
 use crate::parser::frame::Frame;
-use crate::persistence::rdb::RdbHandler;
+use crate::persistence::rdb::{rdb_payload, RdbHandler};
+use crate::persistence::rdb_config::{load_save_config, save_params};
+use crate::server::latency::set_latency_monitor_threshold;
+use crate::server::metrics::run_metrics_server;
+use crate::server::notify::{keyspace_events_string_to_flags, set_keyspace_events};
+use crate::server::pubsub::{PubSub, Subscriptions, SubscriptionKind};
+use crate::server::replication::{
+    add_replica, check_can_sync, is_replica, lagging_replicas, load_replication_config, parse_replconf_ack, remove_replica,
+    replica_ack, replica_read_only, set_replica_online, stream_db_aux, try_partial_resync,
+};
 use crate::server::{REDIS_CONFIG, REDIS_SERVER};
-use crate::server::shutdown::Shutdown;
+use crate::server::shutdown::{
+    cancel_shutdown, set_shutdown_timeout, shutdown_aborted, shutdown_asap, shutdown_requested, shutdown_timeout, start_shutdown, Shutdown, ShutdownFlags,
+};
+use crate::server::slowlog::{load_slowlog_config, CallContext};
+use crate::server::tls::tls_server_config;
+use crate::server::stats::{
+    client_blocked, client_connected, client_unblocked, connected_clients, ConnectedClient,
+    incr_rejected_connections, record_command, track_instantaneous_metrics, STATS_METRIC_SAMPLE_PERIOD_MS,
+};
+use crate::server::zmalloc::used_memory;
 
-const MAX_CONNECTIONS: usize = 250;
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Id of a new client, for the connections accepted and the link to the master alike
+pub(crate) fn next_client_id() -> u64 {
+    NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Time a TLS client has to complete its handshake
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection accepted by one of the listeners
+enum Incoming {
+    Tcp(TcpStream),
+    /// Accepted on `tls-port`, the handshake is still to be done
+    Tls(TcpStream, TlsAcceptor),
+    Unix(UnixStream),
+}
+
+impl Incoming {
+    /// Address of the peer and the local one, as shown by CLIENT LIST
+    fn addrs(&self) -> (String, String) {
+        match self {
+            Incoming::Tcp(socket) | Incoming::Tls(socket, _) => (
+                socket.peer_addr().map(|addr| addr.to_string()).unwrap_or_default(),
+                socket.local_addr().map(|addr| addr.to_string()).unwrap_or_default(),
+            ),
+            // like Redis, the path of the socket with port 0
+            Incoming::Unix(socket) => {
+                let path = socket.local_addr().ok().and_then(|addr| addr.as_pathname().map(|path| path.display().to_string()));
+                let addr = format!("{}:0", path.unwrap_or_default());
+                (addr.clone(), addr)
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct RedisServer {
     listener: TcpListener,
+    /// Listener of `tls-port` and the TLS settings of its connections, if TLS is on
+    tls_listener: Option<(TcpListener, Arc<rustls::ServerConfig>)>,
+    unix_listener: Option<UnixListener>,
     notify_shutdown: broadcast::Sender<()>,
-    limit_connections: Arc<Semaphore>,
-    db_handler: Arc<DbHandler>,
+    pub(crate) db_handler: Arc<DbHandler>,
+    /// Pub/sub channels are server-wide, not scoped to a DB
+    pub(crate) pubsub: Arc<Mutex<PubSub>>,
+    pub(crate) clients: Arc<ClientRegistry>,
     shutdown_complete_tx: mpsc::Sender<()>,
     shutdown_complete_rx: mpsc::Receiver<()>,
     pub(crate) dirty: AtomicU64,
+    /// Memory used once the server was set up, before serving any client
+    pub(crate) initial_memory_usage: usize,
+    pub(crate) rdb_handler: RdbHandler,
+    pub(crate) start_time: Instant,
+    /// Random id of this run of the server
+    pub(crate) run_id: String,
+    pub(crate) port: u16,
+    /// Unix time of the last successful save
+    pub(crate) lastsave: AtomicI64,
+    pub(crate) rdb_bgsave_in_progress: AtomicBool,
+    pub(crate) last_bgsave_ok: AtomicBool,
+    /// Duration of the last save in seconds, -1 if none happened yet
+    pub(crate) last_bgsave_time_sec: AtomicI64,
 }
 
 impl RedisServer {
     pub fn new(listener: TcpListener) -> Self {
         let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel(1);
         let db_num = REDIS_CONFIG.get().unwrap().db_num;
+        // loaded objects start with the access bits of the configured policy
+        if let Err(err) = load_eviction_config(REDIS_CONFIG.get().unwrap()) {
+            error!(cause = %err, "invalid eviction config");
+        }
+        load_slowlog_config(REDIS_CONFIG.get().unwrap());
+        if let Err(err) = load_clients_config(REDIS_CONFIG.get().unwrap()) {
+            error!(cause = %err, "invalid clients config");
+        }
+        if let Err(err) = load_acl_config(REDIS_CONFIG.get().unwrap()) {
+            error!(cause = %err, "invalid acl config");
+        }
+        set_latency_monitor_threshold(REDIS_CONFIG.get().unwrap().latency_monitor_threshold);
+        load_encoding_config(REDIS_CONFIG.get().unwrap());
+        // the DBs are created with a dict per slot in cluster mode
+        if let Err(err) = load_cluster_config(REDIS_CONFIG.get().unwrap()) {
+            error!(cause = %err, "invalid cluster config");
+        }
         let db_handler = Arc::new(DbHandler::new(db_num));
         let db_sender = db_handler.db_sender.clone();
         let rdb_handler = RdbHandler::new(db_sender);
@@ -41,42 +143,156 @@ impl RedisServer {
             Ok(_) => info!("load rdb file success"),
             Err(err) => error!(cause = %err, "load rdb file failed")
         }
-        let save_params = REDIS_CONFIG.get().unwrap().get_param();
-        if !save_params.is_empty()  {
-            for param in save_params {
-                let handler = rdb_handler.clone();
-                tokio::spawn(async move {
-                    Self::run_rdb_check(handler, param.seconds, param.changes as u64).await;
-                });
+        let notify_classes = &REDIS_CONFIG.get().unwrap().notify_keyspace_events;
+        match keyspace_events_string_to_flags(notify_classes) {
+            Some(flags) => set_keyspace_events(flags),
+            None => error!("invalid notify-keyspace-events '{}'", notify_classes),
+        }
+        tokio::spawn(Self::server_cron());
+        if let Some(addr) = REDIS_CONFIG.get().unwrap().metrics_addr.clone() {
+            tokio::spawn(run_metrics_server(addr, db_handler.clone()));
+        }
+        let port = listener.local_addr().map_or(0, |addr| addr.port());
+        let tls_listener = match Self::bind_tls(REDIS_CONFIG.get().unwrap()) {
+            Ok(tls_listener) => tls_listener,
+            Err(err) => {
+                error!(cause = %err, "failed to set up tls");
+                None
             }
+        };
+        let unix_listener = match Self::bind_unix(REDIS_CONFIG.get().unwrap()) {
+            Ok(unix_listener) => unix_listener,
+            Err(err) => {
+                error!(cause = %err, "failed to listen on the unix socket");
+                None
+            }
+        };
+        if let Err(err) = load_save_config(REDIS_CONFIG.get().unwrap()) {
+            error!(cause = %err, "invalid save config");
+        }
+        set_shutdown_timeout(REDIS_CONFIG.get().unwrap().shutdown_timeout);
+        if let Err(err) = load_replication_config(REDIS_CONFIG.get().unwrap()) {
+            error!(cause = %err, "invalid replication config");
         }
+        record_startup_config();
+        tokio::spawn(Self::run_rdb_check());
 
         Self {
             listener,
+            tls_listener,
+            unix_listener,
             notify_shutdown: broadcast::channel(1).0,
-            limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
             db_handler,
+            pubsub: Arc::new(Mutex::new(PubSub::default())),
+            clients: Arc::new(ClientRegistry::default()),
             shutdown_complete_tx,
             shutdown_complete_rx,
             dirty: AtomicU64::new(0),
+            initial_memory_usage: used_memory(),
+            rdb_handler,
+            start_time: Instant::now(),
+            run_id: (0..40).map(|_| char::from_digit(rand::random_range(0..16), 16).unwrap()).collect(),
+            port,
+            lastsave: AtomicI64::new(mstime() / 1000),
+            rdb_bgsave_in_progress: AtomicBool::new(false),
+            last_bgsave_ok: AtomicBool::new(true),
+            last_bgsave_time_sec: AtomicI64::new(-1),
         }
     }
 
+    /// Binds `tls-port` on the `bind` address if set, along with the acceptor built from
+    /// the certificates.
+    fn bind_tls(config: &ServerConfig) -> crate::Result<Option<(TcpListener, Arc<rustls::ServerConfig>)>> {
+        if config.tls_port == 0 {
+            return Ok(None);
+        }
+        let tls_config = tls_server_config(config)?;
+        let listener = std::net::TcpListener::bind((config.bind.as_str(), config.tls_port))?;
+        listener.set_nonblocking(true)?;
+        info!("accepting TLS connections on {}:{}", config.bind, config.tls_port);
+        Ok(Some((TcpListener::from_std(listener)?, tls_config)))
+    }
+
+    /// Listens on `unixsocket` if set, replacing a socket file left by a previous run.
+    fn bind_unix(config: &ServerConfig) -> crate::Result<Option<UnixListener>> {
+        let Some(path) = &config.unixsocket else { return Ok(None) };
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        if !config.unixsocketperm.is_empty() {
+            let mode = u32::from_str_radix(&config.unixsocketperm, 8)
+                .map_err(|_| format!("invalid unixsocketperm '{}'", config.unixsocketperm))?;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        info!("accepting connections on unix socket {}", path);
+        Ok(Some(listener))
+    }
+
     async fn run(&mut self) -> crate::Result<()> {
         info!("ready to accept connection");
         loop {
-            self.limit_connections.acquire().await?.forget();
-            let socket = self.accept().await?;
+            let incoming = self.accept().await?;
+            if connected_clients() >= maxclients() as u64 {
+                incr_rejected_connections();
+                let err = b"-ERR max number of clients reached\r\n";
+                let _ = match incoming {
+                    Incoming::Tcp(mut socket) => socket.write_all(err).await,
+                    Incoming::Unix(mut socket) => socket.write_all(err).await,
+                    // a TLS client could not read the error before its handshake
+                    Incoming::Tls(..) => Ok(()),
+                };
+                continue;
+            }
             info!("accept new connection");
-            let mut handler = Handler {
-                connection: Connection::new(socket),
-                limit_connections: self.limit_connections.clone(),
-                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
-                _shutdown_complete: self.shutdown_complete_tx.clone(),
-                db_sender: self.db_handler.as_ref().get_sender(0).unwrap(),
-                db_handler: self.db_handler.clone(),
-            };
+            let connected = client_connected();
+            let id = next_client_id();
+            let (addr, laddr) = incoming.addrs();
+            let client = Arc::new(ClientInfo::new(id, addr, laddr));
+            let clients = self.clients.clone();
+            let shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+            let shutdown_complete = self.shutdown_complete_tx.clone();
+            let db_sender = self.db_handler.as_ref().get_sender(0).unwrap();
+            let db_handler = self.db_handler.clone();
+            let pubsub = self.pubsub.clone();
+            // the handshake runs on the connection task so a slow client does not hold up accept
             tokio::spawn(async move {
+                let stream = match incoming {
+                    Incoming::Tcp(socket) => Stream::from(socket),
+                    Incoming::Unix(socket) => Stream::from(socket),
+                    Incoming::Tls(socket, acceptor) => match time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+                        Ok(Ok(stream)) => Stream::from(stream),
+                        Ok(Err(err)) => {
+                            info!(cause = %err, "tls handshake failed");
+                            return;
+                        }
+                        Err(_) => {
+                            info!("tls handshake timed out");
+                            return;
+                        }
+                    },
+                };
+                clients.register(client.clone());
+                let subscriptions = Subscriptions::new(client.clone());
+                // the default user needs no AUTH unless it has a password or is disabled
+                let authenticated = get_user(DEFAULT_USER).is_some_and(|user| user.is_enabled() && user.is_nopass());
+                let mut handler = Handler {
+                    client,
+                    authenticated,
+                    clients,
+                    reply_mode: ReplyMode::On,
+                    skip_reply: false,
+                    argv: Frame::Null,
+                    connection: Connection::new(stream),
+                    shutdown,
+                    _shutdown_complete: shutdown_complete,
+                    _connected: connected,
+                    db_sender,
+                    db_index: 0,
+                    db_handler,
+                    pubsub,
+                    subscriptions,
+                    replica_listening_port: 0,
+                    asking: false,
+                };
                 if let Err(err) = handler.run().await {
                     error!(cause = ?err, "handler error");
                 }
@@ -84,11 +300,39 @@ impl RedisServer {
         }
     }
 
-    async fn accept(&mut self) -> crate::Result<TcpStream> {
+    /// Waits for a connection on the TCP port, `tls-port` or the Unix socket.
+    async fn accept(&mut self) -> crate::Result<Incoming> {
         let mut backoff = 1;
         loop {
-            match self.listener.accept().await {
-                Ok((socket, _)) => return Ok(socket),
+            let tls = async {
+                match &self.tls_listener {
+                    Some((listener, tls_config)) => listener
+                        .accept()
+                        .await
+                        .map(|(socket, _)| Incoming::Tls(socket, TlsAcceptor::from(tls_config.clone()))),
+                    None => std::future::pending().await,
+                }
+            };
+            let unix = async {
+                match &self.unix_listener {
+                    Some(listener) => listener.accept().await.map(|(socket, _)| Incoming::Unix(socket)),
+                    None => std::future::pending().await,
+                }
+            };
+            let accepted = tokio::select! {
+                res = self.listener.accept() => res.map(|(socket, _)| Incoming::Tcp(socket)),
+                res = tls => res,
+                res = unix => res,
+            };
+            match accepted {
+                Ok(incoming) => {
+                    if let Incoming::Tcp(socket) | Incoming::Tls(socket, _) = &incoming {
+                        if let Err(err) = set_keepalive(socket, tcp_keepalive()) {
+                            error!(cause = %err, "failed to set tcp keepalive");
+                        }
+                    }
+                    return Ok(incoming);
+                }
                 Err(err) => {
                     if backoff > 64 {
                         return Err(err.into());
@@ -104,105 +348,735 @@ impl RedisServer {
         self.dirty.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
     }
 
-    async fn run_rdb_check(rdb_handler: RdbHandler, seconds: u64, change: u64) {
-        let mut interval = time::interval(Duration::from_secs(seconds));
-        unsafe {
-            loop {
-                interval.tick().await;
-                let current_dirty = REDIS_SERVER.get().unwrap().dirty.load(std::sync::atomic::Ordering::Relaxed);
-                if current_dirty >= change {
-                    let db_num = REDIS_CONFIG.get().unwrap().db_num;
-                    for db_id in 0..db_num {
-                        if let Err(e) = rdb_handler.save(db_id) {
-                            error!("Failed to save RDB for database {}: {}", db_id, e);
-                        }
-                    }
-                    REDIS_SERVER.get().unwrap().dirty.store(0, std::sync::atomic::Ordering::Relaxed);
+    /// Periodic jobs of the server as a whole, the DB tasks run their own.
+    async fn server_cron() {
+        let mut interval = time::interval(Duration::from_millis(STATS_METRIC_SAMPLE_PERIOD_MS));
+        loop {
+            interval.tick().await;
+            track_instantaneous_metrics();
+        }
+    }
+
+    /// Saves once a save rule is met: at least `seconds` since the last save and
+    /// `changes` writes. The rules are read on every check so CONFIG SET applies them.
+    async fn run_rdb_check() {
+        let mut interval = time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let Some(server) = (unsafe { REDIS_SERVER.get() }) else { continue };
+            let current_dirty = server.dirty.load(std::sync::atomic::Ordering::Relaxed);
+            let since_save = (mstime() / 1000 - server.lastsave.load(Ordering::Relaxed)).max(0) as u64;
+            let due = save_params().iter().any(|param| current_dirty >= param.changes as u64 && since_save >= param.seconds);
+            if due {
+                server.rdb_save().await;
+            }
+        }
+    }
+
+    /// Saves every DB to the RDB file, returning whether it succeeded.
+    async fn rdb_save(&self) -> bool {
+        self.rdb_bgsave_in_progress.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        // saving waits on the DB tasks, keep it off the runtime workers
+        let handler = self.rdb_handler.clone();
+        let ok = tokio::task::spawn_blocking(move || match handler.save() {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to save RDB: {}", e);
+                false
+            }
+        }).await.unwrap_or(false);
+        self.last_bgsave_ok.store(ok, Ordering::Relaxed);
+        self.last_bgsave_time_sec.store(start.elapsed().as_secs() as i64, Ordering::Relaxed);
+        if ok {
+            self.lastsave.store(mstime() / 1000, Ordering::Relaxed);
+        }
+        self.rdb_bgsave_in_progress.store(false, Ordering::Relaxed);
+        self.dirty.store(0, std::sync::atomic::Ordering::Relaxed);
+        ok
+    }
+
+    /// Gets the server ready to stop, as SHUTDOWN and SIGTERM/SIGINT do: writes are
+    /// paused, lagging replicas get up to `shutdown-timeout` to catch up, the commands
+    /// the DBs already received are served and the final RDB is saved. On failure the
+    /// server keeps running, unless `force`. Once it returns Ok, `shutdown_asap` stops it.
+    pub async fn prepare_for_shutdown(&self, flags: ShutdownFlags) -> crate::Result<()> {
+        if !start_shutdown() {
+            return Err("ERR Errors trying to SHUTDOWN. Check logs.".into());
+        }
+        let timeout = if flags.now { 0 } else { shutdown_timeout() };
+        // the pause is extended by the save, lifted if the shutdown fails
+        self.clients.pause(PauseMode::Write, Duration::from_secs(timeout + 3600));
+        let res = self.shutdown_sequence(flags, timeout).await;
+        match res {
+            Ok(_) => shutdown_asap(),
+            Err(_) => {
+                self.clients.unpause();
+                cancel_shutdown();
+            }
+        }
+        res
+    }
+
+    async fn shutdown_sequence(&self, flags: ShutdownFlags, timeout: u64) -> crate::Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        while lagging_replicas() > 0 && Instant::now() < deadline {
+            tokio::select! {
+                _ = shutdown_aborted() => {
+                    info!("shutdown aborted");
+                    return Err("ERR Errors trying to SHUTDOWN. Check logs.".into());
+                }
+                _ = time::sleep(Duration::from_millis(100)) => {}
+            }
+        }
+        if lagging_replicas() > 0 {
+            info!("lagging replicas did not catch up within shutdown-timeout");
+        }
+        self.db_handler.drain().await?;
+        // there is no AOF to fsync, the RDB is the only thing persisted
+        let save = flags.save.unwrap_or_else(|| !save_params().is_empty());
+        if save {
+            info!("saving the final RDB snapshot before exiting");
+            if !self.rdb_save().await {
+                if !flags.force {
+                    error!("error trying to save the DB, can't exit");
+                    return Err("ERR Errors trying to SHUTDOWN. Check logs.".into());
                 }
+                error!("error trying to save the DB, exiting anyway (FORCE)");
             }
         }
+        Ok(())
     }
 }
 
 pub struct Handler {
+    /// Identity and metadata of the client, shared with the client registry
+    client: Arc<ClientInfo>,
+    /// Whether the connection may run commands other than AUTH, HELLO and QUIT
+    authenticated: bool,
+    clients: Arc<ClientRegistry>,
+    reply_mode: ReplyMode,
+    /// Whether the reply of the command being served is dropped (CLIENT REPLY SKIP)
+    skip_reply: bool,
+    /// Arguments of the command being served
+    argv: Frame,
     connection: Connection,
-    limit_connections: Arc<Semaphore>,
     shutdown: Shutdown,
     _shutdown_complete: mpsc::Sender<()>,
+    /// Keeps the connection counted in `connected_clients` while the handler lives
+    _connected: ConnectedClient,
     db_sender: crate::MpscSender,
+    db_index: usize,
     db_handler: Arc<DbHandler>,
+    pubsub: Arc<Mutex<PubSub>>,
+    subscriptions: Subscriptions,
+    /// Port a replica serves clients on, sent with REPLCONF listening-port
+    replica_listening_port: u16,
+    /// Set by ASKING, lets the next command use a key of a slot being imported
+    asking: bool,
 }
 
 impl Handler {
     async fn run(&mut self) -> crate::Result<()> {
         loop {
+            self.sync_client_info();
+            // subscribers wait for messages, blocked clients never get here while blocked
+            let subscriber = self.subscriptions.is_subscriber();
+            let client = self.client.clone();
             let frame = tokio::select! {
                 res = self.connection.read_frame() => res?,
+                _ = client.idle_timed_out(), if !subscriber => {
+                    info!("closing idle client {}", self.client.id);
+                    return Ok(());
+                }
+                Some(message) = self.subscriptions.receiver.recv() => {
+                    if self.reply_mode != ReplyMode::Off {
+                        // a client over its output buffer limit is not waited on
+                        tokio::select! {
+                            res = self.connection.write_frame(&message) => res?,
+                            _ = client.killed() => return Ok(()),
+                        }
+                    }
+                    self.client.output_written(message.encoded_len());
+                    continue;
+                }
+                _ = self.client.killed() => return Ok(()),
                 _ = self.shutdown.receiver() => return Ok(())
             };
 
-            if let Some(frame) = frame {
-                let result_cmd = RedisCommand::from_frame("", frame)?;
-                match &result_cmd {
-                    RedisCommand::Connection(cmd) => {
-                        match cmd {
-                            ConnCmd::Quit => {
-                                self.shutdown.shutdown();
-                                return Ok(());
-                            }
-                            _ => {
-                                let result = cmd.apply(self)?;
-                                self.connection.write_frame(&result).await?;
-                                continue;
-                            }
+            // the peer closed the connection
+            let Some(frame) = frame else { return Ok(()) };
+            // CLIENT REPLY SKIP drops the reply of the command after it
+            self.skip_reply = self.reply_mode == ReplyMode::Skip;
+            if self.skip_reply {
+                self.reply_mode = ReplyMode::On;
+            }
+            let cmd_name = get_command_name(&frame)?;
+            self.client.command_received(cmd_name.to_lowercase());
+            let auth_cmd = matches!(&cmd_name.to_lowercase()[..], "auth" | "hello" | "quit" | "reset");
+            if !self.authenticated && !auth_cmd {
+                self.reply(&Frame::Error("NOAUTH Authentication required.".to_string())).await?;
+                continue;
+            }
+            self.argv = frame.clone();
+            // a command that fails to parse gets its error, the connection stays open
+            let result_cmd = match RedisCommand::from_frame("", frame) {
+                Ok(cmd) => cmd,
+                Err(err) => {
+                    self.reply(&Frame::Error(err.to_string())).await?;
+                    continue;
+                }
+            };
+            if !auth_cmd {
+                if let Err((denial, object)) = check_command(&self.client.user(), &self.argv_strings()) {
+                    let username = self.client.user();
+                    acl_log(denial, &object, &username, self.client.info_line());
+                    self.reply(&Frame::Error(denial_message(&username, denial, &object))).await?;
+                    continue;
+                }
+            }
+            // CLIENT commands go through so a paused server can be unpaused
+            let is_client_cmd = matches!(result_cmd, RedisCommand::Connection(ConnCmd::Client(_)));
+            if !is_client_cmd && self.clients.is_paused(result_cmd.is_write()) {
+                let clients = self.clients.clone();
+                tokio::select! {
+                    _ = clients.wait_unpaused(result_cmd.is_write()) => {}
+                    _ = self.client.killed() => return Ok(()),
+                    _ = self.shutdown.receiver() => return Ok(())
+                }
+            }
+            if result_cmd.is_write() && is_replica() && replica_read_only() {
+                self.reply(&Frame::Error("READONLY You can't write against a read only replica.".to_string())).await?;
+                continue;
+            }
+            // ASKING only holds for the command after it
+            let asking = std::mem::take(&mut self.asking);
+            if cluster_enabled() {
+                if let Some(err) = self.cluster_redirect(asking).await? {
+                    self.reply(&Frame::Error(err)).await?;
+                    continue;
+                }
+            }
+            let start = Instant::now();
+            if self.subscriptions.is_subscriber() && !Self::allowed_in_subscriber_mode(&result_cmd) {
+                let err = format!("ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context", cmd_name.to_lowercase());
+                self.reply(&Frame::Error(err)).await?;
+                continue;
+            }
+            match &result_cmd {
+                RedisCommand::Connection(cmd) => {
+                    match cmd {
+                        ConnCmd::Quit => {
+                            self.shutdown.shutdown();
+                            return Ok(());
+                        }
+                        // in subscriber mode PING replies like a pushed message
+                        ConnCmd::Ping { msg } if self.subscriptions.is_subscriber() => {
+                            let msg = msg.clone().unwrap_or_default();
+                            let mut frame = Frame::array();
+                            frame.push_bulk(Bytes::from("pong".as_bytes()));
+                            frame.push_bulk(Bytes::from(msg.into_bytes()));
+                            record_command(&cmd_name, start, &frame);
+                            self.reply(&frame).await?;
+                        }
+                        _ => {
+                            let result = cmd.apply(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                            record_command(&cmd_name, start, &result);
+                            self.reply(&result).await?;
+                            continue;
                         }
                     }
-                    _ => {
-                        let (sender, receiver) = oneshot::channel();
-                        self.db_sender.send((sender, result_cmd)).await?;
-                        let frame = receiver.await?.unwrap_or_else(|e| Frame::Error(e.to_string()));
-                        self.connection.write_frame(&frame).await?;
+                }
+                RedisCommand::PubSub(_) => {
+                    let RedisCommand::PubSub(cmd) = result_cmd else { unreachable!() };
+                    let frames = cmd.apply(self).unwrap_or_else(|e| vec![Frame::Error(e.to_string())]);
+                    record_command(&cmd_name, start, frames.first().unwrap_or(&Frame::Null));
+                    for frame in frames {
+                        self.reply(&frame).await?;
                     }
-                };
+                }
+                RedisCommand::Acl(cmd) => {
+                    let frame = cmd.apply(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                // server-wide state, answered here so it is not timed into the slowlog by a DB
+                RedisCommand::Slowlog(cmd) => {
+                    let frame = cmd.apply().unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                RedisCommand::Latency(cmd) => {
+                    let frame = cmd.apply().unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                RedisCommand::Server(cmd @ ServerCmd::Shutdown { abort: false, .. }) => {
+                    match cmd.apply(self).await {
+                        Ok(_) => {
+                            self.shutdown.shutdown();
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            let frame = Frame::Error(e.to_string());
+                            record_command(&cmd_name, start, &frame);
+                            self.reply(&frame).await?;
+                        }
+                    }
+                }
+                RedisCommand::Server(cmd) => {
+                    let frame = cmd.apply(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                // the connection becomes a replica and is fed the stream until it closes
+                RedisCommand::Replication(ReplicationCmd::Psync { replid, offset }) => {
+                    record_command(&cmd_name, start, &Frame::Null);
+                    return self.sync_replica(replid, *offset).await;
+                }
+                RedisCommand::Replication(cmd) => {
+                    let frame = cmd.apply(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                RedisCommand::Cluster(cmd) => {
+                    let frame = cmd.apply_server(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                RedisCommand::Memory(cmd) if cmd.is_server_wide() => {
+                    let frame = cmd.apply_server(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                RedisCommand::Debug(cmd) if cmd.is_server_wide() => {
+                    let frame = cmd.apply_server(self).await.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                _ if result_cmd.is_blocking() => {
+                    let frame = match self.block(result_cmd).await? {
+                        Some(frame) => frame,
+                        None => return Ok(()),
+                    };
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+                _ => {
+                    let (sender, receiver) = oneshot::channel();
+                    self.db_sender.send((sender, result_cmd, self.call_context())).await?;
+                    let frame = receiver.await?.unwrap_or_else(|e| Frame::Error(e.to_string()));
+                    record_command(&cmd_name, start, &frame);
+                    self.reply(&frame).await?;
+                }
+            };
+        }
+    }
+
+    /// Writes the reply of the command being served, unless CLIENT REPLY turned
+    /// replies off or skips this one.
+    async fn reply(&mut self, frame: &Frame) -> crate::Result<()> {
+        if self.reply_mode == ReplyMode::On && !self.skip_reply {
+            let len = frame.encoded_len();
+            if !self.client.add_output(len) {
+                return Ok(());
+            }
+            let res = tokio::select! {
+                res = self.connection.write_frame(frame) => res,
+                _ = self.client.killed() => Ok(()),
+            };
+            self.client.output_written(len);
+            res?;
+        }
+        Ok(())
+    }
+
+    pub fn set_asking(&mut self) {
+        self.asking = true;
+    }
+
+    /// The -MOVED, -ASK, -TRYAGAIN or -CROSSSLOT error sent back instead of serving the
+    /// command when this node does not serve its keys, None to serve it here.
+    async fn cluster_redirect(&self, asking: bool) -> crate::Result<Option<String>> {
+        let args = self.argv_strings();
+        let keys = command_keys(&args);
+        match route_keys(&keys, asking) {
+            Ok(KeyRoute::Local) => Ok(None),
+            // keys already migrated are asked from the target
+            Ok(KeyRoute::Migrating { slot, target }) => {
+                let count = keys.len();
+                match self.db_handler.existing_keys(0, keys.iter().map(|key| key.to_string()).collect()).await? {
+                    existing if existing == count => Ok(None),
+                    0 => Ok(Some(format!("ASK {} {}", slot, target))),
+                    _ => Ok(Some("TRYAGAIN Multiple keys request during rehashing of slot".to_string())),
+                }
             }
+            Err(err) => Ok(Some(err)),
         }
     }
 
+    pub fn set_replica_listening_port(&mut self, port: u16) {
+        self.replica_listening_port = port;
+    }
+
+    /// Serves PSYNC: from the backlog when it still holds the writes from `offset` of
+    /// history `replid` on, else with a full resync, the RDB of every DB taken at one
+    /// point of the stream. Then feeds the writes applied from that point on, until
+    /// the replica goes away.
+    async fn sync_replica(&mut self, replid: &str, offset: i64) -> crate::Result<()> {
+        self.client.set_repl_role(ClientType::Replica);
+        let res = match try_partial_resync(self.client.clone(), self.replica_listening_port, replid, offset) {
+            Some((mut sync, backlog)) => {
+                info!("partial resync accepted for replica {}, sending {} bytes of backlog", self.client.addr, backlog.len());
+                let header = format!("+CONTINUE {}\r\n", sync.replid);
+                self.feed_replica(header.as_bytes(), &backlog, &mut sync.receiver).await
+            }
+            None => {
+                if let Err(err) = check_can_sync() {
+                    return self.reply(&Frame::Error(err.to_string())).await;
+                }
+                let (sections, resumes) = self.db_handler.snapshot().await?;
+                // registered while every DB is frozen, the stream starts right after the snapshot
+                let mut sync = add_replica(self.client.clone(), self.replica_listening_port);
+                drop(resumes);
+                let aux: Vec<_> = stream_db_aux(sync.stream_db).into_iter().collect();
+                let payload = rdb_payload(&aux, &sections)?;
+                info!("full resync requested by replica {}, replid {} offset {}", self.client.addr, sync.replid, sync.offset);
+                let header = format!("+FULLRESYNC {} {}\r\n${}\r\n", sync.replid, sync.offset, payload.len());
+                self.feed_replica(header.as_bytes(), &payload, &mut sync.receiver).await
+            }
+        };
+        remove_replica(self.client.id);
+        info!("replica {} disconnected", self.client.addr);
+        res
+    }
+
+    async fn feed_replica(&mut self, header: &[u8], payload: &[u8], receiver: &mut mpsc::UnboundedReceiver<Bytes>) -> crate::Result<()> {
+        self.connection.write_raw(header).await?;
+        self.connection.write_raw(payload).await?;
+        set_replica_online(self.client.id);
+        loop {
+            tokio::select! {
+                bytes = receiver.recv() => {
+                    // dropped from the replicas, e.g. over its output buffer limit
+                    let Some(bytes) = bytes else { return Ok(()) };
+                    self.connection.write_raw(&bytes).await?;
+                    self.client.output_written(bytes.len());
+                }
+                // nothing a replica sends is answered, REPLCONF ACK reports its offset
+                res = self.connection.read_frame() => {
+                    let Some(frame) = res? else { return Ok(()) };
+                    if let Some(offset) = parse_replconf_ack(&frame) {
+                        replica_ack(self.client.id, offset);
+                    }
+                }
+                _ = self.client.killed() => return Ok(()),
+                _ = self.shutdown.receiver() => return Ok(())
+            }
+        }
+    }
+
+    pub fn set_reply_mode(&mut self, mode: ReplyMode) {
+        self.reply_mode = mode;
+    }
+
+    pub fn client(&self) -> &Arc<ClientInfo> {
+        &self.client
+    }
+
+    pub fn clients(&self) -> &ClientRegistry {
+        &self.clients
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Switches the connection to `username`, logging the failure to ACL LOG.
+    pub fn authenticate(&mut self, username: &str, password: &str) -> crate::Result<()> {
+        if !crate::server::acl::authenticate(username, password) {
+            acl_log(AclDenial::Auth, "AUTH", username, self.client.info_line());
+            return Err(denial_message(username, AclDenial::Auth, "").into());
+        }
+        self.client.set_user(username.to_string());
+        self.authenticated = true;
+        Ok(())
+    }
+
+    /// Arguments of the command being served as strings
+    fn argv_strings(&self) -> Vec<String> {
+        match &self.argv {
+            Frame::Array(args) => args.iter().map(|arg| arg.to_string()).collect(),
+            frame => vec![frame.to_string()],
+        }
+    }
+
+    /// Publishes the state of the connection to the client registry.
+    fn sync_client_info(&self) {
+        let (qbuf, qbuf_free, obuf) = self.connection.buffer_usage();
+        self.client.set_buffers(qbuf, qbuf_free, obuf);
+        self.client.set_db(self.db_index);
+        self.client.set_subscriptions(
+            self.subscriptions.channels.len(),
+            self.subscriptions.patterns.len(),
+            self.subscriptions.shard_channels.len(),
+        );
+    }
+
+    /// RESET: leaves subscriber mode, goes back to DB 0 with replies on and switches
+    /// to the default user, as a new connection would be.
+    pub fn reset(&mut self) -> crate::Result<()> {
+        self.unsubscribe_all();
+        self.change_db(0)?;
+        self.reply_mode = ReplyMode::On;
+        self.asking = false;
+        self.client.set_no_evict(false);
+        self.client.set_user(DEFAULT_USER.to_string());
+        self.authenticated = get_user(DEFAULT_USER).is_some_and(|user| user.is_enabled() && user.is_nopass());
+        Ok(())
+    }
+
     pub fn change_db(&mut self, index: usize) -> crate::Result<()> {
         let sender = self.db_handler.get_sender(index).ok_or("ERR invalid DB index")?;
         self.db_sender = sender;
+        self.db_index = index;
+        Ok(())
+    }
+
+    /// Hands a blocking command to the DB task and waits for its reply without holding
+    /// up the DB. Returns `None` when the client disconnected or the server shut down
+    /// while waiting, after telling the DB to forget about the client.
+    async fn block(&mut self, cmd: RedisCommand) -> crate::Result<Option<Frame>> {
+        let db_sender = self.db_handler.db_sender[self.db_index].clone();
+        let (sender, receiver) = oneshot::channel();
+        db_sender.send(RDbCommand::Block { client_id: self.client.id, cmd, sender }).await?;
+        client_blocked();
+        self.client.set_blocked(true);
+        let result = tokio::select! {
+            res = receiver => {
+                res.map(|reply| Some(reply.unwrap_or_else(|e| Frame::Error(e.to_string())))).map_err(Into::into)
+            }
+            res = self.connection.wait_closed() => {
+                if let Err(err) = res {
+                    debug!(cause = ?err, "blocked client connection error");
+                }
+                let (sender, _) = oneshot::channel();
+                let _ = db_sender.send(RDbCommand::Unblock { client_id: self.client.id, error: false, sender }).await;
+                Ok(None)
+            }
+            _ = self.client.killed() => Ok(None),
+            _ = self.shutdown.receiver() => Ok(None)
+        };
+        self.client.set_blocked(false);
+        client_unblocked();
+        result
+    }
+
+    /// Key counts of every DB, for INFO keyspace
+    pub async fn keyspace(&self) -> crate::Result<Vec<KeyspaceInfo>> {
+        self.db_handler.keyspace().await
+    }
+
+    /// Unblocks client `id` in whichever DB it is blocked in (CLIENT UNBLOCK).
+    pub async fn unblock_client(&self, id: u64, error: bool) -> crate::Result<bool> {
+        let mut unblocked = false;
+        for db_sender in &self.db_handler.db_sender {
+            let (sender, receiver) = oneshot::channel();
+            db_sender.send(RDbCommand::Unblock { client_id: id, error, sender }).await?;
+            unblocked |= receiver.await?;
+        }
+        Ok(unblocked)
+    }
+
+    /// Collects the hash table overhead of every DB for MEMORY STATS and MEMORY DOCTOR.
+    pub async fn memory_overhead(&self) -> crate::Result<MemoryOverhead> {
+        let dbs = self.db_handler.memory_overhead().await?;
+        let startup = unsafe { REDIS_SERVER.get().unwrap().initial_memory_usage };
+        Ok(MemoryOverhead::compute(startup, dbs))
+    }
+
+    fn call_context(&self) -> CallContext {
+        CallContext { argv: self.argv.clone(), client: self.client.clone() }
+    }
+
+    /// Runs `cmd` in DB `index` rather than in the selected one.
+    pub async fn apply_in_db(&self, index: usize, cmd: RedisCommand) -> crate::Result<Frame> {
+        let db_sender = self.db_handler.get_sender(index).ok_or("ERR Out of range database")?;
+        let (sender, receiver) = oneshot::channel();
+        db_sender.send((sender, cmd, self.call_context())).await?;
+        receiver.await?
+    }
+
+    /// Saves the DBs to the RDB file, empties them and loads the file back.
+    pub async fn reload_db(&self) -> crate::Result<()> {
+        let rdb_handler = unsafe { REDIS_SERVER.get().unwrap().rdb_handler.clone() };
+        // the RDB thread waits on the DB tasks, keep it off the runtime workers
+        let handler = rdb_handler.clone();
+        tokio::task::spawn_blocking(move || handler.save())
+            .await?
+            .map_err(|_| "ERR Error trying to save the DB")?;
+        self.db_handler.empty().await?;
+        tokio::task::spawn_blocking(move || rdb_handler.load())
+            .await?
+            .map_err(|_| "ERR Error trying to load the RDB dump")?;
+        // the keys are loaded before the next command of the client
+        self.db_handler.drain().await?;
         Ok(())
     }
 
     pub fn shutdown(&mut self) {
         self.shutdown.shutdown();
     }
+
+    fn allowed_in_subscriber_mode(cmd: &RedisCommand) -> bool {
+        match cmd {
+            RedisCommand::PubSub(cmd) => cmd.allowed_in_subscriber_mode(),
+            RedisCommand::Connection(ConnCmd::Ping { .. } | ConnCmd::Quit | ConnCmd::Reset) => true,
+            _ => false,
+        }
+    }
+
+    pub fn pubsub(&self) -> MutexGuard<'_, PubSub> {
+        self.pubsub.lock().unwrap()
+    }
+
+    /// Subscribes to every name in `names`, replying once per name with the number of
+    /// subscriptions the client now holds.
+    pub fn subscribe(&mut self, kind: SubscriptionKind, names: Vec<String>) -> Vec<Frame> {
+        let mut pubsub = self.pubsub.lock().unwrap();
+        let mut frames = Vec::with_capacity(names.len());
+        for name in names {
+            if self.subscriptions.get_mut(kind).insert(name.clone()) {
+                pubsub.subscribe(kind, &name, self.client.id, &self.subscriptions.sender);
+            }
+            frames.push(subscription_reply(kind.subscribe_msg(), Some(name), self.subscriptions.count(kind)));
+        }
+        frames
+    }
+
+    /// Unsubscribes from every name in `names`, or from all of `kind` when empty.
+    pub fn unsubscribe(&mut self, kind: SubscriptionKind, names: Vec<String>) -> Vec<Frame> {
+        let names = if names.is_empty() {
+            self.subscriptions.get_mut(kind).iter().cloned().collect()
+        } else {
+            names
+        };
+        let mut pubsub = self.pubsub.lock().unwrap();
+        if names.is_empty() {
+            return vec![subscription_reply(kind.unsubscribe_msg(), None, self.subscriptions.count(kind))];
+        }
+        let mut frames = Vec::with_capacity(names.len());
+        for name in names {
+            if self.subscriptions.get_mut(kind).remove(&name) {
+                pubsub.unsubscribe(kind, &name, self.client.id);
+            }
+            frames.push(subscription_reply(kind.unsubscribe_msg(), Some(name), self.subscriptions.count(kind)));
+        }
+        frames
+    }
+
+    /// Drops every subscription of the connection, without replying.
+    fn unsubscribe_all(&mut self) {
+        if !self.subscriptions.is_subscriber() {
+            return;
+        }
+        let mut pubsub = self.pubsub.lock().unwrap();
+        for kind in [SubscriptionKind::Channel, SubscriptionKind::Pattern, SubscriptionKind::Shard] {
+            for name in self.subscriptions.get_mut(kind).drain() {
+                pubsub.unsubscribe(kind, &name, self.client.id);
+            }
+        }
+    }
+}
+
+fn subscription_reply(msg: &str, name: Option<String>, count: usize) -> Frame {
+    let name = match name {
+        Some(name) => Frame::Bulk(Bytes::from(name.into_bytes())),
+        None => Frame::Null,
+    };
+    Frame::Array(vec![Frame::Bulk(Bytes::from(msg.to_string().into_bytes())), name, Frame::Integer(count as i64)])
+}
+
+/// Turns on TCP keepalive probes for `socket` after `secs` of silence, 0 leaving them off.
+fn set_keepalive(socket: &TcpStream, secs: u64) -> std::io::Result<()> {
+    if secs == 0 {
+        return Ok(());
+    }
+    // like Redis: a probe every third of the period, the peer is dropped after three lost ones
+    let keepalive = socket2::TcpKeepalive::new()
+        .with_time(Duration::from_secs(secs))
+        .with_interval(Duration::from_secs((secs / 3).max(1)))
+        .with_retries(3);
+    socket2::SockRef::from(socket).set_tcp_keepalive(&keepalive)
 }
 
 impl Drop for Handler {
     fn drop(&mut self) {
         info!("handler quit");
-        self.limit_connections.add_permits(1);
+        self.unsubscribe_all();
+        self.clients.unregister(self.client.id);
     }
 }
 
-pub async unsafe fn run_server(listener: TcpListener, shutdown: impl Future, db_num: u32) {
-    let mut server_config = ServerConfig::default();
-    server_config.set_rdb_save_param(1, 1);
+/// Runs the server on `listener` until it shuts down.
+///
+/// # Safety
+///
+/// The server lives in the `static mut REDIS_SERVER`, which this function sets, runs from
+/// and takes back on shutdown. It must be called once per process, and nothing else may
+/// set, take or mutably borrow `REDIS_SERVER` while it runs.
+pub async unsafe fn run_server(mut server_config: ServerConfig, listener: TcpListener) {
+    if !server_config.has_save_params() {
+        server_config.set_rdb_save_param(1, 1);
+    }
     REDIS_CONFIG.set(server_config).expect("set redis config failed");
     let server = RedisServer::new(listener);
     REDIS_SERVER.set(server).expect("set redis server failed");
-    tokio::select! {
-        res = REDIS_SERVER.get_mut().unwrap().run() => {
-            if let Err(err) = res {
-                 error!(cause = %err, "failed to accept");
-            }
-        },
-        _ = shutdown => {
-             info!("server shutting down");
-       }
+    let pidfile = REDIS_CONFIG.get().unwrap().pidfile.clone();
+    if let Some(path) = &pidfile {
+        if let Err(err) = std::fs::write(path, format!("{}\n", std::process::id())) {
+            error!(cause = %err, "failed to write pidfile {}", path);
+        }
+    }
+    let (mut sigterm, mut sigint) = match (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) {
+        (Ok(sigterm), Ok(sigint)) => (sigterm, sigint),
+        (Err(err), _) | (_, Err(err)) => {
+            error!(cause = %err, "failed to listen for signals");
+            return;
+        }
+    };
+    let run = REDIS_SERVER.get_mut().unwrap().run();
+    tokio::pin!(run);
+    loop {
+        tokio::select! {
+            res = &mut run => {
+                if let Err(err) = res {
+                     error!(cause = %err, "failed to accept");
+                }
+                break;
+            },
+            _ = sigterm.recv() => {
+                info!("received SIGTERM, scheduling shutdown");
+            },
+            _ = sigint.recv() => {
+                info!("received SIGINT, scheduling shutdown");
+            },
+            _ = shutdown_requested() => break,
+        }
+        // a signal arrived, stop the same way SHUTDOWN does
+        match REDIS_SERVER.get().unwrap().prepare_for_shutdown(ShutdownFlags::default()).await {
+            Ok(_) => break,
+            Err(err) => error!(cause = %err, "SIGTERM/SIGINT received but errors trying to shut down the server"),
+        }
     }
+    info!("server shutting down");
 
+    if let Some(path) = &pidfile {
+        let _ = std::fs::remove_file(path);
+    }
+    if let Some(path) = &REDIS_CONFIG.get().unwrap().unixsocket {
+        let _ = std::fs::remove_file(path);
+    }
     if let Some(server) = REDIS_SERVER.take() {
         let RedisServer {
             mut shutdown_complete_rx,
@@ -1,4 +1,67 @@
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use tokio::sync::{broadcast, Notify};
+
+static SHUTDOWN_TIMEOUT: AtomicU64 = AtomicU64::new(10);
+/// Set while a SHUTDOWN or signal is saving or waiting on replicas
+static SHUTDOWN_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+/// Wakes a shutdown waiting on replicas, making it give up (SHUTDOWN ABORT)
+static SHUTDOWN_ABORT: Notify = Notify::const_new();
+/// Wakes `run_server` once a shutdown is ready, so it stops the server
+static SHUTDOWN_ASAP: Notify = Notify::const_new();
+
+/// How SHUTDOWN or a signal stops the server
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShutdownFlags {
+    /// Some(true) for SAVE, Some(false) for NOSAVE, None to save if save rules are set
+    pub save: Option<bool>,
+    /// Do not wait for lagging replicas
+    pub now: bool,
+    /// Stop even if the final save fails
+    pub force: bool,
+}
+
+/// Seconds a shutdown waits for lagging replicas, 0 does not wait
+pub fn shutdown_timeout() -> u64 {
+    SHUTDOWN_TIMEOUT.load(Ordering::Relaxed)
+}
+
+pub fn set_shutdown_timeout(secs: u64) {
+    SHUTDOWN_TIMEOUT.store(secs, Ordering::Relaxed);
+}
+
+/// Marks a shutdown as started, false if one already is.
+pub fn start_shutdown() -> bool {
+    !SHUTDOWN_IN_PROGRESS.swap(true, Ordering::SeqCst)
+}
+
+/// Ends a shutdown that failed or was aborted, the server keeps running.
+pub fn cancel_shutdown() {
+    SHUTDOWN_IN_PROGRESS.store(false, Ordering::SeqCst);
+}
+
+/// Aborts a shutdown waiting on replicas, false if none is in progress.
+pub fn abort_shutdown() -> bool {
+    if !SHUTDOWN_IN_PROGRESS.load(Ordering::SeqCst) {
+        return false;
+    }
+    SHUTDOWN_ABORT.notify_waiters();
+    true
+}
+
+pub async fn shutdown_aborted() {
+    SHUTDOWN_ABORT.notified().await
+}
+
+/// Tells `run_server` to stop, once `prepare_for_shutdown` succeeded.
+pub fn shutdown_asap() {
+    SHUTDOWN_ASAP.notify_one();
+}
+
+pub async fn shutdown_requested() {
+    SHUTDOWN_ASAP.notified().await
+}
+
 
 pub struct Shutdown {
     shutdown: bool,
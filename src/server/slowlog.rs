@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::config::ServerConfig;
+use crate::db::expire::mstime;
+use crate::parser::frame::Frame;
+use crate::server::clients::ClientInfo;
+
+/// Arguments kept per entry, the last one telling how many were left out
+const SLOWLOG_ENTRY_MAX_ARGC: usize = 32;
+/// Bytes kept per argument
+const SLOWLOG_ENTRY_MAX_STRING: usize = 128;
+
+static SLOWLOG_LOG_SLOWER_THAN: AtomicI64 = AtomicI64::new(10000);
+static SLOWLOG_MAX_LEN: AtomicUsize = AtomicUsize::new(128);
+
+static SLOWLOG: Mutex<SlowLog> = Mutex::new(SlowLog { entries: VecDeque::new(), next_id: 0 });
+
+/// Who sent a command and its arguments, passed to the DB along with the command so
+/// slow calls can be logged.
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    pub argv: Frame,
+    pub client: Arc<ClientInfo>,
+}
+
+/// A command that ran slower than `slowlog-log-slower-than`
+#[derive(Debug, Clone)]
+pub struct SlowlogEntry {
+    pub id: u64,
+    /// Unix time the entry was logged, in seconds
+    pub time: i64,
+    pub duration_us: u64,
+    pub argv: Vec<Bytes>,
+    pub peer_id: String,
+    pub client_name: String,
+}
+
+/// The most recent slow commands, newest first
+struct SlowLog {
+    entries: VecDeque<SlowlogEntry>,
+    next_id: u64,
+}
+
+/// Applies the slow log settings of the config file.
+pub fn load_slowlog_config(config: &ServerConfig) {
+    set_slowlog_log_slower_than(config.slowlog_log_slower_than);
+    set_slowlog_max_len(config.slowlog_max_len);
+}
+
+/// Threshold in microseconds, negative disables the slow log and 0 logs every command
+pub fn slowlog_log_slower_than() -> i64 {
+    SLOWLOG_LOG_SLOWER_THAN.load(Ordering::Relaxed)
+}
+
+pub fn set_slowlog_log_slower_than(usec: i64) {
+    SLOWLOG_LOG_SLOWER_THAN.store(usec, Ordering::Relaxed);
+}
+
+pub fn slowlog_max_len() -> usize {
+    SLOWLOG_MAX_LEN.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of entries, dropping the oldest ones past it.
+pub fn set_slowlog_max_len(len: usize) {
+    SLOWLOG_MAX_LEN.store(len, Ordering::Relaxed);
+    SLOWLOG.lock().unwrap().entries.truncate(len);
+}
+
+/// Logs the call described by `call` if it took longer than the threshold.
+pub fn slowlog_push_entry_if_needed(call: &CallContext, duration: Duration) {
+    let threshold = slowlog_log_slower_than();
+    let duration_us = duration.as_micros() as u64;
+    if threshold < 0 || duration_us < threshold as u64 {
+        return;
+    }
+    let argv = slowlog_argv(&call.argv);
+    let max_len = slowlog_max_len();
+    let mut slowlog = SLOWLOG.lock().unwrap();
+    let id = slowlog.next_id;
+    slowlog.next_id += 1;
+    slowlog.entries.push_front(SlowlogEntry {
+        id,
+        time: mstime() / 1000,
+        duration_us,
+        argv,
+        peer_id: call.client.addr.clone(),
+        client_name: call.client.name(),
+    });
+    slowlog.entries.truncate(max_len);
+}
+
+/// The `count` newest entries, all of them for a negative count
+pub fn slowlog_get(count: i64) -> Vec<SlowlogEntry> {
+    let slowlog = SLOWLOG.lock().unwrap();
+    let count = if count < 0 { slowlog.entries.len() } else { count as usize };
+    slowlog.entries.iter().take(count).cloned().collect()
+}
+
+pub fn slowlog_len() -> usize {
+    SLOWLOG.lock().unwrap().entries.len()
+}
+
+/// Removes every entry, the ids keep increasing.
+pub fn slowlog_reset() {
+    SLOWLOG.lock().unwrap().entries.clear();
+}
+
+/// The arguments of a command as the slow log keeps them: at most
+/// `SLOWLOG_ENTRY_MAX_ARGC`, each cut at `SLOWLOG_ENTRY_MAX_STRING` bytes.
+fn slowlog_argv(frame: &Frame) -> Vec<Bytes> {
+    let Frame::Array(args) = frame else { return vec![] };
+    let argc = args.len().min(SLOWLOG_ENTRY_MAX_ARGC);
+    let mut argv = Vec::with_capacity(argc);
+    for (i, arg) in args.iter().take(argc).enumerate() {
+        if argc != args.len() && i == argc - 1 {
+            let more = args.len() - argc + 1;
+            argv.push(Bytes::from(format!("... ({} more arguments)", more)));
+            break;
+        }
+        let arg = match arg {
+            Frame::Bulk(bytes) => bytes.clone(),
+            other => Bytes::from(other.to_string()),
+        };
+        if arg.len() > SLOWLOG_ENTRY_MAX_STRING {
+            let more = arg.len() - SLOWLOG_ENTRY_MAX_STRING;
+            let mut truncated = arg[..SLOWLOG_ENTRY_MAX_STRING].to_vec();
+            truncated.extend_from_slice(format!("... ({} more bytes)", more).as_bytes());
+            argv.push(Bytes::from(truncated));
+        } else {
+            argv.push(arg);
+        }
+    }
+    argv
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::parser::frame::Frame;
+    use crate::server::slowlog::{slowlog_argv, SLOWLOG_ENTRY_MAX_ARGC, SLOWLOG_ENTRY_MAX_STRING};
+
+    #[test]
+    fn argv_truncation() {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("rpush"));
+        frame.push_bulk(Bytes::from("x".repeat(SLOWLOG_ENTRY_MAX_STRING + 10)));
+        for i in 0..40 {
+            frame.push_bulk(Bytes::from(i.to_string()));
+        }
+        let argv = slowlog_argv(&frame);
+        assert_eq!(argv.len(), SLOWLOG_ENTRY_MAX_ARGC);
+        assert_eq!(argv[0], Bytes::from("rpush"));
+        assert_eq!(argv[1], Bytes::from(format!("{}... (10 more bytes)", "x".repeat(SLOWLOG_ENTRY_MAX_STRING))));
+        assert_eq!(argv[SLOWLOG_ENTRY_MAX_ARGC - 1], Bytes::from("... (11 more arguments)"));
+    }
+}
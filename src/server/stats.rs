@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use hdrhistogram::Histogram;
+
+use crate::parser::frame::Frame;
+
+/// Number of samples the instantaneous metrics are averaged over
+const STATS_METRIC_SAMPLES: usize = 16;
+/// How often the server cron samples the instantaneous metrics, in milliseconds
+pub const STATS_METRIC_SAMPLE_PERIOD_MS: u64 = 100;
+/// Longest command duration the latency histograms track, in microseconds. Slower calls
+/// are recorded as this.
+const LATENCY_HISTOGRAM_MAX_USEC: u64 = 60_000_000;
+
+static STAT_NUMCOMMANDS: AtomicU64 = AtomicU64::new(0);
+static STAT_NUMCONNECTIONS: AtomicU64 = AtomicU64::new(0);
+static STAT_REJECTED_CONN: AtomicU64 = AtomicU64::new(0);
+static STAT_KEYSPACE_HITS: AtomicU64 = AtomicU64::new(0);
+static STAT_KEYSPACE_MISSES: AtomicU64 = AtomicU64::new(0);
+static STAT_TOTAL_ERROR_REPLIES: AtomicU64 = AtomicU64::new(0);
+static CONNECTED_CLIENTS: AtomicU64 = AtomicU64::new(0);
+static BLOCKED_CLIENTS: AtomicU64 = AtomicU64::new(0);
+
+static OPS_METRIC: Mutex<InstMetric> = Mutex::new(InstMetric::new());
+static COMMAND_STATS: Mutex<BTreeMap<String, CommandStats>> = Mutex::new(BTreeMap::new());
+
+pub fn total_commands_processed() -> u64 {
+    STAT_NUMCOMMANDS.load(Ordering::Relaxed)
+}
+
+pub fn total_connections_received() -> u64 {
+    STAT_NUMCONNECTIONS.load(Ordering::Relaxed)
+}
+
+/// Connections refused because of `maxclients`
+pub fn rejected_connections() -> u64 {
+    STAT_REJECTED_CONN.load(Ordering::Relaxed)
+}
+
+pub fn incr_rejected_connections() {
+    STAT_REJECTED_CONN.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn keyspace_hits() -> u64 {
+    STAT_KEYSPACE_HITS.load(Ordering::Relaxed)
+}
+
+pub fn keyspace_misses() -> u64 {
+    STAT_KEYSPACE_MISSES.load(Ordering::Relaxed)
+}
+
+pub fn incr_keyspace_hits() {
+    STAT_KEYSPACE_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn incr_keyspace_misses() {
+    STAT_KEYSPACE_MISSES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn total_error_replies() -> u64 {
+    STAT_TOTAL_ERROR_REPLIES.load(Ordering::Relaxed)
+}
+
+pub fn connected_clients() -> u64 {
+    CONNECTED_CLIENTS.load(Ordering::Relaxed)
+}
+
+/// A connection counted in `connected_clients` until it is dropped.
+pub struct ConnectedClient(());
+
+/// Counts a new connection as soon as it is accepted, so `maxclients` also covers the
+/// ones still in their TLS handshake.
+pub fn client_connected() -> ConnectedClient {
+    STAT_NUMCONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    CONNECTED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+    ConnectedClient(())
+}
+
+impl Drop for ConnectedClient {
+    fn drop(&mut self) {
+        CONNECTED_CLIENTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn blocked_clients() -> u64 {
+    BLOCKED_CLIENTS.load(Ordering::Relaxed)
+}
+
+pub fn client_blocked() {
+    BLOCKED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn client_unblocked() {
+    BLOCKED_CLIENTS.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Calls, time spent and failures of one command, as reported by INFO commandstats
+#[derive(Debug, Clone)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    pub failed_calls: u64,
+    /// Durations of the calls in microseconds, for the `/metrics` endpoint
+    pub latency: Histogram<u64>,
+}
+
+impl Default for CommandStats {
+    fn default() -> Self {
+        Self {
+            calls: 0,
+            usec: 0,
+            failed_calls: 0,
+            latency: Histogram::new_with_bounds(1, LATENCY_HISTOGRAM_MAX_USEC, 2).unwrap(),
+        }
+    }
+}
+
+/// Records a call of `name` that took `start.elapsed()` and replied `reply`.
+pub fn record_command(name: &str, start: Instant, reply: &Frame) {
+    let usec = start.elapsed().as_micros() as u64;
+    let failed = matches!(reply, Frame::Error(_));
+    STAT_NUMCOMMANDS.fetch_add(1, Ordering::Relaxed);
+    if failed {
+        STAT_TOTAL_ERROR_REPLIES.fetch_add(1, Ordering::Relaxed);
+    }
+    let mut stats = COMMAND_STATS.lock().unwrap();
+    let stats = stats.entry(name.to_lowercase()).or_default();
+    stats.calls += 1;
+    stats.usec += usec;
+    stats.failed_calls += failed as u64;
+    stats.latency.saturating_record(usec.max(1));
+}
+
+/// The stats of the commands called at least once, by name
+pub fn command_stats() -> Vec<(String, CommandStats)> {
+    COMMAND_STATS.lock().unwrap().iter().map(|(name, stats)| (name.clone(), stats.clone())).collect()
+}
+
+/// Zeroes the counters and command stats for CONFIG RESETSTAT, leaving gauges such as
+/// the connected clients alone.
+pub fn reset_stats() {
+    for counter in [
+        &STAT_NUMCOMMANDS,
+        &STAT_NUMCONNECTIONS,
+        &STAT_REJECTED_CONN,
+        &STAT_KEYSPACE_HITS,
+        &STAT_KEYSPACE_MISSES,
+        &STAT_TOTAL_ERROR_REPLIES,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+    COMMAND_STATS.lock().unwrap().clear();
+    *OPS_METRIC.lock().unwrap() = InstMetric::new();
+}
+
+/// Average of the last samples of a counter, per second
+struct InstMetric {
+    last_sample_time: Option<Instant>,
+    last_sample_count: u64,
+    samples: [u64; STATS_METRIC_SAMPLES],
+    idx: usize,
+}
+
+impl InstMetric {
+    const fn new() -> Self {
+        Self { last_sample_time: None, last_sample_count: 0, samples: [0; STATS_METRIC_SAMPLES], idx: 0 }
+    }
+
+    fn track(&mut self, count: u64, now: Instant) {
+        if let Some(last) = self.last_sample_time {
+            let ms = now.duration_since(last).as_millis() as u64;
+            let ops = count.saturating_sub(self.last_sample_count);
+            self.samples[self.idx] = if ms > 0 { ops * 1000 / ms } else { 0 };
+            self.idx = (self.idx + 1) % STATS_METRIC_SAMPLES;
+        }
+        self.last_sample_time = Some(now);
+        self.last_sample_count = count;
+    }
+
+    fn get(&self) -> u64 {
+        self.samples.iter().sum::<u64>() / STATS_METRIC_SAMPLES as u64
+    }
+}
+
+/// Samples the command counter, called every `STATS_METRIC_SAMPLE_PERIOD_MS` by the
+/// server cron.
+pub fn track_instantaneous_metrics() {
+    OPS_METRIC.lock().unwrap().track(total_commands_processed(), Instant::now());
+}
+
+pub fn instantaneous_ops_per_sec() -> u64 {
+    OPS_METRIC.lock().unwrap().get()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::server::stats::{InstMetric, STATS_METRIC_SAMPLES};
+
+    #[test]
+    fn inst_metric_averages_samples() {
+        let start = Instant::now();
+        let mut metric = InstMetric::new();
+        metric.track(0, start);
+        assert_eq!(metric.get(), 0);
+        for i in 1..=STATS_METRIC_SAMPLES as u64 {
+            metric.track(i * 10, start + Duration::from_millis(i * 100));
+        }
+        // 10 commands every 100ms
+        assert_eq!(metric.get(), 100);
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::config::ServerConfig;
+
+/// Whether clients of `tls-port` must present a certificate signed by the CA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsAuthClients {
+    Yes,
+    No,
+    /// A certificate is checked if one is presented
+    Optional,
+}
+
+impl TlsAuthClients {
+    pub fn parse(value: &str) -> Option<Self> {
+        match &value.to_lowercase()[..] {
+            "yes" => Some(TlsAuthClients::Yes),
+            "no" => Some(TlsAuthClients::No),
+            "optional" => Some(TlsAuthClients::Optional),
+            _ => None,
+        }
+    }
+}
+
+fn load_certs(path: &str) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("failed to load certificates from {}: {}", path, err))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path).into());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> crate::Result<PrivateKeyDer<'static>> {
+    Ok(PrivateKeyDer::from_pem_file(path).map_err(|err| format!("failed to load private key from {}: {}", path, err))?)
+}
+
+fn load_roots(path: &str) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+/// Builds the TLS settings of `tls-port` connections from the `tls-*` settings.
+pub fn tls_server_config(config: &ServerConfig) -> crate::Result<Arc<rustls::ServerConfig>> {
+    let cert_file = config.tls_cert_file.as_deref().ok_or("tls-cert-file is required with tls-port")?;
+    let key_file = config.tls_key_file.as_deref().ok_or("tls-key-file is required with tls-port")?;
+    let auth = TlsAuthClients::parse(&config.tls_auth_clients)
+        .ok_or_else(|| format!("invalid tls-auth-clients '{}'", config.tls_auth_clients))?;
+    let builder = rustls::ServerConfig::builder();
+    let builder = match (auth, &config.tls_ca_cert_file) {
+        (TlsAuthClients::No, _) => builder.with_no_client_auth(),
+        (_, None) => return Err("tls-ca-cert-file is required to authenticate TLS clients".into()),
+        (auth, Some(ca_cert_file)) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(ca_cert_file)?));
+            let verifier = if auth == TlsAuthClients::Optional { verifier.allow_unauthenticated() } else { verifier };
+            builder.with_client_cert_verifier(verifier.build()?)
+        }
+    };
+    let server_config = builder.with_single_cert(load_certs(cert_file)?, load_key(key_file)?)?;
+    Ok(Arc::new(server_config))
+}
+
+/// TLS settings of a client connection
+#[derive(Debug, Clone, Default)]
+pub struct ClientTlsConfig {
+    /// CA the server certificate must be signed by
+    pub ca_cert_file: String,
+    /// Certificate and key presented to servers that authenticate their clients
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+    /// Name the server certificate is checked against, the host connected to if None
+    pub server_name: Option<String>,
+}
+
+impl ClientTlsConfig {
+    pub fn connector(&self) -> crate::Result<TlsConnector> {
+        let builder = rustls::ClientConfig::builder().with_root_certificates(load_roots(&self.ca_cert_file)?);
+        let client_config = match (&self.cert_file, &self.key_file) {
+            (Some(cert_file), Some(key_file)) => builder.with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err("a client certificate needs both a cert and a key file".into()),
+        };
+        Ok(TlsConnector::from(Arc::new(client_config)))
+    }
+
+    /// Name to check the certificate of the server at `host` against
+    pub fn server_name(&self, host: &str) -> crate::Result<ServerName<'static>> {
+        let name = self.server_name.as_deref().unwrap_or(host);
+        Ok(ServerName::try_from(name.to_string()).map_err(|_| format!("invalid TLS server name '{}'", name))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use rcgen::{CertificateParams, CertifiedKey, KeyPair};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsAcceptor;
+
+    use crate::config::ServerConfig;
+    use crate::parser::frame::Frame;
+    use crate::server::connection::{Connection, Stream};
+    use crate::server::tls::{tls_server_config, ClientTlsConfig};
+
+    /// PEM files of a CA, a server certificate it signed for localhost and a client one
+    struct Certs {
+        dir: PathBuf,
+    }
+
+    impl Certs {
+        fn generate(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("tinyredis-tls-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(vec![]).unwrap();
+            ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            std::fs::write(dir.join("ca.crt"), ca.pem()).unwrap();
+            for (file, names) in [("server", vec!["localhost".to_string()]), ("client", vec!["client".to_string()])] {
+                let key = KeyPair::generate().unwrap();
+                let cert = CertificateParams::new(names).unwrap().signed_by(&key, &ca, &ca_key).unwrap();
+                std::fs::write(dir.join(format!("{}.crt", file)), cert.pem()).unwrap();
+                std::fs::write(dir.join(format!("{}.key", file)), key.serialize_pem()).unwrap();
+            }
+            // a certificate the CA never signed
+            let CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+            std::fs::write(dir.join("rogue.crt"), cert.pem()).unwrap();
+            std::fs::write(dir.join("rogue.key"), key_pair.serialize_pem()).unwrap();
+            Self { dir }
+        }
+
+        fn path(&self, file: &str) -> String {
+            self.dir.join(file).to_string_lossy().into_owned()
+        }
+
+        fn server_config(&self, auth_clients: &str) -> ServerConfig {
+            ServerConfig {
+                tls_cert_file: Some(self.path("server.crt")),
+                tls_key_file: Some(self.path("server.key")),
+                tls_ca_cert_file: Some(self.path("ca.crt")),
+                tls_auth_clients: auth_clients.to_string(),
+                ..Default::default()
+            }
+        }
+
+        fn client_config(&self, cert: Option<&str>) -> ClientTlsConfig {
+            ClientTlsConfig {
+                ca_cert_file: self.path("ca.crt"),
+                cert_file: cert.map(|cert| self.path(&format!("{}.crt", cert))),
+                key_file: cert.map(|cert| self.path(&format!("{}.key", cert))),
+                server_name: Some("localhost".to_string()),
+            }
+        }
+    }
+
+    impl Drop for Certs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Serves one PING over TLS, returning whether the client got its PONG.
+    async fn ping(server: &ServerConfig, client: &ClientTlsConfig) -> bool {
+        let acceptor = TlsAcceptor::from(tls_server_config(server).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut conn: Connection = Connection::new(acceptor.accept(socket).await.ok()?);
+            conn.read_frame().await.ok()??;
+            conn.write_frame(&Frame::Simple("PONG".to_string())).await.ok()
+        });
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let connector = client.connector().unwrap();
+        let Ok(stream) = connector.connect(client.server_name("127.0.0.1").unwrap(), socket).await else { return false };
+        let mut conn = Connection::<Stream>::new(stream);
+        let mut ping = Frame::array();
+        ping.push_bulk("ping".into());
+        let reply = match conn.write_frame(&ping).await {
+            Ok(_) => conn.read_frame().await.ok().flatten(),
+            Err(_) => None,
+        };
+        let _ = server.await;
+        matches!(reply, Some(frame) if frame == "PONG")
+    }
+
+    #[tokio::test]
+    async fn server_auth() {
+        let certs = Certs::generate("server-auth");
+        assert!(ping(&certs.server_config("no"), &certs.client_config(None)).await);
+        // the client does not trust a server outside its CA
+        let mut untrusted = certs.client_config(None);
+        untrusted.ca_cert_file = certs.path("rogue.crt");
+        assert!(!ping(&certs.server_config("no"), &untrusted).await);
+        let mut wrong_name = certs.client_config(None);
+        wrong_name.server_name = Some("example.com".to_string());
+        assert!(!ping(&certs.server_config("no"), &wrong_name).await);
+    }
+
+    #[tokio::test]
+    async fn client_auth() {
+        let certs = Certs::generate("client-auth");
+        assert!(ping(&certs.server_config("yes"), &certs.client_config(Some("client"))).await);
+        assert!(!ping(&certs.server_config("yes"), &certs.client_config(None)).await);
+        assert!(!ping(&certs.server_config("yes"), &certs.client_config(Some("rogue"))).await);
+        assert!(ping(&certs.server_config("optional"), &certs.client_config(None)).await);
+        assert!(!ping(&certs.server_config("optional"), &certs.client_config(Some("rogue"))).await);
+        assert!(tls_server_config(&ServerConfig { tls_ca_cert_file: None, ..certs.server_config("yes") }).is_err());
+    }
+}
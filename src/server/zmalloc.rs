@@ -0,0 +1,108 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Bytes currently allocated through the global allocator
+static USED_MEMORY: AtomicUsize = AtomicUsize::new(0);
+/// The highest `USED_MEMORY` seen since start or the last reset
+static PEAK_MEMORY: AtomicUsize = AtomicUsize::new(0);
+/// Whether `CountingAllocator` is the global allocator, set by its first allocation
+static COUNTING: AtomicBool = AtomicBool::new(false);
+
+/// The system allocator, counting every allocation so the server can report how
+/// much memory it uses. The binary installs it with `#[global_allocator]`.
+pub struct CountingAllocator;
+
+#[inline]
+fn update_allocated(size: usize) {
+    if !COUNTING.load(Ordering::Relaxed) {
+        COUNTING.store(true, Ordering::Relaxed);
+    }
+    let used = USED_MEMORY.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_MEMORY.fetch_max(used, Ordering::Relaxed);
+}
+
+#[inline]
+fn update_freed(size: usize) {
+    USED_MEMORY.fetch_sub(size, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            update_allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        update_freed(layout.size());
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            update_allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            update_freed(layout.size());
+            update_allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Bytes allocated by the process. Without `CountingAllocator` installed, e.g. in
+/// tests, the resident set size stands in for it.
+pub fn used_memory() -> usize {
+    if COUNTING.load(Ordering::Relaxed) {
+        return USED_MEMORY.load(Ordering::Relaxed);
+    }
+    let used = rss_memory().unwrap_or(0);
+    PEAK_MEMORY.fetch_max(used, Ordering::Relaxed);
+    used
+}
+
+pub fn peak_memory() -> usize {
+    PEAK_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Restarts peak tracking from the current usage (MEMORY STATS after CONFIG RESETSTAT).
+pub fn reset_peak_memory() {
+    PEAK_MEMORY.store(used_memory(), Ordering::Relaxed);
+}
+
+/// Resident set size of the process as reported by the kernel, `None` where it
+/// can't be read.
+#[cfg(target_os = "linux")]
+pub fn rss_memory() -> Option<usize> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size.max(0) as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn rss_memory() -> Option<usize> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::server::zmalloc::{peak_memory, used_memory};
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn used_memory_without_counting_allocator() {
+        // the test binary runs on the system allocator
+        let used = used_memory();
+        assert!(used > 0);
+        assert!(peak_memory() >= used);
+    }
+}
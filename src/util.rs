@@ -0,0 +1,265 @@
+use std::fmt::Write;
+
+/// Glob-style pattern matching, as in Redis `stringmatchlen`. Supports `*`, `?`,
+/// `[...]` (with `^` negation and `a-z` ranges) and `\` escapes.
+pub fn string_match(pattern: &str, string: &str, nocase: bool) -> bool {
+    string_match_len(pattern.as_bytes(), string.as_bytes(), nocase, 0)
+}
+
+fn string_match_len(mut p: &[u8], mut s: &[u8], nocase: bool, nesting: usize) -> bool {
+    // protect against abusive patterns such as a*a*a*a*...b
+    if nesting > 1000 {
+        return false;
+    }
+    let eq = |a: u8, b: u8| if nocase { a.eq_ignore_ascii_case(&b) } else { a == b };
+    while !p.is_empty() && !s.is_empty() {
+        match p[0] {
+            b'*' => {
+                while p.len() > 1 && p[1] == b'*' {
+                    p = &p[1..];
+                }
+                if p.len() == 1 {
+                    return true;
+                }
+                for i in 0..s.len() {
+                    if string_match_len(&p[1..], &s[i..], nocase, nesting + 1) {
+                        return true;
+                    }
+                }
+                return false;
+            }
+            b'?' => s = &s[1..],
+            b'[' => {
+                p = &p[1..];
+                let not = p.first() == Some(&b'^');
+                if not {
+                    p = &p[1..];
+                }
+                let mut matched = false;
+                loop {
+                    if p.is_empty() {
+                        break;
+                    }
+                    if p[0] == b'\\' && p.len() >= 2 {
+                        p = &p[1..];
+                        if eq(p[0], s[0]) {
+                            matched = true;
+                        }
+                    } else if p[0] == b']' {
+                        break;
+                    } else if p.len() >= 3 && p[1] == b'-' {
+                        let (mut start, mut end, mut c) = (p[0], p[2], s[0]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+                        if nocase {
+                            start = start.to_ascii_lowercase();
+                            end = end.to_ascii_lowercase();
+                            c = c.to_ascii_lowercase();
+                        }
+                        p = &p[2..];
+                        if c >= start && c <= end {
+                            matched = true;
+                        }
+                    } else if eq(p[0], s[0]) {
+                        matched = true;
+                    }
+                    p = &p[1..];
+                }
+                if not {
+                    matched = !matched;
+                }
+                if !matched {
+                    return false;
+                }
+                s = &s[1..];
+                // the pattern ended before the closing bracket
+                if p.is_empty() {
+                    return s.is_empty();
+                }
+            }
+            b'\\' if p.len() >= 2 => {
+                p = &p[1..];
+                if !eq(p[0], s[0]) {
+                    return false;
+                }
+                s = &s[1..];
+            }
+            c => {
+                if !eq(c, s[0]) {
+                    return false;
+                }
+                s = &s[1..];
+            }
+        }
+        p = &p[1..];
+    }
+    if s.is_empty() {
+        while p.first() == Some(&b'*') {
+            p = &p[1..];
+        }
+    }
+    p.is_empty() && s.is_empty()
+}
+
+/// Parses a memory amount such as "100", "1k", "1kb", "5mb" or "2gb" into bytes, as in
+/// Redis `memtoull`. "k", "m" and "g" are powers of 1000, "kb", "mb" and "gb" of 1024.
+pub fn memtoull(s: &str) -> Option<u64> {
+    let lower = s.to_ascii_lowercase();
+    let split = lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len());
+    let (digits, unit) = lower.split_at(split);
+    let mul = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(mul)
+}
+
+/// Parses a "yes" or "no" setting, as in Redis `yesnotoi`.
+pub fn yesnotoi(s: &str) -> Option<bool> {
+    match &s.to_ascii_lowercase()[..] {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Formats a byte count the way INFO does, e.g. "1.50M".
+pub fn bytes_to_human(n: u64) -> String {
+    const UNITS: [(&str, u64); 5] = [
+        ("P", 1 << 50),
+        ("T", 1 << 40),
+        ("G", 1 << 30),
+        ("M", 1 << 20),
+        ("K", 1 << 10),
+    ];
+    for (unit, size) in UNITS {
+        if n >= size {
+            return format!("{:.2}{}", n as f64 / size as f64, unit);
+        }
+    }
+    format!("{}B", n)
+}
+
+/// Hex SHA-256 digest of `data`, as ACL passwords are stored.
+pub fn sha256_hex(data: &[u8]) -> String {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    // padding: a 1 bit, zeros, then the length in bits on 64 bits
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    h.iter().fold(String::with_capacity(64), |mut hex, word| {
+        let _ = write!(hex, "{:08x}", word);
+        hex
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bytes_to_human, memtoull, sha256_hex, string_match};
+
+    #[test]
+    fn glob() {
+        assert!(string_match("*", "anything", false));
+        assert!(string_match("*", "", false));
+        assert!(string_match("news.*", "news.sport", false));
+        assert!(!string_match("news.*", "new.sport", false));
+        assert!(string_match("h?llo", "hello", false));
+        assert!(!string_match("h?llo", "hllo", false));
+        assert!(string_match("h[ae]llo", "hallo", false));
+        assert!(!string_match("h[ae]llo", "hillo", false));
+        assert!(string_match("h[^e]llo", "hallo", false));
+        assert!(!string_match("h[^e]llo", "hello", false));
+        assert!(string_match("h[a-b]llo", "hbllo", false));
+        assert!(string_match("h\\*llo", "h*llo", false));
+        assert!(!string_match("h\\*llo", "hello", false));
+        assert!(string_match("HELLO*", "hello world", true));
+        assert!(!string_match("HELLO*", "hello world", false));
+        assert!(string_match("a*b*c", "axxbyyc", false));
+        assert!(!string_match("a*b*c", "axxbyy", false));
+    }
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(memtoull("0"), Some(0));
+        assert_eq!(memtoull("100"), Some(100));
+        assert_eq!(memtoull("1k"), Some(1000));
+        assert_eq!(memtoull("1KB"), Some(1024));
+        assert_eq!(memtoull("5mb"), Some(5 * 1024 * 1024));
+        assert_eq!(memtoull("2g"), Some(2_000_000_000));
+        assert_eq!(memtoull("mb"), None);
+        assert_eq!(memtoull("10tb"), None);
+        assert_eq!(memtoull("-1"), None);
+    }
+
+    #[test]
+    fn human_bytes() {
+        assert_eq!(bytes_to_human(1000), "1000B");
+        assert_eq!(bytes_to_human(1536), "1.50K");
+        assert_eq!(bytes_to_human(3 * 1024 * 1024), "3.00M");
+    }
+
+    #[test]
+    fn sha256() {
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // two blocks once padded
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+}
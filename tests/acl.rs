@@ -0,0 +1,76 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::Error(err) => err,
+        frame => panic!("expected an error, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn requirepass_needs_auth() {
+    let server = TestServer::start(&["--requirepass", "secret"]);
+    let mut client = server.client().await;
+    assert!(error(command(&mut client, &["GET", "k"]).await).starts_with("NOAUTH"));
+    assert!(error(command(&mut client, &["AUTH", "wrong"]).await).starts_with("WRONGPASS"));
+    assert_eq!(command(&mut client, &["AUTH", "secret"]).await, "OK");
+    assert!(matches!(command(&mut client, &["GET", "k"]).await, Frame::Null));
+    // RESET goes back to an unauthenticated default user
+    assert_eq!(command(&mut client, &["RESET"]).await, "RESET");
+    assert!(error(command(&mut client, &["GET", "k"]).await).starts_with("NOAUTH"));
+}
+
+#[tokio::test]
+async fn users_are_limited_to_their_keys_and_commands() {
+    let server = TestServer::start(&[]);
+    let mut admin = server.client().await;
+    let rules = ["SETUSER", "alice", "on", ">pw", "~cache:*", "+@read", "+set", "+acl|whoami"];
+    assert_eq!(command(&mut admin, &[&["ACL"], &rules[..]].concat()).await, "OK");
+
+    let mut alice = server.client().await;
+    assert_eq!(command(&mut alice, &["AUTH", "alice", "pw"]).await, "OK");
+    assert_eq!(command(&mut alice, &["ACL", "WHOAMI"]).await, "alice");
+    assert_eq!(command(&mut alice, &["SET", "cache:1", "v"]).await, "OK");
+    assert_eq!(command(&mut alice, &["GET", "cache:1"]).await, "v");
+    assert_eq!(error(command(&mut alice, &["GET", "other"]).await), "NOPERM No permissions to access a key");
+    assert_eq!(error(command(&mut alice, &["DEL", "cache:1"]).await), "NOPERM User alice has no permissions to run the 'del' command");
+
+    match command(&mut admin, &["ACL", "LOG"]).await {
+        Frame::Array(entries) => {
+            let reasons: Vec<(String, String)> = entries
+                .iter()
+                .map(|entry| (entry.get_frame_by_index(3).unwrap().to_string(), entry.get_frame_by_index(7).unwrap().to_string()))
+                .collect();
+            // newest first
+            assert_eq!(reasons, [("command".to_string(), "del".to_string()), ("key".to_string(), "other".to_string())]);
+        }
+        frame => panic!("unexpected ACL LOG reply {:?}", frame),
+    }
+
+    // removing the user closes its connections
+    assert!(matches!(command(&mut admin, &["ACL", "DELUSER", "alice"]).await, Frame::Integer(1)));
+    let frame = Frame::Array(vec![Frame::Bulk("PING".into())]);
+    let _ = alice.conn.write_frame(&frame).await;
+    assert!(matches!(alice.conn.read_frame().await, Ok(None) | Err(_)));
+}
+
+#[tokio::test]
+async fn aclfile_without_default_user_keeps_requirepass() {
+    let path = std::env::temp_dir().join(format!("tinyredis-test-{}.acl", std::process::id()));
+    std::fs::write(&path, "user bob on >bobpw ~* +@all\n").unwrap();
+    let server = TestServer::start(&["--requirepass", "secret", "--aclfile", path.to_str().unwrap()]);
+    let mut client = server.client().await;
+    assert!(error(command(&mut client, &["GET", "k"]).await).starts_with("NOAUTH"));
+    assert_eq!(command(&mut client, &["AUTH", "secret"]).await, "OK");
+    assert_eq!(command(&mut client, &["AUTH", "bob", "bobpw"]).await, "OK");
+
+    // and so does ACL LOAD
+    assert_eq!(command(&mut client, &["ACL", "LOAD"]).await, "OK");
+    let mut client = server.client().await;
+    assert!(error(command(&mut client, &["GET", "k"]).await).starts_with("NOAUTH"));
+    assert_eq!(command(&mut client, &["AUTH", "secret"]).await, "OK");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,21 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+#[tokio::test]
+async fn invalid_arguments_reply_an_error() {
+    let server = TestServer::start(&[]);
+    let mut client = server.client().await;
+    match command(&mut client, &["BLPOP", "q", "notanumber"]).await {
+        Frame::Error(err) => assert_eq!(err, "ERR timeout is not a float or out of range"),
+        frame => panic!("unexpected BLPOP reply {:?}", frame),
+    }
+    match command(&mut client, &["BZPOPMIN", "z", "-1"]).await {
+        Frame::Error(err) => assert_eq!(err, "ERR timeout is negative"),
+        frame => panic!("unexpected BZPOPMIN reply {:?}", frame),
+    }
+    assert!(matches!(command(&mut client, &["CLIENT", "UNBLOCK", "abc"]).await, Frame::Error(_)));
+    // still the same connection
+    assert_eq!(command(&mut client, &["PING"]).await, "pong");
+}
@@ -0,0 +1,119 @@
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use redis_rs::client::client::Client;
+use redis_rs::parser::frame::Frame;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A redis_server process, running in its own temporary directory and killed on drop.
+pub struct TestServer {
+    child: Child,
+    pub port: u16,
+    pub dir: PathBuf,
+    args: Vec<String>,
+}
+
+impl TestServer {
+    /// Starts a server on a free localhost port with `args` as extra flags. Nothing is
+    /// saved unless `args` sets `--save`.
+    pub fn start(args: &[&str]) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "tinyredis-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let port = free_port();
+        let child = spawn(&dir, port, &args);
+        let server = Self { child, port, dir, args };
+        server.wait_ready();
+        server
+    }
+
+    /// Starts the server again in the same directory, once the previous process exited.
+    pub fn restart(&mut self) {
+        self.wait_exit();
+        self.port = free_port();
+        self.child = spawn(&self.dir, self.port, &self.args);
+        self.wait_ready();
+    }
+
+    pub fn addr(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub async fn client(&self) -> Client {
+        Client::connect(self.addr()).await.unwrap()
+    }
+
+    /// Waits for the process to exit, e.g. after SHUTDOWN.
+    pub fn wait_exit(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.child.try_wait().unwrap().is_none() {
+            assert!(Instant::now() < deadline, "server did not exit");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn wait_ready(&self) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while std::net::TcpStream::connect(self.addr()).is_err() {
+            assert!(Instant::now() < deadline, "server did not start on port {}", self.port);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn spawn(dir: &Path, port: u16, args: &[String]) -> Child {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_redis_server"));
+    cmd.current_dir(dir)
+        .args(["--bind", "127.0.0.1", "--port", &port.to_string()])
+        .args(["--dir", dir.to_str().unwrap()]);
+    if !args.iter().any(|arg| arg == "--save") {
+        cmd.args(["--save", ""]);
+    }
+    cmd.args(args).stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap()
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Sends `args` as a command and returns the reply.
+pub async fn command(client: &mut Client, args: &[&str]) -> Frame {
+    let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect());
+    client.conn.write_frame(&frame).await.unwrap();
+    client.conn.read_frame().await.unwrap().expect("connection closed")
+}
+
+/// Waits for the next message pushed to a subscribed client, as strings.
+pub async fn next_message(client: &mut Client) -> Vec<String> {
+    let frame = tokio::time::timeout(Duration::from_secs(5), client.conn.read_frame())
+        .await
+        .expect("no message")
+        .unwrap();
+    match frame {
+        Some(Frame::Array(parts)) => parts.iter().map(|part| part.to_string()).collect(),
+        frame => panic!("unexpected message {:?}", frame),
+    }
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::client::client::Client;
+use redis_rs::parser::frame::Frame;
+
+async fn config_get(client: &mut Client, pattern: &str) -> Vec<String> {
+    match command(client, &["CONFIG", "GET", pattern]).await {
+        Frame::Array(pairs) => pairs.iter().map(|part| part.to_string()).collect(),
+        frame => panic!("unexpected CONFIG GET reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn set_applies_every_parameter_or_none() {
+    let server = TestServer::start(&[]);
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["CONFIG", "SET", "maxmemory", "1mb", "maxmemory-policy", "allkeys-lru"]).await, "OK");
+    assert_eq!(config_get(&mut client, "maxmemory").await, ["maxmemory", "1048576"]);
+    assert_eq!(config_get(&mut client, "maxmemory-p*").await, ["maxmemory-policy", "allkeys-lru"]);
+
+    // one bad value and nothing changes
+    let reply = command(&mut client, &["CONFIG", "SET", "maxmemory", "2mb", "maxmemory-policy", "bogus"]).await;
+    assert!(matches!(reply, Frame::Error(_)), "{:?}", reply);
+    assert_eq!(config_get(&mut client, "maxmemory").await, ["maxmemory", "1048576"]);
+    assert_eq!(config_get(&mut client, "maxmemory-policy").await, ["maxmemory-policy", "allkeys-lru"]);
+    assert!(matches!(command(&mut client, &["CONFIG", "SET", "nosuchparam", "1"]).await, Frame::Error(_)));
+}
+
+#[tokio::test]
+async fn resetstat_zeroes_the_counters() {
+    let server = TestServer::start(&[]);
+    let mut client = server.client().await;
+    for _ in 0..5 {
+        command(&mut client, &["GET", "k"]).await;
+    }
+    let misses = |info: String| info.lines().find_map(|line| line.strip_prefix("keyspace_misses:").map(str::to_string)).unwrap();
+    assert_eq!(misses(command(&mut client, &["INFO", "stats"]).await.to_string()), "5");
+    assert_eq!(command(&mut client, &["CONFIG", "RESETSTAT"]).await, "OK");
+    assert_eq!(misses(command(&mut client, &["INFO", "stats"]).await.to_string()), "0");
+}
+
+#[tokio::test]
+async fn rewrite_keeps_the_file_and_survives_a_restart() {
+    let path = std::env::temp_dir().join(format!("tinyredis-test-{}.toml", std::process::id()));
+    std::fs::write(&path, "# cache node\nmaxmemory = 1048576\n").unwrap();
+    let mut server = TestServer::start(&["--config", path.to_str().unwrap()]);
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["CONFIG", "SET", "maxmemory", "2mb"]).await, "OK");
+    assert_eq!(command(&mut client, &["CONFIG", "REWRITE"]).await, "OK");
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.starts_with("# cache node\nmaxmemory = 2097152\n"), "{}", content);
+
+    let frame = Frame::Array(vec![Frame::Bulk("SHUTDOWN".into()), Frame::Bulk("NOSAVE".into())]);
+    client.conn.write_frame(&frame).await.unwrap();
+    server.restart();
+    let mut client = server.client().await;
+    assert_eq!(config_get(&mut client, "maxmemory").await, ["maxmemory", "2097152"]);
+    std::fs::remove_file(&path).unwrap();
+}
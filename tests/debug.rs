@@ -0,0 +1,36 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+#[tokio::test]
+async fn reload_keeps_the_dataset() {
+    let server = TestServer::start(&[]);
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["SET", "k", "v"]).await, "OK");
+    command(&mut client, &["EXPIRE", "k", "100"]).await;
+    command(&mut client, &["SADD", "s", "a", "b"]).await;
+
+    assert_eq!(command(&mut client, &["DEBUG", "RELOAD"]).await, "OK");
+    assert_eq!(command(&mut client, &["GET", "k"]).await, "v");
+    assert!(matches!(command(&mut client, &["TTL", "k"]).await, Frame::Integer(1..=100)));
+    assert!(matches!(command(&mut client, &["SCARD", "s"]).await, Frame::Integer(2)));
+    assert_eq!(command(&mut client, &["OBJECT", "ENCODING", "s"]).await, "listpack");
+}
+
+#[tokio::test]
+async fn sleep_only_holds_the_calling_client() {
+    let server = TestServer::start(&[]);
+    let mut sleeper = server.client().await;
+    let mut other = server.client().await;
+
+    let start = Instant::now();
+    let sleep = tokio::spawn(async move { command(&mut sleeper, &["DEBUG", "SLEEP", "0.5"]).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(command(&mut other, &["PING"]).await, "pong");
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert_eq!(sleep.await.unwrap(), "OK");
+    assert!(start.elapsed() >= Duration::from_millis(500));
+}
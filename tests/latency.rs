@@ -0,0 +1,25 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+#[tokio::test]
+async fn histogram_counts_the_calls_of_a_command() {
+    let server = TestServer::start(&[]);
+    let mut client = server.client().await;
+    for value in ["1", "2"] {
+        assert_eq!(command(&mut client, &["SET", "k", value]).await, "OK");
+    }
+    match command(&mut client, &["LATENCY", "HISTOGRAM", "set"]).await {
+        Frame::Array(reply) => match &reply[..] {
+            [name, Frame::Array(stats)] => {
+                assert_eq!(*name, "set");
+                assert!(matches!(stats[1], Frame::Integer(2)));
+            }
+            reply => panic!("unexpected LATENCY HISTOGRAM reply {:?}", reply),
+        },
+        frame => panic!("unexpected LATENCY HISTOGRAM reply {:?}", frame),
+    }
+    // nothing reached a threshold with the monitor off
+    assert!(matches!(command(&mut client, &["LATENCY", "LATEST"]).await, Frame::Array(events) if events.is_empty()));
+}
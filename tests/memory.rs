@@ -0,0 +1,48 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::client::client::Client;
+use redis_rs::parser::frame::Frame;
+
+/// The integer fields of MEMORY STATS
+async fn memory_stats(client: &mut Client) -> Vec<(String, i64)> {
+    match command(client, &["MEMORY", "STATS"]).await {
+        Frame::Array(items) => items
+            .chunks(2)
+            .filter_map(|pair| match &pair[1] {
+                Frame::Integer(value) => Some((pair[0].to_string(), *value)),
+                _ => None,
+            })
+            .collect(),
+        frame => panic!("unexpected MEMORY STATS reply {:?}", frame),
+    }
+}
+
+fn stat(stats: &[(String, i64)], name: &str) -> i64 {
+    stats.iter().find(|(field, _)| field == name).unwrap_or_else(|| panic!("no {} in MEMORY STATS", name)).1
+}
+
+#[tokio::test]
+async fn stats_follow_the_dataset() {
+    let server = TestServer::start(&[]);
+    let mut client = server.client().await;
+    let before = memory_stats(&mut client).await;
+    assert_eq!(stat(&before, "keys.count"), 0);
+    // the server binary counts its allocations
+    assert!(stat(&before, "total.allocated") > 0);
+
+    let value = "v".repeat(100_000);
+    assert_eq!(command(&mut client, &["SET", "big", &value]).await, "OK");
+    match command(&mut client, &["MEMORY", "USAGE", "big"]).await {
+        Frame::Integer(size) => assert!(size >= 100_000),
+        frame => panic!("unexpected MEMORY USAGE reply {:?}", frame),
+    }
+    let after = memory_stats(&mut client).await;
+    assert_eq!(stat(&after, "keys.count"), 1);
+    assert!(stat(&after, "dataset.bytes") - stat(&before, "dataset.bytes") >= 100_000);
+
+    match command(&mut client, &["MEMORY", "DOCTOR"]).await {
+        Frame::Bulk(report) => assert!(!report.is_empty()),
+        frame => panic!("unexpected MEMORY DOCTOR reply {:?}", frame),
+    }
+}
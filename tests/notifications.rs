@@ -0,0 +1,39 @@
+mod common;
+
+use common::{command, next_message, TestServer};
+
+#[tokio::test]
+async fn writes_publish_keyspace_events() {
+    let server = TestServer::start(&[]);
+    let mut subscriber = server.client().await;
+    let mut client = server.client().await;
+    command(&mut subscriber, &["PSUBSCRIBE", "__key*__:*"]).await;
+
+    // nothing is published while notifications are off
+    assert_eq!(command(&mut client, &["SET", "before", "v"]).await, "OK");
+    assert_eq!(command(&mut client, &["CONFIG", "SET", "notify-keyspace-events", "KEA"]).await, "OK");
+    assert_eq!(command(&mut client, &["SET", "k", "v"]).await, "OK");
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "__key*__:*", "__keyspace@0__:k", "set"]);
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "__key*__:*", "__keyevent@0__:set", "k"]);
+
+    command(&mut client, &["HSET", "h", "f", "v"]).await;
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "__key*__:*", "__keyspace@0__:h", "hset"]);
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "__key*__:*", "__keyevent@0__:hset", "h"]);
+
+    command(&mut client, &["DEL", "k"]).await;
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "__key*__:*", "__keyspace@0__:k", "del"]);
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "__key*__:*", "__keyevent@0__:del", "k"]);
+}
+
+#[tokio::test]
+async fn expiry_publishes_expired_events() {
+    let server = TestServer::start(&["--notify-keyspace-events", "Ex"]);
+    let mut subscriber = server.client().await;
+    let mut client = server.client().await;
+    command(&mut subscriber, &["SUBSCRIBE", "__keyevent@0__:expired"]).await;
+
+    // only the expired class is on, SET and PEXPIRE stay silent
+    assert_eq!(command(&mut client, &["SET", "session", "v"]).await, "OK");
+    command(&mut client, &["PEXPIRE", "session", "50"]).await;
+    assert_eq!(next_message(&mut subscriber).await, ["message", "__keyevent@0__:expired", "session"]);
+}
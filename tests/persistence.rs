@@ -0,0 +1,24 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+#[tokio::test]
+async fn shutdown_saves_every_db() {
+    let mut server = TestServer::start(&["--databases", "2"]);
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["SET", "k0", "v0"]).await, "OK");
+    command(&mut client, &["SELECT", "1"]).await;
+    assert_eq!(command(&mut client, &["SET", "k1", "v1"]).await, "OK");
+    // the server exits without replying
+    let frame = Frame::Array(vec![Frame::Bulk("SHUTDOWN".into()), Frame::Bulk("SAVE".into())]);
+    client.conn.write_frame(&frame).await.unwrap();
+    server.restart();
+
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["GET", "k0"]).await, "v0");
+    assert!(matches!(command(&mut client, &["GET", "k1"]).await, Frame::Null));
+    command(&mut client, &["SELECT", "1"]).await;
+    assert_eq!(command(&mut client, &["GET", "k1"]).await, "v1");
+    assert!(matches!(command(&mut client, &["GET", "k0"]).await, Frame::Null));
+}
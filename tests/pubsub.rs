@@ -0,0 +1,70 @@
+mod common;
+
+use common::{command, next_message, TestServer};
+use redis_rs::parser::frame::Frame;
+
+#[tokio::test]
+async fn subscribers_receive_published_messages() {
+    let server = TestServer::start(&[]);
+    let mut subscriber = server.client().await;
+    let mut publisher = server.client().await;
+
+    match command(&mut subscriber, &["SUBSCRIBE", "news.sport"]).await {
+        Frame::Array(reply) => assert!(matches!(reply[..], [_, _, Frame::Integer(1)])),
+        frame => panic!("unexpected SUBSCRIBE reply {:?}", frame),
+    }
+    match command(&mut subscriber, &["PSUBSCRIBE", "news.*"]).await {
+        Frame::Array(reply) => assert!(matches!(reply[..], [_, _, Frame::Integer(2)])),
+        frame => panic!("unexpected PSUBSCRIBE reply {:?}", frame),
+    }
+    // a subscriber may only run pub/sub commands
+    assert!(matches!(command(&mut subscriber, &["GET", "k"]).await, Frame::Error(_)));
+
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "news.sport", "goal"]).await, Frame::Integer(2)));
+    assert_eq!(next_message(&mut subscriber).await, ["message", "news.sport", "goal"]);
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "news.*", "news.sport", "goal"]);
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "news.weather", "rain"]).await, Frame::Integer(1)));
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "news.*", "news.weather", "rain"]);
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "other", "x"]).await, Frame::Integer(0)));
+
+    match command(&mut publisher, &["PUBSUB", "NUMSUB", "news.sport", "other"]).await {
+        Frame::Array(reply) => assert_eq!(reply.iter().map(|part| part.to_string()).collect::<Vec<_>>(), ["news.sport", "1", "other", "0"]),
+        frame => panic!("unexpected PUBSUB NUMSUB reply {:?}", frame),
+    }
+    assert!(matches!(command(&mut publisher, &["PUBSUB", "NUMPAT"]).await, Frame::Integer(1)));
+
+    // the pattern subscription still delivers once the channel is dropped
+    command(&mut subscriber, &["UNSUBSCRIBE", "news.sport"]).await;
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "news.sport", "final"]).await, Frame::Integer(1)));
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "news.*", "news.sport", "final"]);
+}
+
+#[tokio::test]
+async fn shard_channels_are_not_matched_by_patterns() {
+    let server = TestServer::start(&[]);
+    let mut subscriber = server.client().await;
+    let mut publisher = server.client().await;
+
+    command(&mut subscriber, &["SSUBSCRIBE", "orders"]).await;
+    command(&mut subscriber, &["PSUBSCRIBE", "*"]).await;
+    assert!(matches!(command(&mut publisher, &["SPUBLISH", "orders", "created"]).await, Frame::Integer(1)));
+    assert_eq!(next_message(&mut subscriber).await, ["smessage", "orders", "created"]);
+    // PUBLISH does not reach the shard subscriber, only the pattern
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "orders", "created"]).await, Frame::Integer(1)));
+    assert_eq!(next_message(&mut subscriber).await, ["pmessage", "*", "orders", "created"]);
+}
+
+#[tokio::test]
+async fn reset_leaves_subscriber_mode() {
+    let server = TestServer::start(&[]);
+    let mut subscriber = server.client().await;
+    let mut publisher = server.client().await;
+
+    command(&mut subscriber, &["SUBSCRIBE", "news"]).await;
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "news", "hello"]).await, Frame::Integer(1)));
+    assert_eq!(next_message(&mut subscriber).await, ["message", "news", "hello"]);
+
+    assert_eq!(command(&mut subscriber, &["RESET"]).await, "RESET");
+    assert!(matches!(command(&mut subscriber, &["GET", "k"]).await, Frame::Null));
+    assert!(matches!(command(&mut publisher, &["PUBLISH", "news", "hello"]).await, Frame::Integer(0)));
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{command, TestServer};
+use redis_rs::client::client::Client;
+use redis_rs::parser::frame::Frame;
+
+/// Polls `key` on `client` until it holds `value`.
+async fn wait_for_value(client: &mut Client, key: &str, value: &str) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if command(client, &["GET", key]).await == value {
+            return;
+        }
+        assert!(Instant::now() < deadline, "{} never reached the replica", key);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+async fn pttl(client: &mut Client, key: &str) -> i64 {
+    match command(client, &["PTTL", key]).await {
+        Frame::Integer(ttl) => ttl,
+        frame => panic!("unexpected PTTL reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn replica_follows_primary() {
+    let primary = TestServer::start(&[]);
+    let mut client = primary.client().await;
+    // sent to the replica in the RDB of the full resync
+    assert_eq!(command(&mut client, &["SET", "before", "v1"]).await, "OK");
+    assert_eq!(command(&mut client, &["SETEX", "before-ttl", "100", "v2"]).await, "OK");
+
+    let replicaof = format!("127.0.0.1 {}", primary.port);
+    let replica = TestServer::start(&["--replicaof", &replicaof]);
+    let mut replica_client = replica.client().await;
+    wait_for_value(&mut replica_client, "before", "v1").await;
+    wait_for_value(&mut replica_client, "before-ttl", "v2").await;
+    let ttl = pttl(&mut replica_client, "before-ttl").await;
+    assert!(ttl > 90_000 && ttl <= 100_000, "ttl {}", ttl);
+    assert_eq!(pttl(&mut replica_client, "before").await, -1);
+
+    // propagated as commands once the replica is online
+    assert_eq!(command(&mut client, &["SET", "after", "v3"]).await, "OK");
+    assert_eq!(command(&mut client, &["SETPX", "after-ttl", "100000", "v4"]).await, "OK");
+    wait_for_value(&mut replica_client, "after", "v3").await;
+    wait_for_value(&mut replica_client, "after-ttl", "v4").await;
+    let ttl = pttl(&mut replica_client, "after-ttl").await;
+    assert!(ttl > 90_000 && ttl <= 100_000, "ttl {}", ttl);
+
+    // relative TTLs reach the replica as the absolute time the primary set
+    assert!(matches!(command(&mut client, &["EXPIRE", "after", "50"]).await, Frame::Integer(1)));
+    assert_eq!(command(&mut client, &["SET", "set-ex", "v5", "EX", "50"]).await, "OK");
+    wait_for_value(&mut replica_client, "set-ex", "v5").await;
+    for key in ["after", "set-ex"] {
+        let primary_ttl = pttl(&mut client, key).await;
+        let replica_ttl = pttl(&mut replica_client, key).await;
+        assert!(replica_ttl > 0 && replica_ttl <= primary_ttl, "{}: {} on the replica, {} on the primary", key, replica_ttl, primary_ttl);
+    }
+}
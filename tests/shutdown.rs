@@ -0,0 +1,40 @@
+mod common;
+
+use std::process::Command;
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+#[tokio::test]
+async fn nosave_exits_without_saving() {
+    let mut server = TestServer::start(&["--save", "3600 1", "--pidfile", "redis.pid"]);
+    let pidfile = server.dir.join("redis.pid");
+    let mut client = server.client().await;
+    assert_eq!(std::fs::read_to_string(&pidfile).unwrap(), format!("{}\n", server.pid()));
+    match command(&mut client, &["SHUTDOWN", "ABORT"]).await {
+        Frame::Error(err) => assert_eq!(err, "ERR No shutdown in progress."),
+        frame => panic!("unexpected SHUTDOWN ABORT reply {:?}", frame),
+    }
+    assert_eq!(command(&mut client, &["SET", "k", "v"]).await, "OK");
+
+    let frame = Frame::Array(vec![Frame::Bulk("SHUTDOWN".into()), Frame::Bulk("NOSAVE".into())]);
+    client.conn.write_frame(&frame).await.unwrap();
+    server.wait_exit();
+    assert!(!pidfile.exists());
+    server.restart();
+    let mut client = server.client().await;
+    assert!(matches!(command(&mut client, &["GET", "k"]).await, Frame::Null));
+}
+
+#[tokio::test]
+async fn sigterm_saves_before_exiting() {
+    let mut server = TestServer::start(&["--save", "3600 1"]);
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["SET", "k", "v"]).await, "OK");
+
+    let status = Command::new("kill").args(["-TERM", &server.pid().to_string()]).status().unwrap();
+    assert!(status.success());
+    server.restart();
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["GET", "k"]).await, "v");
+}
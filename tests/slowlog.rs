@@ -0,0 +1,69 @@
+mod common;
+
+use common::{command, TestServer};
+use redis_rs::parser::frame::Frame;
+
+fn integer(frame: Frame) -> i64 {
+    match frame {
+        Frame::Integer(value) => value,
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn records_only_commands_over_the_threshold() {
+    let server = TestServer::start(&["--slowlog-log-slower-than", "10000000"]);
+    let mut client = server.client().await;
+    assert_eq!(command(&mut client, &["CLIENT", "SETNAME", "worker"]).await, "OK");
+    assert_eq!(command(&mut client, &["SET", "k", "v"]).await, "OK");
+    assert_eq!(integer(command(&mut client, &["SLOWLOG", "LEN"]).await), 0);
+
+    // every command is over a threshold of 0
+    assert_eq!(command(&mut client, &["CONFIG", "SET", "slowlog-log-slower-than", "0"]).await, "OK");
+    assert_eq!(command(&mut client, &["SET", "k", "v2"]).await, "OK");
+    assert_eq!(integer(command(&mut client, &["SLOWLOG", "LEN"]).await), 1);
+    match command(&mut client, &["SLOWLOG", "GET"]).await {
+        Frame::Array(entries) => match &entries[..] {
+            [Frame::Array(entry)] => {
+                match &entry[3] {
+                    Frame::Array(argv) => assert_eq!(argv.iter().map(|arg| arg.to_string()).collect::<Vec<_>>(), ["SET", "k", "v2"]),
+                    frame => panic!("unexpected arguments {:?}", frame),
+                }
+                assert_eq!(entry[5], "worker");
+            }
+            entries => panic!("unexpected entries {:?}", entries),
+        },
+        frame => panic!("unexpected SLOWLOG GET reply {:?}", frame),
+    }
+
+    // and a negative one turns the log off
+    assert_eq!(command(&mut client, &["CONFIG", "SET", "slowlog-log-slower-than", "-1"]).await, "OK");
+    assert_eq!(command(&mut client, &["SET", "k", "v3"]).await, "OK");
+    assert_eq!(integer(command(&mut client, &["SLOWLOG", "LEN"]).await), 1);
+    assert_eq!(command(&mut client, &["SLOWLOG", "RESET"]).await, "OK");
+    assert_eq!(integer(command(&mut client, &["SLOWLOG", "LEN"]).await), 0);
+}
+
+#[tokio::test]
+async fn keeps_the_newest_entries() {
+    let server = TestServer::start(&["--slowlog-log-slower-than", "0", "--slowlog-max-len", "2"]);
+    let mut client = server.client().await;
+    for key in ["a", "b", "c"] {
+        command(&mut client, &["SET", key, "v"]).await;
+    }
+    // SLOWLOG itself is never recorded
+    assert_eq!(integer(command(&mut client, &["SLOWLOG", "LEN"]).await), 2);
+    match command(&mut client, &["SLOWLOG", "GET", "-1"]).await {
+        Frame::Array(entries) => {
+            let keys: Vec<String> = entries
+                .iter()
+                .map(|entry| match entry {
+                    Frame::Array(entry) => entry[3].get_frame_by_index(1).unwrap().to_string(),
+                    entry => panic!("unexpected entry {:?}", entry),
+                })
+                .collect();
+            assert_eq!(keys, ["c", "b"]);
+        }
+        frame => panic!("unexpected SLOWLOG GET reply {:?}", frame),
+    }
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
+
+use common::{command, TestServer};
+use redis_rs::client::client::Client;
+
+#[tokio::test]
+async fn unix_socket_with_permissions() {
+    let dir = std::env::temp_dir().join(format!("tinyredis-unixsocket-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("redis.sock");
+    let path = path.to_str().unwrap();
+    let _server = TestServer::start(&["--unixsocket", path, "--unixsocketperm", "700"]);
+
+    // the TCP port is bound first, the socket may still be missing once it is up
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while std::fs::metadata(path).is_err() {
+        assert!(Instant::now() < deadline, "socket {} not created", path);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mode = std::fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+
+    let mut client = Client::connect_unix(path).await.unwrap();
+    assert_eq!(command(&mut client, &["PING"]).await, "pong");
+    assert_eq!(command(&mut client, &["SET", "k", "v"]).await, "OK");
+    assert_eq!(command(&mut client, &["GET", "k"]).await, "v");
+    drop(client);
+    std::fs::remove_dir_all(&dir).unwrap();
+}